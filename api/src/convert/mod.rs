@@ -36,6 +36,7 @@ mod reduced_tx_out;
 mod ring_mlsag;
 mod ristretto_private;
 mod signature_rct_bulletproofs;
+mod signed_contingent_input;
 mod signing_data;
 mod tx;
 mod tx_hash;
@@ -88,6 +89,24 @@ pub fn merged_block_num_to_s3block_path(
     path
 }
 
+/// Version of the archive bundle format described by
+/// `blockchain::ArchiveBundleManifest`.
+pub const ARCHIVE_BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Path of the archive bundle manifest, relative to the root of the archive.
+pub const ARCHIVE_BUNDLE_MANIFEST_PATH: &str = "bundles/manifest.pb";
+
+/// Helper method for getting the suggested path/filename of a zstd-compressed
+/// archive bundle of `bundle_size` blocks starting at `first_block_index`.
+pub fn archive_bundle_path(bundle_size: u64, first_block_index: BlockIndex) -> PathBuf {
+    let base_dir = format!("bundles/zstd-{}", bundle_size);
+    let mut path = PathBuf::new();
+    path.push(base_dir);
+    path.push(block_num_to_s3block_path(first_block_index));
+    path.set_extension("pb.zst");
+    path
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,4 +123,12 @@ mod tests {
             PathBuf::from("1a/2b/3c/4e/5a/6b/7c/1a2b3c4e5a6b7c8d.pb"),
         );
     }
+
+    #[test]
+    fn test_archive_bundle_path() {
+        assert_eq!(
+            archive_bundle_path(1000, 0x1a2b_3c4e_5a6b_7c8d),
+            PathBuf::from("bundles/zstd-1000/1a/2b/3c/4e/5a/6b/7c/1a2b3c4e5a6b7c8d.pb.zst"),
+        );
+    }
 }
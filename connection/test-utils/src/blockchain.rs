@@ -34,6 +34,9 @@ pub struct MockBlockchainConnection<L: Ledger + Sync> {
     /// Proposed transactions.
     pub proposed_txs: Vec<Tx>,
 
+    /// Number of times to return an error when propose_tx is called.
+    pub propose_tx_should_error_count: usize,
+
     /// The fee map for this peer.
     pub fee_map: FeeMap,
 }
@@ -45,6 +48,7 @@ impl<L: Ledger + Sync> MockBlockchainConnection<L> {
             ledger,
             latency_millis,
             proposed_txs: Vec::new(),
+            propose_tx_should_error_count: 0,
             fee_map,
         }
     }
@@ -130,6 +134,10 @@ impl<L: Ledger + Sync> BlockchainConnection for MockBlockchainConnection<L> {
 
 impl<L: Ledger + Sync> UserTxConnection for MockBlockchainConnection<L> {
     fn propose_tx(&mut self, tx: &Tx) -> ConnectionResult<BlockIndex> {
+        if self.propose_tx_should_error_count > 0 {
+            self.propose_tx_should_error_count -= 1;
+            return Err(ConnectionError::Other("propose_tx failed".to_string()));
+        }
         self.proposed_txs.push(tx.clone());
         Ok(self.ledger.num_blocks().unwrap())
     }
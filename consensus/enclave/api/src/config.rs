@@ -26,6 +26,11 @@ pub struct BlockchainConfig {
     /// The block version that this enclave will be applying rules for and
     /// publishing.
     pub block_version: BlockVersion,
+
+    /// The congestion-based minimum fee parameters, if minimum fees are
+    /// raised while the network is congested. Left out of the digest when
+    /// not set.
+    pub congestion_fee: Option<CongestionFeeConfig>,
 }
 
 impl Default for BlockchainConfig {
@@ -35,10 +40,34 @@ impl Default for BlockchainConfig {
             governors_map: GovernorsMap::default(),
             governors_signature: None,
             block_version: BlockVersion::MAX,
+            congestion_fee: None,
         }
     }
 }
 
+/// Parameters of the congestion-based minimum fee. Nodes that disagree on
+/// them disagree on which transactions are valid, which is why they are part
+/// of the [BlockchainConfig] digest.
+#[derive(Clone, Deserialize, Debug, Digestible, Eq, Hash, PartialEq, Serialize)]
+pub struct CongestionFeeConfig {
+    /// A block with at least this many transaction inputs counts as full.
+    pub full_block_inputs: u64,
+
+    /// Number of most recent blocks the fee level is computed from.
+    pub window: u64,
+
+    /// Percentage by which the fee level grows after each full block.
+    pub increase_percent: u64,
+
+    /// Percentage by which the fee level decays after each block that is not
+    /// full.
+    pub decrease_percent: u64,
+
+    /// The fee level never exceeds this multiple of the configured minimum
+    /// fee.
+    pub max_multiplier: u64,
+}
+
 impl BlockchainConfig {
     /// Check if the blockchain config is valid.
     pub fn validate(&self, minting_trust_root_public_key: &Ed25519Public) -> Result<(), Error> {
@@ -131,6 +160,7 @@ mod test {
             governors_map: GovernorsMap::default(),
             governors_signature: None,
             block_version: BlockVersion::ZERO,
+            congestion_fee: None,
         }
         .into();
         let config2: BlockchainConfigWithDigest = BlockchainConfig {
@@ -138,6 +168,7 @@ mod test {
             governors_map: GovernorsMap::default(),
             governors_signature: None,
             block_version: BlockVersion::ZERO,
+            congestion_fee: None,
         }
         .into();
         let config3: BlockchainConfigWithDigest = BlockchainConfig {
@@ -145,6 +176,7 @@ mod test {
             governors_map: GovernorsMap::default(),
             governors_signature: None,
             block_version: BlockVersion::ZERO,
+            congestion_fee: None,
         }
         .into();
 
@@ -181,6 +213,7 @@ mod test {
             governors_map: GovernorsMap::default(),
             governors_signature: None,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         }
         .into();
 
@@ -195,6 +228,63 @@ mod test {
         );
     }
 
+    // Enabling or changing the congestion fee results in different responder ids,
+    // and leaving it disabled does not change the digest.
+    #[test]
+    fn congestion_fee_changes_responder_ids() {
+        let congestion_fee = CongestionFeeConfig {
+            full_block_inputs: 500,
+            window: 100,
+            increase_percent: 25,
+            decrease_percent: 10,
+            max_multiplier: 16,
+        };
+        let config1 = BlockchainConfig {
+            block_version: BlockVersion::ONE,
+            ..Default::default()
+        };
+        let config2 = BlockchainConfig {
+            congestion_fee: Some(congestion_fee.clone()),
+            ..config1.clone()
+        };
+        let config3 = BlockchainConfig {
+            congestion_fee: Some(CongestionFeeConfig {
+                window: 50,
+                ..congestion_fee
+            }),
+            ..config1.clone()
+        };
+
+        let responder_id = ResponderId("1.2.3.4:5".to_string());
+        let responder_ids = [&config1, &config2, &config3].map(|config| {
+            BlockchainConfigWithDigest::from(config.clone()).responder_id(&responder_id)
+        });
+        assert_ne!(responder_ids[0], responder_ids[1]);
+        assert_ne!(responder_ids[0], responder_ids[2]);
+        assert_ne!(responder_ids[1], responder_ids[2]);
+
+        // A config without the congestion fee has the same digest as one from
+        // before the field existed.
+        #[derive(Digestible)]
+        #[digestible(name = "BlockchainConfig")]
+        struct PreviousBlockchainConfig {
+            fee_map: FeeMap,
+            governors_map: GovernorsMap,
+            governors_signature: Option<Ed25519Signature>,
+            block_version: BlockVersion,
+        }
+        let previous = PreviousBlockchainConfig {
+            fee_map: config1.fee_map.clone(),
+            governors_map: config1.governors_map.clone(),
+            governors_signature: None,
+            block_version: config1.block_version,
+        };
+        assert_eq!(
+            previous.digest32::<MerlinTranscript>(b"mc-blockchain-config"),
+            config1.digest32::<MerlinTranscript>(b"mc-blockchain-config")
+        );
+    }
+
     // Different governor maps result in differnet responder ids.
     #[test]
     fn different_governor_maps_result_in_different_responder_ids() {
@@ -207,6 +297,7 @@ mod test {
             .unwrap(),
             governors_signature: None,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         }
         .into();
         let config2: BlockchainConfigWithDigest = BlockchainConfig {
@@ -218,6 +309,7 @@ mod test {
             .unwrap(),
             governors_signature: None,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         }
         .into();
         let config3: BlockchainConfigWithDigest = BlockchainConfig {
@@ -229,6 +321,7 @@ mod test {
             .unwrap(),
             governors_signature: None,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         }
         .into();
 
@@ -265,6 +358,7 @@ mod test {
             governors_map,
             governors_signature,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         };
 
         assert_eq!(config.validate(&governors_public_key), Ok(()));
@@ -275,6 +369,7 @@ mod test {
             governors_map: Default::default(),
             governors_signature: None,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         };
 
         assert_eq!(config.validate(&governors_public_key), Ok(()));
@@ -294,6 +389,7 @@ mod test {
             governors_map: Default::default(),
             governors_signature: None,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         };
 
         assert_eq!(
@@ -317,6 +413,7 @@ mod test {
             governors_map,
             governors_signature: None,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         };
 
         assert_eq!(
@@ -347,6 +444,7 @@ mod test {
             governors_map: governors_map2,
             governors_signature,
             block_version: BlockVersion::ONE,
+            congestion_fee: None,
         };
 
         assert_eq!(
@@ -15,7 +15,7 @@ mod governors_sig;
 mod messages;
 
 pub use crate::{
-    config::{BlockchainConfig, BlockchainConfigWithDigest},
+    config::{BlockchainConfig, BlockchainConfigWithDigest, CongestionFeeConfig},
     error::Error,
     fee_map::{Error as FeeMapError, FeeMap, SMALLEST_MINIMUM_FEE_LOG2},
     governors_map::{Error as GovernorsMapError, GovernorsMap},
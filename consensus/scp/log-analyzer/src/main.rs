@@ -0,0 +1,170 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+#![deny(missing_docs)]
+
+//! A utility that replays the SCP logs of several nodes, and reports slots
+//! where the nodes disagreed or stalled.
+
+use clap::Parser;
+use mc_common::logger::{create_app_logger, o, Logger};
+use mc_consensus_scp::{
+    replay_log,
+    scp_log::ScpLogReader,
+    scp_log_analyzer::{NodeSlotTimeline, SlotIssue},
+    test_utils::{get_bounded_combine_fn, trivial_validity_fn},
+    ScpLogAnalysis,
+};
+use mc_peers::ConsensusValue;
+use mc_transaction_core::constants::MAX_TRANSACTIONS_PER_BLOCK;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Configurable options.
+#[derive(Debug, Parser)]
+pub struct Config {
+    /// SCP debug dump of a node, as written with `--scp-debug-dump`. Pass once
+    /// per node.
+    #[clap(long = "scp-debug-dump", required = true)]
+    pub scp_debug_dumps: Vec<PathBuf>,
+
+    /// A node whose state does not change for longer than this many
+    /// milliseconds is reported as stalled.
+    #[clap(long, default_value = "5000", env = "MC_STALL_THRESHOLD_MS")]
+    pub stall_threshold_ms: u64,
+
+    /// Write the analysis as JSON to this file.
+    #[clap(long)]
+    pub json: Option<PathBuf>,
+
+    /// Write the timeline as a Graphviz digraph to this file.
+    #[clap(long)]
+    pub dot: Option<PathBuf>,
+
+    /// Write the timeline as a Mermaid Gantt chart to this file.
+    #[clap(long)]
+    pub mermaid: Option<PathBuf>,
+}
+
+fn replay_dump(
+    scp_debug_dump: &Path,
+    logger: &Logger,
+) -> Result<Vec<NodeSlotTimeline<ConsensusValue>>, String> {
+    // Accept both the dump directory and its cur-slot subdirectory.
+    let cur_slot_path = scp_debug_dump.join("cur-slot");
+    let log_path = if cur_slot_path.is_dir() {
+        cur_slot_path
+    } else {
+        scp_debug_dump.to_path_buf()
+    };
+
+    let scp_reader = ScpLogReader::<ConsensusValue>::new(&log_path)?;
+    replay_log(
+        scp_reader,
+        Arc::new(trivial_validity_fn),
+        Arc::new(get_bounded_combine_fn(MAX_TRANSACTIONS_PER_BLOCK)),
+        logger.clone(),
+    )
+    .map_err(|err| format!("Failed replaying {:?}: {}", log_path, err))
+}
+
+fn print_analysis(analysis: &ScpLogAnalysis<ConsensusValue>) {
+    for slot in &analysis.slots {
+        println!("Slot {}:", slot.slot_index);
+        for timeline in &slot.timelines {
+            let steps = timeline
+                .steps()
+                .iter()
+                .map(|step| {
+                    format!(
+                        "{:?}(b={})@{}ms",
+                        step.phase, step.ballot_counter, step.msec_since_start
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            println!("  {}: {}", timeline.node_id.responder_id, steps);
+            if timeline.replay_mismatches > 0 {
+                println!(
+                    "    WARNING: {} logged messages were not reproduced by the replay",
+                    timeline.replay_mismatches
+                );
+            }
+        }
+
+        if slot.issues.is_empty() {
+            println!("  OK");
+        }
+        for issue in &slot.issues {
+            match issue {
+                SlotIssue::Disagreement(externalized) => {
+                    println!("  FAIL: nodes externalized different values");
+                    for (node_id, values) in externalized {
+                        println!("    {}: {:?}", node_id.responder_id, values);
+                    }
+                }
+                SlotIssue::Stall {
+                    node_id,
+                    phase,
+                    from_msec,
+                    to_msec,
+                } => println!(
+                    "  STALL: {} made no progress in {:?} from {}ms to {}ms",
+                    node_id.responder_id, phase, from_msec, to_msec
+                ),
+                SlotIssue::NotExternalized { node_id, phase } => println!(
+                    "  STALL: {} did not externalize, last phase {:?}",
+                    node_id.responder_id, phase
+                ),
+            }
+        }
+        for (node_id, phase) in &slot.in_progress {
+            println!(
+                "  INFO: {} had not externalized yet when its log ended, last phase {:?}",
+                node_id.responder_id, phase
+            );
+        }
+    }
+}
+
+fn write_output(path: &Option<PathBuf>, contents: &str) {
+    if let Some(path) = path {
+        fs::write(path, contents).unwrap_or_else(|err| {
+            eprintln!("Failed writing {:?}: {}", path, err);
+            exit(2);
+        });
+    }
+}
+
+fn main() {
+    let (logger, _global_logger_guard) = create_app_logger(o!());
+    let config = Config::parse();
+
+    let mut timelines = Vec::new();
+    for scp_debug_dump in &config.scp_debug_dumps {
+        timelines.extend(replay_dump(scp_debug_dump, &logger).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(2);
+        }));
+    }
+
+    let analysis = ScpLogAnalysis::new(timelines, Duration::from_millis(config.stall_threshold_ms));
+    print_analysis(&analysis);
+
+    if config.json.is_some() {
+        let json = analysis.to_json().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(2);
+        });
+        write_output(&config.json, &json);
+    }
+    write_output(&config.dot, &analysis.to_dot());
+    write_output(&config.mermaid, &analysis.to_mermaid());
+
+    if analysis.has_issues() {
+        exit(1);
+    }
+}
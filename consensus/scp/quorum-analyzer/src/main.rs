@@ -0,0 +1,144 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+#![deny(missing_docs)]
+
+//! A utility that checks a network's quorum set configuration for quorum
+//! intersection, and lists the node sets that can split or halt it.
+
+use clap::Parser;
+use mc_common::{HashMap, ResponderId};
+use mc_consensus_scp::{QuorumSet, QuorumSetAnalysis, QuorumSetAnalyzer};
+use mc_util_parse::parse_duration_in_seconds;
+use std::{collections::BTreeMap, fs, path::PathBuf, process::exit, str::FromStr, time::Duration};
+
+/// Configurable options.
+#[derive(Debug, Parser)]
+pub struct Config {
+    /// Network configuration.
+    ///
+    /// A JSON file mapping the responder id of every node to its quorum set,
+    /// in the same format as `--quorum-set`. For example:
+    /// {"node1.test.mobilecoin.com:443":{"threshold":1,"members":[{"type":"
+    /// Node","args":"node2.test.mobilecoin.com:443"}]}, ...}
+    #[clap(long, env = "MC_NETWORK")]
+    pub network: PathBuf,
+
+    /// Largest splitting and blocking set size to search for.
+    #[clap(long, default_value = "3", env = "MC_MAX_SET_SIZE")]
+    pub max_set_size: usize,
+
+    /// Time limit, in seconds, for searching splitting and blocking sets. Once
+    /// it passes, no larger sets are searched, and the largest set size that
+    /// was fully searched is reported.
+    #[clap(long, default_value = "300", value_parser = parse_duration_in_seconds, env = "MC_TIME_LIMIT")]
+    pub time_limit: Duration,
+
+    /// Print the analysis as JSON.
+    #[clap(long)]
+    pub json: bool,
+}
+
+fn load_network(path: &PathBuf) -> Result<HashMap<ResponderId, QuorumSet<ResponderId>>, String> {
+    let data =
+        fs::read_to_string(path).map_err(|err| format!("Failed reading {:?}: {}", path, err))?;
+    let network: BTreeMap<String, QuorumSet<ResponderId>> =
+        serde_json::from_str(&data).map_err(|err| format!("Failed parsing {:?}: {}", path, err))?;
+
+    network
+        .into_iter()
+        .map(|(responder_id, quorum_set)| {
+            let responder_id = ResponderId::from_str(&responder_id)
+                .map_err(|err| format!("Invalid responder id {}: {:?}", responder_id, err))?;
+            Ok((responder_id, quorum_set))
+        })
+        .collect()
+}
+
+fn format_sets(sets: &[Vec<ResponderId>]) -> String {
+    if sets.is_empty() {
+        return "  (none found)".to_string();
+    }
+    sets.iter()
+        .map(|set| format!("  {}", format_set(set)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_set(set: &[ResponderId]) -> String {
+    format!(
+        "{{{}}}",
+        set.iter()
+            .map(|node| node.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+fn print_analysis(analysis: &QuorumSetAnalysis<ResponderId>) {
+    println!("Nodes: {}", format_set(&analysis.nodes));
+    if !analysis.unknown_nodes.is_empty() {
+        println!(
+            "WARNING: no quorum set was given for {}, these nodes are treated as absent",
+            format_set(&analysis.unknown_nodes)
+        );
+    }
+
+    if !analysis.has_quorum() {
+        println!("FAIL: the nodes do not form any quorum, the network cannot make progress");
+    }
+    match &analysis.disjoint_quorums {
+        None => println!("OK: the network enjoys quorum intersection"),
+        Some((q1, q2)) => println!(
+            "FAIL: the network does not enjoy quorum intersection, {} and {} are disjoint quorums",
+            format_set(q1),
+            format_set(q2)
+        ),
+    }
+
+    println!("Top tier: {}", format_set(&analysis.top_tier));
+    println!(
+        "Minimal quorums:\n{}",
+        format_sets(&analysis.minimal_quorums)
+    );
+    println!(
+        "Minimal splitting sets (up to {} nodes):\n{}",
+        analysis.max_splitting_set_size,
+        format_sets(&analysis.minimal_splitting_sets)
+    );
+    println!(
+        "Minimal blocking sets (up to {} nodes):\n{}",
+        analysis.max_blocking_set_size,
+        format_sets(&analysis.minimal_blocking_sets)
+    );
+    if analysis.timed_out {
+        println!(
+            "WARNING: the time limit ran out, larger splitting and blocking sets were not searched"
+        );
+    }
+}
+
+fn main() {
+    let config = Config::parse();
+
+    let network = load_network(&config.network).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(2);
+    });
+    let analyzer = QuorumSetAnalyzer::new(&network).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(2);
+    });
+    let analysis = analyzer.analyze(config.max_set_size, Some(config.time_limit));
+
+    if config.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&analysis).expect("failed serializing analysis")
+        );
+    } else {
+        print_analysis(&analysis);
+    }
+
+    if !analysis.has_quorum() || !analysis.has_quorum_intersection() {
+        exit(1);
+    }
+}
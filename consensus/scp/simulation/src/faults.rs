@@ -0,0 +1,169 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Network faults injected by the simulation.
+
+use mc_common::NodeID;
+use std::{collections::BTreeSet, ops::Range, time::Duration};
+
+/// How messages behave on every link between two nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkFaults {
+    /// Smallest delay between sending and delivering a message.
+    pub min_delay: Duration,
+
+    /// Largest delay between sending and delivering a message. Delays are
+    /// drawn uniformly from `min_delay..=max_delay`.
+    pub max_delay: Duration,
+
+    /// Probability, between 0 and 1, that a message is dropped.
+    pub drop_probability: f64,
+
+    /// If false, messages on a link are delivered in the order they were
+    /// sent. If true, messages may overtake each other.
+    pub reorder: bool,
+}
+
+impl LinkFaults {
+    /// Links that deliver every message, in order, after `delay`.
+    pub fn reliable(delay: Duration) -> Self {
+        Self {
+            min_delay: delay,
+            max_delay: delay,
+            drop_probability: 0.0,
+            reorder: false,
+        }
+    }
+}
+
+impl Default for LinkFaults {
+    fn default() -> Self {
+        Self::reliable(Duration::from_millis(10))
+    }
+}
+
+/// Link faults that apply during a window of virtual time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaultPeriod {
+    /// Virtual time window.
+    pub during: Range<Duration>,
+
+    /// Link faults during the window.
+    pub faults: LinkFaults,
+}
+
+/// Splits the network into groups that cannot exchange messages during a
+/// window of virtual time. Nodes that are not listed in any group are
+/// isolated from every other node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Partition {
+    /// Virtual time window.
+    pub during: Range<Duration>,
+
+    /// Groups of nodes that can still reach each other.
+    pub groups: Vec<BTreeSet<NodeID>>,
+}
+
+impl Partition {
+    /// True if the partition blocks messages from `from` to `to`.
+    pub fn separates(&self, from: &NodeID, to: &NodeID) -> bool {
+        !self
+            .groups
+            .iter()
+            .any(|group| group.contains(from) && group.contains(to))
+    }
+}
+
+/// The faults to inject over the course of a simulation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultSchedule {
+    /// Link faults outside of any fault period.
+    pub default_faults: LinkFaults,
+
+    /// Link faults for specific windows. When windows overlap, the last one
+    /// wins.
+    pub periods: Vec<FaultPeriod>,
+
+    /// Network partitions.
+    pub partitions: Vec<Partition>,
+}
+
+impl FaultSchedule {
+    /// Link faults in effect at virtual time `now`.
+    pub fn link_faults_at(&self, now: Duration) -> &LinkFaults {
+        self.periods
+            .iter()
+            .rev()
+            .find(|period| period.during.contains(&now))
+            .map(|period| &period.faults)
+            .unwrap_or(&self.default_faults)
+    }
+
+    /// True if a partition blocks messages from `from` to `to` at virtual time
+    /// `now`.
+    pub fn is_partitioned(&self, now: Duration, from: &NodeID, to: &NodeID) -> bool {
+        self.partitions
+            .iter()
+            .any(|partition| partition.during.contains(&now) && partition.separates(from, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mc_consensus_scp::test_utils::test_node_id;
+
+    #[test]
+    fn link_faults_follow_schedule() {
+        let lossy = LinkFaults {
+            drop_probability: 0.5,
+            ..Default::default()
+        };
+        let schedule = FaultSchedule {
+            periods: vec![FaultPeriod {
+                during: Duration::from_secs(10)..Duration::from_secs(20),
+                faults: lossy.clone(),
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            schedule.link_faults_at(Duration::from_secs(5)),
+            &LinkFaults::default()
+        );
+        assert_eq!(schedule.link_faults_at(Duration::from_secs(10)), &lossy);
+        assert_eq!(
+            schedule.link_faults_at(Duration::from_secs(20)),
+            &LinkFaults::default()
+        );
+    }
+
+    #[test]
+    fn partitions_separate_groups() {
+        let (a, b, c, d) = (
+            test_node_id(1),
+            test_node_id(2),
+            test_node_id(3),
+            test_node_id(4),
+        );
+        let schedule = FaultSchedule {
+            partitions: vec![Partition {
+                during: Duration::from_secs(10)..Duration::from_secs(20),
+                groups: vec![
+                    [a.clone(), b.clone()].into_iter().collect(),
+                    [c.clone()].into_iter().collect(),
+                ],
+            }],
+            ..Default::default()
+        };
+
+        let during = Duration::from_secs(15);
+        assert!(!schedule.is_partitioned(during, &a, &b));
+        assert!(schedule.is_partitioned(during, &a, &c));
+        assert!(schedule.is_partitioned(during, &c, &b));
+        // Unlisted nodes are isolated.
+        assert!(schedule.is_partitioned(during, &d, &a));
+
+        // The partition heals.
+        assert!(!schedule.is_partitioned(Duration::from_secs(20), &a, &c));
+    }
+}
@@ -0,0 +1,17 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+#![doc = include_str!("../README.md")]
+#![deny(missing_docs)]
+
+pub mod clock;
+pub mod faults;
+pub mod network;
+pub mod simulation;
+pub mod topology;
+
+pub use crate::{
+    clock::VirtualClock,
+    faults::{FaultPeriod, FaultSchedule, LinkFaults, Partition},
+    network::{Behavior, NetworkConfig, NodeConfig},
+    simulation::{InvariantViolation, Simulation, SimulationOptions, SimulationReport, SlotReport},
+};
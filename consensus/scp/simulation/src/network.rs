@@ -0,0 +1,93 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Description of a simulated network.
+
+use mc_common::NodeID;
+use mc_consensus_scp::QuorumSet;
+use std::collections::BTreeSet;
+
+/// How a simulated node behaves.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Behavior {
+    /// Follows the protocol.
+    Honest,
+
+    /// Follows the protocol, but never sends messages to the given peers.
+    /// An empty set withholds messages from every peer.
+    Withhold(BTreeSet<NodeID>),
+
+    /// Runs two instances of the protocol under the same node ID, each
+    /// proposing different values, and sends each half of its peers the
+    /// messages of one instance only.
+    Equivocate,
+}
+
+impl Behavior {
+    /// True if the node follows the protocol.
+    pub fn is_honest(&self) -> bool {
+        matches!(self, Self::Honest)
+    }
+}
+
+/// Describes one simulated node.
+#[derive(Clone, Debug)]
+pub struct NodeConfig {
+    /// This node's short name.
+    pub name: String,
+
+    /// This node's ID.
+    pub id: NodeID,
+
+    /// The nodes to which this node broadcasts.
+    pub peers: BTreeSet<NodeID>,
+
+    /// This node's quorum set.
+    pub quorum_set: QuorumSet,
+
+    /// This node's behavior.
+    pub behavior: Behavior,
+}
+
+impl NodeConfig {
+    /// Create an honest node.
+    pub fn new(name: String, id: NodeID, peers: BTreeSet<NodeID>, quorum_set: QuorumSet) -> Self {
+        Self {
+            name,
+            id,
+            peers,
+            quorum_set,
+            behavior: Behavior::Honest,
+        }
+    }
+}
+
+/// Describes a network of simulated nodes.
+#[derive(Clone, Debug)]
+pub struct NetworkConfig {
+    /// The network's name.
+    pub name: String,
+
+    /// The network's nodes.
+    pub nodes: Vec<NodeConfig>,
+}
+
+impl NetworkConfig {
+    /// Create a network.
+    pub fn new(name: String, nodes: Vec<NodeConfig>) -> Self {
+        Self { name, nodes }
+    }
+
+    /// Change the behavior of the node at `node_index`.
+    pub fn with_behavior(mut self, node_index: usize, behavior: Behavior) -> Self {
+        self.nodes[node_index].behavior = behavior;
+        self
+    }
+
+    /// The IDs of the nodes at the given indices.
+    pub fn node_ids(&self, node_indices: impl IntoIterator<Item = usize>) -> BTreeSet<NodeID> {
+        node_indices
+            .into_iter()
+            .map(|node_index| self.nodes[node_index].id.clone())
+            .collect()
+    }
+}
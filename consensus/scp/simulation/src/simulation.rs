@@ -0,0 +1,874 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Discrete-event simulation of an SCP network.
+//!
+//! All nodes run in a single thread against a [VirtualClock]. Message delays,
+//! drops and partitions are driven by a random number generator seeded from
+//! [SimulationOptions::seed], so a run can be reproduced exactly from its
+//! seed.
+
+use crate::{
+    clock::VirtualClock,
+    faults::FaultSchedule,
+    network::{Behavior, NetworkConfig},
+};
+use displaydoc::Display;
+use mc_common::{
+    logger::{log, o, Logger},
+    NodeID,
+};
+use mc_consensus_scp::{
+    msg::Msg,
+    slot::{CombineFn, ValidityFn},
+    test_utils::{get_bounded_combine_fn, trivial_validity_fn, TransactionValidationError},
+    Node, ScpNode, SlotIndex,
+};
+use rand::{Rng, SeedableRng};
+use rand_hc::Hc128Rng;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Controls a simulation.
+#[derive(Clone)]
+pub struct SimulationOptions {
+    /// Seed for every random choice made by the simulation.
+    pub seed: u64,
+
+    /// Number of values to submit.
+    pub values_to_submit: usize,
+
+    /// Virtual time between value submissions.
+    pub submit_interval: Duration,
+
+    /// Submit each value to every node (true), or to nodes in turn (false).
+    pub submit_to_all_nodes: bool,
+
+    /// We propose up to this many values from a node's pending values per
+    /// slot.
+    pub max_slot_proposed_values: usize,
+
+    /// Virtual time between two checks of a node's timers and pending values.
+    pub tick_interval: Duration,
+
+    /// Virtual time after which the simulation stops, whether or not all
+    /// values were externalized.
+    pub max_duration: Duration,
+
+    /// Number of externalized slots each node keeps answering messages for.
+    /// Nodes that fall further behind catch up by copying the missing blocks
+    /// from an honest peer, like consensus service does with ledger sync.
+    pub max_externalized_slots: usize,
+
+    /// Faults to inject.
+    pub faults: FaultSchedule,
+
+    /// The values validity function to use.
+    pub validity_fn: ValidityFn<String, TransactionValidationError>,
+
+    /// The values combine function to use.
+    pub combine_fn: CombineFn<String, TransactionValidationError>,
+}
+
+impl Default for SimulationOptions {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            values_to_submit: 100,
+            submit_interval: Duration::from_millis(10),
+            submit_to_all_nodes: true,
+            max_slot_proposed_values: 100,
+            tick_interval: Duration::from_millis(10),
+            max_duration: Duration::from_secs(600),
+            max_externalized_slots: 5,
+            faults: FaultSchedule::default(),
+            validity_fn: Arc::new(trivial_validity_fn::<String>),
+            combine_fn: Arc::new(get_bounded_combine_fn::<String>(100)),
+        }
+    }
+}
+
+/// A safety or liveness invariant that did not hold.
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum InvariantViolation {
+    /// Slot {slot_index}: {node} externalized {values:?}, but {other_node}
+    /// externalized {other_values:?}
+    Safety {
+        /// Slot index.
+        slot_index: SlotIndex,
+        /// First node that externalized the slot.
+        node: String,
+        /// Values externalized by `node`.
+        values: Vec<String>,
+        /// Node that externalized different values.
+        other_node: String,
+        /// Values externalized by `other_node`.
+        other_values: Vec<String>,
+    },
+
+    /// Slot {slot_index} was externalized by {node} but not by {other_node}
+    SlotNotExternalized {
+        /// Slot index.
+        slot_index: SlotIndex,
+        /// First node that externalized the slot.
+        node: String,
+        /// Node that did not externalize the slot.
+        other_node: String,
+    },
+
+    /// {node} externalized {externalized} of the {expected} values submitted
+    /// to honest nodes
+    ValuesNotExternalized {
+        /// Node that is missing values.
+        node: String,
+        /// Number of submitted values the node externalized.
+        externalized: usize,
+        /// Number of values submitted to honest nodes.
+        expected: usize,
+    },
+}
+
+/// What happened to one slot.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SlotReport {
+    /// Slot index.
+    pub slot_index: SlotIndex,
+
+    /// Virtual time at which each honest node externalized the slot.
+    pub externalized_at: BTreeMap<String, Duration>,
+
+    /// Honest nodes that copied the slot from a peer instead of externalizing
+    /// it through SCP.
+    pub caught_up: BTreeSet<String>,
+}
+
+/// The outcome of a simulation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SimulationReport {
+    /// Name of the simulated network.
+    pub network_name: String,
+
+    /// Seed of the simulation.
+    pub seed: u64,
+
+    /// Virtual time at which the simulation stopped.
+    pub duration: Duration,
+
+    /// The ledger of each honest node.
+    pub ledgers: BTreeMap<String, Vec<Vec<String>>>,
+
+    /// Per-slot timings.
+    pub slots: Vec<SlotReport>,
+
+    /// Number of messages sent.
+    pub messages_sent: u64,
+
+    /// Number of messages dropped or blocked by a partition.
+    pub messages_dropped: u64,
+
+    /// Invariants that did not hold.
+    pub violations: Vec<InvariantViolation>,
+}
+
+impl SimulationReport {
+    /// True if all safety and liveness invariants held.
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// A discrete-event simulation of an SCP network.
+pub struct Simulation {
+    network_name: String,
+    options: SimulationOptions,
+    clock: VirtualClock,
+    rng: Hc128Rng,
+    nodes: Vec<SimNode>,
+    events: BinaryHeap<Reverse<Event>>,
+    next_event_seq: u64,
+
+    /// Time of the last delivery on each (sender, recipient) link, used to
+    /// keep links in order.
+    last_delivery: BTreeMap<(usize, usize), Duration>,
+
+    /// Values submitted to at least one honest node.
+    expected_values: BTreeSet<String>,
+    remaining_submissions: usize,
+    messages_sent: u64,
+    messages_dropped: u64,
+    logger: Logger,
+}
+
+impl Simulation {
+    /// Create a simulation of `network`.
+    pub fn new(network: &NetworkConfig, options: SimulationOptions, logger: Logger) -> Self {
+        assert!(!network.nodes.is_empty(), "network has no nodes");
+        assert!(options.tick_interval > Duration::ZERO);
+
+        let clock = VirtualClock::new();
+        let node_indices: BTreeMap<NodeID, usize> = network
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(node_index, node_config)| (node_config.id.clone(), node_index))
+            .collect();
+
+        let nodes = network
+            .nodes
+            .iter()
+            .map(|node_config| {
+                let peers: Vec<usize> = node_config
+                    .peers
+                    .iter()
+                    .filter_map(|peer_id| node_indices.get(peer_id).cloned())
+                    .collect();
+
+                // The peers that receive each protocol instance's messages.
+                let recipients: Vec<Vec<usize>> = match &node_config.behavior {
+                    Behavior::Honest => vec![peers],
+                    Behavior::Withhold(withheld) if withheld.is_empty() => vec![vec![]],
+                    Behavior::Withhold(withheld) => vec![peers
+                        .into_iter()
+                        .filter(|peer| !withheld.contains(&network.nodes[*peer].id))
+                        .collect()],
+                    Behavior::Equivocate => {
+                        let (even, odd): (Vec<_>, Vec<_>) =
+                            peers.iter().enumerate().partition(|(i, _)| i % 2 == 0);
+                        vec![
+                            even.into_iter().map(|(_, peer)| *peer).collect(),
+                            odd.into_iter().map(|(_, peer)| *peer).collect(),
+                        ]
+                    }
+                };
+
+                let instances = recipients
+                    .into_iter()
+                    .map(|recipients| {
+                        let mut node = Node::new(
+                            node_config.id.clone(),
+                            node_config.quorum_set.clone(),
+                            options.validity_fn.clone(),
+                            options.combine_fn.clone(),
+                            0,
+                            logger.new(o!("mc.scp.sim.node" => node_config.name.clone())),
+                        )
+                        .with_time_source(clock.time_source());
+                        node.set_max_externalized_slots(options.max_externalized_slots);
+
+                        Instance {
+                            node,
+                            recipients,
+                            pending_values: Vec::new(),
+                            next_slot_index: 0,
+                        }
+                    })
+                    .collect();
+
+                SimNode {
+                    name: node_config.name.clone(),
+                    id: node_config.id.clone(),
+                    honest: node_config.behavior.is_honest(),
+                    instances,
+                    ledger: Vec::new(),
+                    externalized_at: Vec::new(),
+                    externalized_values: HashSet::new(),
+                    caught_up: BTreeSet::new(),
+                }
+            })
+            .collect();
+
+        let mut simulation = Self {
+            network_name: network.name.clone(),
+            rng: Hc128Rng::seed_from_u64(options.seed),
+            clock,
+            nodes,
+            events: BinaryHeap::new(),
+            next_event_seq: 0,
+            last_delivery: BTreeMap::new(),
+            expected_values: BTreeSet::new(),
+            remaining_submissions: 0,
+            messages_sent: 0,
+            messages_dropped: 0,
+            logger,
+            options,
+        };
+        simulation.schedule_submissions();
+        for node_index in 0..simulation.nodes.len() {
+            simulation.schedule(
+                simulation.options.tick_interval,
+                EventKind::Tick(node_index),
+            );
+        }
+        simulation
+    }
+
+    /// Run the simulation until every honest node externalized every value
+    /// submitted to honest nodes, or until the maximum duration elapsed, and
+    /// check the safety and liveness invariants.
+    pub fn run(mut self) -> SimulationReport {
+        log::info!(
+            self.logger,
+            "Simulating {} with seed {}",
+            self.network_name,
+            self.options.seed
+        );
+
+        while let Some(Reverse(event)) = self.events.pop() {
+            if event.at > self.options.max_duration {
+                log::warn!(
+                    self.logger,
+                    "Simulation of {} stopped after {:?}",
+                    self.network_name,
+                    self.options.max_duration
+                );
+                break;
+            }
+            self.clock.advance_to(event.at);
+
+            match event.kind {
+                EventKind::Submit(node_index, value) => {
+                    self.remaining_submissions -= 1;
+                    self.submit(node_index, value);
+                }
+                EventKind::Deliver(node_index, msg) => self.deliver(node_index, &msg),
+                EventKind::Tick(node_index) => {
+                    self.tick(node_index);
+                    self.schedule(
+                        event.at + self.options.tick_interval,
+                        EventKind::Tick(node_index),
+                    );
+
+                    if node_index == 0 && self.is_done() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.report()
+    }
+
+    fn schedule_submissions(&mut self) {
+        let num_nodes = self.nodes.len();
+        for i in 0..self.options.values_to_submit {
+            let at = self.options.submit_interval * i as u32;
+            let value = format!("value-{:06}", i);
+            let recipients: Vec<usize> = if self.options.submit_to_all_nodes {
+                (0..num_nodes).collect()
+            } else {
+                vec![i % num_nodes]
+            };
+
+            for node_index in recipients {
+                if self.nodes[node_index].honest {
+                    self.expected_values.insert(value.clone());
+                }
+                self.remaining_submissions += 1;
+                self.schedule(at, EventKind::Submit(node_index, value.clone()));
+            }
+        }
+    }
+
+    fn schedule(&mut self, at: Duration, kind: EventKind) {
+        let seq = self.next_event_seq;
+        self.next_event_seq += 1;
+        self.events.push(Reverse(Event { at, seq, kind }));
+    }
+
+    fn submit(&mut self, node_index: usize, value: String) {
+        let node = &mut self.nodes[node_index];
+        for (instance_index, instance) in node.instances.iter_mut().enumerate() {
+            // An equivocating node proposes a different value to each half of
+            // its peers.
+            let value = if instance_index == 0 {
+                value.clone()
+            } else {
+                format!("{}-equivocation-{}", value, instance_index)
+            };
+            instance.pending_values.push(value);
+        }
+    }
+
+    fn deliver(&mut self, node_index: usize, msg: &Msg<String>) {
+        for instance_index in 0..self.nodes[node_index].instances.len() {
+            let outgoing = self.nodes[node_index].instances[instance_index]
+                .node
+                .handle_message(msg)
+                .expect("handle_message() failed");
+            self.broadcast(node_index, instance_index, outgoing);
+        }
+        self.record_externalized(node_index);
+    }
+
+    fn tick(&mut self, node_index: usize) {
+        self.catch_up(node_index);
+
+        let max_slot_proposed_values = self.options.max_slot_proposed_values;
+        for instance_index in 0..self.nodes[node_index].instances.len() {
+            let instance = &mut self.nodes[node_index].instances[instance_index];
+
+            let mut outgoing = Vec::new();
+            if !instance.pending_values.is_empty() {
+                let values: BTreeSet<String> = instance
+                    .pending_values
+                    .iter()
+                    .take(max_slot_proposed_values)
+                    .cloned()
+                    .collect();
+                outgoing.extend(
+                    instance
+                        .node
+                        .propose_values(values)
+                        .expect("propose_values() failed"),
+                );
+            }
+            outgoing.extend(instance.node.process_timeouts());
+
+            for msg in outgoing {
+                self.broadcast(node_index, instance_index, Some(msg));
+            }
+        }
+        self.record_externalized(node_index);
+    }
+
+    // Send a message from one of a node's protocol instances to its recipients,
+    // subject to the fault schedule.
+    fn broadcast(&mut self, node_index: usize, instance_index: usize, msg: Option<Msg<String>>) {
+        let msg = match msg {
+            Some(msg) => Arc::new(msg),
+            None => return,
+        };
+
+        let now = self.clock.elapsed();
+        let recipients = self.nodes[node_index].instances[instance_index]
+            .recipients
+            .clone();
+        for recipient in recipients {
+            self.messages_sent += 1;
+
+            let from = &msg.sender_id;
+            let to = &self.nodes[recipient].id;
+            if self.options.faults.is_partitioned(now, from, to) {
+                self.messages_dropped += 1;
+                continue;
+            }
+
+            let faults = self.options.faults.link_faults_at(now).clone();
+            if faults.drop_probability > 0.0 && self.rng.gen_bool(faults.drop_probability) {
+                self.messages_dropped += 1;
+                continue;
+            }
+
+            let delay = Duration::from_nanos(self.rng.gen_range(
+                faults.min_delay.as_nanos() as u64..=faults.max_delay.as_nanos() as u64,
+            ));
+            let mut at = now + delay;
+            if !faults.reorder {
+                let last = self
+                    .last_delivery
+                    .entry((node_index, recipient))
+                    .or_insert(at);
+                at = at.max(*last);
+                *last = at;
+            }
+            self.schedule(at, EventKind::Deliver(recipient, msg.clone()));
+        }
+    }
+
+    // Record the slots a node externalized since the last call.
+    fn record_externalized(&mut self, node_index: usize) {
+        let now = self.clock.elapsed();
+        let node = &mut self.nodes[node_index];
+        for (instance_index, instance) in node.instances.iter_mut().enumerate() {
+            while let Some(values) = instance
+                .node
+                .get_externalized_values(instance.next_slot_index)
+            {
+                instance
+                    .pending_values
+                    .retain(|value| !values.contains(value));
+                instance.next_slot_index += 1;
+
+                // The ledger follows the node's first protocol instance.
+                if instance_index == 0 {
+                    log::debug!(
+                        self.logger,
+                        "{} externalized slot {} at {:?}",
+                        node.name,
+                        node.ledger.len(),
+                        now
+                    );
+                    node.externalized_values.extend(values.iter().cloned());
+                    node.ledger.push(values);
+                    node.externalized_at.push(now);
+                }
+            }
+        }
+    }
+
+    // Copy blocks from a reachable honest peer if this node fell too far behind
+    // to externalize them through SCP.
+    fn catch_up(&mut self, node_index: usize) {
+        if !self.nodes[node_index].honest {
+            return;
+        }
+
+        let now = self.clock.elapsed();
+        let node_id = &self.nodes[node_index].id;
+        let ledger_len = self.nodes[node_index].ledger.len();
+        let donor = self
+            .nodes
+            .iter()
+            .filter(|node| {
+                node.honest && !self.options.faults.is_partitioned(now, node_id, &node.id)
+            })
+            .max_by_key(|node| node.ledger.len())
+            .filter(|node| node.ledger.len() > ledger_len + self.options.max_externalized_slots)
+            .map(|node| (node.name.clone(), node.ledger[ledger_len..].to_vec()));
+
+        if let Some((donor_name, blocks)) = donor {
+            let node = &mut self.nodes[node_index];
+            log::info!(
+                self.logger,
+                "{} catching up {} slots from {}",
+                node.name,
+                blocks.len(),
+                donor_name
+            );
+
+            for values in blocks {
+                node.caught_up.insert(node.ledger.len() as SlotIndex);
+                node.externalized_values.extend(values.iter().cloned());
+                node.instances[0]
+                    .pending_values
+                    .retain(|value| !values.contains(value));
+                node.ledger.push(values);
+                node.externalized_at.push(now);
+            }
+
+            let instance = &mut node.instances[0];
+            instance.next_slot_index = node.ledger.len() as SlotIndex;
+            instance.node.reset_slot_index(instance.next_slot_index);
+        }
+    }
+
+    // True once all submissions were made, every honest node externalized every
+    // expected value, and all honest ledgers have the same length.
+    fn is_done(&self) -> bool {
+        if self.remaining_submissions > 0 {
+            return false;
+        }
+
+        let honest_nodes: Vec<&SimNode> = self.nodes.iter().filter(|node| node.honest).collect();
+        let all_values_externalized = honest_nodes.iter().all(|node| {
+            self.expected_values
+                .iter()
+                .all(|value| node.externalized_values.contains(value))
+        });
+        let ledger_lens: BTreeSet<usize> =
+            honest_nodes.iter().map(|node| node.ledger.len()).collect();
+
+        all_values_externalized && ledger_lens.len() <= 1
+    }
+
+    fn report(self) -> SimulationReport {
+        let honest_nodes: Vec<&SimNode> = self.nodes.iter().filter(|node| node.honest).collect();
+        let mut violations = Vec::new();
+        let mut slots = Vec::new();
+
+        let num_slots = honest_nodes
+            .iter()
+            .map(|node| node.ledger.len())
+            .max()
+            .unwrap_or(0);
+        for slot_index in 0..num_slots {
+            slots.push(SlotReport {
+                slot_index: slot_index as SlotIndex,
+                externalized_at: honest_nodes
+                    .iter()
+                    .filter_map(|node| {
+                        node.externalized_at
+                            .get(slot_index)
+                            .map(|at| (node.name.clone(), *at))
+                    })
+                    .collect(),
+                caught_up: honest_nodes
+                    .iter()
+                    .filter(|node| node.caught_up.contains(&(slot_index as SlotIndex)))
+                    .map(|node| node.name.clone())
+                    .collect(),
+            });
+            violations.extend(check_slot(slot_index, &honest_nodes));
+        }
+
+        for node in honest_nodes.iter() {
+            let externalized = self
+                .expected_values
+                .iter()
+                .filter(|value| node.externalized_values.contains(*value))
+                .count();
+            if externalized < self.expected_values.len() {
+                violations.push(InvariantViolation::ValuesNotExternalized {
+                    node: node.name.clone(),
+                    externalized,
+                    expected: self.expected_values.len(),
+                });
+            }
+        }
+
+        for violation in violations.iter() {
+            log::error!(self.logger, "{}: {}", self.network_name, violation);
+        }
+
+        SimulationReport {
+            network_name: self.network_name.clone(),
+            seed: self.options.seed,
+            duration: self.clock.elapsed(),
+            ledgers: honest_nodes
+                .iter()
+                .map(|node| (node.name.clone(), node.ledger.clone()))
+                .collect(),
+            slots,
+            messages_sent: self.messages_sent,
+            messages_dropped: self.messages_dropped,
+            violations,
+        }
+    }
+}
+
+// Check that every honest node externalized the same values for a slot.
+fn check_slot(slot_index: usize, honest_nodes: &[&SimNode]) -> Vec<InvariantViolation> {
+    let first = match honest_nodes
+        .iter()
+        .find(|node| node.ledger.len() > slot_index)
+    {
+        Some(node) => node,
+        None => return Vec::new(),
+    };
+    let values = &first.ledger[slot_index];
+
+    honest_nodes
+        .iter()
+        .filter(|node| node.name != first.name)
+        .filter_map(|node| match node.ledger.get(slot_index) {
+            Some(other_values) if other_values != values => Some(InvariantViolation::Safety {
+                slot_index: slot_index as SlotIndex,
+                node: first.name.clone(),
+                values: values.clone(),
+                other_node: node.name.clone(),
+                other_values: other_values.clone(),
+            }),
+            Some(_) => None,
+            None => Some(InvariantViolation::SlotNotExternalized {
+                slot_index: slot_index as SlotIndex,
+                node: first.name.clone(),
+                other_node: node.name.clone(),
+            }),
+        })
+        .collect()
+}
+
+// A simulated node.
+struct SimNode {
+    name: String,
+    id: NodeID,
+    honest: bool,
+
+    /// Protocol instances run by this node. Only equivocating nodes run more
+    /// than one.
+    instances: Vec<Instance>,
+
+    /// Values externalized by the first protocol instance, by slot.
+    ledger: Vec<Vec<String>>,
+
+    /// Virtual time at which each slot in the ledger was externalized.
+    externalized_at: Vec<Duration>,
+
+    /// All values in the ledger.
+    externalized_values: HashSet<String>,
+
+    /// Slots copied from a peer.
+    caught_up: BTreeSet<SlotIndex>,
+}
+
+// One instance of the protocol run by a simulated node.
+struct Instance {
+    node: Node<String, TransactionValidationError>,
+
+    /// Nodes that receive this instance's messages.
+    recipients: Vec<usize>,
+
+    /// Values submitted to this instance that were not externalized yet.
+    pending_values: Vec<String>,
+
+    /// The next slot whose externalized values have not been recorded.
+    next_slot_index: SlotIndex,
+}
+
+enum EventKind {
+    /// Submit a value to a node.
+    Submit(usize, String),
+
+    /// Deliver a message to a node.
+    Deliver(usize, Arc<Msg<String>>),
+
+    /// Process a node's timers and pending values.
+    Tick(usize),
+}
+
+// Events are ordered by time, then by the order in which they were scheduled.
+struct Event {
+    at: Duration,
+    seq: u64,
+    kind: EventKind,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        faults::{FaultPeriod, LinkFaults, Partition},
+        topology::{dense_mesh, directed_cycle, metamesh},
+    };
+    use mc_common::logger::test_with_logger;
+
+    fn lossy_options(seed: u64) -> SimulationOptions {
+        SimulationOptions {
+            seed,
+            values_to_submit: 50,
+            faults: FaultSchedule {
+                default_faults: LinkFaults {
+                    min_delay: Duration::from_millis(5),
+                    max_delay: Duration::from_millis(200),
+                    drop_probability: 0.05,
+                    reorder: true,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test_with_logger]
+    fn reliable_networks_externalize_all_values(logger: Logger) {
+        for network in [dense_mesh(4, 3), directed_cycle(4), metamesh(3, 2, 3, 2)] {
+            let report =
+                Simulation::new(&network, SimulationOptions::default(), logger.clone()).run();
+            assert!(report.is_ok(), "{:?}", report.violations);
+            assert!(!report.slots.is_empty());
+        }
+    }
+
+    #[test_with_logger]
+    fn same_seed_reproduces_run(logger: Logger) {
+        let network = dense_mesh(5, 3);
+        let first = Simulation::new(&network, lossy_options(7), logger.clone()).run();
+        let second = Simulation::new(&network, lossy_options(7), logger).run();
+
+        assert!(first.is_ok(), "{:?}", first.violations);
+        assert!(first.messages_dropped > 0);
+        assert_eq!(first, second);
+    }
+
+    #[test_with_logger]
+    fn lossy_network_is_safe_and_live(logger: Logger) {
+        for seed in 0..5 {
+            let report =
+                Simulation::new(&dense_mesh(5, 3), lossy_options(seed), logger.clone()).run();
+            assert!(report.is_ok(), "seed {}: {:?}", seed, report.violations);
+        }
+    }
+
+    #[test_with_logger]
+    fn network_recovers_from_partition(logger: Logger) {
+        // Three of five nodes form a quorum, so the majority side keeps going and
+        // the minority side has to catch up once the partition heals.
+        let network = dense_mesh(5, 2);
+        let options = SimulationOptions {
+            values_to_submit: 200,
+            faults: FaultSchedule {
+                partitions: vec![Partition {
+                    during: Duration::from_millis(500)..Duration::from_secs(20),
+                    groups: vec![network.node_ids(0..3), network.node_ids(3..5)],
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let report = Simulation::new(&network, options, logger).run();
+        assert!(report.is_ok(), "{:?}", report.violations);
+        assert!(report.messages_dropped > 0);
+        assert!(report.duration > Duration::from_secs(20));
+        assert!(report.slots.iter().any(|slot| !slot.caught_up.is_empty()));
+    }
+
+    #[test_with_logger]
+    fn network_stalls_without_quorum(logger: Logger) {
+        let network = dense_mesh(4, 3);
+        let options = SimulationOptions {
+            max_duration: Duration::from_secs(30),
+            faults: FaultSchedule {
+                periods: vec![FaultPeriod {
+                    during: Duration::ZERO..Duration::from_secs(60),
+                    faults: LinkFaults {
+                        drop_probability: 1.0,
+                        ..Default::default()
+                    },
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let report = Simulation::new(&network, options, logger).run();
+        assert!(report.slots.is_empty());
+        assert!(report.violations.iter().all(|violation| matches!(
+            violation,
+            InvariantViolation::ValuesNotExternalized { .. }
+        )));
+        assert_eq!(report.violations.len(), 4);
+    }
+
+    #[test_with_logger]
+    fn tolerates_withholding_node(logger: Logger) {
+        let network = dense_mesh(4, 2).with_behavior(3, Behavior::Withhold(BTreeSet::new()));
+
+        let report = Simulation::new(&network, lossy_options(1), logger).run();
+        assert!(report.is_ok(), "{:?}", report.violations);
+        assert_eq!(report.ledgers.len(), 3);
+    }
+
+    #[test_with_logger]
+    fn tolerates_equivocating_node(logger: Logger) {
+        for seed in 0..3 {
+            let network = dense_mesh(4, 2).with_behavior(0, Behavior::Equivocate);
+
+            let report = Simulation::new(&network, lossy_options(seed), logger.clone()).run();
+            assert!(report.is_ok(), "seed {}: {:?}", seed, report.violations);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Common network topologies.
+//!
+//! These mirror the topologies used by the thread-based network tests in
+//! `consensus/scp/tests/mock_network`.
+
+use crate::network::{NetworkConfig, NodeConfig};
+use mc_common::NodeID;
+use mc_consensus_scp::{test_utils::test_node_id, QuorumSet};
+use std::collections::BTreeSet;
+
+/// Constructs a mesh network of `n` nodes, where each node has all of its
+/// peers as validators and requires `k` of them to agree.
+pub fn dense_mesh(n: usize, k: usize) -> NetworkConfig {
+    let nodes = (0..n)
+        .map(|node_index| {
+            let peers = (0..n)
+                .filter(|other_node_index| *other_node_index != node_index)
+                .map(|other_node_index| test_node_id(other_node_index as u32))
+                .collect::<Vec<NodeID>>();
+
+            NodeConfig::new(
+                format!("m{}", node_index),
+                test_node_id(node_index as u32),
+                peers.iter().cloned().collect(),
+                QuorumSet::new_with_node_ids(k as u32, peers),
+            )
+        })
+        .collect();
+
+    NetworkConfig::new(format!("m{}k{}", n, k), nodes)
+}
+
+/// Constructs a cyclic network (e.g. 1->2->3->4->1), where each node trusts
+/// only the next node. Similar to Figure 4 in the SCP whitepaper.
+pub fn directed_cycle(n: usize) -> NetworkConfig {
+    let nodes = (0..n)
+        .map(|node_index| {
+            let next_node_id = test_node_id(((node_index + 1) % n) as u32);
+            let peers = (0..n)
+                .filter(|other_node_index| *other_node_index != node_index)
+                .map(|other_node_index| test_node_id(other_node_index as u32))
+                .collect::<BTreeSet<NodeID>>();
+
+            NodeConfig::new(
+                format!("c{}", node_index),
+                test_node_id(node_index as u32),
+                peers,
+                QuorumSet::new_with_node_ids(1, vec![next_node_id]),
+            )
+        })
+        .collect();
+
+    NetworkConfig::new(format!("cyclic{}", n), nodes)
+}
+
+/// Constructs a "metamesh" of `n` organizations with `m` servers each. Each
+/// node requires `k_n` organizations to agree, and an organization agrees when
+/// `k_m` of its servers agree.
+pub fn metamesh(n: usize, k_n: usize, m: usize, k_m: usize) -> NetworkConfig {
+    let org_node_ids = |org_index: usize| {
+        (0..m)
+            .map(move |server_index| test_node_id((org_index * m + server_index) as u32))
+            .collect::<Vec<NodeID>>()
+    };
+    let org_quorum_sets = (0..n)
+        .map(|org_index| QuorumSet::new_with_node_ids(k_m as u32, org_node_ids(org_index)))
+        .collect::<Vec<QuorumSet>>();
+
+    let mut nodes = Vec::new();
+    for org_index in 0..n {
+        for server_index in 0..m {
+            let node_id = test_node_id((org_index * m + server_index) as u32);
+
+            // This node's own organization needs one fewer of the other servers.
+            let other_servers_in_this_org = org_node_ids(org_index)
+                .into_iter()
+                .filter(|other_node_id| *other_node_id != node_id)
+                .collect::<Vec<NodeID>>();
+            let k_for_this_org = if k_m > 1 { k_m as u32 - 1 } else { 1 };
+
+            let mut inner_quorum_sets = vec![QuorumSet::new_with_node_ids(
+                k_for_this_org,
+                other_servers_in_this_org,
+            )];
+            inner_quorum_sets.extend(
+                org_quorum_sets
+                    .iter()
+                    .enumerate()
+                    .filter(|(other_org_index, _)| *other_org_index != org_index)
+                    .map(|(_, quorum_set)| quorum_set.clone()),
+            );
+
+            let peers = (0..n * m)
+                .map(|other_node_index| test_node_id(other_node_index as u32))
+                .filter(|other_node_id| *other_node_id != node_id)
+                .collect::<BTreeSet<NodeID>>();
+
+            nodes.push(NodeConfig::new(
+                format!("mm{}-{}", org_index, server_index),
+                node_id,
+                peers,
+                QuorumSet::new_with_inner_sets(k_n as u32, inner_quorum_sets),
+            ));
+        }
+    }
+
+    NetworkConfig::new(format!("{}k{}-{}k{}", n, k_n, m, k_m), nodes)
+}
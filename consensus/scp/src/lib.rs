@@ -8,8 +8,10 @@ pub mod ballot;
 pub mod msg;
 pub mod node;
 pub mod predicates;
+pub mod quorum_set_analyzer;
 pub mod quorum_set_ext;
 pub mod scp_log;
+pub mod scp_log_analyzer;
 pub mod slot;
 pub mod slot_state;
 #[cfg(any(test, feature = "test_utils"))]
@@ -25,5 +27,7 @@ pub use crate::{
     msg::{Msg, Topic},
     node::{MockScpNode, Node, ScpNode},
     quorum_set::{QuorumSet, QuorumSetMember, QuorumSetMemberWrapper},
+    quorum_set_analyzer::{QuorumSetAnalysis, QuorumSetAnalyzer},
     quorum_set_ext::QuorumSetExt,
+    scp_log_analyzer::{replay_log, ScpLogAnalysis},
 };
@@ -4,14 +4,20 @@
 //! with the members of its quorum set.
 use crate::{
     msg::{ExternalizePayload, Msg, Topic},
-    slot::{CombineFn, ScpSlot, Slot, SlotMetrics, ValidityFn},
+    slot::{CombineFn, ScpSlot, Slot, SlotMetrics, TimeSource, ValidityFn},
     QuorumSet, ScpNode, SlotIndex, Value,
 };
 use mc_common::{
     logger::{log, Logger},
     HashMap, NodeID,
 };
-use std::{collections::BTreeSet, fmt::Display, mem, time::Duration};
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    mem,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 /// Default limit on number of externalized slots to store.
 const MAX_EXTERNALIZED_SLOTS: usize = 1;
@@ -47,6 +53,9 @@ pub struct Node<V: Value, ValidationError: Clone + Display> {
     /// creating a slot. (Defaults to 1 second to match the SCP whitepaper
     /// specification.)
     pub scp_timebase: Duration,
+
+    /// Source of the current time for slot timers.
+    time_source: TimeSource,
 }
 
 impl<V: Value, ValidationError: Clone + Display + 'static> Node<V, ValidationError> {
@@ -69,6 +78,7 @@ impl<V: Value, ValidationError: Clone + Display + 'static> Node<V, ValidationErr
         current_slot_index: SlotIndex,
         logger: Logger,
     ) -> Self {
+        let time_source: TimeSource = Arc::new(Instant::now);
         let slot = Slot::new(
             node_id.clone(),
             quorum_set.clone(),
@@ -88,9 +98,33 @@ impl<V: Value, ValidationError: Clone + Display + 'static> Node<V, ValidationErr
             combine_fn,
             logger,
             scp_timebase: Duration::from_millis(1000),
+            time_source,
         }
     }
 
+    /// Replaces the source of the current time used by slot timers, e.g. with
+    /// a virtual clock. The current slot is restarted so that it uses the new
+    /// time source.
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self.current_slot = self.new_slot(self.current_slot.get_index());
+        self
+    }
+
+    // Create a slot with this node's configuration.
+    fn new_slot(&self, slot_index: SlotIndex) -> Box<dyn ScpSlot<V>> {
+        let mut slot = Slot::new(
+            self.ID.clone(),
+            self.Q.clone(),
+            slot_index,
+            self.validity_fn.clone(),
+            self.combine_fn.clone(),
+            self.logger.clone(),
+        );
+        slot.time_source = self.time_source.clone();
+        Box::new(slot)
+    }
+
     // Record the values externalized by the current slot and advance the current
     // slot.
     fn externalize(&mut self, payload: &ExternalizePayload<V>) -> Result<(), String> {
@@ -110,14 +144,7 @@ impl<V: Value, ValidationError: Clone + Display + 'static> Node<V, ValidationErr
             }
         }
 
-        let next_slot = Box::new(Slot::new(
-            self.ID.clone(),
-            self.Q.clone(),
-            slot_index + 1,
-            self.validity_fn.clone(),
-            self.combine_fn.clone(),
-            self.logger.clone(),
-        ));
+        let next_slot = self.new_slot(slot_index + 1);
 
         // Advance to the next slot.
         let externalized_slot = mem::replace(&mut self.current_slot, next_slot);
@@ -297,14 +324,7 @@ impl<V: Value, ValidationError: Clone + Display + 'static> ScpNode<V> for Node<V
         // The slot index should only increase.
         debug_assert!(slot_index > self.current_slot_index());
 
-        self.current_slot = Box::new(Slot::new(
-            self.ID.clone(),
-            self.Q.clone(),
-            slot_index,
-            self.validity_fn.clone(),
-            self.combine_fn.clone(),
-            self.logger.clone(),
-        ));
+        self.current_slot = self.new_slot(slot_index);
 
         self.externalized_slots.clear();
     }
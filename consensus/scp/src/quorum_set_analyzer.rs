@@ -0,0 +1,554 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Network-wide analysis of [QuorumSet] configurations.
+//!
+//! Given the quorum set of every node in a network, [QuorumSetAnalyzer]
+//! checks whether the network enjoys quorum intersection, and finds the
+//! smallest sets of nodes that can split it (by behaving maliciously) or halt
+//! it (by crashing).
+//!
+//! The analysis is exhaustive, and its running time grows exponentially with
+//! the number of nodes `n`. Finding the minimal quorums and checking quorum
+//! intersection look at up to `2^n` sets of nodes, which takes at most a few
+//! seconds for [MAX_ANALYZED_NODES] nodes. Checking whether a set of nodes can
+//! split the network costs as much as checking quorum intersection, and there
+//! are `C(n, k)` candidate sets of `k` nodes, so searching for splitting sets
+//! quickly becomes the bottleneck: for 20 nodes, searching sets of up to 3
+//! nodes means about 1,350 such checks of up to `2^19` sets each. The search
+//! for splitting and blocking sets can therefore be given a time limit, after
+//! which the analysis reports the largest set size that was fully searched.
+
+use crate::{GenericNodeId, QuorumSet, QuorumSetMember};
+use mc_common::HashMap;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// The maximal number of nodes [QuorumSetAnalyzer] accepts.
+pub const MAX_ANALYZED_NODES: usize = 20;
+
+/// A set of nodes, as a bitmask over node indices.
+type NodeMask = u64;
+
+/// A quorum set whose members were replaced by node indices. Members whose
+/// quorum set is unknown are `None`.
+struct IndexedQuorumSet {
+    threshold: u32,
+    nodes: Vec<Option<usize>>,
+    inner_sets: Vec<IndexedQuorumSet>,
+}
+
+impl IndexedQuorumSet {
+    fn new<ID: GenericNodeId>(quorum_set: &QuorumSet<ID>, index_of: &HashMap<ID, usize>) -> Self {
+        let mut nodes = Vec::new();
+        let mut inner_sets = Vec::new();
+        for member in quorum_set.members.iter() {
+            match &**member {
+                Some(QuorumSetMember::Node(node_id)) => nodes.push(index_of.get(node_id).copied()),
+                Some(QuorumSetMember::InnerSet(inner_set)) => {
+                    inner_sets.push(Self::new(inner_set, index_of))
+                }
+                None => {}
+            }
+        }
+        Self {
+            threshold: quorum_set.threshold,
+            nodes,
+            inner_sets,
+        }
+    }
+
+    /// Whether the nodes in `mask` satisfy this quorum set.
+    fn is_satisfied_by(&self, mask: NodeMask) -> bool {
+        let num_nodes = self
+            .nodes
+            .iter()
+            .flatten()
+            .filter(|index| mask & (1 << **index) != 0)
+            .count();
+        let num_inner_sets = self
+            .inner_sets
+            .iter()
+            .filter(|inner_set| inner_set.is_satisfied_by(mask))
+            .count();
+        (num_nodes + num_inner_sets) as u32 >= self.threshold
+    }
+}
+
+/// The result of [QuorumSetAnalyzer::analyze].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct QuorumSetAnalysis<ID: GenericNodeId> {
+    /// The analyzed nodes.
+    pub nodes: Vec<ID>,
+
+    /// Nodes that appear in quorum sets but whose own quorum set is unknown.
+    /// They are treated as absent from the network.
+    pub unknown_nodes: Vec<ID>,
+
+    /// Two quorums that do not intersect, if the network does not enjoy
+    /// quorum intersection.
+    pub disjoint_quorums: Option<(Vec<ID>, Vec<ID>)>,
+
+    /// The minimal quorums of the network.
+    pub minimal_quorums: Vec<Vec<ID>>,
+
+    /// The top tier: every node that belongs to some minimal quorum.
+    pub top_tier: Vec<ID>,
+
+    /// Minimal sets of nodes that, by behaving maliciously, can cause two
+    /// quorums to externalize different values. Every such set of at most
+    /// `max_splitting_set_size` nodes is listed, and larger ones may be.
+    pub minimal_splitting_sets: Vec<Vec<ID>>,
+
+    /// Minimal sets of nodes whose failure leaves the remaining nodes without
+    /// a quorum, halting the network. Every such set of at most
+    /// `max_blocking_set_size` nodes is listed, and larger ones may be.
+    pub minimal_blocking_sets: Vec<Vec<ID>>,
+
+    /// The largest splitting set size that was fully searched.
+    pub max_splitting_set_size: usize,
+
+    /// The largest blocking set size that was fully searched.
+    pub max_blocking_set_size: usize,
+
+    /// Whether the time limit ran out before every splitting and blocking set
+    /// size that was asked for was searched.
+    pub timed_out: bool,
+}
+
+impl<ID: GenericNodeId> QuorumSetAnalysis<ID> {
+    /// Whether every two quorums of the network intersect.
+    pub fn has_quorum_intersection(&self) -> bool {
+        self.disjoint_quorums.is_none()
+    }
+
+    /// Whether the nodes form at least one quorum, i.e. the network can make
+    /// progress when every node is up.
+    pub fn has_quorum(&self) -> bool {
+        !self.minimal_quorums.is_empty()
+    }
+}
+
+/// Analyzes the quorum sets of a whole network.
+pub struct QuorumSetAnalyzer<ID: GenericNodeId> {
+    /// The analyzed nodes, sorted. A node's index in this list is its bit in a
+    /// [NodeMask].
+    nodes: Vec<ID>,
+
+    /// Nodes that appear in quorum sets but are not analyzed, sorted.
+    unknown_nodes: Vec<ID>,
+
+    /// The quorum set of each analyzed node, by index.
+    quorum_sets: Vec<IndexedQuorumSet>,
+}
+
+impl<ID: GenericNodeId> QuorumSetAnalyzer<ID> {
+    /// Create an analyzer for a network, given the quorum set of every node.
+    pub fn new(quorum_sets: &HashMap<ID, QuorumSet<ID>>) -> Result<Self, String> {
+        if quorum_sets.len() > MAX_ANALYZED_NODES {
+            return Err(format!(
+                "Cannot analyze {} nodes, at most {} are supported",
+                quorum_sets.len(),
+                MAX_ANALYZED_NODES
+            ));
+        }
+        if let Some((node_id, quorum_set)) = quorum_sets
+            .iter()
+            .find(|(_node_id, quorum_set)| !quorum_set.is_valid())
+        {
+            return Err(format!(
+                "Invalid quorum set for {}: {:?}",
+                node_id, quorum_set
+            ));
+        }
+
+        let mut nodes = quorum_sets.keys().cloned().collect::<Vec<_>>();
+        nodes.sort();
+        let index_of = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node_id)| (node_id.clone(), index))
+            .collect::<HashMap<_, _>>();
+
+        let mut unknown_nodes = quorum_sets
+            .values()
+            .flat_map(|quorum_set| quorum_set.nodes())
+            .filter(|node_id| !index_of.contains_key(node_id))
+            .collect::<Vec<_>>();
+        unknown_nodes.sort();
+        unknown_nodes.dedup();
+
+        let quorum_sets = nodes
+            .iter()
+            .map(|node_id| IndexedQuorumSet::new(&quorum_sets[node_id], &index_of))
+            .collect();
+
+        Ok(Self {
+            nodes,
+            unknown_nodes,
+            quorum_sets,
+        })
+    }
+
+    /// Run the full analysis, searching for splitting and blocking sets of up
+    /// to `max_set_size` nodes. Once `time_limit` has passed, no larger sets
+    /// are searched.
+    pub fn analyze(
+        &self,
+        max_set_size: usize,
+        time_limit: Option<Duration>,
+    ) -> QuorumSetAnalysis<ID> {
+        let deadline = time_limit.map(|time_limit| Instant::now() + time_limit);
+        let minimal_quorums = self.minimal_quorums();
+        let top_tier = self.to_ids(minimal_quorums.iter().fold(0, |acc, mask| acc | mask));
+
+        // Blocking sets are cheap to check, so they are searched first.
+        let (minimal_blocking_sets, max_blocking_set_size) =
+            self.minimal_sets(max_set_size, deadline, |mask| {
+                self.max_quorum(self.all_nodes() & !mask, 0) == 0
+            });
+        let (minimal_splitting_sets, max_splitting_set_size) =
+            self.minimal_sets(max_set_size, deadline, |mask| {
+                self.find_split(mask).is_some()
+            });
+        let max_searchable_size = max_set_size.min(self.nodes.len());
+
+        QuorumSetAnalysis {
+            nodes: self.nodes.clone(),
+            unknown_nodes: self.unknown_nodes.clone(),
+            disjoint_quorums: self
+                .find_split(0)
+                .map(|(q1, q2)| (self.to_ids(q1), self.to_ids(q2))),
+            minimal_quorums: minimal_quorums
+                .into_iter()
+                .map(|mask| self.to_ids(mask))
+                .collect(),
+            top_tier,
+            minimal_splitting_sets: minimal_splitting_sets
+                .into_iter()
+                .map(|mask| self.to_ids(mask))
+                .collect(),
+            minimal_blocking_sets: minimal_blocking_sets
+                .into_iter()
+                .map(|mask| self.to_ids(mask))
+                .collect(),
+            max_splitting_set_size,
+            max_blocking_set_size,
+            timed_out: max_splitting_set_size < max_searchable_size
+                || max_blocking_set_size < max_searchable_size,
+        }
+    }
+
+    fn all_nodes(&self) -> NodeMask {
+        if self.nodes.is_empty() {
+            0
+        } else {
+            NodeMask::MAX >> (NodeMask::BITS as usize - self.nodes.len())
+        }
+    }
+
+    fn to_ids(&self, mask: NodeMask) -> Vec<ID> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(index, _node_id)| mask & (1 << index) != 0)
+            .map(|(_index, node_id)| node_id.clone())
+            .collect()
+    }
+
+    /// Whether every node in `mask` has its quorum set satisfied by `mask`.
+    fn is_quorum(&self, mask: NodeMask) -> bool {
+        mask != 0 && bits(mask).all(|index| self.quorum_sets[index].is_satisfied_by(mask))
+    }
+
+    /// The largest quorum contained in `mask`, or 0 if there is none. Nodes in
+    /// `always_satisfied` are malicious: they vouch for any quorum, and are
+    /// never removed.
+    fn max_quorum(&self, mut mask: NodeMask, always_satisfied: NodeMask) -> NodeMask {
+        loop {
+            let next = bits(mask)
+                .filter(|index| {
+                    always_satisfied & (1 << index) != 0
+                        || self.quorum_sets[*index].is_satisfied_by(mask)
+                })
+                .fold(0, |acc, index| acc | (1 << index));
+            if next == mask {
+                return mask;
+            }
+            mask = next;
+        }
+    }
+
+    /// Look for two quorums whose intersection only contains malicious
+    /// nodes from `malicious`, and that each contain at least one other node.
+    fn find_split(&self, malicious: NodeMask) -> Option<(NodeMask, NodeMask)> {
+        let honest = self.all_nodes() & !malicious;
+        if honest == 0 {
+            return None;
+        }
+
+        // Any split puts the lowest honest node on one side, so only subsets
+        // containing it need to be considered.
+        let pivot = honest & honest.wrapping_neg();
+        let rest = honest & !pivot;
+        let mut subset = rest;
+        loop {
+            let side = subset | pivot;
+            let q1 = self.max_quorum(side | malicious, malicious);
+            if q1 & honest != 0 {
+                let q2 = self.max_quorum((honest & !side) | malicious, malicious);
+                if q2 & honest != 0 {
+                    return Some((q1, q2));
+                }
+            }
+
+            if subset == 0 {
+                return None;
+            }
+            subset = (subset - 1) & rest;
+        }
+    }
+
+    /// Enumerate the minimal quorums, smallest first.
+    fn minimal_quorums(&self) -> Vec<NodeMask> {
+        let mut found = Vec::new();
+        for size in 1..=self.nodes.len() {
+            for mask in subsets_of_size(self.nodes.len(), size) {
+                if !contains_any(mask, &found) && self.is_quorum(mask) {
+                    found.push(mask);
+                }
+            }
+        }
+        found
+    }
+
+    /// Enumerate the minimal sets of at most `max_size` nodes matching
+    /// `predicate`, smallest first. The search stops once `deadline` has
+    /// passed, and also returns the largest size that was fully searched. The
+    /// empty set is always searched.
+    fn minimal_sets(
+        &self,
+        max_size: usize,
+        deadline: Option<Instant>,
+        predicate: impl Fn(NodeMask) -> bool,
+    ) -> (Vec<NodeMask>, usize) {
+        let is_past_deadline = || deadline.map_or(false, |deadline| Instant::now() >= deadline);
+        let mut found = Vec::new();
+        for size in 0..=max_size.min(self.nodes.len()) {
+            for mask in subsets_of_size(self.nodes.len(), size) {
+                if size > 0 && is_past_deadline() {
+                    return (found, size - 1);
+                }
+                if !contains_any(mask, &found) && predicate(mask) {
+                    found.push(mask);
+                }
+            }
+        }
+        (found, max_size.min(self.nodes.len()))
+    }
+}
+
+/// The indices of the bits set in `mask`.
+fn bits(mask: NodeMask) -> impl Iterator<Item = usize> {
+    (0..NodeMask::BITS as usize).filter(move |index| mask & (1 << index) != 0)
+}
+
+/// Whether `mask` is a superset of any of `sets`.
+fn contains_any(mask: NodeMask, sets: &[NodeMask]) -> bool {
+    sets.iter().any(|set| mask & set == *set)
+}
+
+/// All subsets of `size` elements out of `num_elements`, in increasing order.
+fn subsets_of_size(num_elements: usize, size: usize) -> impl Iterator<Item = NodeMask> {
+    let limit: NodeMask = 1 << num_elements;
+    let first: Option<NodeMask> = if size > num_elements {
+        None
+    } else if size == 0 {
+        Some(0)
+    } else {
+        Some((1 << size) - 1)
+    };
+
+    core::iter::successors(first, move |&mask| {
+        if mask == 0 {
+            return None;
+        }
+        // Gosper's hack: the next larger integer with the same number of bits
+        // set.
+        let lowest = mask & mask.wrapping_neg();
+        let ripple = mask + lowest;
+        let next = (((ripple ^ mask) >> 2) / lowest) | ripple;
+        (next < limit).then(|| next)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mc_common::ResponderId;
+    use std::str::FromStr;
+
+    fn node(index: u32) -> ResponderId {
+        ResponderId::from_str(&format!("node{}.test.com:8443", index)).unwrap()
+    }
+
+    fn ids(indices: &[u32]) -> Vec<ResponderId> {
+        indices.iter().map(|index| node(*index)).collect()
+    }
+
+    /// Every node trusts `threshold` out of all `num_nodes` nodes.
+    fn mesh(num_nodes: u32, threshold: u32) -> HashMap<ResponderId, QuorumSet<ResponderId>> {
+        (0..num_nodes)
+            .map(|index| {
+                let peers = (0..num_nodes)
+                    .filter(|peer| *peer != index)
+                    .map(node)
+                    .collect();
+                (node(index), QuorumSet::new_with_node_ids(threshold, peers))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_subsets_of_size() {
+        assert_eq!(subsets_of_size(3, 0).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(
+            subsets_of_size(3, 2).collect::<Vec<_>>(),
+            vec![0b011, 0b101, 0b110]
+        );
+        assert_eq!(subsets_of_size(3, 3).collect::<Vec<_>>(), vec![0b111]);
+        assert_eq!(subsets_of_size(2, 3).count(), 0);
+        assert_eq!(subsets_of_size(10, 4).count(), 210);
+    }
+
+    #[test]
+    // A 4-node network where each node trusts 2 of its 3 peers tolerates one
+    // faulty node.
+    fn test_mesh_network() {
+        let quorum_sets = mesh(4, 2);
+        let analysis = QuorumSetAnalyzer::new(&quorum_sets)
+            .unwrap()
+            .analyze(2, None);
+
+        assert!(analysis.has_quorum());
+        assert!(analysis.has_quorum_intersection());
+        assert!(analysis.unknown_nodes.is_empty());
+        assert_eq!(analysis.minimal_quorums.len(), 4);
+        assert!(analysis
+            .minimal_quorums
+            .iter()
+            .all(|quorum| quorum.len() == 3));
+        assert_eq!(analysis.top_tier, ids(&[0, 1, 2, 3]));
+
+        // Any two nodes can split or halt the network, but no single node can.
+        assert_eq!(analysis.minimal_splitting_sets.len(), 6);
+        assert!(analysis
+            .minimal_splitting_sets
+            .iter()
+            .all(|set| set.len() == 2));
+        assert_eq!(analysis.minimal_blocking_sets.len(), 6);
+        assert!(analysis
+            .minimal_blocking_sets
+            .iter()
+            .all(|set| set.len() == 2));
+    }
+
+    #[test]
+    // Each node trusting a single peer allows two disjoint quorums.
+    fn test_no_quorum_intersection() {
+        let quorum_sets: HashMap<ResponderId, QuorumSet<ResponderId>> = HashMap::from_iter([
+            (node(0), QuorumSet::new_with_node_ids(1, vec![node(1)])),
+            (node(1), QuorumSet::new_with_node_ids(1, vec![node(0)])),
+            (node(2), QuorumSet::new_with_node_ids(1, vec![node(3)])),
+            (node(3), QuorumSet::new_with_node_ids(1, vec![node(2)])),
+        ]);
+        let analysis = QuorumSetAnalyzer::new(&quorum_sets)
+            .unwrap()
+            .analyze(1, None);
+
+        assert!(!analysis.has_quorum_intersection());
+        let (q1, q2) = analysis.disjoint_quorums.clone().unwrap();
+        assert_eq!(q1, ids(&[0, 1]));
+        assert_eq!(q2, ids(&[2, 3]));
+        assert_eq!(
+            analysis.minimal_splitting_sets,
+            vec![Vec::<ResponderId>::new()]
+        );
+        assert_eq!(analysis.minimal_quorums, vec![ids(&[0, 1]), ids(&[2, 3])]);
+    }
+
+    #[test]
+    // Nodes with a quorum set that is not known can never be part of a quorum.
+    fn test_unknown_nodes() {
+        let quorum_sets: HashMap<ResponderId, QuorumSet<ResponderId>> = HashMap::from_iter([
+            (
+                node(0),
+                QuorumSet::new_with_node_ids(2, vec![node(1), node(5)]),
+            ),
+            (node(1), QuorumSet::new_with_node_ids(1, vec![node(0)])),
+        ]);
+        let analysis = QuorumSetAnalyzer::new(&quorum_sets)
+            .unwrap()
+            .analyze(1, None);
+
+        assert_eq!(analysis.unknown_nodes, ids(&[5]));
+        assert!(!analysis.has_quorum());
+        assert!(analysis.has_quorum_intersection());
+        assert!(analysis.top_tier.is_empty());
+        assert_eq!(
+            analysis.minimal_blocking_sets,
+            vec![Vec::<ResponderId>::new()]
+        );
+    }
+
+    #[test]
+    // Nested quorum sets: two organizations of three nodes each, where every
+    // node needs both organizations to agree.
+    fn test_inner_sets() {
+        let org_a = QuorumSet::new_with_node_ids(2, ids(&[0, 1, 2]));
+        let org_b = QuorumSet::new_with_node_ids(2, ids(&[3, 4, 5]));
+        let quorum_set = QuorumSet::new_with_inner_sets(2, vec![org_a, org_b]);
+        let quorum_sets = (0..6)
+            .map(|index| (node(index), quorum_set.clone()))
+            .collect::<HashMap<_, _>>();
+        let analysis = QuorumSetAnalyzer::new(&quorum_sets)
+            .unwrap()
+            .analyze(2, None);
+
+        assert!(analysis.has_quorum_intersection());
+        assert_eq!(analysis.minimal_quorums.len(), 9);
+        assert_eq!(analysis.top_tier, ids(&[0, 1, 2, 3, 4, 5]));
+        // Two nodes of the same organization can halt the network.
+        assert!(analysis.minimal_blocking_sets.contains(&ids(&[0, 1])));
+        assert!(!analysis.minimal_blocking_sets.contains(&ids(&[0, 3])));
+    }
+
+    #[test]
+    fn test_rejects_invalid_quorum_sets() {
+        let quorum_sets: HashMap<ResponderId, QuorumSet<ResponderId>> =
+            HashMap::from_iter([(node(0), QuorumSet::new_with_node_ids(2, vec![node(1)]))]);
+        assert!(QuorumSetAnalyzer::new(&quorum_sets).is_err());
+
+        assert!(QuorumSetAnalyzer::new(&mesh(MAX_ANALYZED_NODES as u32 + 1, 1)).is_err());
+    }
+
+    #[test]
+    // Only the empty set is searched once the time limit has passed.
+    fn test_time_limit() {
+        let analyzer = QuorumSetAnalyzer::new(&mesh(4, 2)).unwrap();
+
+        let analysis = analyzer.analyze(2, Some(Duration::ZERO));
+        assert!(analysis.timed_out);
+        assert_eq!(analysis.max_splitting_set_size, 0);
+        assert_eq!(analysis.max_blocking_set_size, 0);
+        assert!(analysis.minimal_splitting_sets.is_empty());
+        assert!(analysis.minimal_blocking_sets.is_empty());
+        // Quorums and quorum intersection are not subject to the time limit.
+        assert_eq!(analysis.minimal_quorums.len(), 4);
+        assert!(analysis.has_quorum_intersection());
+
+        let analysis = analyzer.analyze(5, Some(Duration::from_secs(60)));
+        assert!(!analysis.timed_out);
+        assert_eq!(analysis.max_splitting_set_size, 4);
+        assert_eq!(analysis.max_blocking_set_size, 4);
+    }
+}
@@ -0,0 +1,943 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Replays SCP logs written by `LoggingScpNode` and reconstructs how the slot
+//! state of each node evolved, so that a stalled or split network can be
+//! debugged without reading raw logs.
+//!
+//! Each node's log is replayed through a fresh `Node`, and the node's
+//! `SlotState` is diffed after every logged event. Timestamps are milliseconds
+//! since the node started logging the slot, so the timelines of different
+//! nodes are only roughly aligned.
+
+use crate::{
+    msg::Msg,
+    node::Node,
+    scp_log::{LoggedMsg, StoredMsg},
+    slot::{CombineFn, Phase, TimeSource, ValidityFn},
+    ScpNode, SlotIndex, Value,
+};
+use mc_common::{logger::Logger, NodeID};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::{Display, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// The `SlotState` fields that make up a node's own state. The latest messages
+/// received from peers are left out, since almost every incoming message
+/// changes them.
+const TRACKED_FIELDS: [&str; 11] = [
+    "phase",
+    "nominate_round",
+    "W",
+    "X",
+    "Y",
+    "Z",
+    "B",
+    "P",
+    "PP",
+    "H",
+    "C",
+];
+
+/// The `SlotState` fields that hold sets, which serialize in arbitrary order.
+const SET_FIELDS: [&str; 4] = ["W", "X", "Y", "Z"];
+
+/// The logged event that caused a transition.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum ReplayEvent {
+    /// A message from the given node.
+    IncomingMsg(NodeID),
+
+    /// The node proposed values.
+    Nominate,
+
+    /// The node processed timeouts.
+    Timeout,
+}
+
+/// A change in a node's slot state.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SlotTransition {
+    /// Milliseconds since the node started the slot.
+    pub msec_since_start: u64,
+
+    /// The event that caused the change.
+    pub event: ReplayEvent,
+
+    /// The `SlotState` fields that changed.
+    pub changed_fields: Vec<String>,
+
+    /// Phase after the change.
+    pub phase: Phase,
+
+    /// Counter of the current ballot after the change.
+    pub ballot_counter: u32,
+}
+
+/// How the state of one node evolved during one slot.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct NodeSlotTimeline<V: Value> {
+    /// The node.
+    pub node_id: NodeID,
+
+    /// The slot.
+    pub slot_index: SlotIndex,
+
+    /// Changes of the node's slot state, in log order.
+    pub transitions: Vec<SlotTransition>,
+
+    /// Milliseconds since the node started the slot, of the last logged event
+    /// for this slot.
+    pub last_msec_since_start: u64,
+
+    /// The values the node externalized, if any.
+    pub externalized: Option<Vec<V>>,
+
+    /// Number of logged outgoing messages that the replayed node did not
+    /// reproduce. Non-zero when the log is incomplete, or when the replay's
+    /// validity and combine functions do not match the original ones.
+    pub replay_mismatches: usize,
+}
+
+impl<V: Value> NodeSlotTimeline<V> {
+    /// Create an empty timeline.
+    pub fn new(node_id: NodeID, slot_index: SlotIndex) -> Self {
+        Self {
+            node_id,
+            slot_index,
+            transitions: Vec::new(),
+            last_msec_since_start: 0,
+            externalized: None,
+            replay_mismatches: 0,
+        }
+    }
+
+    /// The transitions that changed the phase or the ballot counter.
+    pub fn steps(&self) -> Vec<&SlotTransition> {
+        let mut steps: Vec<&SlotTransition> = Vec::new();
+        for transition in &self.transitions {
+            let is_step = steps.last().map_or(true, |step| {
+                step.phase != transition.phase || step.ballot_counter != transition.ballot_counter
+            });
+            if is_step {
+                steps.push(transition);
+            }
+        }
+        steps
+    }
+
+    /// The periods, as `(from, to)` milliseconds since the start of the slot,
+    /// during which the node's state did not change for longer than
+    /// `threshold`, along with the phase the node was stuck in. A node that
+    /// externalized is not considered stuck afterwards.
+    pub fn stalls(&self, threshold: Duration) -> Vec<(Phase, u64, u64)> {
+        let threshold = threshold.as_millis() as u64;
+        let ends = self
+            .transitions
+            .iter()
+            .skip(1)
+            .map(|transition| transition.msec_since_start)
+            .chain([self.last_msec_since_start]);
+
+        self.transitions
+            .iter()
+            .zip(ends)
+            .filter(|(transition, to)| {
+                transition.phase != Phase::Externalize
+                    && to.saturating_sub(transition.msec_since_start) > threshold
+            })
+            .map(|(transition, to)| (transition.phase, transition.msec_since_start, to))
+            .collect()
+    }
+}
+
+/// Replays the SCP log of one node.
+///
+/// Returns a timeline for every slot in the log. Timeouts are replayed at the
+/// logged times, so the replay does not wait for them.
+pub fn replay_log<V: Value, ValidationError: Clone + Display + 'static>(
+    log: impl IntoIterator<Item = StoredMsg<V>>,
+    validity_fn: ValidityFn<V, ValidationError>,
+    combine_fn: CombineFn<V, ValidationError>,
+    logger: Logger,
+) -> Result<Vec<NodeSlotTimeline<V>>, String> {
+    // The replayed node's clock follows the logged times. Logged times restart
+    // at every slot, so the clock is offset to keep it monotonic.
+    let clock_msec = Arc::new(AtomicU64::new(0));
+    let time_source: TimeSource = {
+        let clock_msec = clock_msec.clone();
+        let start = Instant::now();
+        Arc::new(move || start + Duration::from_millis(clock_msec.load(Ordering::SeqCst)))
+    };
+    let mut clock_offset_msec = 0;
+
+    let mut replay: Option<Replay<V, ValidationError>> = None;
+    let mut timelines: BTreeMap<SlotIndex, TimelineBuilder<V>> = BTreeMap::new();
+
+    for stored_msg in log {
+        let msec = stored_msg.msec_since_start;
+
+        if let LoggedMsg::NodeSettings(node_id, quorum_set, slot_index) = stored_msg.msg {
+            clock_offset_msec = clock_msec.load(Ordering::SeqCst);
+            clock_msec.store(clock_offset_msec + msec, Ordering::SeqCst);
+
+            // A new slot. Keep the current node if it already reached it.
+            if replay
+                .as_ref()
+                .map(|replay| replay.node.current_slot_index())
+                != Some(slot_index)
+            {
+                replay = Some(Replay {
+                    node: Node::new(
+                        node_id,
+                        quorum_set,
+                        validity_fn.clone(),
+                        combine_fn.clone(),
+                        slot_index,
+                        logger.clone(),
+                    )
+                    .with_time_source(time_source.clone()),
+                    sent_msgs: VecDeque::new(),
+                });
+            }
+            let replay = replay.as_mut().expect("just set");
+            let node_id = replay.node.node_id();
+            let timeline = timelines
+                .entry(slot_index)
+                .or_insert_with(|| TimelineBuilder::new(node_id, slot_index));
+            if timeline.state.is_none() {
+                timeline.state = replay.snapshot(slot_index)?;
+            }
+            continue;
+        }
+
+        clock_msec.store(clock_offset_msec + msec, Ordering::SeqCst);
+        let replay = replay
+            .as_mut()
+            .ok_or_else(|| "The log does not start with a NodeSettings entry".to_string())?;
+        let node_id = replay.node.node_id();
+
+        let (slot_index, event) = match stored_msg.msg {
+            LoggedMsg::NodeSettings(..) => unreachable!(),
+
+            LoggedMsg::IncomingMsg(msg) => {
+                if let Some(out_msg) = replay.node.handle_message(&msg)? {
+                    replay.sent_msgs.push_back(out_msg);
+                }
+                (msg.slot_index, ReplayEvent::IncomingMsg(msg.sender_id))
+            }
+
+            LoggedMsg::Nominate(slot_index, values) => {
+                if let Some(out_msg) = replay.node.propose_values(values)? {
+                    replay.sent_msgs.push_back(out_msg);
+                }
+                (slot_index, ReplayEvent::Nominate)
+            }
+
+            LoggedMsg::OutgoingMsg(msg) => {
+                let timeline = timelines
+                    .entry(msg.slot_index)
+                    .or_insert_with(|| TimelineBuilder::new(node_id, msg.slot_index));
+                timeline.timeline.last_msec_since_start = msec;
+                if !replay.reproduces(&msg) {
+                    timeline.timeline.replay_mismatches += 1;
+                }
+                continue;
+            }
+
+            LoggedMsg::ProcessTimeouts(msgs) => {
+                let slot_index = replay.node.current_slot_index();
+                let out_msgs = replay.node.process_timeouts();
+                replay.sent_msgs.extend(out_msgs);
+                for msg in msgs {
+                    if !replay.reproduces(&msg) {
+                        timelines
+                            .entry(msg.slot_index)
+                            .or_insert_with(|| {
+                                TimelineBuilder::new(node_id.clone(), msg.slot_index)
+                            })
+                            .timeline
+                            .replay_mismatches += 1;
+                    }
+                }
+                (slot_index, ReplayEvent::Timeout)
+            }
+
+            LoggedMsg::Marker(_) => continue,
+        };
+
+        let timeline = timelines
+            .entry(slot_index)
+            .or_insert_with(|| TimelineBuilder::new(node_id, slot_index));
+        timeline.timeline.last_msec_since_start = msec;
+        if let Some(state) = replay.snapshot(slot_index)? {
+            timeline.record(msec, event, state)?;
+        }
+        if timeline.timeline.externalized.is_none() {
+            timeline.timeline.externalized = replay.node.get_externalized_values(slot_index);
+        }
+    }
+
+    Ok(timelines
+        .into_values()
+        .map(|builder| builder.timeline)
+        .collect())
+}
+
+/// The node being replayed.
+struct Replay<V: Value, ValidationError: Clone + Display + 'static> {
+    /// The replayed node.
+    node: Node<V, ValidationError>,
+
+    /// Messages sent by the replayed node that were not yet matched with the
+    /// log.
+    sent_msgs: VecDeque<Msg<V>>,
+}
+
+impl<V: Value, ValidationError: Clone + Display + 'static> Replay<V, ValidationError> {
+    /// True if the replayed node sent the logged outgoing message `msg`.
+    fn reproduces(&mut self, msg: &Msg<V>) -> bool {
+        self.sent_msgs.pop_front().as_ref() == Some(msg)
+    }
+
+    /// The tracked fields of the node's state for `slot_index`, if the node
+    /// still holds that slot.
+    fn snapshot(&mut self, slot_index: SlotIndex) -> Result<Option<JsonValue>, String> {
+        let snapshot = match self.node.get_slot_debug_snapshot(slot_index) {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+        let state: JsonValue = serde_json::from_str(&snapshot)
+            .map_err(|e| format!("Failed parsing slot state: {:?}", e))?;
+
+        let mut tracked = serde_json::Map::new();
+        for field in TRACKED_FIELDS {
+            let mut value = state.get(field).cloned().unwrap_or(JsonValue::Null);
+            if let JsonValue::Array(items) = &mut value {
+                if SET_FIELDS.contains(&field) {
+                    items.sort_by_cached_key(|item| item.to_string());
+                }
+            }
+            tracked.insert(field.to_string(), value);
+        }
+        Ok(Some(JsonValue::Object(tracked)))
+    }
+}
+
+/// Builds the timeline of one slot.
+struct TimelineBuilder<V: Value> {
+    /// The timeline so far.
+    timeline: NodeSlotTimeline<V>,
+
+    /// The tracked fields of the latest slot state.
+    state: Option<JsonValue>,
+}
+
+impl<V: Value> TimelineBuilder<V> {
+    fn new(node_id: NodeID, slot_index: SlotIndex) -> Self {
+        Self {
+            timeline: NodeSlotTimeline::new(node_id, slot_index),
+            state: None,
+        }
+    }
+
+    /// Record a transition if `state` differs from the previous state.
+    fn record(&mut self, msec: u64, event: ReplayEvent, state: JsonValue) -> Result<(), String> {
+        let changed_fields: Vec<String> = TRACKED_FIELDS
+            .iter()
+            .filter(|field| {
+                self.state
+                    .as_ref()
+                    .map_or(true, |prev_state| prev_state.get(field) != state.get(field))
+            })
+            .map(|field| field.to_string())
+            .collect();
+
+        if !changed_fields.is_empty() {
+            let phase: Phase = serde_json::from_value(state["phase"].clone())
+                .map_err(|e| format!("Failed parsing phase: {:?}", e))?;
+            let ballot_counter = state["B"]["N"].as_u64().unwrap_or(0) as u32;
+
+            self.timeline.transitions.push(SlotTransition {
+                msec_since_start: msec,
+                event,
+                changed_fields,
+                phase,
+                ballot_counter,
+            });
+        }
+
+        self.state = Some(state);
+        Ok(())
+    }
+}
+
+/// A problem found in a slot.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum SlotIssue<V: Value> {
+    /// Nodes externalized different values. Lists the values externalized by
+    /// each node.
+    Disagreement(Vec<(NodeID, Vec<V>)>),
+
+    /// A node's state did not change for longer than the stall threshold.
+    Stall {
+        /// The node.
+        node_id: NodeID,
+
+        /// The phase the node was stuck in.
+        phase: Phase,
+
+        /// Milliseconds since the node started the slot, when it got stuck.
+        from_msec: u64,
+
+        /// Milliseconds since the node started the slot, when it moved on or
+        /// its log ended.
+        to_msec: u64,
+    },
+
+    /// A node's log ended before it externalized the slot, although another
+    /// node externalized it, or the node's state had not changed for longer
+    /// than the stall threshold.
+    NotExternalized {
+        /// The node.
+        node_id: NodeID,
+
+        /// The node's last phase.
+        phase: Option<Phase>,
+    },
+}
+
+/// The timelines of one slot, and the problems found in it.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SlotReport<V: Value> {
+    /// The slot.
+    pub slot_index: SlotIndex,
+
+    /// The timeline of every node with a log for this slot.
+    pub timelines: Vec<NodeSlotTimeline<V>>,
+
+    /// Problems found in this slot.
+    pub issues: Vec<SlotIssue<V>>,
+
+    /// Nodes whose log ended while they were still making progress on this
+    /// slot, with their last phase. This is expected for the current slot of
+    /// a running node, and is not a problem.
+    pub in_progress: Vec<(NodeID, Option<Phase>)>,
+}
+
+/// The analysis of the SCP logs of several nodes.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ScpLogAnalysis<V: Value> {
+    /// One report per slot, in slot order.
+    pub slots: Vec<SlotReport<V>>,
+}
+
+impl<V: Value> ScpLogAnalysis<V> {
+    /// Groups timelines by slot, and flags slots where nodes disagreed or
+    /// stalled.
+    ///
+    /// # Arguments
+    /// * `timelines` - Timelines of all nodes, e.g. from `replay_log`.
+    /// * `stall_threshold` - A node whose state does not change for longer than
+    ///   this is considered stalled.
+    pub fn new(
+        timelines: impl IntoIterator<Item = NodeSlotTimeline<V>>,
+        stall_threshold: Duration,
+    ) -> Self {
+        let mut slot_to_timelines: BTreeMap<SlotIndex, Vec<NodeSlotTimeline<V>>> = BTreeMap::new();
+        for timeline in timelines {
+            slot_to_timelines
+                .entry(timeline.slot_index)
+                .or_insert_with(Vec::new)
+                .push(timeline);
+        }
+
+        let slots = slot_to_timelines
+            .into_iter()
+            .map(|(slot_index, mut timelines)| {
+                timelines.sort_by(|a, b| {
+                    (&a.node_id.responder_id, &a.node_id)
+                        .cmp(&(&b.node_id.responder_id, &b.node_id))
+                });
+                let (issues, in_progress) = Self::find_issues(&timelines, stall_threshold);
+                SlotReport {
+                    slot_index,
+                    timelines,
+                    issues,
+                    in_progress,
+                }
+            })
+            .collect();
+
+        Self { slots }
+    }
+
+    /// The problems found in a slot, and the nodes that were still working on
+    /// it when their log ended.
+    fn find_issues(
+        timelines: &[NodeSlotTimeline<V>],
+        stall_threshold: Duration,
+    ) -> (Vec<SlotIssue<V>>, Vec<(NodeID, Option<Phase>)>) {
+        let threshold_msec = stall_threshold.as_millis() as u64;
+        let mut issues = Vec::new();
+        let mut in_progress = Vec::new();
+
+        let externalized: Vec<(NodeID, Vec<V>)> = timelines
+            .iter()
+            .filter_map(|timeline| {
+                timeline
+                    .externalized
+                    .clone()
+                    .map(|values| (timeline.node_id.clone(), values))
+            })
+            .collect();
+        let distinct_values: BTreeSet<&Vec<V>> =
+            externalized.iter().map(|(_, values)| values).collect();
+        if distinct_values.len() > 1 {
+            issues.push(SlotIssue::Disagreement(externalized.clone()));
+        }
+
+        for timeline in timelines {
+            for (phase, from_msec, to_msec) in timeline.stalls(stall_threshold) {
+                issues.push(SlotIssue::Stall {
+                    node_id: timeline.node_id.clone(),
+                    phase,
+                    from_msec,
+                    to_msec,
+                });
+            }
+            if timeline.externalized.is_some() {
+                continue;
+            }
+
+            // A log that ends before the slot is externalized is expected for the
+            // slot a node was working on when its log was captured. It is only a
+            // problem if other nodes already moved past the slot, or if the node
+            // had stopped making progress.
+            let last_transition = timeline.transitions.last();
+            let idle_msec = timeline.last_msec_since_start.saturating_sub(
+                last_transition.map_or(0, |transition| transition.msec_since_start),
+            );
+            let phase = last_transition.map(|transition| transition.phase);
+            if !externalized.is_empty() || idle_msec > threshold_msec {
+                issues.push(SlotIssue::NotExternalized {
+                    node_id: timeline.node_id.clone(),
+                    phase,
+                });
+            } else {
+                in_progress.push((timeline.node_id.clone(), phase));
+            }
+        }
+
+        (issues, in_progress)
+    }
+
+    /// True if any slot has issues.
+    pub fn has_issues(&self) -> bool {
+        self.slots.iter().any(|slot| !slot.issues.is_empty())
+    }
+
+    /// The analysis as JSON.
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Failed serializing: {:?}", e))
+    }
+
+    /// A Graphviz digraph with one cluster per node and slot, whose nodes are
+    /// the phase and ballot counter changes. Stalls and nodes that did not
+    /// externalize are drawn in red.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph scp_log {\n    rankdir=LR;\n    node [shape=box];\n");
+        for slot in &self.slots {
+            for (node_index, timeline) in slot.timelines.iter().enumerate() {
+                let prefix = format!("s{}n{}", slot.slot_index, node_index);
+                let has_issues = slot
+                    .issues
+                    .iter()
+                    .any(|issue| issue_involves(issue, &timeline.node_id));
+
+                writeln!(out, "    subgraph \"cluster_{}\" {{", prefix).unwrap();
+                writeln!(
+                    out,
+                    "        label=\"slot {}: {}\";",
+                    slot.slot_index,
+                    escape_dot(&timeline.node_id.responder_id.to_string())
+                )
+                .unwrap();
+                if has_issues {
+                    writeln!(out, "        color=red;").unwrap();
+                }
+
+                let steps = timeline.steps();
+                for (step_index, step) in steps.iter().enumerate() {
+                    writeln!(
+                        out,
+                        "        \"{}t{}\" [label=\"{:?} b={}\\n+{}ms\"];",
+                        prefix, step_index, step.phase, step.ballot_counter, step.msec_since_start
+                    )
+                    .unwrap();
+                }
+                for step_index in 1..steps.len() {
+                    writeln!(
+                        out,
+                        "        \"{}t{}\" -> \"{}t{}\";",
+                        prefix,
+                        step_index - 1,
+                        prefix,
+                        step_index
+                    )
+                    .unwrap();
+                }
+                writeln!(out, "    }}").unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// A Mermaid Gantt chart with one section per node and slot, showing how
+    /// long the node spent at each phase and ballot counter. Periods in which
+    /// a node stalled are marked as critical.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from(
+            "gantt\n    title SCP log replay\n    dateFormat x\n    axisFormat %S.%L\n",
+        );
+        for slot in &self.slots {
+            for timeline in &slot.timelines {
+                writeln!(
+                    out,
+                    "    section slot {} {}",
+                    slot.slot_index,
+                    escape_mermaid(&timeline.node_id.responder_id.to_string())
+                )
+                .unwrap();
+
+                let steps = timeline.steps();
+                let ends = steps
+                    .iter()
+                    .skip(1)
+                    .map(|step| step.msec_since_start)
+                    .chain([timeline.last_msec_since_start]);
+                for (step, to_msec) in steps.iter().zip(ends) {
+                    let from_msec = step.msec_since_start;
+                    // Mermaid does not draw empty tasks.
+                    let to_msec = to_msec.max(from_msec + 1);
+                    let is_stalled = slot.issues.iter().any(|issue| match issue {
+                        SlotIssue::Stall {
+                            node_id,
+                            from_msec: stall_from_msec,
+                            to_msec: stall_to_msec,
+                            ..
+                        } => {
+                            *node_id == timeline.node_id
+                                && *stall_from_msec < to_msec
+                                && *stall_to_msec > from_msec
+                        }
+                        _ => false,
+                    });
+                    writeln!(
+                        out,
+                        "    {:?} b={} :{}{}, {}",
+                        step.phase,
+                        step.ballot_counter,
+                        if is_stalled { "crit, " } else { "" },
+                        from_msec,
+                        to_msec
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        out
+    }
+}
+
+/// True if `issue` is about `node_id`.
+fn issue_involves<V: Value>(issue: &SlotIssue<V>, node_id: &NodeID) -> bool {
+    match issue {
+        SlotIssue::Disagreement(externalized) => externalized
+            .iter()
+            .any(|(other_node_id, _)| other_node_id == node_id),
+        SlotIssue::Stall {
+            node_id: other_node_id,
+            ..
+        }
+        | SlotIssue::NotExternalized {
+            node_id: other_node_id,
+            ..
+        } => other_node_id == node_id,
+    }
+}
+
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Mermaid treats colons as the start of a task's data.
+fn escape_mermaid(label: &str) -> String {
+    label.replace(':', "#58;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        scp_log::{LoggingScpNode, ScpLogReader},
+        test_utils::{
+            test_node_id, trivial_combine_fn, trivial_validity_fn, TransactionValidationError,
+        },
+        QuorumSet,
+    };
+    use maplit::btreeset;
+    use mc_common::logger::{test_with_logger, Logger};
+    use tempdir::TempDir;
+
+    fn transition(msec_since_start: u64, phase: Phase, ballot_counter: u32) -> SlotTransition {
+        SlotTransition {
+            msec_since_start,
+            event: ReplayEvent::Nominate,
+            changed_fields: vec!["phase".to_string()],
+            phase,
+            ballot_counter,
+        }
+    }
+
+    fn timeline(
+        node_index: u32,
+        transitions: Vec<SlotTransition>,
+        last_msec_since_start: u64,
+        externalized: Option<Vec<u32>>,
+    ) -> NodeSlotTimeline<u32> {
+        NodeSlotTimeline {
+            transitions,
+            last_msec_since_start,
+            externalized,
+            ..NodeSlotTimeline::new(test_node_id(node_index), 1)
+        }
+    }
+
+    #[test_with_logger]
+    // Replaying the logs of two nodes that reached agreement should show both of
+    // them externalizing the same values, without issues.
+    fn test_replay_two_node_network(logger: Logger) {
+        let dir = TempDir::new("test").unwrap();
+        let slot_index = 1;
+
+        let mut nodes: Vec<_> = [(1, 2), (2, 1)]
+            .into_iter()
+            .map(|(node_index, peer_index)| {
+                let node = Node::<u32, TransactionValidationError>::new(
+                    test_node_id(node_index),
+                    QuorumSet::new_with_node_ids(1, vec![test_node_id(peer_index)]),
+                    Arc::new(trivial_validity_fn::<u32>),
+                    Arc::new(trivial_combine_fn::<u32>),
+                    slot_index,
+                    logger.clone(),
+                );
+                let out_path = dir.path().join(format!("node{}", node_index));
+                LoggingScpNode::new(node, out_path, logger.clone()).unwrap()
+            })
+            .collect();
+
+        // Messages to deliver, and the index of the node that should receive them.
+        let mut msgs = VecDeque::new();
+        for (node_index, values) in [(0, btreeset! {1, 2}), (1, btreeset! {3})] {
+            if let Some(msg) = nodes[node_index].propose_values(values).unwrap() {
+                msgs.push_back((1 - node_index, msg));
+            }
+        }
+        for _ in 0..1000 {
+            let (node_index, msg) = match msgs.pop_front() {
+                Some(item) => item,
+                None => break,
+            };
+            if let Some(response) = nodes[node_index].handle_message(&msg).unwrap() {
+                msgs.push_back((1 - node_index, response));
+            }
+        }
+        assert!(msgs.is_empty());
+
+        let timelines: Vec<_> = [1, 2]
+            .into_iter()
+            .flat_map(|node_index| {
+                let log_path = dir.path().join(format!("node{}/cur-slot", node_index));
+                replay_log::<u32, TransactionValidationError>(
+                    ScpLogReader::new(&log_path).unwrap(),
+                    Arc::new(trivial_validity_fn::<u32>),
+                    Arc::new(trivial_combine_fn::<u32>),
+                    logger.clone(),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let analysis = ScpLogAnalysis::new(timelines, Duration::from_secs(60));
+        assert!(!analysis.has_issues(), "{:?}", analysis.slots);
+        assert_eq!(analysis.slots.len(), 1);
+
+        let slot = &analysis.slots[0];
+        assert_eq!(slot.slot_index, slot_index);
+        assert_eq!(slot.timelines.len(), 2);
+        for timeline in &slot.timelines {
+            assert_eq!(timeline.externalized, Some(vec![1, 2, 3]));
+            assert_eq!(timeline.replay_mismatches, 0);
+
+            let phases: Vec<Phase> = timeline.steps().iter().map(|step| step.phase).collect();
+            assert_eq!(phases.first(), Some(&Phase::NominatePrepare));
+            assert_eq!(phases.last(), Some(&Phase::Externalize));
+        }
+    }
+
+    #[test]
+    // Nodes that externalized different values should be flagged.
+    fn test_disagreement() {
+        let analysis = ScpLogAnalysis::new(
+            vec![
+                timeline(
+                    1,
+                    vec![transition(0, Phase::Externalize, 1)],
+                    10,
+                    Some(vec![1]),
+                ),
+                timeline(
+                    2,
+                    vec![transition(0, Phase::Externalize, 1)],
+                    10,
+                    Some(vec![2]),
+                ),
+                timeline(
+                    3,
+                    vec![transition(0, Phase::Externalize, 1)],
+                    10,
+                    Some(vec![1]),
+                ),
+            ],
+            Duration::from_secs(1),
+        );
+
+        assert_eq!(
+            analysis.slots[0].issues,
+            vec![SlotIssue::Disagreement(vec![
+                (test_node_id(1), vec![1]),
+                (test_node_id(2), vec![2]),
+                (test_node_id(3), vec![1]),
+            ])]
+        );
+    }
+
+    #[test]
+    // Long gaps between transitions, and logs that end before the slot is
+    // externalized, should be flagged.
+    fn test_stalls() {
+        let stalled = timeline(
+            1,
+            vec![
+                transition(0, Phase::NominatePrepare, 0),
+                transition(100, Phase::Prepare, 1),
+                transition(5000, Phase::Prepare, 2),
+            ],
+            5100,
+            None,
+        );
+        // Idling after externalizing is not a stall.
+        let externalized = timeline(
+            2,
+            vec![
+                transition(0, Phase::NominatePrepare, 0),
+                transition(200, Phase::Externalize, 1),
+            ],
+            9000,
+            Some(vec![1]),
+        );
+
+        let analysis = ScpLogAnalysis::new(vec![externalized, stalled], Duration::from_secs(1));
+        assert_eq!(
+            analysis.slots[0].issues,
+            vec![
+                SlotIssue::Stall {
+                    node_id: test_node_id(1),
+                    phase: Phase::Prepare,
+                    from_msec: 100,
+                    to_msec: 5000,
+                },
+                SlotIssue::NotExternalized {
+                    node_id: test_node_id(1),
+                    phase: Some(Phase::Prepare),
+                },
+            ]
+        );
+        assert!(analysis.slots[0].in_progress.is_empty());
+        assert!(analysis.has_issues());
+
+        let dot = analysis.to_dot();
+        assert!(dot.starts_with("digraph scp_log {"));
+        assert!(dot.contains("\"s1n0t1\" [label=\"Prepare b=1\\n+100ms\"];"));
+        assert!(dot.contains("\"s1n0t1\" -> \"s1n0t2\";"));
+        assert!(dot.contains("color=red;"));
+
+        let mermaid = analysis.to_mermaid();
+        assert!(mermaid.starts_with("gantt\n"));
+        assert!(mermaid.contains("    Prepare b=1 :crit, 100, 5000\n"));
+        assert!(mermaid.contains("    Prepare b=2 :5000, 5100\n"));
+        assert!(mermaid.contains("    Externalize b=1 :200, 9000\n"));
+        assert!(mermaid.contains("    section slot 1 node1.test.com#58;8443\n"));
+    }
+
+    #[test]
+    // A node whose log ends while it is still making progress on a slot that no
+    // other node externalized is not a problem, unless it stopped making
+    // progress.
+    fn test_slot_in_progress() {
+        let in_progress = timeline(
+            1,
+            vec![
+                transition(0, Phase::NominatePrepare, 0),
+                transition(300, Phase::Prepare, 1),
+            ],
+            800,
+            None,
+        );
+        let analysis = ScpLogAnalysis::new(vec![in_progress], Duration::from_secs(1));
+        assert_eq!(analysis.slots[0].issues, vec![]);
+        assert_eq!(
+            analysis.slots[0].in_progress,
+            vec![(test_node_id(1), Some(Phase::Prepare))]
+        );
+        assert!(!analysis.has_issues());
+        assert!(!analysis.to_dot().contains("color=red;"));
+
+        let idle = timeline(
+            1,
+            vec![
+                transition(0, Phase::NominatePrepare, 0),
+                transition(300, Phase::Prepare, 1),
+            ],
+            1800,
+            None,
+        );
+        let analysis = ScpLogAnalysis::new(vec![idle], Duration::from_secs(1));
+        assert_eq!(
+            analysis.slots[0].issues,
+            vec![
+                SlotIssue::Stall {
+                    node_id: test_node_id(1),
+                    phase: Phase::Prepare,
+                    from_msec: 300,
+                    to_msec: 1800,
+                },
+                SlotIssue::NotExternalized {
+                    node_id: test_node_id(1),
+                    phase: Some(Phase::Prepare),
+                },
+            ]
+        );
+        assert!(analysis.slots[0].in_progress.is_empty());
+        assert!(analysis.has_issues());
+    }
+}
@@ -36,6 +36,10 @@ pub type CombineFn<V, E> = Arc<(dyn Fn(&[V]) -> Result<Vec<V>, E> + Sync + Send)
 /// Application-specific validation of value.
 pub type ValidityFn<V, E> = Arc<(dyn Fn(&V) -> Result<(), E> + Sync + Send)>;
 
+/// Source of the current time for round and ballot timers. Defaults to
+/// `Instant::now`, and may be replaced by a virtual clock in simulations.
+pub type TimeSource = Arc<(dyn Fn() -> Instant + Sync + Send)>;
+
 /// The various phases of the SCP protocol.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Phase {
@@ -171,6 +175,9 @@ pub struct Slot<V: Value, ValidationError: Display> {
     /// This parameter sets the base interval for ballot timeout.
     /// SCP suggests this should be one second.
     pub base_ballot_interval: Duration,
+
+    /// Source of the current time for the nomination and ballot timers.
+    pub time_source: TimeSource,
 }
 
 /// Metrics and information about a given slot.
@@ -226,7 +233,7 @@ impl<V: Value, ValidationError: Display> ScpSlot<V> for Slot<V, ValidationError>
 
         // Nomination round timeout.
         if self.next_nominate_round_at.is_some()
-            && Instant::now() > self.next_nominate_round_at.unwrap()
+            && (self.time_source)() > self.next_nominate_round_at.unwrap()
         {
             timeout_occurred = true;
             // Canceling is required since schedule_next_nomination_round will not schedule
@@ -249,7 +256,7 @@ impl<V: Value, ValidationError: Display> ScpSlot<V> for Slot<V, ValidationError>
         }
 
         // Ballot timeout.
-        if self.next_ballot_at.is_some() && Instant::now() > self.next_ballot_at.unwrap() {
+        if self.next_ballot_at.is_some() && (self.time_source)() > self.next_ballot_at.unwrap() {
             log::debug!(
                 self.logger,
                 "Ballot {} timed out in {:?} phase",
@@ -445,6 +452,7 @@ impl<V: Value, ValidationError: Display> Slot<V, ValidationError> {
             logger: logger.new(o!("mc.scp.slot" => slot_index)),
             base_round_interval: Duration::from_millis(1000),
             base_ballot_interval: Duration::from_millis(1000),
+            time_source: Arc::new(Instant::now),
         };
 
         let max_priority_peer = slot.find_max_priority_peer(slot.nominate_round);
@@ -554,7 +562,7 @@ impl<V: Value, ValidationError: Display> Slot<V, ValidationError> {
     fn schedule_next_nomination_round(&mut self) {
         if self.next_nominate_round_at.is_none() {
             self.next_nominate_round_at =
-                Some(Instant::now() + self.base_round_interval * self.nominate_round);
+                Some((self.time_source)() + self.base_round_interval * self.nominate_round);
         }
     }
 
@@ -1202,8 +1210,9 @@ impl<V: Value, ValidationError: Display> Slot<V, ValidationError> {
             });
 
             if !quorum_ids.is_empty() {
-                self.next_ballot_at =
-                    Some(Instant::now() + self.base_ballot_interval * self.B.N.saturating_add(1));
+                self.next_ballot_at = Some(
+                    (self.time_source)() + self.base_ballot_interval * self.B.N.saturating_add(1),
+                );
             }
         }
     }
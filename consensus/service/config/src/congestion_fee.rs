@@ -0,0 +1,121 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Configuration of the congestion-based minimum fee.
+
+use clap::Parser;
+use mc_consensus_enclave_api::CongestionFeeConfig;
+
+/// Policy for raising the minimum fee while the network is congested.
+///
+/// The fee level is derived from the most recent blocks in the ledger, so
+/// every node that agrees on the ledger agrees on the fee level. All nodes in
+/// a network must use the same policy, which is enforced by including it in
+/// the blockchain config, see [CongestionFeePolicy::config].
+#[derive(Clone, Debug, Eq, Parser, PartialEq)]
+pub struct CongestionFeePolicy {
+    /// Raise the minimum fee of every token while blocks are full, and decay
+    /// it back to the configured minimum fee afterwards.
+    #[clap(long = "congestion-fee", env = "MC_CONGESTION_FEE")]
+    pub enabled: bool,
+
+    /// A block with at least this many transaction inputs counts as full.
+    #[clap(
+        long = "congestion-fee-full-block-inputs",
+        default_value = "500",
+        env = "MC_CONGESTION_FEE_FULL_BLOCK_INPUTS"
+    )]
+    pub full_block_inputs: usize,
+
+    /// Number of most recent blocks the fee level is computed from.
+    #[clap(
+        long = "congestion-fee-window",
+        default_value = "100",
+        env = "MC_CONGESTION_FEE_WINDOW"
+    )]
+    pub window: u64,
+
+    /// Percentage by which the fee level grows after each full block.
+    #[clap(
+        long = "congestion-fee-increase-percent",
+        default_value = "25",
+        env = "MC_CONGESTION_FEE_INCREASE_PERCENT"
+    )]
+    pub increase_percent: u64,
+
+    /// Percentage by which the fee level decays after each block that is not
+    /// full.
+    #[clap(
+        long = "congestion-fee-decrease-percent",
+        default_value = "10",
+        value_parser = clap::value_parser!(u64).range(0..100),
+        env = "MC_CONGESTION_FEE_DECREASE_PERCENT"
+    )]
+    pub decrease_percent: u64,
+
+    /// The fee level never exceeds this multiple of the configured minimum
+    /// fee.
+    #[clap(
+        long = "congestion-fee-max-multiplier",
+        default_value = "16",
+        value_parser = clap::value_parser!(u64).range(1..),
+        env = "MC_CONGESTION_FEE_MAX_MULTIPLIER"
+    )]
+    pub max_multiplier: u64,
+}
+
+impl CongestionFeePolicy {
+    /// The congestion fee parameters to include in the blockchain config, or
+    /// `None` when the congestion fee is disabled.
+    pub fn config(&self) -> Option<CongestionFeeConfig> {
+        self.enabled.then(|| CongestionFeeConfig {
+            full_block_inputs: self.full_block_inputs as u64,
+            window: self.window,
+            increase_percent: self.increase_percent,
+            decrease_percent: self.decrease_percent,
+            max_multiplier: self.max_multiplier,
+        })
+    }
+}
+
+impl Default for CongestionFeePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            full_block_inputs: 500,
+            window: 100,
+            increase_percent: 25,
+            decrease_percent: 10,
+            max_multiplier: 16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_defaults_match_command_line_defaults() {
+        let policy = CongestionFeePolicy::try_parse_from(["test"]).unwrap();
+        assert_eq!(policy, CongestionFeePolicy::default());
+
+        let policy = CongestionFeePolicy::try_parse_from([
+            "test",
+            "--congestion-fee",
+            "--congestion-fee-window",
+            "20",
+        ])
+        .unwrap();
+        assert!(policy.enabled);
+        assert_eq!(policy.window, 20);
+        assert_eq!(policy.config().unwrap().window, 20);
+        assert_eq!(CongestionFeePolicy::default().config(), None);
+
+        assert!(CongestionFeePolicy::try_parse_from([
+            "test",
+            "--congestion-fee-decrease-percent",
+            "100"
+        ])
+        .is_err());
+    }
+}
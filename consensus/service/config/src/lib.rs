@@ -3,11 +3,19 @@
 //! Configuration parameters for the Consensus Service application.
 #![deny(missing_docs)]
 
+mod congestion_fee;
 mod error;
 mod network;
+mod pending_values;
 mod tokens;
 
-pub use crate::{error::Error, network::NetworkConfig, tokens::TokensConfig};
+pub use crate::{
+    congestion_fee::CongestionFeePolicy,
+    error::Error,
+    network::NetworkConfig,
+    pending_values::{PendingValuesOrdering, PendingValuesPolicy},
+    tokens::TokensConfig,
+};
 
 use clap::Parser;
 use mc_attest_core::ProviderId;
@@ -118,6 +126,20 @@ pub struct Config {
     /// The configured block version
     #[clap(long, default_value = "0", value_parser = parse_block_version, env = "MC_BLOCK_VERSION")]
     pub block_version: BlockVersion,
+
+    /// Build the ledger's secondary indexes on startup, if they do not exist
+    /// yet. They are needed by the explorer-style BlockchainAPI queries, and
+    /// are kept up to date once built.
+    #[clap(long, env = "MC_LEDGER_SECONDARY_INDEXES")]
+    pub ledger_secondary_indexes: bool,
+
+    /// Policy for choosing which pending values to propose in each slot.
+    #[clap(flatten)]
+    pub pending_values_policy: PendingValuesPolicy,
+
+    /// Policy for raising the minimum fee while the network is congested.
+    #[clap(flatten)]
+    pub congestion_fee_policy: CongestionFeePolicy,
 }
 
 impl Config {
@@ -205,6 +227,9 @@ mod tests {
             client_auth_token_max_lifetime: Duration::from_secs(60),
             tokens_path: None,
             block_version: BlockVersion::ZERO,
+            ledger_secondary_indexes: false,
+            pending_values_policy: PendingValuesPolicy::default(),
+            congestion_fee_policy: CongestionFeePolicy::default(),
         };
 
         assert_eq!(
@@ -273,6 +298,9 @@ mod tests {
             client_auth_token_max_lifetime: Duration::from_secs(60),
             tokens_path: None,
             block_version: BlockVersion::ZERO,
+            ledger_secondary_indexes: false,
+            pending_values_policy: PendingValuesPolicy::default(),
+            congestion_fee_policy: CongestionFeePolicy::default(),
         };
 
         assert_eq!(
@@ -0,0 +1,163 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Configuration of how pending values are chosen for nomination.
+
+use clap::{builder::RangedU64ValueParser, Parser};
+use mc_util_parse::parse_duration_in_seconds;
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+    time::Duration,
+};
+
+/// The order in which pending transactions are proposed for nomination.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PendingValuesOrdering {
+    /// First come, first served.
+    Arrival,
+
+    /// Highest fee per byte of the encrypted transaction first.
+    FeePerByte,
+
+    /// Highest fee per transaction input first.
+    FeePerInput,
+}
+
+impl PendingValuesOrdering {
+    /// True if transactions are ordered by fee.
+    pub fn is_fee_based(&self) -> bool {
+        *self != Self::Arrival
+    }
+}
+
+impl Default for PendingValuesOrdering {
+    fn default() -> Self {
+        Self::Arrival
+    }
+}
+
+impl Display for PendingValuesOrdering {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Arrival => write!(f, "arrival"),
+            Self::FeePerByte => write!(f, "fee-per-byte"),
+            Self::FeePerInput => write!(f, "fee-per-input"),
+        }
+    }
+}
+
+impl FromStr for PendingValuesOrdering {
+    type Err = String;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "arrival" => Ok(Self::Arrival),
+            "fee-per-byte" => Ok(Self::FeePerByte),
+            "fee-per-input" => Ok(Self::FeePerInput),
+            _ => Err(format!(
+                "Unknown pending values ordering '{}', expected one of arrival, fee-per-byte, fee-per-input",
+                src
+            )),
+        }
+    }
+}
+
+/// Policy for choosing which pending values to propose in each slot.
+#[derive(Clone, Debug, Eq, Parser, PartialEq)]
+pub struct PendingValuesPolicy {
+    /// The order in which pending transactions are proposed: arrival,
+    /// fee-per-byte or fee-per-input.
+    #[clap(
+        long = "pending-values-ordering",
+        default_value = "arrival",
+        env = "MC_PENDING_VALUES_ORDERING"
+    )]
+    pub ordering: PendingValuesOrdering,
+
+    /// With a fee-based ordering, the priority of a pending transaction grows
+    /// by its fee rate for every aging period it has been waiting, so that low
+    /// fee transactions are not starved. Zero disables aging.
+    #[clap(
+        long = "pending-values-aging-period",
+        default_value = "30",
+        value_parser = parse_duration_in_seconds,
+        env = "MC_PENDING_VALUES_AGING_PERIOD"
+    )]
+    pub aging_period: Duration,
+
+    /// With a fee-based ordering, the maximum number of mint transactions to
+    /// propose per slot. Mint transactions are then proposed in arrival order
+    /// ahead of other transactions, and do not compete with them on fees.
+    /// With arrival ordering, all values are proposed in arrival order and
+    /// this is ignored.
+    #[clap(
+        long = "max-mint-values-to-nominate",
+        default_value = "10",
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+        env = "MC_MAX_MINT_VALUES_TO_NOMINATE"
+    )]
+    pub max_mint_values: usize,
+}
+
+impl Default for PendingValuesPolicy {
+    fn default() -> Self {
+        Self {
+            ordering: PendingValuesOrdering::Arrival,
+            aging_period: Duration::from_secs(30),
+            max_mint_values: 10,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordering_round_trips_through_strings() {
+        for ordering in [
+            PendingValuesOrdering::Arrival,
+            PendingValuesOrdering::FeePerByte,
+            PendingValuesOrdering::FeePerInput,
+        ] {
+            assert_eq!(
+                PendingValuesOrdering::from_str(&ordering.to_string()),
+                Ok(ordering)
+            );
+        }
+        assert!(PendingValuesOrdering::from_str("fee").is_err());
+    }
+
+    #[test]
+    fn policy_defaults_match_command_line_defaults() {
+        let policy = PendingValuesPolicy::try_parse_from(["test"]).unwrap();
+        assert_eq!(policy, PendingValuesPolicy::default());
+
+        let policy = PendingValuesPolicy::try_parse_from([
+            "test",
+            "--pending-values-ordering",
+            "fee-per-input",
+            "--pending-values-aging-period",
+            "5",
+        ])
+        .unwrap();
+        assert_eq!(policy.ordering, PendingValuesOrdering::FeePerInput);
+        assert_eq!(policy.aging_period, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn zero_max_mint_values_is_rejected() {
+        // Proposing no mint transactions at all would starve them forever.
+        assert!(PendingValuesPolicy::try_parse_from([
+            "test",
+            "--max-mint-values-to-nominate",
+            "0"
+        ])
+        .is_err());
+
+        let policy =
+            PendingValuesPolicy::try_parse_from(["test", "--max-mint-values-to-nominate", "1"])
+                .unwrap();
+        assert_eq!(policy.max_mint_values, 1);
+    }
+}
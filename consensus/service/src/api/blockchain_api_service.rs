@@ -2,18 +2,24 @@
 
 //! Serves blockchain-related API requests.
 
+use crate::congestion_fee::CongestionFee;
 use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
 use mc_common::logger::{log, Logger};
 use mc_consensus_api::{
     blockchain,
-    consensus_common::{BlocksRequest, BlocksResponse, LastBlockInfoResponse},
+    consensus_common::{
+        self, BlocksByTimestampRequest, BlocksRequest, BlocksResponse, LastBlockInfoResponse,
+        TokenEventsRequest, TokenEventsResponse, TxOutCountsByBlockVersionResponse,
+    },
     consensus_common_grpc::BlockchainApi,
     empty::Empty,
 };
 use mc_consensus_enclave::FeeMap;
-use mc_ledger_db::Ledger;
-use mc_transaction_core::{tokens::Mob, BlockVersion, Token};
-use mc_util_grpc::{rpc_logger, send_result, Authenticator};
+use mc_ledger_db::{Ledger, TokenEvent, TokenEventKind};
+use mc_transaction_core::{tokens::Mob, BlockVersion, Token, TokenId};
+use mc_util_grpc::{
+    rpc_database_err, rpc_logger, rpc_precondition_error, send_result, Authenticator,
+};
 use mc_util_metrics::{self, SVC_COUNTERS};
 use protobuf::RepeatedField;
 use std::{cmp, collections::HashMap, sync::Arc};
@@ -33,6 +39,9 @@ pub struct BlockchainApiService<L: Ledger + Clone> {
     /// Minimum fee per token.
     fee_map: FeeMap,
 
+    /// If set, the reported minimum fees follow the congestion-based fee level.
+    congestion_fee: Option<CongestionFee<L>>,
+
     /// Configured block version
     network_block_version: BlockVersion,
 
@@ -53,11 +62,18 @@ impl<L: Ledger + Clone> BlockchainApiService<L> {
             authenticator,
             max_page_size: 2000,
             fee_map,
+            congestion_fee: None,
             network_block_version,
             logger,
         }
     }
 
+    /// Report minimum fees that follow the congestion-based fee level.
+    pub fn with_congestion_fee(mut self, congestion_fee: CongestionFee<L>) -> Self {
+        self.congestion_fee = Some(congestion_fee);
+        self
+    }
+
     // Set the maximum number of items returned for a single request.
     #[cfg(test)]
     pub fn set_max_page_size(&mut self, max_page_size: u16) {
@@ -69,13 +85,21 @@ impl<L: Ledger + Clone> BlockchainApiService<L> {
         let num_blocks = self.ledger.num_blocks()?;
         let mut resp = LastBlockInfoResponse::new();
         resp.set_index(num_blocks - 1);
+        let minimum_fees = match &self.congestion_fee {
+            Some(congestion_fee) => congestion_fee.minimum_fees(&self.fee_map)?,
+            None => self
+                .fee_map
+                .iter()
+                .map(|(token_id, fee)| (*token_id, *fee))
+                .collect(),
+        };
         resp.set_mob_minimum_fee(
-            self.fee_map
-                .get_fee_for_token(&Mob::ID)
+            *minimum_fees
+                .get(&Mob::ID)
                 .expect("should always have a fee for MOB"),
         );
         resp.set_minimum_fees(HashMap::from_iter(
-            self.fee_map
+            minimum_fees
                 .iter()
                 .map(|(token_id, fee)| (**token_id, *fee)),
         ));
@@ -126,6 +150,89 @@ impl<L: Ledger + Clone> BlockchainApiService<L> {
         response.set_blocks(RepeatedField::from_vec(blocks));
         Ok(response)
     }
+
+    /// Returns the blocks whose signature timestamp is in the range
+    /// [start_timestamp, end_timestamp), at most `min(limit, max_page_size)`
+    /// of them.
+    fn get_blocks_by_timestamp_helper(
+        &mut self,
+        start_timestamp: u64,
+        end_timestamp: u64,
+        limit: u32,
+    ) -> Result<BlocksResponse, mc_ledger_db::Error> {
+        let limit = cmp::min(limit, self.max_page_size as u32) as usize;
+        let blocks = self
+            .ledger
+            .get_block_indexes_by_timestamp(start_timestamp, end_timestamp, limit)?
+            .into_iter()
+            .map(|block_index| {
+                self.ledger
+                    .get_block(block_index)
+                    .map(|block| blockchain::Block::from(&block))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut response = BlocksResponse::new();
+        response.set_blocks(RepeatedField::from_vec(blocks));
+        Ok(response)
+    }
+
+    /// Returns the mint and burn events of a token in blocks in the range
+    /// [offset, offset + limit). If `limit` exceeds `max_page_size`, then only
+    /// [offset, offset + max_page_size) is searched.
+    fn get_token_events_helper(
+        &mut self,
+        token_id: u64,
+        offset: u64,
+        limit: u32,
+    ) -> Result<TokenEventsResponse, mc_ledger_db::Error> {
+        let end_index = offset + cmp::min(limit, self.max_page_size as u32) as u64;
+        let events = self
+            .ledger
+            .get_token_events(TokenId::from(token_id), offset, end_index)?
+            .iter()
+            .map(token_event_to_proto)
+            .collect();
+
+        let mut response = TokenEventsResponse::new();
+        response.set_events(RepeatedField::from_vec(events));
+        Ok(response)
+    }
+
+    /// Returns the number of TxOuts in blocks of each block version.
+    fn get_tx_out_counts_by_block_version_helper(
+        &mut self,
+    ) -> Result<TxOutCountsByBlockVersionResponse, mc_ledger_db::Error> {
+        let mut response = TxOutCountsByBlockVersionResponse::new();
+        response.set_tx_out_counts(HashMap::from_iter(
+            self.ledger.get_tx_out_counts_by_block_version()?,
+        ));
+        Ok(response)
+    }
+}
+
+fn token_event_to_proto(event: &TokenEvent) -> consensus_common::TokenEvent {
+    let mut proto = consensus_common::TokenEvent::new();
+    proto.set_block_index(event.block_index);
+    proto.set_token_id(event.token_id);
+    proto.set_value(event.value);
+    match &event.kind {
+        Some(TokenEventKind::Mint(nonce)) => proto.set_mint_tx_nonce(nonce.clone()),
+        Some(TokenEventKind::Burn(tx_out_index)) => proto.set_burned_tx_out_index(*tx_out_index),
+        None => {}
+    }
+    proto
+}
+
+/// Queries that need the secondary indexes fail with FAILED_PRECONDITION when
+/// the ledger does not have them.
+fn ledger_err_to_rpc_status(err: mc_ledger_db::Error, logger: &Logger) -> RpcStatus {
+    match err {
+        mc_ledger_db::Error::SecondaryIndexesNotBuilt => {
+            rpc_precondition_error("ledger", err, logger)
+        }
+        err => rpc_database_err(err, logger),
+    }
 }
 
 impl<L: Ledger + Clone> BlockchainApi for BlockchainApiService<L> {
@@ -177,6 +284,73 @@ impl<L: Ledger + Clone> BlockchainApi for BlockchainApiService<L> {
             send_result(ctx, sink, resp, logger);
         });
     }
+
+    /// Gets the Blocks whose signature timestamp is in a given range.
+    fn get_blocks_by_timestamp(
+        &mut self,
+        ctx: RpcContext,
+        request: BlocksByTimestampRequest,
+        sink: UnarySink<BlocksResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+                return send_result(ctx, sink, err.into(), logger);
+            }
+
+            let resp = self
+                .get_blocks_by_timestamp_helper(
+                    request.start_timestamp,
+                    request.end_timestamp,
+                    request.limit,
+                )
+                .map_err(|err| ledger_err_to_rpc_status(err, logger));
+            send_result(ctx, sink, resp, logger);
+        });
+    }
+
+    /// Gets the mint and burn events of a token in a range of Blocks.
+    fn get_token_events(
+        &mut self,
+        ctx: RpcContext,
+        request: TokenEventsRequest,
+        sink: UnarySink<TokenEventsResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+                return send_result(ctx, sink, err.into(), logger);
+            }
+
+            let resp = self
+                .get_token_events_helper(request.token_id, request.offset, request.limit)
+                .map_err(|err| ledger_err_to_rpc_status(err, logger));
+            send_result(ctx, sink, resp, logger);
+        });
+    }
+
+    /// Gets the number of TxOuts in Blocks of each block version.
+    fn get_tx_out_counts_by_block_version(
+        &mut self,
+        ctx: RpcContext,
+        _request: Empty,
+        sink: UnarySink<TxOutCountsByBlockVersionResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            if let Err(err) = self.authenticator.authenticate_rpc(&ctx) {
+                return send_result(ctx, sink, err.into(), logger);
+            }
+
+            let resp = self
+                .get_tx_out_counts_by_block_version_helper()
+                .map_err(|err| ledger_err_to_rpc_status(err, logger));
+            send_result(ctx, sink, resp, logger);
+        });
+    }
 }
 
 #[cfg(test)]
@@ -185,6 +359,7 @@ mod tests {
     use grpcio::{ChannelBuilder, Environment, Error as GrpcError, Server, ServerBuilder};
     use mc_common::{logger::test_with_logger, time::SystemTimeProvider};
     use mc_consensus_api::consensus_common_grpc::{self, BlockchainApiClient};
+    use mc_consensus_service_config::CongestionFeePolicy;
     use mc_ledger_db::test_utils::{create_ledger, initialize_ledger};
     use mc_transaction_core::TokenId;
     use mc_transaction_core_test_utils::AccountKey;
@@ -243,6 +418,42 @@ mod tests {
         assert_eq!(block_response, expected_response);
     }
 
+    #[test_with_logger]
+    // `get_last_block_info` should report the congestion-based minimum fees.
+    fn test_get_last_block_info_with_congestion_fee(logger: Logger) {
+        let fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 4000000000), (TokenId::from(60), 128000)]).unwrap();
+
+        let mut ledger_db = create_ledger();
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let account_key = AccountKey::random(&mut rng);
+        // Every block but the origin block spends one input.
+        initialize_ledger(BlockVersion::MAX, &mut ledger_db, 3, &account_key, &mut rng);
+        let congestion_fee = CongestionFee::new(
+            ledger_db.clone(),
+            CongestionFeePolicy {
+                enabled: true,
+                full_block_inputs: 1,
+                increase_percent: 50,
+                ..Default::default()
+            }
+            .config(),
+        );
+
+        let mut blockchain_api_service =
+            BlockchainApiService::new(ledger_db, authenticator, fee_map, BlockVersion::MAX, logger)
+                .with_congestion_fee(congestion_fee);
+
+        // Two full blocks raise the fee level to 128 * 1.5 * 1.5 = 288.
+        let block_response = blockchain_api_service.get_last_block_info_helper().unwrap();
+        assert_eq!(block_response.get_mob_minimum_fee(), 9000000000);
+        assert_eq!(
+            block_response.get_minimum_fees(),
+            &HashMap::from_iter([(0, 9000000000), (60, 288000)])
+        );
+    }
+
     #[test_with_logger]
     // `get_last_block_info` should reject unauthenticated responses when configured
     // with an authenticator.
@@ -397,6 +608,115 @@ mod tests {
         assert_eq!(expected_blocks.get(4).unwrap(), blocks.get(4).unwrap());
     }
 
+    #[test_with_logger]
+    // `get_blocks_by_timestamp` should return the blocks signed in the requested
+    // range, at most `max_page_size` of them.
+    fn test_get_blocks_by_timestamp(logger: Logger) {
+        let mut ledger_db = create_ledger();
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let account_key = AccountKey::random(&mut rng);
+        // Test blocks are signed at a timestamp equal to their index.
+        let expected_blocks = initialize_ledger(
+            BlockVersion::MAX,
+            &mut ledger_db,
+            10,
+            &account_key,
+            &mut rng,
+        )
+        .into_iter()
+        .map(|block_data| blockchain::Block::from(block_data.block()))
+        .collect::<Vec<_>>();
+        ledger_db.rebuild_secondary_indexes().unwrap();
+
+        let mut blockchain_api_service = BlockchainApiService::new(
+            ledger_db,
+            authenticator,
+            FeeMap::default(),
+            BlockVersion::MAX,
+            logger,
+        );
+
+        let block_response = blockchain_api_service
+            .get_blocks_by_timestamp_helper(3, 6, 100)
+            .unwrap();
+        assert_eq!(&expected_blocks[3..6], &block_response.blocks[..]);
+
+        blockchain_api_service.set_max_page_size(2);
+        let block_response = blockchain_api_service
+            .get_blocks_by_timestamp_helper(3, 6, 100)
+            .unwrap();
+        assert_eq!(&expected_blocks[3..5], &block_response.blocks[..]);
+    }
+
+    #[test_with_logger]
+    // `get_tx_out_counts_by_block_version` should count the TxOuts of each block
+    // version.
+    fn test_get_tx_out_counts_by_block_version(logger: Logger) {
+        let mut ledger_db = create_ledger();
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let account_key = AccountKey::random(&mut rng);
+        let blocks_data = initialize_ledger(
+            BlockVersion::MAX,
+            &mut ledger_db,
+            10,
+            &account_key,
+            &mut rng,
+        );
+        ledger_db.rebuild_secondary_indexes().unwrap();
+
+        let mut expected_counts = HashMap::new();
+        for block_data in &blocks_data {
+            *expected_counts
+                .entry(block_data.block().version)
+                .or_insert(0) += block_data.contents().outputs.len() as u64;
+        }
+
+        let mut blockchain_api_service = BlockchainApiService::new(
+            ledger_db,
+            authenticator,
+            FeeMap::default(),
+            BlockVersion::MAX,
+            logger,
+        );
+
+        let response = blockchain_api_service
+            .get_tx_out_counts_by_block_version_helper()
+            .unwrap();
+        assert_eq!(&expected_counts, response.get_tx_out_counts());
+    }
+
+    #[test_with_logger]
+    // Queries that need the secondary indexes should fail with FAILED_PRECONDITION
+    // when the ledger does not have them.
+    fn test_secondary_index_queries_require_indexes(logger: Logger) {
+        let ledger_db = create_ledger();
+        let authenticator = Arc::new(AnonymousAuthenticator::default());
+
+        let blockchain_api_service = BlockchainApiService::new(
+            ledger_db,
+            authenticator,
+            FeeMap::default(),
+            BlockVersion::MAX,
+            logger,
+        );
+
+        let (client, _server) = get_client_server(blockchain_api_service);
+
+        match client.get_token_events(&TokenEventsRequest::default()) {
+            Ok(response) => {
+                panic!("Unexpected response {:?}", response);
+            }
+            Err(GrpcError::RpcFailure(rpc_status)) => {
+                assert_eq!(rpc_status.code(), RpcStatusCode::FAILED_PRECONDITION);
+            }
+            Err(err) => {
+                panic!("Unexpected error {:?}", err);
+            }
+        }
+    }
+
     #[test_with_logger]
     // `get_blocks` should reject unauthenticated responses when configured with an
     // authenticator.
@@ -11,6 +11,7 @@ use mc_common::{
 };
 use mc_consensus_enclave::{BlockchainConfig, ConsensusServiceSgxEnclave, ENCLAVE_FILE};
 use mc_consensus_service::{
+    congestion_fee::CongestionFee,
     consensus_service::{ConsensusService, ConsensusServiceError},
     mint_tx_manager::MintTxManagerImpl,
     tx_manager::TxManagerImpl,
@@ -70,6 +71,7 @@ fn main() -> Result<(), ConsensusServiceError> {
         governors_map: governors_map.clone(),
         governors_signature: config.tokens().governors_signature,
         block_version: config.block_version,
+        congestion_fee: config.congestion_fee_policy.config(),
     };
 
     let enclave_path = env::current_exe()
@@ -95,7 +97,13 @@ fn main() -> Result<(), ConsensusServiceError> {
 
     setup_ledger_dir(&config.origin_block_path, &config.ledger_path);
 
-    let local_ledger = LedgerDB::open(&config.ledger_path).expect("Failed creating LedgerDB");
+    let mut local_ledger = LedgerDB::open(&config.ledger_path).expect("Failed creating LedgerDB");
+    if config.ledger_secondary_indexes && !local_ledger.has_secondary_indexes() {
+        log::info!(logger, "Building ledger secondary indexes");
+        local_ledger
+            .rebuild_secondary_indexes()
+            .expect("Failed building ledger secondary indexes");
+    }
 
     let ias_client = Client::new(&config.ias_api_key).expect("Could not create IAS client");
 
@@ -108,9 +116,20 @@ fn main() -> Result<(), ConsensusServiceError> {
         log::debug!(logger, "Enclave will be started in production mode");
     }
 
+    let congestion_fee =
+        CongestionFee::new(local_ledger.clone(), config.congestion_fee_policy.config());
+    if congestion_fee.is_enabled() {
+        log::info!(
+            logger,
+            "Congestion-based minimum fees enabled: {:?}",
+            config.congestion_fee_policy
+        );
+    }
+
     let tx_manager = TxManagerImpl::new(
         enclave.clone(),
-        DefaultTxManagerUntrustedInterfaces::new(local_ledger.clone()),
+        DefaultTxManagerUntrustedInterfaces::new(local_ledger.clone())
+            .with_congestion_fee(congestion_fee),
         logger.clone(),
     );
 
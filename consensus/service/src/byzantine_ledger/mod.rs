@@ -23,6 +23,7 @@ use mc_common::{logger::Logger, NodeID, ResponderId};
 use mc_connection::{BlockchainConnection, ConnectionManager};
 use mc_consensus_enclave::ConsensusEnclave;
 use mc_consensus_scp::{scp_log::LoggingScpNode, Node, QuorumSet, ScpNode};
+use mc_consensus_service_config::PendingValuesPolicy;
 use mc_crypto_keys::Ed25519Pair;
 use mc_ledger_db::Ledger;
 use mc_ledger_sync::{LedgerSyncService, ReqwestTransactionsFetcher};
@@ -107,6 +108,8 @@ impl ByzantineLedger {
     /// * `tx_source_urls` - Source URLs for fetching block contents.
     /// * `scp_debug_dir` - If Some, debugging info will be written in this
     ///   directory.
+    /// * `pending_values_policy` - How pending values are chosen for
+    ///   nomination.
     /// * `logger` - Logger.
     pub fn new<
         PC: BlockchainConnection + ConsensusConnection + 'static,
@@ -126,6 +129,7 @@ impl ByzantineLedger {
         msg_signer_key: Arc<Ed25519Pair>,
         tx_source_urls: Vec<String>,
         scp_debug_dir: Option<PathBuf>,
+        pending_values_policy: PendingValuesPolicy,
         logger: Logger,
     ) -> Self {
         // TODO: this should be passed in as an argument.
@@ -241,6 +245,7 @@ impl ByzantineLedger {
                 is_behind.clone(),
                 highest_peer_block.clone(),
                 highest_issued_msg.clone(),
+                pending_values_policy,
                 logger,
             );
 
@@ -479,6 +484,7 @@ mod tests {
             msg_signer_key,
             Vec::new(),
             None,
+            PendingValuesPolicy::default(),
             logger.clone(),
         );
 
@@ -572,6 +578,7 @@ mod tests {
             local_signer_key.clone(),
             Vec::new(),
             None,
+            PendingValuesPolicy::default(),
             logger.clone(),
         );
 
@@ -954,6 +961,7 @@ mod tests {
             local_signer_key.clone(),
             Vec::new(),
             None,
+            PendingValuesPolicy::default(),
             logger.clone(),
         );
 
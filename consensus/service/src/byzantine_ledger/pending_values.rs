@@ -3,13 +3,20 @@
 //! A utility object for keeping track of pending transaction hashes.
 
 use crate::{mint_tx_manager::MintTxManager, tx_manager::TxManager};
+use mc_consensus_service_config::{PendingValuesOrdering, PendingValuesPolicy};
 use mc_peers::ConsensusValue;
+use mc_transaction_core::tx::TxHash;
 use std::{
+    cmp::{max, Reverse},
     collections::{hash_map::Entry::Vacant, HashMap},
     sync::Arc,
     time::Instant,
 };
 
+/// Fee rates are scaled by this factor so that integer division keeps enough
+/// precision to tell small transactions apart.
+const FEE_RATE_SCALE: u128 = 1_000_000;
+
 /// A list of transactions that this node will attempt to submit to consensus.
 /// Invariant: each pending transaction is well-formed.
 /// Invariant: each pending transaction is valid w.r.t he current ledger.
@@ -36,16 +43,45 @@ pub struct PendingValues<TXM: TxManager, MTXM: MintTxManager> {
     /// saw a value, and not when a specific node saw it.
     pending_values: Vec<ConsensusValue>,
     pending_values_map: HashMap<ConsensusValue, Option<Instant>>,
+
+    /// Policy for choosing which values to propose.
+    policy: PendingValuesPolicy,
+
+    /// Fee rate and arrival time of each pending transaction. Only populated
+    /// when the policy orders transactions by fee.
+    tx_fees: HashMap<TxHash, TxFee>,
+}
+
+/// What a fee-based ordering needs to know about a pending transaction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct TxFee {
+    /// Fee per byte or per input, scaled by `FEE_RATE_SCALE`.
+    fee_rate: u128,
+
+    /// When the transaction was submitted by a client, or when this node
+    /// first saw it if it was relayed by a peer.
+    received_at: Instant,
 }
 
 impl<TXM: TxManager, MTXM: MintTxManager> PendingValues<TXM, MTXM> {
-    /// Create a new instance of `PendingValues`.
+    /// Create a new instance of `PendingValues`, with the default policy.
     pub fn new(tx_manager: Arc<TXM>, mint_tx_manager: Arc<MTXM>) -> Self {
+        Self::with_policy(tx_manager, mint_tx_manager, PendingValuesPolicy::default())
+    }
+
+    /// Create a new instance of `PendingValues`.
+    pub fn with_policy(
+        tx_manager: Arc<TXM>,
+        mint_tx_manager: Arc<MTXM>,
+        policy: PendingValuesPolicy,
+    ) -> Self {
         Self {
             tx_manager,
             mint_tx_manager,
             pending_values: Vec::new(),
             pending_values_map: HashMap::new(),
+            policy,
+            tx_fees: HashMap::new(),
         }
     }
 
@@ -77,6 +113,10 @@ impl<TXM: TxManager, MTXM: MintTxManager> PendingValues<TXM, MTXM> {
                         // The transaction is well-formed and valid.
                         entry.insert(timestamp);
                         self.pending_values.push(value);
+                        if self.policy.ordering.is_fee_based() {
+                            let fee = self.tx_fee(&tx_hash, timestamp.unwrap_or_else(Instant::now));
+                            self.tx_fees.insert(tx_hash, fee);
+                        }
                         true
                     } else {
                         false
@@ -119,6 +159,106 @@ impl<TXM: TxManager, MTXM: MintTxManager> PendingValues<TXM, MTXM> {
         self.pending_values.iter()
     }
 
+    /// Get the number of pending mint transactions.
+    pub fn num_mint_values(&self) -> usize {
+        self.pending_values
+            .iter()
+            .filter(|value| !matches!(value, ConsensusValue::TxHash(_)))
+            .count()
+    }
+
+    /// Choose up to `max_values` values to propose for nomination, according
+    /// to the policy.
+    ///
+    /// With arrival ordering, values are proposed in the order they arrived.
+    /// With a fee-based ordering, mint transactions come first, in arrival
+    /// order, up to the policy's mint quota, and transactions fill the
+    /// remaining space by decreasing fee rate. A transaction's priority grows
+    /// by its fee rate for every aging period it waited, and ties are broken by
+    /// arrival order.
+    pub fn values_to_propose(&self, max_values: usize, now: Instant) -> Vec<ConsensusValue> {
+        if !self.policy.ordering.is_fee_based() {
+            return self
+                .pending_values
+                .iter()
+                .take(max_values)
+                .cloned()
+                .collect();
+        }
+
+        let (mint_values, tx_values): (Vec<_>, Vec<_>) = self
+            .pending_values
+            .iter()
+            .partition(|value| !matches!(value, ConsensusValue::TxHash(_)));
+
+        let mut values: Vec<ConsensusValue> = mint_values
+            .into_iter()
+            .take(self.policy.max_mint_values.min(max_values))
+            .cloned()
+            .collect();
+
+        // The sort is stable, so equal priorities keep arrival order.
+        let mut tx_values = tx_values;
+        tx_values.sort_by_cached_key(|value| Reverse(self.priority(value, now)));
+        values.extend(
+            tx_values
+                .into_iter()
+                .take(max_values - values.len())
+                .cloned(),
+        );
+
+        values
+    }
+
+    /// Get the fee rate of a pending transaction, scaled by `FEE_RATE_SCALE`.
+    /// Only available when the policy orders transactions by fee.
+    pub fn fee_rate(&self, value: &ConsensusValue) -> Option<u128> {
+        match value {
+            ConsensusValue::TxHash(tx_hash) => self.tx_fees.get(tx_hash).map(|fee| fee.fee_rate),
+            _ => None,
+        }
+    }
+
+    // Compute the fee rate of a transaction from its well-formed context.
+    fn tx_fee(&self, tx_hash: &TxHash, received_at: Instant) -> TxFee {
+        let fee_rate = match self.tx_manager.get_context_and_size(tx_hash) {
+            Some((context, size)) => {
+                let divisor = match self.policy.ordering {
+                    PendingValuesOrdering::FeePerInput => context.key_images().len(),
+                    _ => size,
+                };
+                context.priority() as u128 * FEE_RATE_SCALE / max(divisor, 1) as u128
+            }
+            None => 0,
+        };
+
+        TxFee {
+            fee_rate,
+            received_at,
+        }
+    }
+
+    // The fee rate of a pending transaction, grown by one fee rate for every
+    // aging period it has been waiting.
+    fn priority(&self, value: &ConsensusValue, now: Instant) -> u128 {
+        let tx_fee = match value {
+            ConsensusValue::TxHash(tx_hash) => match self.tx_fees.get(tx_hash) {
+                Some(tx_fee) => tx_fee,
+                None => return 0,
+            },
+            _ => return 0,
+        };
+
+        let aging_period = self.policy.aging_period.as_millis();
+        if aging_period == 0 {
+            return tx_fee.fee_rate;
+        }
+        let waited = now
+            .saturating_duration_since(tx_fee.received_at)
+            .as_millis();
+        tx_fee.fee_rate * (aging_period + waited) / aging_period
+    }
+
     /// Try and get the timestamp associated with a given value.
     pub fn get_timestamp_for_value(&self, tx_hash: &ConsensusValue) -> Option<Instant> {
         self.pending_values_map.get(tx_hash).cloned().flatten()
@@ -136,6 +276,9 @@ impl<TXM: TxManager, MTXM: MintTxManager> PendingValues<TXM, MTXM> {
         let self_pending_values_map = &self.pending_values_map;
         self.pending_values
             .retain(|tx_hash| self_pending_values_map.contains_key(tx_hash));
+        self.tx_fees.retain(|tx_hash, _| {
+            self_pending_values_map.contains_key(&ConsensusValue::TxHash(*tx_hash))
+        });
 
         // Invariant
         assert_eq!(self.pending_values_map.len(), self.pending_values.len());
@@ -164,9 +307,46 @@ mod tests {
         mint_tx_manager::MockMintTxManager,
         tx_manager::{MockTxManager, TxManagerError},
     };
-    use mc_transaction_core::{tx::TxHash, validation::TransactionValidationError};
+    use mc_consensus_enclave::WellFormedTxContext;
+    use mc_crypto_keys::Ed25519Pair;
+    use mc_transaction_core::{
+        ring_signature::KeyImage, validation::TransactionValidationError, TokenId,
+    };
+    use mc_transaction_core_test_utils::create_mint_tx;
+    use mc_util_from_random::FromRandom;
     use mockall::predicate::eq;
-    use std::collections::HashSet;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::{collections::HashSet, time::Duration};
+
+    /// A tx manager where every transaction is valid, and each transaction has
+    /// the given priority, number of inputs and encrypted size.
+    fn tx_manager_with_fees(txs: &[(TxHash, u64, usize, usize)]) -> MockTxManager {
+        let mut tx_manager = MockTxManager::new();
+        tx_manager.expect_validate().return_const(Ok(()));
+        for (tx_hash, priority, num_inputs, size) in txs.iter().cloned() {
+            let context = WellFormedTxContext::new(
+                priority,
+                tx_hash,
+                0,
+                (0..num_inputs as u64).map(KeyImage::from).collect(),
+                vec![],
+                vec![],
+            );
+            tx_manager
+                .expect_get_context_and_size()
+                .with(eq(tx_hash))
+                .return_const(Some((Arc::new(context), size)));
+        }
+        tx_manager
+    }
+
+    fn fee_policy(ordering: PendingValuesOrdering, aging_period: Duration) -> PendingValuesPolicy {
+        PendingValuesPolicy {
+            ordering,
+            aging_period,
+            ..Default::default()
+        }
+    }
 
     #[test]
     /// Should only allow valid values to be pushed.
@@ -309,4 +489,167 @@ mod tests {
             HashSet::from_iter(expected_pending_values),
         );
     }
+
+    #[test]
+    /// Fee-based orderings should propose the highest fee rates first, keeping
+    /// arrival order between equal fee rates.
+    fn test_values_to_propose_orders_by_fee_rate() {
+        let tx_hashes = vec![TxHash([1u8; 32]), TxHash([2u8; 32]), TxHash([3u8; 32])];
+        // (tx hash, priority, number of inputs, encrypted size)
+        let txs = vec![
+            (tx_hashes[0], 100, 1, 100),
+            (tx_hashes[1], 300, 3, 100),
+            (tx_hashes[2], 300, 1, 300),
+        ];
+
+        // By fee per byte, the second transaction pays the most, and the first
+        // and last pay the same.
+        let mut pending_values = PendingValues::with_policy(
+            Arc::new(tx_manager_with_fees(&txs)),
+            Arc::new(MockMintTxManager::new()),
+            fee_policy(PendingValuesOrdering::FeePerByte, Duration::ZERO),
+        );
+        for tx_hash in &tx_hashes {
+            assert!(pending_values.push((*tx_hash).into(), None));
+        }
+        assert_eq!(
+            pending_values.values_to_propose(10, Instant::now()),
+            vec![
+                tx_hashes[1].into(),
+                tx_hashes[0].into(),
+                tx_hashes[2].into()
+            ]
+        );
+        assert_eq!(
+            pending_values.values_to_propose(1, Instant::now()),
+            vec![tx_hashes[1].into()]
+        );
+
+        // By fee per input, the last transaction pays the most, and the first two
+        // pay the same.
+        let mut pending_values = PendingValues::with_policy(
+            Arc::new(tx_manager_with_fees(&txs)),
+            Arc::new(MockMintTxManager::new()),
+            fee_policy(PendingValuesOrdering::FeePerInput, Duration::ZERO),
+        );
+        for tx_hash in &tx_hashes {
+            assert!(pending_values.push((*tx_hash).into(), None));
+        }
+        assert_eq!(
+            pending_values.values_to_propose(10, Instant::now()),
+            vec![
+                tx_hashes[2].into(),
+                tx_hashes[0].into(),
+                tx_hashes[1].into()
+            ]
+        );
+        assert_eq!(
+            pending_values.fee_rate(&tx_hashes[2].into()),
+            Some(300 * FEE_RATE_SCALE)
+        );
+    }
+
+    #[test]
+    /// Transactions that waited long enough should overtake higher fee
+    /// transactions.
+    fn test_values_to_propose_ages_waiting_transactions() {
+        let low_fee = TxHash([1u8; 32]);
+        let high_fee = TxHash([2u8; 32]);
+        let txs = vec![(low_fee, 100, 1, 100), (high_fee, 1000, 1, 100)];
+
+        let mut pending_values = PendingValues::with_policy(
+            Arc::new(tx_manager_with_fees(&txs)),
+            Arc::new(MockMintTxManager::new()),
+            fee_policy(PendingValuesOrdering::FeePerByte, Duration::from_secs(10)),
+        );
+
+        // The low fee transaction was submitted 100 seconds before the high fee
+        // one. By then, its fee rate has grown elevenfold, past the high fee rate.
+        let received_at = Instant::now();
+        let now = received_at + Duration::from_secs(100);
+        assert!(pending_values.push(low_fee.into(), Some(received_at)));
+        assert!(pending_values.push(high_fee.into(), Some(now)));
+
+        assert_eq!(
+            pending_values.values_to_propose(10, received_at),
+            vec![high_fee.into(), low_fee.into()]
+        );
+        assert_eq!(
+            pending_values.values_to_propose(10, now),
+            vec![low_fee.into(), high_fee.into()]
+        );
+    }
+
+    #[test]
+    /// With a fee-based ordering, mint transactions should be proposed first,
+    /// up to their quota. With arrival ordering, all values keep arrival
+    /// order.
+    fn test_values_to_propose_limits_mint_transactions() {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let signers = [Ed25519Pair::from_random(&mut rng)];
+        let mint_values: Vec<ConsensusValue> = (0..3)
+            .map(|_| {
+                ConsensusValue::MintTx(create_mint_tx(TokenId::from(1), &signers, 10, &mut rng))
+            })
+            .collect();
+        let tx_hashes = vec![TxHash([1u8; 32]), TxHash([2u8; 32])];
+        // Both transactions pay the same fee rate.
+        let txs = vec![(tx_hashes[0], 100, 1, 100), (tx_hashes[1], 100, 1, 100)];
+
+        let new_pending_values = |policy: PendingValuesPolicy| {
+            let mut mint_tx_manager = MockMintTxManager::new();
+            mint_tx_manager
+                .expect_validate_mint_tx()
+                .return_const(Ok(()));
+            let mut pending_values = PendingValues::with_policy(
+                Arc::new(tx_manager_with_fees(&txs)),
+                Arc::new(mint_tx_manager),
+                policy,
+            );
+            assert!(pending_values.push(tx_hashes[0].into(), None));
+            for value in &mint_values {
+                assert!(pending_values.push(value.clone(), None));
+            }
+            assert!(pending_values.push(tx_hashes[1].into(), None));
+            assert_eq!(pending_values.num_mint_values(), 3);
+            pending_values
+        };
+
+        let pending_values = new_pending_values(PendingValuesPolicy {
+            max_mint_values: 2,
+            ..fee_policy(PendingValuesOrdering::FeePerByte, Duration::ZERO)
+        });
+        assert_eq!(
+            pending_values.values_to_propose(10, Instant::now()),
+            vec![
+                mint_values[0].clone(),
+                mint_values[1].clone(),
+                tx_hashes[0].into(),
+                tx_hashes[1].into()
+            ]
+        );
+        assert_eq!(
+            pending_values.values_to_propose(1, Instant::now()),
+            vec![mint_values[0].clone()]
+        );
+
+        let pending_values = new_pending_values(PendingValuesPolicy {
+            max_mint_values: 2,
+            ..Default::default()
+        });
+        assert_eq!(
+            pending_values.values_to_propose(10, Instant::now()),
+            vec![
+                tx_hashes[0].into(),
+                mint_values[0].clone(),
+                mint_values[1].clone(),
+                mint_values[2].clone(),
+                tx_hashes[1].into()
+            ]
+        );
+        assert_eq!(
+            pending_values.values_to_propose(1, Instant::now()),
+            vec![tx_hashes[0].into()]
+        );
+    }
 }
@@ -20,6 +20,7 @@ use mc_connection::{
 };
 use mc_consensus_enclave::{ConsensusEnclave, FormBlockInputs};
 use mc_consensus_scp::{slot::Phase, Msg, ScpNode, SlotIndex};
+use mc_consensus_service_config::{PendingValuesOrdering, PendingValuesPolicy};
 use mc_crypto_keys::Ed25519Pair;
 use mc_ledger_db::Ledger;
 use mc_ledger_sync::{LedgerSync, NetworkState, SCPNetworkState};
@@ -142,6 +143,8 @@ impl<
     ///   network agrees on.
     /// * `highest_issued_msg` - Worker sets to highest consensus message issued
     ///   by this node.
+    /// * `pending_values_policy` - How pending values are chosen for
+    ///   nomination.
     /// * `logger` - Logger instance.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -158,12 +161,19 @@ impl<
         is_behind: Arc<AtomicBool>,
         highest_peer_block: Arc<AtomicU64>,
         highest_issued_msg: Arc<Mutex<Option<ConsensusMsg>>>,
+        pending_values_policy: PendingValuesPolicy,
         logger: Logger,
     ) -> Self {
         let current_slot_index = ledger.num_blocks().unwrap();
 
         let network_state = SCPNetworkState::new(scp_node.node_id(), scp_node.quorum_set());
 
+        counters::PENDING_VALUES_ORDERING.set(match pending_values_policy.ordering {
+            PendingValuesOrdering::Arrival => 0,
+            PendingValuesOrdering::FeePerByte => 1,
+            PendingValuesOrdering::FeePerInput => 2,
+        });
+
         Self {
             enclave,
             tasks,
@@ -180,7 +190,11 @@ impl<
             logger,
             current_slot_index,
             pending_consensus_msgs: Default::default(),
-            pending_values: PendingValues::new(tx_manager, mint_tx_manager),
+            pending_values: PendingValues::with_policy(
+                tx_manager,
+                mint_tx_manager,
+                pending_values_policy,
+            ),
             need_nominate: false,
             network_state,
             ledger_sync_service,
@@ -433,14 +447,22 @@ impl<
         assert!(!self.pending_values.is_empty());
 
         // Fairness heuristics:
-        // * Values are proposed in the order that they were received.
+        // * Mint transactions are proposed in the order that they were received, up to
+        //   the policy's quota.
+        // * Transactions are proposed in the order that they were received, or by fee
+        //   rate with aging, depending on the policy.
         // * Each node limits the total number of values it proposes per slot.
         let values = self
             .pending_values
+            .values_to_propose(MAX_PENDING_VALUES_TO_NOMINATE, Instant::now());
+        if let Some(fee_rate) = values
             .iter()
-            .take(MAX_PENDING_VALUES_TO_NOMINATE)
-            .cloned()
-            .collect();
+            .filter_map(|value| self.pending_values.fee_rate(value))
+            .min()
+        {
+            counters::LAST_PROPOSED_MIN_FEE_RATE.set(fee_rate as i64);
+        }
+        let values = values.into_iter().collect();
 
         let msg_opt = self
             .scp_node
@@ -797,6 +819,7 @@ impl<
     fn update_current_slot_metrics(&mut self) {
         let slot_metrics = self.scp_node.get_current_slot_metrics();
         counters::CUR_NUM_PENDING_VALUES.set(self.pending_values.len() as i64);
+        counters::CUR_NUM_PENDING_MINT_VALUES.set(self.pending_values.num_mint_values() as i64);
         counters::CUR_SLOT_NUM.set(self.current_slot_index as i64);
         counters::CUR_SLOT_PHASE.set(match &slot_metrics.phase {
             Phase::NominatePrepare => 2,
@@ -1039,6 +1062,7 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            PendingValuesPolicy::default(),
             logger,
         );
 
@@ -1091,6 +1115,7 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            PendingValuesPolicy::default(),
             logger,
         );
 
@@ -1208,6 +1233,7 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            PendingValuesPolicy::default(),
             logger,
         );
 
@@ -1269,6 +1295,7 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            PendingValuesPolicy::default(),
             logger,
         );
 
@@ -1370,6 +1397,7 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            PendingValuesPolicy::default(),
             logger,
         );
 
@@ -1456,6 +1484,7 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            PendingValuesPolicy::default(),
             logger,
         );
 
@@ -1553,6 +1582,7 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            PendingValuesPolicy::default(),
             logger,
         );
 
@@ -1702,6 +1732,7 @@ mod tests {
             Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicU64::new(0)),
             Arc::new(Mutex::new(Option::<ConsensusMsg>::None)),
+            PendingValuesPolicy::default(),
             logger,
         );
 
@@ -0,0 +1,223 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Congestion-based minimum fee.
+//!
+//! While the network is congested, the minimum fee of every token is raised by
+//! a common factor, the "fee level". The fee level is computed from the most
+//! recent blocks in the ledger: starting from the configured minimum fees, it
+//! grows after every full block and decays after every other block. Nodes
+//! that agree on the ledger therefore agree on the fee level, and a
+//! transaction is valid for a slot on every node or on none of them.
+//!
+//! Only block fullness is used. A node's pending values are not visible to
+//! its peers, and the fee level must not depend on anything the nodes may
+//! disagree on, so the depth of the pending values queue is not a trigger. A
+//! growing backlog is instead observed through the blocks it produces: a deep
+//! queue yields a run of full blocks.
+//!
+//! The parameters are part of the enclave's blockchain config, so nodes that
+//! use different parameters have different responder ids and do not peer.
+//!
+//! Fee levels are expressed in the same unit as a transaction's priority: a
+//! transaction that pays exactly the configured minimum fee of its token has
+//! priority `BASE_FEE_LEVEL`, regardless of the token.
+
+use crate::counters;
+use mc_consensus_enclave::{CongestionFeeConfig, FeeMap, SMALLEST_MINIMUM_FEE_LOG2};
+use mc_ledger_db::{Error as LedgerError, Ledger};
+use mc_transaction_core::TokenId;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// The fee level at which minimum fees are the configured minimum fees.
+pub const BASE_FEE_LEVEL: u64 = 1 << SMALLEST_MINIMUM_FEE_LOG2;
+
+/// The fee level that follows `fee_level` after a block with `num_inputs`
+/// transaction inputs.
+pub fn next_fee_level(config: &CongestionFeeConfig, fee_level: u64, num_inputs: usize) -> u64 {
+    let max_fee_level = BASE_FEE_LEVEL.saturating_mul(config.max_multiplier);
+    let next_fee_level = if num_inputs as u64 >= config.full_block_inputs {
+        // Round up, so that any increase is at least one unit.
+        (fee_level.saturating_mul(100 + config.increase_percent) + 99) / 100
+    } else {
+        fee_level.saturating_mul(100 - config.decrease_percent.min(100)) / 100
+    };
+    next_fee_level.clamp(BASE_FEE_LEVEL, max_fee_level.max(BASE_FEE_LEVEL))
+}
+
+/// The minimum fee of a token at `fee_level`, given its configured minimum
+/// fee.
+pub fn minimum_fee_at_level(minimum_fee: u64, fee_level: u64) -> u64 {
+    (minimum_fee >> SMALLEST_MINIMUM_FEE_LOG2).saturating_mul(fee_level)
+}
+
+/// Computes the fee level of a ledger.
+#[derive(Clone)]
+pub struct CongestionFee<L: Ledger> {
+    /// The ledger.
+    ledger: L,
+
+    /// Congestion fee parameters, or `None` if the congestion fee is
+    /// disabled.
+    config: Option<CongestionFeeConfig>,
+
+    /// The number of blocks in the ledger when the fee level was last
+    /// computed, and that fee level.
+    cached_fee_level: Arc<Mutex<Option<(u64, u64)>>>,
+}
+
+impl<L: Ledger> CongestionFee<L> {
+    /// Create a new CongestionFee, following the congestion fee parameters of
+    /// the blockchain config.
+    pub fn new(ledger: L, config: Option<CongestionFeeConfig>) -> Self {
+        Self {
+            ledger,
+            config,
+            cached_fee_level: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// True if minimum fees are raised while the network is congested.
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    /// The fee level for the next block. Always `BASE_FEE_LEVEL` when the
+    /// congestion fee is disabled.
+    pub fn fee_level(&self) -> Result<u64, LedgerError> {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return Ok(BASE_FEE_LEVEL),
+        };
+
+        let num_blocks = self.ledger.num_blocks()?;
+        let mut cached_fee_level = self.cached_fee_level.lock().expect("mutex poisoned");
+        if let Some((cached_num_blocks, fee_level)) = *cached_fee_level {
+            if cached_num_blocks == num_blocks {
+                return Ok(fee_level);
+            }
+        }
+
+        let mut fee_level = BASE_FEE_LEVEL;
+        for block_index in num_blocks.saturating_sub(config.window)..num_blocks {
+            let block_contents = self.ledger.get_block_contents(block_index)?;
+            fee_level = next_fee_level(config, fee_level, block_contents.key_images.len());
+        }
+
+        *cached_fee_level = Some((num_blocks, fee_level));
+        counters::CONGESTION_FEE_LEVEL.set(fee_level as i64);
+        Ok(fee_level)
+    }
+
+    /// The minimum fee of each token in `fee_map` at the current fee level.
+    pub fn minimum_fees(&self, fee_map: &FeeMap) -> Result<HashMap<TokenId, u64>, LedgerError> {
+        let fee_level = self.fee_level()?;
+        Ok(fee_map
+            .iter()
+            .map(|(token_id, fee)| (*token_id, minimum_fee_at_level(*fee, fee_level)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mc_blockchain_types::BlockContents;
+    use mc_ledger_db::MockLedger;
+    use mc_transaction_core::{ring_signature::KeyImage, tokens::Mob, Token};
+
+    fn enabled_config() -> CongestionFeeConfig {
+        CongestionFeeConfig {
+            full_block_inputs: 10,
+            window: 8,
+            increase_percent: 50,
+            decrease_percent: 25,
+            max_multiplier: 4,
+        }
+    }
+
+    /// A ledger whose blocks contain `inputs_per_block[block_index]` key
+    /// images.
+    fn mock_ledger(inputs_per_block: Vec<usize>) -> MockLedger {
+        let mut ledger = MockLedger::new();
+        ledger
+            .expect_num_blocks()
+            .return_const(Ok(inputs_per_block.len() as u64));
+        ledger
+            .expect_get_block_contents()
+            .returning(move |block_index| {
+                Ok(BlockContents {
+                    key_images: (0..inputs_per_block[block_index as usize] as u64)
+                        .map(KeyImage::from)
+                        .collect(),
+                    ..Default::default()
+                })
+            });
+        ledger
+    }
+
+    #[test]
+    // The fee level should grow after full blocks, up to the maximum multiplier,
+    // and decay back to the base fee level after other blocks.
+    fn test_next_fee_level() {
+        let config = enabled_config();
+
+        assert_eq!(next_fee_level(&config, BASE_FEE_LEVEL, 9), BASE_FEE_LEVEL);
+        assert_eq!(next_fee_level(&config, BASE_FEE_LEVEL, 10), 192);
+        assert_eq!(next_fee_level(&config, 192, 100), 288);
+        assert_eq!(next_fee_level(&config, 288, 100), 432);
+        assert_eq!(next_fee_level(&config, 432, 100), 4 * BASE_FEE_LEVEL);
+        assert_eq!(
+            next_fee_level(&config, 4 * BASE_FEE_LEVEL, 100),
+            4 * BASE_FEE_LEVEL
+        );
+
+        assert_eq!(next_fee_level(&config, 4 * BASE_FEE_LEVEL, 0), 384);
+        assert_eq!(next_fee_level(&config, 384, 0), 288);
+        assert_eq!(next_fee_level(&config, 150, 0), BASE_FEE_LEVEL);
+    }
+
+    #[test]
+    // Only the blocks inside the window should affect the fee level.
+    fn test_fee_level_uses_recent_blocks() {
+        // Full blocks outside of the window are ignored.
+        let inputs_per_block = vec![100, 100, 100, 0, 0, 0, 0, 0, 0, 10, 10];
+        let congestion_fee =
+            CongestionFee::new(mock_ledger(inputs_per_block), Some(enabled_config()));
+
+        // 128 -> 128 (x6) -> 192 -> 288.
+        assert_eq!(congestion_fee.fee_level().unwrap(), 288);
+        // The cached value is returned while the ledger does not change.
+        assert_eq!(congestion_fee.fee_level().unwrap(), 288);
+    }
+
+    #[test]
+    // A disabled congestion fee should never raise the fee level nor read the
+    // ledger.
+    fn test_fee_level_when_disabled() {
+        let congestion_fee = CongestionFee::new(MockLedger::new(), None);
+        assert!(!congestion_fee.is_enabled());
+        assert_eq!(congestion_fee.fee_level().unwrap(), BASE_FEE_LEVEL);
+    }
+
+    #[test]
+    // Minimum fees should scale with the fee level.
+    fn test_minimum_fees() {
+        let fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 4000000000), (TokenId::from(60), 128000)]).unwrap();
+
+        let congestion_fee = CongestionFee::new(mock_ledger(vec![0, 0]), Some(enabled_config()));
+        assert_eq!(
+            congestion_fee.minimum_fees(&fee_map).unwrap(),
+            HashMap::from_iter([(Mob::ID, 4000000000), (TokenId::from(60), 128000)])
+        );
+
+        let congestion_fee = CongestionFee::new(mock_ledger(vec![0, 10]), Some(enabled_config()));
+        assert_eq!(
+            congestion_fee.minimum_fees(&fee_map).unwrap(),
+            HashMap::from_iter([(Mob::ID, 6000000000), (TokenId::from(60), 192000)])
+        );
+    }
+}
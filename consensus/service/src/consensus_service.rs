@@ -6,6 +6,7 @@ use crate::{
     api::{AttestedApiService, BlockchainApiService, ClientApiService, PeerApiService},
     background_work_queue::BackgroundWorkQueue,
     byzantine_ledger::ByzantineLedger,
+    congestion_fee::CongestionFee,
     counters,
     mint_tx_manager::MintTxManager,
     peer_keepalive::PeerKeepalive,
@@ -354,14 +355,16 @@ impl<
             self.logger.clone(),
         ));
 
-        let blockchain_service =
-            consensus_common_grpc::create_blockchain_api(BlockchainApiService::new(
+        let blockchain_service = consensus_common_grpc::create_blockchain_api(
+            BlockchainApiService::new(
                 self.ledger_db.clone(),
                 self.client_authenticator.clone(),
                 self.config.tokens().fee_map()?,
                 self.config.block_version,
                 self.logger.clone(),
-            ));
+            )
+            .with_congestion_fee(self.create_congestion_fee()),
+        );
 
         let is_serving_user_requests = self.create_is_serving_user_requests_fn();
         let health_check_callback: Arc<dyn Fn(&str) -> HealthCheckStatus + Sync + Send> =
@@ -449,14 +452,16 @@ impl<
             })
         });
 
-        let blockchain_service =
-            consensus_common_grpc::create_blockchain_api(BlockchainApiService::new(
+        let blockchain_service = consensus_common_grpc::create_blockchain_api(
+            BlockchainApiService::new(
                 self.ledger_db.clone(),
                 peer_authenticator.clone(),
                 self.config.tokens().fee_map()?,
                 self.config.block_version,
                 self.logger.clone(),
-            ));
+            )
+            .with_congestion_fee(self.create_congestion_fee()),
+        );
 
         let peer_service = consensus_peer_grpc::create_consensus_peer_api(PeerApiService::new(
             Arc::new(self.enclave.clone()),
@@ -520,6 +525,7 @@ impl<
                 self.config.msg_signer_key.clone(),
                 self.config.network().tx_source_urls,
                 self.config.scp_debug_dump.clone(),
+                self.config.pending_values_policy.clone(),
                 self.logger.clone(),
             ))
             .is_err()
@@ -564,6 +570,15 @@ impl<
         Ok(())
     }
 
+    /// Creates a CongestionFee for the local ledger, following the configured
+    /// policy. The enclave's blockchain config is built from the same policy.
+    fn create_congestion_fee(&self) -> CongestionFee<LedgerDB> {
+        CongestionFee::new(
+            self.ledger_db.clone(),
+            self.config.congestion_fee_policy.config(),
+        )
+    }
+
     /// Creates a function that returns true if the node is currently serving
     /// user requests.
     fn create_is_serving_user_requests_fn(&self) -> Arc<dyn Fn() -> bool + Sync + Send> {
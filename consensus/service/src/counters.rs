@@ -27,6 +27,19 @@ lazy_static::lazy_static! {
     // under a set limit.
     pub static ref CUR_NUM_PENDING_VALUES: IntGauge = OP_COUNTERS.gauge("cur_num_pending_values");
 
+    // Number of pending mint values.
+    pub static ref CUR_NUM_PENDING_MINT_VALUES: IntGauge = OP_COUNTERS.gauge("cur_num_pending_mint_values");
+
+    // Ordering of pending values (0 = arrival, 1 = fee per byte, 2 = fee per input).
+    pub static ref PENDING_VALUES_ORDERING: IntGauge = OP_COUNTERS.gauge("pending_values_ordering");
+
+    // Lowest fee rate among the transactions last proposed for nomination, scaled by 10^6.
+    // Only updated when pending values are ordered by fee.
+    pub static ref LAST_PROPOSED_MIN_FEE_RATE: IntGauge = OP_COUNTERS.gauge("last_proposed_min_fee_rate");
+
+    // Congestion-based fee level, in 1/128ths of the configured minimum fees.
+    pub static ref CONGESTION_FEE_LEVEL: IntGauge = OP_COUNTERS.gauge("congestion_fee_level");
+
     // Current slot number.
     pub static ref CUR_SLOT_NUM: IntGauge = OP_COUNTERS.gauge("cur_slot_num");
 
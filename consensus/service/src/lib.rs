@@ -7,6 +7,7 @@
 #[cfg(test)]
 extern crate test;
 
+pub mod congestion_fee;
 pub mod consensus_service;
 pub mod mint_tx_manager;
 pub mod tx_manager;
@@ -329,6 +329,14 @@ impl<E: ConsensusEnclave + Send, UI: UntrustedInterfaces + Send> TxManager
             .get(tx_hash)
             .map(|entry| entry.encrypted_tx().clone())
     }
+
+    /// Get the context of the transaction corresponding to the given hash,
+    /// along with the size of the encrypted transaction in bytes.
+    fn get_context_and_size(&self, tx_hash: &TxHash) -> Option<(Arc<WellFormedTxContext>, usize)> {
+        self.lock_cache()
+            .get(tx_hash)
+            .map(|entry| (entry.context().clone(), entry.encrypted_tx().0.len()))
+    }
 }
 
 #[cfg(test)]
@@ -939,6 +947,40 @@ mod tests {
         assert_eq!(tx_manager.get_encrypted_tx(&TxHash([88u8; 32])), None);
     }
 
+    #[test_with_logger]
+    // Should return the context and encrypted size of a cached transaction.
+    fn test_get_context_and_size(logger: Logger) {
+        let mock_untrusted = MockUntrustedInterfaces::new();
+        let mock_enclave = MockConsensusEnclave::new();
+        let tx_manager = TxManagerImpl::new(mock_enclave, mock_untrusted, logger.clone());
+
+        // Add a transaction to the cache.
+        let tx_hash = TxHash([1u8; 32]);
+        let context = Arc::new(WellFormedTxContext::new(
+            1234,
+            tx_hash,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        ));
+        let cache_entry = CacheEntry {
+            encrypted_tx: WellFormedEncryptedTx(vec![1, 2, 3]),
+            context: context.clone(),
+        };
+        tx_manager
+            .cache
+            .lock()
+            .unwrap()
+            .insert(tx_hash, cache_entry);
+
+        assert_eq!(
+            tx_manager.get_context_and_size(&tx_hash),
+            Some((context, 3))
+        );
+        assert_eq!(tx_manager.get_context_and_size(&TxHash([88u8; 32])), None);
+    }
+
     #[test_with_logger]
     // Should return the number of elements in the cache.
     fn test_get_num_entries(logger: Logger) {
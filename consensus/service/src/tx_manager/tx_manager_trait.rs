@@ -3,8 +3,9 @@
 use crate::tx_manager::TxManagerResult;
 use mc_attest_enclave_api::{EnclaveMessage, PeerSession};
 use mc_common::HashSet;
-use mc_consensus_enclave::{TxContext, WellFormedEncryptedTx};
+use mc_consensus_enclave::{TxContext, WellFormedEncryptedTx, WellFormedTxContext};
 use mc_transaction_core::tx::{TxHash, TxOutMembershipProof};
+use std::sync::Arc;
 
 #[cfg(test)]
 use mockall::*;
@@ -61,4 +62,8 @@ pub trait TxManager: Send {
 
     /// Get the encrypted transaction corresponding to the given hash.
     fn get_encrypted_tx(&self, tx_hash: &TxHash) -> Option<WellFormedEncryptedTx>;
+
+    /// Get the context of the transaction corresponding to the given hash,
+    /// along with the size of the encrypted transaction in bytes.
+    fn get_context_and_size(&self, tx_hash: &TxHash) -> Option<(Arc<WellFormedTxContext>, usize)>;
 }
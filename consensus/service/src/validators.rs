@@ -22,7 +22,9 @@
 //! module - the check provided by it is actually the "Is well formed" check,
 //! and might be renamed in the future to match this.
 
-use crate::tx_manager::UntrustedInterfaces as TxManagerUntrustedInterfaces;
+use crate::{
+    congestion_fee::CongestionFee, tx_manager::UntrustedInterfaces as TxManagerUntrustedInterfaces,
+};
 use mc_consensus_enclave::{TxContext, WellFormedTxContext};
 use mc_crypto_keys::CompressedRistrettoPublic;
 use mc_ledger_db::{Error as LedgerError, Ledger};
@@ -36,11 +38,25 @@ use std::{collections::HashSet, sync::Arc};
 #[derive(Clone)]
 pub struct DefaultTxManagerUntrustedInterfaces<L: Ledger> {
     ledger: L,
+
+    /// If set, valid transactions must pay at least the congestion-based
+    /// minimum fee.
+    congestion_fee: Option<CongestionFee<L>>,
 }
 
 impl<L: Ledger + Sync> DefaultTxManagerUntrustedInterfaces<L> {
     pub fn new(ledger: L) -> Self {
-        Self { ledger }
+        Self {
+            ledger,
+            congestion_fee: None,
+        }
+    }
+
+    /// Require valid transactions to pay at least the congestion-based minimum
+    /// fee.
+    pub fn with_congestion_fee(mut self, congestion_fee: CongestionFee<L>) -> Self {
+        self.congestion_fee = Some(congestion_fee);
+        self
     }
 }
 
@@ -105,6 +121,17 @@ impl<L: Ledger + Sync> TxManagerUntrustedInterfaces for DefaultTxManagerUntruste
             return Err(TransactionValidationError::ContainsExistingOutputPublicKey);
         }
 
+        // The fee must meet the current fee level. The priority is the fee, normalized
+        // by the minimum fee of the transaction's token.
+        if let Some(congestion_fee) = &self.congestion_fee {
+            let fee_level = congestion_fee
+                .fee_level()
+                .map_err(|e| TransactionValidationError::Ledger(e.to_string()))?;
+            if context.priority() < fee_level {
+                return Err(TransactionValidationError::TxFeeError);
+            }
+        }
+
         // The transaction is valid w.r.t. the current ledger state.
         Ok(())
     }
@@ -263,6 +290,9 @@ pub mod well_formed_tests {
 #[cfg(test)]
 mod is_valid_tests {
     use super::*;
+    use crate::congestion_fee::BASE_FEE_LEVEL;
+    use mc_blockchain_types::BlockContents;
+    use mc_consensus_service_config::CongestionFeePolicy;
     use mc_ledger_db::{Error as LedgerError, MockLedger};
     use mc_transaction_core::{
         constants::MAX_TOMBSTONE_BLOCKS, validation::TransactionValidationError,
@@ -490,6 +520,65 @@ mod is_valid_tests {
             Err(TransactionValidationError::ContainsExistingOutputPublicKey),
         );
     }
+
+    #[test]
+    /// `is_valid` should reject a transaction whose fee is below the
+    /// congestion-based minimum fee.
+    fn is_valid_rejects_fee_below_congestion_fee_level() {
+        // Number of blocks in the local ledger.
+        let num_blocks = 53;
+
+        let well_formed_tx_context = |priority| {
+            WellFormedTxContext::new(
+                priority,
+                Default::default(),
+                num_blocks + 17,
+                vec![KeyImage::default()],
+                vec![9],
+                vec![CompressedRistrettoPublic::default()],
+            )
+        };
+
+        // Mock the local ledger.
+        let mut ledger = MockLedger::new();
+        ledger.expect_num_blocks().return_const(Ok(num_blocks));
+        ledger.expect_contains_key_image().return_const(Ok(false));
+        ledger
+            .expect_contains_tx_out_public_key()
+            .return_const(Ok(false));
+
+        // Every block is full.
+        let mut congestion_fee_ledger = MockLedger::new();
+        congestion_fee_ledger
+            .expect_num_blocks()
+            .return_const(Ok(num_blocks));
+        congestion_fee_ledger
+            .expect_get_block_contents()
+            .return_const(Ok(BlockContents {
+                key_images: vec![KeyImage::default()],
+                ..Default::default()
+            }));
+        let policy = CongestionFeePolicy {
+            enabled: true,
+            full_block_inputs: 1,
+            max_multiplier: 2,
+            ..Default::default()
+        }
+        .config();
+
+        let untrusted = DefaultTxManagerUntrustedInterfaces::new(ledger)
+            .with_congestion_fee(CongestionFee::new(congestion_fee_ledger, policy));
+
+        // The fee level reached twice the minimum fee.
+        assert_eq!(
+            untrusted.is_valid(Arc::new(well_formed_tx_context(2 * BASE_FEE_LEVEL - 1))),
+            Err(TransactionValidationError::TxFeeError)
+        );
+        assert_eq!(
+            untrusted.is_valid(Arc::new(well_formed_tx_context(2 * BASE_FEE_LEVEL))),
+            Ok(())
+        );
+    }
 }
 
 #[cfg(test)]
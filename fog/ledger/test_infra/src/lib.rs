@@ -13,7 +13,7 @@ use mc_fog_ledger_enclave::{
     GetOutputsResponse, LedgerEnclave, OutputContext, Result as EnclaveResult,
 };
 use mc_fog_ledger_enclave_api::{KeyImageData, UntrustedKeyImageQueryResponse};
-use mc_ledger_db::{ActiveMintConfig, ActiveMintConfigs, Error, Ledger};
+use mc_ledger_db::{ActiveMintConfig, ActiveMintConfigs, Error, Ledger, TokenEvent};
 use mc_sgx_report_cache_api::{ReportableEnclave, Result as ReportableEnclaveResult};
 use mc_transaction_core::{
     mint::MintTx,
@@ -230,4 +230,26 @@ impl Ledger for MockLedger {
     ) -> Result<ActiveMintConfig, Error> {
         unimplemented!()
     }
+
+    fn get_block_indexes_by_timestamp(
+        &self,
+        _start_timestamp: u64,
+        _end_timestamp: u64,
+        _limit: usize,
+    ) -> Result<Vec<BlockIndex>, Error> {
+        unimplemented!()
+    }
+
+    fn get_token_events(
+        &self,
+        _token_id: TokenId,
+        _start_block_index: BlockIndex,
+        _end_block_index: BlockIndex,
+    ) -> Result<Vec<TokenEvent>, Error> {
+        unimplemented!()
+    }
+
+    fn get_tx_out_counts_by_block_version(&self) -> Result<HashMap<u32, u64>, Error> {
+        unimplemented!()
+    }
 }
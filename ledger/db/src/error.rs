@@ -90,6 +90,30 @@ pub enum Error {
 
     /// Missing masked amonut
     MissingMaskedAmount,
+
+    /// IO: {0}
+    Io(String),
+
+    /// Invalid snapshot: {0}
+    InvalidSnapshot(String),
+
+    /// Snapshot checksum mismatch
+    SnapshotChecksumMismatch,
+
+    /// Cannot import a snapshot over an existing ledger
+    ImportTargetExists,
+
+    /// Cannot take a snapshot of a pruned ledger
+    SnapshotOfPrunedLedger,
+
+    /// The secondary indexes have not been built for this ledger
+    SecondaryIndexesNotBuilt,
+
+    /// TxOut {0} has been pruned from this ledger
+    TxOutPruned(u64),
+
+    /// The contents of block {0} have been pruned from this ledger
+    BlockContentsPruned(BlockIndex),
 }
 
 // Implement Debug by forwarding to Display
@@ -116,6 +140,12 @@ impl From<lmdb::Error> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(src: std::io::Error) -> Self {
+        Self::Io(src.to_string())
+    }
+}
+
 impl From<mc_util_serial::decode::Error> for Error {
     fn from(_: mc_util_serial::decode::Error) -> Self {
         Error::Deserialization
@@ -1,8 +1,10 @@
 // Copyright (c) 2018-2022 The MobileCoin Foundation
 
 use crate::{
+    snapshot::{self, SnapshotHeader},
     ActiveMintConfig, ActiveMintConfigs, Error, Ledger, LedgerMetrics, MetadataStore,
-    MetadataStoreSettings, MintConfigStore, MintTxStore, TxOutStore,
+    MetadataStoreSettings, MintConfigStore, MintTxStore, SecondaryIndexStore, TokenEvent,
+    TxOutStore,
 };
 use lmdb::{
     Database, DatabaseFlags, Environment, EnvironmentFlags, RoTransaction, RwTransaction,
@@ -12,7 +14,7 @@ use mc_blockchain_types::{
     Block, BlockContents, BlockData, BlockID, BlockIndex, BlockMetadata, BlockSignature,
     BlockVersion, MAX_BLOCK_VERSION,
 };
-use mc_common::{logger::global_log, HashMap};
+use mc_common::{logger::global_log, HashMap, HashSet};
 use mc_crypto_keys::CompressedRistrettoPublic;
 use mc_transaction_core::{
     membership_proofs::Range,
@@ -25,6 +27,7 @@ use mc_util_serial::{decode, encode, Message};
 use mc_util_telemetry::{
     mark_span_as_active, start_block_span, telemetry_static_key, tracer, Key, Span,
 };
+use rand::{CryptoRng, RngCore};
 use std::{
     fs,
     path::{Path, PathBuf},
@@ -35,7 +38,7 @@ use std::{
 pub const MAX_LMDB_FILE_SIZE: usize = 1 << 40; // 1 TB
 
 /// maximum number of [Database]s in the lmdb file
-pub const MAX_LMDB_DATABASES: u32 = 19;
+pub const MAX_LMDB_DATABASES: u32 = 22;
 
 // LMDB Database names.
 pub const COUNTS_DB_NAME: &str = "ledger_db:counts";
@@ -49,6 +52,7 @@ pub const BLOCK_NUMBER_BY_TX_OUT_INDEX: &str = "ledger_db:block_number_by_tx_out
 
 /// Keys used by the `counts` database.
 pub const NUM_BLOCKS_KEY: &str = "num_blocks";
+pub const PRUNED_NUM_BLOCKS_KEY: &str = "pruned_num_blocks";
 
 /// OpenTelemetry keys
 const TELEMETRY_BLOCK_INDEX_KEY: Key = telemetry_static_key!("block-index");
@@ -99,6 +103,9 @@ pub struct LedgerDB {
 
     /// Aggregate counts about the ledger.
     /// * `NUM_BLOCKS_KEY` --> number of blocks in the ledger.
+    /// * `PRUNED_NUM_BLOCKS_KEY` --> number of blocks, counting from the origin
+    ///   block, whose TxOuts have been pruned. Missing if the ledger has never
+    ///   been pruned.
     counts: Database,
 
     /// Blocks by block number. `block number -> Block`
@@ -134,6 +141,10 @@ pub struct LedgerDB {
     /// Storage abstraction for mint transactions.
     mint_tx_store: MintTxStore,
 
+    /// Optional secondary indexes, None unless they were built for this
+    /// ledger.
+    secondary_index_store: Option<SecondaryIndexStore>,
+
     /// Location on filesystem.
     path: PathBuf,
 
@@ -200,6 +211,16 @@ impl Ledger for LedgerDB {
         // Write block.
         self.write_block(block, signature, metadata, &mut db_transaction)?;
 
+        // Update the secondary indexes, if there are any.
+        if let Some(secondary_index_store) = &self.secondary_index_store {
+            secondary_index_store.write_block(
+                block,
+                block_contents,
+                signature,
+                &mut db_transaction,
+            )?;
+        }
+
         // Commit.
         db_transaction.commit()?;
 
@@ -354,23 +375,7 @@ impl Ledger for LedgerDB {
     /// Get the tx out root membership element from the tx out Merkle Tree.
     fn get_root_tx_out_membership_element(&self) -> Result<TxOutMembershipElement, Error> {
         let db_transaction = self.env.begin_ro_txn()?;
-
-        let num_txos = self.tx_out_store.num_tx_outs(&db_transaction)?;
-        if num_txos == 0 {
-            return Err(Error::NoOutputs);
-        }
-
-        let root_merkle_hash = self.tx_out_store.get_root_merkle_hash(&db_transaction)?;
-
-        let range = Range::new(
-            0,
-            // This duplicates the range calculation logic inside get_root_merkle_hash
-            num_txos
-                .checked_next_power_of_two()
-                .ok_or(Error::CapacityExceeded)?
-                - 1,
-        )?;
-        Ok(TxOutMembershipElement::new(range, root_merkle_hash))
+        self.get_root_tx_out_membership_element_impl(&db_transaction)
     }
 
     /// Get active mint configurations for a given token id.
@@ -427,6 +432,43 @@ impl Ledger for LedgerDB {
         self.mint_config_store
             .get_active_mint_config_for_mint_tx(mint_tx, &db_transaction)
     }
+
+    /// Gets the indexes of at most `limit` blocks whose signature timestamp is
+    /// in the range [start_timestamp, end_timestamp), ordered by timestamp.
+    fn get_block_indexes_by_timestamp(
+        &self,
+        start_timestamp: u64,
+        end_timestamp: u64,
+        limit: usize,
+    ) -> Result<Vec<BlockIndex>, Error> {
+        let db_transaction = self.env.begin_ro_txn()?;
+        self.get_secondary_index_store()?
+            .get_block_indexes_by_timestamp(start_timestamp, end_timestamp, limit, &db_transaction)
+    }
+
+    /// Gets the mint and burn events of a token in blocks in the range
+    /// [start_block_index, end_block_index), ordered by block index.
+    fn get_token_events(
+        &self,
+        token_id: TokenId,
+        start_block_index: BlockIndex,
+        end_block_index: BlockIndex,
+    ) -> Result<Vec<TokenEvent>, Error> {
+        let db_transaction = self.env.begin_ro_txn()?;
+        self.get_secondary_index_store()?.get_token_events(
+            *token_id,
+            start_block_index,
+            end_block_index,
+            &db_transaction,
+        )
+    }
+
+    /// Gets the number of TxOuts in blocks of each block version.
+    fn get_tx_out_counts_by_block_version(&self) -> Result<HashMap<u32, u64>, Error> {
+        let db_transaction = self.env.begin_ro_txn()?;
+        self.get_secondary_index_store()?
+            .get_tx_out_counts_by_block_version(&db_transaction)
+    }
 }
 
 impl LedgerDB {
@@ -462,6 +504,7 @@ impl LedgerDB {
         let tx_out_store = TxOutStore::new(&env)?;
         let mint_config_store = MintConfigStore::new(&env)?;
         let mint_tx_store = MintTxStore::new(&env)?;
+        let secondary_index_store = SecondaryIndexStore::new(&env)?;
 
         let metrics = LedgerMetrics::new(path);
 
@@ -479,6 +522,7 @@ impl LedgerDB {
             tx_out_store,
             mint_config_store,
             mint_tx_store,
+            secondary_index_store,
             metrics,
         };
 
@@ -522,6 +566,431 @@ impl LedgerDB {
         Ok(())
     }
 
+    /// Writes a snapshot of the ledger at its current height to `path`.
+    /// See [crate::snapshot] for the file format.
+    pub fn export_snapshot(&self, path: &Path) -> Result<SnapshotHeader, Error> {
+        snapshot::export_snapshot(&self.env, path)
+    }
+
+    /// Creates a ledger at `ledger_path` from a snapshot whose last block has
+    /// the ID `trusted_block_id`. The snapshot is rejected, and nothing is
+    /// left at `ledger_path`, if its checksum or any of its blocks does not
+    /// check out.
+    pub fn import_snapshot(
+        snapshot_path: &Path,
+        ledger_path: &Path,
+        trusted_block_id: &BlockID,
+    ) -> Result<LedgerDB, Error> {
+        snapshot::import_snapshot(snapshot_path, ledger_path, trusted_block_id)
+    }
+
+    /// Removes every block after `block_index`, so that it becomes the last
+    /// block in the ledger.
+    ///
+    /// Everything the removed blocks added is removed in a single LMDB
+    /// transaction: TxOuts and their Merkle hashes, key images, MintTxs,
+    /// MintConfigTxs, signatures, metadata and secondary index entries. The
+    /// active mint configurations are restored to what they were after
+    /// `block_index`.
+    pub fn truncate_to(&mut self, block_index: BlockIndex) -> Result<(), Error> {
+        let mut db_transaction = self.env.begin_rw_txn()?;
+
+        let num_blocks = key_bytes_to_u64(db_transaction.get(self.counts, &NUM_BLOCKS_KEY)?);
+        if block_index >= num_blocks {
+            return Err(Error::InvalidBlockIndex(block_index));
+        }
+        if block_index + 1 < self.pruned_num_blocks_impl(&db_transaction)? {
+            return Err(Error::BlockContentsPruned(block_index + 1));
+        }
+
+        // Tokens whose active mint configurations were changed by removed blocks.
+        let mut token_ids = HashSet::default();
+
+        for removed_block_index in (block_index + 1..num_blocks).rev() {
+            let key = u64_to_key_bytes(removed_block_index);
+
+            if let Some(secondary_index_store) = &self.secondary_index_store {
+                let block = self.get_block_impl(&db_transaction, removed_block_index)?;
+                let block_contents =
+                    self.get_block_contents_impl(&db_transaction, removed_block_index)?;
+                let signature =
+                    match self.get_block_signature_impl(&db_transaction, removed_block_index) {
+                        Ok(signature) => Some(signature),
+                        Err(Error::NotFound) => None,
+                        Err(err) => return Err(err),
+                    };
+                secondary_index_store.remove_block(
+                    &block,
+                    &block_contents,
+                    signature.as_ref(),
+                    &mut db_transaction,
+                )?;
+            }
+
+            let key_image_list: KeyImageList =
+                decode(db_transaction.get(self.key_images_by_block, &key)?)?;
+            for key_image in &key_image_list.key_images {
+                db_transaction.del(self.key_images, key_image, None)?;
+            }
+            db_transaction.del(self.key_images_by_block, &key, None)?;
+
+            let tx_outs: TxOutsByBlockValue =
+                decode(db_transaction.get(self.tx_outs_by_block, &key)?)?;
+            for tx_out_index in
+                tx_outs.first_tx_out_index..(tx_outs.first_tx_out_index + tx_outs.num_tx_outs)
+            {
+                db_transaction.del(
+                    self.block_number_by_tx_out_index,
+                    &u64_to_key_bytes(tx_out_index),
+                    None,
+                )?;
+            }
+            db_transaction.del(self.tx_outs_by_block, &key, None)?;
+
+            for mint_tx in self
+                .mint_tx_store
+                .remove_mint_txs(removed_block_index, &mut db_transaction)?
+            {
+                token_ids.insert(TokenId::from(mint_tx.prefix.token_id));
+            }
+            for validated_mint_config_tx in self
+                .mint_config_store
+                .remove_validated_mint_config_txs(removed_block_index, &mut db_transaction)?
+            {
+                token_ids.insert(TokenId::from(
+                    validated_mint_config_tx.mint_config_tx.prefix.token_id,
+                ));
+            }
+
+            db_transaction.del(self.blocks, &key, None)?;
+            // Signatures and metadata are optional.
+            for database in [self.block_signatures, self.block_metadata] {
+                match db_transaction.del(database, &key, None) {
+                    Ok(()) | Err(lmdb::Error::NotFound) => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        let last_block = self.get_block_impl(&db_transaction, block_index)?;
+        self.tx_out_store
+            .truncate(last_block.cumulative_txo_count, &mut db_transaction)?;
+
+        for token_id in token_ids {
+            self.rebuild_active_mint_configs(token_id, block_index + 1, &mut db_transaction)?;
+        }
+
+        db_transaction.put(
+            self.counts,
+            &NUM_BLOCKS_KEY,
+            &u64_to_key_bytes(block_index + 1),
+            WriteFlags::empty(),
+        )?;
+
+        db_transaction.commit()?;
+
+        self.update_metrics()
+    }
+
+    /// Recomputes the active mint configurations of a token from the first
+    /// `num_blocks` blocks: the last MintConfigTx for the token, and what was
+    /// minted with its configurations since.
+    fn rebuild_active_mint_configs(
+        &self,
+        token_id: TokenId,
+        num_blocks: u64,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<(), Error> {
+        // MintConfigTxs are rare, so this may walk back a long way.
+        let mut last_mint_config_tx = None;
+        for block_index in (0..num_blocks).rev() {
+            let validated_mint_config_txs = self
+                .mint_config_store
+                .get_validated_mint_config_txs_by_block_index(block_index, db_transaction)?;
+            // A MintConfigTx replaces the ones before it in the same block.
+            if let Some(validated_mint_config_tx) = validated_mint_config_txs
+                .into_iter()
+                .rev()
+                .find(|tx| tx.mint_config_tx.prefix.token_id == *token_id)
+            {
+                last_mint_config_tx = Some((block_index, validated_mint_config_tx.mint_config_tx));
+                break;
+            }
+        }
+
+        let (mint_config_block_index, mint_config_tx) = match last_mint_config_tx {
+            Some(last_mint_config_tx) => last_mint_config_tx,
+            None => {
+                return self.mint_config_store.reset_active_mint_configs(
+                    token_id,
+                    None,
+                    db_transaction,
+                )
+            }
+        };
+        self.mint_config_store.reset_active_mint_configs(
+            token_id,
+            Some(&mint_config_tx),
+            db_transaction,
+        )?;
+
+        // MintTxs are written before MintConfigTxs when appending a block, so the
+        // ones in the same block as the MintConfigTx used the previous
+        // configurations.
+        for block_index in (mint_config_block_index + 1)..num_blocks {
+            let mint_txs = self
+                .mint_tx_store
+                .get_mint_txs_by_block_index(block_index, db_transaction)?;
+            for mint_tx in mint_txs
+                .iter()
+                .filter(|mint_tx| mint_tx.prefix.token_id == *token_id)
+            {
+                let active_mint_config = self
+                    .mint_config_store
+                    .get_active_mint_config_for_mint_tx(mint_tx, db_transaction)?;
+                let new_total_minted = active_mint_config
+                    .total_minted
+                    .checked_add(mint_tx.prefix.amount)
+                    .ok_or(Error::NotFound)?;
+                self.mint_config_store.update_total_minted(
+                    &active_mint_config.mint_config,
+                    new_total_minted,
+                    db_transaction,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Recomputes the TxOut Merkle hashes and the mint state (the active mint
+    /// configurations and the MintTx and MintConfigTx nonce indexes) of an
+    /// imported ledger by replaying its blocks, since snapshots do not include
+    /// them. The mint state must be empty.
+    ///
+    /// Before each block's TxOuts are added, the Merkle root is checked
+    /// against the block's root element. Blocks with a default root element,
+    /// such as the origin block, are not checked.
+    pub(crate) fn rebuild_imported_state(&self) -> Result<(), Error> {
+        let mut db_transaction = self.env.begin_rw_txn()?;
+
+        let num_blocks = key_bytes_to_u64(db_transaction.get(self.counts, &NUM_BLOCKS_KEY)?);
+        let num_tx_outs = self.tx_out_store.reset_merkle_tree(&mut db_transaction)?;
+        let invalid = |block_index: u64, what: &str| {
+            Error::InvalidSnapshot(format!("block {}: {}", block_index, what))
+        };
+        let map_duplicate = |block_index: u64, what: &'static str| {
+            move |err: Error| match err {
+                Error::Lmdb(lmdb::Error::KeyExist) => invalid(block_index, what),
+                err => err,
+            }
+        };
+
+        let mut num_pushed = 0;
+        for block_index in 0..num_blocks {
+            let block = self.get_block_impl(&db_transaction, block_index)?;
+            if block.cumulative_txo_count < num_pushed || block.cumulative_txo_count > num_tx_outs {
+                return Err(invalid(block_index, "wrong cumulative TxOut count"));
+            }
+
+            if block.root_element != TxOutMembershipElement::default()
+                && self.get_root_tx_out_membership_element_impl(&db_transaction)?
+                    != block.root_element
+            {
+                return Err(invalid(block_index, "wrong root element"));
+            }
+            while num_pushed < block.cumulative_txo_count {
+                self.tx_out_store.push_stored(&mut db_transaction)?;
+                num_pushed += 1;
+            }
+
+            // MintTxs are replayed before MintConfigTxs, like in `append_block`.
+            let block_index_bytes = u64_to_key_bytes(block_index);
+            let mint_txs = self
+                .mint_tx_store
+                .get_mint_txs_by_block_index(block_index, &db_transaction)?;
+            for mint_tx in &mint_txs {
+                let active_mint_config = self
+                    .mint_config_store
+                    .get_active_mint_config_for_mint_tx(mint_tx, &db_transaction)?;
+                let new_total_minted = active_mint_config
+                    .total_minted
+                    .checked_add(mint_tx.prefix.amount)
+                    .ok_or(Error::NotFound)?;
+                self.mint_config_store.update_total_minted(
+                    &active_mint_config.mint_config,
+                    new_total_minted,
+                    &mut db_transaction,
+                )?;
+                self.mint_tx_store
+                    .write_block_index_by_mint_tx_nonce_and_token_id(
+                        mint_tx,
+                        &mut db_transaction,
+                        block_index_bytes,
+                    )
+                    .map_err(map_duplicate(block_index, "duplicate MintTx nonce"))?;
+            }
+
+            let validated_mint_config_txs = self
+                .mint_config_store
+                .get_validated_mint_config_txs_by_block_index(block_index, &db_transaction)?;
+            for validated_mint_config_tx in &validated_mint_config_txs {
+                let mint_config_tx = &validated_mint_config_tx.mint_config_tx;
+                MintConfigStore::check_mint_config(mint_config_tx)?;
+                self.mint_config_store
+                    .write_block_index_by_nonce_and_token_id(
+                        mint_config_tx,
+                        &mut db_transaction,
+                        block_index_bytes,
+                    )
+                    .map_err(map_duplicate(block_index, "duplicate MintConfigTx nonce"))?;
+                self.mint_config_store.reset_active_mint_configs(
+                    TokenId::from(mint_config_tx.prefix.token_id),
+                    Some(mint_config_tx),
+                    &mut db_transaction,
+                )?;
+            }
+        }
+
+        if num_pushed != num_tx_outs {
+            return Err(Error::InvalidSnapshot(
+                "TxOuts that do not belong to a block".to_string(),
+            ));
+        }
+
+        db_transaction.commit()?;
+        Ok(())
+    }
+
+    /// Prunes the TxOuts of every block below `num_blocks`, keeping a random
+    /// sample of roughly one in `retain_one_in` of them so that they can still
+    /// be used as mixins. A `retain_one_in` of zero keeps none of them.
+    ///
+    /// A pruned ledger still has every block, block signature, key image and
+    /// Merkle hash, so it can keep syncing, check for spent key images and
+    /// duplicate outputs, and produce proofs of membership for any TxOut.
+    /// What it can no longer do is return the bodies of pruned TxOuts:
+    /// `get_tx_out_by_index` returns `Error::TxOutPruned` for them, and
+    /// `get_block_contents` and `get_block_data` return
+    /// `Error::BlockContentsPruned` for the pruned blocks. Pruned ledgers can
+    /// not be truncated into the pruned blocks or have their secondary indexes
+    /// rebuilt, and snapshots of them can not be exported.
+    ///
+    /// Pruning is incremental: blocks that were pruned by a previous call are
+    /// not considered again, and a `num_blocks` that is not greater than the
+    /// previous one does nothing. Returns the number of TxOuts that were
+    /// pruned by this call.
+    pub fn prune_tx_outs<R: RngCore + CryptoRng>(
+        &mut self,
+        num_blocks: u64,
+        retain_one_in: u64,
+        rng: &mut R,
+    ) -> Result<u64, Error> {
+        let mut db_transaction = self.env.begin_rw_txn()?;
+
+        let ledger_num_blocks = key_bytes_to_u64(db_transaction.get(self.counts, &NUM_BLOCKS_KEY)?);
+        if num_blocks > ledger_num_blocks {
+            return Err(Error::InvalidBlockIndex(num_blocks));
+        }
+        if num_blocks <= self.pruned_num_blocks_impl(&db_transaction)? {
+            return Ok(0);
+        }
+
+        // TxOuts are stored in block order, so the TxOuts of the pruned blocks are
+        // exactly the ones below the first TxOut of block `num_blocks`.
+        let prune_horizon = if num_blocks == ledger_num_blocks {
+            self.tx_out_store.num_tx_outs(&db_transaction)?
+        } else {
+            let value: TxOutsByBlockValue =
+                decode(db_transaction.get(self.tx_outs_by_block, &u64_to_key_bytes(num_blocks))?)?;
+            value.first_tx_out_index
+        };
+
+        let num_pruned =
+            self.tx_out_store
+                .prune(prune_horizon, retain_one_in, rng, &mut db_transaction)?;
+
+        db_transaction.put(
+            self.counts,
+            &PRUNED_NUM_BLOCKS_KEY,
+            &u64_to_key_bytes(num_blocks),
+            WriteFlags::empty(),
+        )?;
+        db_transaction.commit()?;
+
+        Ok(num_pruned)
+    }
+
+    /// Returns the number of blocks, counting from the origin block, whose
+    /// TxOuts have been pruned. This is zero if the ledger has never been
+    /// pruned.
+    pub fn pruned_num_blocks(&self) -> Result<u64, Error> {
+        let db_transaction = self.env.begin_ro_txn()?;
+        self.pruned_num_blocks_impl(&db_transaction)
+    }
+
+    /// Returns the number of TxOuts whose bodies have been pruned.
+    pub fn num_pruned_tx_outs(&self) -> Result<u64, Error> {
+        let db_transaction = self.env.begin_ro_txn()?;
+        self.tx_out_store.num_pruned_tx_outs(&db_transaction)
+    }
+
+    fn pruned_num_blocks_impl(&self, db_transaction: &impl Transaction) -> Result<u64, Error> {
+        match db_transaction.get(self.counts, &PRUNED_NUM_BLOCKS_KEY) {
+            Ok(bytes) => Ok(key_bytes_to_u64(bytes)),
+            Err(lmdb::Error::NotFound) => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns true if the secondary indexes were built for this ledger.
+    pub fn has_secondary_indexes(&self) -> bool {
+        self.secondary_index_store.is_some()
+    }
+
+    /// Builds the secondary indexes from every block in the ledger, replacing
+    /// any existing ones. Once built, the indexes are kept up to date by
+    /// `append_block` and `truncate_to`.
+    ///
+    /// Other processes that have the ledger open do not see the new indexes
+    /// until they reopen it, and would not update them when appending blocks.
+    /// This should therefore be done while nothing else is writing to the
+    /// ledger.
+    pub fn rebuild_secondary_indexes(&mut self) -> Result<(), Error> {
+        let secondary_index_store = SecondaryIndexStore::create(&self.env)?;
+
+        let mut db_transaction = self.env.begin_rw_txn()?;
+        secondary_index_store.clear(&mut db_transaction)?;
+
+        let num_blocks = key_bytes_to_u64(db_transaction.get(self.counts, &NUM_BLOCKS_KEY)?);
+        for block_index in 0..num_blocks {
+            let block = self.get_block_impl(&db_transaction, block_index)?;
+            let block_contents = self.get_block_contents_impl(&db_transaction, block_index)?;
+            let signature = match self.get_block_signature_impl(&db_transaction, block_index) {
+                Ok(signature) => Some(signature),
+                Err(Error::NotFound) => None,
+                Err(err) => return Err(err),
+            };
+            secondary_index_store.write_block(
+                &block,
+                &block_contents,
+                signature.as_ref(),
+                &mut db_transaction,
+            )?;
+        }
+
+        db_transaction.commit()?;
+
+        self.secondary_index_store = Some(secondary_index_store);
+        Ok(())
+    }
+
+    fn get_secondary_index_store(&self) -> Result<&SecondaryIndexStore, Error> {
+        self.secondary_index_store
+            .as_ref()
+            .ok_or(Error::SecondaryIndexesNotBuilt)
+    }
+
     /// Force an update of the metric gauges. This is useful when the ledger db
     /// is being updated externally (for example by mobilecoind), but we
     /// still want to publish the correct metrics. Users can call this
@@ -818,6 +1287,30 @@ impl LedgerDB {
         Ok(metadata.len())
     }
 
+    /// Implementation of the `get_root_tx_out_membership_element` method that
+    /// operates inside a given transaction.
+    fn get_root_tx_out_membership_element_impl(
+        &self,
+        db_transaction: &impl Transaction,
+    ) -> Result<TxOutMembershipElement, Error> {
+        let num_txos = self.tx_out_store.num_tx_outs(db_transaction)?;
+        if num_txos == 0 {
+            return Err(Error::NoOutputs);
+        }
+
+        let root_merkle_hash = self.tx_out_store.get_root_merkle_hash(db_transaction)?;
+
+        let range = Range::new(
+            0,
+            // This duplicates the range calculation logic inside get_root_merkle_hash
+            num_txos
+                .checked_next_power_of_two()
+                .ok_or(Error::CapacityExceeded)?
+                - 1,
+        )?;
+        Ok(TxOutMembershipElement::new(range, root_merkle_hash))
+    }
+
     /// Implementatation of the `get_block` method that operates inside a given
     /// transaction.
     fn get_block_impl(
@@ -846,6 +1339,10 @@ impl LedgerDB {
             .map(|tx_out_index| {
                 self.tx_out_store
                     .get_tx_out_by_index(tx_out_index, db_transaction)
+                    .map_err(|err| match err {
+                        Error::TxOutPruned(_) => Error::BlockContentsPruned(block_number),
+                        err => err,
+                    })
             })
             .collect::<Result<Vec<TxOut>, Error>>()?;
 
@@ -956,10 +1453,16 @@ pub fn key_bytes_to_u64(bytes: &[u8]) -> u64 {
 #[cfg(test)]
 mod ledger_db_test {
     use super::*;
-    use crate::test_utils::{add_block_contents_to_ledger, add_txos_and_key_images_to_ledger};
+    use crate::{
+        test_utils::{add_block_contents_to_ledger, add_txos_and_key_images_to_ledger},
+        TokenEventKind,
+    };
+    use mc_account_keys::burn_address;
     use mc_blockchain_test_utils::{get_blocks, make_block_metadata};
-    use mc_crypto_keys::Ed25519Pair;
-    use mc_transaction_core::{membership_proofs::compute_implied_merkle_root, BlockVersion};
+    use mc_crypto_keys::{Ed25519Pair, RistrettoPrivate};
+    use mc_transaction_core::{
+        membership_proofs::compute_implied_merkle_root, tokens::Mob, Amount, BlockVersion, Token,
+    };
     use mc_transaction_core_test_utils::{
         create_mint_config_tx, create_mint_config_tx_and_signers, create_mint_tx,
         create_test_tx_out, mint_config_tx_to_validated as to_validated,
@@ -2709,6 +3212,356 @@ mod ledger_db_test {
         }
     }
 
+    #[test]
+    // truncate_to should remove everything added after the given block, so that
+    // the removed blocks can be appended again.
+    fn truncate_to_removes_blocks() {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let mut ledger_db = create_db();
+        let token_id1 = TokenId::from(1);
+
+        add_origin_block(&mut ledger_db);
+
+        // Block 1 activates a mint configuration, and block 2 mints with it.
+        let (mint_config_tx1, signers1) = create_mint_config_tx_and_signers(token_id1, &mut rng);
+        let block_contents1 = BlockContents {
+            validated_mint_config_txs: vec![to_validated(&mint_config_tx1)],
+            ..Default::default()
+        };
+        add_block_contents_to_ledger(&mut ledger_db, BLOCK_VERSION, block_contents1, &mut rng)
+            .unwrap();
+
+        let mint_tx1 = create_mint_tx(token_id1, &signers1, 10, &mut rng);
+        let block_contents2 = BlockContents {
+            mint_txs: vec![mint_tx1],
+            outputs: vec![create_test_tx_out(BLOCK_VERSION, &mut rng)],
+            ..Default::default()
+        };
+        let block2 =
+            add_block_contents_to_ledger(&mut ledger_db, BLOCK_VERSION, block_contents2, &mut rng)
+                .unwrap();
+
+        let num_txos = ledger_db.num_txos().unwrap();
+        let root_element = ledger_db.get_root_tx_out_membership_element().unwrap();
+        let active_mint_configs = ledger_db.get_active_mint_configs(token_id1).unwrap();
+
+        // Block 3 mints with the first configuration, block 4 replaces it and
+        // block 5 mints with the new one. Block 6 spends some key images.
+        let mint_tx2 = create_mint_tx(token_id1, &signers1, 20, &mut rng);
+        let (mint_config_tx2, signers2) = create_mint_config_tx_and_signers(token_id1, &mut rng);
+        let mint_tx3 = create_mint_tx(token_id1, &signers2, 30, &mut rng);
+        let key_images: Vec<KeyImage> = (0..5).map(|_i| KeyImage::from(rng.next_u64())).collect();
+        let removed_block_contents = vec![
+            BlockContents {
+                mint_txs: vec![mint_tx2.clone()],
+                outputs: vec![create_test_tx_out(BLOCK_VERSION, &mut rng)],
+                ..Default::default()
+            },
+            BlockContents {
+                validated_mint_config_txs: vec![to_validated(&mint_config_tx2)],
+                ..Default::default()
+            },
+            BlockContents {
+                mint_txs: vec![mint_tx3.clone()],
+                outputs: vec![create_test_tx_out(BLOCK_VERSION, &mut rng)],
+                ..Default::default()
+            },
+            BlockContents {
+                key_images: key_images.clone(),
+                outputs: (0..3)
+                    .map(|_i| create_test_tx_out(BLOCK_VERSION, &mut rng))
+                    .collect(),
+                ..Default::default()
+            },
+        ];
+        for block_contents in &removed_block_contents {
+            add_block_contents_to_ledger(
+                &mut ledger_db,
+                BLOCK_VERSION,
+                block_contents.clone(),
+                &mut rng,
+            )
+            .unwrap();
+        }
+        assert_eq!(7, ledger_db.num_blocks().unwrap());
+        let final_root_element = ledger_db.get_root_tx_out_membership_element().unwrap();
+        let final_active_mint_configs = ledger_db.get_active_mint_configs(token_id1).unwrap();
+
+        // The last block in the ledger cannot be truncated to a later one.
+        assert_eq!(ledger_db.truncate_to(7), Err(Error::InvalidBlockIndex(7)));
+
+        ledger_db.truncate_to(2).unwrap();
+
+        assert_eq!(3, ledger_db.num_blocks().unwrap());
+        assert_eq!(num_txos, ledger_db.num_txos().unwrap());
+        assert_eq!(block2, ledger_db.get_block_data(2).unwrap());
+        assert_eq!(
+            root_element,
+            ledger_db.get_root_tx_out_membership_element().unwrap()
+        );
+        assert_eq!(
+            active_mint_configs,
+            ledger_db.get_active_mint_configs(token_id1).unwrap()
+        );
+        for block_index in 3..7 {
+            assert_eq!(ledger_db.get_block(block_index), Err(Error::NotFound));
+            assert_eq!(
+                ledger_db.get_block_signature(block_index),
+                Err(Error::NotFound)
+            );
+            assert_eq!(
+                ledger_db.get_block_metadata(block_index),
+                Err(Error::NotFound)
+            );
+        }
+        assert_eq!(
+            ledger_db.get_tx_out_by_index(num_txos),
+            Err(Error::NotFound)
+        );
+        for key_image in &key_images {
+            assert_eq!(ledger_db.check_key_image(key_image).unwrap(), None);
+        }
+        for mint_tx in [&mint_tx2, &mint_tx3] {
+            assert_eq!(
+                ledger_db
+                    .check_mint_tx_nonce(*token_id1, &mint_tx.prefix.nonce)
+                    .unwrap(),
+                None
+            );
+        }
+        assert_eq!(
+            ledger_db
+                .check_mint_config_tx_nonce(*token_id1, &mint_config_tx2.prefix.nonce)
+                .unwrap(),
+            None
+        );
+
+        // The removed blocks can be appended again, and lead to the same state.
+        for block_contents in removed_block_contents {
+            add_block_contents_to_ledger(&mut ledger_db, BLOCK_VERSION, block_contents, &mut rng)
+                .unwrap();
+        }
+        assert_eq!(7, ledger_db.num_blocks().unwrap());
+        assert_eq!(
+            final_root_element,
+            ledger_db.get_root_tx_out_membership_element().unwrap()
+        );
+        assert_eq!(
+            final_active_mint_configs,
+            ledger_db.get_active_mint_configs(token_id1).unwrap()
+        );
+
+        // Truncating to before the first MintConfigTx leaves the token without
+        // active mint configurations.
+        ledger_db.truncate_to(0).unwrap();
+        assert_eq!(1, ledger_db.num_blocks().unwrap());
+        assert_eq!(1, ledger_db.num_txos().unwrap());
+        assert_eq!(ledger_db.get_active_mint_configs(token_id1).unwrap(), None);
+    }
+
+    #[test]
+    // Pruning should remove old TxOut bodies, report the queries that can no
+    // longer be answered, and keep proofs of membership working.
+    fn prune_tx_outs() {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let mut ledger_db = create_db();
+        let blocks = populate_db(&mut ledger_db, 10, 5);
+        let num_txos = ledger_db.num_txos().unwrap();
+        let indexes: Vec<u64> = (0..num_txos).collect();
+        let proofs = ledger_db.get_tx_out_proof_of_memberships(&indexes).unwrap();
+
+        assert_eq!(0, ledger_db.pruned_num_blocks().unwrap());
+        assert_eq!(
+            Err(Error::InvalidBlockIndex(11)),
+            ledger_db.prune_tx_outs(11, 3, &mut rng)
+        );
+
+        let num_pruned = ledger_db.prune_tx_outs(6, 3, &mut rng).unwrap();
+        assert_eq!(6, ledger_db.pruned_num_blocks().unwrap());
+        assert_eq!(num_pruned, ledger_db.num_pruned_tx_outs().unwrap());
+        assert_eq!(Ok(0), ledger_db.prune_tx_outs(3, 3, &mut rng));
+
+        // Blocks, key images and proofs of membership are all still available.
+        assert_eq!(10, ledger_db.num_blocks().unwrap());
+        assert_eq!(num_txos, ledger_db.num_txos().unwrap());
+        assert_eq!(
+            proofs,
+            ledger_db.get_tx_out_proof_of_memberships(&indexes).unwrap()
+        );
+        for block_data in &blocks {
+            let block_index = block_data.block().index;
+            assert_eq!(
+                *block_data.block(),
+                ledger_db.get_block(block_index).unwrap()
+            );
+            for key_image in &block_data.contents().key_images {
+                assert_eq!(
+                    Some(block_index),
+                    ledger_db.check_key_image(key_image).unwrap()
+                );
+            }
+        }
+
+        // Only TxOuts of the pruned blocks are missing.
+        let mut num_missing = 0;
+        for block_data in &blocks {
+            let block_index = block_data.block().index;
+            let mut is_block_pruned = false;
+            for tx_out in &block_data.contents().outputs {
+                let index = ledger_db.get_tx_out_index_by_hash(&tx_out.hash()).unwrap();
+                match ledger_db.get_tx_out_by_index(index) {
+                    Ok(retained) => assert_eq!(*tx_out, retained),
+                    Err(Error::TxOutPruned(pruned_index)) if block_index < 6 => {
+                        assert_eq!(index, pruned_index);
+                        is_block_pruned = true;
+                        num_missing += 1;
+                    }
+                    Err(err) => panic!("Unexpected error {:?}", err),
+                }
+            }
+
+            if is_block_pruned {
+                assert_eq!(
+                    Err(Error::BlockContentsPruned(block_index)),
+                    ledger_db.get_block_data(block_index)
+                );
+            } else {
+                assert_eq!(*block_data, ledger_db.get_block_data(block_index).unwrap());
+            }
+        }
+        assert_eq!(num_pruned, num_missing);
+
+        // The ledger can be truncated, but not into the pruned blocks.
+        assert_eq!(Err(Error::BlockContentsPruned(5)), ledger_db.truncate_to(4));
+        ledger_db.truncate_to(5).unwrap();
+        assert_eq!(6, ledger_db.num_blocks().unwrap());
+        assert_eq!(Ok(0), ledger_db.prune_tx_outs(6, 3, &mut rng));
+    }
+
+    #[test]
+    // The secondary indexes should cover the blocks in the ledger when they are
+    // built, and be kept up to date by append_block and truncate_to.
+    fn secondary_indexes() {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let mut ledger_db = create_db();
+        let token_id1 = TokenId::from(1);
+
+        add_origin_block(&mut ledger_db);
+
+        let (mint_config_tx1, signers1) = create_mint_config_tx_and_signers(token_id1, &mut rng);
+        let block_contents1 = BlockContents {
+            validated_mint_config_txs: vec![to_validated(&mint_config_tx1)],
+            ..Default::default()
+        };
+        add_block_contents_to_ledger(&mut ledger_db, BLOCK_VERSION, block_contents1, &mut rng)
+            .unwrap();
+
+        let mint_tx1 = create_mint_tx(token_id1, &signers1, 10, &mut rng);
+        let block_contents2 = BlockContents {
+            mint_txs: vec![mint_tx1.clone()],
+            outputs: vec![create_test_tx_out(BLOCK_VERSION, &mut rng)],
+            ..Default::default()
+        };
+        add_block_contents_to_ledger(&mut ledger_db, BLOCK_VERSION, block_contents2, &mut rng)
+            .unwrap();
+
+        assert_eq!(
+            ledger_db.get_token_events(token_id1, 0, 10),
+            Err(Error::SecondaryIndexesNotBuilt)
+        );
+
+        ledger_db.rebuild_secondary_indexes().unwrap();
+        assert!(ledger_db.has_secondary_indexes());
+
+        // Block 3 burns some MOB, next to a regular output.
+        let burn_tx_out = TxOut::new(
+            BLOCK_VERSION,
+            Amount::new(20, Mob::ID),
+            &burn_address(),
+            &RistrettoPrivate::from_random(&mut rng),
+            Default::default(),
+        )
+        .unwrap();
+        add_txos_and_key_images_to_ledger(
+            &mut ledger_db,
+            BLOCK_VERSION,
+            vec![create_test_tx_out(BLOCK_VERSION, &mut rng), burn_tx_out],
+            vec![KeyImage::from(rng.next_u64())],
+            &mut rng,
+        )
+        .unwrap();
+        let burn_tx_out_index = ledger_db.num_txos().unwrap() - 1;
+
+        assert_eq!(
+            ledger_db.get_token_events(token_id1, 0, 10).unwrap(),
+            vec![TokenEvent {
+                block_index: 2,
+                token_id: *token_id1,
+                value: 10,
+                kind: Some(TokenEventKind::Mint(mint_tx1.prefix.nonce.clone())),
+            }]
+        );
+        assert_eq!(
+            ledger_db.get_token_events(token_id1, 3, 10).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            ledger_db.get_token_events(Mob::ID, 0, 10).unwrap(),
+            vec![TokenEvent {
+                block_index: 3,
+                token_id: *Mob::ID,
+                value: 20,
+                kind: Some(TokenEventKind::Burn(burn_tx_out_index)),
+            }]
+        );
+
+        // Test blocks are signed at a timestamp equal to their index.
+        assert_eq!(
+            ledger_db.get_block_indexes_by_timestamp(1, 3, 10).unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ledger_db.get_block_indexes_by_timestamp(1, 10, 2).unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ledger_db.get_block_indexes_by_timestamp(0, 10, 10).unwrap(),
+            vec![0, 1, 2, 3]
+        );
+
+        assert_eq!(
+            ledger_db.get_tx_out_counts_by_block_version().unwrap(),
+            HashMap::from_iter([(*BLOCK_VERSION, ledger_db.num_txos().unwrap())])
+        );
+
+        // Truncating removes the entries of the removed blocks.
+        ledger_db.truncate_to(2).unwrap();
+        assert_eq!(ledger_db.get_token_events(Mob::ID, 0, 10).unwrap(), vec![]);
+        assert_eq!(
+            ledger_db.get_token_events(token_id1, 0, 10).unwrap().len(),
+            1
+        );
+        assert_eq!(
+            ledger_db.get_block_indexes_by_timestamp(0, 10, 10).unwrap(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            ledger_db.get_tx_out_counts_by_block_version().unwrap(),
+            HashMap::from_iter([(*BLOCK_VERSION, ledger_db.num_txos().unwrap())])
+        );
+
+        // Rebuilding gives the same indexes.
+        ledger_db.rebuild_secondary_indexes().unwrap();
+        assert_eq!(
+            ledger_db.get_token_events(token_id1, 0, 10).unwrap().len(),
+            1
+        );
+        assert_eq!(
+            ledger_db.get_block_indexes_by_timestamp(0, 10, 10).unwrap(),
+            vec![0, 1, 2]
+        );
+    }
+
     // FIXME(MC-526): If these benches are not marked ignore, they get run during
     // cargo test and they are not compiled with optimizations which makes them
     // take several minutes I think they should probably be moved to
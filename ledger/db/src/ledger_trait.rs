@@ -1,6 +1,6 @@
 // Copyright (c) 2018-2022 The MobileCoin Foundation
 
-use crate::{ActiveMintConfig, ActiveMintConfigs, Error};
+use crate::{ActiveMintConfig, ActiveMintConfigs, Error, TokenEvent};
 use mc_blockchain_types::{
     Block, BlockContents, BlockData, BlockIndex, BlockMetadata, BlockSignature,
 };
@@ -141,4 +141,28 @@ pub trait Ledger: Send {
         &self,
         mint_tx: &MintTx,
     ) -> Result<ActiveMintConfig, Error>;
+
+    /// Gets the indexes of at most `limit` blocks whose signature timestamp is
+    /// in the range [start_timestamp, end_timestamp), ordered by timestamp.
+    /// Requires the secondary indexes.
+    fn get_block_indexes_by_timestamp(
+        &self,
+        start_timestamp: u64,
+        end_timestamp: u64,
+        limit: usize,
+    ) -> Result<Vec<BlockIndex>, Error>;
+
+    /// Gets the mint and burn events of a token in blocks in the range
+    /// [start_block_index, end_block_index), ordered by block index.
+    /// Requires the secondary indexes.
+    fn get_token_events(
+        &self,
+        token_id: TokenId,
+        start_block_index: BlockIndex,
+        end_block_index: BlockIndex,
+    ) -> Result<Vec<TokenEvent>, Error>;
+
+    /// Gets the number of TxOuts in blocks of each block version.
+    /// Requires the secondary indexes.
+    fn get_tx_out_counts_by_block_version(&self) -> Result<HashMap<u32, u64>, Error>;
 }
@@ -12,8 +12,10 @@ mod ledger_trait;
 mod metrics;
 mod mint_config_store;
 mod mint_tx_store;
+mod secondary_index_store;
 
 pub mod ledger_db;
+pub mod snapshot;
 #[cfg(any(test, feature = "test_utils"))]
 pub mod test_utils;
 pub mod tx_out_store;
@@ -25,6 +27,7 @@ pub use crate::{
     metrics::LedgerMetrics,
     mint_config_store::{ActiveMintConfig, ActiveMintConfigs, MintConfigStore},
     mint_tx_store::MintTxStore,
+    secondary_index_store::{SecondaryIndexStore, TokenEvent, TokenEventKind},
     tx_out_store::TxOutStore,
 };
 pub use mc_util_lmdb::{MetadataStore, MetadataStoreError, MetadataStoreSettings};
@@ -286,6 +286,65 @@ impl MintConfigStore {
         Ok(())
     }
 
+    /// Remove the validated mint-config-txs in a given block, and return them.
+    ///
+    /// This does not update the active mint configurations, see
+    /// `reset_active_mint_configs`.
+    pub fn remove_validated_mint_config_txs(
+        &self,
+        block_index: u64,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<Vec<ValidatedMintConfigTx>, Error> {
+        let validated_mint_config_txs =
+            self.get_validated_mint_config_txs_by_block_index(block_index, db_transaction)?;
+
+        for validated_mint_config_tx in &validated_mint_config_txs {
+            let mint_config_tx = &validated_mint_config_tx.mint_config_tx;
+            let mut combined_nonce_and_token_id = mint_config_tx.prefix.nonce.clone();
+            combined_nonce_and_token_id
+                .extend_from_slice(&u64_to_key_bytes(mint_config_tx.prefix.token_id));
+            db_transaction.del(
+                self.block_index_by_mint_config_tx_nonce_and_token_id,
+                &combined_nonce_and_token_id,
+                None,
+            )?;
+        }
+
+        db_transaction.del(
+            self.validated_mint_config_txs_by_block,
+            &u64_to_key_bytes(block_index),
+            None,
+        )?;
+
+        Ok(validated_mint_config_txs)
+    }
+
+    /// Replace the active mint configurations of a token with the ones in
+    /// `mint_config_tx`, with nothing minted yet. If `mint_config_tx` is None,
+    /// the token is left without active mint configurations.
+    pub fn reset_active_mint_configs(
+        &self,
+        token_id: TokenId,
+        mint_config_tx: Option<&MintConfigTx>,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<(), Error> {
+        match mint_config_tx {
+            Some(mint_config_tx) => {
+                self.write_active_mint_configs_by_token_id(mint_config_tx, db_transaction)
+            }
+            None => {
+                match db_transaction.del(
+                    self.active_mint_configs_by_token_id,
+                    &u64_to_key_bytes(*token_id),
+                    None,
+                ) {
+                    Ok(()) | Err(lmdb::Error::NotFound) => Ok(()),
+                    Err(err) => Err(err.into()),
+                }
+            }
+        }
+    }
+
     fn write_active_mint_configs_by_token_id(
         &self,
         mint_config_tx: &MintConfigTx,
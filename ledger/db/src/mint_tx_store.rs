@@ -116,6 +116,33 @@ impl MintTxStore {
         Ok(())
     }
 
+    /// Remove the mint txs in a given block, and return them.
+    ///
+    /// This does not update the total minted amounts of the active mint
+    /// configurations, see `MintConfigStore::reset_active_mint_configs`.
+    pub fn remove_mint_txs(
+        &self,
+        block_index: u64,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<Vec<MintTx>, Error> {
+        let mint_txs = self.get_mint_txs_by_block_index(block_index, db_transaction)?;
+
+        for mint_tx in &mint_txs {
+            let mut combined_nonce_and_token_id = mint_tx.prefix.nonce.clone();
+            combined_nonce_and_token_id
+                .extend_from_slice(&u64_to_key_bytes(mint_tx.prefix.token_id));
+            db_transaction.del(
+                self.block_index_by_mint_tx_nonce_and_token_id,
+                &combined_nonce_and_token_id,
+                None,
+            )?;
+        }
+
+        db_transaction.del(self.mint_txs_by_block, &u64_to_key_bytes(block_index), None)?;
+
+        Ok(mint_txs)
+    }
+
     pub fn write_block_index_by_mint_tx_nonce_and_token_id(
         &self,
         mint_tx: &MintTx,
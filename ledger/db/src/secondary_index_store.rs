@@ -0,0 +1,361 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Optional secondary indexes over the ledger, for explorer-style queries.
+//!
+//! None of these indexes are needed for validating or serving blocks, so they
+//! only exist once they have been built with
+//! `LedgerDB::rebuild_secondary_indexes`. From then on, `append_block` and
+//! `truncate_to` keep them in sync in the same LMDB transaction as the blocks
+//! themselves. Snapshots do not include them, so they need to be rebuilt after
+//! importing one.
+//!
+//! This store maintains three LMDB databases:
+//! 1) A mapping of (signature timestamp, block index) -> nothing. Blocks
+//! without a signature are not indexed.
+//! 2) A mapping of (token id, block index) -> list of mint and burn events
+//! of the token in the block.
+//! 3) A mapping of block version -> number of TxOuts in blocks of that
+//! version.
+
+use crate::{key_bytes_to_u64, u64_to_key_bytes, Error};
+use lmdb::{Cursor, Database, DatabaseFlags, Environment, RwTransaction, Transaction, WriteFlags};
+use mc_account_keys::{burn_address, burn_address_view_private};
+use mc_blockchain_types::{Block, BlockContents, BlockIndex, BlockSignature};
+use mc_common::HashMap;
+use mc_crypto_keys::RistrettoPublic;
+use mc_transaction_core::{onetime_keys::recover_public_subaddress_spend_key, tx::TxOut, Amount};
+use mc_util_serial::{decode, encode, Message};
+use prost::Oneof;
+use std::collections::BTreeMap;
+
+// LMDB Database names.
+pub const BLOCK_INDEX_BY_TIMESTAMP_DB_NAME: &str = "secondary_index_store:block_index_by_timestamp";
+pub const TOKEN_EVENTS_BY_TOKEN_ID_AND_BLOCK_DB_NAME: &str =
+    "secondary_index_store:token_events_by_token_id_and_block";
+pub const TX_OUT_COUNT_BY_BLOCK_VERSION_DB_NAME: &str =
+    "secondary_index_store:tx_out_count_by_block_version";
+
+/// A mint or a burn of some token.
+#[derive(Clone, Eq, Message, PartialEq)]
+pub struct TokenEvent {
+    /// The index of the block that contains the event.
+    #[prost(uint64, tag = "1")]
+    pub block_index: BlockIndex,
+
+    /// The token that was minted or burned.
+    #[prost(uint64, tag = "2")]
+    pub token_id: u64,
+
+    /// The amount that was minted or burned.
+    #[prost(uint64, tag = "3")]
+    pub value: u64,
+
+    /// What kind of event this is.
+    #[prost(oneof = "TokenEventKind", tags = "4, 5")]
+    pub kind: Option<TokenEventKind>,
+}
+
+/// The kind of a [TokenEvent].
+#[derive(Clone, Eq, Oneof, PartialEq)]
+pub enum TokenEventKind {
+    /// A MintTx, identified by its nonce.
+    #[prost(bytes, tag = "4")]
+    Mint(Vec<u8>),
+
+    /// A TxOut sent to the burn address, identified by its global index.
+    #[prost(uint64, tag = "5")]
+    Burn(u64),
+}
+
+/// A list of token events that can be prost-encoded.
+#[derive(Clone, Message)]
+pub struct TokenEventList {
+    #[prost(message, repeated, tag = "1")]
+    pub events: Vec<TokenEvent>,
+}
+
+#[derive(Clone)]
+pub struct SecondaryIndexStore {
+    /// (signature timestamp, block index) -> ().
+    block_index_by_timestamp: Database,
+
+    /// (token id, block index) -> TokenEventList.
+    token_events_by_token_id_and_block: Database,
+
+    /// block version -> number of TxOuts.
+    tx_out_count_by_block_version: Database,
+}
+
+impl SecondaryIndexStore {
+    /// Opens an existing SecondaryIndexStore. Returns None if the secondary
+    /// indexes were never built for this ledger.
+    pub fn new(env: &Environment) -> Result<Option<Self>, Error> {
+        let block_index_by_timestamp = match env.open_db(Some(BLOCK_INDEX_BY_TIMESTAMP_DB_NAME)) {
+            Ok(database) => database,
+            Err(lmdb::Error::NotFound) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(SecondaryIndexStore {
+            block_index_by_timestamp,
+            token_events_by_token_id_and_block: env
+                .open_db(Some(TOKEN_EVENTS_BY_TOKEN_ID_AND_BLOCK_DB_NAME))?,
+            tx_out_count_by_block_version: env
+                .open_db(Some(TX_OUT_COUNT_BY_BLOCK_VERSION_DB_NAME))?,
+        }))
+    }
+
+    /// Creates the SecondaryIndexStore databases, or opens them if they
+    /// already exist.
+    pub fn create(env: &Environment) -> Result<Self, Error> {
+        // The database that `new` looks for is created last, so that a crash in
+        // between does not leave a partially created store behind.
+        let token_events_by_token_id_and_block = env.create_db(
+            Some(TOKEN_EVENTS_BY_TOKEN_ID_AND_BLOCK_DB_NAME),
+            DatabaseFlags::empty(),
+        )?;
+        let tx_out_count_by_block_version = env.create_db(
+            Some(TX_OUT_COUNT_BY_BLOCK_VERSION_DB_NAME),
+            DatabaseFlags::empty(),
+        )?;
+        let block_index_by_timestamp = env.create_db(
+            Some(BLOCK_INDEX_BY_TIMESTAMP_DB_NAME),
+            DatabaseFlags::empty(),
+        )?;
+        Ok(SecondaryIndexStore {
+            block_index_by_timestamp,
+            token_events_by_token_id_and_block,
+            tx_out_count_by_block_version,
+        })
+    }
+
+    /// Remove all entries from the indexes.
+    pub fn clear(&self, db_transaction: &mut RwTransaction) -> Result<(), Error> {
+        db_transaction.clear_db(self.block_index_by_timestamp)?;
+        db_transaction.clear_db(self.token_events_by_token_id_and_block)?;
+        db_transaction.clear_db(self.tx_out_count_by_block_version)?;
+        Ok(())
+    }
+
+    /// Add a block to the indexes.
+    pub fn write_block(
+        &self,
+        block: &Block,
+        block_contents: &BlockContents,
+        signature: Option<&BlockSignature>,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<(), Error> {
+        if let Some(signature) = signature {
+            db_transaction.put(
+                self.block_index_by_timestamp,
+                &two_u64s_to_key_bytes(signature.signed_at(), block.index),
+                b"",
+                WriteFlags::NO_OVERWRITE,
+            )?;
+        }
+
+        for (token_id, events) in token_events(block, block_contents) {
+            db_transaction.put(
+                self.token_events_by_token_id_and_block,
+                &two_u64s_to_key_bytes(token_id, block.index),
+                &encode(&TokenEventList { events }),
+                WriteFlags::NO_OVERWRITE,
+            )?;
+        }
+
+        let num_tx_outs = block_contents.outputs.len() as u64;
+        if num_tx_outs > 0 {
+            let key = u64_to_key_bytes(block.version as u64);
+            let count = self.get_tx_out_count(&key, db_transaction)?;
+            db_transaction.put(
+                self.tx_out_count_by_block_version,
+                &key,
+                &u64_to_key_bytes(count + num_tx_outs),
+                WriteFlags::empty(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove a block that was added with `write_block` from the indexes.
+    pub fn remove_block(
+        &self,
+        block: &Block,
+        block_contents: &BlockContents,
+        signature: Option<&BlockSignature>,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<(), Error> {
+        if let Some(signature) = signature {
+            db_transaction.del(
+                self.block_index_by_timestamp,
+                &two_u64s_to_key_bytes(signature.signed_at(), block.index),
+                None,
+            )?;
+        }
+
+        for token_id in token_events(block, block_contents).keys() {
+            db_transaction.del(
+                self.token_events_by_token_id_and_block,
+                &two_u64s_to_key_bytes(*token_id, block.index),
+                None,
+            )?;
+        }
+
+        let num_tx_outs = block_contents.outputs.len() as u64;
+        if num_tx_outs > 0 {
+            let key = u64_to_key_bytes(block.version as u64);
+            let count = self
+                .get_tx_out_count(&key, db_transaction)?
+                .checked_sub(num_tx_outs)
+                .ok_or(Error::NotFound)?;
+            if count > 0 {
+                db_transaction.put(
+                    self.tx_out_count_by_block_version,
+                    &key,
+                    &u64_to_key_bytes(count),
+                    WriteFlags::empty(),
+                )?;
+            } else {
+                db_transaction.del(self.tx_out_count_by_block_version, &key, None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the indexes of at most `limit` blocks whose signature timestamp is
+    /// in the range [start_timestamp, end_timestamp), ordered by timestamp.
+    pub fn get_block_indexes_by_timestamp(
+        &self,
+        start_timestamp: u64,
+        end_timestamp: u64,
+        limit: usize,
+        db_transaction: &impl Transaction,
+    ) -> Result<Vec<BlockIndex>, Error> {
+        let mut cursor = db_transaction.open_ro_cursor(self.block_index_by_timestamp)?;
+        let mut block_indexes = Vec::new();
+        for result in cursor.iter_from(two_u64s_to_key_bytes(start_timestamp, 0)) {
+            let (key, _value) = result?;
+            let (timestamp, block_index) = key_bytes_to_two_u64s(key);
+            if timestamp >= end_timestamp || block_indexes.len() >= limit {
+                break;
+            }
+            block_indexes.push(block_index);
+        }
+        Ok(block_indexes)
+    }
+
+    /// Get the mint and burn events of a token in blocks in the range
+    /// [start_block_index, end_block_index), ordered by block index.
+    pub fn get_token_events(
+        &self,
+        token_id: u64,
+        start_block_index: BlockIndex,
+        end_block_index: BlockIndex,
+        db_transaction: &impl Transaction,
+    ) -> Result<Vec<TokenEvent>, Error> {
+        let mut cursor = db_transaction.open_ro_cursor(self.token_events_by_token_id_and_block)?;
+        let mut events = Vec::new();
+        for result in cursor.iter_from(two_u64s_to_key_bytes(token_id, start_block_index)) {
+            let (key, value) = result?;
+            let (key_token_id, block_index) = key_bytes_to_two_u64s(key);
+            if key_token_id != token_id || block_index >= end_block_index {
+                break;
+            }
+            let event_list: TokenEventList = decode(value)?;
+            events.extend(event_list.events);
+        }
+        Ok(events)
+    }
+
+    /// Get the number of TxOuts in blocks of each block version.
+    pub fn get_tx_out_counts_by_block_version(
+        &self,
+        db_transaction: &impl Transaction,
+    ) -> Result<HashMap<u32, u64>, Error> {
+        let mut cursor = db_transaction.open_ro_cursor(self.tx_out_count_by_block_version)?;
+        cursor
+            .iter_start()
+            .map(|result| {
+                let (key, value) = result?;
+                Ok((key_bytes_to_u64(key) as u32, key_bytes_to_u64(value)))
+            })
+            .collect()
+    }
+
+    fn get_tx_out_count(
+        &self,
+        key: &[u8],
+        db_transaction: &impl Transaction,
+    ) -> Result<u64, Error> {
+        match db_transaction.get(self.tx_out_count_by_block_version, key) {
+            Ok(bytes) => Ok(key_bytes_to_u64(bytes)),
+            Err(lmdb::Error::NotFound) => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// The mint and burn events in a block, by token id.
+fn token_events(block: &Block, block_contents: &BlockContents) -> BTreeMap<u64, Vec<TokenEvent>> {
+    let mut events: BTreeMap<u64, Vec<TokenEvent>> = BTreeMap::new();
+
+    for mint_tx in &block_contents.mint_txs {
+        events
+            .entry(mint_tx.prefix.token_id)
+            .or_default()
+            .push(TokenEvent {
+                block_index: block.index,
+                token_id: mint_tx.prefix.token_id,
+                value: mint_tx.prefix.amount,
+                kind: Some(TokenEventKind::Mint(mint_tx.prefix.nonce.clone())),
+            });
+    }
+
+    let burn_spend_public_key = *burn_address().spend_public_key();
+    let first_tx_out_index = block.cumulative_txo_count - block_contents.outputs.len() as u64;
+    for (i, tx_out) in block_contents.outputs.iter().enumerate() {
+        if let Some(amount) = burned_amount(tx_out, &burn_spend_public_key) {
+            events
+                .entry(*amount.token_id)
+                .or_default()
+                .push(TokenEvent {
+                    block_index: block.index,
+                    token_id: *amount.token_id,
+                    value: amount.value,
+                    kind: Some(TokenEventKind::Burn(first_tx_out_index + i as u64)),
+                });
+        }
+    }
+
+    events
+}
+
+/// The amount of a TxOut if it was sent to the burn address.
+fn burned_amount(tx_out: &TxOut, burn_spend_public_key: &RistrettoPublic) -> Option<Amount> {
+    // Anyone can view-key match burned TxOuts, see MCIP #35.
+    let view_private_key = burn_address_view_private();
+    let (amount, _shared_secret) = tx_out.view_key_match(&view_private_key).ok()?;
+    let spend_public_key = recover_public_subaddress_spend_key(
+        &view_private_key,
+        &RistrettoPublic::try_from(&tx_out.target_key).ok()?,
+        &RistrettoPublic::try_from(&tx_out.public_key).ok()?,
+    );
+    if spend_public_key == *burn_spend_public_key {
+        Some(amount)
+    } else {
+        None
+    }
+}
+
+/// Keys made of two u64s sort by the first one, then by the second one.
+fn two_u64s_to_key_bytes(first: u64, second: u64) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&u64_to_key_bytes(first));
+    key[8..].copy_from_slice(&u64_to_key_bytes(second));
+    key
+}
+
+fn key_bytes_to_two_u64s(key: &[u8]) -> (u64, u64) {
+    (key_bytes_to_u64(&key[..8]), key_bytes_to_u64(&key[8..]))
+}
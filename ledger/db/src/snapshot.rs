@@ -0,0 +1,794 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Snapshots of a ledger database.
+//!
+//! A snapshot is a single file holding every entry of every LMDB database
+//! managed by [LedgerDB]. All entries are read in a single LMDB transaction,
+//! so a snapshot always reflects the ledger at a single block height.
+//!
+//! The file is laid out as follows:
+//! * [SNAPSHOT_MAGIC].
+//! * The length of the encoded [SnapshotHeader], as a little-endian u64,
+//!   followed by the header itself.
+//! * For each database listed in the header, in order, its entries in key
+//!   order. Each entry is the length of the key as a little-endian u32, the
+//!   key, the length of the value as a little-endian u32 and the value.
+//! * The Blake2b256 hash of everything above.
+//!
+//! The TxOut Merkle hashes and the mint state (active mint configurations and
+//! the MintTx and MintConfigTx nonce indexes) are derived from the blocks, and
+//! are not included. Snapshots of pruned ledgers can not be taken.
+//!
+//! The hash only detects corruption, since anyone can recompute it. Importing
+//! a snapshot checks the hash, and then re-checks the imported blocks from the
+//! origin block to the last block, which must have a trusted block ID. Since
+//! block IDs commit to the block contents and to the parent block, this ties
+//! every block in the snapshot to the trusted block. The TxOut and key image
+//! indexes are checked against the block contents. The Merkle hashes and the
+//! mint state are then rebuilt by replaying the blocks, and the Merkle root
+//! before each block is checked against the root element the block commits
+//! to.
+
+use crate::{
+    key_bytes_to_u64,
+    ledger_db::{
+        LedgerDbMetadataStoreSettings, BLOCKS_DB_NAME, BLOCK_METADATA_DB_NAME,
+        BLOCK_NUMBER_BY_TX_OUT_INDEX, BLOCK_SIGNATURES_DB_NAME, COUNTS_DB_NAME,
+        KEY_IMAGES_BY_BLOCK_DB_NAME, KEY_IMAGES_DB_NAME, MAX_LMDB_DATABASES, MAX_LMDB_FILE_SIZE,
+        NUM_BLOCKS_KEY, PRUNED_NUM_BLOCKS_KEY, TX_OUTS_BY_BLOCK_DB_NAME,
+    },
+    mint_config_store::{
+        ACTIVE_MINT_CONFIGS_BY_TOKEN_ID_DB_NAME,
+        BLOCK_INDEX_BY_MINT_CONFIG_TX_NONCE_AND_TOKEN_ID_DB_NAME,
+        VALIDATED_MINT_CONFIG_TXS_BY_BLOCK_DB_NAME,
+    },
+    mint_tx_store::{BLOCK_INDEX_BY_MINT_TX_NONCE_AND_TOKEN_ID_DB_NAME, MINT_TXS_BY_BLOCK_DB_NAME},
+    tx_out_store::{
+        COUNTS_DB_NAME as TX_OUT_COUNTS_DB_NAME, MERKLE_HASH_BY_RANGE_DB_NAME,
+        TX_OUT_BY_INDEX_DB_NAME, TX_OUT_INDEX_BY_HASH_DB_NAME, TX_OUT_INDEX_BY_PUBLIC_KEY_DB_NAME,
+    },
+    u64_to_key_bytes, Error, Ledger, LedgerDB, MetadataStore, MetadataStoreSettings,
+};
+use lmdb::{Cursor, DatabaseFlags, Environment, Transaction, WriteFlags};
+use mc_blockchain_types::{Block, BlockID};
+use mc_common::{logger::global_log, HashMap};
+use mc_crypto_hashes::{Blake2b256, Digest};
+use mc_util_serial::{decode, encode, Message};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// The first bytes of every snapshot file.
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"MCLEDGER";
+
+/// The version of the snapshot file format.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 2;
+
+/// The largest header that will be read from a snapshot file.
+const MAX_HEADER_LEN: u64 = 1 << 20;
+
+/// The largest key or value that will be read from a snapshot file. The
+/// largest values are the key images of a full block.
+const MAX_ENTRY_LEN: u64 = 1 << 26;
+
+/// The LMDB databases included in a snapshot. The metadata store is not
+/// included, since a snapshot can only be imported by a ledger with the same
+/// database format version.
+const SNAPSHOT_DATABASES: &[&str] = &[
+    COUNTS_DB_NAME,
+    BLOCKS_DB_NAME,
+    BLOCK_SIGNATURES_DB_NAME,
+    BLOCK_METADATA_DB_NAME,
+    KEY_IMAGES_DB_NAME,
+    KEY_IMAGES_BY_BLOCK_DB_NAME,
+    TX_OUTS_BY_BLOCK_DB_NAME,
+    BLOCK_NUMBER_BY_TX_OUT_INDEX,
+    TX_OUT_COUNTS_DB_NAME,
+    TX_OUT_INDEX_BY_HASH_DB_NAME,
+    TX_OUT_INDEX_BY_PUBLIC_KEY_DB_NAME,
+    TX_OUT_BY_INDEX_DB_NAME,
+    VALIDATED_MINT_CONFIG_TXS_BY_BLOCK_DB_NAME,
+    MINT_TXS_BY_BLOCK_DB_NAME,
+];
+
+/// The LMDB databases that are rebuilt from the blocks when importing a
+/// snapshot, instead of being included in it.
+const REBUILT_DATABASES: &[&str] = &[
+    MERKLE_HASH_BY_RANGE_DB_NAME,
+    ACTIVE_MINT_CONFIGS_BY_TOKEN_ID_DB_NAME,
+    BLOCK_INDEX_BY_MINT_CONFIG_TX_NONCE_AND_TOKEN_ID_DB_NAME,
+    BLOCK_INDEX_BY_MINT_TX_NONCE_AND_TOKEN_ID_DB_NAME,
+];
+
+/// Describes the contents of a snapshot file.
+#[derive(Clone, Eq, Message, PartialEq)]
+pub struct SnapshotHeader {
+    /// The version of the snapshot file format.
+    #[prost(uint32, tag = "1")]
+    pub format_version: u32,
+
+    /// The format version of the ledger database the snapshot was taken
+    /// from.
+    #[prost(uint64, tag = "2")]
+    pub database_format_version: u64,
+
+    /// The number of blocks in the snapshot.
+    #[prost(uint64, tag = "3")]
+    pub num_blocks: u64,
+
+    /// The ID of the last block in the snapshot.
+    #[prost(message, required, tag = "4")]
+    pub last_block_id: BlockID,
+
+    /// The databases in the snapshot, in the order their entries appear in.
+    #[prost(message, repeated, tag = "5")]
+    pub databases: Vec<SnapshotDatabase>,
+}
+
+/// A database in a snapshot file.
+#[derive(Clone, Eq, Message, PartialEq)]
+pub struct SnapshotDatabase {
+    /// The LMDB database name.
+    #[prost(string, tag = "1")]
+    pub name: String,
+
+    /// The number of entries in the database.
+    #[prost(uint64, tag = "2")]
+    pub num_entries: u64,
+}
+
+/// Writes a snapshot of the ledger database in `env` to `path`. Pruned ledgers
+/// are refused, since the snapshot could not be verified when imported.
+pub fn export_snapshot(env: &Environment, path: &Path) -> Result<SnapshotHeader, Error> {
+    let metadata_store = MetadataStore::<LedgerDbMetadataStoreSettings>::new(env)?;
+    let databases = SNAPSHOT_DATABASES
+        .iter()
+        .map(|name| Ok((*name, env.open_db(Some(name))?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let counts = env.open_db(Some(COUNTS_DB_NAME))?;
+    let blocks = env.open_db(Some(BLOCKS_DB_NAME))?;
+
+    let db_transaction = env.begin_ro_txn()?;
+
+    let version = metadata_store.get_version(&db_transaction)?;
+    let num_blocks = key_bytes_to_u64(db_transaction.get(counts, &NUM_BLOCKS_KEY)?);
+    if num_blocks == 0 {
+        return Err(Error::NoTransactions);
+    }
+    match db_transaction.get(counts, &PRUNED_NUM_BLOCKS_KEY) {
+        Ok(bytes) if key_bytes_to_u64(bytes) > 0 => return Err(Error::SnapshotOfPrunedLedger),
+        Ok(_) | Err(lmdb::Error::NotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+    let last_block_bytes = db_transaction.get(blocks, &u64_to_key_bytes(num_blocks - 1))?;
+    let last_block: Block = decode(last_block_bytes)?;
+
+    let mut header = SnapshotHeader {
+        format_version: SNAPSHOT_FORMAT_VERSION,
+        database_format_version: version.database_format_version,
+        num_blocks,
+        last_block_id: last_block.id,
+        databases: Vec::with_capacity(databases.len()),
+    };
+    for (name, database) in &databases {
+        let mut cursor = db_transaction.open_ro_cursor(*database)?;
+        let num_entries = cursor
+            .iter_start()
+            .try_fold(0u64, |num_entries, result| result.map(|_| num_entries + 1))?;
+        header.databases.push(SnapshotDatabase {
+            name: name.to_string(),
+            num_entries,
+        });
+    }
+
+    let file = File::create(path)?;
+    let mut writer = HashingWriter::new(BufWriter::new(file));
+
+    writer.write_all(SNAPSHOT_MAGIC)?;
+    let header_bytes = encode(&header);
+    writer.write_all(&(header_bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(&header_bytes)?;
+
+    for (_name, database) in &databases {
+        let mut cursor = db_transaction.open_ro_cursor(*database)?;
+        for result in cursor.iter_start() {
+            let (key, value) = result?;
+            writer.write_all(&(key.len() as u32).to_le_bytes())?;
+            writer.write_all(key)?;
+            writer.write_all(&(value.len() as u32).to_le_bytes())?;
+            writer.write_all(value)?;
+        }
+    }
+
+    let (mut inner, checksum) = writer.finalize();
+    inner.write_all(&checksum)?;
+    let file = inner.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+
+    global_log::info!(
+        "Exported a snapshot of {} blocks to {:?}",
+        header.num_blocks,
+        path
+    );
+    Ok(header)
+}
+
+/// Creates a ledger database at `ledger_path` from the snapshot at
+/// `snapshot_path`, after checking that the last block in the snapshot has
+/// the ID `trusted_block_id`.
+///
+/// `ledger_path` must not contain a ledger database. If the snapshot is
+/// rejected, nothing is left behind in `ledger_path`.
+pub fn import_snapshot(
+    snapshot_path: &Path,
+    ledger_path: &Path,
+    trusted_block_id: &BlockID,
+) -> Result<LedgerDB, Error> {
+    if ledger_path.join("data.mdb").exists() {
+        return Err(Error::ImportTargetExists);
+    }
+    fs::create_dir_all(ledger_path)?;
+
+    import_snapshot_impl(snapshot_path, ledger_path, trusted_block_id).map_err(|err| {
+        // The database was closed when the import failed, so nothing is using
+        // these files anymore.
+        let _ = fs::remove_file(ledger_path.join("data.mdb"));
+        let _ = fs::remove_file(ledger_path.join("lock.mdb"));
+        err
+    })
+}
+
+fn import_snapshot_impl(
+    snapshot_path: &Path,
+    ledger_path: &Path,
+    trusted_block_id: &BlockID,
+) -> Result<LedgerDB, Error> {
+    let file = File::open(snapshot_path)?;
+    let mut reader = HashingReader::new(BufReader::new(file));
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != SNAPSHOT_MAGIC {
+        return Err(Error::InvalidSnapshot("not a ledger snapshot".to_string()));
+    }
+
+    let header_len = read_u64(&mut reader)?;
+    if header_len > MAX_HEADER_LEN {
+        return Err(Error::InvalidSnapshot(format!(
+            "header too long: {} bytes",
+            header_len
+        )));
+    }
+    let mut header_bytes = vec![0u8; header_len as usize];
+    reader.read_exact(&mut header_bytes)?;
+    let header: SnapshotHeader = decode(&header_bytes)?;
+    validate_header(&header, trusted_block_id)?;
+
+    // Entries are written with NO_OVERWRITE, so that the number of entries in
+    // each database is known to match the header once the import succeeds.
+    {
+        let env = Environment::new()
+            .set_max_dbs(MAX_LMDB_DATABASES)
+            .set_map_size(MAX_LMDB_FILE_SIZE)
+            .open(ledger_path)?;
+        MetadataStore::<LedgerDbMetadataStoreSettings>::create(&env)?;
+        let databases = SNAPSHOT_DATABASES
+            .iter()
+            .map(|name| env.create_db(Some(name), DatabaseFlags::empty()))
+            .collect::<Result<Vec<_>, _>>()?;
+        for name in REBUILT_DATABASES {
+            env.create_db(Some(name), DatabaseFlags::empty())?;
+        }
+
+        let mut db_transaction = env.begin_rw_txn()?;
+        for (snapshot_database, database) in header.databases.iter().zip(databases) {
+            for _ in 0..snapshot_database.num_entries {
+                let key = read_bytes(&mut reader)?;
+                let value = read_bytes(&mut reader)?;
+                db_transaction
+                    .put(database, &key, &value, WriteFlags::NO_OVERWRITE)
+                    .map_err(|err| match err {
+                        lmdb::Error::KeyExist => Error::InvalidSnapshot(format!(
+                            "duplicate key in {}",
+                            snapshot_database.name
+                        )),
+                        err => Error::from(err),
+                    })?;
+            }
+        }
+
+        let (mut inner, expected_checksum) = reader.finalize();
+        let mut checksum = [0u8; 32];
+        inner.read_exact(&mut checksum)?;
+        if checksum != expected_checksum {
+            return Err(Error::SnapshotChecksumMismatch);
+        }
+        if inner.read(&mut [0u8; 1])? != 0 {
+            return Err(Error::InvalidSnapshot(
+                "trailing data after checksum".to_string(),
+            ));
+        }
+
+        db_transaction.commit()?;
+        env.sync(true)?;
+    }
+
+    let ledger_db = LedgerDB::open(ledger_path)?;
+    verify_imported_ledger(&ledger_db, &header, trusted_block_id)?;
+    ledger_db.rebuild_imported_state()?;
+
+    global_log::info!(
+        "Imported a snapshot of {} blocks into {:?}",
+        header.num_blocks,
+        ledger_path
+    );
+    Ok(ledger_db)
+}
+
+fn validate_header(header: &SnapshotHeader, trusted_block_id: &BlockID) -> Result<(), Error> {
+    if header.format_version != SNAPSHOT_FORMAT_VERSION {
+        return Err(Error::InvalidSnapshot(format!(
+            "unsupported format version {}",
+            header.format_version
+        )));
+    }
+    if header.database_format_version != LedgerDbMetadataStoreSettings::LATEST_VERSION {
+        return Err(Error::InvalidSnapshot(format!(
+            "database format version {} does not match {}",
+            header.database_format_version,
+            LedgerDbMetadataStoreSettings::LATEST_VERSION
+        )));
+    }
+    let names: Vec<&str> = header.databases.iter().map(|db| db.name.as_str()).collect();
+    if names != SNAPSHOT_DATABASES {
+        return Err(Error::InvalidSnapshot(format!(
+            "unexpected databases {:?}",
+            names
+        )));
+    }
+    if &header.last_block_id != trusted_block_id {
+        return Err(Error::InvalidSnapshot(format!(
+            "last block ID {} does not match trusted block ID {}",
+            header.last_block_id, trusted_block_id
+        )));
+    }
+    Ok(())
+}
+
+/// Re-checks the blocks of an imported ledger, and checks that the TxOut and
+/// key image indexes hold exactly what the blocks contain.
+fn verify_imported_ledger(
+    ledger_db: &LedgerDB,
+    header: &SnapshotHeader,
+    trusted_block_id: &BlockID,
+) -> Result<(), Error> {
+    let invalid = |block_index: u64, what: &str| {
+        Error::InvalidSnapshot(format!("block {}: {}", block_index, what))
+    };
+
+    let num_blocks = ledger_db.num_blocks()?;
+    if num_blocks != header.num_blocks {
+        return Err(Error::InvalidSnapshot(format!(
+            "expected {} blocks, found {}",
+            header.num_blocks, num_blocks
+        )));
+    }
+
+    let mut parent_id = BlockID::default();
+    let mut num_tx_outs = 0;
+    let mut num_key_images = 0;
+    for block_index in 0..num_blocks {
+        let block = ledger_db.get_block(block_index)?;
+        let contents = ledger_db.get_block_contents(block_index)?;
+
+        if block.index != block_index {
+            return Err(invalid(block_index, "wrong block index"));
+        }
+        if block.parent_id != parent_id {
+            return Err(invalid(block_index, "wrong parent ID"));
+        }
+        if !block.is_block_id_valid() {
+            return Err(invalid(block_index, "invalid block ID"));
+        }
+        if block.contents_hash != contents.hash() {
+            return Err(invalid(block_index, "wrong contents hash"));
+        }
+        if block.cumulative_txo_count != num_tx_outs + contents.outputs.len() as u64 {
+            return Err(invalid(block_index, "wrong cumulative TxOut count"));
+        }
+
+        for tx_out in &contents.outputs {
+            if ledger_db.get_tx_out_by_index(num_tx_outs)? != *tx_out
+                || ledger_db.get_tx_out_index_by_hash(&tx_out.hash())? != num_tx_outs
+                || ledger_db.get_tx_out_index_by_public_key(&tx_out.public_key)? != num_tx_outs
+                || ledger_db.get_block_index_by_tx_out_index(num_tx_outs)? != block_index
+            {
+                return Err(invalid(
+                    block_index,
+                    &format!("wrong index for TxOut {}", num_tx_outs),
+                ));
+            }
+            num_tx_outs += 1;
+        }
+
+        if ledger_db.get_key_images_by_block(block_index)? != contents.key_images {
+            return Err(invalid(block_index, "wrong key images"));
+        }
+        for key_image in &contents.key_images {
+            if ledger_db.check_key_image(key_image)? != Some(block_index) {
+                return Err(invalid(block_index, "wrong key image index"));
+            }
+        }
+        num_key_images += contents.key_images.len() as u64;
+
+        parent_id = block.id;
+    }
+
+    if &parent_id != trusted_block_id {
+        return Err(Error::InvalidSnapshot(format!(
+            "last block ID {} does not match trusted block ID {}",
+            parent_id, trusted_block_id
+        )));
+    }
+    if ledger_db.num_txos()? != num_tx_outs {
+        return Err(Error::InvalidSnapshot("wrong number of TxOuts".to_string()));
+    }
+
+    // Every index entry was checked above, so matching counts mean there are no
+    // entries that do not belong to a block.
+    let num_entries: HashMap<&str, u64> = header
+        .databases
+        .iter()
+        .map(|db| (db.name.as_str(), db.num_entries))
+        .collect();
+    let expected_num_entries = [
+        (BLOCKS_DB_NAME, num_blocks),
+        (KEY_IMAGES_BY_BLOCK_DB_NAME, num_blocks),
+        (TX_OUTS_BY_BLOCK_DB_NAME, num_blocks),
+        (KEY_IMAGES_DB_NAME, num_key_images),
+        (BLOCK_NUMBER_BY_TX_OUT_INDEX, num_tx_outs),
+        (TX_OUT_BY_INDEX_DB_NAME, num_tx_outs),
+        (TX_OUT_INDEX_BY_HASH_DB_NAME, num_tx_outs),
+        (TX_OUT_INDEX_BY_PUBLIC_KEY_DB_NAME, num_tx_outs),
+        (VALIDATED_MINT_CONFIG_TXS_BY_BLOCK_DB_NAME, num_blocks),
+        (MINT_TXS_BY_BLOCK_DB_NAME, num_blocks),
+    ];
+    for (name, expected) in expected_num_entries {
+        if num_entries.get(name) != Some(&expected) {
+            return Err(Error::InvalidSnapshot(format!(
+                "expected {} entries in {}, found {:?}",
+                expected,
+                name,
+                num_entries.get(name)
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, Error> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>, Error> {
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes)?;
+    let len = u32::from_le_bytes(len_bytes) as u64;
+    if len > MAX_ENTRY_LEN {
+        return Err(Error::InvalidSnapshot(format!(
+            "entry too long: {} bytes",
+            len
+        )));
+    }
+    // The buffer only grows as data is actually read, so a truncated file can
+    // not make us allocate the full length.
+    let mut bytes = Vec::new();
+    if reader.take(len).read_to_end(&mut bytes)? as u64 != len {
+        return Err(Error::InvalidSnapshot("truncated entry".to_string()));
+    }
+    Ok(bytes)
+}
+
+/// Hashes everything written through it.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Blake2b256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Blake2b256::new(),
+        }
+    }
+
+    fn finalize(self) -> (W, [u8; 32]) {
+        (self.inner, self.hasher.finalize().into())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hashes everything read through it.
+struct HashingReader<R: Read> {
+    inner: R,
+    hasher: Blake2b256,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Blake2b256::new(),
+        }
+    }
+
+    fn finalize(self) -> (R, [u8; 32]) {
+        (self.inner, self.hasher.finalize().into())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{add_block_contents_to_ledger, create_ledger};
+    use mc_blockchain_test_utils::get_blocks;
+    use mc_blockchain_types::BlockContents;
+    use mc_transaction_core::{
+        tx::{TxOutMembershipElement, TxOutMembershipHash},
+        BlockVersion, TokenId,
+    };
+    use mc_transaction_core_test_utils::{
+        create_mint_config_tx_and_signers, create_mint_tx, create_test_tx_out,
+        mint_config_tx_to_validated,
+    };
+    use mc_util_test_helper::{get_seeded_rng, RngType, SeedableRng};
+    use tempdir::TempDir;
+
+    fn create_populated_ledger() -> LedgerDB {
+        let mut ledger_db = create_ledger();
+        let blocks = get_blocks(
+            BlockVersion::MAX,
+            5,
+            2,
+            1,
+            2,
+            1 << 20,
+            None,
+            &mut get_seeded_rng(),
+        );
+        for block_data in &blocks {
+            ledger_db.append_block_data(block_data).unwrap();
+        }
+        ledger_db
+    }
+
+    // Appends a block with a single TxOut, committing to `root_element`.
+    fn append_block_with_root_element(
+        ledger_db: &mut LedgerDB,
+        root_element: &TxOutMembershipElement,
+        rng: &mut RngType,
+    ) -> Block {
+        let contents = BlockContents {
+            outputs: vec![create_test_tx_out(BlockVersion::MAX, rng)],
+            ..Default::default()
+        };
+        let parent = ledger_db.get_latest_block().unwrap();
+        let block = Block::new_with_parent(BlockVersion::MAX, &parent, root_element, &contents);
+        ledger_db
+            .append_block(&block, &contents, None, None)
+            .unwrap();
+        block
+    }
+
+    #[test]
+    fn export_and_import_snapshot() {
+        let ledger_db = create_populated_ledger();
+        let temp_dir = TempDir::new("snapshot").unwrap();
+        let snapshot_path = temp_dir.path().join("ledger.snapshot");
+
+        let header = ledger_db.export_snapshot(&snapshot_path).unwrap();
+        assert_eq!(header.num_blocks, 5);
+        let last_block = ledger_db.get_latest_block().unwrap();
+        assert_eq!(header.last_block_id, last_block.id);
+
+        let ledger_path = temp_dir.path().join("ledger");
+        let imported =
+            LedgerDB::import_snapshot(&snapshot_path, &ledger_path, &last_block.id).unwrap();
+
+        assert_eq!(imported.num_blocks().unwrap(), 5);
+        assert_eq!(imported.num_txos().unwrap(), ledger_db.num_txos().unwrap());
+        for block_index in 0..5 {
+            assert_eq!(
+                imported.get_block_data(block_index).unwrap(),
+                ledger_db.get_block_data(block_index).unwrap()
+            );
+        }
+        assert_eq!(
+            imported.get_root_tx_out_membership_element().unwrap(),
+            ledger_db.get_root_tx_out_membership_element().unwrap()
+        );
+
+        // Importing over an existing ledger is refused.
+        assert_eq!(
+            LedgerDB::import_snapshot(&snapshot_path, &ledger_path, &last_block.id).map(|_| ()),
+            Err(Error::ImportTargetExists)
+        );
+    }
+
+    #[test]
+    fn import_rejects_untrusted_snapshot() {
+        let ledger_db = create_populated_ledger();
+        let temp_dir = TempDir::new("snapshot").unwrap();
+        let snapshot_path = temp_dir.path().join("ledger.snapshot");
+        ledger_db.export_snapshot(&snapshot_path).unwrap();
+
+        // The snapshot does not end at the trusted block.
+        let ledger_path = temp_dir.path().join("ledger");
+        let other_block_id = ledger_db.get_block(3).unwrap().id;
+        assert!(matches!(
+            LedgerDB::import_snapshot(&snapshot_path, &ledger_path, &other_block_id),
+            Err(Error::InvalidSnapshot(_))
+        ));
+        assert!(!ledger_path.join("data.mdb").exists());
+
+        // The snapshot was modified after it was written.
+        let mut bytes = fs::read(&snapshot_path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        fs::write(&snapshot_path, bytes).unwrap();
+
+        let last_block_id = ledger_db.get_latest_block().unwrap().id;
+        assert_eq!(
+            LedgerDB::import_snapshot(&snapshot_path, &ledger_path, &last_block_id).map(|_| ()),
+            Err(Error::SnapshotChecksumMismatch)
+        );
+        assert!(!ledger_path.join("data.mdb").exists());
+    }
+
+    #[test]
+    fn import_rebuilds_merkle_hashes_and_mint_state() {
+        let mut rng = RngType::from_seed([7u8; 32]);
+        let mut ledger_db = create_populated_ledger();
+        let token_id = TokenId::from(1);
+
+        let (mint_config_tx, signers) = create_mint_config_tx_and_signers(token_id, &mut rng);
+        add_block_contents_to_ledger(
+            &mut ledger_db,
+            BlockVersion::MAX,
+            BlockContents {
+                validated_mint_config_txs: vec![mint_config_tx_to_validated(&mint_config_tx)],
+                ..Default::default()
+            },
+            &mut rng,
+        )
+        .unwrap();
+        let mint_tx = create_mint_tx(token_id, &signers, 10, &mut rng);
+        add_block_contents_to_ledger(
+            &mut ledger_db,
+            BlockVersion::MAX,
+            BlockContents {
+                mint_txs: vec![mint_tx.clone()],
+                outputs: vec![create_test_tx_out(BlockVersion::MAX, &mut rng)],
+                ..Default::default()
+            },
+            &mut rng,
+        )
+        .unwrap();
+        let root_element = ledger_db.get_root_tx_out_membership_element().unwrap();
+        let last_block = append_block_with_root_element(&mut ledger_db, &root_element, &mut rng);
+
+        let temp_dir = TempDir::new("snapshot").unwrap();
+        let snapshot_path = temp_dir.path().join("ledger.snapshot");
+        ledger_db.export_snapshot(&snapshot_path).unwrap();
+        let ledger_path = temp_dir.path().join("ledger");
+        let imported =
+            LedgerDB::import_snapshot(&snapshot_path, &ledger_path, &last_block.id).unwrap();
+
+        assert_eq!(
+            imported.get_root_tx_out_membership_element().unwrap(),
+            ledger_db.get_root_tx_out_membership_element().unwrap()
+        );
+        assert_eq!(
+            imported.get_tx_out_proof_of_memberships(&[3]).unwrap(),
+            ledger_db.get_tx_out_proof_of_memberships(&[3]).unwrap()
+        );
+        assert_eq!(
+            imported.get_active_mint_configs(token_id).unwrap(),
+            ledger_db.get_active_mint_configs(token_id).unwrap()
+        );
+        assert_eq!(
+            imported
+                .check_mint_config_tx_nonce(*token_id, &mint_config_tx.prefix.nonce)
+                .unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            imported
+                .check_mint_tx_nonce(*token_id, &mint_tx.prefix.nonce)
+                .unwrap(),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn import_rejects_wrong_root_element() {
+        let mut rng = RngType::from_seed([7u8; 32]);
+        let mut ledger_db = create_populated_ledger();
+        let mut root_element = ledger_db.get_root_tx_out_membership_element().unwrap();
+        root_element.hash = TxOutMembershipHash::from([1u8; 32]);
+        let last_block = append_block_with_root_element(&mut ledger_db, &root_element, &mut rng);
+
+        let temp_dir = TempDir::new("snapshot").unwrap();
+        let snapshot_path = temp_dir.path().join("ledger.snapshot");
+        ledger_db.export_snapshot(&snapshot_path).unwrap();
+        let ledger_path = temp_dir.path().join("ledger");
+        assert_eq!(
+            LedgerDB::import_snapshot(&snapshot_path, &ledger_path, &last_block.id).map(|_| ()),
+            Err(Error::InvalidSnapshot(
+                "block 5: wrong root element".to_string()
+            ))
+        );
+        assert!(!ledger_path.join("data.mdb").exists());
+    }
+
+    #[test]
+    fn export_rejects_pruned_ledger() {
+        let mut ledger_db = create_populated_ledger();
+        ledger_db
+            .prune_tx_outs(3, 2, &mut get_seeded_rng())
+            .unwrap();
+
+        let temp_dir = TempDir::new("snapshot").unwrap();
+        let snapshot_path = temp_dir.path().join("ledger.snapshot");
+        assert_eq!(
+            ledger_db.export_snapshot(&snapshot_path).map(|_| ()),
+            Err(Error::SnapshotOfPrunedLedger)
+        );
+    }
+
+    #[test]
+    fn read_bytes_rejects_oversized_and_truncated_entries() {
+        let mut bytes = u32::MAX.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0u8; 16]);
+        assert!(matches!(
+            read_bytes(&mut &bytes[..]),
+            Err(Error::InvalidSnapshot(_))
+        ));
+
+        let mut bytes = 32u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0u8; 16]);
+        assert!(matches!(
+            read_bytes(&mut &bytes[..]),
+            Err(Error::InvalidSnapshot(_))
+        ));
+
+        let mut bytes = 16u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[7u8; 16]);
+        assert_eq!(read_bytes(&mut &bytes[..]).unwrap(), vec![7u8; 16]);
+    }
+}
@@ -1,6 +1,6 @@
 // Copyright (c) 2018-2022 The MobileCoin Foundation
 
-use crate::{ActiveMintConfig, ActiveMintConfigs, Error, Ledger};
+use crate::{ActiveMintConfig, ActiveMintConfigs, Error, Ledger, TokenEvent};
 use mc_blockchain_test_utils::get_blocks;
 use mc_blockchain_types::{
     Block, BlockContents, BlockData, BlockIndex, BlockMetadata, BlockSignature, BlockVersion,
@@ -235,6 +235,28 @@ impl Ledger for MockLedger {
     ) -> Result<ActiveMintConfig, Error> {
         unimplemented!()
     }
+
+    fn get_block_indexes_by_timestamp(
+        &self,
+        _start_timestamp: u64,
+        _end_timestamp: u64,
+        _limit: usize,
+    ) -> Result<Vec<BlockIndex>, Error> {
+        unimplemented!()
+    }
+
+    fn get_token_events(
+        &self,
+        _token_id: TokenId,
+        _start_block_index: BlockIndex,
+        _end_block_index: BlockIndex,
+    ) -> Result<Vec<TokenEvent>, Error> {
+        unimplemented!()
+    }
+
+    fn get_tx_out_counts_by_block_version(&self) -> Result<HashMap<u32, u64>, Error> {
+        unimplemented!()
+    }
 }
 
 /// Creates a MockLedger and populates it with blocks and transactions.
@@ -24,7 +24,7 @@
 //! * [Attacking Merkle Trees with a Second Preimage Attack](https://flawed.net.nz/2018/02/21/attacking-merkle-trees-with-a-second-preimage-attack/)
 
 use crate::{key_bytes_to_u64, u64_to_key_bytes, Error};
-use lmdb::{Database, DatabaseFlags, Environment, RwTransaction, Transaction, WriteFlags};
+use lmdb::{Cursor, Database, DatabaseFlags, Environment, RwTransaction, Transaction, WriteFlags};
 use mc_common::Hash;
 use mc_crypto_keys::CompressedRistrettoPublic;
 use mc_transaction_core::{
@@ -32,6 +32,7 @@ use mc_transaction_core::{
     tx::{TxOut, TxOutMembershipElement, TxOutMembershipProof},
 };
 use mc_util_serial::{decode, encode};
+use rand::{CryptoRng, Rng, RngCore};
 
 // LMDB Database names.
 pub const COUNTS_DB_NAME: &str = "tx_out_store:counts";
@@ -42,14 +43,23 @@ pub const MERKLE_HASH_BY_RANGE_DB_NAME: &str = "tx_out_store:merkle_hash_by_rang
 
 // Keys used by the `counts` database.
 pub const NUM_TX_OUTS_KEY: &str = "num_tx_outs";
+pub const PRUNE_HORIZON_KEY: &str = "prune_horizon";
+pub const NUM_PRUNED_TX_OUTS_KEY: &str = "num_pruned_tx_outs";
 
 #[derive(Clone)]
 pub struct TxOutStore {
     /// Aggregate counts
     /// * `NUM_TX_OUTS_KEY` --> Number (u64) of TxOuts in the ledger.
+    /// * `PRUNE_HORIZON_KEY` --> Index (u64) below which TxOuts may have been
+    ///   pruned. Missing if the store has never been pruned.
+    /// * `NUM_PRUNED_TX_OUTS_KEY` --> Number (u64) of TxOuts that have been
+    ///   pruned. Missing if the store has never been pruned.
     counts: Database,
 
     /// TxOut by index. `key_bytes_to_u64(index) -> encode(&tx_out)`
+    ///
+    /// Pruning removes entries from this database. Everything else about a
+    /// pruned TxOut is kept.
     tx_out_by_index: Database,
 
     /// `tx_out.hash() -> u64_to_key_bytes(index)`
@@ -146,6 +156,171 @@ impl TxOutStore {
         Ok(index)
     }
 
+    /// Removes all TxOuts with an index of `num_tx_outs` or more, and restores
+    /// the Merkle hashes to what they were when the store held `num_tx_outs`
+    /// TxOuts.
+    pub fn truncate(
+        &self,
+        num_tx_outs: u64,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<(), Error> {
+        let prev_num_tx_outs = self.num_tx_outs(db_transaction)?;
+        if num_tx_outs > prev_num_tx_outs {
+            return Err(Error::TxOutIndexOutOfBounds(num_tx_outs));
+        }
+        if num_tx_outs == prev_num_tx_outs {
+            return Ok(());
+        }
+        // Removing a TxOut requires its body, in order to find its hash and
+        // public key.
+        if num_tx_outs < self.prune_horizon(db_transaction)? {
+            return Err(Error::TxOutPruned(num_tx_outs));
+        }
+
+        for index in num_tx_outs..prev_num_tx_outs {
+            let tx_out = self.get_tx_out_by_index(index, db_transaction)?;
+            db_transaction.del(self.tx_out_index_by_hash, &tx_out.hash(), None)?;
+            db_transaction.del(self.tx_out_index_by_public_key, &tx_out.public_key, None)?;
+            db_transaction.del(self.tx_out_by_index, &u64_to_key_bytes(index), None)?;
+        }
+
+        // Ranges that only contain removed TxOuts. Keys are ordered by the start
+        // of the range.
+        let removed_range_keys = {
+            let mut cursor = db_transaction.open_ro_cursor(self.merkle_hashes)?;
+            cursor
+                .iter_from(u64_to_key_bytes(num_tx_outs))
+                .map(|result| result.map(|(key, _hash)| key.to_vec()))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        for key in removed_range_keys {
+            db_transaction.del(self.merkle_hashes, &key, None)?;
+        }
+
+        // Ranges that contain both kept and removed TxOuts all contain the last
+        // kept TxOut. They are removed here, and the ones that are still part of
+        // the smaller tree are recomputed below.
+        if num_tx_outs > 0 {
+            for (low, high) in containing_ranges(num_tx_outs - 1, prev_num_tx_outs)? {
+                if high < num_tx_outs {
+                    continue;
+                }
+                let range = Range::new(low, high)?;
+                match db_transaction.del(self.merkle_hashes, &range_to_key_bytes(&range), None) {
+                    Ok(()) | Err(lmdb::Error::NotFound) => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        db_transaction.put(
+            self.counts,
+            &NUM_TX_OUTS_KEY,
+            &u64_to_key_bytes(num_tx_outs),
+            WriteFlags::empty(),
+        )?;
+
+        if num_tx_outs > 0 {
+            self.restore_merkle_hashes(num_tx_outs - 1, db_transaction)?;
+        }
+
+        Ok(())
+    }
+
+    /// Discards the Merkle hashes, and sets the number of TxOuts to zero
+    /// without removing the TxOuts themselves, so that `push_stored` can add
+    /// them back to the Merkle tree one at a time. Returns the previous number
+    /// of TxOuts.
+    ///
+    /// This is used to recompute the Merkle hashes of TxOuts that were not
+    /// written with `push`.
+    pub fn reset_merkle_tree(&self, db_transaction: &mut RwTransaction) -> Result<u64, Error> {
+        let num_tx_outs = self.num_tx_outs(db_transaction)?;
+        db_transaction.clear_db(self.merkle_hashes)?;
+        db_transaction.put(
+            self.counts,
+            &NUM_TX_OUTS_KEY,
+            &u64_to_key_bytes(0),
+            WriteFlags::empty(),
+        )?;
+        Ok(num_tx_outs)
+    }
+
+    /// Adds the next TxOut that is already stored back to the count and the
+    /// Merkle tree, after `reset_merkle_tree`. Returns its index.
+    pub fn push_stored(&self, db_transaction: &mut RwTransaction) -> Result<u64, Error> {
+        let index = self.num_tx_outs(db_transaction)?;
+        db_transaction.put(
+            self.counts,
+            &NUM_TX_OUTS_KEY,
+            &u64_to_key_bytes(index + 1),
+            WriteFlags::empty(),
+        )?;
+        self.restore_merkle_hashes(index, db_transaction)?;
+        Ok(index)
+    }
+
+    /// Removes the bodies of TxOuts with an index below `prune_horizon`,
+    /// except for a random sample of roughly one in `retain_one_in` of them.
+    /// A `retain_one_in` of zero keeps none of them.
+    ///
+    /// TxOuts below the previous prune horizon are not considered again, so
+    /// repeated calls with an increasing horizon only look at new TxOuts.
+    /// Merkle hashes and the hash and public key indexes are kept, so proofs
+    /// of membership and duplicate checks are unaffected.
+    ///
+    /// Returns the number of TxOuts that were pruned by this call.
+    pub fn prune<R: RngCore + CryptoRng>(
+        &self,
+        prune_horizon: u64,
+        retain_one_in: u64,
+        rng: &mut R,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<u64, Error> {
+        if prune_horizon > self.num_tx_outs(db_transaction)? {
+            return Err(Error::TxOutIndexOutOfBounds(prune_horizon));
+        }
+        let prev_prune_horizon = self.prune_horizon(db_transaction)?;
+        if prune_horizon <= prev_prune_horizon {
+            return Ok(0);
+        }
+
+        let mut num_pruned = 0;
+        for index in prev_prune_horizon..prune_horizon {
+            if retain_one_in == 0 || rng.gen_range(0..retain_one_in) != 0 {
+                db_transaction.del(self.tx_out_by_index, &u64_to_key_bytes(index), None)?;
+                num_pruned += 1;
+            }
+        }
+
+        let total_num_pruned = self.num_pruned_tx_outs(db_transaction)? + num_pruned;
+        db_transaction.put(
+            self.counts,
+            &PRUNE_HORIZON_KEY,
+            &u64_to_key_bytes(prune_horizon),
+            WriteFlags::empty(),
+        )?;
+        db_transaction.put(
+            self.counts,
+            &NUM_PRUNED_TX_OUTS_KEY,
+            &u64_to_key_bytes(total_num_pruned),
+            WriteFlags::empty(),
+        )?;
+
+        Ok(num_pruned)
+    }
+
+    /// Get the index below which TxOuts may have been pruned. This is zero if
+    /// the store has never been pruned.
+    pub fn prune_horizon<T: Transaction>(&self, db_transaction: &T) -> Result<u64, Error> {
+        self.get_optional_count(PRUNE_HORIZON_KEY, db_transaction)
+    }
+
+    /// Get the number of TxOuts whose bodies have been pruned.
+    pub fn num_pruned_tx_outs<T: Transaction>(&self, db_transaction: &T) -> Result<u64, Error> {
+        self.get_optional_count(NUM_PRUNED_TX_OUTS_KEY, db_transaction)
+    }
+
     /// Get the total number of TxOuts in the ledger.
     pub fn num_tx_outs<T: Transaction>(&self, db_transaction: &T) -> Result<u64, Error> {
         Ok(key_bytes_to_u64(
@@ -174,16 +349,39 @@ impl TxOutStore {
     }
 
     /// Gets a TxOut by its index in the ledger.
+    ///
+    /// Returns `Error::TxOutPruned` if the TxOut is in the ledger but its body
+    /// has been pruned.
     pub fn get_tx_out_by_index<T: Transaction>(
         &self,
         index: u64,
         db_transaction: &T,
     ) -> Result<TxOut, Error> {
-        let tx_out_bytes = db_transaction.get(self.tx_out_by_index, &u64_to_key_bytes(index))?;
+        let tx_out_bytes = match db_transaction.get(self.tx_out_by_index, &u64_to_key_bytes(index))
+        {
+            Ok(bytes) => bytes,
+            Err(lmdb::Error::NotFound) if index < self.prune_horizon(db_transaction)? => {
+                return Err(Error::TxOutPruned(index));
+            }
+            Err(err) => return Err(err.into()),
+        };
         let tx_out: TxOut = decode(tx_out_bytes)?;
         Ok(tx_out)
     }
 
+    /// Reads a count that is only present once the store has been pruned.
+    fn get_optional_count<T: Transaction>(
+        &self,
+        key: &str,
+        db_transaction: &T,
+    ) -> Result<u64, Error> {
+        match db_transaction.get(self.counts, &key) {
+            Ok(bytes) => Ok(key_bytes_to_u64(bytes)),
+            Err(lmdb::Error::NotFound) => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     /// Get the root hash of the Merkle Tree
     pub fn get_root_merkle_hash<T: Transaction>(
         &self,
@@ -251,6 +449,37 @@ impl TxOutStore {
         &self,
         index: u64,
         db_transaction: &mut RwTransaction,
+    ) -> Result<(), Error> {
+        let tx_out = self.get_tx_out_by_index(index, db_transaction)?;
+        self.write_merkle_hashes(index, &hash_leaf(&tx_out), db_transaction)
+    }
+
+    /// Update Merkle Hashes to include a TxOut that was already part of the
+    /// tree, when truncating or rebuilding it. The leaf hash is recomputed from
+    /// the TxOut, unless the TxOut has been pruned, in which case the leaf hash
+    /// that is still stored for it is kept.
+    fn restore_merkle_hashes(
+        &self,
+        index: u64,
+        db_transaction: &mut RwTransaction,
+    ) -> Result<(), Error> {
+        let leaf_hash = match self.get_tx_out_by_index(index, db_transaction) {
+            Ok(tx_out) => hash_leaf(&tx_out),
+            Err(Error::TxOutPruned(_)) => {
+                self.get_merkle_hash(&Range::new(index, index)?, db_transaction)?
+            }
+            Err(err) => return Err(err),
+        };
+        self.write_merkle_hashes(index, &leaf_hash, db_transaction)
+    }
+
+    /// Writes the leaf hash of the TxOut with the given index, and recomputes
+    /// the hashes of the ranges that contain it.
+    fn write_merkle_hashes(
+        &self,
+        index: u64,
+        leaf_hash: &[u8; 32],
+        db_transaction: &mut RwTransaction,
     ) -> Result<(), Error> {
         let num_tx_outs = self.num_tx_outs(db_transaction)?;
         if index >= num_tx_outs {
@@ -262,10 +491,8 @@ impl TxOutStore {
         for (low, high) in ranges {
             if low == high {
                 // Leaf.
-                let tx_out = self.get_tx_out_by_index(index, db_transaction)?;
-                let hash = hash_leaf(&tx_out);
                 let range = Range::new(low, low)?;
-                self.write_merkle_hash(&range, &hash, db_transaction)?;
+                self.write_merkle_hash(&range, leaf_hash, db_transaction)?;
             } else {
                 // Internal node.
                 let mid: u64 = (low + high) / 2;
@@ -1346,4 +1573,233 @@ pub mod tx_out_store_tests {
             Err(e) => panic!("Unexpected error {:?}", e),
         }
     }
+
+    #[test]
+    // `truncate` should leave the store as if the removed TxOuts were never pushed.
+    fn test_truncate() {
+        let tx_outs = get_tx_outs(27);
+
+        let (full_tx_out_store, full_env) = init_tx_out_store();
+        {
+            let mut rw_transaction = full_env.begin_rw_txn().unwrap();
+            for tx_out in &tx_outs {
+                full_tx_out_store.push(tx_out, &mut rw_transaction).unwrap();
+            }
+            rw_transaction.commit().unwrap();
+        }
+
+        for num_tx_outs in [0, 1, 8, 13, 26, 27] {
+            let (tx_out_store, env) = init_tx_out_store();
+            let (expected_tx_out_store, expected_env) = init_tx_out_store();
+            {
+                let mut rw_transaction = env.begin_rw_txn().unwrap();
+                for tx_out in &tx_outs {
+                    tx_out_store.push(tx_out, &mut rw_transaction).unwrap();
+                }
+                tx_out_store
+                    .truncate(num_tx_outs, &mut rw_transaction)
+                    .unwrap();
+                rw_transaction.commit().unwrap();
+
+                let mut rw_transaction = expected_env.begin_rw_txn().unwrap();
+                for tx_out in &tx_outs[..num_tx_outs as usize] {
+                    expected_tx_out_store
+                        .push(tx_out, &mut rw_transaction)
+                        .unwrap();
+                }
+                rw_transaction.commit().unwrap();
+            }
+
+            let ro_transaction = env.begin_ro_txn().unwrap();
+            let expected_ro_transaction = expected_env.begin_ro_txn().unwrap();
+            assert_eq!(
+                num_tx_outs,
+                tx_out_store.num_tx_outs(&ro_transaction).unwrap()
+            );
+            assert_eq!(
+                expected_tx_out_store
+                    .get_root_merkle_hash(&expected_ro_transaction)
+                    .unwrap(),
+                tx_out_store.get_root_merkle_hash(&ro_transaction).unwrap()
+            );
+            for index in 0..num_tx_outs {
+                assert_eq!(
+                    expected_tx_out_store
+                        .get_merkle_proof_of_membership(index, &expected_ro_transaction)
+                        .unwrap(),
+                    tx_out_store
+                        .get_merkle_proof_of_membership(index, &ro_transaction)
+                        .unwrap()
+                );
+            }
+            for tx_out in &tx_outs[num_tx_outs as usize..] {
+                assert_eq!(
+                    Err(Error::NotFound),
+                    tx_out_store.get_tx_out_index_by_hash(&tx_out.hash(), &ro_transaction)
+                );
+                assert_eq!(
+                    Err(Error::NotFound),
+                    tx_out_store
+                        .get_tx_out_index_by_public_key(&tx_out.public_key, &ro_transaction)
+                );
+            }
+            drop(ro_transaction);
+
+            // The truncated store can grow again.
+            let mut rw_transaction = env.begin_rw_txn().unwrap();
+            for tx_out in &tx_outs[num_tx_outs as usize..] {
+                tx_out_store.push(tx_out, &mut rw_transaction).unwrap();
+            }
+            rw_transaction.commit().unwrap();
+            let ro_transaction = env.begin_ro_txn().unwrap();
+            assert_eq!(27, tx_out_store.num_tx_outs(&ro_transaction).unwrap());
+            assert_eq!(
+                full_tx_out_store
+                    .get_root_merkle_hash(&full_env.begin_ro_txn().unwrap())
+                    .unwrap(),
+                tx_out_store.get_root_merkle_hash(&ro_transaction).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    // Merkle hashes can be recomputed from the stored TxOuts.
+    fn test_reset_merkle_tree() {
+        let tx_outs = get_tx_outs(27);
+        let (tx_out_store, env) = init_tx_out_store();
+        let mut rw_transaction = env.begin_rw_txn().unwrap();
+        for tx_out in &tx_outs {
+            tx_out_store.push(tx_out, &mut rw_transaction).unwrap();
+        }
+        let root_hash = tx_out_store.get_root_merkle_hash(&rw_transaction).unwrap();
+        let proof = tx_out_store
+            .get_merkle_proof_of_membership(13, &rw_transaction)
+            .unwrap();
+
+        assert_eq!(
+            27,
+            tx_out_store.reset_merkle_tree(&mut rw_transaction).unwrap()
+        );
+        assert_eq!(0, tx_out_store.num_tx_outs(&rw_transaction).unwrap());
+        assert_eq!(
+            Err(Error::TxOutIndexOutOfBounds(0)),
+            tx_out_store.get_merkle_proof_of_membership(0, &rw_transaction)
+        );
+
+        for index in 0..27 {
+            assert_eq!(
+                index,
+                tx_out_store.push_stored(&mut rw_transaction).unwrap()
+            );
+        }
+        assert_eq!(
+            root_hash,
+            tx_out_store.get_root_merkle_hash(&rw_transaction).unwrap()
+        );
+        assert_eq!(
+            proof,
+            tx_out_store
+                .get_merkle_proof_of_membership(13, &rw_transaction)
+                .unwrap()
+        );
+
+        // There are no more stored TxOuts to add.
+        assert!(tx_out_store.push_stored(&mut rw_transaction).is_err());
+    }
+
+    #[test]
+    // `prune` should remove TxOut bodies without affecting proofs of membership.
+    fn test_prune() {
+        let tx_outs = get_tx_outs(50);
+        let mut rng: StdRng = SeedableRng::from_seed([2u8; 32]);
+
+        let (full_tx_out_store, full_env) = init_tx_out_store();
+        let (tx_out_store, env) = init_tx_out_store();
+        {
+            let mut full_rw_transaction = full_env.begin_rw_txn().unwrap();
+            let mut rw_transaction = env.begin_rw_txn().unwrap();
+            for tx_out in &tx_outs {
+                full_tx_out_store
+                    .push(tx_out, &mut full_rw_transaction)
+                    .unwrap();
+                tx_out_store.push(tx_out, &mut rw_transaction).unwrap();
+            }
+            full_rw_transaction.commit().unwrap();
+
+            // Pruning past the end of the store is not allowed.
+            assert_eq!(
+                Err(Error::TxOutIndexOutOfBounds(51)),
+                tx_out_store.prune(51, 4, &mut rng, &mut rw_transaction)
+            );
+
+            let num_pruned = tx_out_store
+                .prune(20, 4, &mut rng, &mut rw_transaction)
+                .unwrap()
+                + tx_out_store
+                    .prune(40, 4, &mut rng, &mut rw_transaction)
+                    .unwrap();
+            assert!(num_pruned > 0 && num_pruned < 40);
+
+            // Lowering the horizon does nothing.
+            assert_eq!(
+                Ok(0),
+                tx_out_store.prune(10, 4, &mut rng, &mut rw_transaction)
+            );
+            rw_transaction.commit().unwrap();
+        }
+
+        let full_ro_transaction = full_env.begin_ro_txn().unwrap();
+        let ro_transaction = env.begin_ro_txn().unwrap();
+        assert_eq!(40, tx_out_store.prune_horizon(&ro_transaction).unwrap());
+        assert_eq!(50, tx_out_store.num_tx_outs(&ro_transaction).unwrap());
+
+        let mut num_missing = 0;
+        for (index, tx_out) in tx_outs.iter().enumerate() {
+            let index = index as u64;
+            match tx_out_store.get_tx_out_by_index(index, &ro_transaction) {
+                Ok(retained) => assert_eq!(*tx_out, retained),
+                Err(Error::TxOutPruned(pruned_index)) if index < 40 => {
+                    assert_eq!(index, pruned_index);
+                    num_missing += 1;
+                }
+                Err(e) => panic!("Unexpected Error {:?}", e),
+            }
+            assert_eq!(
+                index,
+                tx_out_store
+                    .get_tx_out_index_by_hash(&tx_out.hash(), &ro_transaction)
+                    .unwrap()
+            );
+            assert_eq!(
+                full_tx_out_store
+                    .get_merkle_proof_of_membership(index, &full_ro_transaction)
+                    .unwrap(),
+                tx_out_store
+                    .get_merkle_proof_of_membership(index, &ro_transaction)
+                    .unwrap()
+            );
+        }
+        assert_eq!(
+            num_missing,
+            tx_out_store.num_pruned_tx_outs(&ro_transaction).unwrap()
+        );
+        drop(ro_transaction);
+
+        // Truncating into the pruned region fails, truncating above it succeeds.
+        let mut rw_transaction = env.begin_rw_txn().unwrap();
+        assert_eq!(
+            Err(Error::TxOutPruned(39)),
+            tx_out_store.truncate(39, &mut rw_transaction)
+        );
+        tx_out_store.truncate(40, &mut rw_transaction).unwrap();
+        for tx_out in &tx_outs[40..] {
+            tx_out_store.push(tx_out, &mut rw_transaction).unwrap();
+        }
+        assert_eq!(
+            full_tx_out_store
+                .get_root_merkle_hash(&full_ro_transaction)
+                .unwrap(),
+            tx_out_store.get_root_merkle_hash(&rw_transaction).unwrap()
+        );
+    }
 }
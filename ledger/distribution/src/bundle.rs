@@ -0,0 +1,159 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Zstd-compressed archive bundles.
+//!
+//! A bundle is an `ArchiveBlocks` object holding `bundle_size` consecutive
+//! blocks, compressed with zstd. Bundles are indexed by a manifest stored at
+//! `ARCHIVE_BUNDLE_MANIFEST_PATH`, which lists the block range, path, hash and
+//! sizes of every bundle. Readers that do not find a manifest, or that fail to
+//! verify a bundle, fall back to the per-block and merged-block files, which
+//! keep being written alongside the bundles.
+//!
+//! The writer can keep a local copy of the manifest so that it can be extended
+//! when ledger distribution restarts. Bundles for blocks that were distributed
+//! without them, for example before bundles were enabled, are backfilled from
+//! the end of the manifest.
+
+use crate::Error;
+use mc_api::{archive_bundle_path, blockchain, ARCHIVE_BUNDLE_FORMAT_VERSION};
+use mc_blockchain_types::{BlockData, BlockIndex};
+use mc_common::logger::{log, Logger};
+use mc_crypto_hashes::{Blake2b256, Digest};
+use protobuf::Message;
+use std::{fs, path::PathBuf};
+
+/// A compressed bundle, ready to be written.
+pub struct ArchiveBundle {
+    /// Path of the bundle, relative to the root of the archive.
+    pub path: PathBuf,
+
+    /// The compressed bundle.
+    pub bytes: Vec<u8>,
+
+    /// The serialized manifest, including this bundle.
+    pub manifest_bytes: Vec<u8>,
+}
+
+/// Creates archive bundles and maintains the manifest that indexes them.
+pub struct BundleWriter {
+    bundle_size: u64,
+    compression_level: i32,
+    manifest: blockchain::ArchiveBundleManifest,
+    manifest_copy_path: Option<PathBuf>,
+}
+
+impl BundleWriter {
+    /// Create a bundle writer.
+    ///
+    /// # Arguments
+    /// * `bundle_size` - Number of blocks in each bundle.
+    /// * `compression_level` - Zstd compression level.
+    /// * `manifest_copy_path` - Where the local copy of the manifest is kept,
+    ///   if any.
+    /// * `reset` - Start with an empty manifest, ignoring the local copy.
+    /// * `logger` - Logger.
+    pub fn new(
+        bundle_size: u64,
+        compression_level: i32,
+        manifest_copy_path: Option<PathBuf>,
+        reset: bool,
+        logger: &Logger,
+    ) -> Result<Self, Error> {
+        assert!(bundle_size > 0, "bundle size must be positive");
+
+        let mut manifest = blockchain::ArchiveBundleManifest::new();
+        let existing_copy_path = manifest_copy_path
+            .as_ref()
+            .filter(|path| !reset && path.exists());
+        if let Some(manifest_copy_path) = existing_copy_path {
+            let bytes = fs::read(manifest_copy_path)
+                .map_err(|err| Error::Io(format!("{:?}", manifest_copy_path), err))?;
+            let existing =
+                blockchain::ArchiveBundleManifest::parse_from_bytes(&bytes).map_err(|err| {
+                    Error::InvalidState(format!("{:?}", manifest_copy_path), err.to_string())
+                })?;
+            if existing.get_bundle_size() == bundle_size
+                && existing.get_format_version() == ARCHIVE_BUNDLE_FORMAT_VERSION
+            {
+                manifest = existing;
+            } else {
+                log::warn!(
+                    logger,
+                    "Ignoring manifest {:?} written with bundle size {}, starting a new one",
+                    manifest_copy_path,
+                    existing.get_bundle_size()
+                );
+            }
+        }
+        manifest.set_format_version(ARCHIVE_BUNDLE_FORMAT_VERSION);
+        manifest.set_compression("zstd".to_string());
+        manifest.set_bundle_size(bundle_size);
+
+        Ok(Self {
+            bundle_size,
+            compression_level,
+            manifest,
+            manifest_copy_path,
+        })
+    }
+
+    /// Number of blocks in each bundle.
+    pub fn bundle_size(&self) -> u64 {
+        self.bundle_size
+    }
+
+    /// The first block of the bundle that follows the last one in the
+    /// manifest.
+    pub fn next_bundle_first_block(&self) -> BlockIndex {
+        self.manifest.get_bundles().last().map_or(0, |entry| {
+            entry.get_first_block_index() + entry.get_num_blocks()
+        })
+    }
+
+    /// Compress the given blocks into a bundle and add it to the manifest,
+    /// replacing any existing entry for the same blocks.
+    pub fn create_bundle(&mut self, blocks_data: &[BlockData]) -> Result<ArchiveBundle, Error> {
+        assert_eq!(blocks_data.len() as u64, self.bundle_size);
+        let first_block_index = blocks_data[0].block().index;
+
+        let uncompressed = blockchain::ArchiveBlocks::from(blocks_data)
+            .write_to_bytes()
+            .expect("failed to serialize ArchiveBlocks");
+        let bytes = zstd::encode_all(&uncompressed[..], self.compression_level)
+            .expect("failed compressing bundle");
+        let path = archive_bundle_path(self.bundle_size, first_block_index);
+
+        let mut entry = blockchain::ArchiveBundleEntry::new();
+        entry.set_first_block_index(first_block_index);
+        entry.set_num_blocks(blocks_data.len() as u64);
+        entry.set_path(path.to_str().expect("invalid bundle path").to_string());
+        entry.set_hash(Blake2b256::digest(&bytes).to_vec());
+        entry.set_compressed_size(bytes.len() as u64);
+        entry.set_uncompressed_size(uncompressed.len() as u64);
+
+        // Bundles are ordered, and a bundle that is written again after a restart
+        // replaces itself and everything after it.
+        let bundles = self.manifest.mut_bundles();
+        let num_kept = bundles
+            .iter()
+            .take_while(|existing| existing.get_first_block_index() < first_block_index)
+            .count();
+        bundles.truncate(num_kept);
+        bundles.push(entry);
+
+        let manifest_bytes = self
+            .manifest
+            .write_to_bytes()
+            .expect("failed to serialize ArchiveBundleManifest");
+        if let Some(manifest_copy_path) = self.manifest_copy_path.as_ref() {
+            fs::write(manifest_copy_path, &manifest_bytes)
+                .map_err(|err| Error::Io(format!("{:?}", manifest_copy_path), err))?;
+        }
+
+        Ok(ArchiveBundle {
+            path,
+            bytes,
+            manifest_bytes,
+        })
+    }
+}
@@ -0,0 +1,534 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Copies the blocks of a ledger to an object store.
+
+use crate::{
+    bundle::BundleWriter,
+    object_store::ObjectStore,
+    state::{bundle_manifest_copy_path, DistributionState},
+    Error,
+};
+use mc_api::{
+    block_num_to_s3block_path, blockchain, merged_block_num_to_s3block_path,
+    ARCHIVE_BUNDLE_MANIFEST_PATH,
+};
+use mc_blockchain_types::{BlockData, BlockIndex};
+use mc_common::logger::{log, Logger};
+use mc_crypto_hashes::{Blake2b256, Digest};
+use mc_ledger_db::Ledger;
+use mc_util_telemetry::{mark_span_as_active, start_block_span, tracer, Tracer};
+use protobuf::Message;
+use retry::{delay, retry};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The longest delay between two attempts at writing an object.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Ledger distribution configuration.
+#[derive(Clone, Debug)]
+pub struct DistributorConfig {
+    /// Merged blocks bucket sizes. Sizes of 0 and 1 are ignored.
+    pub merge_buckets: Vec<u64>,
+
+    /// Number of blocks in each archive bundle, or 0 to disable bundles.
+    pub archive_bundle_size: u64,
+
+    /// Zstd compression level of archive bundles.
+    pub archive_bundle_compression_level: i32,
+
+    /// The maximal number of attempts at writing an object, or `None` to keep
+    /// retrying until the write succeeds.
+    pub max_write_attempts: Option<usize>,
+
+    /// Read every object back after writing it, and retry the write if its
+    /// checksum does not match.
+    pub verify_writes: bool,
+}
+
+impl Default for DistributorConfig {
+    fn default() -> Self {
+        Self {
+            merge_buckets: vec![100, 1000, 10000],
+            archive_bundle_size: 0,
+            archive_bundle_compression_level: 19,
+            max_write_attempts: None,
+            verify_writes: false,
+        }
+    }
+}
+
+/// Writes blocks, merged blocks and archive bundles from a ledger to an
+/// object store, in the layout read by `ReqwestTransactionsFetcher`.
+///
+/// When given a state file, the distributor records its progress there after
+/// every block, together with its copy of the archive bundle manifest, so
+/// that a new distributor can resume where it stopped. Objects are written
+/// idempotently, so a block that was partially written before a failure is
+/// simply written again.
+pub struct LedgerDistributor<L: Ledger, S: ObjectStore> {
+    ledger: L,
+    store: S,
+    config: DistributorConfig,
+    next_block: BlockIndex,
+    state_file: Option<PathBuf>,
+    bundle_writer: Option<BundleWriter>,
+    logger: Logger,
+}
+
+impl<L: Ledger, S: ObjectStore> LedgerDistributor<L, S> {
+    /// Create a distributor.
+    ///
+    /// # Arguments
+    /// * `ledger` - The ledger to distribute.
+    /// * `store` - The object store to write to.
+    /// * `config` - Distribution configuration.
+    /// * `first_block` - The first block to distribute, see
+    ///   [DistributionState::load] for resuming from a state file.
+    /// * `state_file` - Where progress is recorded, if anywhere.
+    /// * `logger` - Logger.
+    pub fn new(
+        ledger: L,
+        store: S,
+        config: DistributorConfig,
+        first_block: BlockIndex,
+        state_file: Option<PathBuf>,
+        logger: Logger,
+    ) -> Result<Self, Error> {
+        let bundle_writer = if config.archive_bundle_size > 0 {
+            Some(BundleWriter::new(
+                config.archive_bundle_size,
+                config.archive_bundle_compression_level,
+                state_file.as_deref().map(bundle_manifest_copy_path),
+                first_block == 0,
+                &logger,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            ledger,
+            store,
+            config,
+            next_block: first_block,
+            state_file,
+            bundle_writer,
+            logger,
+        })
+    }
+
+    /// The next block to distribute.
+    pub fn next_block(&self) -> BlockIndex {
+        self.next_block
+    }
+
+    /// The object store this distributor writes to.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Distribute every block currently in the ledger that has not been
+    /// distributed yet. Returns the number of blocks distributed.
+    ///
+    /// On error, the block that failed is distributed again on the next call.
+    pub fn distribute_available_blocks(&mut self) -> Result<u64, Error> {
+        let num_blocks = self.ledger.num_blocks()?;
+        let first_block = self.next_block;
+        let tracer = tracer!();
+
+        self.backfill_bundles()?;
+
+        while self.next_block < num_blocks {
+            let block_index = self.next_block;
+            log::trace!(self.logger, "Handling block #{}", block_index);
+
+            let span = start_block_span(&tracer, "distribute-block", block_index);
+            let _active_span = mark_span_as_active(span);
+
+            let block_data = self.ledger.get_block_data(block_index)?;
+            tracer.in_span("write_single_block", |_cx| {
+                self.write_single_block(&block_data)
+            })?;
+
+            for bucket_size in self.config.merge_buckets.iter() {
+                // Zero bucket size is invalid, bucket size of 1 is a single block.
+                if *bucket_size <= 1 {
+                    continue;
+                }
+
+                // Check if we just completed a bucket.
+                if (block_index + 1) % bucket_size != 0 {
+                    continue;
+                }
+
+                let first_block_index = block_index + 1 - *bucket_size;
+                log::debug!(
+                    self.logger,
+                    "Preparing to write merged block [{}-{}]",
+                    first_block_index,
+                    block_index
+                );
+
+                let blocks_data = self.get_blocks_data(first_block_index, block_index)?;
+                tracer.in_span("write_multiple_blocks", |_cx| {
+                    self.write_multiple_blocks(&blocks_data)
+                })?;
+            }
+
+            let bundle_size = self.config.archive_bundle_size;
+            if bundle_size > 0 && (block_index + 1) % bundle_size == 0 {
+                let first_block_index = block_index + 1 - bundle_size;
+                tracer.in_span("write_archive_bundle", |_cx| {
+                    self.write_bundle(first_block_index)
+                })?;
+            }
+
+            self.next_block += 1;
+            if let Some(state_file) = self.state_file.as_ref() {
+                DistributionState {
+                    next_block: self.next_block,
+                }
+                .save(state_file)?;
+            }
+        }
+
+        Ok(self.next_block - first_block)
+    }
+
+    /// Write the bundles that are missing from the manifest for blocks that
+    /// were already distributed, such as when bundles are enabled for an
+    /// existing archive or the local copy of the manifest was lost.
+    fn backfill_bundles(&mut self) -> Result<(), Error> {
+        let mut first_block_index = match self.bundle_writer.as_ref() {
+            Some(bundle_writer) => bundle_writer.next_bundle_first_block(),
+            None => return Ok(()),
+        };
+        let bundle_size = self.config.archive_bundle_size;
+        while first_block_index + bundle_size <= self.next_block {
+            log::info!(
+                self.logger,
+                "Backfilling bundle [{}-{}]",
+                first_block_index,
+                first_block_index + bundle_size - 1
+            );
+            self.write_bundle(first_block_index)?;
+            first_block_index += bundle_size;
+        }
+        Ok(())
+    }
+
+    /// Write the bundle starting at `first_block_index`, and the manifest.
+    fn write_bundle(&mut self, first_block_index: BlockIndex) -> Result<(), Error> {
+        let last_block_index = first_block_index + self.config.archive_bundle_size - 1;
+        log::debug!(
+            self.logger,
+            "Preparing to write bundle [{}-{}]",
+            first_block_index,
+            last_block_index
+        );
+
+        let blocks_data = self.get_blocks_data(first_block_index, last_block_index)?;
+        let bundle = self
+            .bundle_writer
+            .as_mut()
+            .expect("bundles are enabled")
+            .create_bundle(&blocks_data)?;
+
+        log::info!(self.logger, "Writing bundle {:?}", bundle.path);
+        self.write_object(&bundle.path, &bundle.bytes)?;
+        self.write_object(
+            Path::new(ARCHIVE_BUNDLE_MANIFEST_PATH),
+            &bundle.manifest_bytes,
+        )
+    }
+
+    fn write_single_block(&self, block_data: &BlockData) -> Result<(), Error> {
+        log::info!(self.logger, "Handling block {}", block_data.block().index);
+
+        let bytes = blockchain::ArchiveBlock::from(block_data)
+            .write_to_bytes()
+            .expect("failed to serialize ArchiveBlock");
+        self.write_object(&block_num_to_s3block_path(block_data.block().index), &bytes)
+    }
+
+    fn write_multiple_blocks(&self, blocks_data: &[BlockData]) -> Result<(), Error> {
+        assert!(blocks_data.len() >= 2);
+
+        let first_block_index = blocks_data[0].block().index;
+        let last_block_index = blocks_data.last().unwrap().block().index;
+        assert_eq!(
+            last_block_index,
+            first_block_index + blocks_data.len() as u64 - 1
+        );
+
+        log::info!(
+            self.logger,
+            "Handling blocks {}-{}",
+            first_block_index,
+            last_block_index,
+        );
+
+        let bytes = blockchain::ArchiveBlocks::from(blocks_data)
+            .write_to_bytes()
+            .expect("failed to serialize ArchiveBlocks");
+        self.write_object(
+            &merged_block_num_to_s3block_path(blocks_data.len() as u64, first_block_index),
+            &bytes,
+        )
+    }
+
+    /// Write an object, retrying with exponential backoff until it succeeds
+    /// or `max_write_attempts` is exhausted.
+    fn write_object(&self, path: &Path, bytes: &[u8]) -> Result<(), Error> {
+        let key = path.to_str().expect("invalid object path");
+        let max_retries = self
+            .config
+            .max_write_attempts
+            .map_or(usize::MAX, |attempts| attempts.saturating_sub(1));
+
+        retry(
+            delay::Exponential::from_millis_with_base_factor(10)
+                .map(|delay| delay.min(MAX_RETRY_DELAY))
+                .map(delay::jitter)
+                .take(max_retries),
+            || {
+                self.try_write_object(key, bytes).map_err(|err| {
+                    log::warn!(self.logger, "Failed writing {}: {}, retrying...", key, err);
+                    err
+                })
+            },
+        )
+        .map_err(|err| err.error)
+    }
+
+    fn try_write_object(&self, key: &str, bytes: &[u8]) -> Result<(), Error> {
+        self.store.put(key, bytes)?;
+
+        if self.config.verify_writes {
+            let stored = self.store.get(key)?;
+            if Blake2b256::digest(&stored) != Blake2b256::digest(bytes) {
+                return Err(Error::ChecksumMismatch(key.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the data of blocks `first_block_index..=last_block_index`.
+    fn get_blocks_data(
+        &self,
+        first_block_index: BlockIndex,
+        last_block_index: BlockIndex,
+    ) -> Result<Vec<BlockData>, Error> {
+        (first_block_index..=last_block_index)
+            .map(|block_index| Ok(self.ledger.get_block_data(block_index)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_store::InMemoryObjectStore;
+    use mc_account_keys::AccountKey;
+    use mc_blockchain_types::BlockVersion;
+    use mc_common::logger::{test_with_logger, Logger};
+    use mc_ledger_db::{
+        test_utils::{create_ledger, initialize_ledger},
+        LedgerDB,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use tempdir::TempDir;
+
+    fn create_test_ledger(num_blocks: u64) -> LedgerDB {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let mut ledger = create_ledger();
+        let account_key = AccountKey::random(&mut rng);
+        initialize_ledger(
+            BlockVersion::MAX,
+            &mut ledger,
+            num_blocks,
+            &account_key,
+            &mut rng,
+        );
+        ledger
+    }
+
+    fn block_key(block_index: BlockIndex) -> String {
+        block_num_to_s3block_path(block_index)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test_with_logger]
+    fn distributes_blocks_merged_blocks_and_bundles(logger: Logger) {
+        let ledger = create_test_ledger(25);
+        let store = InMemoryObjectStore::new();
+        let state_dir = TempDir::new("distribution_state").unwrap();
+        let state_file = state_dir.path().join("state");
+
+        let config = DistributorConfig {
+            merge_buckets: vec![0, 1, 10],
+            archive_bundle_size: 5,
+            ..Default::default()
+        };
+        let mut distributor = LedgerDistributor::new(
+            ledger.clone(),
+            store.clone(),
+            config,
+            0,
+            Some(state_file.clone()),
+            logger,
+        )
+        .unwrap();
+
+        assert_eq!(distributor.distribute_available_blocks().unwrap(), 25);
+        assert_eq!(distributor.distribute_available_blocks().unwrap(), 0);
+        assert_eq!(distributor.next_block(), 25);
+
+        for block_index in 0..25 {
+            let bytes = store.get(&block_key(block_index)).unwrap();
+            let archive_block = blockchain::ArchiveBlock::parse_from_bytes(&bytes).unwrap();
+            assert_eq!(
+                BlockData::try_from(&archive_block).unwrap(),
+                ledger.get_block_data(block_index).unwrap()
+            );
+        }
+
+        for first_block_index in [0, 10] {
+            let path = merged_block_num_to_s3block_path(10, first_block_index);
+            assert!(store.get(path.to_str().unwrap()).is_ok());
+        }
+        let path = merged_block_num_to_s3block_path(10, 20);
+        assert!(matches!(
+            store.get(path.to_str().unwrap()),
+            Err(Error::NotFound(_))
+        ));
+
+        let manifest = blockchain::ArchiveBundleManifest::parse_from_bytes(
+            &store.get(ARCHIVE_BUNDLE_MANIFEST_PATH).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(manifest.get_bundles().len(), 5);
+        for entry in manifest.get_bundles() {
+            let bytes = store.get(entry.get_path()).unwrap();
+            assert_eq!(entry.get_hash(), &Blake2b256::digest(&bytes)[..]);
+        }
+
+        assert_eq!(
+            DistributionState::load(&state_file).unwrap(),
+            Some(DistributionState { next_block: 25 })
+        );
+    }
+
+    #[test_with_logger]
+    fn resumes_from_state_file(logger: Logger) {
+        let ledger = create_test_ledger(20);
+        let state_dir = TempDir::new("distribution_state").unwrap();
+        let state_file = state_dir.path().join("state");
+        assert_eq!(DistributionState::load(&state_file).unwrap(), None);
+
+        DistributionState { next_block: 15 }
+            .save(&state_file)
+            .unwrap();
+        let state = DistributionState::load(&state_file).unwrap().unwrap();
+
+        let store = InMemoryObjectStore::new();
+        let mut distributor = LedgerDistributor::new(
+            ledger,
+            store.clone(),
+            DistributorConfig::default(),
+            state.next_block,
+            Some(state_file.clone()),
+            logger,
+        )
+        .unwrap();
+        assert_eq!(distributor.distribute_available_blocks().unwrap(), 5);
+
+        let expected_keys = (15..20).map(block_key).collect::<Vec<_>>();
+        assert_eq!(store.keys(), expected_keys);
+        assert_eq!(
+            DistributionState::load(&state_file).unwrap(),
+            Some(DistributionState { next_block: 20 })
+        );
+    }
+
+    #[test_with_logger]
+    fn backfills_missing_bundles(logger: Logger) {
+        let ledger = create_test_ledger(20);
+        let store = InMemoryObjectStore::new();
+        let state_dir = TempDir::new("distribution_state").unwrap();
+        let state_file = state_dir.path().join("state");
+
+        // Blocks up to 17 were distributed without bundles.
+        let config = DistributorConfig {
+            archive_bundle_size: 5,
+            ..Default::default()
+        };
+        let mut distributor =
+            LedgerDistributor::new(ledger, store.clone(), config, 17, Some(state_file), logger)
+                .unwrap();
+        assert_eq!(distributor.distribute_available_blocks().unwrap(), 3);
+
+        let manifest = blockchain::ArchiveBundleManifest::parse_from_bytes(
+            &store.get(ARCHIVE_BUNDLE_MANIFEST_PATH).unwrap(),
+        )
+        .unwrap();
+        let first_block_indexes = manifest
+            .get_bundles()
+            .iter()
+            .map(|entry| entry.get_first_block_index())
+            .collect::<Vec<_>>();
+        assert_eq!(first_block_indexes, vec![0, 5, 10, 15]);
+        for entry in manifest.get_bundles() {
+            let bytes = store.get(entry.get_path()).unwrap();
+            assert_eq!(entry.get_hash(), &Blake2b256::digest(&bytes)[..]);
+        }
+    }
+
+    #[test_with_logger]
+    fn retries_failed_and_corrupted_writes(logger: Logger) {
+        let ledger = create_test_ledger(3);
+        let store = InMemoryObjectStore::new();
+        let config = DistributorConfig {
+            max_write_attempts: Some(4),
+            verify_writes: true,
+            ..Default::default()
+        };
+        let mut distributor =
+            LedgerDistributor::new(ledger, store.clone(), config, 0, None, logger).unwrap();
+
+        store.fail_next_puts(2);
+        store.corrupt_next_puts(1);
+        assert_eq!(distributor.distribute_available_blocks().unwrap(), 3);
+
+        let bytes = store.get(&block_key(0)).unwrap();
+        assert!(blockchain::ArchiveBlock::parse_from_bytes(&bytes).is_ok());
+    }
+
+    #[test_with_logger]
+    fn gives_up_after_max_write_attempts(logger: Logger) {
+        let ledger = create_test_ledger(3);
+        let store = InMemoryObjectStore::new();
+        let config = DistributorConfig {
+            max_write_attempts: Some(2),
+            verify_writes: true,
+            ..Default::default()
+        };
+        let mut distributor =
+            LedgerDistributor::new(ledger, store.clone(), config, 0, None, logger).unwrap();
+
+        store.corrupt_next_puts(2);
+        assert!(matches!(
+            distributor.distribute_available_blocks(),
+            Err(Error::ChecksumMismatch(_))
+        ));
+        assert_eq!(distributor.next_block(), 0);
+
+        // The failed block is written again on the next call.
+        assert_eq!(distributor.distribute_available_blocks().unwrap(), 3);
+    }
+}
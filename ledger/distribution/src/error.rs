@@ -0,0 +1,37 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Ledger distribution errors.
+
+use displaydoc::Display;
+use mc_ledger_db::Error as LedgerDbError;
+use std::io::Error as IoError;
+
+/// Ledger distribution errors.
+#[derive(Debug, Display)]
+pub enum Error {
+    /// Object not found: {0}
+    NotFound(String),
+
+    /// IO error on {0}: {1}
+    Io(String, IoError),
+
+    /// Object store request for {0} failed: {1}
+    Request(String, String),
+
+    /// Checksum mismatch reading back {0}
+    ChecksumMismatch(String),
+
+    /// Ledger db: {0}
+    LedgerDb(LedgerDbError),
+
+    /// Invalid state file {0}: {1}
+    InvalidState(String, String),
+}
+
+impl std::error::Error for Error {}
+
+impl From<LedgerDbError> for Error {
+    fn from(src: LedgerDbError) -> Self {
+        Self::LedgerDb(src)
+    }
+}
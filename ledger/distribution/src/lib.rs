@@ -0,0 +1,26 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+#![deny(missing_docs)]
+
+//! Distributes the blocks of a local ledger to long-term storage.
+//!
+//! A [LedgerDistributor] writes every block of a ledger, merged blocks and,
+//! optionally, zstd-compressed archive bundles to an [ObjectStore], in the
+//! layout that ledger sync reads. Object stores are provided for S3 and
+//! S3-compatible services ([S3ObjectStore]), local directories
+//! ([LocalObjectStore]) and memory ([InMemoryObjectStore]), the latter being
+//! meant for tests.
+
+pub mod bundle;
+pub mod object_store;
+pub mod uri;
+
+mod distributor;
+mod error;
+mod state;
+
+pub use crate::{
+    distributor::{DistributorConfig, LedgerDistributor},
+    error::Error,
+    object_store::{InMemoryObjectStore, LocalObjectStore, ObjectStore, S3ObjectStore},
+    state::{bundle_manifest_copy_path, DistributionState},
+};
@@ -4,30 +4,15 @@
 //! A helper utility for collecting blocks from a local ledger file and storing
 //! them as Protobuf-serialized files on S3.
 
-pub mod uri;
-
-use crate::uri::{Destination, Uri};
 use clap::{Parser, ValueEnum};
-use mc_api::{block_num_to_s3block_path, blockchain, merged_block_num_to_s3block_path};
-use mc_blockchain_types::{BlockData, BlockIndex};
-use mc_common::logger::{create_app_logger, log, o, Logger};
+use mc_common::logger::{create_app_logger, log, o};
 use mc_ledger_db::{Ledger, LedgerDB};
-use mc_util_telemetry::{mark_span_as_active, start_block_span, tracer, Tracer};
-use protobuf::Message;
-use retry::{delay, retry, OperationResult};
-use rusoto_core::Region;
-use rusoto_s3::{PutObjectRequest, S3Client, S3};
-use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
-use tokio::runtime::Handle;
-
-/// Block writer.
-pub trait BlockHandler {
-    /// Write a single block.
-    fn write_single_block(&mut self, block_data: &BlockData);
-    /// Write multiple blocks, possibly merged.
-    fn write_multiple_blocks(&mut self, blocks_data: &[BlockData]);
-}
+use mc_ledger_distribution::{
+    uri::{Destination, Uri},
+    DistributionState, DistributorConfig, LedgerDistributor, LocalObjectStore, ObjectStore,
+    S3ObjectStore,
+};
+use std::{path::PathBuf, thread, time::Duration};
 
 /// Block to start syncing from.
 #[derive(ValueEnum, Clone, Debug)]
@@ -74,221 +59,30 @@ pub struct Config {
         env = "MC_MERGE_BUCKETS"
     )]
     merge_buckets: Vec<u64>,
-}
-
-/// State file contents.
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
-pub struct StateData {
-    next_block: BlockIndex,
-}
-
-/// S3 block writer.
-pub struct S3BlockWriter {
-    path: PathBuf,
-    s3_client: S3Client,
-    logger: Logger,
-}
-
-impl S3BlockWriter {
-    fn new(path: PathBuf, region: Region, logger: Logger) -> S3BlockWriter {
-        log::debug!(
-            logger,
-            "Creating S3 Block Writer with path={:?} region={:?}",
-            path,
-            region
-        );
-
-        let s3_client = S3Client::new(region);
-        S3BlockWriter {
-            path,
-            s3_client,
-            logger,
-        }
-    }
-
-    fn write_bytes_to_s3(&self, path: &str, filename: &str, value: &[u8]) {
-        let runtime = Handle::current();
-        let result = retry(
-            delay::Exponential::from_millis_with_base_factor(10).map(delay::jitter),
-            || {
-                let req = PutObjectRequest {
-                    bucket: path.to_string(),
-                    key: filename.to_string(),
-                    body: Some(value.to_vec().into()),
-                    acl: Some("public-read".to_string()),
-                    ..Default::default()
-                };
-
-                runtime
-                    .block_on(self.s3_client.put_object(req))
-                    .map_or_else(
-                        |err| {
-                            log::warn!(
-                                self.logger,
-                                "Failed writing {}: {:?}, retrying...",
-                                filename,
-                                err
-                            );
-                            OperationResult::Retry(err)
-                        },
-                        OperationResult::Ok,
-                    )
-            },
-        );
-
-        // We should always succeed since retrying should never stop until that happens.
-        result.expect("failed to write to S3");
-    }
-}
-
-impl BlockHandler for S3BlockWriter {
-    fn write_single_block(&mut self, block_data: &BlockData) {
-        log::info!(
-            self.logger,
-            "S3: Handling block {}",
-            block_data.block().index
-        );
-
-        let archive_block = blockchain::ArchiveBlock::from(block_data);
-
-        let dest = self
-            .path
-            .as_path()
-            .join(block_num_to_s3block_path(block_data.block().index));
-
-        let dir = dest.as_path().parent().expect("failed getting parent");
-        let filename = dest.file_name().unwrap();
-
-        self.write_bytes_to_s3(
-            dir.to_str().unwrap(),
-            filename.to_str().unwrap(),
-            &archive_block
-                .write_to_bytes()
-                .expect("failed to serialize ArchiveBlock"),
-        );
-    }
-
-    fn write_multiple_blocks(&mut self, blocks_data: &[BlockData]) {
-        assert!(blocks_data.len() >= 2);
-
-        let first_block_index = blocks_data[0].block().index;
-        let last_block_index = blocks_data.last().unwrap().block().index;
-        assert_eq!(
-            last_block_index,
-            first_block_index + blocks_data.len() as u64 - 1
-        );
-
-        log::info!(
-            self.logger,
-            "S3: Handling blocks {}-{}",
-            first_block_index,
-            last_block_index,
-        );
-
-        let archive_blocks = blockchain::ArchiveBlocks::from(blocks_data);
-
-        let dest = self.path.as_path().join(merged_block_num_to_s3block_path(
-            blocks_data.len() as u64,
-            first_block_index,
-        ));
-
-        let dir = dest.as_path().parent().expect("failed getting parent");
-        let filename = dest.file_name().unwrap();
-
-        self.write_bytes_to_s3(
-            dir.to_str().unwrap(),
-            filename.to_str().unwrap(),
-            &archive_blocks
-                .write_to_bytes()
-                .expect("failed to serialize ArchiveBlocks"),
-        );
-    }
-}
-
-/// Local directory block writer.
-pub struct LocalBlockWriter {
-    path: PathBuf,
-    logger: Logger,
-}
 
-impl LocalBlockWriter {
-    fn new(path: PathBuf, logger: Logger) -> LocalBlockWriter {
-        log::debug!(logger, "Creating Local Block Writer with path={:?}", path,);
+    /// Number of blocks in each zstd-compressed archive bundle. Bundles and
+    /// their manifest are written in addition to the per-block and merged
+    /// block files. Use 0 to disable.
+    #[clap(long, default_value = "0", env = "MC_ARCHIVE_BUNDLE_SIZE")]
+    archive_bundle_size: u64,
 
-        LocalBlockWriter { path, logger }
-    }
-}
-
-impl BlockHandler for LocalBlockWriter {
-    fn write_single_block(&mut self, block_data: &BlockData) {
-        log::info!(
-            self.logger,
-            "Local: Handling block {}",
-            block_data.block().index
-        );
-
-        let archive_block = blockchain::ArchiveBlock::from(block_data);
-
-        let bytes = archive_block
-            .write_to_bytes()
-            .expect("failed to serialize ArchiveBlock");
-
-        let dest = self
-            .path
-            .as_path()
-            .join(block_num_to_s3block_path(block_data.block().index));
-        let dir = dest.as_path().parent().expect("failed getting parent");
-
-        fs::create_dir_all(dir)
-            .unwrap_or_else(|e| panic!("failed creating directory {:?}: {:?}", dir, e));
-        fs::write(&dest, bytes).unwrap_or_else(|err| {
-            panic!(
-                "failed writing block #{} to {:?}: {}",
-                block_data.block().index,
-                dest,
-                err
-            )
-        });
-    }
-
-    fn write_multiple_blocks(&mut self, blocks_data: &[BlockData]) {
-        assert!(blocks_data.len() >= 2);
-
-        let first_block_index = blocks_data[0].block().index;
-        let last_block_index = blocks_data.last().unwrap().block().index;
-        assert_eq!(
-            last_block_index,
-            first_block_index + blocks_data.len() as u64 - 1
-        );
-
-        log::info!(
-            self.logger,
-            "Local: Handling blocks {}-{}",
-            first_block_index,
-            last_block_index,
-        );
-
-        let archive_blocks = blockchain::ArchiveBlocks::from(blocks_data);
-
-        let bytes = archive_blocks
-            .write_to_bytes()
-            .expect("failed to serialize ArchiveBlock");
+    /// Zstd compression level of archive bundles.
+    #[clap(
+        long,
+        default_value = "19",
+        env = "MC_ARCHIVE_BUNDLE_COMPRESSION_LEVEL"
+    )]
+    archive_bundle_compression_level: i32,
 
-        let dest = self.path.as_path().join(merged_block_num_to_s3block_path(
-            blocks_data.len() as u64,
-            first_block_index,
-        ));
-        let dir = dest.as_path().parent().expect("failed getting parent");
+    /// Maximal number of attempts at writing an object before giving up on
+    /// the current block, which is then retried on the next poll. Retries
+    /// forever when unset.
+    #[clap(long, env = "MC_MAX_WRITE_ATTEMPTS")]
+    max_write_attempts: Option<usize>,
 
-        fs::create_dir_all(dir)
-            .unwrap_or_else(|e| panic!("failed creating directory {:?}: {:?}", dir, e));
-        fs::write(&dest, bytes).unwrap_or_else(|err| {
-            panic!(
-                "failed writing merged block #{}-{} to {:?}: {}",
-                first_block_index, last_block_index, dest, err,
-            )
-        });
-    }
+    /// Read every object back after writing it and verify its checksum.
+    #[clap(long, env = "MC_VERIFY_WRITES")]
+    verify_writes: bool,
 }
 
 // Implements the ledger db polling loop
@@ -302,12 +96,6 @@ fn main() {
     let _tracer = mc_util_telemetry::setup_default_tracer(env!("CARGO_PKG_NAME"))
         .expect("Failed setting telemetry tracer");
 
-    let runtime = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .expect("tokio runtime");
-    let _enter_guard = runtime.enter();
-
     // Get path to our state file.
     let state_file_path = config.state_file.clone().unwrap_or_else(|| {
         let mut home_dir = dirs::home_dir().unwrap_or_else(|| panic!("Unable to get home directory, please specify state file explicitly with --state-file"));
@@ -332,105 +120,69 @@ fn main() {
             .expect("Failed getting number of blocks in ledger"),
 
         // Sync from the last attempted block, according to a previous state file.
-        StartFrom::Last => {
-            // See if the state file exists and read it if it does.
-            if state_file_path.as_path().exists() {
-                let file_data = fs::read_to_string(&state_file_path).unwrap_or_else(|e| {
-                    panic!("Failed reading state file {:?}: {:?}", state_file_path, e)
-                });
-                let state_data: StateData = serde_json::from_str(&file_data).unwrap_or_else(|e| {
-                    panic!("Failed parsing state file {:?}: {:?}", state_file_path, e)
-                });
-                state_data.next_block
-            } else {
-                0
-            }
-        }
+        StartFrom::Last => DistributionState::load(&state_file_path)
+            .unwrap_or_else(|err| panic!("Failed loading state file: {}", err))
+            .map_or(0, |state| state.next_block),
     };
 
-    // Create block handler
-    let mut block_handler: Box<dyn BlockHandler> = match config.destination.destination {
+    // Create the object store
+    let store: Box<dyn ObjectStore> = match config.destination.destination {
         Destination::S3 { path, region } => {
-            Box::new(S3BlockWriter::new(path, region, logger.clone()))
+            log::debug!(
+                logger,
+                "Creating S3 object store with path={:?} region={:?}",
+                path,
+                region
+            );
+            Box::new(S3ObjectStore::new(region, &path).expect("Failed creating S3 object store"))
         }
 
         Destination::Local { path } => {
-            fs::create_dir_all(&path).unwrap_or_else(|_| {
-                panic!("Failed creating local destination directory {:?}", path)
-            });
-            Box::new(LocalBlockWriter::new(path, logger.clone()))
+            log::debug!(logger, "Creating local object store with path={:?}", path);
+            Box::new(LocalObjectStore::new(&path).unwrap_or_else(|err| {
+                panic!(
+                    "Failed creating local destination directory {:?}: {}",
+                    path, err
+                )
+            }))
         }
     };
 
+    let distributor_config = DistributorConfig {
+        merge_buckets: config.merge_buckets,
+        archive_bundle_size: config.archive_bundle_size,
+        archive_bundle_compression_level: config.archive_bundle_compression_level,
+        max_write_attempts: config.max_write_attempts,
+        verify_writes: config.verify_writes,
+    };
+    let mut distributor = LedgerDistributor::new(
+        ledger_db,
+        store,
+        distributor_config,
+        first_desired_block,
+        Some(state_file_path),
+        logger.clone(),
+    )
+    .expect("Failed creating ledger distributor");
+
     // Poll ledger for new blocks and process them as they come.
     log::info!(
         logger,
         "Polling for blocks, starting at {}...",
         first_desired_block
     );
-    let mut next_block_num = first_desired_block;
-    let tracer = tracer!();
 
     loop {
-        while let Ok(block_data) = ledger_db.get_block_data(next_block_num) {
-            log::trace!(logger, "Handling block #{}", next_block_num);
-
-            let span = start_block_span(&tracer, "distribute-block", next_block_num);
-            let _active_span = mark_span_as_active(span);
-
-            tracer.in_span("write_single_block", |_cx| {
-                block_handler.write_single_block(&block_data);
-            });
-
-            let cur_block_index = block_data.block().index;
-            for bucket_size in config.merge_buckets.iter() {
-                // Zero bucket size is invalid, bucket size of 1 is a single block.
-                if *bucket_size <= 1 {
-                    continue;
-                }
-
-                // Check if we just completed a bucket.
-                if (cur_block_index + 1) % bucket_size != 0 {
-                    continue;
-                }
-
-                let first_block_index = cur_block_index + 1 - *bucket_size;
-                let last_block_index = cur_block_index;
-
-                log::debug!(
-                    logger,
-                    "Preparing to write merged block [{}-{}]",
-                    first_block_index,
-                    last_block_index
-                );
-
-                let mut blocks_data = Vec::new();
-                for block_index in first_block_index..=last_block_index {
-                    // We panic here since this block and its associated data is expected to be in
-                    // the ledger due to block_index <= next_block_num (which we
-                    // successfully fetched or otherwise this code wouldn't be
-                    // running).
-                    let block_data = ledger_db.get_block_data(block_index).unwrap_or_else(|err| {
-                        panic!("failed getting block #{}: {}", block_index, err)
-                    });
-                    blocks_data.push(block_data);
-                }
-
-                tracer.in_span("write_multiple_blocks", |_cx| {
-                    block_handler.write_multiple_blocks(&blocks_data);
-                });
-            }
-
-            next_block_num += 1;
-
-            let state = StateData {
-                next_block: next_block_num,
-            };
-            let json_data = serde_json::to_string(&state).expect("failed serializing state data");
-            fs::write(&state_file_path, json_data).expect("failed writing state file");
+        if let Err(err) = distributor.distribute_available_blocks() {
+            log::error!(
+                logger,
+                "Failed distributing block #{}: {}",
+                distributor.next_block(),
+                err
+            );
         }
 
         // TODO: make this configurable
-        std::thread::sleep(std::time::Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(10));
     }
 }
@@ -0,0 +1,87 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! An object store backed by a local directory.
+
+use super::ObjectStore;
+use crate::Error;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Stores objects as files under a local directory.
+///
+/// Objects are written to a temporary file that is then renamed into place,
+/// so readers never see a partially written object.
+#[derive(Clone, Debug)]
+pub struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    /// Create a store rooted at `root`, creating the directory if needed.
+    pub fn new(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).map_err(|err| Error::Io(format!("{:?}", root), err))?;
+        Ok(Self { root })
+    }
+
+    /// The root directory of this store.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl ObjectStore for LocalObjectStore {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), Error> {
+        let dest = self.root.join(key);
+        let io_err = |err| Error::Io(format!("{:?}", dest), err);
+
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir).map_err(io_err)?;
+        }
+
+        let mut tmp_dest = dest.clone().into_os_string();
+        tmp_dest.push(".tmp");
+        fs::write(&tmp_dest, bytes)
+            .and_then(|_| fs::rename(&tmp_dest, &dest))
+            .map_err(io_err)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let src = self.root.join(key);
+        fs::read(&src).map_err(|err| match err.kind() {
+            ErrorKind::NotFound => Error::NotFound(key.to_string()),
+            _ => Error::Io(format!("{:?}", src), err),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn put_and_get() {
+        let dir = TempDir::new("local_object_store").unwrap();
+        let store = LocalObjectStore::new(dir.path().join("archive")).unwrap();
+
+        assert!(matches!(
+            store.get("00/01/0000000000000001.pb"),
+            Err(Error::NotFound(_))
+        ));
+
+        store.put("00/01/0000000000000001.pb", b"first").unwrap();
+        store.put("00/01/0000000000000001.pb", b"second").unwrap();
+        assert_eq!(
+            store.get("00/01/0000000000000001.pb").unwrap(),
+            b"second".to_vec()
+        );
+        assert!(!dir
+            .path()
+            .join("archive/00/01/0000000000000001.pb.tmp")
+            .exists());
+    }
+}
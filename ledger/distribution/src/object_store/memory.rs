@@ -0,0 +1,79 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! An in-memory object store, for tests.
+
+use super::ObjectStore;
+use crate::Error;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// Keeps objects in memory.
+///
+/// Clones share the same objects. Write failures and corrupted writes can be
+/// injected to exercise the retry and verification logic of callers.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryObjectStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    objects: BTreeMap<String, Vec<u8>>,
+    failing_puts: usize,
+    corrupted_puts: usize,
+}
+
+impl InMemoryObjectStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The keys of all stored objects, in order.
+    pub fn keys(&self) -> Vec<String> {
+        self.inner.lock().unwrap().objects.keys().cloned().collect()
+    }
+
+    /// Make the next `n` writes fail without storing anything.
+    pub fn fail_next_puts(&self, n: usize) {
+        self.inner.lock().unwrap().failing_puts = n;
+    }
+
+    /// Make the next `n` successful writes store corrupted bytes.
+    pub fn corrupt_next_puts(&self, n: usize) {
+        self.inner.lock().unwrap().corrupted_puts = n;
+    }
+}
+
+impl ObjectStore for InMemoryObjectStore {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.failing_puts > 0 {
+            inner.failing_puts -= 1;
+            return Err(Error::Request(
+                key.to_string(),
+                "injected failure".to_string(),
+            ));
+        }
+
+        let mut bytes = bytes.to_vec();
+        if inner.corrupted_puts > 0 {
+            inner.corrupted_puts -= 1;
+            bytes.push(0);
+        }
+        inner.objects.insert(key.to_string(), bytes);
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        self.inner
+            .lock()
+            .unwrap()
+            .objects
+            .get(key)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(key.to_string()))
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Object stores that ledger distribution writes to.
+//!
+//! Objects are addressed by `/`-separated keys relative to the root of the
+//! store, e.g. the paths returned by `block_num_to_s3block_path`.
+
+mod local;
+mod memory;
+mod s3;
+
+pub use self::{local::LocalObjectStore, memory::InMemoryObjectStore, s3::S3ObjectStore};
+
+use crate::Error;
+use std::sync::Arc;
+
+/// A store of immutable, byte-valued objects.
+pub trait ObjectStore: Send + Sync {
+    /// Store `bytes` under `key`, replacing any existing object.
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), Error>;
+
+    /// Read the object stored under `key`. Returns `Error::NotFound` if there
+    /// is none.
+    fn get(&self, key: &str) -> Result<Vec<u8>, Error>;
+}
+
+impl<S: ObjectStore + ?Sized> ObjectStore for Box<S> {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), Error> {
+        (**self).put(key, bytes)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        (**self).get(key)
+    }
+}
+
+impl<S: ObjectStore + ?Sized> ObjectStore for Arc<S> {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), Error> {
+        (**self).put(key, bytes)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        (**self).get(key)
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! An object store backed by S3, or by any S3-compatible service such as MinIO.
+
+use super::ObjectStore;
+use crate::Error;
+use rusoto_core::{Region, RusotoError};
+use rusoto_s3::{GetObjectError, GetObjectRequest, PutObjectRequest, S3Client, S3};
+use std::path::Path;
+use tokio::{io::AsyncReadExt, runtime::Runtime};
+
+/// Stores objects in an S3 bucket, under an optional key prefix.
+///
+/// Requests are made on a runtime owned by the store, so it can be used from
+/// synchronous code. Uploaded objects are publicly readable.
+pub struct S3ObjectStore {
+    s3_client: S3Client,
+    bucket: String,
+    prefix: String,
+    runtime: Runtime,
+}
+
+impl S3ObjectStore {
+    /// Create a store.
+    ///
+    /// # Arguments
+    /// * `region` - The region to connect to. S3-compatible services are
+    ///   reached with `Region::Custom`, whose endpoint is the URL of the
+    ///   service.
+    /// * `path` - The bucket, optionally followed by a key prefix, e.g.
+    ///   `my_bucket/my_node.my_domain.com`.
+    pub fn new(region: Region, path: &Path) -> Result<Self, Error> {
+        let path = path.to_string_lossy();
+        let (bucket, prefix) = path
+            .trim_matches('/')
+            .split_once('/')
+            .unwrap_or((path.trim_matches('/'), ""));
+        if bucket.is_empty() {
+            return Err(Error::Request(
+                path.to_string(),
+                "missing bucket name".to_string(),
+            ));
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| Error::Io("tokio runtime".to_string(), err))?;
+        let s3_client = {
+            let _enter_guard = runtime.enter();
+            S3Client::new(region)
+        };
+
+        Ok(Self {
+            s3_client,
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            runtime,
+        })
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+}
+
+impl ObjectStore for S3ObjectStore {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), Error> {
+        let req = PutObjectRequest {
+            bucket: self.bucket.clone(),
+            key: self.object_key(key),
+            body: Some(bytes.to_vec().into()),
+            acl: Some("public-read".to_string()),
+            ..Default::default()
+        };
+
+        self.runtime
+            .block_on(self.s3_client.put_object(req))
+            .map(|_| ())
+            .map_err(|err| Error::Request(key.to_string(), err.to_string()))
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let req = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            key: self.object_key(key),
+            ..Default::default()
+        };
+
+        self.runtime.block_on(async {
+            let output = self
+                .s3_client
+                .get_object(req)
+                .await
+                .map_err(|err| match err {
+                    RusotoError::Service(GetObjectError::NoSuchKey(_)) => {
+                        Error::NotFound(key.to_string())
+                    }
+                    err => Error::Request(key.to_string(), err.to_string()),
+                })?;
+
+            let mut bytes = Vec::new();
+            if let Some(body) = output.body {
+                body.into_async_read()
+                    .read_to_end(&mut bytes)
+                    .await
+                    .map_err(|err| Error::Io(key.to_string(), err))?;
+            }
+            Ok(bytes)
+        })
+    }
+}
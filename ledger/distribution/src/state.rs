@@ -0,0 +1,53 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Persistent distribution state, used to resume after a restart.
+
+use crate::Error;
+use mc_blockchain_types::BlockIndex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// State file contents.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DistributionState {
+    /// The next block to distribute.
+    pub next_block: BlockIndex,
+}
+
+impl DistributionState {
+    /// Read the state file at `path`. Returns `None` if there is none.
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        let file_data = match fs::read_to_string(path) {
+            Ok(file_data) => file_data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::Io(format!("{:?}", path), err)),
+        };
+        serde_json::from_str(&file_data)
+            .map(Some)
+            .map_err(|err| Error::InvalidState(format!("{:?}", path), err.to_string()))
+    }
+
+    /// Write the state file at `path`. The file is replaced atomically, so an
+    /// interrupted write leaves the previous state in place.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let json_data = serde_json::to_string(self).expect("failed serializing state data");
+
+        let mut tmp_path = path.to_path_buf().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, json_data)
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .map_err(|err| Error::Io(format!("{:?}", path), err))
+    }
+}
+
+/// Where the local copy of the archive bundle manifest is kept, next to the
+/// state file.
+pub fn bundle_manifest_copy_path(state_file: &Path) -> PathBuf {
+    let mut path = state_file.to_path_buf().into_os_string();
+    path.push(".bundle-manifest");
+    PathBuf::from(path)
+}
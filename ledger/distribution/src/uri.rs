@@ -1,7 +1,11 @@
 // Copyright (c) 2018-2022 The MobileCoin Foundation
-#![deny(missing_docs)]
 
-//! Distribution target URI
+//! Distribution target URI.
+//!
+//! Destinations are either `s3://bucket/prefix?region=...` or
+//! `file:///path/to/dir`. An S3-compatible service is selected by adding an
+//! `endpoint` parameter, e.g.
+//! `s3://bucket/prefix?endpoint=http://localhost:9000`.
 
 use displaydoc::Display;
 use rusoto_core::{region::ParseRegionError, Region};
@@ -68,17 +72,27 @@ impl FromStr for Uri {
                     return Err(UriParseError::MissingPath);
                 }
 
-                let region_param = url.query_pairs().find_map(|(k, v)| {
-                    if k == "region" && !v.is_empty() {
-                        Some(v.to_string())
-                    } else {
-                        None
-                    }
-                });
-
-                let region = region_param
-                    .map_or_else(|| Ok(Region::default()), |param| Region::from_str(&param))
-                    .map_err(UriParseError::InvalidS3Region)?;
+                let query_param = |name: &str| {
+                    url.query_pairs().find_map(|(k, v)| {
+                        if k == name && !v.is_empty() {
+                            Some(v.to_string())
+                        } else {
+                            None
+                        }
+                    })
+                };
+
+                // S3-compatible services (e.g. MinIO) are reached through a custom endpoint,
+                // in which case the region is only used for signing requests.
+                let region = match (query_param("region"), query_param("endpoint")) {
+                    (region_param, Some(endpoint)) => Region::Custom {
+                        name: region_param.unwrap_or_else(|| Region::default().name().to_string()),
+                        endpoint,
+                    },
+                    (region_param, None) => region_param
+                        .map_or_else(|| Ok(Region::default()), |param| Region::from_str(&param))
+                        .map_err(UriParseError::InvalidS3Region)?,
+                };
 
                 Destination::S3 {
                     path: PathBuf::from(path),
@@ -105,3 +119,29 @@ impl FromStr for Uri {
         Ok(Self { url, destination })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_s3_with_custom_endpoint() {
+        let uri = Uri::from_str(
+            "s3://my_bucket/my_node.my_domain.com?region=eu-west-1&endpoint=http://localhost:9000",
+        )
+        .unwrap();
+        match uri.destination {
+            Destination::S3 { region, path } => {
+                assert_eq!(
+                    region,
+                    Region::Custom {
+                        name: "eu-west-1".to_string(),
+                        endpoint: "http://localhost:9000".to_string(),
+                    }
+                );
+                assert_eq!(path, PathBuf::from("my_bucket/my_node.my_domain.com"));
+            }
+            destination => panic!("unexpected destination {:?}", destination),
+        }
+    }
+}
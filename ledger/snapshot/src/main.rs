@@ -0,0 +1,87 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+#![deny(missing_docs)]
+#![doc = include_str!("../README.md")]
+#![forbid(unsafe_code)]
+
+use clap::{Parser, Subcommand};
+use mc_blockchain_types::BlockID;
+use mc_common::logger::{create_app_logger, log, o};
+use mc_ledger_db::{Ledger, LedgerDB};
+use mc_util_parse::parse_hex;
+use std::path::PathBuf;
+
+/// Command line configuration.
+#[derive(Debug, Parser)]
+#[clap(
+    name = "mc-ledger-snapshot",
+    about = "Export a ledger db to a snapshot file, or import one."
+)]
+pub struct Config {
+    /// Path to ledger db (lmdb).
+    #[clap(long, env = "MC_LEDGER_DB")]
+    pub ledger_db: PathBuf,
+
+    /// Command to run.
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+/// Commands that the tool recognizes.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Write a snapshot of the ledger db at its current height.
+    Export {
+        /// Path of the snapshot file to write.
+        #[clap(long, env = "MC_SNAPSHOT")]
+        snapshot: PathBuf,
+    },
+
+    /// Create a new ledger db from a snapshot.
+    Import {
+        /// Path of the snapshot file to read.
+        #[clap(long, env = "MC_SNAPSHOT")]
+        snapshot: PathBuf,
+
+        /// Hex-encoded ID of the last block in the snapshot, from a trusted
+        /// source.
+        #[clap(long, value_parser = parse_hex::<[u8; 32]>, env = "MC_TRUSTED_BLOCK_ID")]
+        trusted_block_id: [u8; 32],
+    },
+}
+
+fn main() {
+    mc_common::setup_panic_handler();
+    let (logger, _global_logger_guard) = create_app_logger(o!());
+    let config = Config::parse();
+
+    match config.command {
+        Command::Export { snapshot } => {
+            let ledger_db = LedgerDB::open(&config.ledger_db).expect("Could not open ledger_db");
+            let header = ledger_db
+                .export_snapshot(&snapshot)
+                .expect("Failed exporting snapshot");
+            log::info!(
+                logger,
+                "Exported {} blocks to {:?}, last block ID: {}",
+                header.num_blocks,
+                snapshot,
+                header.last_block_id
+            );
+        }
+
+        Command::Import {
+            snapshot,
+            trusted_block_id,
+        } => {
+            let ledger_db =
+                LedgerDB::import_snapshot(&snapshot, &config.ledger_db, &BlockID(trusted_block_id))
+                    .expect("Failed importing snapshot");
+            log::info!(
+                logger,
+                "Imported {} blocks into {:?}",
+                ledger_db.num_blocks().expect("Could not read num_blocks"),
+                config.ledger_db
+            );
+        }
+    }
+}
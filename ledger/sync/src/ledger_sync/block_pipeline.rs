@@ -0,0 +1,466 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Pipelined fetching and validation of block contents.
+//!
+//! A pool of workers fetches ranges of consecutive blocks through a
+//! [`TransactionsFetcher`] and checks each block's contents, ID and
+//! signatures. The checks that depend on the local ledger (parent chaining and
+//! key images) are left to the consumer, which receives the validated blocks
+//! in order, as soon as a consecutive run of them is available. This lets the
+//! consumer append blocks to the ledger while later ranges are still being
+//! fetched.
+
+use crate::{LedgerSyncError, TransactionsFetcher};
+use crossbeam_channel::{Receiver, Sender};
+use mc_blockchain_types::{compute_block_id, Block, BlockData, BlockIndex};
+use mc_common::{
+    logger::{log, Logger},
+    ResponderId,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The longest a worker waits before retrying a block it failed to fetch.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Configuration of pipelined block fetching.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PipelineConfig {
+    /// Number of worker threads fetching and validating blocks.
+    pub num_workers: usize,
+
+    /// Number of consecutive blocks handed to a worker at a time. Ranges that
+    /// line up with merged blocks or archive bundles let a worker fetch all of
+    /// its blocks with a single request.
+    pub blocks_per_range: u64,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            num_workers: 8,
+            blocks_per_range: 100,
+        }
+    }
+}
+
+/// Fetches and validates blocks in a worker pool, handing them out in order.
+///
+/// Dropping the pipeline stops its workers.
+pub struct BlockPipeline {
+    /// Validated blocks, or the reason a block could not be fetched.
+    receiver: Receiver<(BlockIndex, Result<BlockData, LedgerSyncError>)>,
+
+    /// Results received out of order.
+    pending: BTreeMap<BlockIndex, Result<BlockData, LedgerSyncError>>,
+
+    /// The next block to hand out.
+    next_index: BlockIndex,
+
+    /// One past the last block of the pipeline.
+    end_index: BlockIndex,
+
+    /// Set once a block failed, after which nothing more is handed out.
+    failed: bool,
+
+    stop_requested: Arc<AtomicBool>,
+    join_handles: Vec<thread::JoinHandle<()>>,
+}
+
+impl BlockPipeline {
+    /// Start fetching the contents of `blocks`.
+    ///
+    /// # Arguments
+    /// * `transactions_fetcher` - The mechanism used for fetching block
+    ///   contents.
+    /// * `safe_responder_ids` - ResponderIds that agree with each other on
+    ///   `blocks`.
+    /// * `blocks` - Consecutive blocks, in increasing order of block index.
+    /// * `config` - Pipeline configuration.
+    /// * `timeout` - How long workers keep retrying a block before giving up on
+    ///   it.
+    /// * `logger` - Logger.
+    pub fn start<TF: TransactionsFetcher + 'static>(
+        transactions_fetcher: Arc<TF>,
+        safe_responder_ids: &[ResponderId],
+        blocks: Vec<Block>,
+        config: &PipelineConfig,
+        timeout: Duration,
+        logger: &Logger,
+    ) -> Self {
+        let next_index = blocks.first().map_or(0, |block| block.index);
+        let end_index = next_index + blocks.len() as u64;
+        let deadline = Instant::now() + timeout;
+
+        // Hand out ranges in order, so that the earliest blocks are fetched first.
+        let ranges = blocks
+            .chunks(config.blocks_per_range.max(1) as usize)
+            .map(|range| range.to_vec())
+            .collect::<Vec<_>>();
+        let (range_sender, range_receiver) = crossbeam_channel::unbounded();
+        let num_ranges = ranges.len();
+        for range in ranges {
+            range_sender.send(range).expect("failed sending to channel");
+        }
+        drop(range_sender);
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let stop_requested = Arc::new(AtomicBool::new(false));
+
+        let num_workers = config.num_workers.max(1).min(num_ranges);
+        let join_handles = (0..num_workers)
+            .map(|worker_num| {
+                let worker = Worker {
+                    worker_num,
+                    transactions_fetcher: transactions_fetcher.clone(),
+                    safe_responder_ids: safe_responder_ids.to_vec(),
+                    deadline,
+                    sender: sender.clone(),
+                    stop_requested: stop_requested.clone(),
+                    logger: logger.clone(),
+                };
+                let range_receiver = range_receiver.clone();
+
+                thread::Builder::new()
+                    .name(format!("LedgerSync::Pipeline:{}", worker_num))
+                    .spawn(move || worker.run(range_receiver))
+                    .expect("Failed spawning block pipeline thread!")
+            })
+            .collect();
+
+        Self {
+            receiver,
+            pending: BTreeMap::new(),
+            next_index,
+            end_index,
+            failed: false,
+            stop_requested,
+            join_handles,
+        }
+    }
+
+    /// Wait for the next block, and return it together with every following
+    /// block that is already available. Returns an empty batch once all blocks
+    /// have been handed out.
+    ///
+    /// If a block could not be fetched, the blocks before it are returned
+    /// first, and its error is returned by the following call. No blocks are
+    /// handed out after an error.
+    pub fn next_batch(&mut self) -> Result<Vec<BlockData>, LedgerSyncError> {
+        let mut batch = Vec::new();
+        if self.failed {
+            return Ok(batch);
+        }
+
+        while self.next_index < self.end_index {
+            if !self.pending.contains_key(&self.next_index) {
+                if !batch.is_empty() {
+                    // Only wait for blocks when there is nothing to return yet.
+                    self.receive_available();
+                    if !self.pending.contains_key(&self.next_index) {
+                        break;
+                    }
+                } else {
+                    match self.receiver.recv() {
+                        Ok((block_index, result)) => {
+                            self.pending.insert(block_index, result);
+                        }
+                        // All workers stopped without producing this block.
+                        Err(_) => return Err(LedgerSyncError::NoTransactionData),
+                    }
+                    continue;
+                }
+            }
+
+            match self
+                .pending
+                .remove(&self.next_index)
+                .expect("block is pending")
+            {
+                Ok(block_data) => {
+                    batch.push(block_data);
+                    self.next_index += 1;
+                }
+                Err(err) => {
+                    if !batch.is_empty() {
+                        // Report the error on the next call.
+                        self.pending.insert(self.next_index, Err(err));
+                        break;
+                    }
+                    self.stop();
+                    self.failed = true;
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(batch)
+    }
+
+    fn receive_available(&mut self) {
+        while let Ok((block_index, result)) = self.receiver.try_recv() {
+            self.pending.insert(block_index, result);
+        }
+    }
+
+    fn stop(&mut self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for BlockPipeline {
+    fn drop(&mut self) {
+        self.stop();
+        for join_handle in self.join_handles.drain(..) {
+            let _ = join_handle.join();
+        }
+    }
+}
+
+/// A pipeline worker.
+struct Worker<TF: TransactionsFetcher> {
+    worker_num: usize,
+    transactions_fetcher: Arc<TF>,
+    safe_responder_ids: Vec<ResponderId>,
+    deadline: Instant,
+    sender: Sender<(BlockIndex, Result<BlockData, LedgerSyncError>)>,
+    stop_requested: Arc<AtomicBool>,
+    logger: Logger,
+}
+
+impl<TF: TransactionsFetcher> Worker<TF> {
+    fn run(self, range_receiver: Receiver<Vec<Block>>) {
+        for range in range_receiver.iter() {
+            for block in range {
+                if self.stop_requested.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                let result = self.fetch_block(&block);
+                if self.sender.send((block.index, result)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Fetch and validate a block, retrying until the deadline.
+    fn fetch_block(&self, block: &Block) -> Result<BlockData, LedgerSyncError> {
+        let mut num_attempts = 0;
+        loop {
+            let result = self
+                .transactions_fetcher
+                .get_block_data(&self.safe_responder_ids, block)
+                .map_err(LedgerSyncError::from)
+                .and_then(|block_data| {
+                    validate_block_data(block, &block_data)?;
+                    Ok(block_data)
+                });
+
+            match result {
+                Ok(block_data) => {
+                    log::trace!(
+                        self.logger,
+                        "Worker {} got contents for block {}",
+                        self.worker_num,
+                        block.index
+                    );
+                    return Ok(block_data);
+                }
+                Err(err) => {
+                    log::info!(
+                        self.logger,
+                        "Worker {} failed getting block {}: {}",
+                        self.worker_num,
+                        block.index,
+                        err
+                    );
+                }
+            }
+
+            num_attempts += 1;
+            let delay = MAX_RETRY_DELAY.min(Duration::from_millis(100 << num_attempts.min(10)));
+            if Instant::now() + delay > self.deadline {
+                log::error!(
+                    self.logger,
+                    "Worker {} giving up on block {}: deadline exceeded",
+                    self.worker_num,
+                    block.index,
+                );
+                return Err(LedgerSyncError::BlockFetchTimeout(block.index));
+            }
+            if self.stop_requested.load(Ordering::SeqCst) {
+                return Err(LedgerSyncError::NoTransactionData);
+            }
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Check that fetched block data matches the block it was fetched for, that
+/// the block ID agrees with its contents, and that its signature and metadata
+/// are valid, if present.
+pub fn validate_block_data(block: &Block, block_data: &BlockData) -> Result<(), LedgerSyncError> {
+    if block != block_data.block() {
+        return Err(LedgerSyncError::TransactionsAndBlockMismatch);
+    }
+
+    let contents_hash = block_data.contents().hash();
+    if contents_hash != block.contents_hash {
+        return Err(LedgerSyncError::TransactionsAndBlockMismatch);
+    }
+
+    let derived_block_id = compute_block_id(
+        block.version,
+        &block.parent_id,
+        block.index,
+        block.cumulative_txo_count,
+        &block.root_element,
+        &contents_hash,
+    );
+    if block.id != derived_block_id {
+        return Err(LedgerSyncError::InvalidBlockId);
+    }
+
+    if let Some(signature) = block_data.signature() {
+        signature
+            .verify(block)
+            .map_err(|_| LedgerSyncError::InvalidBlockSignature(block.index))?;
+    }
+
+    if let Some(metadata) = block_data.metadata() {
+        if metadata.contents().block_id() != &block.id {
+            return Err(LedgerSyncError::InvalidBlockSignature(block.index));
+        }
+        metadata
+            .verify()
+            .map_err(|_| LedgerSyncError::InvalidBlockSignature(block.index))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockTransactionsFetcher;
+    use mc_common::logger::test_with_logger;
+    use mc_ledger_db::{
+        test_utils::{get_mock_ledger, MockLedger},
+        Ledger,
+    };
+
+    /// Fails to fetch a single block.
+    struct FailingTransactionsFetcher {
+        inner: MockTransactionsFetcher<MockLedger>,
+        failing_block_index: BlockIndex,
+    }
+
+    impl TransactionsFetcher for FailingTransactionsFetcher {
+        type Error = String;
+
+        fn get_block_data(
+            &self,
+            safe_responder_ids: &[ResponderId],
+            block: &Block,
+        ) -> Result<BlockData, Self::Error> {
+            if block.index == self.failing_block_index {
+                return Err(format!("block {} is unavailable", block.index));
+            }
+            self.inner.get_block_data(safe_responder_ids, block)
+        }
+    }
+
+    fn collect_batches(pipeline: &mut BlockPipeline) -> (Vec<BlockData>, Option<LedgerSyncError>) {
+        let mut blocks = Vec::new();
+        loop {
+            match pipeline.next_batch() {
+                Ok(batch) if batch.is_empty() => return (blocks, None),
+                Ok(batch) => blocks.extend(batch),
+                Err(err) => return (blocks, Some(err)),
+            }
+        }
+    }
+
+    #[test_with_logger]
+    // Blocks fetched by several workers should be handed out in order.
+    fn test_blocks_are_handed_out_in_order(logger: Logger) {
+        let ledger = get_mock_ledger(25);
+        let blocks = (1..25)
+            .map(|block_index| ledger.get_block(block_index).unwrap())
+            .collect::<Vec<_>>();
+
+        let config = PipelineConfig {
+            num_workers: 4,
+            blocks_per_range: 3,
+        };
+        let mut pipeline = BlockPipeline::start(
+            Arc::new(MockTransactionsFetcher::new(ledger.clone())),
+            &[],
+            blocks,
+            &config,
+            Duration::from_secs(10),
+            &logger,
+        );
+
+        let (fetched, err) = collect_batches(&mut pipeline);
+        assert!(err.is_none());
+        let expected = (1..25)
+            .map(|block_index| ledger.get_block_data(block_index).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(fetched, expected);
+    }
+
+    #[test_with_logger]
+    // Blocks before one that could not be fetched should be handed out, followed
+    // by the error.
+    fn test_stops_at_failed_block(logger: Logger) {
+        let ledger = get_mock_ledger(20);
+        let blocks = (1..20)
+            .map(|block_index| ledger.get_block(block_index).unwrap())
+            .collect::<Vec<_>>();
+
+        let transactions_fetcher = FailingTransactionsFetcher {
+            inner: MockTransactionsFetcher::new(ledger.clone()),
+            failing_block_index: 6,
+        };
+        let mut pipeline = BlockPipeline::start(
+            Arc::new(transactions_fetcher),
+            &[],
+            blocks,
+            &PipelineConfig::default(),
+            Duration::from_millis(500),
+            &logger,
+        );
+
+        let (fetched, err) = collect_batches(&mut pipeline);
+        assert!(matches!(err, Some(LedgerSyncError::BlockFetchTimeout(6))));
+        let expected = (1..6)
+            .map(|block_index| ledger.get_block_data(block_index).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(fetched, expected);
+        assert!(pipeline.next_batch().unwrap().is_empty());
+    }
+
+    #[test]
+    // Block data that does not match the block it was fetched for should be
+    // rejected.
+    fn test_validate_block_data() {
+        let ledger = get_mock_ledger(3);
+        let block_data = ledger.get_block_data(1).unwrap();
+        assert!(validate_block_data(block_data.block(), &block_data).is_ok());
+
+        let other_block = ledger.get_block(2).unwrap();
+        assert!(matches!(
+            validate_block_data(&other_block, &block_data),
+            Err(LedgerSyncError::TransactionsAndBlockMismatch)
+        ));
+    }
+}
@@ -4,6 +4,7 @@
 
 use crate::transactions_fetcher_trait::TransactionFetcherError;
 use displaydoc::Display;
+use mc_blockchain_types::BlockIndex;
 use mc_connection::Error as ConnectionError;
 use mc_ledger_db::Error as LedgerDbError;
 use retry::Error as RetryError;
@@ -37,6 +38,12 @@ pub enum LedgerSyncError {
 
     /// No transaction data
     NoTransactionData,
+
+    /// Invalid signature or metadata for block {0}
+    InvalidBlockSignature(BlockIndex),
+
+    /// Timed out fetching block {0}
+    BlockFetchTimeout(BlockIndex),
 }
 
 impl<TFE: TransactionFetcherError + 'static> From<TFE> for LedgerSyncError {
@@ -6,8 +6,8 @@
 //! transaction data.
 
 use crate::{
-    BlockMetadataProvider, LedgerSync, LedgerSyncError, NetworkState, PassThroughMetadataProvider,
-    TransactionsFetcher,
+    BlockMetadataProvider, BlockPipeline, LedgerSync, LedgerSyncError, NetworkState,
+    PassThroughMetadataProvider, PipelineConfig, TransactionsFetcher,
 };
 use mc_blockchain_types::{compute_block_id, Block, BlockData, BlockID, BlockIndex};
 use mc_common::{
@@ -53,6 +53,9 @@ pub struct LedgerSyncService<
     /// Timeout for network requests.
     get_blocks_timeout: Duration,
     get_block_contents_timeout: Duration,
+    /// When set, block contents are fetched and validated in a pipeline, and
+    /// blocks are appended as they become available.
+    pipeline_config: Option<PipelineConfig>,
     metadata_provider: BMP,
     logger: Logger,
 }
@@ -105,10 +108,22 @@ impl<
             metadata_provider,
             get_blocks_timeout: DEFAULT_GET_BLOCKS_TIMEOUT,
             get_block_contents_timeout: DEFAULT_GET_BLOCK_CONTENTS_TIMEOUT,
+            pipeline_config: None,
             logger,
         }
     }
 
+    /// Enable or disable pipelined block fetching.
+    ///
+    /// In pipelined mode, the contents of potentially safe blocks are fetched
+    /// in ranges by a pool of workers, which also validate block contents and
+    /// signatures. Blocks are still appended strictly in order, but appending
+    /// starts as soon as the first blocks are available instead of after all
+    /// of them have been fetched.
+    pub fn set_pipeline_config(&mut self, pipeline_config: Option<PipelineConfig>) {
+        self.pipeline_config = pipeline_config;
+    }
+
     /// Identifies Blocks that are potentially safe to append to the local
     /// ledger.
     ///
@@ -217,6 +232,74 @@ impl<
         }
     }
 
+    /// Fetch the contents of potentially safe blocks through a
+    /// [BlockPipeline], appending safe blocks as they become available.
+    fn sync_pipelined(
+        &mut self,
+        responder_ids: &[ResponderId],
+        potentially_safe_blocks: Vec<Block>,
+        pipeline_config: &PipelineConfig,
+    ) -> Result<(), LedgerSyncError> {
+        // Sort and deduplicate safe blocks by index.
+        let safe_blocks_by_index: BTreeMap<BlockIndex, Block> = potentially_safe_blocks
+            .into_iter()
+            .map(|block| (block.index, block))
+            .collect();
+        let num_potentially_safe_blocks = safe_blocks_by_index.len();
+
+        let mut pipeline = BlockPipeline::start(
+            self.transactions_fetcher.clone(),
+            responder_ids,
+            safe_blocks_by_index.into_values().collect(),
+            pipeline_config,
+            self.get_block_contents_timeout,
+            &self.logger,
+        );
+
+        let mut num_appended = 0;
+        loop {
+            let blocks = match pipeline.next_batch() {
+                Ok(blocks) if blocks.is_empty() => break,
+                Ok(blocks) => blocks,
+                Err(err) if num_appended == 0 => {
+                    log::error!(
+                        self.logger,
+                        "Identified {} safe blocks but was unable to get block contents: {}",
+                        num_potentially_safe_blocks,
+                        err
+                    );
+                    return Err(LedgerSyncError::NoTransactionData);
+                }
+                Err(err) => {
+                    log::error!(
+                        self.logger,
+                        "Failed getting block contents after appending {} blocks: {}",
+                        num_appended,
+                        err
+                    );
+                    break;
+                }
+            };
+
+            // Block contents and IDs were validated by the pipeline.
+            let safe_blocks = identify_safe_blocks_impl(&self.ledger, &blocks, false, &self.logger);
+            let all_safe = safe_blocks.len() == blocks.len();
+
+            tracer!().in_span("append_safe_blocks", |cx| {
+                cx.span()
+                    .set_attribute(TELEMETRY_NUM_BLOCKS_APPENDED.i64(safe_blocks.len() as i64));
+                self.append_safe_blocks(&safe_blocks)
+            })?;
+            num_appended += safe_blocks.len();
+
+            if !all_safe {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Append safe blocks to the local ledger.
     fn append_safe_blocks(&mut self, blocks: &[BlockData]) -> Result<(), LedgerSyncError> {
         log::info!(
@@ -325,6 +408,14 @@ impl<
                 return Err(LedgerSyncError::EmptyBlockVec);
             }
 
+            if let Some(pipeline_config) = self.pipeline_config.clone() {
+                return self.sync_pipelined(
+                    &responder_ids,
+                    potentially_safe_blocks,
+                    &pipeline_config,
+                );
+            }
+
             let num_potentially_safe_blocks = potentially_safe_blocks.len();
 
             // Get transactions.
@@ -795,6 +886,17 @@ pub fn identify_safe_blocks<L: Ledger>(
     ledger: &L,
     blocks: &[BlockData],
     logger: &Logger,
+) -> Vec<BlockData> {
+    identify_safe_blocks_impl(ledger, blocks, true, logger)
+}
+
+/// Identify safe blocks, optionally skipping the block ID check for blocks
+/// whose IDs have already been validated against their contents.
+fn identify_safe_blocks_impl<L: Ledger>(
+    ledger: &L,
+    blocks: &[BlockData],
+    check_block_ids: bool,
+    logger: &Logger,
 ) -> Vec<BlockData> {
     // The highest block externalized by the local node.
     let highest_local_block = ledger
@@ -823,22 +925,24 @@ pub fn identify_safe_blocks<L: Ledger>(
 
         // The block's ID must agree with the merkle hash of its transactions.
         let block_contents = block_data.contents();
-        let derived_block_id = compute_block_id(
-            block.version,
-            &block.parent_id,
-            block.index,
-            block.cumulative_txo_count,
-            &block.root_element,
-            &block_contents.hash(),
-        );
-        if block.id != derived_block_id {
-            log::error!(
-                logger,
-                "Block ids do not match. Block: {:?}, derived block ID: {:?}",
-                block,
-                derived_block_id
+        if check_block_ids {
+            let derived_block_id = compute_block_id(
+                block.version,
+                &block.parent_id,
+                block.index,
+                block.cumulative_txo_count,
+                &block.root_element,
+                &block_contents.hash(),
             );
-            break;
+            if block.id != derived_block_id {
+                log::error!(
+                    logger,
+                    "Block ids do not match. Block: {:?}, derived block ID: {:?}",
+                    block,
+                    derived_block_id
+                );
+                break;
+            }
         }
 
         // No key images in the block may have been previously seen.
@@ -3,7 +3,9 @@
 //! An integration between `PollingNetworkState` and `LedgerSyncService` that
 //! performs the sync in a background thread.
 
-use crate::{LedgerSync, LedgerSyncService, PollingNetworkState, TransactionsFetcher};
+use crate::{
+    LedgerSync, LedgerSyncService, PipelineConfig, PollingNetworkState, TransactionsFetcher,
+};
 use mc_common::logger::{log, Logger};
 use mc_connection::{BlockchainConnection, ConnectionManager};
 use mc_ledger_db::Ledger;
@@ -36,14 +38,16 @@ impl LedgerSyncServiceThread {
         network_state: Arc<RwLock<PollingNetworkState<BC>>>,
         transactions_fetcher: TF,
         poll_interval: Duration,
+        pipeline_config: Option<PipelineConfig>,
         logger: Logger,
     ) -> Self {
-        let ledger_sync_service = LedgerSyncService::new(
+        let mut ledger_sync_service = LedgerSyncService::new(
             ledger.clone(),
             manager,
             transactions_fetcher,
             logger.clone(),
         );
+        ledger_sync_service.set_pipeline_config(pipeline_config);
 
         let currently_behind = Arc::new(AtomicBool::new(false));
         let stop_requested = Arc::new(AtomicBool::new(false));
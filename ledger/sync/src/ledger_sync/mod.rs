@@ -1,8 +1,10 @@
+mod block_pipeline;
 mod ledger_sync_error;
 mod ledger_sync_service;
 mod ledger_sync_service_thread;
 mod ledger_sync_trait;
 
+pub use block_pipeline::{validate_block_data, BlockPipeline, PipelineConfig};
 pub use ledger_sync_error::LedgerSyncError;
 pub use ledger_sync_service::{identify_safe_blocks, LedgerSyncService};
 pub use ledger_sync_service_thread::LedgerSyncServiceThread;
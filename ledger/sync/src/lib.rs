@@ -11,8 +11,8 @@ pub mod test_utils;
 
 pub use crate::{
     ledger_sync::{
-        identify_safe_blocks, LedgerSync, LedgerSyncError, LedgerSyncService,
-        LedgerSyncServiceThread, MockLedgerSync,
+        identify_safe_blocks, validate_block_data, BlockPipeline, LedgerSync, LedgerSyncError,
+        LedgerSyncService, LedgerSyncServiceThread, MockLedgerSync, PipelineConfig,
     },
     metadata_provider::{BlockMetadataProvider, PassThroughMetadataProvider},
     network_state::{NetworkState, PollingNetworkState, SCPNetworkState},
@@ -6,14 +6,19 @@
 
 use crate::transactions_fetcher_trait::{TransactionFetcherError, TransactionsFetcher};
 use displaydoc::Display;
-use mc_api::{block_num_to_s3block_path, blockchain, merged_block_num_to_s3block_path};
+use mc_api::{
+    archive_bundle_path, block_num_to_s3block_path, blockchain, merged_block_num_to_s3block_path,
+    ARCHIVE_BUNDLE_FORMAT_VERSION, ARCHIVE_BUNDLE_MANIFEST_PATH,
+};
 use mc_blockchain_types::{Block, BlockData, BlockIndex};
 use mc_common::{
     logger::{log, Logger},
     lru::LruCache,
-    ResponderId,
+    HashMap, ResponderId,
 };
+use mc_crypto_hashes::{Blake2b256, Digest};
 use protobuf::Message;
+use rand::distributions::{Distribution, WeightedIndex};
 use reqwest::Error as ReqwestError;
 use std::{
     fs,
@@ -21,6 +26,7 @@ use std::{
         atomic::{AtomicU64, Ordering},
         Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 use url::Url;
 
@@ -35,6 +41,75 @@ pub const DEFAULT_MERGED_BLOCKS_BUCKET_SIZES: &[u64] = &[10000, 1000, 100];
 /// Maximum number of pre-fetched blocks to keep in cache.
 pub const MAX_PREFETCHED_BLOCKS: usize = 10000;
 
+/// How long an archive bundle manifest is used before it is fetched again,
+/// when it does not cover the requested block. This also applies to sources
+/// that have no manifest, or whose bundles failed to verify.
+pub const BUNDLE_MANIFEST_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum uncompressed size of an archive bundle that we are willing to
+/// decompress.
+pub const MAX_BUNDLE_UNCOMPRESSED_SIZE: u64 = 1 << 30;
+
+/// Weight of the most recent request in a source's health score.
+const SOURCE_HEALTH_SMOOTHING: f64 = 0.2;
+
+/// Minimum selection weight of a source, so that unhealthy sources keep
+/// getting the occasional request and can recover.
+const MIN_SOURCE_WEIGHT: f64 = 0.02;
+
+/// Health of a source URL, based on recent requests to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceHealth {
+    /// Exponentially weighted success rate of recent requests, between 0 and
+    /// 1. Sources start out healthy.
+    pub score: f64,
+
+    /// Exponentially weighted latency of recent successful requests.
+    pub latency: Duration,
+
+    /// Number of successful requests.
+    pub num_successes: u64,
+
+    /// Number of failed requests.
+    pub num_failures: u64,
+}
+
+impl Default for SourceHealth {
+    fn default() -> Self {
+        Self {
+            score: 1.0,
+            latency: Duration::ZERO,
+            num_successes: 0,
+            num_failures: 0,
+        }
+    }
+}
+
+impl SourceHealth {
+    /// Record the outcome of a request.
+    fn record(&mut self, success: bool, elapsed: Duration) {
+        let outcome = if success { 1.0 } else { 0.0 };
+        self.score += SOURCE_HEALTH_SMOOTHING * (outcome - self.score);
+        if success {
+            self.num_successes += 1;
+            self.latency = if self.num_successes == 1 {
+                elapsed
+            } else {
+                self.latency.mul_f64(1.0 - SOURCE_HEALTH_SMOOTHING)
+                    + elapsed.mul_f64(SOURCE_HEALTH_SMOOTHING)
+            };
+        } else {
+            self.num_failures += 1;
+        }
+    }
+
+    /// How likely this source is to be picked for the next request. Healthy
+    /// and fast sources are preferred.
+    fn weight(&self) -> f64 {
+        self.score.max(MIN_SOURCE_WEIGHT) / (1.0 + self.latency.as_secs_f64())
+    }
+}
+
 #[derive(Debug, Display)]
 pub enum ReqwestTransactionsFetcherError {
     /// Url parse error on {0}: {1}
@@ -51,6 +126,9 @@ pub enum ReqwestTransactionsFetcherError {
 
     /// No URLs configured
     NoUrlsConfigured,
+
+    /// Received an invalid archive bundle from {0}: {1}
+    InvalidBundleReceived(String, String),
 }
 
 impl From<ReqwestError> for ReqwestTransactionsFetcherError {
@@ -75,8 +153,9 @@ pub struct ReqwestTransactionsFetcher {
     /// Logger.
     logger: Logger,
 
-    /// The most recently used URL index (in `source_urls`).
-    source_index_counter: Arc<AtomicU64>,
+    /// Health of each of the `source_urls`, used to pick the source of each
+    /// request.
+    source_health: Arc<Mutex<Vec<SourceHealth>>>,
 
     /// Cache mapping a `BlockIndex` to `BlockData`, filled by merged blocks
     /// when possible.
@@ -85,6 +164,12 @@ pub struct ReqwestTransactionsFetcher {
     /// Merged blocks bucket sizes to attempt fetching.
     merged_blocks_bucket_sizes: Vec<u64>,
 
+    /// Whether to attempt fetching compressed archive bundles.
+    archive_bundles_enabled: bool,
+
+    /// The most recently fetched archive bundle manifest of each source URL.
+    bundle_manifests: Arc<Mutex<HashMap<Url, CachedBundleManifest>>>,
+
     /// Number of successful cache hits when attempting ot get block data.
     /// Used for debugging purposes.
     hits: Arc<AtomicU64>,
@@ -123,13 +208,18 @@ impl ReqwestTransactionsFetcher {
             })
             .collect();
 
+        let source_urls = source_urls?;
+        let source_health = vec![SourceHealth::default(); source_urls.len()];
+
         Ok(Self {
-            source_urls: source_urls?,
+            source_urls,
             client,
             logger,
-            source_index_counter: Arc::new(AtomicU64::new(0)),
+            source_health: Arc::new(Mutex::new(source_health)),
             blocks_cache: Arc::new(Mutex::new(LruCache::new(MAX_PREFETCHED_BLOCKS))),
             merged_blocks_bucket_sizes: DEFAULT_MERGED_BLOCKS_BUCKET_SIZES.to_vec(),
+            archive_bundles_enabled: true,
+            bundle_manifests: Arc::new(Mutex::new(HashMap::default())),
             hits: Arc::new(AtomicU64::new(0)),
             misses: Arc::new(AtomicU64::new(0)),
         })
@@ -139,6 +229,51 @@ impl ReqwestTransactionsFetcher {
         self.merged_blocks_bucket_sizes = bucket_sizes.to_vec();
     }
 
+    /// Enable or disable fetching compressed archive bundles. They are enabled
+    /// by default, and only used for sources that publish a bundle manifest.
+    pub fn set_archive_bundles_enabled(&mut self, enabled: bool) {
+        self.archive_bundles_enabled = enabled;
+    }
+
+    /// The health of each source URL.
+    pub fn source_health(&self) -> Vec<(Url, SourceHealth)> {
+        let source_health = self.source_health.lock().expect("mutex poisoned");
+        self.source_urls
+            .iter()
+            .cloned()
+            .zip(source_health.iter().cloned())
+            .collect()
+    }
+
+    /// Pick the source of the next request, at random, weighted by the health
+    /// of each source.
+    fn select_source(&self) -> Result<usize, ReqwestTransactionsFetcherError> {
+        let weights = self
+            .source_health
+            .lock()
+            .expect("mutex poisoned")
+            .iter()
+            .map(SourceHealth::weight)
+            .collect::<Vec<_>>();
+        let distribution = WeightedIndex::new(&weights)
+            .map_err(|_| ReqwestTransactionsFetcherError::NoUrlsConfigured)?;
+        Ok(distribution.sample(&mut rand::thread_rng()))
+    }
+
+    fn record_source_result(&self, source_index: usize, success: bool, elapsed: Duration) {
+        let mut source_health = self.source_health.lock().expect("mutex poisoned");
+        let health = &mut source_health[source_index];
+        health.record(success, elapsed);
+        if !success {
+            log::debug!(
+                self.logger,
+                "Request to {} failed, health score is now {:.2}",
+                self.source_urls[source_index],
+                health.score
+            );
+        }
+    }
+
     pub fn block_from_url(&self, url: &Url) -> Result<BlockData, ReqwestTransactionsFetcherError> {
         let archive_block: blockchain::ArchiveBlock = self.fetch_protobuf_object(url)?;
 
@@ -167,10 +302,186 @@ impl ReqwestTransactionsFetcher {
         self.get_block_data_by_index(0, None)
     }
 
+    /// Fetches the compressed archive bundle described by a manifest entry,
+    /// verifying its hash before decompressing it.
+    pub fn blocks_from_bundle(
+        &self,
+        source_url: &Url,
+        bundle_size: u64,
+        entry: &blockchain::ArchiveBundleEntry,
+    ) -> Result<Vec<BlockData>, ReqwestTransactionsFetcherError> {
+        // Bundle paths are derived from the block range rather than taken from the
+        // manifest, so that a manifest can not point us elsewhere.
+        let filename = archive_bundle_path(bundle_size, entry.get_first_block_index())
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        let url = source_url
+            .join(&filename)
+            .map_err(|e| ReqwestTransactionsFetcherError::UrlParse(filename.clone(), e))?;
+        let invalid_bundle = |reason: String| {
+            ReqwestTransactionsFetcherError::InvalidBundleReceived(url.to_string(), reason)
+        };
+
+        if entry.get_path() != filename {
+            return Err(invalid_bundle(format!(
+                "unexpected path {}",
+                entry.get_path()
+            )));
+        }
+        if entry.get_uncompressed_size() > MAX_BUNDLE_UNCOMPRESSED_SIZE {
+            return Err(invalid_bundle(format!(
+                "uncompressed size {} is too large",
+                entry.get_uncompressed_size()
+            )));
+        }
+
+        let bytes = self.fetch_bytes(&url)?;
+        if bytes.len() as u64 != entry.get_compressed_size()
+            || Blake2b256::digest(&bytes).as_slice() != entry.get_hash()
+        {
+            return Err(invalid_bundle("hash mismatch".to_string()));
+        }
+
+        let uncompressed =
+            zstd::bulk::decompress(&bytes, entry.get_uncompressed_size() as usize)
+                .map_err(|err| invalid_bundle(format!("decompression failed: {}", err)))?;
+        let archive_blocks = blockchain::ArchiveBlocks::parse_from_bytes(&uncompressed)
+            .map_err(|err| invalid_bundle(format!("protobuf parse failed: {:?}", err)))?;
+        let blocks_data = Vec::<BlockData>::try_from(&archive_blocks)
+            .map_err(|err| invalid_bundle(err.to_string()))?;
+
+        let expected_indexes =
+            entry.get_first_block_index()..entry.get_first_block_index() + entry.get_num_blocks();
+        if blocks_data.len() as u64 != entry.get_num_blocks()
+            || !blocks_data
+                .iter()
+                .zip(expected_indexes)
+                .all(|(block_data, index)| block_data.block().index == index)
+        {
+            return Err(invalid_bundle("unexpected blocks".to_string()));
+        }
+
+        Ok(blocks_data)
+    }
+
+    /// Find the archive bundle that contains a given block, according to the
+    /// manifest of a source URL. The manifest is fetched again if it does not
+    /// cover the block and is older than `BUNDLE_MANIFEST_REFRESH_INTERVAL`.
+    ///
+    /// Returns the bundle size and the bundle's manifest entry.
+    fn get_bundle_entry(
+        &self,
+        source_url: &Url,
+        block_index: BlockIndex,
+    ) -> Option<(u64, blockchain::ArchiveBundleEntry)> {
+        let find_entry = |manifest: &blockchain::ArchiveBundleManifest| {
+            let bundles = manifest.get_bundles();
+            let position =
+                bundles.partition_point(|entry| entry.get_first_block_index() <= block_index);
+            let entry = bundles.get(position.checked_sub(1)?)?;
+            if block_index < entry.get_first_block_index() + entry.get_num_blocks() {
+                Some((manifest.get_bundle_size(), entry.clone()))
+            } else {
+                None
+            }
+        };
+
+        if let Some(cached) = self
+            .bundle_manifests
+            .lock()
+            .expect("mutex poisoned")
+            .get(source_url)
+        {
+            let found = cached.manifest.as_ref().and_then(find_entry);
+            if found.is_some() || cached.fetched_at.elapsed() < BUNDLE_MANIFEST_REFRESH_INTERVAL {
+                return found;
+            }
+        }
+
+        let manifest = match self.fetch_bundle_manifest(source_url) {
+            Ok(manifest) => Some(manifest),
+            Err(err) => {
+                log::debug!(
+                    self.logger,
+                    "No archive bundle manifest available at {}: {}",
+                    source_url,
+                    err
+                );
+                None
+            }
+        };
+        let found = manifest.as_ref().and_then(find_entry);
+        self.bundle_manifests
+            .lock()
+            .expect("mutex poisoned")
+            .insert(
+                source_url.clone(),
+                CachedBundleManifest {
+                    fetched_at: Instant::now(),
+                    manifest,
+                },
+            );
+        found
+    }
+
+    fn fetch_bundle_manifest(
+        &self,
+        source_url: &Url,
+    ) -> Result<blockchain::ArchiveBundleManifest, ReqwestTransactionsFetcherError> {
+        let url = source_url.join(ARCHIVE_BUNDLE_MANIFEST_PATH).map_err(|e| {
+            ReqwestTransactionsFetcherError::UrlParse(ARCHIVE_BUNDLE_MANIFEST_PATH.to_string(), e)
+        })?;
+        let manifest: blockchain::ArchiveBundleManifest = self.fetch_protobuf_object(&url)?;
+
+        if manifest.get_format_version() != ARCHIVE_BUNDLE_FORMAT_VERSION
+            || manifest.get_compression() != "zstd"
+            || manifest.get_bundle_size() == 0
+        {
+            return Err(ReqwestTransactionsFetcherError::InvalidBundleReceived(
+                url.to_string(),
+                format!(
+                    "unsupported manifest: version {}, compression {:?}, bundle size {}",
+                    manifest.get_format_version(),
+                    manifest.get_compression(),
+                    manifest.get_bundle_size()
+                ),
+            ));
+        }
+
+        Ok(manifest)
+    }
+
+    /// Stop using the archive bundles of a source URL until its manifest is
+    /// next refreshed.
+    fn disable_bundles(&self, source_url: &Url) {
+        let mut bundle_manifests = self.bundle_manifests.lock().expect("mutex poisoned");
+        bundle_manifests.insert(
+            source_url.clone(),
+            CachedBundleManifest {
+                fetched_at: Instant::now(),
+                manifest: None,
+            },
+        );
+    }
+
     fn fetch_protobuf_object<M: Message>(
         &self,
         url: &Url,
     ) -> Result<M, ReqwestTransactionsFetcherError> {
+        let bytes = self.fetch_bytes(url)?;
+
+        let obj = M::parse_from_bytes(&bytes).map_err(|err| {
+            ReqwestTransactionsFetcherError::InvalidBlockReceived(
+                url.to_string(),
+                format!("protobuf parse failed: {:?}", err),
+            )
+        })?;
+
+        Ok(obj)
+    }
+
+    fn fetch_bytes(&self, url: &Url) -> Result<Vec<u8>, ReqwestTransactionsFetcherError> {
         // Special treatment for file:// to read from a local directory.
         let bytes: Vec<u8> = if url.scheme() == "file" {
             let path = &url[url::Position::BeforeHost..url::Position::AfterPath];
@@ -187,14 +498,7 @@ impl ReqwestTransactionsFetcher {
             bytes
         };
 
-        let obj = M::parse_from_bytes(&bytes).map_err(|err| {
-            ReqwestTransactionsFetcherError::InvalidBlockReceived(
-                url.to_string(),
-                format!("protobuf parse failed: {:?}", err),
-            )
-        })?;
-
-        Ok(obj)
+        Ok(bytes)
     }
 
     fn get_cached_block_data(
@@ -262,9 +566,60 @@ impl ReqwestTransactionsFetcher {
         }
 
         // Get the source to fetch from.
-        let source_index_counter =
-            self.source_index_counter.fetch_add(1, Ordering::SeqCst) as usize;
-        let source_url = &self.source_urls[source_index_counter % self.source_urls.len()];
+        let source_index = self.select_source()?;
+        let source_url = &self.source_urls[source_index];
+
+        let start = Instant::now();
+        let result = self.get_block_data_from_source(source_url, block_index, expected_block);
+        self.record_source_result(source_index, result.is_ok(), start.elapsed());
+        result
+    }
+
+    fn get_block_data_from_source(
+        &self,
+        source_url: &Url,
+        block_index: BlockIndex,
+        expected_block: Option<&Block>,
+    ) -> Result<BlockData, ReqwestTransactionsFetcherError> {
+        // Try and fetch the archive bundle containing this block, if the source has
+        // one.
+        if self.archive_bundles_enabled {
+            if let Some((bundle_size, entry)) = self.get_bundle_entry(source_url, block_index) {
+                match self.blocks_from_bundle(source_url, bundle_size, &entry) {
+                    Ok(blocks_data) => {
+                        log::debug!(
+                            self.logger,
+                            "Got an archive bundle for #{}: {} entries",
+                            block_index,
+                            blocks_data.len()
+                        );
+
+                        {
+                            let mut blocks_cache =
+                                self.blocks_cache.lock().expect("mutex poisoned");
+                            for block_data in blocks_data.into_iter() {
+                                blocks_cache.put(block_data.block().index, block_data);
+                            }
+                        }
+
+                        if let Some(cached_block_data) =
+                            self.get_cached_block_data(block_index, expected_block)
+                        {
+                            return Ok(cached_block_data);
+                        }
+                    }
+                    Err(err) => {
+                        log::warn!(
+                            self.logger,
+                            "Failed fetching archive bundle for #{}, falling back to individual blocks: {}",
+                            block_index,
+                            err
+                        );
+                        self.disable_bundles(source_url);
+                    }
+                }
+            }
+        }
 
         // Try and fetch a merged block if we stand a chance of finding one.
         for bucket in self.merged_blocks_bucket_sizes.iter() {
@@ -355,6 +710,15 @@ impl ReqwestTransactionsFetcher {
     }
 }
 
+/// An archive bundle manifest, and when it was fetched.
+#[derive(Clone)]
+struct CachedBundleManifest {
+    fetched_at: Instant,
+
+    /// None if the source has no valid manifest.
+    manifest: Option<blockchain::ArchiveBundleManifest>,
+}
+
 impl TransactionsFetcher for ReqwestTransactionsFetcher {
     type Error = ReqwestTransactionsFetcherError;
 
@@ -366,3 +730,201 @@ impl TransactionsFetcher for ReqwestTransactionsFetcher {
         self.get_block_data_by_index(block.index, Some(block))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mc_blockchain_test_utils::get_blocks;
+    use mc_blockchain_types::BlockVersion;
+    use mc_common::logger::test_with_logger;
+    use mc_util_test_helper::get_seeded_rng;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    fn write_file(root: &Path, path: impl AsRef<Path>, bytes: &[u8]) {
+        let dest = root.join(path);
+        fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        fs::write(dest, bytes).unwrap();
+    }
+
+    /// Writes the given blocks as a single archive bundle, and returns its
+    /// manifest.
+    fn write_bundle(root: &Path, blocks_data: &[BlockData]) -> blockchain::ArchiveBundleManifest {
+        let bundle_size = blocks_data.len() as u64;
+        let first_block_index = blocks_data[0].block().index;
+        let uncompressed = blockchain::ArchiveBlocks::from(blocks_data)
+            .write_to_bytes()
+            .unwrap();
+        let bytes = zstd::encode_all(&uncompressed[..], 3).unwrap();
+        let path = archive_bundle_path(bundle_size, first_block_index);
+        write_file(root, &path, &bytes);
+
+        let mut entry = blockchain::ArchiveBundleEntry::new();
+        entry.set_first_block_index(first_block_index);
+        entry.set_num_blocks(bundle_size);
+        entry.set_path(path.to_str().unwrap().to_string());
+        entry.set_hash(Blake2b256::digest(&bytes).to_vec());
+        entry.set_compressed_size(bytes.len() as u64);
+        entry.set_uncompressed_size(uncompressed.len() as u64);
+
+        let mut manifest = blockchain::ArchiveBundleManifest::new();
+        manifest.set_format_version(ARCHIVE_BUNDLE_FORMAT_VERSION);
+        manifest.set_compression("zstd".to_string());
+        manifest.set_bundle_size(bundle_size);
+        manifest.mut_bundles().push(entry);
+        write_file(
+            root,
+            ARCHIVE_BUNDLE_MANIFEST_PATH,
+            &manifest.write_to_bytes().unwrap(),
+        );
+        manifest
+    }
+
+    fn write_blocks(root: &Path, blocks_data: &[BlockData]) {
+        for block_data in blocks_data {
+            write_file(
+                root,
+                block_num_to_s3block_path(block_data.block().index),
+                &blockchain::ArchiveBlock::from(block_data)
+                    .write_to_bytes()
+                    .unwrap(),
+            );
+        }
+    }
+
+    fn create_fetcher(root: &Path, logger: Logger) -> ReqwestTransactionsFetcher {
+        let mut fetcher =
+            ReqwestTransactionsFetcher::new(vec![format!("file://{}", root.display())], logger)
+                .unwrap();
+        fetcher.set_merged_blocks_bucket_sizes(&[]);
+        fetcher
+    }
+
+    #[test_with_logger]
+    // Blocks covered by an archive bundle should be fetched from it, and the
+    // remaining blocks individually.
+    fn test_fetch_from_archive_bundle(logger: Logger) {
+        let blocks = get_blocks(
+            BlockVersion::MAX,
+            6,
+            2,
+            1,
+            1,
+            1 << 20,
+            None,
+            &mut get_seeded_rng(),
+        );
+        let temp_dir = TempDir::new("archive").unwrap();
+
+        // Blocks 0-3 are only available in the bundle.
+        write_bundle(temp_dir.path(), &blocks[..4]);
+        write_blocks(temp_dir.path(), &blocks[4..]);
+
+        let fetcher = create_fetcher(temp_dir.path(), logger);
+        for block_data in &blocks {
+            assert_eq!(
+                *block_data,
+                fetcher
+                    .get_block_data_by_index(block_data.block().index, Some(block_data.block()))
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test_with_logger]
+    // A bundle that fails verification should not be used, and blocks should be
+    // fetched individually instead.
+    fn test_invalid_archive_bundle_falls_back(logger: Logger) {
+        let blocks = get_blocks(
+            BlockVersion::MAX,
+            6,
+            2,
+            1,
+            1,
+            1 << 20,
+            None,
+            &mut get_seeded_rng(),
+        );
+        let temp_dir = TempDir::new("archive").unwrap();
+
+        write_blocks(temp_dir.path(), &blocks);
+        let mut manifest = write_bundle(temp_dir.path(), &blocks[..4]);
+        manifest.mut_bundles()[0].set_hash(vec![0; 32]);
+        write_file(
+            temp_dir.path(),
+            ARCHIVE_BUNDLE_MANIFEST_PATH,
+            &manifest.write_to_bytes().unwrap(),
+        );
+
+        let fetcher = create_fetcher(temp_dir.path(), logger);
+        assert!(matches!(
+            fetcher.blocks_from_bundle(&fetcher.source_urls[0], 4, &manifest.get_bundles()[0]),
+            Err(ReqwestTransactionsFetcherError::InvalidBundleReceived(_, _))
+        ));
+        for block_data in &blocks {
+            assert_eq!(
+                *block_data,
+                fetcher
+                    .get_block_data_by_index(block_data.block().index, Some(block_data.block()))
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test_with_logger]
+    // Requests should go to healthy sources, and be recorded in their health.
+    fn test_source_health(logger: Logger) {
+        let blocks = get_blocks(
+            BlockVersion::MAX,
+            3,
+            2,
+            1,
+            1,
+            1 << 20,
+            None,
+            &mut get_seeded_rng(),
+        );
+        let temp_dir = TempDir::new("archive").unwrap();
+        write_blocks(temp_dir.path(), &blocks);
+
+        let mut fetcher = ReqwestTransactionsFetcher::new(
+            vec![
+                format!("file://{}", temp_dir.path().display()),
+                format!("file://{}/missing", temp_dir.path().display()),
+            ],
+            logger,
+        )
+        .unwrap();
+        fetcher.set_merged_blocks_bucket_sizes(&[]);
+        fetcher.set_archive_bundles_enabled(false);
+
+        // The second source has none of the blocks.
+        for _ in 0..10 {
+            fetcher.record_source_result(1, false, Duration::ZERO);
+        }
+        let health = fetcher.source_health();
+        assert_eq!(health[1].1.num_failures, 10);
+        assert!(health[1].1.score < 0.2);
+
+        let num_first_source = (0..1000)
+            .filter(|_| fetcher.select_source().unwrap() == 0)
+            .count();
+        assert!(num_first_source > 900, "{}", num_first_source);
+
+        // Every block is eventually fetched from the healthy source.
+        for block_data in &blocks {
+            let fetched = (0..100)
+                .find_map(|_| {
+                    fetcher
+                        .get_block_data_by_index(block_data.block().index, Some(block_data.block()))
+                        .ok()
+                })
+                .unwrap();
+            assert_eq!(&fetched, block_data);
+        }
+        let health = fetcher.source_health();
+        assert_eq!(health[0].1.num_successes, blocks.len() as u64);
+        assert_eq!(health[0].1.num_failures, 0);
+        assert_eq!(health[0].1.score, 1.0);
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+#![deny(missing_docs)]
+#![doc = include_str!("../README.md")]
+#![forbid(unsafe_code)]
+
+use clap::Parser;
+use mc_common::logger::{create_app_logger, log, o};
+use mc_ledger_db::{Ledger, LedgerDB};
+use std::path::PathBuf;
+
+/// Command line configuration.
+#[derive(Debug, Parser)]
+#[clap(
+    name = "mc-ledger-truncate",
+    about = "Remove all blocks after a given block from a ledger db."
+)]
+pub struct Config {
+    /// Path to ledger db (lmdb).
+    #[clap(long, env = "MC_LEDGER_DB")]
+    pub ledger_db: PathBuf,
+
+    /// Index of the last block to keep.
+    #[clap(long, env = "MC_BLOCK_INDEX")]
+    pub block_index: u64,
+}
+
+fn main() {
+    mc_common::setup_panic_handler();
+    let (logger, _global_logger_guard) = create_app_logger(o!());
+    let config = Config::parse();
+
+    let mut ledger_db = LedgerDB::open(&config.ledger_db).expect("Could not open ledger_db");
+    let num_blocks = ledger_db.num_blocks().expect("Could not read num_blocks");
+
+    ledger_db
+        .truncate_to(config.block_index)
+        .expect("Failed truncating ledger_db");
+
+    let last_block = ledger_db
+        .get_latest_block()
+        .expect("Could not read last block");
+    log::info!(
+        logger,
+        "Removed {} blocks from {:?}, last block is now {} with ID {}",
+        num_blocks - ledger_db.num_blocks().expect("Could not read num_blocks"),
+        config.ledger_db,
+        last_block.index,
+        last_block.id
+    );
+}
@@ -0,0 +1,565 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+#![deny(missing_docs)]
+
+//! Re-checks the integrity of a ledger from the origin block onwards.
+//!
+//! Every block is re-hashed and compared against what the ledger stores about
+//! it: block ids, contents hashes, parent chaining, cumulative TxOut counts and
+//! the TxOut Merkle root each block was validated against. The indexes that
+//! the ledger keeps alongside the blocks (TxOuts by index, hash and public key,
+//! block index by TxOut index, key images) are checked against the block
+//! contents, and block signatures and metadata are verified. Verification
+//! stops at the first inconsistency, which is described in the returned
+//! [VerificationReport].
+
+use mc_blockchain_types::{Block, BlockID, BlockIndex, BlockVersion};
+use mc_blockchain_validators::MetadataValidator;
+use mc_common::logger::{log, Logger};
+use mc_ledger_db::{Error as LedgerError, Ledger};
+use mc_transaction_core::{
+    membership_proofs::{hash_leaf, hash_nodes, Range, NIL_HASH},
+    tx::TxOutMembershipElement,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// How often progress is logged, in blocks.
+const LOG_INTERVAL: u64 = 10_000;
+
+/// The outcome of verifying a ledger.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct VerificationReport {
+    /// The number of blocks in the ledger.
+    pub num_blocks: u64,
+
+    /// The number of blocks that passed all checks.
+    pub num_blocks_verified: u64,
+
+    /// The number of TxOuts in the blocks that passed all checks.
+    pub num_tx_outs_verified: u64,
+
+    /// The first inconsistency found, if any.
+    pub first_inconsistency: Option<Inconsistency>,
+}
+
+impl VerificationReport {
+    /// Whether the whole ledger passed verification.
+    pub fn is_ok(&self) -> bool {
+        self.first_inconsistency.is_none()
+    }
+}
+
+/// A mismatch between the ledger and what it should contain.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Inconsistency {
+    /// The index of the block being verified when the inconsistency was found.
+    pub block_index: BlockIndex,
+
+    /// The check that failed.
+    pub check: Check,
+
+    /// A human readable description of the inconsistency.
+    pub details: String,
+}
+
+/// The checks performed by the verifier.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// The block and its contents can be read.
+    ReadBlock,
+    /// The block's index matches its position in the ledger.
+    BlockIndex,
+    /// The block's parent id is the id of the previous block.
+    ParentId,
+    /// The block's id matches the hash of the block.
+    BlockId,
+    /// The block's contents hash matches the hash of its contents.
+    ContentsHash,
+    /// The block's cumulative TxOut count matches its outputs.
+    CumulativeTxoCount,
+    /// The block's root element is the Merkle root of all previous TxOuts.
+    RootElement,
+    /// The TxOut indexes agree with the block's outputs.
+    TxOutIndex,
+    /// The key image indexes agree with the block's key images.
+    KeyImageIndex,
+    /// The block signature is valid.
+    BlockSignature,
+    /// The block metadata is valid.
+    BlockMetadata,
+    /// The total number of TxOuts matches the last block.
+    NumTxOuts,
+}
+
+/// Verify every block of a ledger.
+///
+/// # Arguments
+/// * `ledger` - The ledger to verify.
+/// * `metadata_validator` - If provided, block metadata must be signed by a key
+///   that is valid for the block. Otherwise, only metadata signatures are
+///   checked.
+/// * `logger` - Logger.
+///
+/// Returns an error only if the number of blocks in the ledger cannot be
+/// read. Any other failure to read the ledger is reported as an
+/// inconsistency.
+pub fn verify_ledger<L: Ledger>(
+    ledger: &L,
+    metadata_validator: Option<&MetadataValidator>,
+    logger: &Logger,
+) -> Result<VerificationReport, LedgerError> {
+    let num_blocks = ledger.num_blocks()?;
+    let mut verifier = Verifier {
+        ledger,
+        metadata_validator,
+        tx_out_tree: MerkleFrontier::default(),
+        prev_block: None,
+    };
+
+    let mut report = VerificationReport {
+        num_blocks,
+        num_blocks_verified: 0,
+        num_tx_outs_verified: 0,
+        first_inconsistency: None,
+    };
+
+    for block_index in 0..num_blocks {
+        if let Err(inconsistency) = verifier.verify_block(block_index) {
+            log::error!(
+                logger,
+                "Block {} failed {:?} check: {}",
+                block_index,
+                inconsistency.check,
+                inconsistency.details
+            );
+            report.first_inconsistency = Some(inconsistency);
+            return Ok(report);
+        }
+        report.num_blocks_verified += 1;
+        report.num_tx_outs_verified = verifier.tx_out_tree.num_leaves;
+
+        if (block_index + 1) % LOG_INTERVAL == 0 {
+            log::info!(
+                logger,
+                "Verified {} of {} blocks",
+                block_index + 1,
+                num_blocks
+            );
+        }
+    }
+
+    if let Err(inconsistency) = verifier.verify_num_tx_outs(num_blocks) {
+        report.first_inconsistency = Some(inconsistency);
+    }
+
+    Ok(report)
+}
+
+struct Verifier<'a, L: Ledger> {
+    ledger: &'a L,
+    metadata_validator: Option<&'a MetadataValidator>,
+    /// The Merkle tree of all TxOuts in the blocks verified so far.
+    tx_out_tree: MerkleFrontier,
+    /// The last block that was verified.
+    prev_block: Option<Block>,
+}
+
+impl<'a, L: Ledger> Verifier<'a, L> {
+    fn verify_block(&mut self, block_index: BlockIndex) -> Result<(), Inconsistency> {
+        let fail = |check: Check, details: String| Inconsistency {
+            block_index,
+            check,
+            details,
+        };
+        let read_error = |what: &str, err: LedgerError| {
+            fail(
+                Check::ReadBlock,
+                format!("Failed reading {}: {}", what, err),
+            )
+        };
+
+        let block = self
+            .ledger
+            .get_block(block_index)
+            .map_err(|err| read_error("block", err))?;
+        let contents = self
+            .ledger
+            .get_block_contents(block_index)
+            .map_err(|err| read_error("block contents", err))?;
+
+        ensure_eq(&block.index, &block_index, |details| {
+            fail(Check::BlockIndex, details)
+        })?;
+
+        let expected_parent_id = self
+            .prev_block
+            .as_ref()
+            .map_or_else(BlockID::default, |prev_block| prev_block.id.clone());
+        ensure_eq(&block.parent_id, &expected_parent_id, |details| {
+            fail(Check::ParentId, details)
+        })?;
+
+        if !block.is_block_id_valid() {
+            return Err(fail(
+                Check::BlockId,
+                format!("Block id {:?} does not match the block", block.id),
+            ));
+        }
+
+        ensure_eq(&block.contents_hash, &contents.hash(), |details| {
+            fail(Check::ContentsHash, details)
+        })?;
+
+        let num_prev_tx_outs = self.tx_out_tree.num_leaves;
+        ensure_eq(
+            &block.cumulative_txo_count,
+            &(num_prev_tx_outs + contents.outputs.len() as u64),
+            |details| fail(Check::CumulativeTxoCount, details),
+        )?;
+
+        // The origin block is not validated against anything. Test ledgers also
+        // leave the root element empty, so only a non-default root element is
+        // compared against the TxOuts that precede the block.
+        if block_index == 0 || block.root_element != TxOutMembershipElement::default() {
+            let expected_root_element = if block_index == 0 {
+                TxOutMembershipElement::default()
+            } else {
+                self.tx_out_tree.root_element()
+            };
+            ensure_eq(&block.root_element, &expected_root_element, |details| {
+                fail(Check::RootElement, details)
+            })?;
+        }
+
+        for (i, tx_out) in contents.outputs.iter().enumerate() {
+            let tx_out_index = num_prev_tx_outs + i as u64;
+            let index_fail = |what: &str, expected: u64, result: Result<u64, LedgerError>| {
+                fail(
+                    Check::TxOutIndex,
+                    format!(
+                        "TxOut {} {}: expected {}, got {:?}",
+                        tx_out_index, what, expected, result
+                    ),
+                )
+            };
+
+            match self
+                .ledger
+                .get_tx_out_index_by_public_key(&tx_out.public_key)
+            {
+                Ok(index) if index == tx_out_index => {}
+                result => return Err(index_fail("index by public key", tx_out_index, result)),
+            }
+            match self.ledger.get_tx_out_index_by_hash(&tx_out.hash()) {
+                Ok(index) if index == tx_out_index => {}
+                result => return Err(index_fail("index by hash", tx_out_index, result)),
+            }
+            match self.ledger.get_block_index_by_tx_out_index(tx_out_index) {
+                Ok(index) if index == block_index => {}
+                result => return Err(index_fail("block index", block_index, result)),
+            }
+            match self.ledger.get_tx_out_by_index(tx_out_index) {
+                Ok(stored) if &stored == tx_out => {}
+                Ok(_) => {
+                    return Err(fail(
+                        Check::TxOutIndex,
+                        format!("TxOut {} does not match the block's outputs", tx_out_index),
+                    ))
+                }
+                Err(err) => return Err(read_error(&format!("TxOut {}", tx_out_index), err)),
+            }
+
+            self.tx_out_tree.push(hash_leaf(tx_out));
+        }
+
+        let key_images = self
+            .ledger
+            .get_key_images_by_block(block_index)
+            .map_err(|err| read_error("key images", err))?;
+        ensure_eq(&key_images, &contents.key_images, |details| {
+            fail(Check::KeyImageIndex, details)
+        })?;
+        for key_image in &contents.key_images {
+            match self.ledger.check_key_image(key_image) {
+                Ok(Some(index)) if index == block_index => {}
+                result => {
+                    return Err(fail(
+                        Check::KeyImageIndex,
+                        format!(
+                            "Key image {:?}: expected block {}, got {:?}",
+                            key_image, block_index, result
+                        ),
+                    ))
+                }
+            }
+        }
+
+        match self.ledger.get_block_signature(block_index) {
+            Ok(signature) => signature.verify(&block).map_err(|err| {
+                fail(
+                    Check::BlockSignature,
+                    format!("Invalid signature by {:?}: {}", signature.signer(), err),
+                )
+            })?,
+            Err(LedgerError::NotFound) => {}
+            Err(err) => return Err(read_error("block signature", err)),
+        }
+
+        match self.ledger.get_block_metadata(block_index) {
+            Ok(metadata) => {
+                ensure_eq(metadata.contents().block_id(), &block.id, |details| {
+                    fail(Check::BlockMetadata, details)
+                })?;
+                let result = match self.metadata_validator {
+                    Some(validator) => validator
+                        .validate(&metadata, block_index)
+                        .map_err(|err| err.to_string()),
+                    None => metadata.verify().map_err(|err| err.to_string()),
+                };
+                result.map_err(|err| {
+                    fail(
+                        Check::BlockMetadata,
+                        format!("Invalid metadata by {:?}: {}", metadata.node_key(), err),
+                    )
+                })?;
+            }
+            Err(LedgerError::NotFound) => {
+                let requires_metadata = BlockVersion::try_from(block.version)
+                    .map_or(false, |version| version.require_block_metadata());
+                if requires_metadata {
+                    return Err(fail(
+                        Check::BlockMetadata,
+                        format!("Missing metadata for block version {}", block.version),
+                    ));
+                }
+            }
+            Err(err) => return Err(read_error("block metadata", err)),
+        }
+
+        self.prev_block = Some(block);
+        Ok(())
+    }
+
+    fn verify_num_tx_outs(&self, num_blocks: u64) -> Result<(), Inconsistency> {
+        let block_index = num_blocks.saturating_sub(1);
+        match self.ledger.num_txos() {
+            Ok(num_txos) if num_txos == self.tx_out_tree.num_leaves => Ok(()),
+            result => Err(Inconsistency {
+                block_index,
+                check: Check::NumTxOuts,
+                details: format!(
+                    "Expected {} TxOuts, got {:?}",
+                    self.tx_out_tree.num_leaves, result
+                ),
+            }),
+        }
+    }
+}
+
+/// Returns the error built by `fail` if `actual` and `expected` differ.
+fn ensure_eq<T: Debug + PartialEq + ?Sized>(
+    actual: &T,
+    expected: &T,
+    fail: impl FnOnce(String) -> Inconsistency,
+) -> Result<(), Inconsistency> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(fail(format!("Expected {:?}, got {:?}", expected, actual)))
+    }
+}
+
+/// The roots of the largest perfect subtrees of a TxOut Merkle tree that is
+/// being built one leaf at a time. This is enough to compute the root of the
+/// tree after each leaf without keeping the leaves around.
+#[derive(Default)]
+struct MerkleFrontier {
+    /// The root of the perfect subtree with 2^i leaves, for each bit i of
+    /// `num_leaves` that is set.
+    subtrees: Vec<Option<[u8; 32]>>,
+
+    /// The number of leaves in the tree.
+    num_leaves: u64,
+}
+
+impl MerkleFrontier {
+    fn push(&mut self, leaf_hash: [u8; 32]) {
+        let mut hash = leaf_hash;
+        let mut level = 0;
+        while let Some(Some(left)) = self.subtrees.get(level) {
+            hash = hash_nodes(left, &hash);
+            self.subtrees[level] = None;
+            level += 1;
+        }
+        if level == self.subtrees.len() {
+            self.subtrees.push(None);
+        }
+        self.subtrees[level] = Some(hash);
+        self.num_leaves += 1;
+    }
+
+    /// The root element of the tree, as returned by
+    /// `Ledger::get_root_tx_out_membership_element`.
+    fn root_element(&self) -> TxOutMembershipElement {
+        if self.num_leaves == 0 {
+            return TxOutMembershipElement::default();
+        }
+        let size = self.num_leaves.next_power_of_two();
+        let range = Range::new(0, size - 1).expect("range is not empty");
+        TxOutMembershipElement::new(range, self.subtree_hash(0, size))
+    }
+
+    /// The hash of the subtree with `size` leaves starting at `offset`.
+    fn subtree_hash(&self, offset: u64, size: u64) -> [u8; 32] {
+        if offset >= self.num_leaves {
+            *NIL_HASH
+        } else if offset + size <= self.num_leaves {
+            // A full subtree reached from the root is always one of the stored
+            // ones, since those are laid out left to right by decreasing size.
+            self.subtrees[size.trailing_zeros() as usize].expect("missing full subtree")
+        } else {
+            let half = size / 2;
+            hash_nodes(
+                &self.subtree_hash(offset, half),
+                &self.subtree_hash(offset + half, half),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mc_blockchain_types::{BlockData, BlockMetadata};
+    use mc_common::logger::test_with_logger;
+    use mc_ledger_db::test_utils::{
+        create_ledger, get_mock_ledger_and_blocks, get_test_ledger_blocks,
+    };
+    use mc_transaction_core::ring_signature::KeyImage;
+
+    #[test_with_logger]
+    fn verify_valid_ledger(logger: Logger) {
+        let (ledger, _blocks) = get_mock_ledger_and_blocks(5);
+        let report = verify_ledger(&ledger, None, &logger).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.num_blocks, 5);
+        assert_eq!(report.num_blocks_verified, 5);
+        assert_eq!(report.num_tx_outs_verified, ledger.num_txos().unwrap());
+    }
+
+    #[test_with_logger]
+    fn verify_root_elements(logger: Logger) {
+        // Rebuild the test blocks so that they carry the root element of the
+        // ledger they are appended to.
+        let mut ledger = create_ledger();
+        for block_data in get_test_ledger_blocks(6) {
+            let contents = block_data.contents().clone();
+            let block = if ledger.num_blocks().unwrap() == 0 {
+                block_data.block().clone()
+            } else {
+                let parent = ledger.get_block(ledger.num_blocks().unwrap() - 1).unwrap();
+                let root_element = ledger.get_root_tx_out_membership_element().unwrap();
+                Block::new_with_parent(BlockVersion::ZERO, &parent, &root_element, &contents)
+            };
+            ledger.append_block(&block, &contents, None, None).unwrap();
+        }
+
+        let report = verify_ledger(&ledger, None, &logger).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.num_blocks_verified, 6);
+
+        // The frontier agrees with the ledger's own Merkle tree.
+        let mut tree = MerkleFrontier::default();
+        for index in 0..ledger.num_txos().unwrap() {
+            tree.push(hash_leaf(&ledger.get_tx_out_by_index(index).unwrap()));
+        }
+        assert_eq!(
+            tree.root_element(),
+            ledger.get_root_tx_out_membership_element().unwrap()
+        );
+
+        // A block validated against the wrong TxOuts is reported.
+        let parent = ledger.get_block(5).unwrap();
+        let contents = get_test_ledger_blocks(7)[6].contents().clone();
+        let wrong_root_element = TxOutMembershipElement::new(Range::new(0, 15).unwrap(), [7; 32]);
+        let block =
+            Block::new_with_parent(BlockVersion::ZERO, &parent, &wrong_root_element, &contents);
+        ledger.append_block(&block, &contents, None, None).unwrap();
+
+        let report = verify_ledger(&ledger, None, &logger).unwrap();
+        assert_eq!(report.num_blocks_verified, 6);
+        let inconsistency = report.first_inconsistency.unwrap();
+        assert_eq!(inconsistency.block_index, 6);
+        assert_eq!(inconsistency.check, Check::RootElement);
+    }
+
+    #[test_with_logger]
+    fn detects_tampered_contents(logger: Logger) {
+        let (ledger, blocks) = get_mock_ledger_and_blocks(5);
+
+        let mut contents = blocks[3].contents().clone();
+        contents.key_images.push(KeyImage::from(12345));
+        ledger.lock().block_data_by_index.insert(
+            3,
+            BlockData::new(
+                blocks[3].block().clone(),
+                contents,
+                blocks[3].signature().cloned(),
+                blocks[3].metadata().cloned(),
+            ),
+        );
+
+        let report = verify_ledger(&ledger, None, &logger).unwrap();
+        assert_eq!(report.num_blocks_verified, 3);
+        let inconsistency = report.first_inconsistency.unwrap();
+        assert_eq!(inconsistency.block_index, 3);
+        assert_eq!(inconsistency.check, Check::ContentsHash);
+    }
+
+    #[test_with_logger]
+    fn detects_tampered_indexes(logger: Logger) {
+        let (ledger, blocks) = get_mock_ledger_and_blocks(5);
+        let tx_out_index = blocks[1].block().cumulative_txo_count;
+        ledger
+            .lock()
+            .block_index_by_tx_out_index
+            .insert(tx_out_index, 4);
+
+        let report = verify_ledger(&ledger, None, &logger).unwrap();
+        let inconsistency = report.first_inconsistency.unwrap();
+        assert_eq!(inconsistency.block_index, 2);
+        assert_eq!(inconsistency.check, Check::TxOutIndex);
+
+        let (ledger, blocks) = get_mock_ledger_and_blocks(5);
+        let key_image = blocks[2].contents().key_images[0];
+        ledger.lock().key_images.insert(key_image, 1);
+
+        let report = verify_ledger(&ledger, None, &logger).unwrap();
+        let inconsistency = report.first_inconsistency.unwrap();
+        assert_eq!(inconsistency.block_index, 2);
+        assert_eq!(inconsistency.check, Check::KeyImageIndex);
+    }
+
+    #[test_with_logger]
+    fn detects_mismatched_metadata(logger: Logger) {
+        let (ledger, blocks) = get_mock_ledger_and_blocks(5);
+
+        // Metadata signed for a different block.
+        let other_metadata: BlockMetadata = blocks[1].metadata().cloned().unwrap();
+        ledger.lock().block_data_by_index.insert(
+            2,
+            BlockData::new(
+                blocks[2].block().clone(),
+                blocks[2].contents().clone(),
+                blocks[2].signature().cloned(),
+                Some(other_metadata),
+            ),
+        );
+
+        let report = verify_ledger(&ledger, None, &logger).unwrap();
+        let inconsistency = report.first_inconsistency.unwrap();
+        assert_eq!(inconsistency.block_index, 2);
+        assert_eq!(inconsistency.check, Check::BlockMetadata);
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+#![deny(missing_docs)]
+#![doc = include_str!("../README.md")]
+#![forbid(unsafe_code)]
+
+use clap::Parser;
+use mc_blockchain_validators::MetadataValidator;
+use mc_common::logger::{create_app_logger, log, o};
+use mc_ledger_db::LedgerDB;
+use mc_ledger_verifier::verify_ledger;
+use std::path::PathBuf;
+
+/// Command line configuration.
+#[derive(Debug, Parser)]
+#[clap(
+    name = "mc-ledger-verifier",
+    about = "Verify the integrity of a ledger db."
+)]
+pub struct Config {
+    /// Path to ledger db (lmdb).
+    #[clap(long, env = "MC_LEDGER_DB")]
+    pub ledger_db: PathBuf,
+
+    /// (Optional) Path to metadata-signers.toml, used to check that block
+    /// metadata was signed by an allowed key.
+    #[clap(long, env = "MC_METADATA_SIGNERS")]
+    pub metadata_signers: Option<PathBuf>,
+}
+
+fn main() {
+    mc_common::setup_panic_handler();
+    let (logger, _global_logger_guard) = create_app_logger(o!());
+    let config = Config::parse();
+
+    let metadata_validator = config
+        .metadata_signers
+        .as_ref()
+        .map(|path| MetadataValidator::new(path).expect("Could not load metadata signers"));
+
+    let ledger_db = LedgerDB::open(&config.ledger_db).expect("Could not open ledger_db");
+
+    log::info!(logger, "Verifying ledger at {:?}", config.ledger_db);
+    let report = verify_ledger(&ledger_db, metadata_validator.as_ref(), &logger)
+        .expect("Could not read the number of blocks");
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).expect("Failed serializing report")
+    );
+
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+    log::info!(logger, "Verified {} blocks", report.num_blocks_verified);
+}
@@ -7,10 +7,12 @@
 
 use clap::Parser;
 use grpcio::ChannelBuilder;
-use mc_api::external::{CompressedRistretto, PublicAddress, RistrettoPrivate};
+use mc_api::external::{
+    CompressedRistretto, PublicAddress, RistrettoPrivate, SignedContingentInput,
+};
 use mc_common::logger::{create_app_logger, log, o};
 use mc_mobilecoind_api::{self as api, mobilecoind_api_grpc::MobilecoindApiClient, MobilecoindUri};
-use mc_mobilecoind_json::data_types::*;
+use mc_mobilecoind_json::{data_types::*, webhook::WebhookForwarder};
 use mc_util_grpc::ConnectionUriGrpcioChannel;
 use protobuf::RepeatedField;
 use rocket::{delete, get, post, routes, serde::json::Json};
@@ -36,6 +38,22 @@ pub struct Config {
         env = "MC_MOBILECOIND_URI"
     )]
     pub mobilecoind_uri: MobilecoindUri,
+
+    /// URL to POST monitor events to, as they are processed by mobilecoind.
+    #[clap(long, env = "MC_WEBHOOK_URL", requires = "webhook_monitor_ids")]
+    pub webhook_url: Option<String>,
+
+    /// Hex-encoded ids of the monitors whose events are sent to the webhook.
+    #[clap(
+        long = "webhook-monitor-id",
+        env = "MC_WEBHOOK_MONITOR_IDS",
+        value_delimiter = ','
+    )]
+    pub webhook_monitor_ids: Vec<String>,
+
+    /// The block index to start sending monitor events from.
+    #[clap(long, default_value = "0", env = "MC_WEBHOOK_START_BLOCK")]
+    pub webhook_start_block: u64,
 }
 
 /// Connection to the mobilecoind client
@@ -416,6 +434,9 @@ fn build_and_submit(
         req.set_override_change_subaddress(true);
         req.set_change_subaddress(u64::from(subaddress))
     }
+    if let Some(strategy) = transfer.utxo_selection_strategy {
+        req.set_utxo_selection_strategy(strategy.into());
+    }
 
     let resp = state
         .mobilecoind_api_client
@@ -463,6 +484,9 @@ fn pay_address_code(
         req.set_override_change_subaddress(true);
         req.set_change_subaddress(u64::from(subaddress))
     }
+    if let Some(strategy) = transfer.utxo_selection_strategy {
+        req.set_utxo_selection_strategy(strategy.into());
+    }
 
     let resp = state
         .mobilecoind_api_client
@@ -512,6 +536,9 @@ fn generate_request_code_transaction(
     req.set_change_subaddress(subaddress_index);
     req.set_outlay_list(RepeatedField::from_vec(vec![outlay]));
     req.set_input_list(RepeatedField::from_vec(inputs));
+    if let Some(strategy) = request.utxo_selection_strategy {
+        req.set_utxo_selection_strategy(strategy.into());
+    }
 
     let resp = state
         .mobilecoind_api_client
@@ -543,6 +570,170 @@ fn submit_tx(
     Ok(Json(JsonSubmitTxResponse::from(&resp)))
 }
 
+/// Creates a signed contingent input (MCIP #31) offering one of the monitor's
+/// unspent outputs, in exchange for the outputs specified in the POST data.
+#[post(
+    "/monitors/<monitor_hex>/subaddresses/<subaddress_index>/generate-signed-contingent-input",
+    format = "json",
+    data = "<request>"
+)]
+fn generate_signed_contingent_input(
+    state: &rocket::State<State>,
+    monitor_hex: String,
+    subaddress_index: u64,
+    request: Json<JsonGenerateSignedContingentInputRequest>,
+) -> Result<Json<JsonSignedContingentInput>, String> {
+    let monitor_id =
+        hex::decode(monitor_hex).map_err(|err| format!("Failed to decode monitor hex: {}", err))?;
+
+    let input = api::UnspentTxOut::try_from(&request.input)
+        .map_err(|err| format!("Failed to convert input: {}", err))?;
+
+    let required_outlays: Vec<api::OutlayV2> = request
+        .required_outlays
+        .iter()
+        .map(api::OutlayV2::try_from)
+        .collect::<Result<_, String>>()?;
+
+    let partial_fill_outlays: Vec<api::OutlayV2> = request
+        .partial_fill_outlays
+        .iter()
+        .map(api::OutlayV2::try_from)
+        .collect::<Result<_, String>>()?;
+
+    let mut req = api::GenerateSignedContingentInputRequest::new();
+    req.set_sender_monitor_id(monitor_id);
+    req.set_change_subaddress(subaddress_index);
+    req.set_input(input);
+    req.set_offered_value(request.offered_value.as_ref().map(u64::from).unwrap_or(0));
+    req.set_required_outlay_list(RepeatedField::from_vec(required_outlays));
+    req.set_partial_fill_outlay_list(RepeatedField::from_vec(partial_fill_outlays));
+    req.set_min_partial_fill_value(
+        request
+            .min_partial_fill_value
+            .as_ref()
+            .map(u64::from)
+            .unwrap_or(0),
+    );
+    req.set_tombstone(request.tombstone.as_ref().map(u64::from).unwrap_or(0));
+
+    let resp = state
+        .mobilecoind_api_client
+        .generate_signed_contingent_input(&req)
+        .map_err(|err| format!("Failed to generate signed contingent input: {}", err))?;
+
+    Ok(Json(JsonSignedContingentInput::from(&resp)))
+}
+
+/// Validates a signed contingent input and describes what it offers and
+/// requires
+#[post(
+    "/signed-contingent-input/inspect",
+    format = "json",
+    data = "<request>"
+)]
+fn inspect_signed_contingent_input(
+    state: &rocket::State<State>,
+    request: Json<JsonSignedContingentInput>,
+) -> Result<Json<JsonInspectSignedContingentInputResponse>, String> {
+    let mut req = api::InspectSignedContingentInputRequest::new();
+    req.set_sci(SignedContingentInput::try_from(&request.0)?);
+
+    let resp = state
+        .mobilecoind_api_client
+        .inspect_signed_contingent_input(&req)
+        .map_err(|err| format!("Failed to inspect signed contingent input: {}", err))?;
+
+    Ok(Json(JsonInspectSignedContingentInputResponse::from(&resp)))
+}
+
+/// Fills a signed contingent input using the funds of a monitor and
+/// subaddress, and submits the resulting transaction.
+#[post(
+    "/monitors/<monitor_hex>/subaddresses/<subaddress_index>/fill-signed-contingent-input",
+    format = "json",
+    data = "<request>"
+)]
+fn fill_signed_contingent_input(
+    state: &rocket::State<State>,
+    monitor_hex: String,
+    subaddress_index: u64,
+    request: Json<JsonFillSignedContingentInputRequest>,
+) -> Result<Json<JsonSubmitTxResponse>, String> {
+    let monitor_id =
+        hex::decode(monitor_hex).map_err(|err| format!("Failed to decode monitor hex: {}", err))?;
+
+    let sci = SignedContingentInput::try_from(&JsonSignedContingentInput {
+        sci: request.sci.clone(),
+    })?;
+    let fee_token_id = request.fee_token_id.as_ref().map(u64::from).unwrap_or(0);
+
+    // Find out which tokens we need to spend: the fee token, and the tokens of
+    // every output the signed contingent input requires.
+    let mut inspect_req = api::InspectSignedContingentInputRequest::new();
+    inspect_req.set_sci(sci.clone());
+    let inspect_resp = state
+        .mobilecoind_api_client
+        .inspect_signed_contingent_input(&inspect_req)
+        .map_err(|err| format!("Failed to inspect signed contingent input: {}", err))?;
+    if !inspect_resp.get_is_valid() {
+        return Err(format!(
+            "Invalid signed contingent input: {}",
+            inspect_resp.get_validation_error()
+        ));
+    }
+
+    let mut token_ids: Vec<u64> = inspect_resp
+        .get_required_output_amounts()
+        .iter()
+        .chain(inspect_resp.get_partial_fill_output_amounts().iter())
+        .map(|amount| amount.get_token_id())
+        .chain(std::iter::once(fee_token_id))
+        .collect();
+    token_ids.sort_unstable();
+    token_ids.dedup();
+
+    // Gather our unspent outputs in those tokens.
+    let mut inputs = Vec::new();
+    for token_id in token_ids {
+        let mut req = api::GetUnspentTxOutListRequest::new();
+        req.set_monitor_id(monitor_id.clone());
+        req.set_subaddress_index(subaddress_index);
+        req.set_token_id(token_id);
+
+        let resp = state
+            .mobilecoind_api_client
+            .get_unspent_tx_out_list(&req)
+            .map_err(|err| format!("Failed getting utxos: {}", err))?;
+        inputs.extend(resp.get_output_list().iter().cloned());
+    }
+
+    let mut req = api::FillSignedContingentInputRequest::new();
+    req.set_sender_monitor_id(monitor_id);
+    req.set_change_subaddress(subaddress_index);
+    req.set_input_list(RepeatedField::from_vec(inputs));
+    req.set_sci(sci);
+    req.set_fill_value(request.fill_value.as_ref().map(u64::from).unwrap_or(0));
+    req.set_fee(request.fee.as_ref().map(u64::from).unwrap_or(0));
+    req.set_fee_token_id(fee_token_id);
+    req.set_tombstone(request.tombstone.as_ref().map(u64::from).unwrap_or(0));
+
+    let resp = state
+        .mobilecoind_api_client
+        .fill_signed_contingent_input(&req)
+        .map_err(|err| format!("Failed to fill signed contingent input: {}", err))?;
+
+    let mut req = api::SubmitTxRequest::new();
+    req.set_tx_proposal(resp.get_tx_proposal().clone());
+
+    let resp = state
+        .mobilecoind_api_client
+        .submit_tx(&req)
+        .map_err(|err| format!("Failed to submit tx: {}", err))?;
+
+    Ok(Json(JsonSubmitTxResponse::from(&resp)))
+}
+
 /// Checks the status of a transfer given a key image and tombstone block
 #[post("/tx/status-as-sender", format = "json", data = "<submit_response>")]
 fn check_transfer_status(
@@ -801,6 +992,27 @@ async fn main() -> Result<(), rocket::Error> {
 
     let mobilecoind_api_client = MobilecoindApiClient::new(ch);
 
+    // Forward monitor events to the webhook, if one was configured.
+    if let Some(webhook_url) = config.webhook_url.as_ref() {
+        for monitor_id in config.webhook_monitor_ids.iter() {
+            let monitor_id = hex::decode(monitor_id).expect("Failed decoding monitor id hex");
+            log::info!(
+                logger,
+                "Sending events of monitor {} to {}",
+                hex::encode(&monitor_id),
+                webhook_url,
+            );
+            WebhookForwarder::new(
+                mobilecoind_api_client.clone(),
+                webhook_url.clone(),
+                monitor_id,
+                config.webhook_start_block,
+                logger.clone(),
+            )
+            .start();
+        }
+    }
+
     let figment = rocket::Config::figment()
         .merge(("port", config.listen_port))
         .merge(("address", config.listen_host.clone()));
@@ -831,6 +1043,9 @@ async fn main() -> Result<(), rocket::Error> {
                 pay_address_code,
                 generate_request_code_transaction,
                 submit_tx,
+                generate_signed_contingent_input,
+                inspect_signed_contingent_input,
+                fill_signed_contingent_input,
                 check_transfer_status,
                 check_receiver_transfer_status,
                 ledger_info,
@@ -4,12 +4,12 @@
 
 use mc_api::external::{
     CompressedRistretto, EncryptedFogHint, EncryptedMemo, InputRules, KeyImage, MaskedAmount,
-    PublicAddress, RingMLSAG, SignatureRctBulletproofs, Tx, TxIn, TxOutMembershipElement,
-    TxOutMembershipHash, TxOutMembershipProof, TxPrefix,
+    PublicAddress, RingMLSAG, SignatureRctBulletproofs, SignedContingentInput, Tx, TxIn,
+    TxOutMembershipElement, TxOutMembershipHash, TxOutMembershipProof, TxPrefix,
 };
 use mc_mobilecoind_api as api;
 use mc_util_serial::JsonU64;
-use protobuf::RepeatedField;
+use protobuf::{Message, RepeatedField};
 use serde_derive::{Deserialize, Serialize};
 
 #[derive(Deserialize, Default, Debug)]
@@ -153,6 +153,8 @@ pub struct JsonUnspentTxOut {
     pub attempted_spend_height: u64,
     pub attempted_spend_tombstone: u64,
     pub monitor_id: String,
+    #[serde(default)]
+    pub token_id: JsonU64,
 }
 
 impl From<&api::UnspentTxOut> for JsonUnspentTxOut {
@@ -165,6 +167,7 @@ impl From<&api::UnspentTxOut> for JsonUnspentTxOut {
             attempted_spend_height: src.get_attempted_spend_height(),
             attempted_spend_tombstone: src.get_attempted_spend_tombstone(),
             monitor_id: hex::encode(&src.get_monitor_id()),
+            token_id: JsonU64(src.get_token_id()),
         }
     }
 }
@@ -195,6 +198,7 @@ impl TryFrom<&JsonUnspentTxOut> for api::UnspentTxOut {
             hex::decode(&src.monitor_id)
                 .map_err(|err| format!("Failed to decode monitor id hex: {}", err))?,
         );
+        utxo.set_token_id(src.token_id.into());
 
         Ok(utxo)
     }
@@ -422,11 +426,33 @@ impl From<&api::ReceiverTxReceipt> for JsonReceiverTxReceipt {
     }
 }
 
+/// How mobilecoind chooses which utxos a transaction spends.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonUtxoSelectionStrategy {
+    SmallestFirst,
+    LargestFirst,
+    BranchAndBound,
+    Random,
+}
+
+impl From<JsonUtxoSelectionStrategy> for api::UtxoSelectionStrategy {
+    fn from(src: JsonUtxoSelectionStrategy) -> Self {
+        match src {
+            JsonUtxoSelectionStrategy::SmallestFirst => Self::SmallestFirst,
+            JsonUtxoSelectionStrategy::LargestFirst => Self::LargestFirst,
+            JsonUtxoSelectionStrategy::BranchAndBound => Self::BranchAndBound,
+            JsonUtxoSelectionStrategy::Random => Self::Random,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Default, Debug)]
 pub struct JsonSendPaymentRequest {
     pub request_data: JsonParseRequestCodeResponse,
     pub max_input_utxo_value: Option<JsonU64>,
     pub change_subaddress: Option<JsonU64>,
+    pub utxo_selection_strategy: Option<JsonUtxoSelectionStrategy>,
 }
 
 #[derive(Deserialize, Serialize, Default, Debug)]
@@ -454,6 +480,7 @@ pub struct JsonPayAddressCodeRequest {
     pub value: JsonU64,
     pub max_input_utxo_value: Option<JsonU64>,
     pub change_subaddress: Option<JsonU64>,
+    pub utxo_selection_strategy: Option<JsonUtxoSelectionStrategy>,
 }
 
 #[derive(Deserialize, Serialize, Default, Debug, Clone)]
@@ -1099,6 +1126,7 @@ impl TryFrom<&JsonTxProposal> for api::TxProposal {
 pub struct JsonCreateTxProposalRequest {
     pub input_list: Vec<JsonUnspentTxOut>,
     pub transfer: JsonParseRequestCodeResponse,
+    pub utxo_selection_strategy: Option<JsonUtxoSelectionStrategy>,
 }
 
 #[derive(Deserialize, Serialize, Default, Debug)]
@@ -1195,6 +1223,152 @@ impl TryFrom<&JsonSubmitTxResponse> for api::SubmitTxResponse {
     }
 }
 
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
+pub struct JsonAmount {
+    pub value: JsonU64,
+    pub token_id: JsonU64,
+}
+
+impl From<&mc_api::external::Amount> for JsonAmount {
+    fn from(src: &mc_api::external::Amount) -> Self {
+        Self {
+            value: JsonU64(src.get_value()),
+            token_id: JsonU64(src.get_token_id()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
+pub struct JsonOutlayV2 {
+    pub value: JsonU64,
+    pub receiver: JsonPublicAddress,
+    pub token_id: JsonU64,
+}
+
+impl From<&api::OutlayV2> for JsonOutlayV2 {
+    fn from(src: &api::OutlayV2) -> Self {
+        Self {
+            value: JsonU64(src.get_value()),
+            receiver: src.get_receiver().into(),
+            token_id: JsonU64(src.get_token_id()),
+        }
+    }
+}
+
+impl TryFrom<&JsonOutlayV2> for api::OutlayV2 {
+    type Error = String;
+
+    fn try_from(src: &JsonOutlayV2) -> Result<api::OutlayV2, String> {
+        let mut outlay = api::OutlayV2::new();
+        outlay.set_value(src.value.into());
+        outlay.set_receiver(
+            PublicAddress::try_from(&src.receiver)
+                .map_err(|err| format!("Could not convert receiver: {}", err))?,
+        );
+        outlay.set_token_id(src.token_id.into());
+
+        Ok(outlay)
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct JsonGenerateSignedContingentInputRequest {
+    pub input: JsonUnspentTxOut,
+    pub offered_value: Option<JsonU64>,
+    pub required_outlays: Vec<JsonOutlayV2>,
+    #[serde(default)]
+    pub partial_fill_outlays: Vec<JsonOutlayV2>,
+    pub min_partial_fill_value: Option<JsonU64>,
+    pub tombstone: Option<JsonU64>,
+}
+
+/// A signed contingent input, as the hex-encoded protobuf bytes of an
+/// external.SignedContingentInput.
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct JsonSignedContingentInput {
+    pub sci: String,
+}
+
+impl From<&SignedContingentInput> for JsonSignedContingentInput {
+    fn from(src: &SignedContingentInput) -> Self {
+        Self {
+            sci: hex::encode(
+                src.write_to_bytes()
+                    .expect("Serializing a protobuf message to bytes cannot fail"),
+            ),
+        }
+    }
+}
+
+impl TryFrom<&JsonSignedContingentInput> for SignedContingentInput {
+    type Error = String;
+
+    fn try_from(src: &JsonSignedContingentInput) -> Result<SignedContingentInput, String> {
+        let bytes = hex::decode(&src.sci)
+            .map_err(|err| format!("Failed to decode signed contingent input hex: {}", err))?;
+        SignedContingentInput::parse_from_bytes(&bytes)
+            .map_err(|err| format!("Failed to parse signed contingent input: {}", err))
+    }
+}
+
+impl From<&api::GenerateSignedContingentInputResponse> for JsonSignedContingentInput {
+    fn from(src: &api::GenerateSignedContingentInputResponse) -> Self {
+        Self::from(src.get_sci())
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct JsonInspectSignedContingentInputResponse {
+    pub is_valid: bool,
+    pub validation_error: String,
+    pub key_image: String,
+    pub is_spent: bool,
+    pub block_version: u32,
+    pub offered_amount: JsonAmount,
+    pub required_output_amounts: Vec<JsonAmount>,
+    pub max_tombstone_block: JsonU64,
+    pub is_partial_fill: bool,
+    pub partial_fill_change_amount: JsonAmount,
+    pub partial_fill_output_amounts: Vec<JsonAmount>,
+    pub min_partial_fill_value: JsonU64,
+}
+
+impl From<&api::InspectSignedContingentInputResponse> for JsonInspectSignedContingentInputResponse {
+    fn from(src: &api::InspectSignedContingentInputResponse) -> Self {
+        Self {
+            is_valid: src.get_is_valid(),
+            validation_error: src.get_validation_error().to_string(),
+            key_image: hex::encode(&src.get_key_image().get_data()),
+            is_spent: src.get_is_spent(),
+            block_version: src.get_block_version(),
+            offered_amount: src.get_offered_amount().into(),
+            required_output_amounts: src
+                .get_required_output_amounts()
+                .iter()
+                .map(JsonAmount::from)
+                .collect(),
+            max_tombstone_block: JsonU64(src.get_max_tombstone_block()),
+            is_partial_fill: src.get_is_partial_fill(),
+            partial_fill_change_amount: src.get_partial_fill_change_amount().into(),
+            partial_fill_output_amounts: src
+                .get_partial_fill_output_amounts()
+                .iter()
+                .map(JsonAmount::from)
+                .collect(),
+            min_partial_fill_value: JsonU64(src.get_min_partial_fill_value()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct JsonFillSignedContingentInputRequest {
+    pub sci: String,
+    pub fill_value: Option<JsonU64>,
+    pub fee: Option<JsonU64>,
+    pub fee_token_id: Option<JsonU64>,
+    pub tombstone: Option<JsonU64>,
+}
+
 #[derive(Serialize, Default, Debug)]
 pub struct JsonStatusResponse {
     pub status: String,
@@ -1353,6 +1527,69 @@ impl From<&api::GetProcessedBlockResponse> for JsonProcessedBlockResponse {
     }
 }
 
+#[derive(Serialize, Default, Debug)]
+pub struct JsonTransactionHistoryEntry {
+    pub block_index: JsonU64,
+    pub direction: String,
+    pub subaddress_index: JsonU64,
+    pub public_key: String,
+    pub key_image: String,
+    pub value: JsonU64,
+    pub token_id: JsonU64,
+}
+
+impl From<&api::TransactionHistoryEntry> for JsonTransactionHistoryEntry {
+    fn from(src: &api::TransactionHistoryEntry) -> Self {
+        let direction_str = match src.direction {
+            api::ProcessedTxOutDirection::Invalid => "invalid",
+            api::ProcessedTxOutDirection::Received => "received",
+            api::ProcessedTxOutDirection::Spent => "spent",
+        };
+
+        Self {
+            block_index: JsonU64(src.block_index),
+            direction: direction_str.to_owned(),
+            subaddress_index: JsonU64(src.subaddress_index),
+            public_key: hex::encode(&src.get_public_key().get_data()),
+            key_image: hex::encode(&src.get_key_image().get_data()),
+            value: JsonU64(src.value),
+            token_id: JsonU64(src.token_id),
+        }
+    }
+}
+
+/// The body of a monitor event webhook request.
+#[derive(Serialize, Default, Debug)]
+pub struct JsonMonitorEvent {
+    pub monitor_id: String,
+    pub event_type: String,
+    pub block_index: JsonU64,
+    pub entry: Option<JsonTransactionHistoryEntry>,
+    pub cursor: String,
+}
+
+impl JsonMonitorEvent {
+    pub fn new(monitor_id: &[u8], src: &api::MonitorEvent) -> Self {
+        let event_type_str = match src.event_type {
+            api::MonitorEventType::MonitorEventReceived => "received",
+            api::MonitorEventType::MonitorEventSpent => "spent",
+            api::MonitorEventType::MonitorEventCaughtUp => "caught_up",
+        };
+
+        Self {
+            monitor_id: hex::encode(monitor_id),
+            event_type: event_type_str.to_owned(),
+            block_index: JsonU64(src.block_index),
+            entry: if src.has_entry() {
+                Some(JsonTransactionHistoryEntry::from(src.get_entry()))
+            } else {
+                None
+            },
+            cursor: hex::encode(src.get_cursor()),
+        }
+    }
+}
+
 #[derive(Serialize, Default, Debug)]
 pub struct JsonBlockIndexByTxPubKeyResponse {
     pub block_index: String,
@@ -0,0 +1,134 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Forwards mobilecoind monitor events to a webhook.
+//!
+//! Each monitor gets a thread that subscribes to its event stream and POSTs
+//! every event, as a `JsonMonitorEvent`, to the webhook URL. Events are
+//! delivered in order, and a failed delivery is retried until it succeeds. If
+//! the connection to mobilecoind drops, the subscription is resumed from the
+//! cursor of the last delivered event.
+
+use crate::data_types::JsonMonitorEvent;
+use futures::{executor::block_on, StreamExt};
+use mc_common::logger::{log, Logger};
+use mc_mobilecoind_api::{self as api, mobilecoind_api_grpc::MobilecoindApiClient};
+use reqwest::{blocking::Client, header::CONTENT_TYPE};
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How long to wait before retrying a failed delivery or subscription.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Forwards the events of a single monitor to a webhook.
+pub struct WebhookForwarder {
+    mobilecoind_api_client: MobilecoindApiClient,
+    http_client: Client,
+    webhook_url: String,
+    monitor_id: Vec<u8>,
+
+    /// The block to start at, until an event has been delivered.
+    start_block_index: u64,
+
+    /// The cursor of the last delivered event, if any.
+    cursor: Option<Vec<u8>>,
+
+    logger: Logger,
+}
+
+impl WebhookForwarder {
+    /// Create a forwarder that delivers the events of `monitor_id`, starting
+    /// at `start_block_index`.
+    pub fn new(
+        mobilecoind_api_client: MobilecoindApiClient,
+        webhook_url: String,
+        monitor_id: Vec<u8>,
+        start_block_index: u64,
+        logger: Logger,
+    ) -> Self {
+        Self {
+            mobilecoind_api_client,
+            http_client: Client::new(),
+            webhook_url,
+            monitor_id,
+            start_block_index,
+            cursor: None,
+            logger,
+        }
+    }
+
+    /// Start forwarding events on a background thread.
+    pub fn start(mut self) -> JoinHandle<()> {
+        thread::Builder::new()
+            .name(format!("Webhook-{}", hex::encode(&self.monitor_id)))
+            .spawn(move || loop {
+                if let Err(err) = self.forward_events() {
+                    log::warn!(
+                        self.logger,
+                        "Monitor {} event stream failed: {}",
+                        hex::encode(&self.monitor_id),
+                        err
+                    );
+                }
+                thread::sleep(RETRY_INTERVAL);
+            })
+            .expect("Failed spawning webhook thread")
+    }
+
+    /// Subscribe to the monitor's events and deliver them until the stream
+    /// ends.
+    fn forward_events(&mut self) -> Result<(), grpcio::Error> {
+        let mut request = api::SubscribeMonitorEventsRequest::new();
+        request.set_monitor_id(self.monitor_id.clone());
+        request.set_start_block_index(self.start_block_index);
+        if let Some(cursor) = self.cursor.as_ref() {
+            request.set_start_cursor(cursor.clone());
+        }
+        let mut events = self
+            .mobilecoind_api_client
+            .subscribe_monitor_events(&request)?;
+
+        while let Some(event) = block_on(events.next()) {
+            let mut event = event?;
+
+            // A caught up event is repeated while the monitor stays idle.
+            if self.cursor.as_deref() == Some(event.get_cursor()) {
+                continue;
+            }
+
+            self.deliver(&event);
+            self.cursor = Some(event.take_cursor());
+        }
+
+        Ok(())
+    }
+
+    /// POST an event to the webhook, retrying until it is accepted.
+    fn deliver(&self, event: &api::MonitorEvent) {
+        let body = rocket::serde::json::to_string(&JsonMonitorEvent::new(&self.monitor_id, event))
+            .expect("Failed serializing monitor event");
+
+        loop {
+            let result = self
+                .http_client
+                .post(&self.webhook_url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => return,
+                Err(err) => {
+                    log::warn!(
+                        self.logger,
+                        "Failed delivering event to {}: {}",
+                        self.webhook_url,
+                        err
+                    );
+                    thread::sleep(RETRY_INTERVAL);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+#![deny(missing_docs)]
+
+//! Offline tool for changing the encryption of a mobilecoind database.
+//!
+//! mobilecoind must not be running while this tool is used. All monitor
+//! records are re-encrypted and the new password is stored in a single LMDB
+//! transaction, so a crash leaves the database either entirely in its old
+//! state or entirely in its new one. Once the transaction is committed, the
+//! database is reopened from disk and every monitor record is decrypted and
+//! checked against its monitor id.
+//!
+//! The key derivation parameters (algorithm, rounds and salt) are stored in
+//! the database. Encrypting or re-keying always derives the new key with
+//! PBKDF2-HMAC-SHA512 and a fresh salt, so re-keying with the same password
+//! upgrades databases that still use the original Blake2b derivation.
+
+use clap::{Parser, Subcommand};
+use mc_common::logger::{create_app_logger, log, o};
+use mc_mobilecoind::{database::Database, KdfParams, DEFAULT_PBKDF2_ROUNDS};
+use mc_util_parse::parse_hex;
+use std::path::PathBuf;
+
+/// Encrypt, decrypt or re-key a mobilecoind database while mobilecoind is
+/// stopped.
+///
+/// Passwords are 32 bytes, hex-encoded, matching the `SetDbPassword` and
+/// `UnlockDb` APIs.
+#[derive(Clone, Debug, Parser)]
+#[clap(
+    name = "mobilecoind-db-crypto",
+    about = "Offline mobilecoind database encryption tool."
+)]
+pub struct Config {
+    /// Path to mobilecoind database.
+    #[clap(long, env = "MC_MOBILECOIND_DB")]
+    pub mobilecoind_db: PathBuf,
+
+    /// Command to run.
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+/// Commands that the tool recognizes.
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Encrypt a database that is not currently encrypted.
+    Encrypt {
+        /// The password to encrypt with.
+        #[clap(long, value_parser = parse_hex::<[u8; 32]>, env = "MC_NEW_DB_PASSWORD")]
+        new_password: [u8; 32],
+
+        /// Number of PBKDF2 rounds used to derive the encryption key.
+        #[clap(long, default_value_t = DEFAULT_PBKDF2_ROUNDS, env = "MC_KDF_ROUNDS")]
+        kdf_rounds: u32,
+    },
+
+    /// Decrypt an encrypted database.
+    Decrypt {
+        /// The password the database is currently encrypted with.
+        #[clap(long, value_parser = parse_hex::<[u8; 32]>, env = "MC_DB_PASSWORD")]
+        password: [u8; 32],
+    },
+
+    /// Re-encrypt an encrypted database with a new password.
+    Rekey {
+        /// The password the database is currently encrypted with.
+        #[clap(long, value_parser = parse_hex::<[u8; 32]>, env = "MC_DB_PASSWORD")]
+        password: [u8; 32],
+
+        /// The password to re-encrypt with. This may be the current password,
+        /// to only change the key derivation parameters.
+        #[clap(long, value_parser = parse_hex::<[u8; 32]>, env = "MC_NEW_DB_PASSWORD")]
+        new_password: [u8; 32],
+
+        /// Number of PBKDF2 rounds used to derive the new encryption key. A
+        /// fresh salt is generated on every re-key.
+        #[clap(long, default_value_t = DEFAULT_PBKDF2_ROUNDS, env = "MC_KDF_ROUNDS")]
+        kdf_rounds: u32,
+    },
+}
+
+fn main() {
+    mc_common::setup_panic_handler();
+    let (logger, _global_logger_guard) = create_app_logger(o!());
+    let config = Config::parse();
+
+    let (expect_encrypted, password, new_password, kdf_rounds): (bool, &[u8], &[u8], u32) =
+        match &config.command {
+            Command::Encrypt {
+                new_password,
+                kdf_rounds,
+            } => (false, &[], &new_password[..], *kdf_rounds),
+            Command::Decrypt { password } => (true, &password[..], &[], 0),
+            Command::Rekey {
+                password,
+                new_password,
+                kdf_rounds,
+            } => (true, &password[..], &new_password[..], *kdf_rounds),
+        };
+    if !new_password.is_empty() && kdf_rounds == 0 {
+        panic!("--kdf-rounds must be greater than zero");
+    }
+
+    let mobilecoind_db = Database::new(&config.mobilecoind_db, logger.clone())
+        .expect("Could not open mobilecoind_db");
+    if mobilecoind_db.is_db_encrypted() != expect_encrypted {
+        panic!(
+            "Database at {:?} is {}encrypted",
+            config.mobilecoind_db,
+            if expect_encrypted { "not " } else { "" }
+        );
+    }
+    unlock(&mobilecoind_db, password);
+
+    // Make sure every record can be read before changing anything.
+    let monitor_ids = mobilecoind_db
+        .verify_monitor_data()
+        .expect("Failed verifying monitor data before re-encrypting");
+    log::info!(
+        logger,
+        "Re-encrypting {} monitors in {:?}",
+        monitor_ids.len(),
+        config.mobilecoind_db
+    );
+
+    // Decrypting removes the stored parameters, so the ones passed here do not
+    // matter in that case.
+    let kdf_params = KdfParams::pbkdf2(kdf_rounds);
+    mobilecoind_db
+        .re_encrypt_with_kdf_params(new_password, &kdf_params)
+        .expect("Failed re-encrypting database");
+    drop(mobilecoind_db);
+
+    // Reopen the database, so that what gets verified is what was committed to
+    // disk.
+    let mobilecoind_db = Database::new(&config.mobilecoind_db, logger.clone())
+        .expect("Could not reopen mobilecoind_db");
+    assert_eq!(
+        mobilecoind_db.is_db_encrypted(),
+        !new_password.is_empty(),
+        "Unexpected encryption state after re-encrypting"
+    );
+    unlock(&mobilecoind_db, new_password);
+    let new_monitor_ids = mobilecoind_db
+        .verify_monitor_data()
+        .expect("Failed verifying monitor data after re-encrypting");
+    assert_eq!(
+        monitor_ids, new_monitor_ids,
+        "Monitors changed while re-encrypting"
+    );
+
+    if new_password.is_empty() {
+        log::info!(
+            logger,
+            "Verified {} monitors, database is not encrypted",
+            new_monitor_ids.len()
+        );
+    } else {
+        assert_eq!(
+            mobilecoind_db.kdf_params(),
+            kdf_params,
+            "Unexpected key derivation parameters after re-encrypting"
+        );
+        log::info!(
+            logger,
+            "Verified {} monitors, database is encrypted using PBKDF2 with {} rounds",
+            new_monitor_ids.len(),
+            kdf_rounds
+        );
+    }
+}
+
+fn unlock(mobilecoind_db: &Database, password: &[u8]) {
+    // Unencrypted databases are always unlocked, and reject non-empty passwords.
+    if mobilecoind_db.is_db_encrypted() {
+        mobilecoind_db
+            .check_and_store_password(password)
+            .expect("Failed unlocking database, is the password correct?");
+    }
+}
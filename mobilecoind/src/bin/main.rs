@@ -7,9 +7,12 @@ use clap::Parser;
 use mc_attest_verifier::{MrSignerVerifier, Verifier, DEBUG_ENCLAVE};
 use mc_common::logger::{create_app_logger, log, o, Logger};
 use mc_ledger_db::{Ledger, LedgerDB};
-use mc_ledger_sync::{LedgerSyncServiceThread, PollingNetworkState, ReqwestTransactionsFetcher};
+use mc_ledger_sync::{
+    LedgerSyncServiceThread, PipelineConfig, PollingNetworkState, ReqwestTransactionsFetcher,
+};
 use mc_mobilecoind::{
-    config::Config, database::Database, payments::TransactionsManager, service::Service,
+    config::Config, database::Database, ledger_pruning::LedgerPruningThread,
+    payments::TransactionsManager, service::Service,
 };
 use mc_util_telemetry::setup_default_tracer;
 use mc_watcher::{watcher::WatcherSyncThread, watcher_db::create_or_open_rw_watcher_db};
@@ -70,6 +73,12 @@ fn main() {
             network_state.clone(),
             transactions_fetcher.clone(),
             config.poll_interval,
+            config
+                .ledger_sync_concurrency
+                .map(|num_workers| PipelineConfig {
+                    num_workers,
+                    ..Default::default()
+                }),
             logger.clone(),
         ))
     };
@@ -118,6 +127,13 @@ fn main() {
             let mobilecoind_db = Database::new(mobilecoind_db, logger.clone())
                 .expect("Could not open mobilecoind_db");
 
+            let _ledger_pruning_thread = start_ledger_pruning_thread(
+                &config,
+                &ledger_db,
+                Some(mobilecoind_db.clone()),
+                &logger,
+            );
+
             let transactions_manager = TransactionsManager::new(
                 ledger_db.clone(),
                 mobilecoind_db.clone(),
@@ -144,6 +160,9 @@ fn main() {
 
         (None, None) => {
             // No mobilecoind service, only ledger syncing.
+            let _ledger_pruning_thread =
+                start_ledger_pruning_thread(&config, &ledger_db, None, &logger);
+
             loop {
                 std::thread::sleep(config.poll_interval);
             }
@@ -157,6 +176,28 @@ fn main() {
     }
 }
 
+fn start_ledger_pruning_thread(
+    config: &Config,
+    ledger_db: &LedgerDB,
+    mobilecoind_db: Option<Database>,
+    logger: &Logger,
+) -> Option<LedgerPruningThread> {
+    let horizon = config.ledger_prune_horizon?;
+    log::info!(
+        logger,
+        "Pruning ledger TxOuts older than {} blocks, retaining one in {}",
+        horizon,
+        config.ledger_prune_retain_one_in
+    );
+    Some(LedgerPruningThread::start(
+        ledger_db.clone(),
+        mobilecoind_db,
+        horizon,
+        config.ledger_prune_retain_one_in,
+        logger.clone(),
+    ))
+}
+
 fn create_or_open_ledger_db(
     config: &Config,
     logger: &Logger,
@@ -63,6 +63,12 @@ pub struct Config {
     )]
     pub tx_source_urls: Option<Vec<String>>,
 
+    /// Number of worker threads fetching and validating blocks in parallel
+    /// during ledger sync. Blocks are then appended as soon as they are
+    /// available. By default, block contents are fetched in batches.
+    #[clap(long, env = "MC_LEDGER_SYNC_CONCURRENCY")]
+    pub ledger_sync_concurrency: Option<usize>,
+
     /// How many seconds to wait between polling.
     #[clap(long, default_value = "5", value_parser = parse_duration_in_seconds, env = "MC_POLL_INTERVAL")]
     pub poll_interval: Duration,
@@ -95,6 +101,22 @@ pub struct Config {
     #[clap(long, env = "MC_LEDGER_DB_MIGRATE")]
     pub ledger_db_migrate: bool,
 
+    /// Prune the TxOuts of blocks that are more than this many blocks old, once
+    /// every monitor has scanned them. Proofs of membership and key images are
+    /// not affected. By default the ledger is not pruned.
+    #[clap(long, env = "MC_LEDGER_PRUNE_HORIZON")]
+    pub ledger_prune_horizon: Option<u64>,
+
+    /// When pruning the ledger, retain roughly one in this many pruned TxOuts
+    /// for use as mixins.
+    #[clap(
+        long,
+        default_value = "8",
+        value_parser = clap::value_parser!(u64).range(1..),
+        env = "MC_LEDGER_PRUNE_RETAIN_ONE_IN"
+    )]
+    pub ledger_prune_retain_one_in: u64,
+
     /// An authorization token for the ipinfo.io service, if available
     #[clap(long, env = "MC_IP_INFO_TOKEN", default_value = "")]
     pub ip_info_token: String,
@@ -4,19 +4,28 @@
 //! types.
 
 use crate::{
-    payments::{Outlay, TxProposal},
+    monitor_store::DefragmentationPolicy,
+    outlay_memo_builder::OutlayMemo,
+    payments::{Outlay, OutlayV2, TxProposal},
+    tx_history_store::TxHistoryEntry,
+    utxo_selection::{
+        BranchAndBoundUtxoSelection, LargestFirstUtxoSelection, RandomUtxoSelection,
+        SmallestFirstUtxoSelection, UtxoSelectionStrategy,
+    },
     utxo_store::UnspentTxOut,
 };
-use mc_account_keys::PublicAddress;
+use mc_account_keys::{PublicAddress, ViewAccountKey};
 use mc_api::ConversionError;
 use mc_common::HashMap;
+use mc_crypto_keys::{RistrettoPrivate, RistrettoPublic};
 use mc_mobilecoind_api as api;
 use mc_transaction_core::{
     ring_signature::KeyImage,
     tx::{Tx, TxOut},
+    Amount, TokenId,
 };
 use mc_transaction_extra::TxOutConfirmationNumber;
-use protobuf::RepeatedField;
+use protobuf::{ProtobufEnum, RepeatedField};
 
 impl From<&UnspentTxOut> for api::UnspentTxOut {
     fn from(src: &UnspentTxOut) -> Self {
@@ -29,6 +38,7 @@ impl From<&UnspentTxOut> for api::UnspentTxOut {
         dst.set_attempted_spend_height(src.attempted_spend_height);
         dst.set_attempted_spend_tombstone(src.attempted_spend_tombstone);
         dst.set_token_id(src.token_id);
+        dst.set_key_image_unknown(src.key_image_unknown);
 
         dst
     }
@@ -45,6 +55,7 @@ impl TryFrom<&api::UnspentTxOut> for UnspentTxOut {
         let attempted_spend_height = src.attempted_spend_height;
         let attempted_spend_tombstone = src.attempted_spend_tombstone;
         let token_id = src.token_id;
+        let key_image_unknown = src.key_image_unknown;
 
         Ok(Self {
             tx_out,
@@ -54,10 +65,33 @@ impl TryFrom<&api::UnspentTxOut> for UnspentTxOut {
             attempted_spend_height,
             attempted_spend_tombstone,
             token_id,
+            key_image_unknown,
         })
     }
 }
 
+impl From<&ViewAccountKey> for api::ViewAccountKey {
+    fn from(src: &ViewAccountKey) -> Self {
+        let mut dst = Self::new();
+
+        dst.set_view_private_key(src.view_private_key().into());
+        dst.set_spend_public_key(src.spend_public_key().into());
+
+        dst
+    }
+}
+
+impl TryFrom<&api::ViewAccountKey> for ViewAccountKey {
+    type Error = ConversionError;
+
+    fn try_from(src: &api::ViewAccountKey) -> Result<Self, Self::Error> {
+        let view_private_key = RistrettoPrivate::try_from(src.get_view_private_key())?;
+        let spend_public_key = RistrettoPublic::try_from(src.get_spend_public_key())?;
+
+        Ok(Self::new(view_private_key, spend_public_key))
+    }
+}
+
 impl From<&Outlay> for api::Outlay {
     fn from(src: &Outlay) -> Self {
         let mut dst = Self::new();
@@ -80,6 +114,115 @@ impl TryFrom<&api::Outlay> for Outlay {
     }
 }
 
+impl From<&api::OutlayMemo> for OutlayMemo {
+    fn from(src: &api::OutlayMemo) -> Self {
+        match src.get_memo_type() {
+            api::OutlayMemoType::OutlayMemoNone => Self::None,
+            api::OutlayMemoType::OutlayMemoSender => Self::Sender,
+            api::OutlayMemoType::OutlayMemoSenderWithPaymentRequestId => {
+                Self::SenderWithPaymentRequestId(src.id)
+            }
+            api::OutlayMemoType::OutlayMemoSenderWithPaymentIntentId => {
+                Self::SenderWithPaymentIntentId(src.id)
+            }
+        }
+    }
+}
+
+impl From<&DefragmentationPolicy> for api::DefragmentationPolicy {
+    fn from(src: &DefragmentationPolicy) -> Self {
+        let mut dst = Self::new();
+
+        dst.set_subaddress_index(src.subaddress_index);
+        dst.set_token_id(src.token_id);
+        dst.set_target_utxo_count(src.target_utxo_count);
+        dst.set_max_fee_budget(src.max_fee_budget);
+
+        dst
+    }
+}
+
+impl From<&api::DefragmentationPolicy> for DefragmentationPolicy {
+    fn from(src: &api::DefragmentationPolicy) -> Self {
+        Self {
+            subaddress_index: src.subaddress_index,
+            token_id: src.token_id,
+            target_utxo_count: src.target_utxo_count,
+            max_fee_budget: src.max_fee_budget,
+        }
+    }
+}
+
+impl From<&OutlayV2> for api::OutlayV2 {
+    fn from(src: &OutlayV2) -> Self {
+        let mut dst = Self::new();
+
+        dst.set_value(src.amount.value);
+        dst.set_receiver((&src.receiver).into());
+        dst.set_token_id(*src.amount.token_id);
+
+        dst
+    }
+}
+
+impl TryFrom<&api::OutlayV2> for OutlayV2 {
+    type Error = ConversionError;
+
+    fn try_from(src: &api::OutlayV2) -> Result<Self, Self::Error> {
+        let amount = Amount::new(src.value, TokenId::from(src.token_id));
+        let receiver = PublicAddress::try_from(src.get_receiver())?;
+
+        Ok(Self { amount, receiver })
+    }
+}
+
+impl From<api::UtxoSelectionStrategy> for Box<dyn UtxoSelectionStrategy> {
+    fn from(src: api::UtxoSelectionStrategy) -> Self {
+        match src {
+            api::UtxoSelectionStrategy::SmallestFirst => Box::new(SmallestFirstUtxoSelection),
+            api::UtxoSelectionStrategy::LargestFirst => Box::new(LargestFirstUtxoSelection),
+            api::UtxoSelectionStrategy::BranchAndBound => {
+                Box::new(BranchAndBoundUtxoSelection::default())
+            }
+            api::UtxoSelectionStrategy::Random => Box::new(RandomUtxoSelection),
+        }
+    }
+}
+
+impl From<&TxHistoryEntry> for api::TransactionHistoryEntry {
+    fn from(src: &TxHistoryEntry) -> Self {
+        let mut dst = Self::new();
+
+        dst.set_block_index(src.block_index);
+        dst.set_direction(
+            api::ProcessedTxOutDirection::from_i32(src.direction)
+                .unwrap_or(api::ProcessedTxOutDirection::Invalid),
+        );
+        dst.set_subaddress_index(src.subaddress_index);
+        dst.set_public_key((&src.public_key).into());
+        dst.set_key_image((&src.key_image).into());
+        dst.set_value(src.value);
+        dst.set_token_id(src.token_id);
+        dst.set_memo_type(
+            api::TransactionHistoryMemoType::from_i32(src.memo_type)
+                .unwrap_or(api::TransactionHistoryMemoType::MemoTypeUnknown),
+        );
+        if let Some(address_hash) = src.address_hash.as_ref() {
+            dst.set_address_hash(address_hash.clone());
+        }
+        if let Some(payment_request_id) = src.payment_request_id {
+            dst.set_has_payment_request_id(true);
+            dst.set_payment_request_id(payment_request_id);
+        }
+        if let Some(payment_intent_id) = src.payment_intent_id {
+            dst.set_has_payment_intent_id(true);
+            dst.set_payment_intent_id(payment_intent_id);
+        }
+
+        dst
+    }
+}
+
 impl From<&TxProposal> for api::TxProposal {
     fn from(src: &TxProposal) -> api::TxProposal {
         let mut dst = api::TxProposal::new();
@@ -175,7 +318,6 @@ impl TryFrom<&api::TxProposal> for TxProposal {
 mod test {
     use super::*;
     use mc_account_keys::AccountKey;
-    use mc_crypto_keys::RistrettoPrivate;
     use mc_ledger_db::{
         test_utils::{create_ledger, create_transaction, initialize_ledger},
         Ledger,
@@ -216,6 +358,7 @@ mod test {
             attempted_spend_height,
             attempted_spend_tombstone,
             token_id: *Mob::ID,
+            key_image_unknown: true,
         };
 
         let proto = api::UnspentTxOut::from(&rust);
@@ -229,11 +372,32 @@ mod test {
         assert_eq!(value, proto.value);
         assert_eq!(attempted_spend_height, proto.attempted_spend_height);
         assert_eq!(attempted_spend_tombstone, proto.attempted_spend_tombstone);
+        assert!(proto.key_image_unknown);
 
         // Proto -> Rust
         assert_eq!(rust, UnspentTxOut::try_from(&proto).unwrap());
     }
 
+    #[test]
+    fn test_view_account_key_conversion() {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let rust = ViewAccountKey::from(&AccountKey::random(&mut rng));
+
+        // Rust -> Proto
+        let proto = api::ViewAccountKey::from(&rust);
+        assert_eq!(
+            rust.view_private_key(),
+            &RistrettoPrivate::try_from(proto.get_view_private_key()).unwrap()
+        );
+        assert_eq!(
+            rust.spend_public_key(),
+            &RistrettoPublic::try_from(proto.get_spend_public_key()).unwrap()
+        );
+
+        // Proto -> Rust
+        assert_eq!(rust, ViewAccountKey::try_from(&proto).unwrap());
+    }
+
     #[test]
     fn test_outlay_conversion() {
         let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
@@ -256,6 +420,29 @@ mod test {
         assert_eq!(rust, Outlay::try_from(&proto).unwrap());
     }
 
+    #[test]
+    fn test_outlay_v2_conversion() {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let public_addr = AccountKey::random(&mut rng).default_subaddress();
+
+        // Rust -> Proto
+        let rust = OutlayV2 {
+            receiver: public_addr.clone(),
+            amount: Amount::new(1234, TokenId::from(5)),
+        };
+        let proto = api::OutlayV2::from(&rust);
+
+        assert_eq!(proto.value, rust.amount.value);
+        assert_eq!(proto.token_id, 5);
+        assert_eq!(
+            PublicAddress::try_from(proto.get_receiver()).unwrap(),
+            public_addr
+        );
+
+        // Proto -> Rust
+        assert_eq!(rust, OutlayV2::try_from(&proto).unwrap());
+    }
+
     #[test]
     fn test_tx_proposal_conversion() {
         let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
@@ -308,6 +495,7 @@ mod test {
                 attempted_spend_height,
                 attempted_spend_tombstone,
                 token_id: *Mob::ID,
+                key_image_unknown: false,
             }
         };
 
@@ -3,11 +3,14 @@
 //! The mobilecoind database
 
 use crate::{
-    db_crypto::DbCryptoProvider,
+    db_crypto::{DbCryptoProvider, KdfParams},
     error::Error,
-    monitor_store::{MonitorData, MonitorId, MonitorStore},
+    monitor_store::{
+        DefragmentationPolicy, DefragmentationProgress, MonitorData, MonitorId, MonitorStore,
+    },
     processed_block_store::{ProcessedBlockStore, ProcessedTxOut},
     subaddress_store::{SubaddressId, SubaddressSPKId, SubaddressStore},
+    tx_history_store::{TxHistoryEntry, TxHistoryKey, TxHistoryStore},
     utxo_store::{UtxoId, UtxoStore},
 };
 
@@ -17,9 +20,14 @@ use mc_common::{
     logger::{log, Logger},
     HashMap,
 };
+use mc_crypto_keys::CompressedRistrettoPublic;
 use mc_transaction_core::ring_signature::KeyImage;
 use mc_util_lmdb::{MetadataStore, MetadataStoreSettings};
-use std::{path::Path, sync::Arc};
+use std::{
+    path::Path,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
 
 // LMDB Constants
 const MAX_LMDB_FILE_SIZE: usize = 1_099_511_627_776; // 1 TB
@@ -63,6 +71,17 @@ pub struct Database {
     /// Processed block store.
     processed_block_store: ProcessedBlockStore,
 
+    /// Tx history store.
+    tx_history_store: TxHistoryStore,
+
+    /// Counts the blocks processed since the database was opened, and wakes up
+    /// anyone waiting for that count to change.
+    blocks_processed: Arc<(Mutex<u64>, Condvar)>,
+
+    /// Serializes adding monitors with pruning the ledger, see
+    /// `lock_pruning`.
+    pruning_lock: Arc<Mutex<()>>,
+
     /// Logger.
     logger: Logger,
 }
@@ -97,6 +116,7 @@ impl Database {
         let subaddress_store = SubaddressStore::new(env.clone(), logger.clone())?;
         let utxo_store = UtxoStore::new(env.clone(), logger.clone())?;
         let processed_block_store = ProcessedBlockStore::new(env.clone(), logger.clone())?;
+        let tx_history_store = TxHistoryStore::new(env.clone(), logger.clone())?;
 
         Ok(Self {
             env,
@@ -105,6 +125,9 @@ impl Database {
             subaddress_store,
             utxo_store,
             processed_block_store,
+            tx_history_store,
+            blocks_processed: Arc::new((Mutex::new(0), Condvar::new())),
+            pruning_lock: Arc::new(Mutex::new(())),
             logger,
         })
     }
@@ -132,17 +155,58 @@ impl Database {
     /// since part of the re-encryption process relies on being able to
     /// decrypt the existing data.
     pub fn re_encrypt(&self, new_password: &[u8]) -> Result<(), Error> {
+        self.re_encrypt_with_kdf_params(new_password, &KdfParams::recommended())
+    }
+
+    /// Re-encrypt the encrypted parts of the database with a new password,
+    /// deriving the encryption key with the given parameters. The parameters
+    /// are stored in the database, so that they can later be changed.
+    pub fn re_encrypt_with_kdf_params(
+        &self,
+        new_password: &[u8],
+        new_kdf_params: &KdfParams,
+    ) -> Result<(), Error> {
         let mut db_txn = self.env.begin_rw_txn()?;
 
         // Currently only the monitor store stores encrypted data.
-        self.monitor_store.re_encrypt(&mut db_txn, new_password)?;
-
-        // set_password consumes the transaction to ensure atomicity.
-        self.crypto_provider.change_password(db_txn, new_password)?;
+        self.monitor_store
+            .re_encrypt(&mut db_txn, new_password, new_kdf_params)?;
+
+        // change_password_with_kdf_params consumes the transaction to ensure
+        // atomicity.
+        self.crypto_provider.change_password_with_kdf_params(
+            db_txn,
+            new_password,
+            new_kdf_params,
+        )?;
 
         Ok(())
     }
 
+    /// The key derivation parameters the database is currently encrypted with.
+    pub fn kdf_params(&self) -> KdfParams {
+        self.crypto_provider.kdf_params()
+    }
+
+    /// Decrypt and decode every monitor record, checking that each one is
+    /// stored under the id derived from its contents. Returns the ids of the
+    /// monitors. This fails if the database is locked.
+    pub fn verify_monitor_data(&self) -> Result<Vec<MonitorId>, Error> {
+        let db_txn = self.env.begin_ro_txn()?;
+        let monitor_map = self.monitor_store.get_map(&db_txn)?;
+
+        let mut monitor_ids = Vec::with_capacity(monitor_map.len());
+        for (monitor_id, data) in monitor_map {
+            if MonitorId::try_from(&data)? != monitor_id {
+                return Err(Error::MonitorDataMismatch(monitor_id.to_string()));
+            }
+            monitor_ids.push(monitor_id);
+        }
+        monitor_ids.sort();
+
+        Ok(monitor_ids)
+    }
+
     pub fn add_monitor(&self, data: &MonitorData) -> Result<MonitorId, Error> {
         mc_common::trace_time!(self.logger, "add_monitor");
 
@@ -172,6 +236,7 @@ impl Database {
         }
 
         self.processed_block_store.remove(&mut db_txn, id)?;
+        self.tx_history_store.remove(&mut db_txn, id)?;
 
         self.monitor_store.remove(&mut db_txn, id)?;
 
@@ -195,6 +260,50 @@ impl Database {
         self.monitor_store.get_ids(&db_txn)
     }
 
+    /// Set or clear the defragmentation policy of a monitor. This resets the
+    /// progress of the policy, so a new fee budget starts from zero.
+    pub fn set_defragmentation_policy(
+        &self,
+        id: &MonitorId,
+        policy: Option<DefragmentationPolicy>,
+    ) -> Result<(), Error> {
+        let mut db_txn = self.env.begin_rw_txn()?;
+
+        let mut data = self.monitor_store.get_data(&db_txn, id)?;
+        if let Some(policy) = policy.as_ref() {
+            if !data.subaddress_indexes().contains(&policy.subaddress_index) {
+                return Err(Error::InvalidArgument(
+                    "subaddress_index".to_string(),
+                    "not monitored by this monitor".to_string(),
+                ));
+            }
+        }
+
+        data.defragmentation_progress = policy.as_ref().map(|_| DefragmentationProgress::default());
+        data.defragmentation_policy = policy;
+        self.monitor_store.set_data(&mut db_txn, id, &data)?;
+
+        db_txn.commit()?;
+        Ok(())
+    }
+
+    /// Record that an optimization transaction paying `fee` was submitted on
+    /// behalf of a monitor's defragmentation policy.
+    pub fn record_defragmentation_tx(&self, id: &MonitorId, fee: u64) -> Result<(), Error> {
+        let mut db_txn = self.env.begin_rw_txn()?;
+
+        let mut data = self.monitor_store.get_data(&db_txn, id)?;
+        let progress = data
+            .defragmentation_progress
+            .get_or_insert_with(Default::default);
+        progress.num_txs_submitted += 1;
+        progress.fees_spent = progress.fees_spent.saturating_add(fee);
+        self.monitor_store.set_data(&mut db_txn, id, &data)?;
+
+        db_txn.commit()?;
+        Ok(())
+    }
+
     pub fn get_subaddress_id_by_spk(
         &self,
         subaddress_spk: &SubaddressSPKId,
@@ -239,6 +348,49 @@ impl Database {
         Ok(())
     }
 
+    /// Supply the real key image of a TxOut owned by a view-only monitor,
+    /// replacing the placeholder that was stored when the TxOut was
+    /// discovered. Returns the updated UnspentTxOut.
+    pub fn set_utxo_key_image(
+        &self,
+        monitor_id: &MonitorId,
+        tx_out_public_key: &CompressedRistrettoPublic,
+        key_image: &KeyImage,
+    ) -> Result<UnspentTxOut, Error> {
+        let mut db_txn = self.env.begin_rw_txn()?;
+
+        let placeholder_key_image = UnspentTxOut::placeholder_key_image(tx_out_public_key);
+        let utxo = self.utxo_store.set_key_image(
+            &mut db_txn,
+            monitor_id,
+            &placeholder_key_image,
+            key_image,
+        )?;
+
+        db_txn.commit()?;
+
+        Ok(utxo)
+    }
+
+    /// Remove the utxos matching a list of key images that are known to have
+    /// been spent, outside of regular block processing. Returns the list of
+    /// removed utxos.
+    pub fn remove_utxos_by_key_images(
+        &self,
+        monitor_id: &MonitorId,
+        key_images: &[KeyImage],
+    ) -> Result<Vec<UnspentTxOut>, Error> {
+        let mut db_txn = self.env.begin_rw_txn()?;
+
+        let removed_utxos =
+            self.utxo_store
+                .remove_utxos_by_key_images(&mut db_txn, monitor_id, key_images)?;
+
+        db_txn.commit()?;
+
+        Ok(removed_utxos)
+    }
+
     /// Feed data processed from a given block into the various stores.
     pub fn block_processed(
         &self,
@@ -290,9 +442,26 @@ impl Database {
             &removed_utxos,
         )?;
 
+        // Update tx history store.
+        self.tx_history_store.block_processed(
+            &mut db_txn,
+            monitor_id,
+            monitor_data.view_private_key()?,
+            block_num,
+            discovered_utxos,
+            &removed_utxos,
+        )?;
+
         // Commit.
         db_txn.commit()?;
 
+        // Wake up anyone waiting for new blocks.
+        {
+            let (count, condvar) = &*self.blocks_processed;
+            *count.lock().expect("mutex poisoned") += 1;
+            condvar.notify_all();
+        }
+
         // Success.
         if discovered_utxos.is_empty() && removed_utxos.is_empty() {
             log::debug!(
@@ -316,6 +485,33 @@ impl Database {
         Ok(())
     }
 
+    /// The number of blocks processed, across all monitors, since the database
+    /// was opened. Pass this to `wait_for_blocks_processed` to wait for more
+    /// blocks to be processed.
+    pub fn num_blocks_processed(&self) -> u64 {
+        *self.blocks_processed.0.lock().expect("mutex poisoned")
+    }
+
+    /// Wait until more than `num_blocks_processed` blocks have been processed,
+    /// or until the timeout elapses.
+    pub fn wait_for_blocks_processed(&self, num_blocks_processed: u64, timeout: Duration) {
+        let (count, condvar) = &*self.blocks_processed;
+        let _ = condvar
+            .wait_timeout_while(count.lock().expect("mutex poisoned"), timeout, |count| {
+                *count <= num_blocks_processed
+            })
+            .expect("mutex poisoned");
+    }
+
+    /// Lock out ledger pruning, or adding monitors while the ledger is being
+    /// pruned. The ledger pruner holds this from reading the monitors until
+    /// its pruning is committed, and adding a monitor holds it from checking
+    /// the monitor's first block against the pruned blocks until the monitor
+    /// is stored, so that no blocks a new monitor still needs get pruned.
+    pub fn lock_pruning(&self) -> MutexGuard<()> {
+        self.pruning_lock.lock().expect("mutex poisoned")
+    }
+
     /// Get processed block information for a given (monitor id, block number).
     pub fn get_processed_block(
         &self,
@@ -342,6 +538,25 @@ impl Database {
         self.processed_block_store
             .get_processed_block(&db_txn, monitor_id, block_num)
     }
+
+    /// Get up to `max_entries` tx history entries of a given monitor id, in
+    /// block order, starting at `start_key`. If `exclude_start_key` is set, an
+    /// entry stored under exactly `start_key` is skipped.
+    pub fn get_tx_history(
+        &self,
+        start_key: &TxHistoryKey,
+        exclude_start_key: bool,
+        max_entries: usize,
+    ) -> Result<Vec<TxHistoryEntry>, Error> {
+        let db_txn = self.env.begin_ro_txn()?;
+
+        // Make sure the monitor exists.
+        self.monitor_store
+            .get_data(&db_txn, &start_key.monitor_id)?;
+
+        self.tx_history_store
+            .get_entries(&db_txn, start_key, exclude_start_key, max_entries)
+    }
 }
 
 #[cfg(test)]
@@ -518,6 +733,126 @@ mod test {
         );
     }
 
+    // Test that monitor data can be verified after re-keying and reopening the
+    // database.
+    #[test_with_logger]
+    fn test_verify_monitor_data(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([123u8; 32]);
+
+        let mobilecoind_db_tmp =
+            TempDir::new("mobilecoind_db").expect("Could not make tempdir for mobilecoind db");
+        let mobilecoind_db_path = mobilecoind_db_tmp
+            .path()
+            .to_str()
+            .expect("Could not get path as string");
+
+        let mobilecoind_db = Database::new(mobilecoind_db_path, logger.clone())
+            .expect("failed creating new mobilecoind db");
+
+        let mut monitor_ids: Vec<MonitorId> = (0..2)
+            .map(|_| {
+                let data = MonitorData::new(
+                    AccountKey::random(&mut rng),
+                    0,  // first_subaddress
+                    10, // num_subaddresses
+                    0,  // first_block
+                    "", // name
+                )
+                .unwrap();
+                mobilecoind_db.add_monitor(&data).unwrap()
+            })
+            .collect();
+        monitor_ids.sort();
+
+        assert_eq!(mobilecoind_db.verify_monitor_data().unwrap(), monitor_ids);
+
+        // Encrypt, and then re-key with a different password.
+        for password in [[5; 32], [6; 32]] {
+            mobilecoind_db.re_encrypt(&password).unwrap();
+            assert_eq!(mobilecoind_db.verify_monitor_data().unwrap(), monitor_ids);
+        }
+
+        // Reopening requires the latest password to read the monitor data.
+        drop(mobilecoind_db);
+        let mobilecoind_db = Database::new(mobilecoind_db_path, logger.clone())
+            .expect("failed opening mobilecoind db");
+        assert!(mobilecoind_db.verify_monitor_data().is_err());
+        assert!(mobilecoind_db.check_and_store_password(&[5; 32]).is_err());
+        mobilecoind_db.check_and_store_password(&[6; 32]).unwrap();
+        assert_eq!(mobilecoind_db.verify_monitor_data().unwrap(), monitor_ids);
+
+        // Decrypt.
+        mobilecoind_db.re_encrypt(&[]).unwrap();
+        drop(mobilecoind_db);
+        let mobilecoind_db =
+            Database::new(mobilecoind_db_path, logger).expect("failed opening mobilecoind db");
+        assert!(!mobilecoind_db.is_db_encrypted());
+        assert_eq!(mobilecoind_db.verify_monitor_data().unwrap(), monitor_ids);
+    }
+
+    // Test that re-keying can change the key derivation parameters, including
+    // of databases encrypted before the parameters were stored.
+    #[test_with_logger]
+    fn test_re_encrypt_with_kdf_params(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([123u8; 32]);
+
+        let mobilecoind_db_tmp =
+            TempDir::new("mobilecoind_db").expect("Could not make tempdir for mobilecoind db");
+        let mobilecoind_db_path = mobilecoind_db_tmp
+            .path()
+            .to_str()
+            .expect("Could not get path as string");
+
+        let mobilecoind_db = Database::new(mobilecoind_db_path, logger.clone())
+            .expect("failed creating new mobilecoind db");
+        let data = MonitorData::new(
+            AccountKey::random(&mut rng),
+            0,  // first_subaddress
+            10, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+        let monitor_ids = vec![mobilecoind_db.add_monitor(&data).unwrap()];
+
+        // Encrypt with the Blake2b derivation older databases use.
+        mobilecoind_db
+            .re_encrypt_with_kdf_params(&[5; 32], &KdfParams::default())
+            .unwrap();
+        drop(mobilecoind_db);
+        let mobilecoind_db = Database::new(mobilecoind_db_path, logger.clone())
+            .expect("failed opening mobilecoind db");
+        assert_eq!(mobilecoind_db.kdf_params(), KdfParams::default());
+        mobilecoind_db.check_and_store_password(&[5; 32]).unwrap();
+        assert_eq!(mobilecoind_db.verify_monitor_data().unwrap(), monitor_ids);
+
+        // Re-key with PBKDF2, keeping the same password.
+        let kdf_params = KdfParams::pbkdf2(10);
+        mobilecoind_db
+            .re_encrypt_with_kdf_params(&[5; 32], &kdf_params)
+            .unwrap();
+        assert_eq!(mobilecoind_db.kdf_params(), kdf_params);
+        assert_eq!(mobilecoind_db.verify_monitor_data().unwrap(), monitor_ids);
+
+        // The parameters are read back when reopening.
+        drop(mobilecoind_db);
+        let mobilecoind_db = Database::new(mobilecoind_db_path, logger.clone())
+            .expect("failed opening mobilecoind db");
+        assert_eq!(mobilecoind_db.kdf_params(), kdf_params);
+        assert!(mobilecoind_db.verify_monitor_data().is_err());
+        mobilecoind_db.check_and_store_password(&[5; 32]).unwrap();
+        assert_eq!(mobilecoind_db.verify_monitor_data().unwrap(), monitor_ids);
+
+        // Decrypting removes the parameters.
+        mobilecoind_db.re_encrypt(&[]).unwrap();
+        drop(mobilecoind_db);
+        let mobilecoind_db =
+            Database::new(mobilecoind_db_path, logger).expect("failed opening mobilecoind db");
+        assert!(!mobilecoind_db.is_db_encrypted());
+        assert_eq!(mobilecoind_db.kdf_params(), KdfParams::default());
+        assert_eq!(mobilecoind_db.verify_monitor_data().unwrap(), monitor_ids);
+    }
+
     // Inserting a monitor that overlaps subaddresses of another monitor should
     // result in an error.
     #[test_with_logger]
@@ -3,18 +3,19 @@
 //! Helper for managing database encryption.
 
 use aes_gcm::{
-    aead::{
-        generic_array::{sequence::Split, GenericArray},
-        Aead,
-    },
+    aead::{generic_array::GenericArray, Aead},
     AeadCore, Aes256Gcm, Error as AeadError, NewAead,
 };
 use displaydoc::Display;
+use hmac::Hmac;
 use lmdb::{
     Database, DatabaseFlags, Environment, Error as LmdbError, RwTransaction, Transaction,
     WriteFlags,
 };
 use mc_crypto_hashes::{Blake2b512, Digest};
+use mc_crypto_rand::{McRng, RngCore};
+use prost::{DecodeError, Enumeration, Message};
+use sha2::Sha512;
 use std::sync::{Arc, Mutex};
 
 /// Domain tag for database-wide encryption.
@@ -25,6 +26,16 @@ pub const MOBILECOIND_DB_KEY_DOMAIN_TAG: &str = "mc_mobilecoind";
 /// hash of a password and not the actual password the user typed.
 pub const PASSWORD_LEN: usize = 32;
 
+/// Number of PBKDF2 rounds used when a password is set without explicit key
+/// derivation parameters.
+#[cfg(not(test))]
+pub const DEFAULT_PBKDF2_ROUNDS: u32 = 100_000;
+#[cfg(test)]
+pub const DEFAULT_PBKDF2_ROUNDS: u32 = 1_000;
+
+/// Length of the random PBKDF2 salt.
+const PBKDF2_SALT_LEN: usize = 32;
+
 /// LMDB database name for storing metadata.
 const CRYPTO_DB_NAME: &str = "db_crypto";
 
@@ -32,6 +43,14 @@ const CRYPTO_DB_NAME: &str = "db_crypto";
 const ENCRYPTION_STATE_KEY: &str = "db_encrypted";
 const ENCRYPTION_STATE_VAL: &str = "true";
 
+/// Key used for storing the key derivation parameters. Databases encrypted
+/// before these were recorded do not have it, and use the default
+/// (Blake2b) parameters.
+const KDF_PARAMS_KEY: &str = "kdf_params";
+
+type EncryptionKey = GenericArray<u8, <Aes256Gcm as NewAead>::KeySize>;
+type EncryptionNonce = GenericArray<u8, <Aes256Gcm as AeadCore>::NonceSize>;
+
 /// Possible db crypto error types.
 #[derive(Debug, Display)]
 pub enum DbCryptoError {
@@ -44,11 +63,17 @@ pub enum DbCryptoError {
     /// Password needed
     PasswordNeeded,
 
+    /// Unsupported key derivation parameters: algorithm {0}, {1} rounds
+    UnsupportedKdfParams(i32, u32),
+
     /// AEAD: {0}
     Aead(AeadError),
 
     /// LMDB: {0}
     Lmdb(LmdbError),
+
+    /// Decode: {0}
+    Decode(DecodeError),
 }
 
 impl From<AeadError> for DbCryptoError {
@@ -63,16 +88,126 @@ impl From<LmdbError> for DbCryptoError {
     }
 }
 
+impl From<DecodeError> for DbCryptoError {
+    fn from(src: DecodeError) -> Self {
+        Self::Decode(src)
+    }
+}
+
+/// Algorithm used for deriving the encryption key from the password.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Enumeration)]
+pub enum KdfAlgorithm {
+    /// A single Blake2b hash of the password. Only used by databases that were
+    /// encrypted before key derivation parameters were recorded.
+    Blake2b = 0,
+
+    /// PBKDF2-HMAC-SHA512 with a random salt.
+    Pbkdf2HmacSha512 = 1,
+}
+
+/// Key derivation parameters, stored in the database alongside the password
+/// test value. The default value describes the Blake2b derivation used by
+/// databases that do not have parameters stored.
+#[derive(Clone, Eq, Hash, PartialEq, Message)]
+pub struct KdfParams {
+    /// The key derivation algorithm.
+    #[prost(enumeration = "KdfAlgorithm", tag = "1")]
+    pub algorithm: i32,
+
+    /// Number of rounds, for iterated algorithms.
+    #[prost(uint32, tag = "2")]
+    pub rounds: u32,
+
+    /// Salt, for salted algorithms.
+    #[prost(bytes, tag = "3")]
+    pub salt: Vec<u8>,
+}
+
+impl KdfParams {
+    /// PBKDF2-HMAC-SHA512 parameters with the given number of rounds and a
+    /// fresh random salt.
+    pub fn pbkdf2(rounds: u32) -> Self {
+        let mut salt = vec![0u8; PBKDF2_SALT_LEN];
+        McRng::default().fill_bytes(&mut salt);
+        Self {
+            algorithm: KdfAlgorithm::Pbkdf2HmacSha512 as i32,
+            rounds,
+            salt,
+        }
+    }
+
+    /// The parameters used for newly set passwords.
+    pub fn recommended() -> Self {
+        Self::pbkdf2(DEFAULT_PBKDF2_ROUNDS)
+    }
+
+    /// Derive an encryption key and a nonce from a password.
+    pub fn derive_key(&self, password: &[u8]) -> Result<DbEncryptionKey, DbCryptoError> {
+        if password.len() != PASSWORD_LEN {
+            return Err(DbCryptoError::InvalidPasswordLength);
+        }
+
+        // 64 bytes of output, first 32 for the aes key, next 12 for the nonce.
+        let mut output = [0u8; 64];
+        match KdfAlgorithm::from_i32(self.algorithm) {
+            Some(KdfAlgorithm::Blake2b) => {
+                let mut hasher = Blake2b512::new();
+                hasher.update(&MOBILECOIND_DB_KEY_DOMAIN_TAG);
+                hasher.update(&password);
+                output.copy_from_slice(&hasher.finalize());
+            }
+            Some(KdfAlgorithm::Pbkdf2HmacSha512) if self.rounds > 0 => {
+                pbkdf2::pbkdf2::<Hmac<Sha512>>(password, &self.salt, self.rounds, &mut output);
+            }
+            _ => {
+                return Err(DbCryptoError::UnsupportedKdfParams(
+                    self.algorithm,
+                    self.rounds,
+                ))
+            }
+        }
+
+        let (key_bytes, nonce_bytes) = output.split_at(32);
+        Ok(DbEncryptionKey {
+            key: EncryptionKey::clone_from_slice(key_bytes),
+            nonce: EncryptionNonce::clone_from_slice(&nonce_bytes[..12]),
+        })
+    }
+}
+
+/// An encryption key and nonce derived from a password.
+#[derive(Clone)]
+pub struct DbEncryptionKey {
+    key: EncryptionKey,
+    nonce: EncryptionNonce,
+}
+
+impl DbEncryptionKey {
+    /// Encrypt data with this key.
+    pub fn encrypt(&self, plaintext_bytes: &[u8]) -> Result<Vec<u8>, DbCryptoError> {
+        let cipher = Aes256Gcm::new(&self.key);
+        Ok(cipher.encrypt(&self.nonce, plaintext_bytes)?)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DbCryptoError> {
+        let cipher = Aes256Gcm::new(&self.key);
+        Ok(cipher.decrypt(&self.nonce, ciphertext)?)
+    }
+}
+
 /// Database crypto state that is shared between multiple threads.
 struct DbCryptoProviderState {
     /// Is the database currently encrypted?
     is_db_encrypted: bool,
 
+    /// The key derivation parameters the database is encrypted with.
+    kdf_params: KdfParams,
+
     /// The current encryption key, stored inside Arc/Mutex so that this object
     /// could be safely shared.
     /// This should only be set once the password has been determined to be
     /// valid!
-    encryption_key: Vec<u8>,
+    encryption_key: Option<DbEncryptionKey>,
 }
 
 /// Database encryption helper.
@@ -93,32 +228,42 @@ impl DbCryptoProvider {
     pub fn new(env: Arc<Environment>) -> Result<Self, DbCryptoError> {
         let database = env.create_db(Some(CRYPTO_DB_NAME), DatabaseFlags::empty())?;
 
+        let db_txn = env.begin_ro_txn()?;
+
         // Check if the database is currently encrypted.
-        let is_db_encrypted = {
-            let db_txn = env.begin_ro_txn()?;
-            match db_txn.get(database, &ENCRYPTION_STATE_KEY.as_bytes()) {
-                Ok(_test_val) => {
-                    // The encryption indicator key is present in the database, this means
-                    // encryption is enabled.
-                    true
-                }
-                Err(LmdbError::NotFound) => {
-                    // The encryption indicator key is not in the database, this means encryption is
-                    // not enabled.
-                    false
-                }
-                Err(err) => {
-                    return Err(err.into());
-                }
+        let is_db_encrypted = match db_txn.get(database, &ENCRYPTION_STATE_KEY.as_bytes()) {
+            Ok(_test_val) => {
+                // The encryption indicator key is present in the database, this means
+                // encryption is enabled.
+                true
+            }
+            Err(LmdbError::NotFound) => {
+                // The encryption indicator key is not in the database, this means encryption is
+                // not enabled.
+                false
+            }
+            Err(err) => {
+                return Err(err.into());
+            }
+        };
+
+        let kdf_params = match db_txn.get(database, &KDF_PARAMS_KEY.as_bytes()) {
+            Ok(kdf_params_bytes) => mc_util_serial::decode(kdf_params_bytes)?,
+            Err(LmdbError::NotFound) => KdfParams::default(),
+            Err(err) => {
+                return Err(err.into());
             }
         };
 
+        drop(db_txn);
+
         Ok(Self {
             env,
             database,
             state: Arc::new(Mutex::new(DbCryptoProviderState {
                 is_db_encrypted,
-                encryption_key: vec![],
+                kdf_params,
+                encryption_key: None,
             })),
         })
     }
@@ -129,6 +274,12 @@ impl DbCryptoProvider {
         state.is_db_encrypted
     }
 
+    /// The key derivation parameters the database is currently encrypted with.
+    pub fn kdf_params(&self) -> KdfParams {
+        let state = self.state.lock().expect("mutex poisoned");
+        state.kdf_params.clone()
+    }
+
     /// Check if a given password is the password used to encrypt data in the
     /// db, and if so store it for future encryption/decryption operations.
     pub fn check_and_store_password(&self, password: &[u8]) -> Result<(), DbCryptoError> {
@@ -138,10 +289,10 @@ impl DbCryptoProvider {
             // password.
             let db_txn = self.env.begin_ro_txn()?;
             let test_val = db_txn.get(self.database, &ENCRYPTION_STATE_KEY.as_bytes())?;
-            let expected_val =
-                self.encrypt_with_password(password, ENCRYPTION_STATE_VAL.as_bytes())?;
+            let key = state.kdf_params.derive_key(password)?;
+            let expected_val = key.encrypt(ENCRYPTION_STATE_VAL.as_bytes())?;
             if test_val == expected_val {
-                state.encryption_key = password.to_vec();
+                state.encryption_key = Some(key);
                 Ok(())
             } else {
                 Err(DbCryptoError::InvalidPassword)
@@ -149,7 +300,7 @@ impl DbCryptoProvider {
         } else {
             // Db is not encrypted, password should be empty.
             if password.is_empty() {
-                assert!(state.encryption_key.is_empty());
+                assert!(state.encryption_key.is_none());
                 Ok(())
             } else {
                 Err(DbCryptoError::InvalidPassword)
@@ -164,51 +315,79 @@ impl DbCryptoProvider {
         let state = self.state.lock().expect("mutex poisoned");
         if state.is_db_encrypted {
             // We're encrypted, and only unlocked if a password has been provided.
-            !state.encryption_key.is_empty()
+            state.encryption_key.is_some()
         } else {
             // Not encrypted, so we're always unlocked. Sanity check that the key is empty.
-            assert!(state.encryption_key.is_empty());
+            assert!(state.encryption_key.is_none());
             true
         }
     }
 
     /// Change the password that will be used for all future
-    /// encryption/decryption operations. This should only be called after
-    /// all existing data has been re-encrypted to the new password!
+    /// encryption/decryption operations, using the recommended key derivation
+    /// parameters. This should only be called after all existing data has
+    /// been re-encrypted to the new password!
     pub fn change_password<'env>(
+        &self,
+        db_txn: RwTransaction<'env>,
+        password: &[u8],
+    ) -> Result<(), DbCryptoError> {
+        self.change_password_with_kdf_params(db_txn, password, &KdfParams::recommended())
+    }
+
+    /// Change the password and key derivation parameters that will be used for
+    /// all future encryption/decryption operations. This should only be
+    /// called after all existing data has been re-encrypted with a key derived
+    /// from the same password and parameters:
+    /// 1. Go over all encrypted data, decrypt it with the current password and
+    ///    re-encrypt it with `KdfParams::derive_key(new_password)`.
+    /// 2. Once all data has been re-encrypted, call this so that future
+    ///    operations use the new password.
+    pub fn change_password_with_kdf_params<'env>(
         &self,
         mut db_txn: RwTransaction<'env>,
         password: &[u8],
+        kdf_params: &KdfParams,
     ) -> Result<(), DbCryptoError> {
         let mut state = self.state.lock().expect("muted poisoned");
 
         // The test value will be used to verify whether a given password is correct.
-        if password.is_empty() {
+        let encryption_key = if password.is_empty() {
             if state.is_db_encrypted {
                 db_txn.del(self.database, &ENCRYPTION_STATE_KEY.as_bytes(), None)?;
             }
-        } else {
-            if password.len() != PASSWORD_LEN {
-                return Err(DbCryptoError::InvalidPasswordLength);
+            match db_txn.del(self.database, &KDF_PARAMS_KEY.as_bytes(), None) {
+                Ok(()) | Err(LmdbError::NotFound) => {}
+                Err(err) => return Err(err.into()),
             }
+            None
+        } else {
+            let key = kdf_params.derive_key(password)?;
 
             db_txn.put(
                 self.database,
                 &ENCRYPTION_STATE_KEY.as_bytes(),
-                &self.encrypt_with_password(password, ENCRYPTION_STATE_VAL.as_bytes())?,
+                &key.encrypt(ENCRYPTION_STATE_VAL.as_bytes())?,
                 WriteFlags::empty(),
             )?;
-        }
+            db_txn.put(
+                self.database,
+                &KDF_PARAMS_KEY.as_bytes(),
+                &mc_util_serial::encode(kdf_params),
+                WriteFlags::empty(),
+            )?;
+            Some(key)
+        };
 
         db_txn.commit()?;
 
-        if password.is_empty() {
-            state.is_db_encrypted = false;
-            state.encryption_key = vec![];
+        state.is_db_encrypted = encryption_key.is_some();
+        state.kdf_params = if encryption_key.is_some() {
+            kdf_params.clone()
         } else {
-            state.is_db_encrypted = true;
-            state.encryption_key = password.to_vec();
-        }
+            KdfParams::default()
+        };
+        state.encryption_key = encryption_key;
 
         Ok(())
     }
@@ -217,26 +396,17 @@ impl DbCryptoProvider {
     pub fn encrypt(&self, plaintext_bytes: &[u8]) -> Result<Vec<u8>, DbCryptoError> {
         let state = self.state.lock().expect("mutex poisoned");
         if state.is_db_encrypted {
-            if state.encryption_key.is_empty() {
-                return Err(DbCryptoError::PasswordNeeded);
+            match &state.encryption_key {
+                Some(key) => key.encrypt(plaintext_bytes),
+                None => Err(DbCryptoError::PasswordNeeded),
             }
-
-            let (key, nonce) = Self::expand_password(&state.encryption_key)?;
-
-            let cipher = Aes256Gcm::new(&key);
-
-            Ok(cipher.encrypt(&nonce, plaintext_bytes)?)
         } else {
             Ok(plaintext_bytes.to_vec())
         }
     }
 
-    /// Encrypt data with a specific password.
-    /// This is used when we want to re-encrypt data as a result of a password
-    /// change: 1. Go over all encrypted data, decrypt it with the current
-    /// password and re-encrypt with the    new password using this method.
-    /// 2. Once all data has been re-encrypted, call set_password so that future
-    /// operations use the    new password.
+    /// Encrypt data with a specific password, using the key derivation
+    /// parameters the database is currently encrypted with.
     pub fn encrypt_with_password(
         &self,
         password: &[u8],
@@ -247,14 +417,9 @@ impl DbCryptoProvider {
             return Ok(plaintext_bytes.to_vec());
         }
 
-        if password.len() != PASSWORD_LEN {
-            return Err(DbCryptoError::InvalidPasswordLength);
-        }
-
-        let (key, nonce) = Self::expand_password(password)?;
-
-        let cipher = Aes256Gcm::new(&key);
-        Ok(cipher.encrypt(&nonce, plaintext_bytes)?)
+        self.kdf_params()
+            .derive_key(password)?
+            .encrypt(plaintext_bytes)
     }
 
     /// Decrypt data with the currently set password.
@@ -269,49 +434,20 @@ impl DbCryptoProvider {
         //    test if that is the case before assuming no password is required.
         //    This allows callers to get a meaningful error (PasswordNeeded) instead of
         //    prost decode errors.
-        match (state.is_db_encrypted, state.encryption_key.is_empty()) {
+        match (state.is_db_encrypted, &state.encryption_key) {
             // Db is not encrypted and password is empty
-            (false, true) => Ok(ciphertext.to_vec()),
+            (false, None) => Ok(ciphertext.to_vec()),
 
             // Db is not encrypted and password is not empty (should never happen)
-            (false, false) => panic!("invalid db encryption state"),
+            (false, Some(_)) => panic!("invalid db encryption state"),
 
             // Db is encrypted but password is missing
-            (true, true) => Err(DbCryptoError::PasswordNeeded),
+            (true, None) => Err(DbCryptoError::PasswordNeeded),
 
             // Db is encrypted and we have a password
-            (true, false) => {
-                let (key, nonce) = Self::expand_password(&state.encryption_key)?;
-
-                let cipher = Aes256Gcm::new(&key);
-
-                Ok(cipher.decrypt(&nonce, ciphertext)?)
-            }
+            (true, Some(key)) => key.decrypt(ciphertext),
         }
     }
-
-    /// Expands the password into an encryption key and a nonce.
-    fn expand_password(
-        password: &[u8],
-    ) -> Result<
-        (
-            GenericArray<u8, <Aes256Gcm as NewAead>::KeySize>,
-            GenericArray<u8, <Aes256Gcm as AeadCore>::NonceSize>,
-        ),
-        DbCryptoError,
-    > {
-        // Hash the password hash with Blake2b to get 64 bytes, first 32 for aeskey,
-        // second 32 for nonce
-        let mut hasher = Blake2b512::new();
-        hasher.update(&MOBILECOIND_DB_KEY_DOMAIN_TAG);
-        hasher.update(&password);
-        let result = hasher.finalize();
-
-        let (key, remainder) = Split::<u8, <Aes256Gcm as NewAead>::KeySize>::split(result);
-        let (nonce, _remainder) = Split::<u8, <Aes256Gcm as AeadCore>::NonceSize>::split(remainder);
-
-        Ok((key, nonce))
-    }
 }
 
 #[cfg(test)]
@@ -565,6 +701,20 @@ mod tests {
             .is_ok());
     }
 
+    #[test]
+    fn test_derive_key_rejects_unsupported_kdf_params() {
+        let password = [123; PASSWORD_LEN];
+        assert!(KdfParams::default().derive_key(&password).is_ok());
+        assert!(KdfParams::pbkdf2(1).derive_key(&password).is_ok());
+        assert!(KdfParams::pbkdf2(0).derive_key(&password).is_err());
+
+        let kdf_params = KdfParams {
+            algorithm: 100,
+            ..Default::default()
+        };
+        assert!(kdf_params.derive_key(&password).is_err());
+    }
+
     #[test]
     fn test_db_reopen() {
         // Get the initial db.
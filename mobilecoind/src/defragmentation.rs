@@ -0,0 +1,278 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Merges the UTXOs of monitors in the background.
+//!
+//! Monitors may have a defragmentation policy, which names a subaddress and a
+//! token whose UTXOs should be merged until at most a target number of them
+//! remain. The defragmentation thread periodically goes over the monitors
+//! that have a policy and submits a single optimization transaction for each
+//! one that is still above its target. The fees paid by these transactions
+//! are recorded in the monitor data, and the policy stops submitting
+//! transactions once its fee budget would be exceeded.
+
+use crate::{
+    database::Database,
+    error::Error,
+    monitor_store::{MonitorData, MonitorId},
+    payments::TransactionsManager,
+    utxo_store::UtxoId,
+};
+use mc_common::logger::{log, Logger};
+use mc_connection::{BlockInfo, BlockchainConnection, UserTxConnection};
+use mc_fog_report_validation::FogPubkeyResolver;
+use mc_ledger_db::{Ledger, LedgerDB};
+use mc_ledger_sync::{NetworkState, PollingNetworkState};
+use mc_transaction_core::TokenId;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often monitors are checked for UTXOs that need merging.
+const DEFRAGMENTATION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Defragmentation thread - holds objects needed to cleanly terminate the
+/// thread.
+pub struct DefragmentationThread {
+    /// The thread handle.
+    join_handle: Option<thread::JoinHandle<()>>,
+
+    /// Stop trigger, used to signal the thread to terminate.
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl DefragmentationThread {
+    pub fn start<
+        T: BlockchainConnection + UserTxConnection + 'static,
+        FPR: FogPubkeyResolver + 'static,
+    >(
+        ledger_db: LedgerDB,
+        mobilecoind_db: Database,
+        transactions_manager: TransactionsManager<T, FPR>,
+        network_state: Arc<RwLock<PollingNetworkState<T>>>,
+        logger: Logger,
+    ) -> Self {
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let thread_stop_requested = stop_requested.clone();
+
+        let join_handle = Some(
+            thread::Builder::new()
+                .name("defragmentation".to_string())
+                .spawn(move || {
+                    log::debug!(logger, "DefragmentationThread started.");
+
+                    let defragmenter = Defragmenter {
+                        ledger_db,
+                        mobilecoind_db,
+                        transactions_manager,
+                        network_state,
+                        logger: logger.clone(),
+                    };
+
+                    let mut last_pass: Option<Instant> = None;
+                    loop {
+                        if thread_stop_requested.load(Ordering::SeqCst) {
+                            log::debug!(logger, "DefragmentationThread stop requested.");
+                            break;
+                        }
+
+                        if last_pass.map_or(true, |instant| {
+                            instant.elapsed() >= DEFRAGMENTATION_INTERVAL
+                        }) {
+                            defragmenter.run_pass();
+                            last_pass = Some(Instant::now());
+                        }
+
+                        thread::sleep(Duration::from_secs(1));
+                    }
+
+                    log::debug!(logger, "DefragmentationThread stopped.");
+                })
+                .expect("failed starting defragmentation thread"),
+        );
+
+        Self {
+            join_handle,
+            stop_requested,
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle
+                .join()
+                .expect("DefragmentationThread join failed");
+        }
+    }
+}
+
+impl Drop for DefragmentationThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The number of UTXOs of a token held by a subaddress of a monitor.
+pub fn get_utxo_count(
+    mobilecoind_db: &Database,
+    monitor_id: &MonitorId,
+    subaddress_index: u64,
+    token_id: u64,
+) -> Result<u64, Error> {
+    Ok(mobilecoind_db
+        .get_utxos_for_subaddress(monitor_id, subaddress_index)?
+        .iter()
+        .filter(|utxo| utxo.token_id == token_id)
+        .count() as u64)
+}
+
+/// Runs the defragmentation policies of all monitors.
+struct Defragmenter<
+    T: BlockchainConnection + UserTxConnection + 'static,
+    FPR: FogPubkeyResolver + 'static,
+> {
+    ledger_db: LedgerDB,
+    mobilecoind_db: Database,
+    transactions_manager: TransactionsManager<T, FPR>,
+    network_state: Arc<RwLock<PollingNetworkState<T>>>,
+    logger: Logger,
+}
+
+impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolver + 'static>
+    Defragmenter<T, FPR>
+{
+    /// Submit at most one optimization transaction for each monitor whose
+    /// policy has not been fulfilled yet.
+    fn run_pass(&self) {
+        // Monitor data is not readable until the database is unlocked.
+        if !self.mobilecoind_db.is_unlocked() {
+            return;
+        }
+
+        let monitor_map = match self.mobilecoind_db.get_monitor_map() {
+            Ok(monitor_map) => monitor_map,
+            Err(err) => {
+                log::error!(self.logger, "Failed getting monitor map: {}", err);
+                return;
+            }
+        };
+
+        for (monitor_id, monitor_data) in monitor_map {
+            if let Err(err) = self.defragment_monitor(&monitor_id, &monitor_data) {
+                log::warn!(
+                    self.logger,
+                    "Failed defragmenting monitor {}: {}",
+                    monitor_id,
+                    err
+                );
+            }
+        }
+    }
+
+    fn defragment_monitor(
+        &self,
+        monitor_id: &MonitorId,
+        monitor_data: &MonitorData,
+    ) -> Result<(), Error> {
+        let policy = match monitor_data.defragmentation_policy.as_ref() {
+            Some(policy) if !monitor_data.is_view_only() => policy,
+            _ => return Ok(()),
+        };
+
+        // UTXOs of a monitor that is still syncing may already be spent.
+        let num_blocks = self.ledger_db.num_blocks()?;
+        if monitor_data.next_block < num_blocks {
+            return Ok(());
+        }
+
+        let utxos: Vec<_> = self
+            .mobilecoind_db
+            .get_utxos_for_subaddress(monitor_id, policy.subaddress_index)?
+            .into_iter()
+            .filter(|utxo| utxo.token_id == policy.token_id)
+            .collect();
+        if utxos.len() as u64 <= policy.target_utxo_count {
+            return Ok(());
+        }
+
+        // Wait for the previous optimization transaction to land or expire, so
+        // that its output can be merged too.
+        if utxos
+            .iter()
+            .any(|utxo| utxo.attempted_spend_tombstone > num_blocks)
+        {
+            return Ok(());
+        }
+
+        let fees_spent = monitor_data
+            .defragmentation_progress
+            .as_ref()
+            .map_or(0, |progress| progress.fees_spent);
+        if fees_spent >= policy.max_fee_budget {
+            return Ok(());
+        }
+
+        let tx_proposal = match self.transactions_manager.generate_optimization_tx(
+            monitor_id,
+            policy.subaddress_index,
+            TokenId::from(policy.token_id),
+            &self.get_last_block_infos(),
+            0,
+        ) {
+            Ok(tx_proposal) => tx_proposal,
+            // Nothing left that can be merged without losing money.
+            Err(Error::OptimizationNotBeneficial(_)) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        if fees_spent + tx_proposal.fee() > policy.max_fee_budget {
+            log::info!(
+                self.logger,
+                "Monitor {} defragmentation fee budget exhausted",
+                monitor_id
+            );
+            return Ok(());
+        }
+
+        let block_height = self.transactions_manager.submit_tx_proposal(&tx_proposal)?;
+
+        // The transaction was submitted, so make sure the fee counts against the
+        // budget even if updating the attempted spend fails.
+        self.mobilecoind_db
+            .record_defragmentation_tx(monitor_id, tx_proposal.fee())?;
+
+        let utxo_ids: Vec<UtxoId> = tx_proposal.utxos.iter().map(UtxoId::from).collect();
+        self.mobilecoind_db.update_attempted_spend(
+            &utxo_ids,
+            block_height,
+            tx_proposal.tx.prefix.tombstone_block,
+        )?;
+
+        log::info!(
+            self.logger,
+            "Monitor {} merged {} of {} UTXOs of token {} in subaddress {}",
+            monitor_id,
+            tx_proposal.utxos.len(),
+            utxos.len(),
+            policy.token_id,
+            policy.subaddress_index,
+        );
+
+        Ok(())
+    }
+
+    fn get_last_block_infos(&self) -> Vec<BlockInfo> {
+        self.network_state
+            .read()
+            .expect("lock poisoned")
+            .peer_to_block_info()
+            .values()
+            .cloned()
+            .collect()
+    }
+}
@@ -9,6 +9,7 @@ use mc_connection::Error as ConnectionError;
 use mc_consensus_api::ConversionError;
 use mc_crypto_keys::KeyError;
 use mc_ledger_db::Error as LedgerDbError;
+use mc_transaction_extra::SignedContingentInputError;
 use mc_util_lmdb::MetadataStoreError;
 use mc_util_serial::{decode::Error as DecodeError, encode::Error as EncodeError};
 use prost::DecodeError as ProstDecodeError;
@@ -111,6 +112,27 @@ pub enum Error {
 
     /// Db encryption: {0}
     DbCrypto(DbCryptoError),
+
+    /// Monitor is view-only and does not hold the spend private key
+    ViewOnlyMonitor,
+
+    /// Signed contingent input: {0}
+    SignedContingentInput(SignedContingentInputError),
+
+    /// Signed contingent input key image already appears in the ledger
+    SignedContingentInputSpent,
+
+    /// Gift code does not match the TxOut at its global index
+    GiftCodeMismatch,
+
+    /// Gift code key image already appears in the ledger
+    GiftCodeSpent,
+
+    /// Monitor data stored under {0} does not match its monitor id
+    MonitorDataMismatch(String),
+
+    /// Monitor data has neither an account key nor a view account key
+    InvalidMonitorData,
 }
 
 impl From<RetryError<ConnectionError>> for Error {
@@ -184,3 +206,9 @@ impl From<DbCryptoError> for Error {
         Self::DbCrypto(e)
     }
 }
+
+impl From<SignedContingentInputError> for Error {
+    fn from(e: SignedContingentInputError) -> Self {
+        Self::SignedContingentInput(e)
+    }
+}
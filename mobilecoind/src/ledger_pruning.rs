@@ -0,0 +1,195 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Prunes old TxOuts from the ledger in the background.
+//!
+//! Wallets only need their own TxOuts, which monitors copy into the
+//! mobilecoind database as they scan blocks, together with proofs of
+//! membership and the key image set, both of which survive pruning. The
+//! pruning thread periodically drops the TxOut bodies of blocks that are
+//! older than the configured horizon, except for a random sample that is
+//! retained for mixin selection. Blocks are only pruned once every monitor
+//! has scanned them, so a monitor that is behind holds back pruning.
+
+use crate::{database::Database, error::Error};
+use mc_common::logger::{log, Logger};
+use mc_ledger_db::{Ledger, LedgerDB};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often the ledger is checked for blocks that can be pruned.
+const PRUNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The maximal number of blocks pruned in a single pass. Pruning happens in a
+/// single LMDB write transaction, which blocks ledger sync while it runs.
+const MAX_BLOCKS_PER_PASS: u64 = 1000;
+
+/// Ledger pruning thread - holds objects needed to cleanly terminate the
+/// thread.
+pub struct LedgerPruningThread {
+    /// The thread handle.
+    join_handle: Option<thread::JoinHandle<()>>,
+
+    /// Stop trigger, used to signal the thread to terminate.
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl LedgerPruningThread {
+    /// Start pruning the ledger.
+    ///
+    /// # Arguments
+    /// * `ledger_db` - The ledger to prune.
+    /// * `mobilecoind_db` - The mobilecoind database, if mobilecoind is serving
+    ///   its API. Blocks are only pruned once all of its monitors have scanned
+    ///   them.
+    /// * `horizon` - The number of most recent blocks that are never pruned.
+    /// * `retain_one_in` - Roughly one in this many pruned TxOuts is retained
+    ///   for use as a mixin.
+    /// * `logger` - Logger.
+    pub fn start(
+        ledger_db: LedgerDB,
+        mobilecoind_db: Option<Database>,
+        horizon: u64,
+        retain_one_in: u64,
+        logger: Logger,
+    ) -> Self {
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let thread_stop_requested = stop_requested.clone();
+
+        let join_handle = Some(
+            thread::Builder::new()
+                .name("ledger_pruning".to_string())
+                .spawn(move || {
+                    log::debug!(logger, "LedgerPruningThread started.");
+
+                    let mut ledger_db = ledger_db;
+                    let mut last_pass: Option<Instant> = None;
+                    loop {
+                        if thread_stop_requested.load(Ordering::SeqCst) {
+                            log::debug!(logger, "LedgerPruningThread stop requested.");
+                            break;
+                        }
+
+                        if last_pass.map_or(true, |instant| instant.elapsed() >= PRUNING_INTERVAL) {
+                            if let Err(err) = prune_ledger(
+                                &mut ledger_db,
+                                mobilecoind_db.as_ref(),
+                                horizon,
+                                retain_one_in,
+                                &logger,
+                            ) {
+                                log::error!(logger, "Failed pruning ledger: {}", err);
+                            }
+                            last_pass = Some(Instant::now());
+                        }
+
+                        thread::sleep(Duration::from_secs(1));
+                    }
+
+                    log::debug!(logger, "LedgerPruningThread stopped.");
+                })
+                .expect("failed starting ledger pruning thread"),
+        );
+
+        Self {
+            join_handle,
+            stop_requested,
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle.join().expect("LedgerPruningThread join failed");
+        }
+    }
+}
+
+impl Drop for LedgerPruningThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Prune the blocks that are older than `horizon` and have been scanned by
+/// every monitor, up to `MAX_BLOCKS_PER_PASS` of them. Returns the number of
+/// TxOuts that were pruned.
+fn prune_ledger(
+    ledger_db: &mut LedgerDB,
+    mobilecoind_db: Option<&Database>,
+    horizon: u64,
+    retain_one_in: u64,
+    logger: &Logger,
+) -> Result<u64, Error> {
+    let pruned_num_blocks = ledger_db.pruned_num_blocks()?;
+    let mut num_blocks = ledger_db
+        .num_blocks()?
+        .saturating_sub(horizon)
+        .min(pruned_num_blocks + MAX_BLOCKS_PER_PASS);
+
+    // Monitors of an encrypted database that has not been unlocked yet can not be
+    // read, in which case nothing is pruned. No monitors can be added until
+    // pruning is committed.
+    let _pruning_guard = mobilecoind_db.map(Database::lock_pruning);
+    if let Some(mobilecoind_db) = mobilecoind_db {
+        for monitor_data in mobilecoind_db.get_monitor_map()?.values() {
+            num_blocks = num_blocks.min(monitor_data.next_block);
+        }
+    }
+
+    if num_blocks <= pruned_num_blocks {
+        return Ok(0);
+    }
+
+    let num_pruned = ledger_db.prune_tx_outs(num_blocks, retain_one_in, &mut rand::thread_rng())?;
+    log::info!(
+        logger,
+        "Pruned {} TxOuts from blocks {} to {}",
+        num_pruned,
+        pruned_num_blocks,
+        num_blocks - 1
+    );
+    Ok(num_pruned)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        monitor_store::MonitorData,
+        test_utils::{get_test_databases, BlockVersion},
+    };
+    use mc_account_keys::AccountKey;
+    use mc_common::logger::test_with_logger;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test_with_logger]
+    // Pruning should stay behind the horizon and the slowest monitor.
+    fn test_prune_ledger(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([123u8; 32]);
+
+        let (mut ledger_db, mobilecoind_db) =
+            get_test_databases(BlockVersion::MAX, 3, &[], 10, logger.clone(), &mut rng);
+
+        let mut data = MonitorData::new(AccountKey::random(&mut rng), 0, 1, 0, "").unwrap();
+        data.next_block = 4;
+        mobilecoind_db.add_monitor(&data).unwrap();
+
+        // The monitor has only scanned the first 4 blocks.
+        prune_ledger(&mut ledger_db, Some(&mobilecoind_db), 3, 2, &logger).unwrap();
+        assert_eq!(4, ledger_db.pruned_num_blocks().unwrap());
+
+        // Without monitors, pruning stops at the horizon.
+        prune_ledger(&mut ledger_db, None, 3, 2, &logger).unwrap();
+        assert_eq!(7, ledger_db.pruned_num_blocks().unwrap());
+        assert_eq!(
+            0,
+            prune_ledger(&mut ledger_db, None, 3, 2, &logger).unwrap()
+        );
+    }
+}
@@ -6,18 +6,25 @@ extern crate alloc;
 
 pub mod config;
 pub mod database;
+pub mod ledger_pruning;
+pub mod outlay_memo_builder;
 pub mod payments;
 pub mod service;
+pub mod utxo_selection;
 
 mod conversions;
 mod database_key;
 mod db_crypto;
+mod defragmentation;
 mod error;
+mod monitor_events;
 mod monitor_store;
 mod processed_block_store;
 mod subaddress_store;
 mod sync;
+mod tx_history_store;
 mod utxo_store;
+pub use db_crypto::{KdfParams, DEFAULT_PBKDF2_ROUNDS};
 pub use utxo_store::UnspentTxOut;
 
 #[cfg(any(test, feature = "test_utils"))]
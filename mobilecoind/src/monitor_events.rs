@@ -0,0 +1,237 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Streams monitor activity to clients as the sync thread processes blocks.
+//!
+//! Events are read back from the tx history store rather than being produced
+//! by the sync thread directly. This means a client can resume a stream from
+//! any block index, and events are never lost if the client is not connected
+//! while blocks get processed.
+//!
+//! Every event carries an opaque cursor. A client that resumes from the cursor
+//! of the last event it handled gets exactly the events that followed it.
+
+use crate::{
+    database::Database, error::Error, monitor_store::MonitorId,
+    processed_block_store::ProcessedTxOutDirection, tx_history_store::TxHistoryKey,
+};
+use mc_ledger_db::{Ledger, LedgerDB};
+use mc_mobilecoind_api as api;
+use std::time::Duration;
+
+/// The maximal number of events returned by a single call to `next_events`.
+const MAX_EVENTS_PER_BATCH: usize = 100;
+
+/// Length of a serialized cursor: a tx history key, whether that key was
+/// already returned, and the block index of the caught up event the cursor was
+/// taken from, plus one (zero for other events).
+const CURSOR_LEN: usize = 73 + 1 + 8;
+
+/// Produces the events of a single monitor, in block order.
+pub struct MonitorEventStream {
+    /// Ledger database.
+    ledger_db: LedgerDB,
+
+    /// mobilecoind database.
+    mobilecoind_db: Database,
+
+    /// The monitor whose events we are producing.
+    monitor_id: MonitorId,
+
+    /// Where to continue reading the tx history from.
+    cursor: TxHistoryKey,
+
+    /// Whether the entry at `cursor` was already returned.
+    exclude_cursor: bool,
+
+    /// The block index we last reported the monitor being caught up at.
+    caught_up_block_index: Option<u64>,
+
+    /// The last caught up event we produced.
+    last_caught_up_event: Option<api::MonitorEvent>,
+}
+
+impl MonitorEventStream {
+    /// Create a stream of the events that happen at or after
+    /// `start_block_index`.
+    pub fn new(
+        ledger_db: LedgerDB,
+        mobilecoind_db: Database,
+        monitor_id: MonitorId,
+        start_block_index: u64,
+    ) -> Result<Self, Error> {
+        // Make sure the monitor exists.
+        mobilecoind_db.get_monitor_data(&monitor_id)?;
+
+        Ok(Self {
+            ledger_db,
+            mobilecoind_db,
+            cursor: TxHistoryKey::first_in_block(&monitor_id, start_block_index),
+            monitor_id,
+            exclude_cursor: false,
+            caught_up_block_index: None,
+            last_caught_up_event: None,
+        })
+    }
+
+    /// Create a stream of the events that follow the event `cursor` was taken
+    /// from. A cursor that is malformed, belongs to another monitor or points
+    /// past the end of the ledger is rejected with `Error::InvalidArgument`.
+    pub fn resume(
+        ledger_db: LedgerDB,
+        mobilecoind_db: Database,
+        monitor_id: MonitorId,
+        cursor: &[u8],
+    ) -> Result<Self, Error> {
+        if cursor.len() != CURSOR_LEN {
+            return Err(Error::InvalidArgument(
+                "cursor".to_string(),
+                format!("cursor length must be exactly {}", CURSOR_LEN),
+            ));
+        }
+        let key = TxHistoryKey::try_from(&cursor[..73]).map_err(|err| {
+            Error::InvalidArgument("cursor".to_string(), format!("malformed cursor: {}", err))
+        })?;
+        if cursor[73] > 1 {
+            return Err(Error::InvalidArgument(
+                "cursor".to_string(),
+                "malformed cursor".to_string(),
+            ));
+        }
+        if key.monitor_id != monitor_id {
+            return Err(Error::InvalidArgument(
+                "cursor".to_string(),
+                "cursor belongs to a different monitor".to_string(),
+            ));
+        }
+        let mut caught_up_bytes = [0u8; 8];
+        caught_up_bytes.copy_from_slice(&cursor[74..]);
+        let caught_up_block_index = u64::from_be_bytes(caught_up_bytes).checked_sub(1);
+        let num_blocks = ledger_db.num_blocks()?;
+        if key.block_index > num_blocks
+            || caught_up_block_index.map_or(false, |index| index > num_blocks)
+        {
+            return Err(Error::InvalidArgument(
+                "cursor".to_string(),
+                "cursor is past the end of the ledger".to_string(),
+            ));
+        }
+
+        let mut stream = Self::new(ledger_db, mobilecoind_db, monitor_id, key.block_index)?;
+        stream.cursor = key;
+        stream.exclude_cursor = cursor[73] != 0;
+        stream.caught_up_block_index = caught_up_block_index;
+        Ok(stream)
+    }
+
+    /// The cursor of the position `key`, `exclude_key`. For a caught up event,
+    /// `caught_up_block_index` is the block index it reports.
+    fn encode_cursor(
+        key: &TxHistoryKey,
+        exclude_key: bool,
+        caught_up_block_index: Option<u64>,
+    ) -> Vec<u8> {
+        let mut cursor = Vec::with_capacity(CURSOR_LEN);
+        cursor.extend_from_slice(&key.to_bytes());
+        cursor.push(exclude_key as u8);
+        cursor.extend_from_slice(
+            &caught_up_block_index
+                .map_or(0, |index| index + 1)
+                .to_be_bytes(),
+        );
+        cursor
+    }
+
+    /// The last caught up event, if the monitor is still caught up at the same
+    /// block and no other events were produced since. Sending it again lets
+    /// the server notice idle clients that went away.
+    pub fn keepalive_event(&self) -> Option<api::MonitorEvent> {
+        self.last_caught_up_event.clone()
+    }
+
+    /// Get the next batch of events. If there are none, this waits up to
+    /// `timeout` for more blocks to be processed and returns an empty batch,
+    /// so callers get a chance to check whether anyone is still listening.
+    pub fn next_events(&mut self, timeout: Duration) -> Result<Vec<api::MonitorEvent>, Error> {
+        // Grab this before reading anything, so that a block processed while we
+        // read is not missed when we wait below.
+        let num_blocks_processed = self.mobilecoind_db.num_blocks_processed();
+
+        let monitor_data = self.mobilecoind_db.get_monitor_data(&self.monitor_id)?;
+
+        // The history is read in a separate transaction, so it may already contain
+        // blocks processed after we got the monitor data. Leave those for the next
+        // batch, so that a caught up event never precedes events from earlier
+        // blocks.
+        let entries = self.mobilecoind_db.get_tx_history(
+            &self.cursor,
+            self.exclude_cursor,
+            MAX_EVENTS_PER_BATCH,
+        )?;
+        let has_more_entries = entries.len() == MAX_EVENTS_PER_BATCH;
+        let entries: Vec<_> = entries
+            .into_iter()
+            .take_while(|entry| entry.block_index < monitor_data.next_block)
+            .collect();
+
+        if let Some(entry) = entries.last() {
+            self.cursor = entry.key(&self.monitor_id);
+            self.exclude_cursor = true;
+        }
+
+        let mut events: Vec<api::MonitorEvent> = entries
+            .iter()
+            .map(|entry| {
+                let event_type = if entry.direction == ProcessedTxOutDirection::Spent as i32 {
+                    api::MonitorEventType::MonitorEventSpent
+                } else {
+                    api::MonitorEventType::MonitorEventReceived
+                };
+
+                let mut event = api::MonitorEvent::new();
+                event.set_event_type(event_type);
+                event.set_block_index(entry.block_index);
+                event.set_entry(entry.into());
+                // Caught up events precede all entries of later blocks, so there is
+                // no caught up event to remember.
+                event.set_cursor(Self::encode_cursor(
+                    &entry.key(&self.monitor_id),
+                    true,
+                    None,
+                ));
+                event
+            })
+            .collect();
+        if !events.is_empty() {
+            self.last_caught_up_event = None;
+        }
+
+        // Once all of the history has been delivered, let the client know whenever
+        // the monitor catches up with the ledger.
+        if !has_more_entries {
+            let num_blocks = self.ledger_db.num_blocks()?;
+            if monitor_data.next_block >= num_blocks
+                && self.caught_up_block_index != Some(monitor_data.next_block)
+            {
+                self.caught_up_block_index = Some(monitor_data.next_block);
+
+                let mut event = api::MonitorEvent::new();
+                event.set_event_type(api::MonitorEventType::MonitorEventCaughtUp);
+                event.set_block_index(monitor_data.next_block);
+                event.set_cursor(Self::encode_cursor(
+                    &self.cursor,
+                    self.exclude_cursor,
+                    self.caught_up_block_index,
+                ));
+                self.last_caught_up_event = Some(event.clone());
+                events.push(event);
+            }
+        }
+
+        if events.is_empty() {
+            self.mobilecoind_db
+                .wait_for_blocks_processed(num_blocks_processed, timeout);
+        }
+
+        Ok(events)
+    }
+}
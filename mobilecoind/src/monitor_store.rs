@@ -4,16 +4,20 @@
 //! * Provides monitor configuration and status from MonitorId.
 //! * MonitorId is a hash of the instantiation parameters.
 
-use crate::{database_key::DatabaseByteArrayKey, db_crypto::DbCryptoProvider, error::Error};
+use crate::{
+    database_key::DatabaseByteArrayKey,
+    db_crypto::{DbCryptoProvider, KdfParams},
+    error::Error,
+};
 
 use lmdb::{Cursor, Database, DatabaseFlags, Environment, RwTransaction, Transaction, WriteFlags};
-use mc_account_keys::AccountKey;
+use mc_account_keys::{AccountKey, PublicAddress, ViewAccountKey};
 use mc_common::{
     logger::{log, Logger},
     HashMap,
 };
 use mc_crypto_digestible::{Digestible, MerlinTranscript};
-use mc_crypto_keys::RistrettoPublic;
+use mc_crypto_keys::{RistrettoPrivate, RistrettoPublic};
 use mc_util_serial::Message;
 use std::{ops::Range, sync::Arc};
 
@@ -25,8 +29,9 @@ pub const MONITOR_ID_TO_MONITOR_DATA_DB_NAME: &str =
 #[derive(Clone, Eq, Hash, PartialEq, Message)]
 pub struct MonitorData {
     /// The private key pair for the account this monitor watches.
-    #[prost(message, required, tag = "1")]
-    pub account_key: AccountKey,
+    /// This is `None` for view-only monitors.
+    #[prost(message, optional, tag = "1")]
+    pub account_key: Option<AccountKey>,
 
     /// The smallest subaddress index in the range this monitor watches.
     #[prost(uint64, tag = "2")]
@@ -47,6 +52,55 @@ pub struct MonitorData {
     /// Optional monitor name.
     #[prost(string, tag = "6")]
     pub name: String,
+
+    /// The view private key and spend public key for the account this monitor
+    /// watches. Only set for view-only monitors.
+    #[prost(message, optional, tag = "7")]
+    pub view_account_key: Option<ViewAccountKey>,
+
+    /// Optional policy for merging the UTXOs of this monitor in the
+    /// background.
+    #[prost(message, optional, tag = "8")]
+    pub defragmentation_policy: Option<DefragmentationPolicy>,
+
+    /// What the defragmentation policy has done so far. Reset whenever the
+    /// policy is changed.
+    #[prost(message, optional, tag = "9")]
+    pub defragmentation_progress: Option<DefragmentationProgress>,
+}
+
+/// Controls background merging of the UTXOs held by a single subaddress of a
+/// monitor.
+#[derive(Clone, Eq, Hash, PartialEq, Message)]
+pub struct DefragmentationPolicy {
+    /// The subaddress whose UTXOs get merged.
+    #[prost(uint64, tag = "1")]
+    pub subaddress_index: u64,
+
+    /// The token whose UTXOs get merged.
+    #[prost(uint64, tag = "2")]
+    pub token_id: u64,
+
+    /// Stop merging once the subaddress holds at most this many UTXOs of the
+    /// token.
+    #[prost(uint64, tag = "3")]
+    pub target_utxo_count: u64,
+
+    /// The maximal total amount of fees the policy may spend.
+    #[prost(uint64, tag = "4")]
+    pub max_fee_budget: u64,
+}
+
+/// Progress of a monitor's defragmentation policy.
+#[derive(Clone, Eq, Hash, PartialEq, Message)]
+pub struct DefragmentationProgress {
+    /// The number of optimization transactions submitted.
+    #[prost(uint64, tag = "1")]
+    pub num_txs_submitted: u64,
+
+    /// The total amount of fees paid by the submitted transactions.
+    #[prost(uint64, tag = "2")]
+    pub fees_spent: u64,
 }
 
 impl MonitorData {
@@ -65,31 +119,126 @@ impl MonitorData {
         }
 
         Ok(Self {
-            account_key,
+            account_key: Some(account_key),
+            first_subaddress,
+            num_subaddresses,
+            first_block,
+            // The next block we need to sync is our first block.
+            next_block: first_block,
+            name: name.to_owned(),
+            view_account_key: None,
+            defragmentation_policy: None,
+            defragmentation_progress: None,
+        })
+    }
+
+    /// Create a view-only monitor. View-only monitors discover TxOuts and
+    /// track balances, but cannot spend. Since computing a key image requires
+    /// the spend private key, the spent status of TxOuts owned by a view-only
+    /// monitor is unknown unless key images are supplied by the user.
+    pub fn new_view_only(
+        view_account_key: ViewAccountKey,
+        first_subaddress: u64,
+        num_subaddresses: u64,
+        first_block: u64,
+        name: &str,
+    ) -> Result<Self, Error> {
+        if num_subaddresses == 0 {
+            return Err(Error::InvalidArgument(
+                "num_subaddresses".to_string(),
+                "must be greater than zero".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            account_key: None,
             first_subaddress,
             num_subaddresses,
             first_block,
             // The next block we need to sync is our first block.
             next_block: first_block,
             name: name.to_owned(),
+            view_account_key: Some(view_account_key),
+            defragmentation_policy: None,
+            defragmentation_progress: None,
         })
     }
 
     pub fn subaddress_indexes(&self) -> Range<u64> {
         self.first_subaddress..self.first_subaddress + self.num_subaddresses
     }
+
+    /// Returns true if this monitor does not hold the spend private key.
+    pub fn is_view_only(&self) -> bool {
+        self.account_key.is_none()
+    }
+
+    /// Get the account key, failing if this is a view-only monitor.
+    pub fn spendable_account_key(&self) -> Result<&AccountKey, Error> {
+        self.account_key.as_ref().ok_or(Error::ViewOnlyMonitor)
+    }
+
+    /// Check that this monitor holds either an account key or a view account
+    /// key. Data that does not can only come from a corrupt database record.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.account_key.is_none() && self.view_account_key.is_none() {
+            return Err(Error::InvalidMonitorData);
+        }
+        Ok(())
+    }
+
+    /// Get the view account key. For monitors that hold the full account key
+    /// this is derived from it.
+    pub fn view_account_key(&self) -> Result<ViewAccountKey, Error> {
+        match (&self.account_key, &self.view_account_key) {
+            (Some(account_key), _) => Ok(ViewAccountKey::from(account_key)),
+            (None, Some(view_account_key)) => Ok(view_account_key.clone()),
+            (None, None) => Err(Error::InvalidMonitorData),
+        }
+    }
+
+    /// The view private key of the account this monitor watches.
+    pub fn view_private_key(&self) -> Result<&RistrettoPrivate, Error> {
+        match (&self.account_key, &self.view_account_key) {
+            (Some(account_key), _) => Ok(account_key.view_private_key()),
+            (None, Some(view_account_key)) => Ok(view_account_key.view_private_key()),
+            (None, None) => Err(Error::InvalidMonitorData),
+        }
+    }
+
+    /// The public address of a subaddress of the account this monitor
+    /// watches. Addresses of view-only monitors never contain fog
+    /// information.
+    pub fn subaddress(&self, index: u64) -> Result<PublicAddress, Error> {
+        match (&self.account_key, &self.view_account_key) {
+            (Some(account_key), _) => Ok(account_key.subaddress(index)),
+            (None, Some(view_account_key)) => Ok(view_account_key.subaddress(index)),
+            (None, None) => Err(Error::InvalidMonitorData),
+        }
+    }
+
+    /// The default subaddress of the account this monitor watches.
+    pub fn default_subaddress(&self) -> Result<PublicAddress, Error> {
+        match (&self.account_key, &self.view_account_key) {
+            (Some(account_key), _) => Ok(account_key.default_subaddress()),
+            (None, Some(view_account_key)) => Ok(view_account_key.default_subaddress()),
+            (None, None) => Err(Error::InvalidMonitorData),
+        }
+    }
 }
 
 /// Type used as the key in the monitor_id_to_monitor_data database
 pub type MonitorId = DatabaseByteArrayKey;
 
-impl From<&MonitorData> for MonitorId {
+impl TryFrom<&MonitorData> for MonitorId {
+    type Error = Error;
+
     // When constructing a MonitorId from a given MonitorData object we only want to
     // hash the data that doesn't change over time.
     // Name isn't included here - two monitors with identical address/subaddress
     // range/first_block should have the same id even if they have a different
     // name,
-    fn from(src: &MonitorData) -> MonitorId {
+    fn try_from(src: &MonitorData) -> Result<MonitorId, Error> {
         // The structure of mc_account_keys::PublicAddress changed when the fog
         // signature scheme was implemented. This re-implements the original
         // structure in order to maintain a consistent hash in the database.
@@ -119,7 +268,7 @@ impl From<&MonitorData> for MonitorId {
             pub first_block: u64,
         }
 
-        let real_subaddress = src.account_key.default_subaddress();
+        let real_subaddress = src.default_subaddress()?;
 
         let const_data = ConstMonitorData {
             address: PublicAddress {
@@ -145,7 +294,7 @@ impl From<&MonitorData> for MonitorId {
 
         let temp: [u8; 32] = const_data.digest32::<MerlinTranscript>(b"monitor_data");
 
-        Self::from(temp)
+        Ok(Self::from(temp))
     }
 }
 
@@ -191,7 +340,7 @@ impl MonitorStore {
         db_txn: &mut RwTransaction<'env>,
         data: &MonitorData,
     ) -> Result<MonitorId, Error> {
-        let monitor_id = MonitorId::from(data);
+        let monitor_id = MonitorId::try_from(data)?;
         let key_bytes = monitor_id.as_bytes();
 
         let value_bytes = self
@@ -229,11 +378,7 @@ impl MonitorStore {
         monitor_id: &MonitorId,
     ) -> Result<MonitorData, Error> {
         match db_txn.get(self.monitor_id_to_monitor_data, monitor_id) {
-            Ok(value_bytes) => {
-                let value_bytes = self.crypto_provider.decrypt(value_bytes)?;
-                let data: MonitorData = mc_util_serial::decode(&value_bytes)?;
-                Ok(data)
-            }
+            Ok(value_bytes) => self.decode_data(value_bytes),
             Err(lmdb::Error::NotFound) => Err(Error::MonitorIdNotFound),
             Err(err) => Err(Error::Lmdb(err)),
         }
@@ -254,8 +399,7 @@ impl MonitorStore {
                     .and_then(|(key_bytes, value_bytes)| {
                         let monitor_id = MonitorId::try_from(key_bytes)
                             .map_err(|_| Error::KeyDeserialization)?;
-                        let value_bytes = self.crypto_provider.decrypt(value_bytes)?;
-                        let data: MonitorData = mc_util_serial::decode(&value_bytes)?;
+                        let data = self.decode_data(value_bytes)?;
 
                         Ok((monitor_id, data))
                     })
@@ -263,6 +407,14 @@ impl MonitorStore {
             .collect::<Result<HashMap<_, _>, Error>>()
     }
 
+    // Decrypt and decode a stored MonitorData, checking that it is valid.
+    fn decode_data(&self, value_bytes: &[u8]) -> Result<MonitorData, Error> {
+        let value_bytes = self.crypto_provider.decrypt(value_bytes)?;
+        let data: MonitorData = mc_util_serial::decode(&value_bytes)?;
+        data.validate()?;
+        Ok(data)
+    }
+
     /// Get a list of all MonitorIds in database.
     pub fn get_ids(&self, db_txn: &impl Transaction) -> Result<Vec<MonitorId>, Error> {
         let mut cursor = db_txn.open_ro_cursor(self.monitor_id_to_monitor_data)?;
@@ -312,14 +464,26 @@ impl MonitorStore {
         &self,
         db_txn: &mut RwTransaction<'env>,
         new_password: &[u8],
+        new_kdf_params: &KdfParams,
     ) -> Result<(), Error> {
+        // Derive the new key once, rather than for every record.
+        let new_key = if new_password.is_empty() {
+            None
+        } else {
+            Some(new_kdf_params.derive_key(new_password)?)
+        };
+
         let mut cursor = db_txn.open_rw_cursor(self.monitor_id_to_monitor_data)?;
 
-        for (key_bytes, value_bytes) in cursor.iter().filter_map(|r| r.ok()) {
+        // Errors are propagated rather than skipped, so that a failure aborts the
+        // transaction instead of committing a partially re-encrypted store.
+        for result in cursor.iter() {
+            let (key_bytes, value_bytes) = result?;
             let decrypted_bytes = self.crypto_provider.decrypt(value_bytes)?;
-            let encrypted_bytes = self
-                .crypto_provider
-                .encrypt_with_password(new_password, &decrypted_bytes)?;
+            let encrypted_bytes = match &new_key {
+                Some(key) => key.encrypt(&decrypted_bytes)?,
+                None => decrypted_bytes,
+            };
             cursor.put(&key_bytes, &encrypted_bytes, WriteFlags::CURRENT)?;
         }
 
@@ -340,6 +504,7 @@ mod test {
     use rand_chacha::ChaChaRng;
     use rand_core::SeedableRng;
     use std::{assert_matches::assert_matches, collections::HashSet};
+    use tempdir::TempDir;
 
     /// A randomly generated RSA subjectPublicKeyInfo, used as a fog authority.
     const AUTHORITY_PUBKEY: &str = r"-----BEGIN PUBLIC KEY-----
@@ -374,7 +539,7 @@ pKZkdp8MQU5TLFOE9qjNeVsCAwEAAQ==
         let key = AccountKey::try_from(&identity)
             .expect("Could not create account key from non-fog identity");
         let data = MonitorData::new(key, 1, 10, 1, "test").expect("Could not create monitor data");
-        let id = MonitorId::from(&data);
+        let id = MonitorId::try_from(&data).unwrap();
         let expected = hex::decode(HEXPECTED).expect("Could not decode expected data to bytes");
         assert_eq!(expected, id.as_bytes().to_vec(), "{}", hex_fmt::HexFmt(id));
 
@@ -390,7 +555,7 @@ pKZkdp8MQU5TLFOE9qjNeVsCAwEAAQ==
         let fog_key = AccountKey::from(&fog_identity);
         let fog_data = MonitorData::new(fog_key, 10, 100, 10, "fog test")
             .expect("Could not create monitor data");
-        let fog_id = MonitorId::from(&fog_data);
+        let fog_id = MonitorId::try_from(&fog_data).unwrap();
         let fog_expected =
             hex::decode(FOG_HEXPECTED).expect("Could not decode expected data to bytes");
         assert_eq!(
@@ -402,6 +567,104 @@ pKZkdp8MQU5TLFOE9qjNeVsCAwEAAQ==
         );
     }
 
+    /// A view-only monitor and a full monitor for the same (non-fog) account
+    /// and range share a monitor id, and view-only data round-trips through
+    /// the database.
+    #[test_with_logger]
+    fn test_view_only_monitor_data(logger: Logger) {
+        let mut rng = ChaChaRng::from_seed([124u8; 32]);
+
+        let (_ledger_db, mobilecoind_db) =
+            get_test_databases(BlockVersion::MAX, 3, &[], 10, logger, &mut rng);
+
+        let account_key = AccountKey::random(&mut rng);
+        let view_account_key = ViewAccountKey::from(&account_key);
+
+        let full_data = MonitorData::new(account_key.clone(), 0, 5, 0, "full").unwrap();
+        let view_data =
+            MonitorData::new_view_only(view_account_key.clone(), 0, 5, 0, "view").unwrap();
+
+        assert!(!full_data.is_view_only());
+        assert!(view_data.is_view_only());
+        assert_matches!(
+            view_data.spendable_account_key(),
+            Err(Error::ViewOnlyMonitor)
+        );
+        assert_eq!(view_data.view_account_key().unwrap(), view_account_key);
+        assert_eq!(full_data.view_account_key().unwrap(), view_account_key);
+        assert_eq!(view_data.subaddress(3).unwrap(), account_key.subaddress(3));
+        assert_eq!(
+            MonitorId::try_from(&full_data).unwrap(),
+            MonitorId::try_from(&view_data).unwrap()
+        );
+
+        let monitor_id = mobilecoind_db
+            .add_monitor(&view_data)
+            .expect("failed inserting view-only monitor");
+        assert_eq!(
+            mobilecoind_db
+                .get_monitor_data(&monitor_id)
+                .expect("failed getting view-only monitor data"),
+            view_data
+        );
+    }
+
+    /// Monitor data without any keys is rejected instead of being used.
+    #[test_with_logger]
+    fn test_invalid_monitor_data(logger: Logger) {
+        let mut rng = ChaChaRng::from_seed([125u8; 32]);
+
+        let db_tmp =
+            TempDir::new("monitor_store_db").expect("Could not make tempdir for monitor store db");
+        let env = Arc::new(
+            Environment::new()
+                .set_max_dbs(10)
+                .set_map_size(10000000)
+                .open(db_tmp.path())
+                .unwrap(),
+        );
+        let crypto_provider = DbCryptoProvider::new(env.clone()).unwrap();
+        let monitor_store = MonitorStore::new(env.clone(), crypto_provider, logger).unwrap();
+
+        let data = MonitorData::new(AccountKey::random(&mut rng), 0, 5, 0, "").unwrap();
+        let invalid_data = MonitorData {
+            account_key: None,
+            ..data.clone()
+        };
+        assert_matches!(invalid_data.validate(), Err(Error::InvalidMonitorData));
+        assert!(matches!(
+            invalid_data.view_private_key(),
+            Err(Error::InvalidMonitorData)
+        ));
+        assert_matches!(
+            MonitorId::try_from(&invalid_data),
+            Err(Error::InvalidMonitorData)
+        );
+
+        let mut db_txn = env.begin_rw_txn().unwrap();
+        assert_matches!(
+            monitor_store.add(&mut db_txn, &invalid_data),
+            Err(Error::InvalidMonitorData)
+        );
+
+        // Simulate a corrupt record.
+        let monitor_id = monitor_store.add(&mut db_txn, &data).unwrap();
+        monitor_store
+            .set_data(&mut db_txn, &monitor_id, &invalid_data)
+            .unwrap();
+        db_txn.commit().unwrap();
+
+        let db_txn = env.begin_ro_txn().unwrap();
+        assert_matches!(
+            monitor_store.get_data(&db_txn, &monitor_id),
+            Err(Error::InvalidMonitorData)
+        );
+        assert_matches!(
+            monitor_store.get_map(&db_txn),
+            Err(Error::InvalidMonitorData)
+        );
+    }
+
     // MonitorStore basic functionality tests
     #[test_with_logger]
     fn test_monitor_store(logger: Logger) {
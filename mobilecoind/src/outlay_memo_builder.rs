@@ -0,0 +1,186 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! A memo builder that writes a separately configured memo for each outlay
+//! of a transaction.
+
+use mc_account_keys::{AccountKey, PublicAddress};
+use mc_transaction_builder::{MemoBuilder, RTHMemoBuilder, ReservedSubaddresses};
+use mc_transaction_core::{Amount, MemoContext, MemoPayload, NewMemoError};
+use mc_transaction_extra::SenderMemoCredential;
+use std::collections::VecDeque;
+
+/// The memo to attach to the TxOut created for an outlay.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutlayMemo {
+    /// No memo (an unused memo).
+    None,
+
+    /// An authenticated sender memo.
+    Sender,
+
+    /// An authenticated sender memo with a payment request id.
+    SenderWithPaymentRequestId(u64),
+
+    /// An authenticated sender memo with a payment intent id.
+    SenderWithPaymentIntentId(u64),
+}
+
+/// Writes the memo of each outlay, in the order the outputs are added to the
+/// transaction. Change outputs get an unused memo, since the outlays of a
+/// transaction may carry different payment request and intent ids.
+#[derive(Debug)]
+pub struct OutlayMemoBuilder {
+    /// Produces the memos, reconfigured before each output.
+    rth_memo_builder: RTHMemoBuilder,
+
+    /// The credential used for authenticated sender memos.
+    sender_cred: SenderMemoCredential,
+
+    /// The memos of the outlays that have not been added yet.
+    outlay_memos: VecDeque<OutlayMemo>,
+}
+
+impl OutlayMemoBuilder {
+    /// Create a memo builder for the given outlay memos, signing sender memos
+    /// with `sender_cred`.
+    pub fn new(sender_cred: SenderMemoCredential, outlay_memos: &[OutlayMemo]) -> Self {
+        Self {
+            rth_memo_builder: RTHMemoBuilder::default(),
+            sender_cred,
+            outlay_memos: outlay_memos.iter().cloned().collect(),
+        }
+    }
+
+    /// Get a memo builder for a transaction paying the given outlays from
+    /// `sender_account_key`. Returns None if none of the outlays have a memo,
+    /// in which case the default memo builder should be used.
+    pub fn for_outlays(
+        sender_account_key: &AccountKey,
+        outlay_memos: &[OutlayMemo],
+    ) -> Option<Box<dyn MemoBuilder + 'static + Send + Sync>> {
+        if outlay_memos.iter().all(|memo| *memo == OutlayMemo::None) {
+            return None;
+        }
+
+        Some(Box::new(Self::new(
+            SenderMemoCredential::from(sender_account_key),
+            outlay_memos,
+        )))
+    }
+}
+
+impl MemoBuilder for OutlayMemoBuilder {
+    fn set_fee(&mut self, fee: Amount) -> Result<(), NewMemoError> {
+        self.rth_memo_builder.set_fee(fee)
+    }
+
+    fn make_memo_for_output(
+        &mut self,
+        amount: Amount,
+        recipient: &PublicAddress,
+        memo_context: MemoContext,
+    ) -> Result<MemoPayload, NewMemoError> {
+        let outlay_memo = self
+            .outlay_memos
+            .pop_front()
+            .ok_or_else(|| NewMemoError::MissingInput("outlay memo".into()))?;
+
+        let builder = &mut self.rth_memo_builder;
+        builder.clear_payment_request_id();
+        builder.clear_payment_intent_id();
+        match outlay_memo {
+            OutlayMemo::None => builder.clear_sender_credential(),
+            OutlayMemo::Sender => builder.set_sender_credential(self.sender_cred.clone()),
+            OutlayMemo::SenderWithPaymentRequestId(id) => {
+                builder.set_sender_credential(self.sender_cred.clone());
+                builder.set_payment_request_id(id);
+            }
+            OutlayMemo::SenderWithPaymentIntentId(id) => {
+                builder.set_sender_credential(self.sender_cred.clone());
+                builder.set_payment_intent_id(id);
+            }
+        }
+
+        builder.make_memo_for_output(amount, recipient, memo_context)
+    }
+
+    fn make_memo_for_change_output(
+        &mut self,
+        amount: Amount,
+        change_destination: &ReservedSubaddresses,
+        memo_context: MemoContext,
+    ) -> Result<MemoPayload, NewMemoError> {
+        // Destination memos are not enabled, so this produces an unused memo.
+        self.rth_memo_builder
+            .make_memo_for_change_output(amount, change_destination, memo_context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_crypto_keys::RistrettoPublic;
+    use mc_transaction_core::{tokens::Mob, Token};
+    use mc_transaction_extra::{
+        AuthenticatedSenderMemo, AuthenticatedSenderWithPaymentIntentIdMemo,
+        AuthenticatedSenderWithPaymentRequestIdMemo, RegisteredMemoType, UnusedMemo,
+    };
+    use mc_util_from_random::FromRandom;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_outlay_memos() {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let sender = AccountKey::random(&mut rng);
+        let recipient = AccountKey::random(&mut rng).default_subaddress();
+        let tx_public_key = RistrettoPublic::from_random(&mut rng);
+        let amount = Amount::new(100, Mob::ID);
+
+        let outlay_memos = [
+            OutlayMemo::None,
+            OutlayMemo::Sender,
+            OutlayMemo::SenderWithPaymentRequestId(123),
+            OutlayMemo::SenderWithPaymentIntentId(456),
+        ];
+        let mut builder = OutlayMemoBuilder::for_outlays(&sender, &outlay_memos).unwrap();
+
+        let memo_types: Vec<[u8; 2]> = outlay_memos
+            .iter()
+            .map(|_| {
+                *builder
+                    .make_memo_for_output(
+                        amount,
+                        &recipient,
+                        MemoContext {
+                            tx_public_key: &tx_public_key,
+                        },
+                    )
+                    .unwrap()
+                    .get_memo_type()
+            })
+            .collect();
+        assert_eq!(
+            memo_types,
+            vec![
+                UnusedMemo::MEMO_TYPE_BYTES,
+                AuthenticatedSenderMemo::MEMO_TYPE_BYTES,
+                AuthenticatedSenderWithPaymentRequestIdMemo::MEMO_TYPE_BYTES,
+                AuthenticatedSenderWithPaymentIntentIdMemo::MEMO_TYPE_BYTES,
+            ]
+        );
+
+        // There are no more outlays.
+        assert!(builder
+            .make_memo_for_output(
+                amount,
+                &recipient,
+                MemoContext {
+                    tx_public_key: &tx_public_key,
+                },
+            )
+            .is_err());
+
+        // Outlays without memos use the default memo builder.
+        assert!(OutlayMemoBuilder::for_outlays(&sender, &[OutlayMemo::None]).is_none());
+    }
+}
@@ -2,8 +2,15 @@
 
 //! Construct and submit transactions to the validator network.
 
-use crate::{database::Database, error::Error, monitor_store::MonitorId, utxo_store::UnspentTxOut};
-use mc_account_keys::{AccountKey, PublicAddress};
+use crate::{
+    database::Database,
+    error::Error,
+    monitor_store::MonitorId,
+    outlay_memo_builder::{OutlayMemo, OutlayMemoBuilder},
+    utxo_selection::{SmallestFirstUtxoSelection, UtxoSelectionStrategy},
+    utxo_store::UnspentTxOut,
+};
+use mc_account_keys::{AccountKey, PublicAddress, GIFT_CODE_SUBADDRESS_INDEX};
 use mc_blockchain_types::{BlockIndex, BlockVersion};
 use mc_common::{
     logger::{log, o, Logger},
@@ -12,23 +19,27 @@ use mc_common::{
 use mc_connection::{
     BlockInfo, BlockchainConnection, ConnectionManager, RetryableUserTxConnection, UserTxConnection,
 };
-use mc_crypto_keys::RistrettoPublic;
+use mc_crypto_keys::{CompressedRistrettoPublic, RistrettoPublic};
 use mc_crypto_rand::{CryptoRng, RngCore};
-use mc_crypto_ring_signature_signer::NoKeysRingSigner;
+use mc_crypto_ring_signature_signer::{InputSecret, NoKeysRingSigner};
 use mc_fog_report_validation::FogPubkeyResolver;
 use mc_ledger_db::{Error as LedgerError, Ledger, LedgerDB};
 use mc_transaction_builder::{
-    EmptyMemoBuilder, InputCredentials, MemoBuilder, ReservedSubaddresses, TransactionBuilder,
-    TxOutContext,
+    EmptyMemoBuilder, GiftCodeCancellationMemoBuilder, GiftCodeFundingMemoBuilder,
+    GiftCodeSenderMemoBuilder, InputCredentials, MemoBuilder, ReservedSubaddresses,
+    SignedContingentInputBuilder, TransactionBuilder, TxOutContext,
 };
 use mc_transaction_core::{
-    constants::{MAX_INPUTS, MILLIMOB_TO_PICOMOB, RING_SIZE},
+    constants::{MAX_INPUTS, MAX_OUTPUTS, MILLIMOB_TO_PICOMOB, RING_SIZE},
+    get_tx_out_shared_secret,
     onetime_keys::recover_onetime_private_key,
     ring_signature::KeyImage,
     tx::{Tx, TxOut, TxOutMembershipProof},
     Amount, TokenId,
 };
-use mc_transaction_extra::TxOutConfirmationNumber;
+use mc_transaction_extra::{
+    SignedContingentInput, SignedContingentInputError, TxOutConfirmationNumber, TxOutGiftCode,
+};
 use mc_util_uri::FogUri;
 use rand::Rng;
 use std::{
@@ -49,6 +60,10 @@ pub const DEFAULT_NEW_TX_BLOCK_ATTEMPTS: u64 = 50;
 /// Default ring size
 pub const DEFAULT_RING_SIZE: usize = RING_SIZE;
 
+/// The maximal number of outlays in a single transaction of a batch payment.
+/// This leaves room for a change output.
+pub const MAX_OUTLAYS_PER_TX: usize = MAX_OUTPUTS as usize - 1;
+
 /// The original hard-coded 10mMOB fee, used as a fallback when calls to
 /// consensus fail or we have no peers.
 const FALLBACK_FEE: u64 = 10 * MILLIMOB_TO_PICOMOB;
@@ -63,6 +78,18 @@ pub struct Outlay {
     pub receiver: PublicAddress,
 }
 
+/// An outlay in an explicit token id. This is used when the outputs being
+/// created are not necessarily in the same token as the inputs, e.g. the
+/// outputs required by a signed contingent input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutlayV2 {
+    /// Amount being sent.
+    pub amount: Amount,
+
+    /// Destination.
+    pub receiver: PublicAddress,
+}
+
 /// A single pending transaction.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TxProposal {
@@ -90,6 +117,30 @@ impl TxProposal {
     }
 }
 
+/// What a payment would cost and which inputs it would use.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaymentEstimate {
+    /// The fee the transaction would pay.
+    pub fee: u64,
+
+    /// UTXOs the transaction would spend.
+    pub utxos: Vec<UnspentTxOut>,
+
+    /// The value that would be returned as change.
+    pub change: u64,
+
+    /// Whether the payment needs more than MAX_INPUTS inputs, meaning the
+    /// wallet has to be optimized before the payment can be sent.
+    pub requires_optimization: bool,
+
+    /// The number of optimization transactions that have to be sent before
+    /// the payment. Their inputs are included in `utxos`.
+    pub num_optimization_txs: u64,
+
+    /// The total fee paid by the optimization transactions.
+    pub optimization_fee: u64,
+}
+
 pub struct TransactionsManager<
     T: BlockchainConnection + UserTxConnection + 'static,
     FPR: FogPubkeyResolver,
@@ -210,6 +261,8 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
     /// * `change_subaddress` - Recipient of any change.
     /// * `inputs` - UTXOs that will be spent by the transaction.
     /// * `outlays` - Output amounts and recipients.
+    /// * `utxo_selection_strategy` - How to choose which of the inputs to
+    ///   spend.
     /// * `last_block_infos` - Last block info responses from the network, for
     ///   determining fees. This should normally come from polling_network_state
     /// * `opt_fee` - Transaction fee in picoMOB. If zero, defaults to MIN_FEE.
@@ -223,6 +276,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         change_subaddress: u64,
         inputs: &[UnspentTxOut],
         outlays: &[Outlay],
+        utxo_selection_strategy: &dyn UtxoSelectionStrategy,
         last_block_infos: &[BlockInfo],
         opt_fee: u64,
         opt_tombstone: u64,
@@ -244,8 +298,9 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             return Err(Error::TxBuild("Must have at least one destination".into()));
         }
 
-        // Get sender monitor data.
+        // Get sender monitor data. View-only monitors cannot spend.
         let sender_monitor_data = self.mobilecoind_db.get_monitor_data(sender_monitor_id)?;
+        let sender_account_key = sender_monitor_data.spendable_account_key()?;
 
         // Figure out total amount of transaction (excluding fee).
         let total_value: u64 = outlays.iter().map(|outlay| outlay.value).sum();
@@ -265,8 +320,13 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             BlockVersion::try_from(block_version).map_err(|err| Error::TxBuild(err.to_string()))?;
 
         // Select the UTXOs to be used for this transaction.
-        let selected_utxos =
-            Self::select_utxos_for_value(token_id, inputs, total_value + fee, MAX_INPUTS as usize)?;
+        let selected_utxos = Self::select_utxos_for_value(
+            utxo_selection_strategy,
+            token_id,
+            inputs,
+            total_value + fee,
+            MAX_INPUTS as usize,
+        )?;
         log::trace!(
             logger,
             "Selected {} utxos ({:?})",
@@ -318,7 +378,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             block_version,
             token_id,
             fee,
-            &sender_monitor_data.account_key,
+            sender_account_key,
             change_subaddress,
             outlays,
             tombstone_block,
@@ -332,6 +392,247 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         Ok(tx_proposal)
     }
 
+    /// Estimate the fee, inputs and change of a payment, without building it.
+    /// Inputs are selected the same way `build_transaction` selects them.
+    ///
+    /// # Arguments
+    /// * `token_id` - The token id to transact in.
+    /// * `inputs` - UTXOs that could be spent by the transaction.
+    /// * `outlays` - Output amounts and recipients.
+    /// * `utxo_selection_strategy` - How to choose which of the inputs to
+    ///   spend.
+    /// * `last_block_infos` - Last block info responses from the network, for
+    ///   determining fees. This should normally come from polling_network_state
+    /// * `opt_fee` - Transaction fee in picoMOB. If zero, defaults to MIN_FEE.
+    pub fn estimate_payment(
+        &self,
+        token_id: TokenId,
+        inputs: &[UnspentTxOut],
+        outlays: &[Outlay],
+        utxo_selection_strategy: &dyn UtxoSelectionStrategy,
+        last_block_infos: &[BlockInfo],
+        opt_fee: u64,
+    ) -> Result<PaymentEstimate, Error> {
+        // Must have at least one output
+        if outlays.is_empty() {
+            return Err(Error::TxBuild("Must have at least one destination".into()));
+        }
+
+        let total_value: u64 = outlays.iter().map(|outlay| outlay.value).sum();
+        let (fee, _block_version) =
+            self.get_network_fee_and_block_version(token_id, opt_fee, last_block_infos)?;
+
+        // If the payment cannot be made with MAX_INPUTS inputs, see which optimization
+        // transactions would have to be sent first.
+        let (utxos, num_optimization_txs) = match Self::select_utxos_for_value(
+            utxo_selection_strategy,
+            token_id,
+            inputs,
+            total_value + fee,
+            MAX_INPUTS as usize,
+        ) {
+            Ok(utxos) => (utxos, 0),
+            Err(Error::InsufficientFundsFragmentedUtxos) => Self::plan_optimization(
+                utxo_selection_strategy,
+                token_id,
+                inputs,
+                total_value + fee,
+                fee,
+                MAX_INPUTS as usize,
+            )?,
+            Err(err) => return Err(err),
+        };
+
+        let input_value: u64 = utxos.iter().map(|utxo| utxo.value).sum();
+        let optimization_fee = num_optimization_txs * fee;
+        Ok(PaymentEstimate {
+            fee,
+            change: input_value - total_value - fee - optimization_fee,
+            utxos,
+            requires_optimization: num_optimization_txs > 0,
+            num_optimization_txs,
+            optimization_fee,
+        })
+    }
+
+    /// Simulate the optimization transactions `generate_optimization_tx` would
+    /// produce until `value` can be spent with at most `max_inputs` inputs.
+    ///
+    /// Returns the UTXOs spent by the optimization transactions and by the
+    /// payment that follows them, and the number of optimization transactions.
+    fn plan_optimization(
+        utxo_selection_strategy: &dyn UtxoSelectionStrategy,
+        token_id: TokenId,
+        utxos: &[UnspentTxOut],
+        value: u64,
+        fee: u64,
+        max_inputs: usize,
+    ) -> Result<(Vec<UnspentTxOut>, u64), Error> {
+        // Each candidate input is either a UTXO, or the output of an optimization
+        // transaction, along with the UTXOs it is made of.
+        let mut candidates: Vec<(u64, Vec<&UnspentTxOut>)> = utxos
+            .iter()
+            .filter(|utxo| utxo.token_id == *token_id)
+            .map(|utxo| (utxo.value, vec![utxo]))
+            .collect();
+        let mut num_optimization_txs = 0;
+
+        loop {
+            candidates.sort_by_key(|(value, _)| Reverse(*value));
+            let max_spendable: u64 = candidates
+                .iter()
+                .take(max_inputs)
+                .map(|(value, _)| value)
+                .sum();
+            if max_spendable >= value {
+                break;
+            }
+            if candidates.len() <= max_inputs {
+                return Err(Error::InsufficientFunds);
+            }
+
+            // Like `select_utxos_for_optimization`, merge the smallest candidates into
+            // the biggest one, leaving out the smallest ones for as long as merging
+            // them would lose money.
+            let num_merged = max_inputs - 1;
+            let mut smallest_first = candidates.split_off(1);
+            smallest_first.reverse();
+            let start = (0..smallest_first.len())
+                .find(|start| {
+                    smallest_first[*start..]
+                        .iter()
+                        .take(num_merged)
+                        .map(|(value, _)| value)
+                        .sum::<u64>()
+                        > fee
+                })
+                .ok_or(Error::InsufficientFunds)?;
+            let end = (start + num_merged).min(smallest_first.len());
+            let merged: Vec<_> = smallest_first.drain(start..end).collect();
+            let merged_value: u64 = merged.iter().map(|(value, _)| value).sum();
+            let (biggest_value, mut merged_utxos) = candidates.remove(0);
+            merged_utxos.extend(merged.into_iter().flat_map(|(_, utxos)| utxos));
+            candidates = smallest_first;
+            candidates.push((biggest_value + merged_value - fee, merged_utxos));
+            num_optimization_txs += 1;
+        }
+
+        // The payment spends the candidates chosen by the selection strategy. The
+        // output of an optimization transaction is stood in for by the biggest UTXO it
+        // merges, with the merged value.
+        let stand_ins: Vec<UnspentTxOut> = candidates
+            .iter()
+            .map(|(candidate_value, candidate_utxos)| UnspentTxOut {
+                value: *candidate_value,
+                ..candidate_utxos[0].clone()
+            })
+            .collect();
+        let selected_utxos = Self::select_utxos_for_value(
+            utxo_selection_strategy,
+            token_id,
+            &stand_ins,
+            value,
+            max_inputs,
+        )?;
+        let mut is_selected = vec![false; candidates.len()];
+        for utxo in &selected_utxos {
+            // Prefer optimization outputs, which are spent anyway.
+            let index = (0..candidates.len())
+                .filter(|index| !is_selected[*index] && stand_ins[*index] == *utxo)
+                .min_by_key(|index| candidates[*index].1.len() == 1)
+                .ok_or_else(|| {
+                    Error::TxBuild("UTXO selection strategy chose an unknown utxo".into())
+                })?;
+            is_selected[index] = true;
+        }
+
+        // The optimization transactions spend their inputs even if the payment does
+        // not use their output.
+        let spent_utxos = candidates
+            .into_iter()
+            .zip(is_selected)
+            .filter(|((_, candidate_utxos), is_selected)| *is_selected || candidate_utxos.len() > 1)
+            .flat_map(|((_, candidate_utxos), _)| candidate_utxos)
+            .cloned()
+            .collect();
+
+        Ok((spent_utxos, num_optimization_txs))
+    }
+
+    /// Create the TxProposals of a batch payment. Each outlay carries its own
+    /// memo, and the outlays are split across as many transactions as needed to
+    /// stay within the per-transaction output limit. The transactions spend
+    /// disjoint sets of inputs, so they can all be submitted at once. Change
+    /// from one transaction is not available to the others.
+    ///
+    /// # Arguments
+    /// * `sender_monitor_id` - Indicates the the account key needed to spend
+    ///   the txo's.
+    /// * `token_id` - The token id to transact in.
+    /// * `change_subaddress` - Recipient of any change.
+    /// * `inputs` - UTXOs that will be spent by the transactions.
+    /// * `outlays` - Output amounts and recipients, with their memos.
+    /// * `utxo_selection_strategy` - How to choose which of the inputs to
+    ///   spend.
+    /// * `last_block_infos` - Last block info responses from the network, for
+    ///   determining fees. This should normally come from polling_network_state
+    /// * `opt_fee` - Transaction fee in picoMOB, paid by every transaction of
+    ///   the batch. If zero, defaults to MIN_FEE.
+    /// * `opt_tombstone` - Tombstone block. If zero, sets to default.
+    pub fn build_batch_transactions(
+        &self,
+        sender_monitor_id: &MonitorId,
+        token_id: TokenId,
+        change_subaddress: u64,
+        inputs: &[UnspentTxOut],
+        outlays: &[(Outlay, OutlayMemo)],
+        utxo_selection_strategy: &dyn UtxoSelectionStrategy,
+        last_block_infos: &[BlockInfo],
+        opt_fee: u64,
+        opt_tombstone: u64,
+    ) -> Result<Vec<TxProposal>, Error> {
+        // Must have at least one output
+        if outlays.is_empty() {
+            return Err(Error::TxBuild("Must have at least one destination".into()));
+        }
+
+        // Get sender monitor data. View-only monitors cannot spend.
+        let sender_monitor_data = self.mobilecoind_db.get_monitor_data(sender_monitor_id)?;
+        let sender_account_key = sender_monitor_data.spendable_account_key()?;
+
+        let mut remaining_inputs = inputs.to_vec();
+        let mut tx_proposals = Vec::new();
+        for batch_outlays in outlays.chunks(MAX_OUTLAYS_PER_TX) {
+            let (batch_outlays, batch_memos): (Vec<Outlay>, Vec<OutlayMemo>) =
+                batch_outlays.iter().cloned().unzip();
+
+            let tx_proposal = self.build_transaction(
+                sender_monitor_id,
+                token_id,
+                change_subaddress,
+                &remaining_inputs,
+                &batch_outlays,
+                utxo_selection_strategy,
+                last_block_infos,
+                opt_fee,
+                opt_tombstone,
+                OutlayMemoBuilder::for_outlays(sender_account_key, &batch_memos),
+            )?;
+
+            remaining_inputs.retain(|utxo| !tx_proposal.utxos.contains(utxo));
+            tx_proposals.push(tx_proposal);
+        }
+
+        log::trace!(
+            self.logger,
+            "Built {} transactions for {} outlays",
+            tx_proposals.len(),
+            outlays.len()
+        );
+
+        Ok(tx_proposals)
+    }
+
     /// Create a TxProposal that attempts to merge multiple UTXOs into a single
     /// larger UTXO.
     ///
@@ -356,8 +657,9 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         );
         log::trace!(logger, "Generating optimization transaction...");
 
-        // Get monitor data.
+        // Get monitor data. View-only monitors cannot spend.
         let monitor_data = self.mobilecoind_db.get_monitor_data(monitor_id)?;
+        let account_key = monitor_data.spendable_account_key()?;
 
         let num_blocks_in_ledger = self.ledger_db.num_blocks()?;
 
@@ -370,11 +672,16 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         let block_version =
             BlockVersion::try_from(block_version).map_err(|err| Error::TxBuild(err.to_string()))?;
 
-        // Select UTXOs that will be spent by this transaction.
+        // Select UTXOs that will be spent by this transaction. Only UTXOs of the
+        // token being merged are considered, since a subaddress may hold several
+        // tokens.
         let selected_utxos = {
-            let inputs = self
+            let inputs: Vec<UnspentTxOut> = self
                 .mobilecoind_db
-                .get_utxos_for_subaddress(monitor_id, subaddress_index)?;
+                .get_utxos_for_subaddress(monitor_id, subaddress_index)?
+                .into_iter()
+                .filter(|utxo| utxo.token_id == *token_id)
+                .collect();
             Self::select_utxos_for_optimization(
                 num_blocks_in_ledger,
                 &inputs,
@@ -432,7 +739,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
 
         // We are paying ourselves the entire amount.
         let outlays = vec![Outlay {
-            receiver: monitor_data.account_key.subaddress(subaddress_index),
+            receiver: account_key.subaddress(subaddress_index),
             value: total_value - fee,
         }];
 
@@ -444,7 +751,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             block_version,
             token_id,
             fee,
-            &monitor_data.account_key,
+            account_key,
             subaddress_index,
             &outlays,
             tombstone_block,
@@ -459,171 +766,1081 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             tx_proposal.tx.tx_hash()
         );
 
-        Ok(tx_proposal)
+        Ok(tx_proposal)
+    }
+
+    /// Create a TxProposal that sends the total value of all inputs minus the
+    /// fee to a single receiver. (ignoring inputs with wrong token id)
+    ///
+    /// # Arguments
+    /// * `account_key` - Account key that owns the inputs.
+    /// * `token_id` - The token id to transact in.
+    /// * `inputs` - UTXOs that will be spent by the transaction.
+    /// * `receiver` - The single receiver of the transaction's outputs.
+    /// * `last_block_infos` - Last block info responses from the network, for
+    ///   determining fees. This should normally come from polling_network_state
+    /// * `opt_fee` - Transaction fee. If zero, defaults to the highest fee set
+    ///   by configured consensus nodes, or the hard-coded FALLBACK_FEE.
+    pub fn generate_tx_from_tx_list(
+        &self,
+        account_key: &AccountKey,
+        token_id: TokenId,
+        inputs: &[UnspentTxOut],
+        receiver: &PublicAddress,
+        last_block_infos: &[BlockInfo],
+        opt_fee: u64,
+    ) -> Result<TxProposal, Error> {
+        let logger = self.logger.new(o!("receiver" => receiver.to_string()));
+        log::trace!(logger, "Generating txo list transaction...");
+
+        // All inputs must be of the correct token id.
+        if inputs.iter().any(|utxo| utxo.token_id != *token_id) {
+            return Err(Error::InvalidArgument(
+                "inputs".to_string(),
+                format!("All inputs must be of token_id {}", token_id),
+            ));
+        }
+
+        // Figure out the block_version and fee (involves network round-trips to
+        // consensus, unless fee arg is non-zero)
+        let (fee, block_version) =
+            self.get_network_fee_and_block_version(token_id, opt_fee, last_block_infos)?;
+
+        // Make sure we understand this block version
+        let block_version =
+            BlockVersion::try_from(block_version).map_err(|err| Error::TxBuild(err.to_string()))?;
+
+        // All inputs are to be spent, except those with wrong token id
+        let total_value: u64 = inputs.iter().map(|utxo| utxo.value).sum();
+
+        if total_value < fee {
+            return Err(Error::InsufficientFunds);
+        }
+
+        log::trace!(
+            logger,
+            "Total transaction value excluding fees: {}",
+            total_value - fee
+        );
+
+        // The inputs with corresponding proofs of membership.
+        let inputs_with_proofs: Vec<(UnspentTxOut, TxOutMembershipProof)> = {
+            let tx_outs: Vec<TxOut> = inputs.iter().map(|utxo| utxo.tx_out.clone()).collect();
+            let proofs = self.get_membership_proofs(&tx_outs)?;
+            inputs.iter().cloned().zip(proofs.into_iter()).collect()
+        };
+        log::trace!(logger, "Got membership proofs");
+
+        // The index of each input in the ledger.
+        let input_indices: Vec<u64> = inputs_with_proofs
+            .iter()
+            .map(|(_, membership_proof)| membership_proof.index)
+            .collect();
+
+        let rings = self.get_rings(DEFAULT_RING_SIZE, inputs_with_proofs.len(), &input_indices)?;
+        log::trace!(logger, "Got {} rings", rings.len());
+
+        // Come up with tombstone block.
+        let tombstone_block = self.ledger_db.num_blocks()? + DEFAULT_NEW_TX_BLOCK_ATTEMPTS;
+        log::trace!(logger, "Tombstone block set to {}", tombstone_block);
+
+        // The entire value goes to receiver
+        let outlays = vec![Outlay {
+            receiver: receiver.clone(),
+            value: total_value - fee,
+        }];
+
+        // Build and return the TxProposal object
+        let mut rng = rand::thread_rng();
+        let tx_proposal = Self::build_tx_proposal(
+            &inputs_with_proofs,
+            rings,
+            block_version,
+            token_id,
+            fee,
+            account_key,
+            0,
+            &outlays,
+            tombstone_block,
+            &self.fog_resolver_factory,
+            None,
+            &mut rng,
+            &self.logger,
+        )?;
+        log::trace!(
+            logger,
+            "Tx list tx constructed, hash={}",
+            tx_proposal.tx.tx_hash()
+        );
+
+        Ok(tx_proposal)
+    }
+
+    /// Submit a previously built tx proposal to the network.
+    pub fn submit_tx_proposal(&self, tx_proposal: &TxProposal) -> Result<u64, Error> {
+        // Pick a peer to submit to.
+        let responder_ids = self.peer_manager.responder_ids();
+        if responder_ids.is_empty() {
+            return Err(Error::NoPeersConfigured);
+        }
+
+        let idx = self.submit_node_offset.fetch_add(1, Ordering::SeqCst);
+        let responder_id = &responder_ids[idx % responder_ids.len()];
+
+        // Try and submit.
+        let block_height = self
+            .peer_manager
+            .conn(responder_id)
+            .ok_or(Error::NodeNotFound)?
+            .propose_tx(&tx_proposal.tx, empty())
+            .map_err(Error::from)?;
+
+        log::info!(
+            self.logger,
+            "Tx {} submitted at block height {}",
+            tx_proposal.tx,
+            block_height
+        );
+
+        // Successfully submitted.
+        Ok(block_height)
+    }
+
+    /// Create a signed contingent input (MCIP #31), offering a single
+    /// UnspentTxOut to whichever counterparty builds a transaction that
+    /// creates the outputs it requires.
+    ///
+    /// # Arguments
+    /// * `sender_monitor_id` - Monitor that owns the input.
+    /// * `change_subaddress` - Recipient of any change.
+    /// * `input` - The UnspentTxOut being offered.
+    /// * `offered_value` - How much of the input's value is offered. Whatever
+    ///   is not offered is returned to us in a required change output. If zero,
+    ///   the entire value is offered.
+    /// * `required_outlays` - Outputs which must be created by any transaction
+    ///   that spends the input.
+    /// * `partial_fill_outlays` - Outputs which must be created in proportion
+    ///   to how much of the offered value is taken. If non-empty, the offer may
+    ///   be partially filled.
+    /// * `min_partial_fill_value` - The least amount of the offered value a
+    ///   counterparty may take when partially filling.
+    /// * `last_block_infos` - Last block info responses from the network, for
+    ///   determining the block version.
+    /// * `opt_tombstone` - Tombstone block. If zero, sets to default.
+    pub fn build_signed_contingent_input(
+        &self,
+        sender_monitor_id: &MonitorId,
+        change_subaddress: u64,
+        input: &UnspentTxOut,
+        offered_value: u64,
+        required_outlays: &[OutlayV2],
+        partial_fill_outlays: &[OutlayV2],
+        min_partial_fill_value: u64,
+        last_block_infos: &[BlockInfo],
+        opt_tombstone: u64,
+    ) -> Result<SignedContingentInput, Error> {
+        let logger = self.logger.new(o!("sender_monitor_id" => sender_monitor_id.to_string(), "input" => format!("{:?}", input.key_image)));
+        log::trace!(logger, "Building signed contingent input...");
+
+        // Something must be asked for in return.
+        if required_outlays.is_empty() && partial_fill_outlays.is_empty() {
+            return Err(Error::InvalidArgument(
+                "required_outlays".to_string(),
+                "Must require at least one output".to_string(),
+            ));
+        }
+        if partial_fill_outlays.is_empty() && min_partial_fill_value != 0 {
+            return Err(Error::InvalidArgument(
+                "min_partial_fill_value".to_string(),
+                "Only allowed when there are partial fill outlays".to_string(),
+            ));
+        }
+
+        // Get sender monitor data. View-only monitors cannot spend.
+        let sender_monitor_data = self.mobilecoind_db.get_monitor_data(sender_monitor_id)?;
+        let sender_account_key = sender_monitor_data.spendable_account_key()?;
+
+        // Figure out how much of the input is offered, and how much is change.
+        let token_id = TokenId::from(input.token_id);
+        let offered_value = if offered_value == 0 {
+            input.value
+        } else {
+            offered_value
+        };
+        if offered_value > input.value {
+            return Err(Error::InsufficientFunds);
+        }
+        let change_value = input.value - offered_value;
+
+        // The counterparty pays the fee, so all we need from the network is the
+        // block version.
+        let (_fee, block_version) =
+            self.get_network_fee_and_block_version(token_id, 0, last_block_infos)?;
+        let block_version =
+            BlockVersion::try_from(block_version).map_err(|err| Error::TxBuild(err.to_string()))?;
+
+        // The input with its proof of membership, and a ring of mixins.
+        let proof = self
+            .get_membership_proofs(core::slice::from_ref(&input.tx_out))?
+            .pop()
+            .ok_or_else(|| Error::TxBuild("missing membership proof".to_string()))?;
+        let (ring, membership_proofs): (Vec<TxOut>, Vec<TxOutMembershipProof>) = self
+            .get_rings(DEFAULT_RING_SIZE, 1, &[proof.index])?
+            .pop()
+            .ok_or_else(|| Error::TxBuild("missing ring".to_string()))?
+            .into_iter()
+            .unzip();
+        let input_credentials = Self::get_input_credentials(
+            input,
+            &proof,
+            ring,
+            membership_proofs,
+            sender_account_key,
+            &logger,
+        )?;
+
+        let change_address = sender_account_key.subaddress(change_subaddress);
+        let fog_resolver = Self::get_fog_resolver(
+            &self.fog_resolver_factory,
+            core::slice::from_ref(&change_address).iter().chain(
+                required_outlays
+                    .iter()
+                    .chain(partial_fill_outlays.iter())
+                    .map(|outlay| &outlay.receiver),
+            ),
+        )?;
+
+        // TODO (GH #1522): Use RTH memo builder, optionally?
+        let mut sci_builder = SignedContingentInputBuilder::new(
+            block_version,
+            input_credentials,
+            fog_resolver,
+            EmptyMemoBuilder::default(),
+        )
+        .map_err(|err| {
+            Error::TxBuild(format!(
+                "Error creating signed contingent input builder: {}",
+                err
+            ))
+        })?;
+
+        let mut rng = rand::thread_rng();
+        for outlay in required_outlays {
+            sci_builder
+                .add_required_output(outlay.amount, &outlay.receiver, &mut rng)
+                .map_err(|err| Error::TxBuild(format!("failed adding required output: {}", err)))?;
+        }
+
+        let change_dest = ReservedSubaddresses::from_subaddress_index(
+            sender_account_key,
+            Some(change_subaddress),
+            None,
+        );
+
+        if !partial_fill_outlays.is_empty() {
+            for outlay in partial_fill_outlays {
+                sci_builder
+                    .add_partial_fill_output(outlay.amount, &outlay.receiver, &mut rng)
+                    .map_err(|err| {
+                        Error::TxBuild(format!("failed adding partial fill output: {}", err))
+                    })?;
+            }
+
+            // Whatever part of the offered value is not taken comes back to us.
+            sci_builder
+                .add_partial_fill_change_output(
+                    Amount::new(offered_value, token_id),
+                    &change_dest,
+                    &mut rng,
+                )
+                .map_err(|err| {
+                    Error::TxBuild(format!("failed adding partial fill change: {}", err))
+                })?;
+            sci_builder.set_min_partial_fill_value(min_partial_fill_value);
+        }
+
+        if change_value > 0 {
+            sci_builder
+                .add_required_change_output(
+                    Amount::new(change_value, token_id),
+                    &change_dest,
+                    &mut rng,
+                )
+                .map_err(|err| Error::TxBuild(format!("failed adding output (change): {}", err)))?;
+        }
+
+        // Come up with tombstone block.
+        let tombstone_block = if opt_tombstone > 0 {
+            opt_tombstone
+        } else {
+            self.ledger_db.num_blocks()? + DEFAULT_NEW_TX_BLOCK_ATTEMPTS
+        };
+        sci_builder.set_tombstone_block(tombstone_block);
+
+        let sci = sci_builder
+            .build(&NoKeysRingSigner {}, &mut rng)
+            .map_err(|err| {
+                Error::TxBuild(format!("build signed contingent input failed: {}", err))
+            })?;
+        log::trace!(logger, "Signed contingent input constructed");
+
+        Ok(sci)
+    }
+
+    /// Prepare a signed contingent input received from a counterparty for
+    /// inclusion in a transaction: validate it, make sure it has not been
+    /// spent, and attach proofs of membership for its ring from our ledger.
+    pub fn prepare_signed_contingent_input(
+        &self,
+        sci: &mut SignedContingentInput,
+    ) -> Result<(), Error> {
+        sci.validate()?;
+
+        if self.ledger_db.contains_key_image(&sci.key_image())? {
+            return Err(Error::SignedContingentInputSpent);
+        }
+
+        // The ring members must be the TxOuts at the claimed global indices.
+        for (i, (tx_out, index)) in sci
+            .tx_in
+            .ring
+            .iter()
+            .zip(sci.tx_out_global_indices.iter())
+            .enumerate()
+        {
+            if self.ledger_db.get_tx_out_by_index(*index)? != *tx_out {
+                return Err(Error::InvalidArgument(
+                    format!("sci.tx_out_global_indices[{}]", i),
+                    "Does not match the ring member".to_string(),
+                ));
+            }
+        }
+        sci.tx_in.proofs = self
+            .ledger_db
+            .get_tx_out_proof_of_memberships(&sci.tx_out_global_indices)?;
+
+        Ok(())
+    }
+
+    /// Create a TxProposal which fills a signed contingent input received
+    /// from a counterparty. Our own inputs pay for the outputs it requires and
+    /// the fee, and the value it offers is paid to us as change.
+    ///
+    /// # Arguments
+    /// * `sender_monitor_id` - Monitor whose funds are used to fill the signed
+    ///   contingent input.
+    /// * `change_subaddress` - Recipient of the offered value and any change.
+    /// * `sci` - The signed contingent input being filled.
+    /// * `fill_value` - For a partial fill signed contingent input, how much of
+    ///   its partial fill change value we are taking. Must be zero otherwise.
+    /// * `inputs` - UTXOs that may be spent to fill the signed contingent
+    ///   input.
+    /// * `fee_token_id` - The token id to pay the fee in.
+    /// * `last_block_infos` - Last block info responses from the network, for
+    ///   determining fees. This should normally come from polling_network_state
+    /// * `opt_fee` - Transaction fee. If zero, we will attempt to query the
+    ///   network for fee information.
+    /// * `opt_tombstone` - Tombstone block. If zero, sets to default.
+    pub fn fill_signed_contingent_input(
+        &self,
+        sender_monitor_id: &MonitorId,
+        change_subaddress: u64,
+        mut sci: SignedContingentInput,
+        fill_value: u64,
+        inputs: &[UnspentTxOut],
+        fee_token_id: TokenId,
+        last_block_infos: &[BlockInfo],
+        opt_fee: u64,
+        opt_tombstone: u64,
+    ) -> Result<TxProposal, Error> {
+        let logger = self.logger.new(o!("sender_monitor_id" => sender_monitor_id.to_string(), "sci_key_image" => format!("{:?}", sci.key_image())));
+        log::trace!(logger, "Filling signed contingent input...");
+
+        // Get sender monitor data. View-only monitors cannot spend.
+        let sender_monitor_data = self.mobilecoind_db.get_monitor_data(sender_monitor_id)?;
+        let sender_account_key = sender_monitor_data.spendable_account_key()?;
+
+        self.prepare_signed_contingent_input(&mut sci)?;
+
+        // Figure out the block_version and fee (involves network round-trips to
+        // consensus, unless opt_fee is non-zero)
+        let (fee, block_version) =
+            self.get_network_fee_and_block_version(fee_token_id, opt_fee, last_block_infos)?;
+        let block_version =
+            BlockVersion::try_from(block_version).map_err(|err| Error::TxBuild(err.to_string()))?;
+
+        let change_address = sender_account_key.subaddress(change_subaddress);
+        let fog_resolver = Self::get_fog_resolver(
+            &self.fog_resolver_factory,
+            core::slice::from_ref(&change_address).iter(),
+        )?;
+
+        // TODO (GH #1522): Use RTH memo builder, optionally?
+        let mut tx_builder = TransactionBuilder::new(
+            block_version,
+            Amount::new(fee, fee_token_id),
+            fog_resolver,
+            EmptyMemoBuilder::default(),
+        )
+        .map_err(|err| Error::TxBuild(format!("Error creating transaction builder: {}", err)))?;
+
+        // Set the tombstone block before adding the signed contingent input, which
+        // may lower it further.
+        let tombstone_block = if opt_tombstone > 0 {
+            opt_tombstone
+        } else {
+            self.ledger_db.num_blocks()? + DEFAULT_NEW_TX_BLOCK_ATTEMPTS
+        };
+        tx_builder.set_tombstone_block(tombstone_block);
+
+        // Aggregate the value we have to provide, per token id. The partial fill
+        // case adds more outlays below.
+        let mut outlays: HashMap<TokenId, u64> = HashMap::default();
+        outlays.insert(fee_token_id, fee);
+        for amount in sci.required_output_amounts.iter() {
+            *outlays.entry(TokenId::from(amount.token_id)).or_default() += amount.value;
+        }
+
+        let sci_amount = Amount::from(&sci.pseudo_output_amount);
+        let sci_tx_out_global_indices = sci.tx_out_global_indices.clone();
+
+        let partial_fill_change = sci
+            .tx_in
+            .input_rules
+            .as_ref()
+            .and_then(|rules| rules.partial_fill_change.as_ref())
+            .map(|partial_fill_change| partial_fill_change.reveal_amount())
+            .transpose()
+            .map_err(SignedContingentInputError::from)?;
+        if let Some((partial_fill_change, _blinding)) = partial_fill_change {
+            if fill_value > partial_fill_change.value {
+                return Err(Error::InvalidArgument(
+                    "fill_value".to_string(),
+                    "Exceeds the partial fill change value".to_string(),
+                ));
+            }
+            let sci_change_amount = Amount::new(
+                partial_fill_change.value - fill_value,
+                partial_fill_change.token_id,
+            );
+
+            // The builder computes how much of each partial fill output we owe.
+            let fractional_amounts =
+                tx_builder.add_presigned_partial_fill_input(sci, sci_change_amount)?;
+            for amount in fractional_amounts {
+                *outlays.entry(amount.token_id).or_default() += amount.value;
+            }
+            *outlays.entry(sci_change_amount.token_id).or_default() += sci_change_amount.value;
+        } else {
+            if fill_value != 0 {
+                return Err(Error::InvalidArgument(
+                    "fill_value".to_string(),
+                    "Only allowed for partial fill signed contingent inputs".to_string(),
+                ));
+            }
+            tx_builder.add_presigned_input(sci)?;
+        }
+
+        // Whatever the signed contingent input offers beyond the outlays in its own
+        // token id is ours to keep.
+        let matching_outlay = outlays.remove(&sci_amount.token_id).unwrap_or(0);
+        if sci_amount.value < matching_outlay {
+            return Err(Error::InvalidArgument(
+                "sci".to_string(),
+                "Requires more than it offers in its own token id".to_string(),
+            ));
+        }
+
+        let change_dest = ReservedSubaddresses::from_subaddress_index(
+            sender_account_key,
+            Some(change_subaddress),
+            None,
+        );
+
+        let mut rng = rand::thread_rng();
+        let leftover = sci_amount.value - matching_outlay;
+        if leftover > 0 {
+            tx_builder
+                .add_change_output(
+                    Amount::new(leftover, sci_amount.token_id),
+                    &change_dest,
+                    &mut rng,
+                )
+                .map_err(|err| Error::TxBuild(format!("failed adding output (change): {}", err)))?;
+        }
+
+        // Contribute our own inputs to cover the remaining outlays. Sort to make
+        // input selection deterministic.
+        let mut outlays: Vec<(TokenId, u64)> = outlays.into_iter().collect();
+        outlays.sort();
+
+        let mut selected_utxos: Vec<UnspentTxOut> = Vec::new();
+        for (token_id, value) in outlays {
+            if value == 0 {
+                continue;
+            }
+
+            // The signed contingent input takes up one of the inputs.
+            let max_inputs = (MAX_INPUTS as usize)
+                .checked_sub(1 + selected_utxos.len())
+                .filter(|max_inputs| *max_inputs > 0)
+                .ok_or(Error::InsufficientFundsFragmentedUtxos)?;
+            let token_utxos = Self::select_utxos_for_value(
+                &SmallestFirstUtxoSelection,
+                token_id,
+                inputs,
+                value,
+                max_inputs,
+            )?;
+            let token_utxos_value: u64 = token_utxos.iter().map(|utxo| utxo.value).sum();
+
+            let outputs: Vec<TxOut> = token_utxos.iter().map(|utxo| utxo.tx_out.clone()).collect();
+            let proofs = self.get_membership_proofs(&outputs)?;
+
+            // Avoid using the ring members of the signed contingent input, or our own
+            // inputs, as mixins.
+            let excluded_tx_out_indices: Vec<u64> = sci_tx_out_global_indices
+                .iter()
+                .cloned()
+                .chain(proofs.iter().map(|proof| proof.index))
+                .collect();
+            let rings =
+                self.get_rings(DEFAULT_RING_SIZE, proofs.len(), &excluded_tx_out_indices)?;
+
+            for ((utxo, proof), ring) in token_utxos.iter().zip(proofs.iter()).zip(rings) {
+                let (ring, membership_proofs) = ring.into_iter().unzip();
+                tx_builder.add_input(Self::get_input_credentials(
+                    utxo,
+                    proof,
+                    ring,
+                    membership_proofs,
+                    sender_account_key,
+                    &logger,
+                )?);
+            }
+
+            if token_utxos_value > value {
+                tx_builder
+                    .add_change_output(
+                        Amount::new(token_utxos_value - value, token_id),
+                        &change_dest,
+                        &mut rng,
+                    )
+                    .map_err(|err| {
+                        Error::TxBuild(format!("failed adding output (change): {}", err))
+                    })?;
+            }
+
+            selected_utxos.extend(token_utxos);
+        }
+
+        let tx = tx_builder
+            .build(&NoKeysRingSigner {}, &mut rng)
+            .map_err(|err| Error::TxBuild(format!("build tx failed: {}", err)))?;
+        log::trace!(logger, "Tx constructed, hash={}", tx.tx_hash());
+
+        // The outputs required by the signed contingent input are owed to the
+        // counterparty, not to recipients we chose, so there are no outlays.
+        Ok(TxProposal {
+            utxos: selected_utxos,
+            outlays: Vec::new(),
+            tx,
+            outlay_index_to_tx_out_index: HashMap::default(),
+            outlay_confirmation_numbers: Vec::new(),
+        })
+    }
+
+    /// Create a TxProposal that funds a gift code (MCIP #32).
+    ///
+    /// The gift code TxOut is sent to the sender's reserved gift code
+    /// subaddress. A change output carrying the gift code funding memo is
+    /// always written, even if it is zero valued, so that the funding can be
+    /// recovered from the sender's transaction history.
+    ///
+    /// # Arguments
+    /// * `sender_monitor_id` - Indicates the the account key needed to spend
+    ///   the txo's.
+    /// * `token_id` - The token id to fund the gift code in.
+    /// * `change_subaddress` - Recipient of any change.
+    /// * `inputs` - UTXOs that will be spent by the transaction.
+    /// * `value` - Value of the gift code.
+    /// * `utxo_selection_strategy` - How to choose which of the inputs to
+    ///   spend.
+    /// * `last_block_infos` - Last block info responses from the network, for
+    ///   determining fees. This should normally come from polling_network_state
+    /// * `opt_fee` - Transaction fee. If zero, we will attempt to query the
+    ///   network for fee information.
+    /// * `opt_tombstone` - Tombstone block. If zero, sets to default.
+    /// * `note` - Note written into the funding memo.
+    pub fn build_gift_code_funding_transaction(
+        &self,
+        sender_monitor_id: &MonitorId,
+        token_id: TokenId,
+        change_subaddress: u64,
+        inputs: &[UnspentTxOut],
+        value: u64,
+        utxo_selection_strategy: &dyn UtxoSelectionStrategy,
+        last_block_infos: &[BlockInfo],
+        opt_fee: u64,
+        opt_tombstone: u64,
+        note: &str,
+    ) -> Result<TxProposal, Error> {
+        let logger = self.logger.new(
+            o!("sender_monitor_id" => sender_monitor_id.to_string(), "gift_code_value" => value),
+        );
+        log::trace!(logger, "Building gift code funding transaction...");
+
+        // All inputs must be of the correct token id.
+        if inputs.iter().any(|utxo| utxo.token_id != *token_id) {
+            return Err(Error::InvalidArgument(
+                "inputs".to_string(),
+                format!("All inputs must be of token_id {}", token_id),
+            ));
+        }
+
+        if value == 0 {
+            return Err(Error::InvalidArgument(
+                "value".to_string(),
+                "Gift code value must be non-zero".to_string(),
+            ));
+        }
+
+        let memo_builder = GiftCodeFundingMemoBuilder::new(note)
+            .map_err(|err| Error::InvalidArgument("note".to_string(), err.to_string()))?;
+
+        // Get sender monitor data. View-only monitors cannot spend.
+        let sender_monitor_data = self.mobilecoind_db.get_monitor_data(sender_monitor_id)?;
+        let sender_account_key = sender_monitor_data.spendable_account_key()?;
+
+        // Figure out the block_version and fee (involves network round-trips to
+        // consensus, unless opt_fee is non-zero)
+        let (fee, block_version) =
+            self.get_network_fee_and_block_version(token_id, opt_fee, last_block_infos)?;
+        let block_version =
+            BlockVersion::try_from(block_version).map_err(|err| Error::TxBuild(err.to_string()))?;
+
+        // Select the UTXOs to be used for this transaction.
+        let selected_utxos = Self::select_utxos_for_value(
+            utxo_selection_strategy,
+            token_id,
+            inputs,
+            value + fee,
+            MAX_INPUTS as usize,
+        )?;
+        let selected_value: u64 = selected_utxos.iter().map(|utxo| utxo.value).sum();
+
+        let outputs: Vec<TxOut> = selected_utxos
+            .iter()
+            .map(|utxo| utxo.tx_out.clone())
+            .collect();
+        let proofs = self.get_membership_proofs(&outputs)?;
+
+        let excluded_tx_out_indices: Vec<u64> = proofs.iter().map(|proof| proof.index).collect();
+        let rings = self.get_rings(DEFAULT_RING_SIZE, proofs.len(), &excluded_tx_out_indices)?;
+
+        // Both the gift code output and the change output use the primary address
+        // for their fog hint.
+        let reserved_subaddresses = ReservedSubaddresses::from_subaddress_index(
+            sender_account_key,
+            Some(change_subaddress),
+            None,
+        );
+        let fog_resolver = Self::get_fog_resolver(
+            &self.fog_resolver_factory,
+            core::slice::from_ref(&reserved_subaddresses.primary_address).iter(),
+        )?;
+
+        let mut tx_builder = TransactionBuilder::new(
+            block_version,
+            Amount::new(fee, token_id),
+            fog_resolver,
+            memo_builder,
+        )
+        .map_err(|err| Error::TxBuild(format!("Error creating transaction builder: {}", err)))?;
+
+        for ((utxo, proof), ring) in selected_utxos.iter().zip(proofs.iter()).zip(rings) {
+            let (ring, membership_proofs) = ring.into_iter().unzip();
+            tx_builder.add_input(Self::get_input_credentials(
+                utxo,
+                proof,
+                ring,
+                membership_proofs,
+                sender_account_key,
+                &logger,
+            )?);
+        }
+
+        let mut rng = rand::thread_rng();
+        let TxOutContext {
+            tx_out: gift_code_tx_out,
+            confirmation,
+            ..
+        } = tx_builder
+            .add_gift_code_output(
+                Amount::new(value, token_id),
+                &reserved_subaddresses,
+                &mut rng,
+            )
+            .map_err(|err| Error::TxBuild(format!("failed adding output (gift code): {}", err)))?;
+
+        // The funding memo goes on the change output, so it is written even if there
+        // is no change.
+        tx_builder
+            .add_change_output(
+                Amount::new(selected_value - value - fee, token_id),
+                &reserved_subaddresses,
+                &mut rng,
+            )
+            .map_err(|err| Error::TxBuild(format!("failed adding output (change): {}", err)))?;
+
+        let tombstone_block = if opt_tombstone > 0 {
+            opt_tombstone
+        } else {
+            self.ledger_db.num_blocks()? + DEFAULT_NEW_TX_BLOCK_ATTEMPTS
+        };
+        tx_builder.set_tombstone_block(tombstone_block);
+
+        let tx = tx_builder
+            .build(&NoKeysRingSigner {}, &mut rng)
+            .map_err(|err| Error::TxBuild(format!("build tx failed: {}", err)))?;
+        log::trace!(logger, "Tx constructed, hash={}", tx.tx_hash());
+
+        // The gift code output is our single outlay.
+        let tx_out_index = tx
+            .prefix
+            .outputs
+            .iter()
+            .position(|tx_out| *tx_out == gift_code_tx_out)
+            .ok_or_else(|| Error::TxBuild("gift code output missing from tx".into()))?;
+
+        Ok(TxProposal {
+            utxos: selected_utxos,
+            outlays: vec![Outlay {
+                value,
+                receiver: reserved_subaddresses.gift_code_subaddress,
+            }],
+            tx,
+            outlay_index_to_tx_out_index: HashMap::from_iter(vec![(0, tx_out_index)]),
+            outlay_confirmation_numbers: vec![confirmation],
+        })
+    }
+
+    /// Create the gift code for a funded gift code TxOut, once it has landed in
+    /// the ledger.
+    ///
+    /// # Arguments
+    /// * `sender_monitor_id` - Monitor that funded the gift code.
+    /// * `tx_out_public_key` - Public key of the gift code TxOut.
+    pub fn create_gift_code(
+        &self,
+        sender_monitor_id: &MonitorId,
+        tx_out_public_key: &CompressedRistrettoPublic,
+    ) -> Result<TxOutGiftCode, Error> {
+        let sender_monitor_data = self.mobilecoind_db.get_monitor_data(sender_monitor_id)?;
+        let sender_account_key = sender_monitor_data.spendable_account_key()?;
+
+        let global_index = self
+            .ledger_db
+            .get_tx_out_index_by_public_key(tx_out_public_key)?;
+        let tx_out = self.ledger_db.get_tx_out_by_index(global_index)?;
+        let tx_public_key = RistrettoPublic::try_from(&tx_out.public_key)?;
+
+        let onetime_private_key = recover_onetime_private_key(
+            &tx_public_key,
+            sender_account_key.view_private_key(),
+            &sender_account_key.gift_code_subaddress_spend_private(),
+        );
+        let shared_secret =
+            get_tx_out_shared_secret(sender_account_key.view_private_key(), &tx_public_key);
+        let gift_code = TxOutGiftCode::new(global_index, onetime_private_key, shared_secret);
+
+        // Make sure this TxOut really was sent to our gift code subaddress.
+        self.get_gift_code_tx_out(&gift_code)?;
+
+        Ok(gift_code)
+    }
+
+    /// Look up the TxOut a gift code refers to, and un-blind its amount.
+    ///
+    /// Fails with `Error::GiftCodeMismatch` if the gift code cannot spend the
+    /// TxOut at its global index.
+    pub fn get_gift_code_tx_out(
+        &self,
+        gift_code: &TxOutGiftCode,
+    ) -> Result<(TxOut, Amount), Error> {
+        let tx_out = self.ledger_db.get_tx_out_by_index(gift_code.global_index)?;
+
+        let onetime_public_key = RistrettoPublic::from(&gift_code.onetime_private_key);
+        if CompressedRistrettoPublic::from(&onetime_public_key) != tx_out.target_key {
+            return Err(Error::GiftCodeMismatch);
+        }
+
+        let amount = tx_out
+            .get_masked_amount()
+            .ok()
+            .and_then(|masked_amount| gift_code.unblind_amount(masked_amount).ok())
+            .ok_or(Error::GiftCodeMismatch)?;
+
+        Ok((tx_out, amount))
+    }
+
+    /// Create a TxProposal that claims a gift code, sending its value (minus
+    /// the fee) to one of our subaddresses with a gift code sender memo
+    /// attached.
+    ///
+    /// # Arguments
+    /// * `monitor_id` - Monitor receiving the gift code. This may be a
+    ///   view-only monitor.
+    /// * `subaddress_index` - Subaddress receiving the gift code.
+    /// * `gift_code` - The gift code being claimed.
+    /// * `last_block_infos` - Last block info responses from the network, for
+    ///   determining fees. This should normally come from polling_network_state
+    /// * `opt_fee` - Transaction fee. If zero, we will attempt to query the
+    ///   network for fee information.
+    /// * `opt_tombstone` - Tombstone block. If zero, sets to default.
+    /// * `note` - Note written into the sender memo.
+    pub fn build_gift_code_claim_transaction(
+        &self,
+        monitor_id: &MonitorId,
+        subaddress_index: u64,
+        gift_code: &TxOutGiftCode,
+        last_block_infos: &[BlockInfo],
+        opt_fee: u64,
+        opt_tombstone: u64,
+        note: &str,
+    ) -> Result<TxProposal, Error> {
+        let memo_builder = GiftCodeSenderMemoBuilder::new(note)
+            .map_err(|err| Error::InvalidArgument("note".to_string(), err.to_string()))?;
+
+        self.build_gift_code_spend_transaction(
+            monitor_id,
+            subaddress_index,
+            gift_code,
+            last_block_infos,
+            opt_fee,
+            opt_tombstone,
+            Box::new(memo_builder),
+        )
     }
 
-    /// Create a TxProposal that sends the total value of all inputs minus the
-    /// fee to a single receiver. (ignoring inputs with wrong token id)
+    /// Create a TxProposal that cancels a gift code we funded, returning its
+    /// value (minus the fee) to us with a gift code cancellation memo
+    /// attached.
     ///
     /// # Arguments
-    /// * `account_key` - Account key that owns the inputs.
-    /// * `token_id` - The token id to transact in.
-    /// * `inputs` - UTXOs that will be spent by the transaction.
-    /// * `receiver` - The single receiver of the transaction's outputs.
+    /// * `sender_monitor_id` - Monitor that funded the gift code.
+    /// * `change_subaddress` - Subaddress receiving the cancelled value.
+    /// * `gift_code` - The gift code being cancelled.
     /// * `last_block_infos` - Last block info responses from the network, for
     ///   determining fees. This should normally come from polling_network_state
-    /// * `opt_fee` - Transaction fee. If zero, defaults to the highest fee set
-    ///   by configured consensus nodes, or the hard-coded FALLBACK_FEE.
-    pub fn generate_tx_from_tx_list(
+    /// * `opt_fee` - Transaction fee. If zero, we will attempt to query the
+    ///   network for fee information.
+    /// * `opt_tombstone` - Tombstone block. If zero, sets to default.
+    pub fn build_gift_code_cancellation_transaction(
         &self,
-        account_key: &AccountKey,
-        token_id: TokenId,
-        inputs: &[UnspentTxOut],
-        receiver: &PublicAddress,
+        sender_monitor_id: &MonitorId,
+        change_subaddress: u64,
+        gift_code: &TxOutGiftCode,
         last_block_infos: &[BlockInfo],
         opt_fee: u64,
+        opt_tombstone: u64,
     ) -> Result<TxProposal, Error> {
-        let logger = self.logger.new(o!("receiver" => receiver.to_string()));
-        log::trace!(logger, "Generating txo list transaction...");
+        // Only the funder may cancel a gift code, i.e. it must have been sent to our
+        // gift code subaddress.
+        let (tx_out, _amount) = self.get_gift_code_tx_out(gift_code)?;
+        if self.create_gift_code(sender_monitor_id, &tx_out.public_key)? != *gift_code {
+            return Err(Error::GiftCodeMismatch);
+        }
 
-        // All inputs must be of the correct token id.
-        if inputs.iter().any(|utxo| utxo.token_id != *token_id) {
-            return Err(Error::InvalidArgument(
-                "inputs".to_string(),
-                format!("All inputs must be of token_id {}", token_id),
-            ));
+        let memo_builder = GiftCodeCancellationMemoBuilder::new(gift_code.global_index);
+
+        self.build_gift_code_spend_transaction(
+            sender_monitor_id,
+            change_subaddress,
+            gift_code,
+            last_block_infos,
+            opt_fee,
+            opt_tombstone,
+            Box::new(memo_builder),
+        )
+    }
+
+    /// Create a TxProposal that spends a gift code TxOut into a change output
+    /// at one of our subaddresses. Claiming and cancelling a gift code differ
+    /// only in the memo builder used.
+    fn build_gift_code_spend_transaction(
+        &self,
+        monitor_id: &MonitorId,
+        subaddress_index: u64,
+        gift_code: &TxOutGiftCode,
+        last_block_infos: &[BlockInfo],
+        opt_fee: u64,
+        opt_tombstone: u64,
+        memo_builder: Box<dyn MemoBuilder + Send + Sync>,
+    ) -> Result<TxProposal, Error> {
+        let logger = self.logger.new(o!("monitor_id" => monitor_id.to_string(), "gift_code_global_index" => gift_code.global_index));
+        log::trace!(logger, "Building gift code spend transaction...");
+
+        let monitor_data = self.mobilecoind_db.get_monitor_data(monitor_id)?;
+
+        let (tx_out, amount) = self.get_gift_code_tx_out(gift_code)?;
+
+        let key_image = KeyImage::from(&gift_code.onetime_private_key);
+        if self.ledger_db.contains_key_image(&key_image)? {
+            return Err(Error::GiftCodeSpent);
         }
 
-        // Figure out the block_version and fee (involves network round-trips to
-        // consensus, unless fee arg is non-zero)
+        // The fee is paid out of the gift code.
         let (fee, block_version) =
-            self.get_network_fee_and_block_version(token_id, opt_fee, last_block_infos)?;
-
-        // Make sure we understand this block version
+            self.get_network_fee_and_block_version(amount.token_id, opt_fee, last_block_infos)?;
         let block_version =
             BlockVersion::try_from(block_version).map_err(|err| Error::TxBuild(err.to_string()))?;
-
-        // All inputs are to be spent, except those with wrong token id
-        let total_value: u64 = inputs.iter().map(|utxo| utxo.value).sum();
-
-        if total_value < fee {
+        if amount.value <= fee {
             return Err(Error::InsufficientFunds);
         }
+        let output_value = amount.value - fee;
 
-        log::trace!(
-            logger,
-            "Total transaction value excluding fees: {}",
-            total_value - fee
-        );
-
-        // The inputs with corresponding proofs of membership.
-        let inputs_with_proofs: Vec<(UnspentTxOut, TxOutMembershipProof)> = {
-            let tx_outs: Vec<TxOut> = inputs.iter().map(|utxo| utxo.tx_out.clone()).collect();
-            let proofs = self.get_membership_proofs(&tx_outs)?;
-            inputs.iter().cloned().zip(proofs.into_iter()).collect()
-        };
-        log::trace!(logger, "Got membership proofs");
-
-        // The index of each input in the ledger.
-        let input_indices: Vec<u64> = inputs_with_proofs
+        // The gift code TxOut was not sent to us, so we cannot use
+        // get_input_credentials. Place it into a ring of mixins, which is sorted
+        // the same way InputCredentials::new sorts it.
+        let proof = self
+            .ledger_db
+            .get_tx_out_proof_of_memberships(&[gift_code.global_index])?
+            .pop()
+            .ok_or_else(|| Error::TxBuild("missing gift code membership proof".into()))?;
+        let mut ring_and_proofs = self
+            .get_rings(DEFAULT_RING_SIZE, 1, &[gift_code.global_index])?
+            .pop()
+            .ok_or_else(|| Error::TxBuild("missing gift code ring".into()))?;
+        ring_and_proofs[0] = (tx_out.clone(), proof);
+        ring_and_proofs
+            .sort_by(|(tx_out_a, _), (tx_out_b, _)| tx_out_a.public_key.cmp(&tx_out_b.public_key));
+        let real_index = ring_and_proofs
             .iter()
-            .map(|(_, membership_proof)| membership_proof.index)
-            .collect();
-
-        let rings = self.get_rings(DEFAULT_RING_SIZE, inputs_with_proofs.len(), &input_indices)?;
-        log::trace!(logger, "Got {} rings", rings.len());
-
-        // Come up with tombstone block.
-        let tombstone_block = self.ledger_db.num_blocks()? + DEFAULT_NEW_TX_BLOCK_ATTEMPTS;
-        log::trace!(logger, "Tombstone block set to {}", tombstone_block);
-
-        // The entire value goes to receiver
-        let outlays = vec![Outlay {
-            receiver: receiver.clone(),
-            value: total_value - fee,
-        }];
+            .position(|(ring_tx_out, _)| *ring_tx_out == tx_out)
+            .expect("Must still contain real input");
+        let (ring, membership_proofs) = ring_and_proofs.into_iter().unzip();
+
+        let (_, blinding) = tx_out
+            .get_masked_amount()
+            .map_err(|err| Error::TxBuild(err.to_string()))?
+            .get_value(&gift_code.shared_secret)
+            .map_err(|err| Error::TxBuild(err.to_string()))?;
+        let input_credentials = InputCredentials {
+            ring,
+            membership_proofs,
+            real_index,
+            input_secret: InputSecret {
+                onetime_key_derive_data: gift_code.onetime_private_key.clone().into(),
+                amount,
+                blinding,
+            },
+        };
 
-        // Build and return the TxProposal object
-        let mut rng = rand::thread_rng();
-        let tx_proposal = Self::build_tx_proposal(
-            &inputs_with_proofs,
-            rings,
-            block_version,
-            token_id,
-            fee,
-            account_key,
-            0,
-            &outlays,
-            tombstone_block,
+        // Only public addresses are needed here, which lets view-only monitors
+        // claim gift codes.
+        let reserved_subaddresses = ReservedSubaddresses {
+            primary_address: monitor_data.default_subaddress()?,
+            change_subaddress: monitor_data.subaddress(subaddress_index)?,
+            gift_code_subaddress: monitor_data.subaddress(GIFT_CODE_SUBADDRESS_INDEX)?,
+        };
+        let fog_resolver = Self::get_fog_resolver(
             &self.fog_resolver_factory,
-            None,
-            &mut rng,
-            &self.logger,
+            core::slice::from_ref(&reserved_subaddresses.primary_address).iter(),
         )?;
-        log::trace!(
-            logger,
-            "Tx list tx constructed, hash={}",
-            tx_proposal.tx.tx_hash()
-        );
 
-        Ok(tx_proposal)
-    }
+        let mut tx_builder = TransactionBuilder::new_with_box(
+            block_version,
+            Amount::new(fee, amount.token_id),
+            fog_resolver,
+            memo_builder,
+        )
+        .map_err(|err| Error::TxBuild(format!("Error creating transaction builder: {}", err)))?;
+        tx_builder.add_input(input_credentials);
 
-    /// Submit a previously built tx proposal to the network.
-    pub fn submit_tx_proposal(&self, tx_proposal: &TxProposal) -> Result<u64, Error> {
-        // Pick a peer to submit to.
-        let responder_ids = self.peer_manager.responder_ids();
-        if responder_ids.is_empty() {
-            return Err(Error::NoPeersConfigured);
-        }
+        let mut rng = rand::thread_rng();
+        let TxOutContext {
+            tx_out: change_tx_out,
+            confirmation,
+            ..
+        } = tx_builder
+            .add_change_output(
+                Amount::new(output_value, amount.token_id),
+                &reserved_subaddresses,
+                &mut rng,
+            )
+            .map_err(|err| Error::TxBuild(format!("failed adding output (change): {}", err)))?;
 
-        let idx = self.submit_node_offset.fetch_add(1, Ordering::SeqCst);
-        let responder_id = &responder_ids[idx % responder_ids.len()];
+        let tombstone_block = if opt_tombstone > 0 {
+            opt_tombstone
+        } else {
+            self.ledger_db.num_blocks()? + DEFAULT_NEW_TX_BLOCK_ATTEMPTS
+        };
+        tx_builder.set_tombstone_block(tombstone_block);
 
-        // Try and submit.
-        let block_height = self
-            .peer_manager
-            .conn(responder_id)
-            .ok_or(Error::NodeNotFound)?
-            .propose_tx(&tx_proposal.tx, empty())
-            .map_err(Error::from)?;
+        let tx = tx_builder
+            .build(&NoKeysRingSigner {}, &mut rng)
+            .map_err(|err| Error::TxBuild(format!("build tx failed: {}", err)))?;
+        log::trace!(logger, "Tx constructed, hash={}", tx.tx_hash());
 
-        log::info!(
-            self.logger,
-            "Tx {} submitted at block height {}",
-            tx_proposal.tx,
-            block_height
-        );
+        let tx_out_index = tx
+            .prefix
+            .outputs
+            .iter()
+            .position(|tx_out| *tx_out == change_tx_out)
+            .ok_or_else(|| Error::TxBuild("change output missing from tx".into()))?;
+
+        // The gift code TxOut is not in our UTXO store, but including it lets the
+        // sender receipt track its key image.
+        let utxo = UnspentTxOut {
+            tx_out,
+            subaddress_index: GIFT_CODE_SUBADDRESS_INDEX,
+            key_image,
+            value: amount.value,
+            attempted_spend_height: 0,
+            attempted_spend_tombstone: 0,
+            token_id: *amount.token_id,
+            key_image_unknown: false,
+        };
 
-        // Successfully submitted.
-        Ok(block_height)
+        Ok(TxProposal {
+            utxos: vec![utxo],
+            outlays: vec![Outlay {
+                value: output_value,
+                receiver: reserved_subaddresses.change_subaddress,
+            }],
+            tx,
+            outlay_index_to_tx_out_index: HashMap::from_iter(vec![(0, tx_out_index)]),
+            outlay_confirmation_numbers: vec![confirmation],
+        })
     }
 
-    /// Returns a subset of UTXOs totalling at least the given amount.
+    /// Returns a subset of UTXOs totalling at least the given amount, chosen by
+    /// the given strategy.
     // TODO: This method should take attempted_spend_height into account.
     fn select_utxos_for_value(
+        utxo_selection_strategy: &dyn UtxoSelectionStrategy,
         token_id: TokenId,
         utxos: &[UnspentTxOut],
         value: u64,
         max_inputs: usize,
     ) -> Result<Vec<UnspentTxOut>, Error> {
-        // Sort the utxos in descending order by value.
-        let mut sorted_utxos: Vec<UnspentTxOut> = utxos
+        let mut token_utxos: Vec<UnspentTxOut> = utxos
             .iter()
             .filter(|utxo| utxo.token_id == token_id)
             .cloned()
             .collect();
-        sorted_utxos.sort_by_key(|utxo| Reverse(utxo.value));
+        token_utxos.sort_by_key(|utxo| Reverse(utxo.value));
 
         // The maximum spendable is limited by the maximal number of inputs we can use.
-        let max_spendable_amount = sorted_utxos
+        let max_spendable_amount = token_utxos
             .iter()
             .take(max_inputs)
             .map(|utxo| utxo.value)
             .sum();
         if value > max_spendable_amount {
             // See if we merged the UTXOs we would be able to spend this amount.
-            let total_utxos_value: u64 = sorted_utxos.iter().map(|utxo| utxo.value).sum();
+            let total_utxos_value: u64 = token_utxos.iter().map(|utxo| utxo.value).sum();
             if total_utxos_value >= value {
                 return Err(Error::InsufficientFundsFragmentedUtxos);
             } else {
@@ -632,27 +1849,21 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         }
 
         // Choose utxos to spend.
-        let mut selected_utxos: Vec<UnspentTxOut> = Vec::new();
-        loop {
-            let total: u64 = selected_utxos.iter().map(|utxo| utxo.value).sum();
-            if total >= value {
-                break;
-            }
-
-            // Grab the next (smallest utxo)
-            let next_utxo = sorted_utxos.pop().ok_or(Error::InsufficientFunds)?;
-            selected_utxos.push(next_utxo.clone());
-
-            // Cap at maximum allowed inputs.
-            if selected_utxos.len() > max_inputs {
-                // Remove the lowest utxo.
-                selected_utxos.remove(0);
-            }
-        }
+        let selected_utxos =
+            utxo_selection_strategy.select_utxos(&token_utxos, value, max_inputs)?;
 
         // Sanity.
-        assert!(!selected_utxos.is_empty());
-        assert!(selected_utxos.len() <= max_inputs);
+        let selected_value: u64 = selected_utxos.iter().map(|utxo| utxo.value).sum();
+        if selected_utxos.is_empty() || selected_utxos.len() > max_inputs || selected_value < value
+        {
+            return Err(Error::TxBuild(format!(
+                "UTXO selection strategy chose {} utxos totalling {}, expected at most {} utxos totalling at least {}",
+                selected_utxos.len(),
+                selected_value,
+                max_inputs,
+                value
+            )));
+        }
 
         // Return selected utxos.
         Ok(selected_utxos)
@@ -767,6 +1978,8 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
     ) -> Result<Vec<Vec<(TxOut, TxOutMembershipProof)>>, Error> {
         let num_requested = ring_size * num_rings;
         let num_txos = self.ledger_db.num_txos()?;
+        // TxOuts that have been pruned from the ledger can not be used as mixins.
+        let num_available_txos = num_txos - self.ledger_db.num_pruned_tx_outs()?;
 
         // Check that the ledger contains enough tx outs.
         if excluded_tx_out_indices.len() as u64 > num_txos {
@@ -776,30 +1989,37 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             ));
         }
 
-        if num_requested > (num_txos as usize - excluded_tx_out_indices.len()) {
+        if num_requested
+            > (num_available_txos as usize).saturating_sub(excluded_tx_out_indices.len())
+        {
             return Err(Error::InsufficientTxOuts);
         }
 
-        // Randomly sample `num_requested` indices of TxOuts to use as mixins.
-        let mixin_indices: Vec<u64> = {
+        // Randomly sample `num_requested` TxOuts to use as mixins. Indices of
+        // pruned TxOuts are rejected, and another index is sampled instead.
+        let (mixin_indices, mixins): (Vec<u64>, Vec<TxOut>) = {
             let mut rng = rand::thread_rng();
-            let mut samples: HashSet<u64> = HashSet::default();
+            let mut sampled_indices: HashSet<u64> = HashSet::default();
+            let mut samples = Vec::with_capacity(num_requested);
             while samples.len() < num_requested {
+                if sampled_indices.len() as u64 >= num_txos {
+                    // Every index has been tried, so there are fewer available TxOuts
+                    // than expected.
+                    return Err(Error::InsufficientTxOuts);
+                }
                 let index = rng.gen_range(0..num_txos);
-                if excluded_tx_out_indices.contains(&index) {
+                if !sampled_indices.insert(index) || excluded_tx_out_indices.contains(&index) {
                     continue;
                 }
-                samples.insert(index);
+                match self.ledger_db.get_tx_out_by_index(index) {
+                    Ok(tx_out) => samples.push((index, tx_out)),
+                    Err(LedgerError::TxOutPruned(_)) => {}
+                    Err(err) => return Err(err.into()),
+                }
             }
-            samples.into_iter().collect()
+            samples.into_iter().unzip()
         };
 
-        let mixins_result: Result<Vec<TxOut>, _> = mixin_indices
-            .iter()
-            .map(|&index| self.ledger_db.get_tx_out_by_index(index))
-            .collect();
-        let mixins: Vec<TxOut> = mixins_result?;
-
         let membership_proofs = self
             .ledger_db
             .get_tx_out_proof_of_memberships(&mixin_indices)?;
@@ -818,6 +2038,100 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         Ok(result)
     }
 
+    /// Construct the credentials needed to spend an UnspentTxOut, placing it
+    /// in the given ring of mixins if it is not already a member.
+    ///
+    /// # Arguments
+    /// * `utxo` - The UnspentTxOut being spent.
+    /// * `proof` - Membership proof of the UnspentTxOut.
+    /// * `ring` - Mixins for the ring.
+    /// * `membership_proofs` - Membership proofs of the mixins.
+    /// * `from_account_key` - Owns the UnspentTxOut.
+    /// * `logger` - Logger
+    fn get_input_credentials(
+        utxo: &UnspentTxOut,
+        proof: &TxOutMembershipProof,
+        mut ring: Vec<TxOut>,
+        mut membership_proofs: Vec<TxOutMembershipProof>,
+        from_account_key: &AccountKey,
+        logger: &Logger,
+    ) -> Result<InputCredentials, Error> {
+        assert_eq!(
+            ring.len(),
+            membership_proofs.len(),
+            "Each ring element must have a corresponding membership proof."
+        );
+
+        // Add the input to the ring.
+        let position_opt = ring.iter().position(|tx_out| *tx_out == utxo.tx_out);
+        let real_key_index = match position_opt {
+            Some(position) => {
+                // The input is already present in the ring.
+                // This could happen if ring elements are sampled randomly from the ledger.
+                position
+            }
+            None => {
+                // The input is not already in the ring.
+                if ring.is_empty() {
+                    // Append the input and its proof of membership.
+                    ring.push(utxo.tx_out.clone());
+                    membership_proofs.push(proof.clone());
+                } else {
+                    // Replace the first element of the ring.
+                    ring[0] = utxo.tx_out.clone();
+                    membership_proofs[0] = proof.clone();
+                }
+                // The real input is always the first element. This is safe because
+                // TransactionBuilder sorts each ring.
+                0
+            }
+        };
+
+        assert_eq!(
+            ring.len(),
+            membership_proofs.len(),
+            "Each ring element must have a corresponding membership proof."
+        );
+
+        let public_key = RistrettoPublic::try_from(&utxo.tx_out.public_key).unwrap();
+        let onetime_private_key = recover_onetime_private_key(
+            &public_key,
+            from_account_key.view_private_key(),
+            &from_account_key.subaddress_spend_private(utxo.subaddress_index),
+        );
+
+        let key_image = KeyImage::from(&onetime_private_key);
+        log::debug!(
+            logger,
+            "Adding input: ring {:?}, utxo index {:?}, key image {:?}, pubkey {:?}",
+            ring,
+            real_key_index,
+            key_image,
+            public_key
+        );
+
+        InputCredentials::new(
+            ring,
+            membership_proofs,
+            real_key_index,
+            onetime_private_key,
+            *from_account_key.view_private_key(),
+        )
+        .map_err(|_| Error::TxBuild("failed creating InputCredentials".into()))
+    }
+
+    /// Construct a fog resolver able to resolve fog reports for each of the
+    /// given addresses.
+    fn get_fog_resolver<'a>(
+        fog_resolver_factory: &Arc<dyn Fn(&[FogUri]) -> Result<FPR, String> + Send + Sync>,
+        addresses: impl Iterator<Item = &'a PublicAddress>,
+    ) -> Result<FPR, Error> {
+        let fog_uris = addresses
+            .filter_map(|x| extract_fog_uri(x).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        fog_resolver_factory(&fog_uris).map_err(Error::Fog)
+    }
+
     /// Create a TxProposal.
     ///
     /// # Arguments
@@ -871,12 +2185,12 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         // factory
         let fog_resolver = {
             let change_address = from_account_key.subaddress(change_subaddress);
-            let fog_uris = core::slice::from_ref(&change_address)
-                .iter()
-                .chain(destinations.iter().map(|x| &x.receiver))
-                .filter_map(|x| extract_fog_uri(x).transpose())
-                .collect::<Result<Vec<_>, _>>()?;
-            fog_resolver_factory(&fog_uris).map_err(Error::Fog)?
+            Self::get_fog_resolver(
+                fog_resolver_factory,
+                core::slice::from_ref(&change_address)
+                    .iter()
+                    .chain(destinations.iter().map(|x| &x.receiver)),
+            )?
         };
 
         // Create tx_builder.
@@ -899,73 +2213,18 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
 
         // Add inputs to the tx.
         for (utxo, proof) in inputs {
-            let (mut ring, mut membership_proofs) = rings_and_proofs
+            let (ring, membership_proofs) = rings_and_proofs
                 .pop()
                 .ok_or_else(|| Error::TxBuild("rings_and_proofs was empty".to_string()))?;
-            assert_eq!(
-                ring.len(),
-                membership_proofs.len(),
-                "Each ring element must have a corresponding membership proof."
-            );
-
-            // Add the input to the ring.
-            let position_opt = ring.iter().position(|tx_out| *tx_out == utxo.tx_out);
-            let real_key_index = match position_opt {
-                Some(position) => {
-                    // The input is already present in the ring.
-                    // This could happen if ring elements are sampled randomly from the ledger.
-                    position
-                }
-                None => {
-                    // The input is not already in the ring.
-                    if ring.is_empty() {
-                        // Append the input and its proof of membership.
-                        ring.push(utxo.tx_out.clone());
-                        membership_proofs.push(proof.clone());
-                    } else {
-                        // Replace the first element of the ring.
-                        ring[0] = utxo.tx_out.clone();
-                        membership_proofs[0] = proof.clone();
-                    }
-                    // The real input is always the first element. This is safe because
-                    // TransactionBuilder sorts each ring.
-                    0
-                }
-            };
-
-            assert_eq!(
-                ring.len(),
-                membership_proofs.len(),
-                "Each ring element must have a corresponding membership proof."
-            );
-
-            let public_key = RistrettoPublic::try_from(&utxo.tx_out.public_key).unwrap();
-            let onetime_private_key = recover_onetime_private_key(
-                &public_key,
-                from_account_key.view_private_key(),
-                &from_account_key.subaddress_spend_private(utxo.subaddress_index),
-            );
 
-            let key_image = KeyImage::from(&onetime_private_key);
-            log::debug!(
-                logger,
-                "Adding input: ring {:?}, utxo index {:?}, key image {:?}, pubkey {:?}",
+            tx_builder.add_input(Self::get_input_credentials(
+                utxo,
+                proof,
                 ring,
-                real_key_index,
-                key_image,
-                public_key
-            );
-
-            tx_builder.add_input(
-                InputCredentials::new(
-                    ring,
-                    membership_proofs,
-                    real_key_index,
-                    onetime_private_key,
-                    *from_account_key.view_private_key(),
-                )
-                .map_err(|_| Error::TxBuild("failed creating InputCredentials".into()))?,
-            );
+                membership_proofs,
+                from_account_key,
+                logger,
+            )?);
         }
 
         // Add outputs to our destinations.
@@ -1121,6 +2380,7 @@ mod test {
                 attempted_spend_height: 0,
                 attempted_spend_tombstone: 0,
                 token_id: *token_id,
+                key_image_unknown: false,
             })
             .collect()
     }
@@ -1139,7 +2399,13 @@ mod test {
         let selected_utxos = TransactionsManager::<
             ThickClient<HardcodedCredentialsProvider>,
             MockFogPubkeyResolver,
-        >::select_utxos_for_value(Mob::ID, &utxos, 300, utxos.len())
+        >::select_utxos_for_value(
+            &SmallestFirstUtxoSelection,
+            Mob::ID,
+            &utxos,
+            300,
+            utxos.len(),
+        )
         .unwrap();
 
         assert_eq!(selected_utxos, vec![utxos[0].clone(), utxos[1].clone()]);
@@ -1148,7 +2414,13 @@ mod test {
         let selected_utxos = TransactionsManager::<
             ThickClient<HardcodedCredentialsProvider>,
             MockFogPubkeyResolver,
-        >::select_utxos_for_value(Mob::ID, &utxos, 301, utxos.len())
+        >::select_utxos_for_value(
+            &SmallestFirstUtxoSelection,
+            Mob::ID,
+            &utxos,
+            301,
+            utxos.len(),
+        )
         .unwrap();
 
         assert_eq!(
@@ -1160,7 +2432,9 @@ mod test {
         let selected_utxos = TransactionsManager::<
             ThickClient<HardcodedCredentialsProvider>,
             MockFogPubkeyResolver,
-        >::select_utxos_for_value(Mob::ID, &utxos, 301, 2)
+        >::select_utxos_for_value(
+            &SmallestFirstUtxoSelection, Mob::ID, &utxos, 301, 2
+        )
         .unwrap();
 
         assert_eq!(selected_utxos, vec![utxos[1].clone(), utxos[2].clone()]);
@@ -1172,7 +2446,7 @@ mod test {
         // While we have enough utxos to sum to 5, if the input limit is 4 we should
         // fail.
         match TransactionsManager::<ThickClient<HardcodedCredentialsProvider>, MockFogPubkeyResolver>::select_utxos_for_value(
-            Mob::ID, &utxos, 5, 4,
+            &SmallestFirstUtxoSelection, Mob::ID, &utxos, 5, 4,
         ) {
             Err(Error::InsufficientFundsFragmentedUtxos) => {
                 // Expected.
@@ -1187,7 +2461,7 @@ mod test {
         // While we have enough utxos to sum to 5, if the input limit is 4 we should
         // fail.
         match TransactionsManager::<ThickClient<HardcodedCredentialsProvider>, MockFogPubkeyResolver>::select_utxos_for_value(
-            Mob::ID, &utxos, 50, 100,
+            &SmallestFirstUtxoSelection, Mob::ID, &utxos, 50, 100,
         ) {
             Err(Error::InsufficientFunds) => {
                 // Expected.
@@ -1359,4 +2633,109 @@ mod test {
         );
         assert!(result.is_err());
     }
+    // The optimization transactions planned by `plan_optimization` should be the
+    // ones that repeatedly selecting inputs like `generate_optimization_tx` does
+    // produces, also when some UTXOs are worth less than the fee.
+    #[test]
+    fn test_plan_optimization_with_dust_matches_optimization_txs() {
+        type Manager =
+            TransactionsManager<ThickClient<HardcodedCredentialsProvider>, MockFogPubkeyResolver>;
+
+        let fee = Mob::MINIMUM_FEE;
+        let max_inputs = 3;
+        let values = [
+            100 * fee,
+            20 * fee / 10,
+            21 * fee / 10,
+            22 * fee / 10,
+            23 * fee / 10,
+            fee / 10,
+            2 * fee / 10,
+            3 * fee / 10,
+            4 * fee / 10,
+        ];
+        let mut utxos = generate_utxos(values.len());
+        for (index, (utxo, value)) in utxos.iter_mut().zip(values).enumerate() {
+            utxo.value = value;
+            utxo.key_image = KeyImage::from(index as u64);
+        }
+        // Merging the two smallest dust UTXOs loses money, so the optimization
+        // transaction merges the biggest dust UTXO and the smallest other one into the
+        // biggest UTXO instead.
+        let value = 105 * fee + 2 * fee / 10;
+
+        // Send optimization transactions until the payment can be made.
+        let mut remaining_utxos = utxos.clone();
+        let mut spent_utxos = Vec::new();
+        let mut num_optimization_txs = 0;
+        let payment_utxos = loop {
+            match Manager::select_utxos_for_value(
+                &SmallestFirstUtxoSelection,
+                Mob::ID,
+                &remaining_utxos,
+                value,
+                max_inputs,
+            ) {
+                Ok(payment_utxos) => break payment_utxos,
+                Err(Error::InsufficientFundsFragmentedUtxos) => {}
+                Err(err) => panic!("Unexpected error: {:?}", err),
+            }
+
+            let selected_utxos = Manager::select_utxos_for_optimization(
+                1000,
+                &remaining_utxos,
+                max_inputs,
+                Mob::ID,
+                fee,
+            )
+            .unwrap();
+            remaining_utxos.retain(|utxo| !selected_utxos.contains(utxo));
+            remaining_utxos.push(UnspentTxOut {
+                value: selected_utxos.iter().map(|utxo| utxo.value).sum::<u64>() - fee,
+                key_image: KeyImage::from(1000 + num_optimization_txs),
+                ..utxos[0].clone()
+            });
+            spent_utxos.extend(selected_utxos);
+            num_optimization_txs += 1;
+        };
+        let change = payment_utxos.iter().map(|utxo| utxo.value).sum::<u64>() - value;
+        spent_utxos.extend(payment_utxos);
+
+        let (planned_utxos, planned_num_optimization_txs) = Manager::plan_optimization(
+            &SmallestFirstUtxoSelection,
+            Mob::ID,
+            &utxos,
+            value,
+            fee,
+            max_inputs,
+        )
+        .unwrap();
+        assert_eq!(planned_num_optimization_txs, 1);
+        assert_eq!(planned_num_optimization_txs, num_optimization_txs);
+        assert_eq!(
+            planned_utxos.iter().map(|utxo| utxo.value).sum::<u64>()
+                - value
+                - planned_num_optimization_txs * fee,
+            change
+        );
+
+        // Only compare the original UTXOs, not the outputs of the optimization
+        // transactions.
+        let original_indices = |spent_utxos: &[UnspentTxOut]| {
+            let mut indices: Vec<usize> = spent_utxos
+                .iter()
+                .filter_map(|spent_utxo| utxos.iter().position(|utxo| utxo == spent_utxo))
+                .collect();
+            indices.sort_unstable();
+            indices
+        };
+        let planned_indices = original_indices(&planned_utxos);
+        assert_eq!(planned_indices, original_indices(&spent_utxos));
+        assert!([0, 1, 8]
+            .iter()
+            .all(|index| planned_indices.contains(index)));
+        assert!([5, 6, 7]
+            .iter()
+            .all(|index| !planned_indices.contains(index)));
+    }
 }
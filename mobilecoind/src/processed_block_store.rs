@@ -321,6 +321,7 @@ mod test {
                     attempted_spend_height: 0,
                     attempted_spend_tombstone: 0,
                     token_id: *Mob::ID,
+                    key_image_unknown: false,
                 }
             })
             .collect();
@@ -375,7 +376,7 @@ mod test {
         )
         .expect("failed to create data");
 
-        let monitor_id = MonitorId::from(&monitor_data);
+        let monitor_id = MonitorId::try_from(&monitor_data).unwrap();
 
         // Initially, we should have no data for any of our blocks.
         {
@@ -456,7 +457,7 @@ mod test {
             )
             .expect("failed to create data");
 
-            let monitor_id = MonitorId::from(&monitor_data);
+            let monitor_id = MonitorId::try_from(&monitor_data).unwrap();
 
             let mut db_txn = env.begin_rw_txn().unwrap();
 
@@ -524,7 +525,7 @@ mod test {
             )
             .expect("failed to create data");
 
-            let monitor_id2 = MonitorId::from(&monitor_data2);
+            let monitor_id2 = MonitorId::try_from(&monitor_data2).unwrap();
 
             store
                 .block_processed(&mut db_txn, &monitor_id2, 0, &utxos[0..1], &utxos[1..2])
@@ -8,16 +8,25 @@
 
 use crate::{
     database::Database,
+    defragmentation::{get_utxo_count, DefragmentationThread},
     error::Error,
-    monitor_store::{MonitorData, MonitorId},
-    payments::{Outlay, TransactionsManager, TxProposal},
+    monitor_events::MonitorEventStream,
+    monitor_store::{DefragmentationPolicy, MonitorData, MonitorId},
+    outlay_memo_builder::{OutlayMemo, OutlayMemoBuilder},
+    payments::{Outlay, OutlayV2, TransactionsManager, TxProposal},
     sync::SyncThread,
+    tx_history_store::{TxHistoryKey, MAX_TX_HISTORY_ENTRIES_PER_REQUEST},
+    utxo_selection::{SmallestFirstUtxoSelection, UtxoSelectionStrategy},
     utxo_store::{UnspentTxOut, UtxoId},
 };
 use bip39::{Language, Mnemonic, MnemonicType};
-use grpcio::{EnvBuilder, RpcContext, RpcStatus, RpcStatusCode, ServerBuilder, UnarySink};
+use futures::{channel::mpsc, executor::block_on, prelude::*};
+use grpcio::{
+    EnvBuilder, RpcContext, RpcStatus, RpcStatusCode, ServerBuilder, ServerStreamingSink,
+    UnarySink, WriteFlags,
+};
 use mc_account_keys::{
-    burn_address, AccountKey, PublicAddress, RootIdentity, DEFAULT_SUBADDRESS_INDEX,
+    burn_address, AccountKey, PublicAddress, RootIdentity, ViewAccountKey, DEFAULT_SUBADDRESS_INDEX,
 };
 use mc_common::{
     logger::{log, Logger},
@@ -34,28 +43,49 @@ use mc_mobilecoind_api::{
     mobilecoind_api_grpc::{create_mobilecoind_api, MobilecoindApi},
     MobilecoindUri,
 };
-use mc_transaction_builder::BurnRedemptionMemoBuilder;
+use mc_transaction_builder::{BurnRedemptionMemoBuilder, MemoBuilder};
 use mc_transaction_core::{
     get_tx_out_shared_secret,
     onetime_keys::recover_onetime_private_key,
     ring_signature::KeyImage,
     tx::{TxOut, TxOutMembershipProof},
-    TokenId,
+    Amount, TokenId,
+};
+use mc_transaction_extra::{
+    BurnRedemptionMemo, SignedContingentInput, TxOutConfirmationNumber, TxOutGiftCode,
 };
-use mc_transaction_extra::{BurnRedemptionMemo, TxOutConfirmationNumber};
 use mc_util_from_random::FromRandom;
 use mc_util_grpc::{
-    rpc_internal_error, rpc_invalid_arg_error, rpc_logger, send_result, AdminService,
-    BuildInfoService, ConnectionUriGrpcioServer,
+    rpc_internal_error, rpc_invalid_arg_error, rpc_logger, rpc_precondition_error, send_result,
+    AdminService, BuildInfoService, ConnectionUriGrpcioServer,
 };
 use mc_watcher::watcher_db::WatcherDB;
 use protobuf::{ProtobufEnum, RepeatedField};
-use std::sync::{Arc, Mutex, RwLock};
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+/// How long a monitor event subscription waits for new blocks before checking
+/// whether the client is still connected.
+const MONITOR_EVENTS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a monitor event subscription may stay idle before the last caught
+/// up event is sent again. A client that went away is only noticed when
+/// sending to it fails.
+const MONITOR_EVENTS_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The number of monitor events that can be queued for delivery to a single
+/// subscriber.
+const MONITOR_EVENTS_CHANNEL_SIZE: usize = 100;
 
 pub struct Service {
     /// Sync thread.
     _sync_thread: Arc<Mutex<Option<SyncThread>>>,
 
+    /// Defragmentation thread.
+    _defragmentation_thread: DefragmentationThread,
+
     /// GRPC server.
     _server: grpcio::Server,
 }
@@ -105,6 +135,15 @@ impl Service {
             })
         };
 
+        log::info!(logger, "Starting mobilecoind defragmentation thread");
+        let defragmentation_thread = DefragmentationThread::start(
+            ledger_db.clone(),
+            mobilecoind_db.clone(),
+            transactions_manager.clone(),
+            network_state.clone(),
+            logger.clone(),
+        );
+
         let api = ServiceApi::new(
             transactions_manager,
             ledger_db,
@@ -155,6 +194,7 @@ impl Service {
         Self {
             _server: server,
             _sync_thread: sync_thread,
+            _defragmentation_thread: defragmentation_thread,
         }
     }
 }
@@ -223,6 +263,70 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             .collect()
     }
 
+    // Building transactions requires the spend private key, which view-only
+    // monitors do not have.
+    fn check_monitor_can_spend(&self, monitor_data: &MonitorData) -> Result<(), RpcStatus> {
+        if monitor_data.is_view_only() {
+            return Err(rpc_precondition_error(
+                "monitor_data.is_view_only",
+                Error::ViewOnlyMonitor,
+                &self.logger,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Get the memo builder for the memos attached to a list of outlays, or
+    /// None if they have none.
+    fn get_outlay_memo_builder(
+        &self,
+        monitor_data: &MonitorData,
+        outlay_list: &[api::Outlay],
+    ) -> Result<Option<Box<dyn MemoBuilder + 'static + Send + Sync>>, RpcStatus> {
+        let outlay_memos: Vec<OutlayMemo> = outlay_list
+            .iter()
+            .map(|outlay| OutlayMemo::from(outlay.get_memo()))
+            .collect();
+        let account_key = monitor_data.spendable_account_key().map_err(|err| {
+            rpc_precondition_error("monitor_data.spendable_account_key", err, &self.logger)
+        })?;
+        Ok(OutlayMemoBuilder::for_outlays(account_key, &outlay_memos))
+    }
+
+    /// Decode a b58 gift code.
+    fn parse_gift_code(&self, b58_code: &str) -> Result<TxOutGiftCode, RpcStatus> {
+        let wrapper =
+            api::printable::PrintableWrapper::b58_decode(b58_code.to_string()).map_err(|err| {
+                rpc_invalid_arg_error("PrintableWrapper.b58_decode", err, &self.logger)
+            })?;
+
+        if !wrapper.has_tx_out_gift_code() {
+            return Err(RpcStatus::with_message(
+                RpcStatusCode::INVALID_ARGUMENT,
+                "has_tx_out_gift_code".into(),
+            ));
+        }
+
+        TxOutGiftCode::try_from(wrapper.get_tx_out_gift_code())
+            .map_err(|err| rpc_invalid_arg_error("tx_out_gift_code.try_from", err, &self.logger))
+    }
+
+    /// Map an error from one of the gift code calls on the transactions manager
+    /// to an RpcStatus.
+    fn gift_code_rpc_error(&self, context: &str, err: Error) -> RpcStatus {
+        match err {
+            Error::GiftCodeMismatch | Error::InvalidArgument(..) => {
+                rpc_invalid_arg_error(context, err, &self.logger)
+            }
+            Error::GiftCodeSpent
+            | Error::InsufficientFunds
+            | Error::LedgerDB(LedgerError::NotFound) => {
+                rpc_precondition_error(context, err, &self.logger)
+            }
+            err => rpc_internal_error(context, err, &self.logger),
+        }
+    }
+
     fn get_version_impl(
         &self,
         _request: api::Empty,
@@ -236,27 +340,78 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         &mut self,
         request: api::AddMonitorRequest,
     ) -> Result<api::AddMonitorResponse, RpcStatus> {
-        // Get the AccountKey from the GRPC request.
-        let proto_account_key = request.account_key.as_ref().ok_or_else(|| {
-            RpcStatus::with_message(RpcStatusCode::INVALID_ARGUMENT, "account_key".into())
-        })?;
-        let account_key = AccountKey::try_from(proto_account_key)
-            .map_err(|err| rpc_internal_error("account_key.try_from", err, &self.logger))?;
+        // Populate a new `MonitorData` instance from either the AccountKey or, for
+        // view-only monitors, the ViewAccountKey in the GRPC request.
+        let data = match (
+            request.account_key.as_ref(),
+            request.view_account_key.as_ref(),
+        ) {
+            (Some(proto_account_key), None) => {
+                let account_key = AccountKey::try_from(proto_account_key)
+                    .map_err(|err| rpc_internal_error("account_key.try_from", err, &self.logger))?;
+
+                MonitorData::new(
+                    account_key,
+                    request.first_subaddress,
+                    request.num_subaddresses,
+                    request.first_block,
+                    &request.name,
+                )
+                .map_err(|err| rpc_internal_error("monitor_data.new", err, &self.logger))?
+            }
+            (None, Some(proto_view_account_key)) => {
+                let view_account_key =
+                    ViewAccountKey::try_from(proto_view_account_key).map_err(|err| {
+                        rpc_internal_error("view_account_key.try_from", err, &self.logger)
+                    })?;
 
-        // Populate a new `MonitorData` instance.
-        let data = MonitorData::new(
-            account_key,
-            request.first_subaddress,
-            request.num_subaddresses,
-            request.first_block,
-            &request.name,
-        )
-        .map_err(|err| rpc_internal_error("monitor_data.new", err, &self.logger))?;
+                MonitorData::new_view_only(
+                    view_account_key,
+                    request.first_subaddress,
+                    request.num_subaddresses,
+                    request.first_block,
+                    &request.name,
+                )
+                .map_err(|err| {
+                    rpc_internal_error("monitor_data.new_view_only", err, &self.logger)
+                })?
+            }
+            _ => {
+                return Err(RpcStatus::with_message(
+                    RpcStatusCode::INVALID_ARGUMENT,
+                    "exactly one of account_key and view_account_key must be set".into(),
+                ));
+            }
+        };
+
+        // New monitors scan the TxOuts of every block from `first_block` on, which a
+        // pruned ledger no longer has for its oldest blocks. Pruning is locked out
+        // until the monitor is stored, so it can not prune past `first_block` in the
+        // meantime.
+        let monitor_id = MonitorId::try_from(&data)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.data", err, &self.logger))?;
+        let _pruning_guard = self.mobilecoind_db.lock_pruning();
+        let pruned_num_blocks = self
+            .ledger_db
+            .pruned_num_blocks()
+            .map_err(|err| rpc_internal_error("ledger_db.pruned_num_blocks", err, &self.logger))?;
+        if data.first_block < pruned_num_blocks {
+            if let Err(Error::MonitorIdNotFound) = self.mobilecoind_db.get_monitor_data(&monitor_id)
+            {
+                return Err(RpcStatus::with_message(
+                    RpcStatusCode::FAILED_PRECONDITION,
+                    format!(
+                        "first_block {} has been pruned from the ledger, the earliest block that can be scanned is {}",
+                        data.first_block, pruned_num_blocks
+                    ),
+                ));
+            }
+        }
 
         // Insert into database. Return the id and flag if the monitor already existed.
         let (id, is_new) = match self.mobilecoind_db.add_monitor(&data) {
             Ok(id) => Ok((id, true)),
-            Err(Error::MonitorIdExists) => Ok((MonitorId::from(&data), false)),
+            Err(Error::MonitorIdExists) => Ok((monitor_id, false)),
             Err(err) => Err(err),
         }
         .map_err(|err| rpc_internal_error("mobilecoind_db.add_monitor", err, &self.logger))?;
@@ -319,12 +474,39 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             })?;
 
         let mut status = api::MonitorStatus::new();
-        status.set_account_key(mc_api::external::AccountKey::from(&data.account_key));
+        if let Some(account_key) = data.account_key.as_ref() {
+            status.set_account_key(mc_api::external::AccountKey::from(account_key));
+        }
+        let view_account_key = data
+            .view_account_key()
+            .map_err(|err| rpc_internal_error("data.view_account_key", err, &self.logger))?;
+        status.set_view_account_key((&view_account_key).into());
+        status.set_is_view_only(data.is_view_only());
         status.set_first_subaddress(data.first_subaddress);
         status.set_num_subaddresses(data.num_subaddresses);
         status.set_first_block(data.first_block);
         status.set_next_block(data.next_block);
 
+        if let Some(policy) = data.defragmentation_policy.as_ref() {
+            let utxo_count = get_utxo_count(
+                &self.mobilecoind_db,
+                &monitor_id,
+                policy.subaddress_index,
+                policy.token_id,
+            )
+            .map_err(|err| rpc_internal_error("get_utxo_count", err, &self.logger))?;
+
+            let mut progress = api::DefragmentationProgress::new();
+            if let Some(data_progress) = data.defragmentation_progress.as_ref() {
+                progress.set_num_txs_submitted(data_progress.num_txs_submitted);
+                progress.set_fees_spent(data_progress.fees_spent);
+            }
+            progress.set_utxo_count(utxo_count);
+
+            status.set_defragmentation_policy(policy.into());
+            status.set_defragmentation_progress(progress);
+        }
+
         let mut response = api::GetMonitorStatusResponse::new();
         response.set_status(status);
         Ok(response)
@@ -361,6 +543,128 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         Ok(response)
     }
 
+    fn import_key_images_impl(
+        &mut self,
+        request: api::ImportKeyImagesRequest,
+    ) -> Result<api::ImportKeyImagesResponse, RpcStatus> {
+        // Get MonitorId from from the GRPC request.
+        let monitor_id = MonitorId::try_from(&request.monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Parse the (tx public key, key image) pairs.
+        let key_images = request
+            .get_key_images()
+            .iter()
+            .enumerate()
+            .map(|(i, proto)| {
+                let tx_public_key = CompressedRistrettoPublic::try_from(proto.get_tx_public_key())
+                    .map_err(|err| {
+                        rpc_invalid_arg_error(
+                            format!("key_images[{}].tx_public_key", i),
+                            err,
+                            &self.logger,
+                        )
+                    })?;
+                let key_image = KeyImage::try_from(proto.get_key_image()).map_err(|err| {
+                    rpc_invalid_arg_error(format!("key_images[{}].key_image", i), err, &self.logger)
+                })?;
+                Ok((tx_public_key, key_image))
+            })
+            .collect::<Result<Vec<_>, RpcStatus>>()?;
+
+        // Replace the placeholder key images in the database.
+        let mut imported_key_images = Vec::new();
+        for (tx_public_key, key_image) in key_images.iter() {
+            let utxo = self
+                .mobilecoind_db
+                .set_utxo_key_image(&monitor_id, tx_public_key, key_image)
+                .map_err(|err| match err {
+                    Error::UtxoIdNotFound => rpc_invalid_arg_error(
+                        "mobilecoind_db.set_utxo_key_image",
+                        format!("no utxo with an unknown key image for {}", tx_public_key),
+                        &self.logger,
+                    ),
+                    err => {
+                        rpc_internal_error("mobilecoind_db.set_utxo_key_image", err, &self.logger)
+                    }
+                })?;
+            imported_key_images.push(utxo.key_image);
+        }
+
+        // Key images that already appear in the ledger were spent before they were
+        // imported, so the sync thread will never see them. This check happens after
+        // the import so that key images spent in blocks appended concurrently are
+        // handled by the sync thread.
+        let mut spent_key_images = Vec::new();
+        for key_image in imported_key_images.iter() {
+            if self
+                .ledger_db
+                .contains_key_image(key_image)
+                .map_err(|err| {
+                    rpc_internal_error("ledger_db.contains_key_image", err, &self.logger)
+                })?
+            {
+                spent_key_images.push(*key_image);
+            }
+        }
+        let removed_utxos = self
+            .mobilecoind_db
+            .remove_utxos_by_key_images(&monitor_id, &spent_key_images)
+            .map_err(|err| {
+                rpc_internal_error(
+                    "mobilecoind_db.remove_utxos_by_key_images",
+                    err,
+                    &self.logger,
+                )
+            })?;
+
+        // Return response.
+        let mut response = api::ImportKeyImagesResponse::new();
+        response.set_num_imported(imported_key_images.len() as u64);
+        response.set_num_spent(removed_utxos.len() as u64);
+        Ok(response)
+    }
+
+    fn set_defragmentation_policy_impl(
+        &mut self,
+        request: api::SetDefragmentationPolicyRequest,
+    ) -> Result<api::Empty, RpcStatus> {
+        // Get MonitorId from from the GRPC request.
+        let monitor_id = MonitorId::try_from(&request.monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Defragmenting builds transactions, which view-only monitors cannot do.
+        let monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+        let policy = if request.has_policy() {
+            self.check_monitor_can_spend(&monitor_data)?;
+            Some(DefragmentationPolicy::from(request.get_policy()))
+        } else {
+            None
+        };
+
+        self.mobilecoind_db
+            .set_defragmentation_policy(&monitor_id, policy)
+            .map_err(|err| match err {
+                Error::InvalidArgument(_, _) => rpc_invalid_arg_error(
+                    "mobilecoind_db.set_defragmentation_policy",
+                    err,
+                    &self.logger,
+                ),
+                err => rpc_internal_error(
+                    "mobilecoind_db.set_defragmentation_policy",
+                    err,
+                    &self.logger,
+                ),
+            })?;
+
+        Ok(api::Empty::new())
+    }
+
     fn generate_root_entropy_impl(
         &mut self,
         _request: api::Empty,
@@ -451,7 +755,9 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         }
 
         // Get the subaddress.
-        let subaddress = data.account_key.subaddress(request.subaddress_index);
+        let subaddress = data
+            .subaddress(request.subaddress_index)
+            .map_err(|err| rpc_internal_error("data.subaddress", err, &self.logger))?;
 
         // Also build the b58 wrapper
         let mut wrapper = api::printable::PrintableWrapper::new();
@@ -614,6 +920,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             token_id: *amount.token_id,
             attempted_spend_height: 0,
             attempted_spend_tombstone: 0,
+            key_image_unknown: false,
         };
 
         let mut response = api::ParseTransferCodeResponse::new();
@@ -830,6 +1137,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             .map_err(|err| {
                 rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
             })?;
+        self.check_monitor_can_spend(&sender_monitor_data)?;
 
         // Check that change_subaddress is covered by this monitor.
         if !sender_monitor_data
@@ -894,8 +1202,12 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
                     .map_err(|err| rpc_internal_error("outlay.try_from", err, &self.logger))
             })
             .collect::<Result<Vec<Outlay>, RpcStatus>>()?;
+        let memo_builder =
+            self.get_outlay_memo_builder(&sender_monitor_data, request.get_outlay_list())?;
 
         // Attempt to construct a transaction.
+        let utxo_selection_strategy: Box<dyn UtxoSelectionStrategy> =
+            request.get_utxo_selection_strategy().into();
         let tx_proposal = self
             .transactions_manager
             .build_transaction(
@@ -904,10 +1216,11 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
                 request.change_subaddress,
                 &input_list,
                 &outlays,
+                utxo_selection_strategy.as_ref(),
                 &self.get_last_block_infos(),
                 request.fee,
                 request.tombstone,
-                None,
+                memo_builder,
             )
             .map_err(|err| {
                 rpc_internal_error("transactions_manager.build_transaction", err, &self.logger)
@@ -919,6 +1232,68 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         Ok(response)
     }
 
+    fn estimate_payment_impl(
+        &mut self,
+        request: api::EstimatePaymentRequest,
+    ) -> Result<api::EstimatePaymentResponse, RpcStatus> {
+        // Get sender monitor id from request.
+        let sender_monitor_id = MonitorId::try_from(&request.sender_monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Get all utxos for this monitor id.
+        let mut utxos = self
+            .mobilecoind_db
+            .get_utxos_for_subaddress(&sender_monitor_id, request.sender_subaddress)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_utxos_for_subaddress", err, &self.logger)
+            })?;
+
+        // Optionally filter for max value.
+        if request.max_input_utxo_value > 0 {
+            utxos.retain(|utxo| utxo.value <= request.max_input_utxo_value);
+        }
+
+        // Filter for requested token id.
+        utxos.retain(|utxo| utxo.token_id == request.token_id);
+
+        // Get the list of outlays.
+        let outlays: Vec<Outlay> = request
+            .get_outlay_list()
+            .iter()
+            .map(|outlay_proto| {
+                Outlay::try_from(outlay_proto)
+                    .map_err(|err| rpc_internal_error("outlay.try_from", err, &self.logger))
+            })
+            .collect::<Result<Vec<Outlay>, RpcStatus>>()?;
+
+        // Estimate.
+        let utxo_selection_strategy: Box<dyn UtxoSelectionStrategy> =
+            request.get_utxo_selection_strategy().into();
+        let estimate = self
+            .transactions_manager
+            .estimate_payment(
+                TokenId::from(request.token_id),
+                &utxos,
+                &outlays,
+                utxo_selection_strategy.as_ref(),
+                &self.get_last_block_infos(),
+                request.fee,
+            )
+            .map_err(|err| {
+                rpc_internal_error("transactions_manager.estimate_payment", err, &self.logger)
+            })?;
+
+        let mut response = api::EstimatePaymentResponse::new();
+        response.set_fee(estimate.fee);
+        response.set_num_inputs(estimate.utxos.len() as u64);
+        response.set_input_value(estimate.utxos.iter().map(|utxo| utxo.value).sum());
+        response.set_change(estimate.change);
+        response.set_requires_optimization(estimate.requires_optimization);
+        response.set_num_optimization_txs(estimate.num_optimization_txs);
+        response.set_optimization_fee(estimate.optimization_fee);
+        Ok(response)
+    }
+
     fn generate_optimization_tx_impl(
         &mut self,
         request: api::GenerateOptimizationTxRequest,
@@ -927,6 +1302,15 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         let monitor_id = MonitorId::try_from(&request.monitor_id)
             .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
 
+        // Get monitor data for this monitor.
+        let monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+        self.check_monitor_can_spend(&monitor_data)?;
+
         // Generate optimization tx.
         let tx_proposal = self
             .transactions_manager
@@ -1027,6 +1411,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             .map_err(|err| {
                 rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
             })?;
+        self.check_monitor_can_spend(&sender_monitor_data)?;
 
         // Check that change_subaddress is covered by this monitor.
         if !sender_monitor_data
@@ -1111,6 +1496,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
                 request.change_subaddress,
                 &input_list,
                 &outlays,
+                &SmallestFirstUtxoSelection,
                 &self.get_last_block_infos(),
                 request.fee,
                 request.tombstone,
@@ -1325,55 +1711,600 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         Ok(response)
     }
 
-    fn get_ledger_info_impl(
-        &mut self,
-        _request: api::Empty,
-    ) -> Result<api::GetLedgerInfoResponse, RpcStatus> {
-        let num_blocks = self
-            .ledger_db
-            .num_blocks()
-            .map_err(|err| rpc_internal_error("ledger_db.num_blocks", err, &self.logger))?;
-
-        let num_txos = self
-            .ledger_db
-            .num_txos()
-            .map_err(|err| rpc_internal_error("ledger_db.num_txos", err, &self.logger))?;
-
-        let mut response = api::GetLedgerInfoResponse::new();
-        response.set_block_count(num_blocks);
-        response.set_txo_count(num_txos);
-        Ok(response)
-    }
+    // Convert UnspentTxOuts from a request, verifying that they all belong to
+    // the given monitor.
+    fn get_monitor_utxos(
+        &self,
+        monitor_id: &MonitorId,
+        field: &str,
+        proto_utxos: &[api::UnspentTxOut],
+    ) -> Result<Vec<UnspentTxOut>, RpcStatus> {
+        proto_utxos
+            .iter()
+            .enumerate()
+            .map(|(i, proto_utxo)| {
+                // Proto -> Rust struct conversion.
+                let utxo = UnspentTxOut::try_from(proto_utxo).map_err(|err| {
+                    rpc_internal_error("unspent_tx_out.try_from", err, &self.logger)
+                })?;
 
-    fn get_block_info_impl(
-        &mut self,
-        request: api::GetBlockInfoRequest,
-    ) -> Result<api::GetBlockInfoResponse, RpcStatus> {
-        let block_contents = self
-            .ledger_db
-            .get_block_contents(request.block)
-            .map_err(|err| rpc_internal_error("ledger_db.get_block_contents", err, &self.logger))?;
+                // Verify this output belongs to the monitor.
+                let subaddress_id = self
+                    .mobilecoind_db
+                    .get_subaddress_id_by_utxo_id(&UtxoId::from(&utxo))
+                    .map_err(|err| {
+                        rpc_internal_error(
+                            "mobilecoind_db.get_subaddress_id_by_utxo_id",
+                            err,
+                            &self.logger,
+                        )
+                    })?;
 
-        let num_tx_outs = block_contents.outputs.len();
-        let num_key_images = block_contents.key_images.len();
+                if subaddress_id.monitor_id != *monitor_id {
+                    return Err(RpcStatus::with_message(
+                        RpcStatusCode::INVALID_ARGUMENT,
+                        format!("{}.{}", field, i),
+                    ));
+                }
 
-        // Return response.
-        let mut response = api::GetBlockInfoResponse::new();
-        response.set_key_image_count(num_key_images as u64);
-        response.set_txo_count(num_tx_outs as u64);
-        Ok(response)
+                Ok(utxo)
+            })
+            .collect()
     }
 
-    fn get_block_impl(
+    fn generate_signed_contingent_input_impl(
         &mut self,
-        request: api::GetBlockRequest,
-    ) -> Result<api::GetBlockResponse, RpcStatus> {
-        let mut response = api::GetBlockResponse::new();
+        request: api::GenerateSignedContingentInputRequest,
+    ) -> Result<api::GenerateSignedContingentInputResponse, RpcStatus> {
+        // Get sender monitor id from request.
+        let sender_monitor_id = MonitorId::try_from(&request.sender_monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
 
-        let block_data = self
-            .ledger_db
-            .get_block_data(request.block)
-            .map_err(|err| rpc_internal_error("ledger_db.get_block_data", err, &self.logger))?;
+        // Get monitor data for this monitor.
+        let sender_monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&sender_monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+        self.check_monitor_can_spend(&sender_monitor_data)?;
+
+        // Check that change_subaddress is covered by this monitor.
+        if !sender_monitor_data
+            .subaddress_indexes()
+            .contains(&request.change_subaddress)
+        {
+            return Err(RpcStatus::with_message(
+                RpcStatusCode::INVALID_ARGUMENT,
+                "change_subaddress".into(),
+            ));
+        }
+
+        // Get the input being offered.
+        let input = self
+            .get_monitor_utxos(
+                &sender_monitor_id,
+                "input",
+                core::slice::from_ref(request.get_input()),
+            )?
+            .pop()
+            .ok_or_else(|| {
+                RpcStatus::with_message(RpcStatusCode::INVALID_ARGUMENT, "input".into())
+            })?;
+
+        // Get the lists of outlays.
+        let required_outlays: Vec<OutlayV2> = request
+            .get_required_outlay_list()
+            .iter()
+            .map(|outlay_proto| {
+                OutlayV2::try_from(outlay_proto)
+                    .map_err(|err| rpc_internal_error("outlay_v2.try_from", err, &self.logger))
+            })
+            .collect::<Result<Vec<OutlayV2>, RpcStatus>>()?;
+        let partial_fill_outlays: Vec<OutlayV2> = request
+            .get_partial_fill_outlay_list()
+            .iter()
+            .map(|outlay_proto| {
+                OutlayV2::try_from(outlay_proto)
+                    .map_err(|err| rpc_internal_error("outlay_v2.try_from", err, &self.logger))
+            })
+            .collect::<Result<Vec<OutlayV2>, RpcStatus>>()?;
+
+        // Attempt to construct the signed contingent input.
+        let sci = self
+            .transactions_manager
+            .build_signed_contingent_input(
+                &sender_monitor_id,
+                request.change_subaddress,
+                &input,
+                request.offered_value,
+                &required_outlays,
+                &partial_fill_outlays,
+                request.min_partial_fill_value,
+                &self.get_last_block_infos(),
+                request.tombstone,
+            )
+            .map_err(|err| match err {
+                Error::InvalidArgument(..) | Error::InsufficientFunds => rpc_invalid_arg_error(
+                    "transactions_manager.build_signed_contingent_input",
+                    err,
+                    &self.logger,
+                ),
+                err => rpc_internal_error(
+                    "transactions_manager.build_signed_contingent_input",
+                    err,
+                    &self.logger,
+                ),
+            })?;
+
+        // Success.
+        let mut response = api::GenerateSignedContingentInputResponse::new();
+        response.set_sci((&sci).into());
+        Ok(response)
+    }
+
+    fn inspect_signed_contingent_input_impl(
+        &mut self,
+        request: api::InspectSignedContingentInputRequest,
+    ) -> Result<api::InspectSignedContingentInputResponse, RpcStatus> {
+        let sci = SignedContingentInput::try_from(request.get_sci())
+            .map_err(|err| rpc_invalid_arg_error("sci.try_from", err, &self.logger))?;
+
+        let mut response = api::InspectSignedContingentInputResponse::new();
+
+        match sci.validate() {
+            Ok(()) => response.set_is_valid(true),
+            Err(err) => response.set_validation_error(err.to_string()),
+        }
+
+        let key_image = sci.key_image();
+        let is_spent = self
+            .ledger_db
+            .contains_key_image(&key_image)
+            .map_err(|err| rpc_internal_error("ledger_db.contains_key_image", err, &self.logger))?;
+        response.set_key_image((&key_image).into());
+        response.set_is_spent(is_spent);
+
+        response.set_block_version(sci.block_version);
+        response.set_offered_amount((&Amount::from(&sci.pseudo_output_amount)).into());
+        response.set_required_output_amounts(RepeatedField::from_vec(
+            sci.required_output_amounts
+                .iter()
+                .map(|amount| (&Amount::from(amount)).into())
+                .collect(),
+        ));
+
+        if let Some(rules) = sci.tx_in.input_rules.as_ref() {
+            response.set_max_tombstone_block(rules.max_tombstone_block);
+
+            if let Some(partial_fill_change) = rules.partial_fill_change.as_ref() {
+                response.set_is_partial_fill(true);
+
+                // Validation checks that these amounts can be revealed.
+                if response.get_is_valid() {
+                    let (change_amount, _) =
+                        partial_fill_change.reveal_amount().map_err(|err| {
+                            rpc_internal_error(
+                                "partial_fill_change.reveal_amount",
+                                err,
+                                &self.logger,
+                            )
+                        })?;
+                    let output_amounts = rules
+                        .partial_fill_outputs
+                        .iter()
+                        .map(|output| {
+                            output
+                                .reveal_amount()
+                                .map(|(amount, _)| api::external::Amount::from(&amount))
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| {
+                            rpc_internal_error(
+                                "partial_fill_outputs.reveal_amount",
+                                err,
+                                &self.logger,
+                            )
+                        })?;
+
+                    response.set_partial_fill_change_amount((&change_amount).into());
+                    response
+                        .set_partial_fill_output_amounts(RepeatedField::from_vec(output_amounts));
+                    response.set_min_partial_fill_value(rules.min_partial_fill_value);
+                }
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn fill_signed_contingent_input_impl(
+        &mut self,
+        request: api::FillSignedContingentInputRequest,
+    ) -> Result<api::FillSignedContingentInputResponse, RpcStatus> {
+        // Get sender monitor id from request.
+        let sender_monitor_id = MonitorId::try_from(&request.sender_monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Get monitor data for this monitor.
+        let sender_monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&sender_monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+        self.check_monitor_can_spend(&sender_monitor_data)?;
+
+        // Check that change_subaddress is covered by this monitor.
+        if !sender_monitor_data
+            .subaddress_indexes()
+            .contains(&request.change_subaddress)
+        {
+            return Err(RpcStatus::with_message(
+                RpcStatusCode::INVALID_ARGUMENT,
+                "change_subaddress".into(),
+            ));
+        }
+
+        // Get the list of potential inputs passed to.
+        let input_list =
+            self.get_monitor_utxos(&sender_monitor_id, "input_list", request.get_input_list())?;
+
+        let sci = SignedContingentInput::try_from(request.get_sci())
+            .map_err(|err| rpc_invalid_arg_error("sci.try_from", err, &self.logger))?;
+
+        // Attempt to construct a transaction.
+        let tx_proposal = self
+            .transactions_manager
+            .fill_signed_contingent_input(
+                &sender_monitor_id,
+                request.change_subaddress,
+                sci,
+                request.fill_value,
+                &input_list,
+                TokenId::from(request.fee_token_id),
+                &self.get_last_block_infos(),
+                request.fee,
+                request.tombstone,
+            )
+            .map_err(|err| match err {
+                Error::SignedContingentInput(_) | Error::InvalidArgument(..) => {
+                    rpc_invalid_arg_error(
+                        "transactions_manager.fill_signed_contingent_input",
+                        err,
+                        &self.logger,
+                    )
+                }
+                Error::SignedContingentInputSpent => rpc_precondition_error(
+                    "transactions_manager.fill_signed_contingent_input",
+                    err,
+                    &self.logger,
+                ),
+                err => rpc_internal_error(
+                    "transactions_manager.fill_signed_contingent_input",
+                    err,
+                    &self.logger,
+                ),
+            })?;
+
+        // Success.
+        let mut response = api::FillSignedContingentInputResponse::new();
+        response.set_tx_proposal((&tx_proposal).into());
+        Ok(response)
+    }
+
+    fn generate_gift_code_funding_tx_impl(
+        &mut self,
+        request: api::GenerateGiftCodeFundingTxRequest,
+    ) -> Result<api::GenerateGiftCodeFundingTxResponse, RpcStatus> {
+        // Get sender monitor id from request.
+        let sender_monitor_id = MonitorId::try_from(&request.sender_monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Get monitor data for this monitor.
+        let sender_monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&sender_monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+        self.check_monitor_can_spend(&sender_monitor_data)?;
+
+        // Check that change_subaddress is covered by this monitor.
+        if !sender_monitor_data
+            .subaddress_indexes()
+            .contains(&request.change_subaddress)
+        {
+            return Err(RpcStatus::with_message(
+                RpcStatusCode::INVALID_ARGUMENT,
+                "change_subaddress".into(),
+            ));
+        }
+
+        // Get the list of potential inputs passed to.
+        let input_list =
+            self.get_monitor_utxos(&sender_monitor_id, "input_list", request.get_input_list())?;
+
+        let utxo_selection_strategy: Box<dyn UtxoSelectionStrategy> =
+            request.get_utxo_selection_strategy().into();
+
+        // Attempt to construct a transaction.
+        let tx_proposal = self
+            .transactions_manager
+            .build_gift_code_funding_transaction(
+                &sender_monitor_id,
+                TokenId::from(request.token_id),
+                request.change_subaddress,
+                &input_list,
+                request.value,
+                utxo_selection_strategy.as_ref(),
+                &self.get_last_block_infos(),
+                request.fee,
+                request.tombstone,
+                request.get_note(),
+            )
+            .map_err(|err| {
+                self.gift_code_rpc_error(
+                    "transactions_manager.build_gift_code_funding_transaction",
+                    err,
+                )
+            })?;
+
+        // The gift code TxOut is the single outlay.
+        let tx_out_index = tx_proposal
+            .outlay_index_to_tx_out_index
+            .get(&0)
+            .ok_or_else(|| {
+                RpcStatus::with_message(
+                    RpcStatusCode::INTERNAL,
+                    "outlay_index_to_tx_out_index doesn't contain index 0".to_owned(),
+                )
+            })?;
+        let tx_public_key = &tx_proposal.tx.prefix.outputs[*tx_out_index].public_key;
+
+        // Success.
+        let mut response = api::GenerateGiftCodeFundingTxResponse::new();
+        response.set_tx_public_key(tx_public_key.into());
+        response.set_tx_proposal((&tx_proposal).into());
+        Ok(response)
+    }
+
+    fn create_gift_code_impl(
+        &mut self,
+        request: api::CreateGiftCodeRequest,
+    ) -> Result<api::CreateGiftCodeResponse, RpcStatus> {
+        // Get sender monitor id from request.
+        let sender_monitor_id = MonitorId::try_from(&request.sender_monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        let tx_public_key = CompressedRistrettoPublic::try_from(request.get_tx_public_key())
+            .map_err(|err| rpc_invalid_arg_error("tx_public_key", err, &self.logger))?;
+
+        let gift_code = self
+            .transactions_manager
+            .create_gift_code(&sender_monitor_id, &tx_public_key)
+            .map_err(|err| {
+                self.gift_code_rpc_error("transactions_manager.create_gift_code", err)
+            })?;
+
+        let (_tx_out, amount) = self
+            .transactions_manager
+            .get_gift_code_tx_out(&gift_code)
+            .map_err(|err| {
+                self.gift_code_rpc_error("transactions_manager.get_gift_code_tx_out", err)
+            })?;
+
+        let mut wrapper = api::printable::PrintableWrapper::new();
+        wrapper.set_tx_out_gift_code((&gift_code).into());
+
+        let b58_code = wrapper
+            .b58_encode()
+            .map_err(|err| rpc_internal_error("b58_encode", err, &self.logger))?;
+
+        let mut response = api::CreateGiftCodeResponse::new();
+        response.set_b58_code(b58_code);
+        response.set_global_index(gift_code.global_index);
+        response.set_amount((&amount).into());
+        Ok(response)
+    }
+
+    fn get_gift_code_status_impl(
+        &mut self,
+        request: api::GetGiftCodeStatusRequest,
+    ) -> Result<api::GetGiftCodeStatusResponse, RpcStatus> {
+        let gift_code = self.parse_gift_code(request.get_b58_code())?;
+
+        let mut response = api::GetGiftCodeStatusResponse::new();
+        response.set_global_index(gift_code.global_index);
+
+        let (tx_out, amount) = match self.transactions_manager.get_gift_code_tx_out(&gift_code) {
+            Ok(result) => result,
+            Err(Error::LedgerDB(LedgerError::NotFound)) => {
+                response.set_status(api::GiftCodeStatus::GiftCodeNotFound);
+                return Ok(response);
+            }
+            Err(err) => {
+                return Err(
+                    self.gift_code_rpc_error("transactions_manager.get_gift_code_tx_out", err)
+                );
+            }
+        };
+        response.set_amount((&amount).into());
+        response.set_tx_public_key((&tx_out.public_key).into());
+
+        let key_image = KeyImage::from(&gift_code.onetime_private_key);
+        match self
+            .ledger_db
+            .check_key_image(&key_image)
+            .map_err(|err| rpc_internal_error("ledger_db.check_key_image", err, &self.logger))?
+        {
+            Some(block_index) => {
+                response.set_status(api::GiftCodeStatus::GiftCodeSpent);
+                response.set_spent_block_index(block_index);
+            }
+            None => response.set_status(api::GiftCodeStatus::GiftCodeAvailable),
+        }
+
+        Ok(response)
+    }
+
+    fn generate_gift_code_claim_tx_impl(
+        &mut self,
+        request: api::GenerateGiftCodeClaimTxRequest,
+    ) -> Result<api::GenerateGiftCodeClaimTxResponse, RpcStatus> {
+        // Get monitor id from request.
+        let monitor_id = MonitorId::try_from(&request.monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Get monitor data for this monitor.
+        let monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+
+        // Check that subaddress_index is covered by this monitor.
+        if !monitor_data
+            .subaddress_indexes()
+            .contains(&request.subaddress_index)
+        {
+            return Err(RpcStatus::with_message(
+                RpcStatusCode::INVALID_ARGUMENT,
+                "subaddress_index".into(),
+            ));
+        }
+
+        let gift_code = self.parse_gift_code(request.get_b58_code())?;
+
+        // Attempt to construct a transaction.
+        let tx_proposal = self
+            .transactions_manager
+            .build_gift_code_claim_transaction(
+                &monitor_id,
+                request.subaddress_index,
+                &gift_code,
+                &self.get_last_block_infos(),
+                request.fee,
+                request.tombstone,
+                request.get_note(),
+            )
+            .map_err(|err| {
+                self.gift_code_rpc_error(
+                    "transactions_manager.build_gift_code_claim_transaction",
+                    err,
+                )
+            })?;
+
+        // Success.
+        let mut response = api::GenerateGiftCodeClaimTxResponse::new();
+        response.set_tx_proposal((&tx_proposal).into());
+        Ok(response)
+    }
+
+    fn generate_gift_code_cancellation_tx_impl(
+        &mut self,
+        request: api::GenerateGiftCodeCancellationTxRequest,
+    ) -> Result<api::GenerateGiftCodeCancellationTxResponse, RpcStatus> {
+        // Get sender monitor id from request.
+        let sender_monitor_id = MonitorId::try_from(&request.sender_monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Get monitor data for this monitor.
+        let sender_monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&sender_monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+        self.check_monitor_can_spend(&sender_monitor_data)?;
+
+        // Check that change_subaddress is covered by this monitor.
+        if !sender_monitor_data
+            .subaddress_indexes()
+            .contains(&request.change_subaddress)
+        {
+            return Err(RpcStatus::with_message(
+                RpcStatusCode::INVALID_ARGUMENT,
+                "change_subaddress".into(),
+            ));
+        }
+
+        let gift_code = self.parse_gift_code(request.get_b58_code())?;
+
+        // Attempt to construct a transaction.
+        let tx_proposal = self
+            .transactions_manager
+            .build_gift_code_cancellation_transaction(
+                &sender_monitor_id,
+                request.change_subaddress,
+                &gift_code,
+                &self.get_last_block_infos(),
+                request.fee,
+                request.tombstone,
+            )
+            .map_err(|err| {
+                self.gift_code_rpc_error(
+                    "transactions_manager.build_gift_code_cancellation_transaction",
+                    err,
+                )
+            })?;
+
+        // Success.
+        let mut response = api::GenerateGiftCodeCancellationTxResponse::new();
+        response.set_tx_proposal((&tx_proposal).into());
+        Ok(response)
+    }
+
+    fn get_ledger_info_impl(
+        &mut self,
+        _request: api::Empty,
+    ) -> Result<api::GetLedgerInfoResponse, RpcStatus> {
+        let num_blocks = self
+            .ledger_db
+            .num_blocks()
+            .map_err(|err| rpc_internal_error("ledger_db.num_blocks", err, &self.logger))?;
+
+        let num_txos = self
+            .ledger_db
+            .num_txos()
+            .map_err(|err| rpc_internal_error("ledger_db.num_txos", err, &self.logger))?;
+
+        let mut response = api::GetLedgerInfoResponse::new();
+        response.set_block_count(num_blocks);
+        response.set_txo_count(num_txos);
+        Ok(response)
+    }
+
+    fn get_block_info_impl(
+        &mut self,
+        request: api::GetBlockInfoRequest,
+    ) -> Result<api::GetBlockInfoResponse, RpcStatus> {
+        let block_contents = self
+            .ledger_db
+            .get_block_contents(request.block)
+            .map_err(|err| rpc_internal_error("ledger_db.get_block_contents", err, &self.logger))?;
+
+        let num_tx_outs = block_contents.outputs.len();
+        let num_key_images = block_contents.key_images.len();
+
+        // Return response.
+        let mut response = api::GetBlockInfoResponse::new();
+        response.set_key_image_count(num_key_images as u64);
+        response.set_txo_count(num_tx_outs as u64);
+        Ok(response)
+    }
+
+    fn get_block_impl(
+        &mut self,
+        request: api::GetBlockRequest,
+    ) -> Result<api::GetBlockResponse, RpcStatus> {
+        let mut response = api::GetBlockResponse::new();
+
+        let block_data = self
+            .ledger_db
+            .get_block_data(request.block)
+            .map_err(|err| rpc_internal_error("ledger_db.get_block_data", err, &self.logger))?;
 
         response.set_block(mc_consensus_api::blockchain::Block::from(
             block_data.block(),
@@ -1571,6 +2502,49 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         Ok(response)
     }
 
+    fn get_batch_status_as_sender_impl(
+        &mut self,
+        request: api::GetBatchStatusAsSenderRequest,
+    ) -> Result<api::GetBatchStatusAsSenderResponse, RpcStatus> {
+        if request.get_tx_list().is_empty() {
+            return Err(RpcStatus::with_message(
+                RpcStatusCode::INVALID_ARGUMENT,
+                "tx_list".into(),
+            ));
+        }
+
+        let tx_statuses = request
+            .get_tx_list()
+            .iter()
+            .map(|tx| {
+                self.get_tx_status_as_sender_impl(tx.clone())
+                    .map(|response| response.get_status())
+            })
+            .collect::<Result<Vec<_>, RpcStatus>>()?;
+
+        // The batch is verified once all of its transactions are, and failed as soon as
+        // any of them did.
+        let status = if tx_statuses
+            .iter()
+            .all(|status| *status == api::TxStatus::Verified)
+        {
+            api::TxStatus::Verified
+        } else {
+            tx_statuses
+                .iter()
+                .find(|status| {
+                    **status != api::TxStatus::Unknown && **status != api::TxStatus::Verified
+                })
+                .cloned()
+                .unwrap_or(api::TxStatus::Unknown)
+        };
+
+        let mut response = api::GetBatchStatusAsSenderResponse::new();
+        response.set_status(status);
+        response.set_tx_status_list(tx_statuses);
+        Ok(response)
+    }
+
     fn get_tx_status_as_receiver_impl(
         &mut self,
         request: api::GetTxStatusAsReceiverRequest,
@@ -1625,7 +2599,9 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
                                         &self.logger,
                                     )
                                 })?;
-                        let view_private_key = monitor_data.account_key.view_private_key();
+                        let view_private_key = monitor_data.view_private_key().map_err(|err| {
+                            rpc_internal_error("monitor_data.view_private_key", err, &self.logger)
+                        })?;
 
                         if request.get_receipt().get_confirmation_number().len() != 32 {
                             return Err(RpcStatus::with_message(
@@ -1703,14 +2679,13 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         let monitor_id = MonitorId::try_from(&request.monitor_id)
             .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
 
-        // We will use the AccountKey to compute the Address Code
-        let account_key = self
+        // We will use the monitor's keys to compute the Address Code
+        let monitor_data = self
             .mobilecoind_db
             .get_monitor_data(&monitor_id)
             .map_err(|err| {
                 rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
-            })?
-            .account_key;
+            })?;
 
         // Get all processed block data for the requested block.
         let processed_tx_outs = self
@@ -1732,7 +2707,11 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
                         .unwrap_or(api::ProcessedTxOutDirection::Invalid),
                 );
 
-                let subaddress = account_key.subaddress(src.subaddress_index);
+                let subaddress = monitor_data
+                    .subaddress(src.subaddress_index)
+                    .map_err(|err| {
+                        rpc_internal_error("monitor_data.subaddress", err, &self.logger)
+                    })?;
                 let mut wrapper = api::printable::PrintableWrapper::new();
                 wrapper.set_public_address((&subaddress).into());
                 let encoded = wrapper
@@ -1745,8 +2724,64 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
             .collect::<Result<Vec<_>, _>>()?;
 
         // Return response
-        let mut response = api::GetProcessedBlockResponse::new();
-        response.set_tx_outs(RepeatedField::from_vec(processed_tx_outs));
+        let mut response = api::GetProcessedBlockResponse::new();
+        response.set_tx_outs(RepeatedField::from_vec(processed_tx_outs));
+        Ok(response)
+    }
+
+    fn get_transaction_history_impl(
+        &mut self,
+        request: api::GetTransactionHistoryRequest,
+    ) -> Result<api::GetTransactionHistoryResponse, RpcStatus> {
+        // Get MonitorId from from the GRPC request.
+        let monitor_id = MonitorId::try_from(&request.monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Figure out where to start. A page token is the key of the last entry
+        // returned by a previous request, which we continue after.
+        let (start_key, exclude_start_key) = if request.page_token.is_empty() {
+            (
+                TxHistoryKey::first_in_block(&monitor_id, request.start_block_index),
+                false,
+            )
+        } else {
+            let key = TxHistoryKey::try_from(request.get_page_token())
+                .map_err(|err| rpc_invalid_arg_error("page_token", err, &self.logger))?;
+            if key.monitor_id != monitor_id {
+                return Err(RpcStatus::with_message(
+                    RpcStatusCode::INVALID_ARGUMENT,
+                    "page_token.monitor_id".into(),
+                ));
+            }
+            (key, true)
+        };
+
+        let max_entries = match request.max_entries as usize {
+            0 => MAX_TX_HISTORY_ENTRIES_PER_REQUEST,
+            n => n.min(MAX_TX_HISTORY_ENTRIES_PER_REQUEST),
+        };
+
+        let entries = self
+            .mobilecoind_db
+            .get_tx_history(&start_key, exclude_start_key, max_entries)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_tx_history", err, &self.logger)
+            })?;
+
+        let next_page_token = match entries.last() {
+            Some(entry) => entry.key(&monitor_id).to_vec(),
+            None => request.page_token.clone(),
+        };
+
+        let entry_list = entries
+            .iter()
+            .map(api::TransactionHistoryEntry::from)
+            .collect();
+
+        // Return response
+        let mut response = api::GetTransactionHistoryResponse::new();
+        response.set_entry_list(RepeatedField::from_vec(entry_list));
+        response.set_next_page_token(next_page_token);
         Ok(response)
     }
 
@@ -1786,6 +2821,118 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         Ok(response)
     }
 
+    fn subscribe_monitor_events_impl(
+        &mut self,
+        ctx: RpcContext,
+        request: api::SubscribeMonitorEventsRequest,
+        mut sink: ServerStreamingSink<api::MonitorEvent>,
+    ) {
+        let logger = rpc_logger(&ctx, &self.logger);
+
+        // Get MonitorId from from the GRPC request.
+        let stream = MonitorId::try_from(&request.monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &logger))
+            .and_then(|monitor_id| {
+                if request.start_cursor.is_empty() {
+                    MonitorEventStream::new(
+                        self.ledger_db.clone(),
+                        self.mobilecoind_db.clone(),
+                        monitor_id,
+                        request.start_block_index,
+                    )
+                    .map_err(|err| rpc_internal_error("MonitorEventStream.new", err, &logger))
+                } else {
+                    MonitorEventStream::resume(
+                        self.ledger_db.clone(),
+                        self.mobilecoind_db.clone(),
+                        monitor_id,
+                        &request.start_cursor,
+                    )
+                    .map_err(|err| match err {
+                        Error::InvalidArgument(..) => {
+                            rpc_invalid_arg_error("start_cursor", err, &logger)
+                        }
+                        err => rpc_internal_error("MonitorEventStream.resume", err, &logger),
+                    })
+                }
+            });
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(status) => {
+                let resp = sink
+                    .fail(status)
+                    .map_err(move |err| log::error!(logger, "failed to reply: {:?}", err))
+                    .map(|_| ());
+                ctx.spawn(resp);
+                return;
+            }
+        };
+
+        // Waiting for events blocks, so it happens on a dedicated thread that hands
+        // events over to the grpc completion queue. Sending to the client fails once
+        // it went away, which closes the channel and makes the thread exit. While
+        // there are no new events, the last caught up event is sent again every
+        // keepalive interval, so that this also happens for idle clients.
+        let (mut sender, mut receiver) = mpsc::channel(MONITOR_EVENTS_CHANNEL_SIZE);
+        let thread_logger = logger.clone();
+        let spawn_result = std::thread::Builder::new()
+            .name("MonitorEvents".into())
+            .spawn(move || {
+                let mut last_sent = Instant::now();
+                loop {
+                    let mut items: Vec<_> = match stream.next_events(MONITOR_EVENTS_POLL_INTERVAL) {
+                        Ok(events) => events.into_iter().map(Ok).collect(),
+                        Err(err) => vec![Err(rpc_internal_error(
+                            "MonitorEventStream.next_events",
+                            err,
+                            &thread_logger,
+                        ))],
+                    };
+                    if items.is_empty() && last_sent.elapsed() >= MONITOR_EVENTS_KEEPALIVE_INTERVAL
+                    {
+                        items.extend(stream.keepalive_event().map(Ok));
+                    }
+                    for item in items {
+                        let is_err = item.is_err();
+                        if block_on(sender.send(item)).is_err() || is_err {
+                            return;
+                        }
+                        last_sent = Instant::now();
+                    }
+                    if sender.is_closed() {
+                        return;
+                    }
+                }
+            });
+        if let Err(err) = spawn_result {
+            let resp = sink
+                .fail(rpc_internal_error("thread.spawn", err, &logger))
+                .map_err(move |err| log::error!(logger, "failed to reply: {:?}", err))
+                .map(|_| ());
+            ctx.spawn(resp);
+            return;
+        }
+
+        ctx.spawn(async move {
+            while let Some(item) = receiver.next().await {
+                let result = match item {
+                    Ok(event) => sink.send((event, WriteFlags::default())).await,
+                    Err(status) => {
+                        if let Err(err) = sink.fail(status).await {
+                            log::error!(logger, "failed to reply: {:?}", err);
+                        }
+                        return;
+                    }
+                };
+                if let Err(err) = result {
+                    log::debug!(logger, "Monitor event subscriber went away: {:?}", err);
+                    return;
+                }
+            }
+            let _ = sink.close().await;
+        });
+    }
+
     fn get_balance_impl(
         &mut self,
         request: api::GetBalanceRequest,
@@ -1802,13 +2949,19 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
                 rpc_internal_error("mobilecoind_db.get_utxos_for_subaddress", err, &self.logger)
             })?;
 
-        // Sum them up.
-        let balance = utxos
+        // Sum them up, keeping track of the part held in utxos whose spent status is
+        // unknown.
+        let (balance, unknown_spent_status_balance) = utxos
             .iter()
             // Filter only to the requested token id.
             .filter(|utxo| utxo.token_id == request.token_id)
-            .map(|utxo| utxo.value as u128)
-            .sum::<u128>();
+            .fold((0u128, 0u128), |(balance, unknown), utxo| {
+                if utxo.key_image_unknown {
+                    (balance + utxo.value as u128, unknown + utxo.value as u128)
+                } else {
+                    (balance + utxo.value as u128, unknown)
+                }
+            });
 
         // It's possible the balance does not fit into a u64.
         if balance > u64::max_value().into() {
@@ -1824,6 +2977,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         // Return response.
         let mut response = api::GetBalanceResponse::new();
         response.set_balance(balance as u64);
+        response.set_unknown_spent_status_balance(unknown_spent_status_balance as u64);
         Ok(response)
     }
 
@@ -1835,6 +2989,15 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         let sender_monitor_id = MonitorId::try_from(&request.sender_monitor_id)
             .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
 
+        // Get monitor data for this monitor.
+        let sender_monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&sender_monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+        self.check_monitor_can_spend(&sender_monitor_data)?;
+
         // Get all utxos for this monitor id.
         let mut utxos = self
             .mobilecoind_db
@@ -1860,6 +3023,8 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
                     .map_err(|err| rpc_internal_error("outlay.try_from", err, &self.logger))
             })
             .collect::<Result<Vec<Outlay>, RpcStatus>>()?;
+        let memo_builder =
+            self.get_outlay_memo_builder(&sender_monitor_data, request.get_outlay_list())?;
 
         // Set change address to sender address unless it has been overridden
         let change_subaddress = if request.override_change_subaddress {
@@ -1869,6 +3034,8 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         };
 
         // Attempt to construct a transaction.
+        let utxo_selection_strategy: Box<dyn UtxoSelectionStrategy> =
+            request.get_utxo_selection_strategy().into();
         let tx_proposal = self
             .transactions_manager
             .build_transaction(
@@ -1877,10 +3044,11 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
                 change_subaddress,
                 &utxos,
                 &outlays,
+                utxo_selection_strategy.as_ref(),
                 &self.get_last_block_infos(),
                 request.fee,
                 request.tombstone,
-                None,
+                memo_builder,
             )
             .map_err(|err| {
                 rpc_internal_error("transactions_manager.build_transaction", err, &self.logger)
@@ -1901,6 +3069,115 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         Ok(response)
     }
 
+    fn send_batch_payment_impl(
+        &mut self,
+        request: api::SendBatchPaymentRequest,
+    ) -> Result<api::SendBatchPaymentResponse, RpcStatus> {
+        // Get sender monitor id from request.
+        let sender_monitor_id = MonitorId::try_from(&request.sender_monitor_id)
+            .map_err(|err| rpc_internal_error("monitor_id.try_from.bytes", err, &self.logger))?;
+
+        // Get monitor data for this monitor.
+        let sender_monitor_data = self
+            .mobilecoind_db
+            .get_monitor_data(&sender_monitor_id)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_monitor_data", err, &self.logger)
+            })?;
+        self.check_monitor_can_spend(&sender_monitor_data)?;
+
+        // Get all utxos for this monitor id.
+        let mut utxos = self
+            .mobilecoind_db
+            .get_utxos_for_subaddress(&sender_monitor_id, request.sender_subaddress)
+            .map_err(|err| {
+                rpc_internal_error("mobilecoind_db.get_utxos_for_subaddress", err, &self.logger)
+            })?;
+
+        // Optionally filter for max value.
+        if request.max_input_utxo_value > 0 {
+            utxos.retain(|utxo| utxo.value <= request.max_input_utxo_value);
+        }
+
+        // Filter for requested token id.
+        utxos.retain(|utxo| utxo.token_id == request.token_id);
+
+        // Get the list of outlays, with their memos.
+        let outlays: Vec<(Outlay, OutlayMemo)> = request
+            .get_outlay_list()
+            .iter()
+            .map(|outlay_proto| {
+                Outlay::try_from(outlay_proto)
+                    .map(|outlay| (outlay, OutlayMemo::from(outlay_proto.get_memo())))
+                    .map_err(|err| rpc_internal_error("outlay.try_from", err, &self.logger))
+            })
+            .collect::<Result<Vec<_>, RpcStatus>>()?;
+
+        // Set change address to sender address unless it has been overridden
+        let change_subaddress = if request.override_change_subaddress {
+            request.change_subaddress
+        } else {
+            request.sender_subaddress
+        };
+
+        // Attempt to construct the transactions. Nothing gets submitted unless all of
+        // them could be built.
+        let utxo_selection_strategy: Box<dyn UtxoSelectionStrategy> =
+            request.get_utxo_selection_strategy().into();
+        let tx_proposals = self
+            .transactions_manager
+            .build_batch_transactions(
+                &sender_monitor_id,
+                TokenId::from(request.token_id),
+                change_subaddress,
+                &utxos,
+                &outlays,
+                utxo_selection_strategy.as_ref(),
+                &self.get_last_block_infos(),
+                request.fee,
+                request.tombstone,
+            )
+            .map_err(|err| {
+                rpc_internal_error(
+                    "transactions_manager.build_batch_transactions",
+                    err,
+                    &self.logger,
+                )
+            })?;
+
+        // Submit transactions. They spend disjoint inputs, so a failure to submit one
+        // of them does not affect the others, and the caller needs the receipts of
+        // those that were submitted.
+        let mut response = api::SendBatchPaymentResponse::new();
+        let mut first_error = None;
+        for tx_proposal in tx_proposals.iter() {
+            let proto_tx_proposal = api::TxProposal::from(tx_proposal);
+
+            let mut submit_tx_request = api::SubmitTxRequest::new();
+            submit_tx_request.set_tx_proposal(proto_tx_proposal.clone());
+
+            let mut tx_result = api::BatchTxResult::new();
+            tx_result.set_tx_proposal(proto_tx_proposal.clone());
+            match self.submit_tx_impl(submit_tx_request) {
+                Ok(submit_tx_response) => {
+                    tx_result.set_submit_tx_response(submit_tx_response.clone());
+                    response.mut_tx_list().push(submit_tx_response);
+                    response.mut_tx_proposal_list().push(proto_tx_proposal);
+                }
+                Err(status) => {
+                    tx_result.set_error(status.message().to_string());
+                    first_error.get_or_insert(status);
+                }
+            }
+            response.mut_tx_result_list().push(tx_result);
+        }
+
+        match first_error {
+            Some(status) if response.get_tx_list().is_empty() => Err(status),
+            _ => Ok(response),
+        }
+    }
+
     fn pay_address_code_impl(
         &mut self,
         request: api::PayAddressCodeRequest,
@@ -1934,6 +3211,7 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
         send_payment_request.set_override_change_subaddress(request.override_change_subaddress);
         send_payment_request.set_change_subaddress(request.change_subaddress);
         send_payment_request.set_token_id(request.token_id);
+        send_payment_request.set_utxo_selection_strategy(request.get_utxo_selection_strategy());
 
         self.send_payment_impl(send_payment_request)
     }
@@ -2029,7 +3307,10 @@ impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolve
 }
 
 macro_rules! build_api {
-    ($( $service_function_name:ident $service_request_type:ident $service_response_type:ident $service_function_impl:ident $(,)?)+)
+    (
+        $( $service_function_name:ident $service_request_type:ident $service_response_type:ident $service_function_impl:ident $(,)?)+
+        $( ; streaming $( $streaming_function_name:ident $streaming_request_type:ident $streaming_response_type:ident $streaming_function_impl:ident $(,)?)+ )?
+    )
     =>
     (
         impl<T: BlockchainConnection + UserTxConnection + 'static, FPR: FogPubkeyResolver> MobilecoindApi for ServiceApi<T, FPR> {
@@ -2049,6 +3330,17 @@ macro_rules! build_api {
                     )
                 }
             )+
+
+            $($(
+                fn $streaming_function_name(
+                    &mut self,
+                    ctx: RpcContext,
+                    request: api::$streaming_request_type,
+                    sink: ServerStreamingSink<api::$streaming_response_type>,
+                ) {
+                    self.$streaming_function_impl(ctx, request, sink)
+                }
+            )+)?
         }
     );
 }
@@ -2060,6 +3352,8 @@ build_api! {
     get_monitor_list Empty GetMonitorListResponse get_monitor_list_impl,
     get_monitor_status GetMonitorStatusRequest GetMonitorStatusResponse get_monitor_status_impl,
     get_unspent_tx_out_list GetUnspentTxOutListRequest GetUnspentTxOutListResponse get_unspent_tx_out_list_impl,
+    import_key_images ImportKeyImagesRequest ImportKeyImagesResponse import_key_images_impl,
+    set_defragmentation_policy SetDefragmentationPolicyRequest Empty set_defragmentation_policy_impl,
 
     // Utilities
     generate_root_entropy Empty GenerateRootEntropyResponse generate_root_entropy_impl,
@@ -2080,24 +3374,38 @@ build_api! {
     get_mixins GetMixinsRequest GetMixinsResponse get_mixins_impl,
     get_membership_proofs GetMembershipProofsRequest GetMembershipProofsResponse get_membership_proofs_impl,
     generate_tx GenerateTxRequest GenerateTxResponse generate_tx_impl,
+    estimate_payment EstimatePaymentRequest EstimatePaymentResponse estimate_payment_impl,
     generate_optimization_tx GenerateOptimizationTxRequest GenerateOptimizationTxResponse generate_optimization_tx_impl,
     generate_transfer_code_tx GenerateTransferCodeTxRequest GenerateTransferCodeTxResponse generate_transfer_code_tx_impl,
     generate_tx_from_tx_out_list GenerateTxFromTxOutListRequest GenerateTxFromTxOutListResponse generate_tx_from_tx_out_list_impl,
     generate_burn_redemption_tx GenerateBurnRedemptionTxRequest GenerateBurnRedemptionTxResponse generate_burn_redemption_tx_impl,
     submit_tx SubmitTxRequest SubmitTxResponse submit_tx_impl,
 
+    // Signed contingent inputs
+    generate_signed_contingent_input GenerateSignedContingentInputRequest GenerateSignedContingentInputResponse generate_signed_contingent_input_impl,
+    inspect_signed_contingent_input InspectSignedContingentInputRequest InspectSignedContingentInputResponse inspect_signed_contingent_input_impl,
+    fill_signed_contingent_input FillSignedContingentInputRequest FillSignedContingentInputResponse fill_signed_contingent_input_impl,
+    generate_gift_code_funding_tx GenerateGiftCodeFundingTxRequest GenerateGiftCodeFundingTxResponse generate_gift_code_funding_tx_impl,
+    create_gift_code CreateGiftCodeRequest CreateGiftCodeResponse create_gift_code_impl,
+    get_gift_code_status GetGiftCodeStatusRequest GetGiftCodeStatusResponse get_gift_code_status_impl,
+    generate_gift_code_claim_tx GenerateGiftCodeClaimTxRequest GenerateGiftCodeClaimTxResponse generate_gift_code_claim_tx_impl,
+    generate_gift_code_cancellation_tx GenerateGiftCodeCancellationTxRequest GenerateGiftCodeCancellationTxResponse generate_gift_code_cancellation_tx_impl,
+
     // Databases
     get_ledger_info Empty GetLedgerInfoResponse get_ledger_info_impl,
     get_block_info GetBlockInfoRequest GetBlockInfoResponse get_block_info_impl,
     get_block GetBlockRequest GetBlockResponse get_block_impl,
     get_tx_status_as_sender SubmitTxResponse GetTxStatusAsSenderResponse get_tx_status_as_sender_impl,
+    get_batch_status_as_sender GetBatchStatusAsSenderRequest GetBatchStatusAsSenderResponse get_batch_status_as_sender_impl,
     get_tx_status_as_receiver GetTxStatusAsReceiverRequest GetTxStatusAsReceiverResponse get_tx_status_as_receiver_impl,
     get_processed_block GetProcessedBlockRequest GetProcessedBlockResponse get_processed_block_impl,
+    get_transaction_history GetTransactionHistoryRequest GetTransactionHistoryResponse get_transaction_history_impl,
     get_block_index_by_tx_pub_key GetBlockIndexByTxPubKeyRequest GetBlockIndexByTxPubKeyResponse get_block_index_by_tx_pub_key_impl,
 
     // Convenience calls
     get_balance GetBalanceRequest GetBalanceResponse get_balance_impl,
     send_payment SendPaymentRequest SendPaymentResponse send_payment_impl,
+    send_batch_payment SendBatchPaymentRequest SendBatchPaymentResponse send_batch_payment_impl,
     pay_address_code PayAddressCodeRequest SendPaymentResponse pay_address_code_impl,
 
     // Network status
@@ -2108,6 +3416,11 @@ build_api! {
     unlock_db UnlockDbRequest Empty unlock_db_impl,
 
     get_version Empty MobilecoindVersionResponse get_version_impl,
+
+    ; streaming
+
+    // Events
+    subscribe_monitor_events SubscribeMonitorEventsRequest MonitorEvent subscribe_monitor_events_impl,
 }
 
 #[cfg(test)]
@@ -2115,7 +3428,7 @@ build_api! {
 mod test {
     use super::*;
     use crate::{
-        payments::DEFAULT_NEW_TX_BLOCK_ATTEMPTS,
+        payments::{DEFAULT_NEW_TX_BLOCK_ATTEMPTS, MAX_OUTLAYS_PER_TX},
         subaddress_store::SubaddressSPKId,
         test_utils::{
             self, add_block_to_ledger, add_txos_to_ledger, get_testing_environment,
@@ -2172,7 +3485,9 @@ mod test {
         .expect("failed to create data");
 
         let mut request = api::AddMonitorRequest::new();
-        request.set_account_key(mc_api::external::AccountKey::from(&data.account_key));
+        request.set_account_key(mc_api::external::AccountKey::from(
+            data.account_key.as_ref().unwrap(),
+        ));
         request.set_first_subaddress(data.first_subaddress);
         request.set_num_subaddresses(data.num_subaddresses);
         request.set_first_block(data.first_block);
@@ -2186,7 +3501,7 @@ mod test {
         // Compare the MonitorId we got back to the value we expected.
         let monitor_id = MonitorId::try_from(&response.monitor_id)
             .expect("failed to convert response to MonitorId");
-        let expected_monitor_id = MonitorId::from(&data);
+        let expected_monitor_id = MonitorId::try_from(&data).unwrap();
 
         assert_eq!(expected_monitor_id, monitor_id);
 
@@ -2206,6 +3521,36 @@ mod test {
         assert!(!repeated_response.is_new);
     }
 
+    #[test_with_logger]
+    fn test_add_monitor_impl_pruned_ledger(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([20u8; 32]);
+
+        // Three random recipients and no monitors.
+        let (mut ledger_db, _mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(BLOCK_VERSION, 3, &[], &[], logger, &mut rng);
+        ledger_db.prune_tx_outs(5, 2, &mut rng).unwrap();
+
+        let account_key = AccountKey::random(&mut rng);
+        let mut request = api::AddMonitorRequest::new();
+        request.set_account_key(mc_api::external::AccountKey::from(&account_key));
+        request.set_first_subaddress(DEFAULT_SUBADDRESS_INDEX);
+        request.set_num_subaddresses(1);
+
+        // Monitors can not start scanning in the pruned blocks.
+        request.set_first_block(4);
+        match client.add_monitor(&request) {
+            Ok(_) => panic!("Should've returned an error"),
+            Err(GrpcError::RpcFailure(rpc_status)) => {
+                assert_eq!(rpc_status.code(), RpcStatusCode::FAILED_PRECONDITION);
+            }
+            Err(err) => panic!("Unexpected error {:?}", err),
+        }
+
+        request.set_first_block(5);
+        let response = client.add_monitor(&request).expect("failed to add monitor");
+        assert!(response.is_new);
+    }
+
     #[test_with_logger]
     fn test_remove_monitor_impl(logger: Logger) {
         let mut rng: StdRng = SeedableRng::from_seed([22u8; 32]);
@@ -2336,9 +3681,10 @@ mod test {
 
         // Verify the data we got matches what we expected
         assert_eq!(
-            data.account_key,
-            AccountKey::try_from(status.account_key.as_ref().unwrap()).unwrap(),
+            data.account_key.as_ref().unwrap(),
+            &AccountKey::try_from(status.account_key.as_ref().unwrap()).unwrap(),
         );
+        assert!(!status.is_view_only);
         assert_eq!(status.first_subaddress, data.first_subaddress);
         assert_eq!(status.num_subaddresses, data.num_subaddresses);
         assert_eq!(status.first_block, data.first_block);
@@ -2473,6 +3819,7 @@ mod test {
                     token_id: *amount.token_id,
                     attempted_spend_height: 0,
                     attempted_spend_tombstone: 0,
+                    key_image_unknown: false,
                 }
             })
             .collect();
@@ -3173,6 +4520,7 @@ mod test {
                     attempted_spend_height: 0,
                     attempted_spend_tombstone: 0,
                     token_id: *Mob::ID,
+                    key_image_unknown: false,
                 }
             })
             .collect();
@@ -3299,52 +4647,427 @@ mod test {
                 &[KeyImage::from(101)],
                 &mut rng,
             )
-            .unwrap();
+            .unwrap();
+
+            wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+            let mut request = api::GetProcessedBlockRequest::new();
+            request.set_monitor_id(monitor_id.to_vec());
+            request.set_block(num_blocks + 1);
+
+            let response = client
+                .get_processed_block(&request)
+                .expect("failed to get processed block");
+
+            let tx_outs = response.get_tx_outs();
+            assert_eq!(tx_outs.len(), 1);
+
+            let tx_out = &tx_outs[0];
+            assert_eq!(tx_out.get_monitor_id().to_vec(), monitor_id.to_vec());
+            assert_eq!(tx_out.get_value(), 102030);
+            assert_eq!(
+                tx_out.get_direction(),
+                api::ProcessedTxOutDirection::Received
+            );
+            assert_eq!(tx_out.get_token_id(), 2);
+        }
+
+        // Query a block that will never get processed since its before the monitor's
+        // first block.
+        let mut request = api::GetProcessedBlockRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        request.set_block(0);
+
+        assert!(client.get_processed_block(&request).is_err());
+
+        // Query a block that hasn't been processed yet.
+        let mut request = api::GetProcessedBlockRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        request.set_block(num_blocks + 2);
+
+        assert!(client.get_processed_block(&request).is_err());
+
+        // Query with an unknown monitor id.
+        let mut request = api::GetProcessedBlockRequest::new();
+        request.set_monitor_id(vec![1; 32]);
+        request.set_block(1);
+
+        assert!(client.get_processed_block(&request).is_err());
+    }
+
+    #[test_with_logger]
+    fn test_get_transaction_history(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
+
+        let account_key = AccountKey::random(&mut rng);
+        let monitor_data = MonitorData::new(
+            account_key.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (mut ledger_db, mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[account_key.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+
+        // Insert into database.
+        let monitor_id = mobilecoind_db.add_monitor(&monitor_data).unwrap();
+
+        // Allow the new monitor to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        let num_blocks = ledger_db.num_blocks().expect("failed getting num blocks");
+
+        // Page through the history, three entries at a time.
+        let mut entries = Vec::new();
+        let mut page_token = Vec::new();
+        loop {
+            let mut request = api::GetTransactionHistoryRequest::new();
+            request.set_monitor_id(monitor_id.to_vec());
+            request.set_max_entries(3);
+            request.set_page_token(page_token.clone());
+            let response = client
+                .get_transaction_history(&request)
+                .expect("failed to get transaction history");
+
+            assert!(response.get_entry_list().len() <= 3);
+            page_token = response.get_next_page_token().to_vec();
+            if response.get_entry_list().is_empty() {
+                break;
+            }
+            entries.extend(response.get_entry_list().iter().cloned());
+        }
+
+        // We expect one received entry per block, in block order.
+        assert_eq!(entries.len(), num_blocks as usize);
+        for (block_index, entry) in entries.iter().enumerate() {
+            let tx_out = &ledger_db
+                .get_block_contents(block_index as u64)
+                .unwrap()
+                .outputs[3];
+
+            assert_eq!(entry.get_block_index(), block_index as u64);
+            assert_eq!(
+                entry.get_direction(),
+                api::ProcessedTxOutDirection::Received
+            );
+            assert_eq!(entry.get_subaddress_index(), 0);
+            assert_eq!(entry.get_public_key(), &(&tx_out.public_key).into());
+            assert_eq!(entry.get_value(), DEFAULT_PER_RECIPIENT_AMOUNT);
+            assert_eq!(entry.get_token_id(), *Mob::ID);
+        }
+
+        // Starting from a block index skips earlier entries.
+        let mut request = api::GetTransactionHistoryRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        request.set_start_block_index(num_blocks - 2);
+        let response = client.get_transaction_history(&request).unwrap();
+        assert_eq!(
+            response.get_entry_list(),
+            &entries[num_blocks as usize - 2..]
+        );
+
+        // Spend the first two utxos, and see that polling with the last page token
+        // picks up the new entries.
+        let recipient = AccountKey::random(&mut rng).default_subaddress();
+        add_block_to_ledger(
+            &mut ledger_db,
+            BLOCK_VERSION,
+            &[recipient],
+            Amount::new(DEFAULT_PER_RECIPIENT_AMOUNT, Mob::ID),
+            &[
+                KeyImage::try_from(entries[0].get_key_image()).unwrap(),
+                KeyImage::try_from(entries[1].get_key_image()).unwrap(),
+            ],
+            &mut rng,
+        )
+        .unwrap();
+
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        let mut request = api::GetTransactionHistoryRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        request.set_page_token(page_token);
+        let response = client.get_transaction_history(&request).unwrap();
+
+        let spent = response.get_entry_list();
+        assert_eq!(spent.len(), 2);
+        for entry in spent {
+            assert_eq!(entry.get_block_index(), num_blocks);
+            assert_eq!(entry.get_direction(), api::ProcessedTxOutDirection::Spent);
+            assert!(entries[..2]
+                .iter()
+                .any(|received| received.get_key_image() == entry.get_key_image()));
+        }
+
+        // A page token belonging to a different monitor is rejected.
+        let other_monitor_id = MonitorId::try_from(
+            &MonitorData::new(
+                AccountKey::random(&mut rng),
+                0,  // first_subaddress
+                1,  // num_subaddresses
+                0,  // first_block
+                "", // name
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut request = api::GetTransactionHistoryRequest::new();
+        request.set_monitor_id(other_monitor_id.to_vec());
+        request.set_page_token(response.get_next_page_token().to_vec());
+        assert!(client.get_transaction_history(&request).is_err());
+    }
 
-            wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+    #[test_with_logger]
+    fn test_subscribe_monitor_events(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([24u8; 32]);
 
-            let mut request = api::GetProcessedBlockRequest::new();
-            request.set_monitor_id(monitor_id.to_vec());
-            request.set_block(num_blocks + 1);
+        let account_key = AccountKey::random(&mut rng);
+        let monitor_data = MonitorData::new(
+            account_key.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
 
-            let response = client
-                .get_processed_block(&request)
-                .expect("failed to get processed block");
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (mut ledger_db, mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[account_key.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
 
-            let tx_outs = response.get_tx_outs();
-            assert_eq!(tx_outs.len(), 1);
+        // Insert into database.
+        let monitor_id = mobilecoind_db.add_monitor(&monitor_data).unwrap();
 
-            let tx_out = &tx_outs[0];
-            assert_eq!(tx_out.get_monitor_id().to_vec(), monitor_id.to_vec());
-            assert_eq!(tx_out.get_value(), 102030);
+        // Allow the new monitor to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        let num_blocks = ledger_db.num_blocks().expect("failed getting num blocks");
+
+        let mut request = api::SubscribeMonitorEventsRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        let mut events = client
+            .subscribe_monitor_events(&request)
+            .expect("failed to subscribe to monitor events");
+        let mut next_event = move || block_on(events.next()).unwrap().unwrap();
+
+        // We expect one received event per block, followed by a caught up event.
+        let mut received = Vec::new();
+        for block_index in 0..num_blocks {
+            let event = next_event();
             assert_eq!(
-                tx_out.get_direction(),
-                api::ProcessedTxOutDirection::Received
+                event.get_event_type(),
+                api::MonitorEventType::MonitorEventReceived
             );
-            assert_eq!(tx_out.get_token_id(), 2);
+            assert_eq!(event.get_block_index(), block_index);
+            assert_eq!(event.get_entry().get_block_index(), block_index);
+            assert_eq!(event.get_entry().get_value(), DEFAULT_PER_RECIPIENT_AMOUNT);
+            received.push(event);
         }
 
-        // Query a block that will never get processed since its before the monitor's
-        // first block.
-        let mut request = api::GetProcessedBlockRequest::new();
-        request.set_monitor_id(monitor_id.to_vec());
-        request.set_block(0);
+        let event = next_event();
+        assert_eq!(
+            event.get_event_type(),
+            api::MonitorEventType::MonitorEventCaughtUp
+        );
+        assert_eq!(event.get_block_index(), num_blocks);
+        assert!(!event.has_entry());
 
-        assert!(client.get_processed_block(&request).is_err());
+        // Receive and spend in a new block. The events show up as the monitor
+        // processes it.
+        add_block_to_ledger(
+            &mut ledger_db,
+            BLOCK_VERSION,
+            &[account_key.default_subaddress()],
+            Amount::new(DEFAULT_PER_RECIPIENT_AMOUNT, Mob::ID),
+            &[KeyImage::try_from(received[0].get_entry().get_key_image()).unwrap()],
+            &mut rng,
+        )
+        .unwrap();
 
-        // Query a block that hasn't been processed yet.
-        let mut request = api::GetProcessedBlockRequest::new();
+        let mut new_events = vec![next_event(), next_event()];
+        new_events.sort_by_key(|event| event.get_event_type() as i32);
+        assert_eq!(
+            new_events[0].get_event_type(),
+            api::MonitorEventType::MonitorEventReceived
+        );
+        assert_eq!(
+            new_events[1].get_event_type(),
+            api::MonitorEventType::MonitorEventSpent
+        );
+        assert_eq!(
+            new_events[1].get_entry().get_public_key(),
+            received[0].get_entry().get_public_key()
+        );
+        for event in new_events.iter() {
+            assert_eq!(event.get_block_index(), num_blocks);
+        }
+
+        let event = next_event();
+        assert_eq!(
+            event.get_event_type(),
+            api::MonitorEventType::MonitorEventCaughtUp
+        );
+        assert_eq!(event.get_block_index(), num_blocks + 1);
+
+        // Resuming from the last block only replays the events of that block.
+        let mut request = api::SubscribeMonitorEventsRequest::new();
         request.set_monitor_id(monitor_id.to_vec());
-        request.set_block(num_blocks + 2);
+        request.set_start_block_index(num_blocks);
+        let mut events = client.subscribe_monitor_events(&request).unwrap();
+        let mut resumed_events = vec![
+            block_on(events.next()).unwrap().unwrap(),
+            block_on(events.next()).unwrap().unwrap(),
+        ];
+        resumed_events.sort_by_key(|event| event.get_event_type() as i32);
+        assert_eq!(resumed_events, new_events);
 
-        assert!(client.get_processed_block(&request).is_err());
+        let event = block_on(events.next()).unwrap().unwrap();
+        assert_eq!(
+            event.get_event_type(),
+            api::MonitorEventType::MonitorEventCaughtUp
+        );
+        assert_eq!(event.get_block_index(), num_blocks + 1);
 
-        // Query with an unknown monitor id.
-        let mut request = api::GetProcessedBlockRequest::new();
-        request.set_monitor_id(vec![1; 32]);
-        request.set_block(1);
+        // Subscribing to an unknown monitor fails.
+        let mut request = api::SubscribeMonitorEventsRequest::new();
+        request.set_monitor_id(vec![3; 32]);
+        let mut events = client.subscribe_monitor_events(&request).unwrap();
+        assert!(block_on(events.next()).unwrap().is_err());
+    }
 
-        assert!(client.get_processed_block(&request).is_err());
+    #[test_with_logger]
+    // Resuming a subscription from the cursor of an event should deliver exactly
+    // the events that followed it, including after a caught up event.
+    fn test_subscribe_monitor_events_resume_from_cursor(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([25u8; 32]);
+
+        let account_key = AccountKey::random(&mut rng);
+        let monitor_data = MonitorData::new(
+            account_key.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (mut ledger_db, mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[account_key.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+        let monitor_id = mobilecoind_db.add_monitor(&monitor_data).unwrap();
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+        let num_blocks = ledger_db.num_blocks().unwrap();
+
+        let subscribe = |start_cursor: &[u8]| {
+            let mut request = api::SubscribeMonitorEventsRequest::new();
+            request.set_monitor_id(monitor_id.to_vec());
+            request.set_start_cursor(start_cursor.to_vec());
+            let mut events = client.subscribe_monitor_events(&request).unwrap();
+            move || block_on(events.next()).unwrap().unwrap()
+        };
+
+        // Read everything up to the first caught up event, then disconnect.
+        let mut next_event = subscribe(&[]);
+        let received: Vec<_> = (0..num_blocks).map(|_| next_event()).collect();
+        let caught_up = next_event();
+        assert_eq!(
+            caught_up.get_event_type(),
+            api::MonitorEventType::MonitorEventCaughtUp
+        );
+        assert_eq!(caught_up.get_block_index(), num_blocks);
+        drop(next_event);
+
+        // Reconnect after the caught up event. Receive and spend in a new block.
+        let mut next_event = subscribe(caught_up.get_cursor());
+        add_block_to_ledger(
+            &mut ledger_db,
+            BLOCK_VERSION,
+            &[account_key.default_subaddress()],
+            Amount::new(DEFAULT_PER_RECIPIENT_AMOUNT, Mob::ID),
+            &[KeyImage::try_from(received[0].get_entry().get_key_image()).unwrap()],
+            &mut rng,
+        )
+        .unwrap();
+
+        // Both events of the new block are delivered, and the caught up event is
+        // not repeated.
+        let new_events = vec![next_event(), next_event()];
+        let mut event_types: Vec<_> = new_events
+            .iter()
+            .map(|event| event.get_event_type())
+            .collect();
+        event_types.sort_by_key(|event_type| *event_type as i32);
+        assert_eq!(
+            event_types,
+            vec![
+                api::MonitorEventType::MonitorEventReceived,
+                api::MonitorEventType::MonitorEventSpent
+            ]
+        );
+        for event in new_events.iter() {
+            assert_eq!(event.get_block_index(), num_blocks);
+        }
+        let event = next_event();
+        assert_eq!(
+            event.get_event_type(),
+            api::MonitorEventType::MonitorEventCaughtUp
+        );
+        assert_eq!(event.get_block_index(), num_blocks + 1);
+        drop(next_event);
+
+        // Reconnecting in the middle of a block only skips the events that were
+        // handled.
+        let mut next_event = subscribe(new_events[0].get_cursor());
+        assert_eq!(next_event(), new_events[1]);
+        assert_eq!(next_event(), event);
+
+        // Cursors of another monitor, garbage cursors and cursors past the end of
+        // the ledger are rejected as invalid arguments.
+        let mut past_the_end_cursor = event.get_cursor().to_vec();
+        past_the_end_cursor[32..40].copy_from_slice(&(num_blocks + 10).to_be_bytes());
+        for (monitor_id, start_cursor) in [
+            (vec![3; 32], event.get_cursor().to_vec()),
+            (monitor_id.to_vec(), vec![0xff; 7]),
+            (monitor_id.to_vec(), vec![0xff; event.get_cursor().len()]),
+            (monitor_id.to_vec(), past_the_end_cursor),
+        ] {
+            let mut request = api::SubscribeMonitorEventsRequest::new();
+            request.set_monitor_id(monitor_id);
+            request.set_start_cursor(start_cursor);
+            let mut events = client.subscribe_monitor_events(&request).unwrap();
+            match block_on(events.next()).unwrap() {
+                Ok(_) => panic!("Should've returned an error"),
+                Err(GrpcError::RpcFailure(rpc_status)) => {
+                    assert_eq!(rpc_status.code(), RpcStatusCode::INVALID_ARGUMENT);
+                }
+                Err(err) => panic!("Unexpected error {:?}", err),
+            }
+        }
     }
 
     #[test_with_logger]
@@ -3400,6 +5123,48 @@ mod test {
         }
     }
 
+    #[test_with_logger]
+    /// Get mixins should only return TxOuts that were retained when pruning.
+    fn test_get_mixins_pruned_ledger(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([45u8; 32]);
+
+        // 3 random recipients and no monitors.
+        let (mut ledger_db, _mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(BLOCK_VERSION, 3, &[], &[], logger, &mut rng);
+        let num_blocks = ledger_db.num_blocks().unwrap();
+        ledger_db.prune_tx_outs(num_blocks, 2, &mut rng).unwrap();
+
+        let retained_hashes: HashSet<_> = (0..ledger_db.num_txos().unwrap())
+            .filter_map(|index| ledger_db.get_tx_out_by_index(index).ok())
+            .map(|tx_out| tx_out.hash())
+            .collect();
+        assert_eq!(
+            retained_hashes.len() as u64,
+            ledger_db.num_txos().unwrap() - ledger_db.num_pruned_tx_outs().unwrap()
+        );
+
+        // Requesting every retained TxOut should return exactly those.
+        {
+            let mut request = api::GetMixinsRequest::new();
+            request.set_num_mixins(retained_hashes.len() as u64);
+            let response = client.get_mixins(&request).unwrap();
+            let mixin_hashes: HashSet<_> = response
+                .get_mixins()
+                .iter()
+                .map(|mixin| TxOut::try_from(mixin.get_output()).unwrap().hash())
+                .collect();
+
+            assert_eq!(mixin_hashes, retained_hashes);
+        }
+
+        // Requesting more mixins than were retained should return an error.
+        {
+            let mut request = api::GetMixinsRequest::new();
+            request.set_num_mixins(retained_hashes.len() as u64 + 1);
+            assert!(client.get_mixins(&request).is_err());
+        }
+    }
+
     #[test_with_logger]
     /// Get mixins should not return an "excluded" TxOut.
     fn test_get_mixins_excluded(logger: Logger) {
@@ -3849,59 +5614,163 @@ mod test {
             assert!(client.generate_tx(&request).is_err());
         }
 
-        {
-            // Unrecognized monitor id
-            let sender = AccountKey::random(&mut rng);
-            let data = MonitorData::new(
-                sender, 0,  // first_subaddress
-                20, // num_subaddresses
-                0,  // first_block
-                "", // name
-            )
+        {
+            // Unrecognized monitor id
+            let sender = AccountKey::random(&mut rng);
+            let data = MonitorData::new(
+                sender, 0,  // first_subaddress
+                20, // num_subaddresses
+                0,  // first_block
+                "", // name
+            )
+            .unwrap();
+
+            let mut request = request.clone();
+            request.set_sender_monitor_id(MonitorId::try_from(&data).unwrap().to_vec());
+            assert!(client.generate_tx(&request).is_err());
+        }
+
+        {
+            // Subaddress index out of range
+            let mut request = request.clone();
+            request.set_change_subaddress(data.first_subaddress + data.num_subaddresses + 1);
+            assert!(client.generate_tx(&request).is_err());
+        }
+
+        {
+            // Junk input
+            let mut request = request.clone();
+            request.mut_input_list().push(api::UnspentTxOut::default());
+            assert!(client.generate_tx(&request).is_err());
+        }
+
+        {
+            // Attempt to spend more than we have
+            let num_blocks = ledger_db.num_blocks().unwrap();
+            let mut request = request.clone();
+            request.set_outlay_list(RepeatedField::from_vec(vec![api::Outlay::from(&Outlay {
+                receiver: receiver1.default_subaddress(),
+                value: test_utils::DEFAULT_PER_RECIPIENT_AMOUNT * num_blocks,
+            })]));
+            assert!(client.generate_tx(&request).is_err());
+        }
+
+        {
+            // Mixing input tokens (utxos has both Mob and TokenId(2))
+            let mut request = api::GenerateTxRequest::new();
+            request.set_sender_monitor_id(monitor_id.to_vec());
+            request.set_change_subaddress(0);
+            request.set_input_list(RepeatedField::from_vec(
+                utxos.iter().map(api::UnspentTxOut::from).collect(),
+            ));
+            request.set_outlay_list(RepeatedField::from_vec(
+                outlays.iter().map(api::Outlay::from).collect(),
+            ));
+            assert!(client.generate_tx(&request).is_err());
+        }
+    }
+
+    #[test_with_logger]
+    fn test_generate_tx_utxo_selection_strategy(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
+
+        let sender = AccountKey::random(&mut rng);
+        let data = MonitorData::new(
+            sender.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (mut ledger_db, mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[sender.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+
+        // Add a block with a utxo twice as large as the others.
+        add_block_to_ledger(
+            &mut ledger_db,
+            BLOCK_VERSION,
+            &[sender.default_subaddress()],
+            Amount::new(2 * test_utils::DEFAULT_PER_RECIPIENT_AMOUNT, Mob::ID),
+            &[KeyImage::from(101)],
+            &mut rng,
+        )
+        .unwrap();
+
+        // Insert into database.
+        let monitor_id = mobilecoind_db.add_monitor(&data).unwrap();
+
+        // Allow the new monitor to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        let utxos = mobilecoind_db
+            .get_utxos_for_subaddress(&monitor_id, 0)
             .unwrap();
 
-            let mut request = request.clone();
-            request.set_sender_monitor_id(MonitorId::from(&data).to_vec());
-            assert!(client.generate_tx(&request).is_err());
-        }
+        // Send exactly the value of one of the smaller utxos, after paying the fee.
+        let receiver = AccountKey::random(&mut rng);
+        let outlay = Outlay {
+            value: test_utils::DEFAULT_PER_RECIPIENT_AMOUNT - Mob::MINIMUM_FEE,
+            receiver: receiver.default_subaddress(),
+        };
 
-        {
-            // Subaddress index out of range
-            let mut request = request.clone();
-            request.set_change_subaddress(data.first_subaddress + data.num_subaddresses + 1);
-            assert!(client.generate_tx(&request).is_err());
-        }
+        let mut request = api::GenerateTxRequest::new();
+        request.set_sender_monitor_id(monitor_id.to_vec());
+        request.set_change_subaddress(0);
+        request.set_input_list(RepeatedField::from_vec(
+            utxos.iter().map(api::UnspentTxOut::from).collect(),
+        ));
+        request.set_outlay_list(RepeatedField::from_vec(vec![api::Outlay::from(&outlay)]));
 
+        // Largest first spends the large utxo, and needs a change output.
         {
-            // Junk input
-            let mut request = request.clone();
-            request.mut_input_list().push(api::UnspentTxOut::default());
-            assert!(client.generate_tx(&request).is_err());
+            request.set_utxo_selection_strategy(api::UtxoSelectionStrategy::LargestFirst);
+            let response = client.generate_tx(&request).unwrap();
+            let tx_proposal = response.get_tx_proposal();
+
+            assert_eq!(tx_proposal.get_input_list().len(), 1);
+            assert_eq!(
+                tx_proposal.get_input_list()[0].get_value(),
+                2 * test_utils::DEFAULT_PER_RECIPIENT_AMOUNT
+            );
+            assert_eq!(tx_proposal.get_tx().get_prefix().get_outputs().len(), 2);
         }
 
+        // Branch and bound finds the exact match, and needs no change output.
         {
-            // Attempt to spend more than we have
-            let num_blocks = ledger_db.num_blocks().unwrap();
-            let mut request = request.clone();
-            request.set_outlay_list(RepeatedField::from_vec(vec![api::Outlay::from(&Outlay {
-                receiver: receiver1.default_subaddress(),
-                value: test_utils::DEFAULT_PER_RECIPIENT_AMOUNT * num_blocks,
-            })]));
-            assert!(client.generate_tx(&request).is_err());
+            request.set_utxo_selection_strategy(api::UtxoSelectionStrategy::BranchAndBound);
+            let response = client.generate_tx(&request).unwrap();
+            let tx_proposal = response.get_tx_proposal();
+
+            assert_eq!(tx_proposal.get_input_list().len(), 1);
+            assert_eq!(
+                tx_proposal.get_input_list()[0].get_value(),
+                test_utils::DEFAULT_PER_RECIPIENT_AMOUNT
+            );
+            assert_eq!(tx_proposal.get_tx().get_prefix().get_outputs().len(), 1);
         }
 
+        // Random selection picks enough to cover the outlay and fee.
         {
-            // Mixing input tokens (utxos has both Mob and TokenId(2))
-            let mut request = api::GenerateTxRequest::new();
-            request.set_sender_monitor_id(monitor_id.to_vec());
-            request.set_change_subaddress(0);
-            request.set_input_list(RepeatedField::from_vec(
-                utxos.iter().map(api::UnspentTxOut::from).collect(),
-            ));
-            request.set_outlay_list(RepeatedField::from_vec(
-                outlays.iter().map(api::Outlay::from).collect(),
-            ));
-            assert!(client.generate_tx(&request).is_err());
+            request.set_utxo_selection_strategy(api::UtxoSelectionStrategy::Random);
+            let response = client.generate_tx(&request).unwrap();
+            let tx_proposal = response.get_tx_proposal();
+
+            let input_value: u64 = tx_proposal
+                .get_input_list()
+                .iter()
+                .map(|utxo| utxo.get_value())
+                .sum();
+            assert!(input_value >= test_utils::DEFAULT_PER_RECIPIENT_AMOUNT);
         }
     }
 
@@ -4071,28 +5940,333 @@ mod test {
         let (ledger_db, _mobilecoind_db, client, _server, _server_conn_manager) =
             get_testing_environment(BLOCK_VERSION, 3, &[], &[], logger.clone(), &mut rng);
 
-        // Grab the first TxOut of each block in the database and verify its index.
-        for block_index in 0..test_utils::GET_TESTING_ENVIRONMENT_NUM_BLOCKS as u64 {
-            let block_contents = ledger_db.get_block_contents(block_index).unwrap();
-            let tx_out_pub_key =
-                api::external::CompressedRistretto::from(&block_contents.outputs[0].public_key);
+        // Grab the first TxOut of each block in the database and verify its index.
+        for block_index in 0..test_utils::GET_TESTING_ENVIRONMENT_NUM_BLOCKS as u64 {
+            let block_contents = ledger_db.get_block_contents(block_index).unwrap();
+            let tx_out_pub_key =
+                api::external::CompressedRistretto::from(&block_contents.outputs[0].public_key);
+
+            let mut request = api::GetBlockIndexByTxPubKeyRequest::new();
+            request.set_tx_public_key(tx_out_pub_key);
+
+            let response = client.get_block_index_by_tx_pub_key(&request).unwrap();
+            assert_eq!(block_index, response.block);
+        }
+    }
+
+    #[test_with_logger]
+    fn test_generate_transfer_code_tx(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
+
+        let sender = AccountKey::random(&mut rng);
+        let data = MonitorData::new(
+            sender.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (mut ledger_db, mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[sender.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+
+        // Insert into database.
+        let monitor_id = mobilecoind_db.add_monitor(&data).unwrap();
+
+        // Allow the new monitor to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        // Get list of unspent tx outs
+        let utxos = mobilecoind_db
+            .get_utxos_for_subaddress(&monitor_id, 0)
+            .unwrap();
+        assert!(!utxos.is_empty());
+
+        // Call generate transfer code ctx.
+        let mut request = api::GenerateTransferCodeTxRequest::new();
+        request.set_sender_monitor_id(monitor_id.to_vec());
+        request.set_change_subaddress(0);
+        request.set_input_list(RepeatedField::from_vec(
+            utxos.iter().map(api::UnspentTxOut::from).collect(),
+        ));
+        request.set_value(1337);
+
+        let response = client.generate_transfer_code_tx(&request).unwrap();
+
+        // Test that the generated transaction can be picked up by mobilecoind.
+        {
+            let tx_proposal = TxProposal::try_from(response.get_tx_proposal()).unwrap();
+            let key_images = tx_proposal.tx.key_images();
+            let outputs = tx_proposal.tx.prefix.outputs;
+            add_txos_and_key_images_to_ledger(
+                &mut ledger_db,
+                BLOCK_VERSION,
+                outputs,
+                key_images,
+                &mut rng,
+            )
+            .unwrap();
+
+            // Use bip39 entropy to construct AccountKey.
+            let mnemonic =
+                Mnemonic::from_entropy(response.get_bip39_entropy(), Language::English).unwrap();
+            let key = mnemonic.derive_slip10_key(0);
+            let account_key = AccountKey::from(key);
+
+            // Add a monitor based on the entropy we received.
+            let monitor_data = MonitorData::new(
+                account_key,
+                DEFAULT_SUBADDRESS_INDEX, // first_subaddress
+                1,                        // num_subaddresses
+                0,                        // first_block
+                "",                       // name
+            )
+            .unwrap();
+
+            let monitor_id = mobilecoind_db.add_monitor(&monitor_data).unwrap();
+
+            // Wait for sync to complete.
+            wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+            // Get utxos for the new account and verify we only have one utxo.
+            let utxos = mobilecoind_db
+                .get_utxos_for_subaddress(&monitor_id, DEFAULT_SUBADDRESS_INDEX)
+                .unwrap();
+            assert_eq!(utxos.len(), 1);
+
+            let utxo = &utxos[0];
+
+            assert_eq!(utxo.value, 1337);
+            assert_eq!(
+                utxo.tx_out.public_key,
+                RistrettoPublic::try_from(response.get_tx_public_key())
+                    .unwrap()
+                    .into()
+            );
+        }
+    }
+
+    #[test_with_logger]
+    fn test_gift_code_lifecycle(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
+
+        let sender = AccountKey::random(&mut rng);
+        let sender_data = MonitorData::new(
+            sender.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        let receiver = AccountKey::random(&mut rng);
+        let receiver_data = MonitorData::new(
+            receiver.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (mut ledger_db, mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[sender.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+
+        // Insert into database.
+        let sender_monitor_id = mobilecoind_db.add_monitor(&sender_data).unwrap();
+        let receiver_monitor_id = mobilecoind_db.add_monitor(&receiver_data).unwrap();
+
+        // Allow the new monitors to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        // Helper for landing a TxProposal in the ledger.
+        let land_tx_proposal =
+            |ledger_db: &mut LedgerDB, rng: &mut StdRng, tx_proposal: &api::TxProposal| {
+                let tx_proposal = TxProposal::try_from(tx_proposal).unwrap();
+                add_txos_and_key_images_to_ledger(
+                    ledger_db,
+                    BLOCK_VERSION,
+                    tx_proposal.tx.prefix.outputs.clone(),
+                    tx_proposal.tx.key_images(),
+                    rng,
+                )
+                .unwrap();
+                wait_for_monitors(&mobilecoind_db, ledger_db, &logger);
+                tx_proposal
+            };
+
+        // Helper for decoding the memo of a TxOut.
+        let get_memo = |tx_out: &TxOut, account_key: &AccountKey| {
+            let shared_secret = get_tx_out_shared_secret(
+                account_key.view_private_key(),
+                &RistrettoPublic::try_from(&tx_out.public_key).unwrap(),
+            );
+            MemoType::try_from(&tx_out.e_memo.unwrap().decrypt(&shared_secret)).unwrap()
+        };
+
+        // Helper for funding a gift code and obtaining its b58 code.
+        let fund_gift_code = |ledger_db: &mut LedgerDB, rng: &mut StdRng, value: u64| {
+            let utxos = mobilecoind_db
+                .get_utxos_for_subaddress(&sender_monitor_id, 0)
+                .unwrap();
+
+            let mut request = api::GenerateGiftCodeFundingTxRequest::new();
+            request.set_sender_monitor_id(sender_monitor_id.to_vec());
+            request.set_change_subaddress(0);
+            request.set_input_list(RepeatedField::from_vec(
+                utxos.iter().map(api::UnspentTxOut::from).collect(),
+            ));
+            request.set_value(value);
+            request.set_note("happy birthday".to_owned());
+            let response = client.generate_gift_code_funding_tx(&request).unwrap();
+
+            // Before the funding transaction lands there is no gift code.
+            let mut request = api::CreateGiftCodeRequest::new();
+            request.set_sender_monitor_id(sender_monitor_id.to_vec());
+            request.set_tx_public_key(response.get_tx_public_key().clone());
+            assert!(client.create_gift_code(&request).is_err());
+
+            let tx_proposal = land_tx_proposal(ledger_db, rng, response.get_tx_proposal());
+
+            // The change output carries the funding memo.
+            let gift_code_public_key =
+                CompressedRistrettoPublic::try_from(response.get_tx_public_key()).unwrap();
+            let outputs = &tx_proposal.tx.prefix.outputs;
+            assert_eq!(outputs.len(), 2);
+            let change = outputs
+                .iter()
+                .find(|tx_out| tx_out.public_key != gift_code_public_key)
+                .unwrap();
+            match get_memo(change, &sender) {
+                MemoType::GiftCodeFunding(memo) => {
+                    assert!(memo.public_key_matches(
+                        &RistrettoPublic::try_from(&gift_code_public_key).unwrap()
+                    ));
+                    assert_eq!(memo.funding_note().unwrap(), "happy birthday");
+                }
+                memo => panic!("unexpected memo type {:?}", memo),
+            }
+
+            let response = client.create_gift_code(&request).unwrap();
+            assert_eq!(response.get_amount().get_value(), value);
+            assert_eq!(response.get_amount().get_token_id(), *Mob::ID);
+            response.get_b58_code().to_owned()
+        };
+
+        // Fund a gift code, and claim it into the receiver's monitor.
+        {
+            let b58_code = fund_gift_code(&mut ledger_db, &mut rng, 1337);
+
+            let mut request = api::GetGiftCodeStatusRequest::new();
+            request.set_b58_code(b58_code.clone());
+            let response = client.get_gift_code_status(&request).unwrap();
+            assert_eq!(
+                response.get_status(),
+                api::GiftCodeStatus::GiftCodeAvailable
+            );
+            assert_eq!(response.get_amount().get_value(), 1337);
+
+            // Only the funder can cancel the gift code.
+            let mut request = api::GenerateGiftCodeCancellationTxRequest::new();
+            request.set_sender_monitor_id(receiver_monitor_id.to_vec());
+            request.set_b58_code(b58_code.clone());
+            assert!(client.generate_gift_code_cancellation_tx(&request).is_err());
+
+            let mut request = api::GenerateGiftCodeClaimTxRequest::new();
+            request.set_b58_code(b58_code.clone());
+            request.set_monitor_id(receiver_monitor_id.to_vec());
+            request.set_subaddress_index(3);
+            request.set_note("thanks!".to_owned());
+            let response = client.generate_gift_code_claim_tx(&request).unwrap();
+            let tx_proposal =
+                land_tx_proposal(&mut ledger_db, &mut rng, response.get_tx_proposal());
+
+            // The receiver got the gift code minus the fee, with a sender memo.
+            let utxos = mobilecoind_db
+                .get_utxos_for_subaddress(&receiver_monitor_id, 3)
+                .unwrap();
+            assert_eq!(utxos.len(), 1);
+            assert_eq!(utxos[0].value, 1337 - tx_proposal.fee());
+            match get_memo(&utxos[0].tx_out, &receiver) {
+                MemoType::GiftCodeSender(memo) => {
+                    assert_eq!(memo.sender_note().unwrap(), "thanks!");
+                }
+                memo => panic!("unexpected memo type {:?}", memo),
+            }
+
+            let mut status_request = api::GetGiftCodeStatusRequest::new();
+            status_request.set_b58_code(b58_code);
+            let response = client.get_gift_code_status(&status_request).unwrap();
+            assert_eq!(response.get_status(), api::GiftCodeStatus::GiftCodeSpent);
+            assert_eq!(
+                response.get_spent_block_index(),
+                ledger_db.num_blocks().unwrap() - 1
+            );
+
+            // A gift code cannot be claimed twice.
+            assert!(client.generate_gift_code_claim_tx(&request).is_err());
+        }
+
+        // Fund a gift code, and cancel it.
+        {
+            let b58_code = fund_gift_code(&mut ledger_db, &mut rng, 4242);
+
+            let mut request = api::GetGiftCodeStatusRequest::new();
+            request.set_b58_code(b58_code.clone());
+            let global_index = client
+                .get_gift_code_status(&request)
+                .unwrap()
+                .get_global_index();
 
-            let mut request = api::GetBlockIndexByTxPubKeyRequest::new();
-            request.set_tx_public_key(tx_out_pub_key);
+            let mut request = api::GenerateGiftCodeCancellationTxRequest::new();
+            request.set_sender_monitor_id(sender_monitor_id.to_vec());
+            request.set_change_subaddress(5);
+            request.set_b58_code(b58_code);
+            let response = client.generate_gift_code_cancellation_tx(&request).unwrap();
+            let tx_proposal =
+                land_tx_proposal(&mut ledger_db, &mut rng, response.get_tx_proposal());
 
-            let response = client.get_block_index_by_tx_pub_key(&request).unwrap();
-            assert_eq!(block_index, response.block);
+            // The sender got the gift code back minus the fee, with a cancellation memo.
+            let utxos = mobilecoind_db
+                .get_utxos_for_subaddress(&sender_monitor_id, 5)
+                .unwrap();
+            assert_eq!(utxos.len(), 1);
+            assert_eq!(utxos[0].value, 4242 - tx_proposal.fee());
+            match get_memo(&utxos[0].tx_out, &sender) {
+                MemoType::GiftCodeCancellation(memo) => {
+                    assert_eq!(memo.cancelled_gift_code_index(), global_index);
+                }
+                memo => panic!("unexpected memo type {:?}", memo),
+            }
         }
     }
 
     #[test_with_logger]
-    fn test_generate_transfer_code_tx(logger: Logger) {
+    fn test_estimate_payment(logger: Logger) {
         let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
 
         let sender = AccountKey::random(&mut rng);
+        let sender_default_subaddress = sender.default_subaddress();
         let data = MonitorData::new(
-            sender.clone(),
-            0,  // first_subaddress
+            sender, 0,  // first_subaddress
             20, // num_subaddresses
             0,  // first_block
             "", // name
@@ -4104,86 +6278,87 @@ mod test {
             get_testing_environment(
                 BLOCK_VERSION,
                 3,
-                &[sender.default_subaddress()],
+                &[sender_default_subaddress.clone()],
                 &[],
                 logger.clone(),
                 &mut rng,
             );
 
+        // Add enough utxos that spending all of them takes more than MAX_INPUTS inputs.
+        for _ in 0..MAX_INPUTS {
+            let _ = add_block_to_ledger(
+                &mut ledger_db,
+                BLOCK_VERSION,
+                &[sender_default_subaddress.clone()],
+                Amount::new(DEFAULT_PER_RECIPIENT_AMOUNT, Mob::ID),
+                &[KeyImage::from(rng.next_u64())],
+                &mut rng,
+            );
+        }
+
         // Insert into database.
         let monitor_id = mobilecoind_db.add_monitor(&data).unwrap();
 
         // Allow the new monitor to process the ledger.
         wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
 
-        // Get list of unspent tx outs
-        let utxos = mobilecoind_db
+        let num_utxos = mobilecoind_db
             .get_utxos_for_subaddress(&monitor_id, 0)
-            .unwrap();
-        assert!(!utxos.is_empty());
-
-        // Call generate transfer code ctx.
-        let mut request = api::GenerateTransferCodeTxRequest::new();
-        request.set_sender_monitor_id(monitor_id.to_vec());
-        request.set_change_subaddress(0);
-        request.set_input_list(RepeatedField::from_vec(
-            utxos.iter().map(api::UnspentTxOut::from).collect(),
-        ));
-        request.set_value(1337);
+            .unwrap()
+            .len() as u64;
+        assert!(num_utxos > MAX_INPUTS + 1);
 
-        let response = client.generate_transfer_code_tx(&request).unwrap();
+        let mut estimate = |value: u64| {
+            let mut request = api::EstimatePaymentRequest::new();
+            request.set_sender_monitor_id(monitor_id.to_vec());
+            request.set_sender_subaddress(0);
+            request.set_outlay_list(RepeatedField::from_vec(vec![api::Outlay::from(&Outlay {
+                value,
+                receiver: AccountKey::random(&mut rng).default_subaddress(),
+            })]));
+            request.set_fee(Mob::MINIMUM_FEE);
+            client.estimate_payment(&request)
+        };
 
-        // Test that the generated transaction can be picked up by mobilecoind.
-        {
-            let tx_proposal = TxProposal::try_from(response.get_tx_proposal()).unwrap();
-            let key_images = tx_proposal.tx.key_images();
-            let outputs = tx_proposal.tx.prefix.outputs;
-            add_txos_and_key_images_to_ledger(
-                &mut ledger_db,
-                BLOCK_VERSION,
-                outputs,
-                key_images,
-                &mut rng,
-            )
-            .unwrap();
+        // A small payment is covered by a single input.
+        let response = estimate(123).unwrap();
+        assert_eq!(response.get_fee(), Mob::MINIMUM_FEE);
+        assert_eq!(response.get_num_inputs(), 1);
+        assert_eq!(response.get_input_value(), DEFAULT_PER_RECIPIENT_AMOUNT);
+        assert_eq!(
+            response.get_change(),
+            DEFAULT_PER_RECIPIENT_AMOUNT - 123 - Mob::MINIMUM_FEE
+        );
+        assert!(!response.get_requires_optimization());
+        assert_eq!(response.get_num_optimization_txs(), 0);
+        assert_eq!(response.get_optimization_fee(), 0);
+
+        // A payment that needs more than MAX_INPUTS inputs requires optimizing first.
+        // One optimization tx merges MAX_INPUTS utxos, and the payment spends its
+        // output and two more utxos.
+        let response = estimate(DEFAULT_PER_RECIPIENT_AMOUNT * (MAX_INPUTS + 1)).unwrap();
+        assert_eq!(response.get_num_inputs(), MAX_INPUTS + 2);
+        assert_eq!(
+            response.get_input_value(),
+            DEFAULT_PER_RECIPIENT_AMOUNT * (MAX_INPUTS + 2)
+        );
+        assert_eq!(
+            response.get_change(),
+            DEFAULT_PER_RECIPIENT_AMOUNT - 2 * Mob::MINIMUM_FEE
+        );
+        assert!(response.get_requires_optimization());
+        assert_eq!(response.get_num_optimization_txs(), 1);
+        assert_eq!(response.get_optimization_fee(), Mob::MINIMUM_FEE);
 
-            // Use bip39 entropy to construct AccountKey.
-            let mnemonic =
-                Mnemonic::from_entropy(response.get_bip39_entropy(), Language::English).unwrap();
-            let key = mnemonic.derive_slip10_key(0);
-            let account_key = AccountKey::from(key);
+        // A payment larger than the balance cannot be made at all.
+        assert!(estimate(DEFAULT_PER_RECIPIENT_AMOUNT * num_utxos).is_err());
 
-            // Add a monitor based on the entropy we received.
-            let monitor_data = MonitorData::new(
-                account_key,
-                DEFAULT_SUBADDRESS_INDEX, // first_subaddress
-                1,                        // num_subaddresses
-                0,                        // first_block
-                "",                       // name
-            )
+        // Nothing was submitted, so all utxos are still unspent and untouched.
+        let utxos = mobilecoind_db
+            .get_utxos_for_subaddress(&monitor_id, 0)
             .unwrap();
-
-            let monitor_id = mobilecoind_db.add_monitor(&monitor_data).unwrap();
-
-            // Wait for sync to complete.
-            wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
-
-            // Get utxos for the new account and verify we only have one utxo.
-            let utxos = mobilecoind_db
-                .get_utxos_for_subaddress(&monitor_id, DEFAULT_SUBADDRESS_INDEX)
-                .unwrap();
-            assert_eq!(utxos.len(), 1);
-
-            let utxo = &utxos[0];
-
-            assert_eq!(utxo.value, 1337);
-            assert_eq!(
-                utxo.tx_out.public_key,
-                RistrettoPublic::try_from(response.get_tx_public_key())
-                    .unwrap()
-                    .into()
-            );
-        }
+        assert_eq!(utxos.len() as u64, num_utxos);
+        assert!(utxos.iter().all(|utxo| utxo.attempted_spend_height == 0));
     }
 
     #[test_with_logger]
@@ -4253,10 +6428,7 @@ mod test {
         assert_eq!(tx_proposal.tx.prefix.inputs.len(), expected_num_inputs);
 
         assert_eq!(tx_proposal.outlays.len(), 1);
-        assert_eq!(
-            tx_proposal.outlays[0].receiver,
-            data.account_key.subaddress(0)
-        );
+        assert_eq!(tx_proposal.outlays[0].receiver, data.subaddress(0).unwrap());
         assert_eq!(
             tx_proposal.outlays[0].value,
             // Each UTXO we have has PER_RECIPIENT_AMOUNT coins. We will be merging MAX_INPUTS of
@@ -4271,7 +6443,7 @@ mod test {
         let tx_out = &tx_proposal.tx.prefix.outputs[0];
         let tx_public_key = RistrettoPublic::try_from(&tx_out.public_key).unwrap();
         let shared_secret =
-            get_tx_out_shared_secret(data.account_key.view_private_key(), &tx_public_key);
+            get_tx_out_shared_secret(data.view_private_key().unwrap(), &tx_public_key);
         let (amount, _blinding) = tx_out
             .get_masked_amount()
             .unwrap()
@@ -4292,6 +6464,136 @@ mod test {
         );
     }
 
+    #[test_with_logger]
+    fn test_defragmentation_policy(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
+
+        let sender = AccountKey::random(&mut rng);
+        let sender_default_subaddress = sender.default_subaddress();
+        let data = MonitorData::new(
+            sender, 0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // 1 known recipient, and a bunch of random recipients and no monitors.
+        // The random recipients are needed for mixins.
+        let num_random_recipients = MAX_INPUTS as u32 * RING_SIZE as u32
+            / test_utils::GET_TESTING_ENVIRONMENT_NUM_BLOCKS as u32;
+        let (mut ledger_db, mobilecoind_db, client, _server, server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                num_random_recipients as u32,
+                &[sender_default_subaddress.clone()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+
+        // Add a bunch of blocks/utxos for our recipient.
+        for _ in 0..MAX_INPUTS {
+            let _ = add_block_to_ledger(
+                &mut ledger_db,
+                BLOCK_VERSION,
+                &[sender_default_subaddress.clone()],
+                Amount::new(DEFAULT_PER_RECIPIENT_AMOUNT, Mob::ID),
+                &[KeyImage::from(rng.next_u64())],
+                &mut rng,
+            );
+        }
+
+        // Insert into database.
+        let monitor_id = mobilecoind_db.add_monitor(&data).unwrap();
+
+        // Allow the new monitor to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        let num_utxos = mobilecoind_db
+            .get_utxos_for_subaddress(&monitor_id, 0)
+            .unwrap()
+            .len() as u64;
+        assert!(num_utxos > MAX_INPUTS);
+
+        let get_status = || {
+            let mut request = api::GetMonitorStatusRequest::new();
+            request.set_monitor_id(monitor_id.to_vec());
+            client.get_monitor_status(&request).unwrap().take_status()
+        };
+
+        // There is no policy by default.
+        let status = get_status();
+        assert!(!status.has_defragmentation_policy());
+        assert!(!status.has_defragmentation_progress());
+
+        // Subaddresses outside of the monitor cannot be defragmented.
+        let mut policy = api::DefragmentationPolicy::new();
+        policy.set_subaddress_index(20);
+        policy.set_token_id(*Mob::ID);
+        policy.set_target_utxo_count(MAX_INPUTS);
+        policy.set_max_fee_budget(Mob::MINIMUM_FEE);
+
+        let mut request = api::SetDefragmentationPolicyRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        request.set_policy(policy.clone());
+        assert!(client.set_defragmentation_policy(&request).is_err());
+
+        // Set a policy whose budget allows for a single optimization transaction.
+        policy.set_subaddress_index(0);
+        request.set_policy(policy.clone());
+        client.set_defragmentation_policy(&request).unwrap();
+
+        let status = get_status();
+        assert_eq!(status.get_defragmentation_policy(), &policy);
+        assert_eq!(
+            status
+                .get_defragmentation_progress()
+                .get_num_txs_submitted(),
+            0
+        );
+        assert_eq!(
+            status.get_defragmentation_progress().get_utxo_count(),
+            num_utxos
+        );
+
+        // Wait for the defragmentation thread to submit a transaction.
+        let start = std::time::Instant::now();
+        while get_status()
+            .get_defragmentation_progress()
+            .get_num_txs_submitted()
+            == 0
+        {
+            assert!(
+                start.elapsed() < Duration::from_secs(60),
+                "timed out waiting for the optimization transaction"
+            );
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        let progress = get_status().take_defragmentation_progress();
+        assert_eq!(progress.get_num_txs_submitted(), 1);
+        assert_eq!(progress.get_fees_spent(), Mob::MINIMUM_FEE);
+
+        let proposed_txs: Vec<Tx> = server_conn_manager
+            .conns()
+            .iter()
+            .flat_map(|mock_peer| mock_peer.read().proposed_txs.clone())
+            .collect();
+        assert_eq!(proposed_txs.len(), 1);
+        assert_eq!(proposed_txs[0].prefix.inputs.len(), MAX_INPUTS as usize);
+        assert_eq!(proposed_txs[0].prefix.outputs.len(), 1);
+
+        // Clearing the policy removes it from the status.
+        let mut request = api::SetDefragmentationPolicyRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        client.set_defragmentation_policy(&request).unwrap();
+
+        let status = get_status();
+        assert!(!status.has_defragmentation_policy());
+        assert!(!status.has_defragmentation_progress());
+    }
+
     #[test_with_logger]
     fn test_generate_tx_from_tx_out_list(logger: Logger) {
         let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
@@ -4330,36 +6632,243 @@ mod test {
             .unwrap();
         assert!(!utxos.is_empty());
 
-        // Build a request to transfer the first two TxOuts
-        let tx_utxos = utxos[0..2].to_vec();
-        let mut request = api::GenerateTxFromTxOutListRequest::new();
-        request.set_account_key((&sender).into());
+        // Build a request to transfer the first two TxOuts
+        let tx_utxos = utxos[0..2].to_vec();
+        let mut request = api::GenerateTxFromTxOutListRequest::new();
+        request.set_account_key((&sender).into());
+        request.set_input_list(RepeatedField::from_vec(
+            tx_utxos.iter().map(api::UnspentTxOut::from).collect(),
+        ));
+        let receiver = AccountKey::random(&mut rng);
+        request.set_receiver((&receiver.default_subaddress()).into());
+        request.set_fee(Mob::MINIMUM_FEE);
+
+        let response = client.generate_tx_from_tx_out_list(&request).unwrap();
+        let tx_proposal = TxProposal::try_from(response.get_tx_proposal()).unwrap();
+
+        // We should end up with one output
+        assert_eq!(tx_proposal.tx.prefix.outputs.len(), 1);
+
+        // It should equal the sum of the inputs minus the fee
+        let expected_value = tx_utxos.iter().map(|utxo| utxo.value).sum::<u64>() - Mob::MINIMUM_FEE;
+
+        let tx_out = &tx_proposal.tx.prefix.outputs[0];
+        let tx_public_key = RistrettoPublic::try_from(&tx_out.public_key).unwrap();
+        let shared_secret = get_tx_out_shared_secret(receiver.view_private_key(), &tx_public_key);
+        let (amount, _blinding) = tx_out
+            .get_masked_amount()
+            .unwrap()
+            .get_value(&shared_secret)
+            .unwrap();
+        assert_eq!(amount.value, expected_value);
+        assert_eq!(amount.token_id, Mob::ID);
+    }
+
+    #[test_with_logger]
+    fn test_signed_contingent_input(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
+
+        let alice = AccountKey::random(&mut rng);
+        let bob = AccountKey::random(&mut rng);
+        let alice_data = MonitorData::new(
+            alice.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+        let bob_data = MonitorData::new(
+            bob.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // Bob is a known recipient of MOB, and there are 10 random recipients for
+        // mixins.
+        let (mut ledger_db, mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                10,
+                &[bob.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+
+        // Alice receives a non-MOB token.
+        let token2 = TokenId::from(2);
+        add_block_to_ledger(
+            &mut ledger_db,
+            BLOCK_VERSION,
+            &[alice.default_subaddress()],
+            Amount::new(1_000_000, token2),
+            &[KeyImage::from(101)],
+            &mut rng,
+        )
+        .unwrap();
+
+        // Insert into database.
+        let alice_monitor_id = mobilecoind_db.add_monitor(&alice_data).unwrap();
+        let bob_monitor_id = mobilecoind_db.add_monitor(&bob_data).unwrap();
+
+        // Allow the new monitors to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        let alice_utxos = mobilecoind_db
+            .get_utxos_for_subaddress(&alice_monitor_id, 0)
+            .unwrap();
+        assert_eq!(alice_utxos.len(), 1);
+        let bob_utxos = mobilecoind_db
+            .get_utxos_for_subaddress(&bob_monitor_id, 0)
+            .unwrap();
+        assert!(!bob_utxos.is_empty());
+
+        // Alice offers half of her token2 in exchange for 1000 picoMOB.
+        let mut request = api::GenerateSignedContingentInputRequest::new();
+        request.set_sender_monitor_id(alice_monitor_id.to_vec());
+        request.set_change_subaddress(0);
+        request.set_input((&alice_utxos[0]).into());
+        request.set_offered_value(500_000);
+        request.set_required_outlay_list(RepeatedField::from_vec(vec![(&OutlayV2 {
+            amount: Amount::new(1000, Mob::ID),
+            receiver: alice.default_subaddress(),
+        })
+            .into()]));
+
+        let response = client.generate_signed_contingent_input(&request).unwrap();
+        let sci = SignedContingentInput::try_from(response.get_sci()).unwrap();
+        sci.validate().unwrap();
+        assert_eq!(sci.key_image(), alice_utxos[0].key_image);
+
+        // Inspect the signed contingent input.
+        let mut request = api::InspectSignedContingentInputRequest::new();
+        request.set_sci(response.get_sci().clone());
+        let inspection = client.inspect_signed_contingent_input(&request).unwrap();
+        assert!(inspection.get_is_valid());
+        assert!(!inspection.get_is_spent());
+        assert!(!inspection.get_is_partial_fill());
+        assert_eq!(
+            Amount::from(inspection.get_offered_amount()),
+            Amount::new(1_000_000, token2)
+        );
+        let required_amounts: Vec<Amount> = inspection
+            .get_required_output_amounts()
+            .iter()
+            .map(Amount::from)
+            .collect();
+        assert_eq!(required_amounts.len(), 2);
+        assert!(required_amounts.contains(&Amount::new(1000, Mob::ID)));
+        assert!(required_amounts.contains(&Amount::new(500_000, token2)));
+
+        // A fill value is only allowed for partial fill signed contingent inputs.
+        let mut request = api::FillSignedContingentInputRequest::new();
+        request.set_sender_monitor_id(bob_monitor_id.to_vec());
+        request.set_change_subaddress(0);
         request.set_input_list(RepeatedField::from_vec(
-            tx_utxos.iter().map(api::UnspentTxOut::from).collect(),
+            bob_utxos.iter().map(api::UnspentTxOut::from).collect(),
         ));
-        let receiver = AccountKey::random(&mut rng);
-        request.set_receiver((&receiver.default_subaddress()).into());
-        request.set_fee(Mob::MINIMUM_FEE);
+        request.set_sci(response.get_sci().clone());
+        request.set_fill_value(10);
+        match client.fill_signed_contingent_input(&request) {
+            Ok(_) => panic!("Should've returned an error"),
+            Err(GrpcError::RpcFailure(rpc_status)) => {
+                assert_eq!(rpc_status.code(), RpcStatusCode::INVALID_ARGUMENT);
+            }
+            Err(err) => panic!("Unexpected error {:?}", err),
+        }
 
-        let response = client.generate_tx_from_tx_out_list(&request).unwrap();
+        // Bob fills the offer with his MOB.
+        request.set_fill_value(0);
+        let response = client.fill_signed_contingent_input(&request).unwrap();
         let tx_proposal = TxProposal::try_from(response.get_tx_proposal()).unwrap();
+        assert!(!tx_proposal.utxos.is_empty());
+        assert!(tx_proposal.outlays.is_empty());
+        assert_eq!(
+            tx_proposal.tx.prefix.inputs.len(),
+            tx_proposal.utxos.len() + 1
+        );
+        assert_eq!(tx_proposal.fee(), Mob::MINIMUM_FEE);
+        assert!(tx_proposal
+            .tx
+            .signature
+            .ring_signatures
+            .iter()
+            .any(|signature| signature.key_image == sci.key_image()));
+        for required_output in sci
+            .tx_in
+            .input_rules
+            .as_ref()
+            .unwrap()
+            .required_outputs
+            .iter()
+        {
+            assert!(tx_proposal.tx.prefix.outputs.contains(required_output));
+        }
 
-        // We should end up with one output
-        assert_eq!(tx_proposal.tx.prefix.outputs.len(), 1);
+        // Alice instead offers all of her token2 as a partial fill offer.
+        let mut request = api::GenerateSignedContingentInputRequest::new();
+        request.set_sender_monitor_id(alice_monitor_id.to_vec());
+        request.set_change_subaddress(0);
+        request.set_input((&alice_utxos[0]).into());
+        request.set_partial_fill_outlay_list(RepeatedField::from_vec(vec![(&OutlayV2 {
+            amount: Amount::new(1000, Mob::ID),
+            receiver: alice.default_subaddress(),
+        })
+            .into()]));
+        request.set_min_partial_fill_value(100_000);
+
+        let response = client.generate_signed_contingent_input(&request).unwrap();
+
+        let mut request = api::InspectSignedContingentInputRequest::new();
+        request.set_sci(response.get_sci().clone());
+        let inspection = client.inspect_signed_contingent_input(&request).unwrap();
+        assert!(inspection.get_is_valid());
+        assert!(inspection.get_is_partial_fill());
+        assert!(inspection.get_required_output_amounts().is_empty());
+        assert_eq!(
+            Amount::from(inspection.get_partial_fill_change_amount()),
+            Amount::new(1_000_000, token2)
+        );
+        assert_eq!(
+            inspection
+                .get_partial_fill_output_amounts()
+                .iter()
+                .map(Amount::from)
+                .collect::<Vec<_>>(),
+            vec![Amount::new(1000, Mob::ID)]
+        );
+        assert_eq!(inspection.get_min_partial_fill_value(), 100_000);
 
-        // It should equal the sum of the inputs minus the fee
-        let expected_value = tx_utxos.iter().map(|utxo| utxo.value).sum::<u64>() - Mob::MINIMUM_FEE;
+        // Bob cannot take more than is offered.
+        let mut request = api::FillSignedContingentInputRequest::new();
+        request.set_sender_monitor_id(bob_monitor_id.to_vec());
+        request.set_change_subaddress(0);
+        request.set_input_list(RepeatedField::from_vec(
+            bob_utxos.iter().map(api::UnspentTxOut::from).collect(),
+        ));
+        request.set_sci(response.get_sci().clone());
+        request.set_fill_value(2_000_000);
+        match client.fill_signed_contingent_input(&request) {
+            Ok(_) => panic!("Should've returned an error"),
+            Err(GrpcError::RpcFailure(rpc_status)) => {
+                assert_eq!(rpc_status.code(), RpcStatusCode::INVALID_ARGUMENT);
+            }
+            Err(err) => panic!("Unexpected error {:?}", err),
+        }
 
-        let tx_out = &tx_proposal.tx.prefix.outputs[0];
-        let tx_public_key = RistrettoPublic::try_from(&tx_out.public_key).unwrap();
-        let shared_secret = get_tx_out_shared_secret(receiver.view_private_key(), &tx_public_key);
-        let (amount, _blinding) = tx_out
-            .get_masked_amount()
-            .unwrap()
-            .get_value(&shared_secret)
-            .unwrap();
-        assert_eq!(amount.value, expected_value);
-        assert_eq!(amount.token_id, Mob::ID);
+        // Bob takes half of the offer.
+        request.set_fill_value(500_000);
+        let response = client.fill_signed_contingent_input(&request).unwrap();
+        let tx_proposal = TxProposal::try_from(response.get_tx_proposal()).unwrap();
+        assert!(!tx_proposal.utxos.is_empty());
+        assert_eq!(
+            tx_proposal.tx.prefix.inputs.len(),
+            tx_proposal.utxos.len() + 1
+        );
     }
 
     #[test_with_logger]
@@ -4614,6 +7123,137 @@ mod test {
         assert!(client.get_balance(&request).is_err());
     }
 
+    #[test_with_logger]
+    fn test_view_only_monitor(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([24u8; 32]);
+
+        let account_key = AccountKey::random(&mut rng);
+        let view_account_key = ViewAccountKey::from(&account_key);
+
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (mut ledger_db, mobilecoind_db, client, _server, _server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[account_key.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+
+        // Add a view-only monitor.
+        let mut request = api::AddMonitorRequest::new();
+        request.set_view_account_key((&view_account_key).into());
+        request.set_num_subaddresses(1);
+        let response = client.add_monitor(&request).unwrap();
+        let monitor_id = MonitorId::try_from(&response.monitor_id).unwrap();
+
+        // Setting both an account key and a view account key should fail.
+        request.set_account_key((&account_key).into());
+        assert!(client.add_monitor(&request).is_err());
+
+        // Allow the new monitor to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        // The monitor should report itself as view-only.
+        let mut request = api::GetMonitorStatusRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        let status = client.get_monitor_status(&request).unwrap().take_status();
+        assert!(status.is_view_only);
+        assert!(!status.has_account_key());
+        assert_eq!(
+            ViewAccountKey::try_from(status.get_view_account_key()).unwrap(),
+            view_account_key
+        );
+
+        // All our utxos should have been discovered, with unknown key images.
+        let num_blocks = ledger_db.num_blocks().unwrap();
+        let utxos = mobilecoind_db
+            .get_utxos_for_subaddress(&monitor_id, 0)
+            .unwrap();
+        assert_eq!(utxos.len() as u64, num_blocks);
+        assert!(utxos.iter().all(|utxo| utxo.key_image_unknown));
+
+        let mut balance_request = api::GetBalanceRequest::new();
+        balance_request.set_monitor_id(monitor_id.to_vec());
+        let response = client.get_balance(&balance_request).unwrap();
+        assert_eq!(response.balance, DEFAULT_PER_RECIPIENT_AMOUNT * num_blocks);
+        assert_eq!(
+            response.unknown_spent_status_balance,
+            DEFAULT_PER_RECIPIENT_AMOUNT * num_blocks
+        );
+
+        // Spending from a view-only monitor should fail.
+        let mut request = api::GenerateOptimizationTxRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        match client.generate_optimization_tx(&request) {
+            Ok(_) => panic!("Should've returned an error"),
+            Err(GrpcError::RpcFailure(rpc_status)) => {
+                assert_eq!(rpc_status.code(), RpcStatusCode::FAILED_PRECONDITION);
+            }
+            Err(err) => panic!("Unexpected error: {:?}", err),
+        };
+
+        // Compute the real key images of the first two utxos, and spend the first one.
+        let key_images: Vec<KeyImage> = utxos[0..2]
+            .iter()
+            .map(|utxo| {
+                let tx_public_key = RistrettoPublic::try_from(&utxo.tx_out.public_key).unwrap();
+                KeyImage::from(&recover_onetime_private_key(
+                    &tx_public_key,
+                    account_key.view_private_key(),
+                    &account_key.subaddress_spend_private(0),
+                ))
+            })
+            .collect();
+
+        add_block_to_ledger(
+            &mut ledger_db,
+            BLOCK_VERSION,
+            &[AccountKey::random(&mut rng).default_subaddress()],
+            Amount::new(DEFAULT_PER_RECIPIENT_AMOUNT, Mob::ID),
+            &key_images[0..1],
+            &mut rng,
+        )
+        .unwrap();
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        // Import the key images. The first one is already in the ledger, so its utxo
+        // should get removed.
+        let mut request = api::ImportKeyImagesRequest::new();
+        request.set_monitor_id(monitor_id.to_vec());
+        for (utxo, key_image) in utxos.iter().zip(key_images.iter()) {
+            let mut proto = api::TxOutKeyImage::new();
+            proto.set_tx_public_key((&utxo.tx_out.public_key).into());
+            proto.set_key_image(key_image.into());
+            request.mut_key_images().push(proto);
+        }
+        let response = client.import_key_images(&request).unwrap();
+        assert_eq!(response.num_imported, 2);
+        assert_eq!(response.num_spent, 1);
+
+        // Importing again should fail since the key images are no longer unknown.
+        assert!(client.import_key_images(&request).is_err());
+
+        let utxos = mobilecoind_db
+            .get_utxos_for_subaddress(&monitor_id, 0)
+            .unwrap();
+        assert_eq!(utxos.len() as u64, num_blocks - 1);
+        assert!(utxos
+            .iter()
+            .any(|utxo| !utxo.key_image_unknown && utxo.key_image == key_images[1]));
+
+        let response = client.get_balance(&balance_request).unwrap();
+        assert_eq!(
+            response.balance,
+            DEFAULT_PER_RECIPIENT_AMOUNT * (num_blocks - 1)
+        );
+        assert_eq!(
+            response.unknown_spent_status_balance,
+            DEFAULT_PER_RECIPIENT_AMOUNT * (num_blocks - 2)
+        );
+    }
+
     #[test_with_logger]
     fn test_send_payment(logger: Logger) {
         let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
@@ -4787,6 +7427,248 @@ mod test {
         assert_eq!(matched_utxos, tx_proposal.utxos.len());
     }
 
+    #[test_with_logger]
+    fn test_send_batch_payment(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
+
+        let sender = AccountKey::random(&mut rng);
+        let data = MonitorData::new(
+            sender.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (ledger_db, mobilecoind_db, client, _server, server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[sender.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+
+        // Insert into database.
+        let monitor_id = mobilecoind_db.add_monitor(&data).unwrap();
+
+        // Allow the new monitor to process the ledger.
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        // More recipients than fit in a single transaction, each with a payment request
+        // id, except for the first one which gets no memo.
+        let num_outlays = MAX_OUTLAYS_PER_TX + 5;
+        let receivers: Vec<AccountKey> = (0..num_outlays)
+            .map(|_| AccountKey::random(&mut rng))
+            .collect();
+        let outlays: Vec<api::Outlay> = receivers
+            .iter()
+            .enumerate()
+            .map(|(i, receiver)| {
+                let mut outlay = api::Outlay::from(&Outlay {
+                    value: 100 + i as u64,
+                    receiver: receiver.default_subaddress(),
+                });
+                if i > 0 {
+                    let mut memo = api::OutlayMemo::new();
+                    memo.set_memo_type(api::OutlayMemoType::OutlayMemoSenderWithPaymentRequestId);
+                    memo.set_id(i as u64);
+                    outlay.set_memo(memo);
+                }
+                outlay
+            })
+            .collect();
+
+        // Call send batch payment.
+        let mut request = api::SendBatchPaymentRequest::new();
+        request.set_sender_monitor_id(monitor_id.to_vec());
+        request.set_sender_subaddress(0);
+        request.set_outlay_list(RepeatedField::from_vec(outlays));
+
+        let response = client.send_batch_payment(&request).unwrap();
+
+        // The outlays were split across two transactions.
+        assert_eq!(response.get_tx_list().len(), 2);
+        assert_eq!(response.get_tx_proposal_list().len(), 2);
+        assert_eq!(
+            response.get_tx_proposal_list()[0].get_outlay_list().len(),
+            MAX_OUTLAYS_PER_TX
+        );
+        assert_eq!(
+            response.get_tx_proposal_list()[1].get_outlay_list().len(),
+            5
+        );
+
+        // Both were submitted.
+        let num_submitted_txs: usize = server_conn_manager
+            .conns()
+            .iter()
+            .map(|mock_peer| mock_peer.read().proposed_txs.len())
+            .sum();
+        assert_eq!(num_submitted_txs, 2);
+        assert_eq!(response.get_tx_result_list().len(), 2);
+        for (tx_result, submit_tx_response) in response
+            .get_tx_result_list()
+            .iter()
+            .zip(response.get_tx_list())
+        {
+            assert_eq!(tx_result.get_error(), "");
+            assert_eq!(tx_result.get_submit_tx_response(), submit_tx_response);
+        }
+
+        // The transactions spend different inputs.
+        let key_images: Vec<_> = response
+            .get_tx_list()
+            .iter()
+            .map(|tx| tx.get_sender_tx_receipt().get_key_image_list().to_vec())
+            .collect();
+        assert!(key_images[0]
+            .iter()
+            .all(|key_image| !key_images[1].contains(key_image)));
+
+        // The receiver receipts are in the same order as the outlays, and each output
+        // carries the memo of its outlay.
+        let receipts: Vec<&api::ReceiverTxReceipt> = response
+            .get_tx_list()
+            .iter()
+            .flat_map(|tx| tx.get_receiver_tx_receipt_list().iter())
+            .collect();
+        assert_eq!(receipts.len(), num_outlays);
+        let tx_outs: Vec<TxOut> = response
+            .get_tx_proposal_list()
+            .iter()
+            .flat_map(|tx_proposal| tx_proposal.get_tx().get_prefix().get_outputs().iter())
+            .map(|tx_out| TxOut::try_from(tx_out).unwrap())
+            .collect();
+        for (i, (receiver, receipt)) in receivers.iter().zip(receipts.iter()).enumerate() {
+            assert_eq!(
+                receiver.default_subaddress(),
+                PublicAddress::try_from(receipt.get_recipient()).unwrap()
+            );
+
+            let tx_public_key = RistrettoPublic::try_from(receipt.get_tx_public_key()).unwrap();
+            let tx_out = tx_outs
+                .iter()
+                .find(|tx_out| tx_out.public_key == CompressedRistrettoPublic::from(&tx_public_key))
+                .unwrap();
+            let shared_secret =
+                get_tx_out_shared_secret(receiver.view_private_key(), &tx_public_key);
+            let memo = MemoType::try_from(&tx_out.decrypt_memo(&shared_secret)).unwrap();
+            if i == 0 {
+                assert_matches!(memo, MemoType::Unused(_));
+            } else {
+                assert_matches!(memo, MemoType::AuthenticatedSenderWithPaymentRequestId(memo) if memo.payment_request_id() == i as u64);
+            }
+        }
+
+        // Nothing landed yet, so the status of the batch is unknown.
+        let mut request = api::GetBatchStatusAsSenderRequest::new();
+        request.set_tx_list(response.get_tx_list().into());
+        let status_response = client.get_batch_status_as_sender(&request).unwrap();
+        assert_eq!(status_response.get_status(), api::TxStatus::Unknown);
+        assert_eq!(
+            status_response.get_tx_status_list(),
+            &[api::TxStatus::Unknown, api::TxStatus::Unknown]
+        );
+
+        // An empty batch is rejected.
+        assert!(client
+            .get_batch_status_as_sender(&api::GetBatchStatusAsSenderRequest::new())
+            .is_err());
+    }
+
+    #[test_with_logger]
+    // When submitting some of the transactions of a batch fails, the receipts of
+    // the others should still be returned.
+    fn test_send_batch_payment_partial_failure(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
+
+        let sender = AccountKey::random(&mut rng);
+        let data = MonitorData::new(
+            sender.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .unwrap();
+
+        // 1 known recipient, 3 random recipients and no monitors.
+        let (ledger_db, mobilecoind_db, client, _server, server_conn_manager) =
+            get_testing_environment(
+                BLOCK_VERSION,
+                3,
+                &[sender.default_subaddress()],
+                &[],
+                logger.clone(),
+                &mut rng,
+            );
+        let monitor_id = mobilecoind_db.add_monitor(&data).unwrap();
+        wait_for_monitors(&mobilecoind_db, &ledger_db, &logger);
+
+        // Two transactions worth of outlays.
+        let outlays: Vec<api::Outlay> = (0..MAX_OUTLAYS_PER_TX + 5)
+            .map(|i| {
+                api::Outlay::from(&Outlay {
+                    value: 100 + i as u64,
+                    receiver: AccountKey::random(&mut rng).default_subaddress(),
+                })
+            })
+            .collect();
+
+        let mut request = api::SendBatchPaymentRequest::new();
+        request.set_sender_monitor_id(monitor_id.to_vec());
+        request.set_sender_subaddress(0);
+        request.set_outlay_list(RepeatedField::from_vec(outlays));
+
+        // Transactions are submitted to the peers in turn, and one of them fails.
+        server_conn_manager.conns()[1]
+            .write()
+            .propose_tx_should_error_count = 1;
+
+        let response = client.send_batch_payment(&request).unwrap();
+
+        // The outcome of both transactions is reported.
+        let tx_results = response.get_tx_result_list();
+        assert_eq!(tx_results.len(), 2);
+        let (submitted, failed): (Vec<_>, Vec<_>) = tx_results
+            .iter()
+            .partition(|tx_result| tx_result.has_submit_tx_response());
+        assert_eq!(submitted.len(), 1);
+        assert_eq!(failed.len(), 1);
+        assert!(!failed[0].get_error().is_empty());
+        assert!(submitted[0].get_error().is_empty());
+
+        // Only the submitted transaction has a receipt to track.
+        assert_eq!(
+            response.get_tx_list(),
+            &[submitted[0].get_submit_tx_response().clone()]
+        );
+        assert_eq!(
+            response.get_tx_proposal_list(),
+            &[submitted[0].get_tx_proposal().clone()]
+        );
+        let proposed_txs: Vec<Tx> = server_conn_manager
+            .conns()
+            .iter()
+            .flat_map(|mock_peer| mock_peer.read().proposed_txs.clone())
+            .collect();
+        assert_eq!(proposed_txs.len(), 1);
+        assert_eq!(
+            proposed_txs[0],
+            Tx::try_from(submitted[0].get_tx_proposal().get_tx()).unwrap()
+        );
+
+        // If nothing can be submitted, the call fails.
+        for conn in server_conn_manager.conns() {
+            conn.write().propose_tx_should_error_count = 2;
+        }
+        assert!(client.send_batch_payment(&request).is_err());
+    }
+
     #[test_with_logger]
     fn test_send_payment_with_max_input_utxo_value(logger: Logger) {
         let mut rng: StdRng = SeedableRng::from_seed([23u8; 32]);
@@ -5777,7 +8659,9 @@ mod test {
             );
 
         let mut request = api::AddMonitorRequest::new();
-        request.set_account_key(mc_api::external::AccountKey::from(&data.account_key));
+        request.set_account_key(mc_api::external::AccountKey::from(
+            data.account_key.as_ref().unwrap(),
+        ));
         request.set_first_subaddress(data.first_subaddress);
         request.set_num_subaddresses(data.num_subaddresses);
         request.set_first_block(data.first_block);
@@ -5866,7 +8750,9 @@ mod test {
 
         // Re-add the monitor.
         let mut request = api::AddMonitorRequest::new();
-        request.set_account_key(mc_api::external::AccountKey::from(&data.account_key));
+        request.set_account_key(mc_api::external::AccountKey::from(
+            data.account_key.as_ref().unwrap(),
+        ));
         request.set_first_subaddress(data.first_subaddress);
         request.set_num_subaddresses(data.num_subaddresses);
         request.set_first_block(data.first_block);
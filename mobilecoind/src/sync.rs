@@ -344,7 +344,7 @@ fn match_tx_outs_into_utxos(
     monitor_data: &MonitorData,
     logger: &Logger,
 ) -> Result<Vec<UnspentTxOut>, Error> {
-    let account_key = &monitor_data.account_key;
+    let view_private_key = monitor_data.view_private_key()?;
     let mut results = Vec::new();
 
     for tx_out in outputs {
@@ -353,7 +353,7 @@ fn match_tx_outs_into_utxos(
         let tx_public_key = RistrettoPublic::try_from(&tx_out.public_key)?;
 
         let subaddress_spk = SubaddressSPKId::from(&recover_public_subaddress_spend_key(
-            account_key.view_private_key(),
+            view_private_key,
             &tx_out_target_key,
             &tx_public_key,
         ));
@@ -379,8 +379,7 @@ fn match_tx_outs_into_utxos(
         // Sanity - we should only get a match for our own monitor id.
         assert_eq!(monitor_id, &subaddress_id.monitor_id);
 
-        let shared_secret =
-            get_tx_out_shared_secret(account_key.view_private_key(), &tx_public_key);
+        let shared_secret = get_tx_out_shared_secret(view_private_key, &tx_public_key);
 
         let (amount, _blinding) = tx_out
             .get_masked_amount()
@@ -388,13 +387,22 @@ fn match_tx_outs_into_utxos(
             .get_value(&shared_secret)
             .expect("Malformed amount"); // TODO
 
-        let onetime_private_key = recover_onetime_private_key(
-            &tx_public_key,
-            account_key.view_private_key(),
-            &account_key.subaddress_spend_private(subaddress_id.index),
-        );
-
-        let key_image = KeyImage::from(&onetime_private_key);
+        // View-only monitors cannot compute key images, so a placeholder is stored
+        // until the user supplies the real one.
+        let (key_image, key_image_unknown) = match monitor_data.account_key.as_ref() {
+            Some(account_key) => {
+                let onetime_private_key = recover_onetime_private_key(
+                    &tx_public_key,
+                    view_private_key,
+                    &account_key.subaddress_spend_private(subaddress_id.index),
+                );
+                (KeyImage::from(&onetime_private_key), false)
+            }
+            None => (
+                UnspentTxOut::placeholder_key_image(&tx_out.public_key),
+                true,
+            ),
+        };
 
         results.push(UnspentTxOut {
             tx_out: tx_out.clone(),
@@ -404,6 +412,7 @@ fn match_tx_outs_into_utxos(
             attempted_spend_height: 0,
             attempted_spend_tombstone: 0,
             token_id: *amount.token_id,
+            key_image_unknown,
         });
     }
 
@@ -440,7 +449,7 @@ mod test {
         )
         .unwrap();
 
-        let monitor_id = MonitorId::from(&data);
+        let monitor_id = MonitorId::try_from(&data).unwrap();
 
         let recipients: Vec<PublicAddress> = account_keys
             .iter()
@@ -618,7 +627,7 @@ mod test {
         )
         .unwrap();
 
-        let monitor_id = MonitorId::from(&data);
+        let monitor_id = MonitorId::try_from(&data).unwrap();
 
         let recipients: Vec<PublicAddress> = account_keys
             .iter()
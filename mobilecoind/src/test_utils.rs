@@ -115,7 +115,7 @@ pub fn get_test_monitor_data_and_id(
     )
     .unwrap();
 
-    let monitor_id = MonitorId::from(&data);
+    let monitor_id = MonitorId::try_from(&data).unwrap();
     (data, monitor_id)
 }
 
@@ -0,0 +1,648 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Database storage for the transaction history of each monitor.
+//! * Stores a map of (monitor id, block index, tx out public key, direction) ->
+//!   history entry describing a TxOut that was received or spent by the
+//!   monitor, together with the contents of its memo.
+
+use crate::{
+    error::Error, monitor_store::MonitorId, processed_block_store::ProcessedTxOutDirection,
+    utxo_store::UnspentTxOut,
+};
+use lmdb::{Cursor, Database, DatabaseFlags, Environment, RwTransaction, Transaction, WriteFlags};
+use mc_common::logger::Logger;
+use mc_crypto_keys::{CompressedRistrettoPublic, RistrettoPrivate, RistrettoPublic};
+use mc_transaction_core::{get_tx_out_shared_secret, ring_signature::KeyImage};
+use mc_transaction_extra::MemoType;
+use prost::{Enumeration, Message};
+use std::sync::Arc;
+
+// LMDB Database Names
+pub const TX_HISTORY_KEY_TO_TX_HISTORY_ENTRY_DB_NAME: &str =
+    "mobilecoind_db:tx_history_store:tx_history_key_to_tx_history_entry";
+
+/// The maximum number of entries returned by a single history query.
+pub const MAX_TX_HISTORY_ENTRIES_PER_REQUEST: usize = 1000;
+
+/// Type used as the key in the database managed by the tx history store.
+/// Keys sort by monitor id, then block index, so iterating over them yields
+/// the history of a monitor in block order.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TxHistoryKey {
+    /// The monitor_id for which the data belongs to.
+    pub monitor_id: MonitorId,
+
+    /// The block index.
+    pub block_index: u64,
+
+    /// The public key of the TxOut.
+    pub public_key: CompressedRistrettoPublic,
+
+    /// The direction (ProcessedTxOutDirection).
+    pub direction: u8,
+}
+
+impl TxHistoryKey {
+    /// The smallest key of a given monitor id and block index.
+    pub fn first_in_block(monitor_id: &MonitorId, block_index: u64) -> Self {
+        Self {
+            monitor_id: *monitor_id,
+            block_index,
+            public_key: CompressedRistrettoPublic::from(&[0u8; 32]),
+            direction: 0,
+        }
+    }
+
+    // 73 bytes: 32 for MonitorId, 8 for block index, 32 for public key, 1 for
+    // direction.
+    pub fn to_bytes(&self) -> [u8; 73] {
+        let mut buf = [0u8; 73];
+        buf[0..32].copy_from_slice(self.monitor_id.as_bytes());
+        buf[32..40].copy_from_slice(&self.block_index.to_be_bytes());
+        buf[40..72].copy_from_slice(self.public_key.as_bytes());
+        buf[72] = self.direction;
+        buf
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+}
+
+impl TryFrom<&[u8]> for TxHistoryKey {
+    type Error = Error;
+
+    fn try_from(src: &[u8]) -> Result<Self, Self::Error> {
+        if src.len() != 73 {
+            return Err(Error::InvalidArgument(
+                "src".to_string(),
+                "src length must be exactly 73".to_string(),
+            ));
+        }
+
+        let monitor_id = MonitorId::try_from(&src[0..32])?;
+
+        let mut index_bytes = [0u8; 8];
+        index_bytes.copy_from_slice(&src[32..40]);
+        let block_index = u64::from_be_bytes(index_bytes);
+
+        let public_key = CompressedRistrettoPublic::try_from(&src[40..72])?;
+
+        Ok(Self {
+            monitor_id,
+            block_index,
+            public_key,
+            direction: src[72],
+        })
+    }
+}
+
+/// The type of memo attached to a TxOut in the history.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Enumeration)]
+pub enum TxHistoryMemoType {
+    /// The memo could not be decoded, e.g. it is of a type this version of
+    /// mobilecoind does not know about.
+    Unknown = 0,
+    Unused = 1,
+    AuthenticatedSender = 2,
+    AuthenticatedSenderWithPaymentRequestId = 3,
+    AuthenticatedSenderWithPaymentIntentId = 4,
+    Destination = 5,
+    DestinationWithPaymentRequestId = 6,
+    DestinationWithPaymentIntentId = 7,
+    BurnRedemption = 8,
+    GiftCodeFunding = 9,
+    GiftCodeSender = 10,
+    GiftCodeCancellation = 11,
+}
+
+/// A TxOut received or spent by a monitor.
+///
+/// The memo fields describe the memo attached to the TxOut itself. For a
+/// received TxOut with a sender memo, `address_hash` is the hash of the
+/// address the sender claims to be. It is not authenticated, since that
+/// requires knowing the sender's public address. For a destination memo (on
+/// change sent back to ourselves), it is the hash of the address that was paid.
+#[derive(Clone, Eq, Hash, PartialEq, Message)]
+pub struct TxHistoryEntry {
+    /// The block the TxOut was received or spent in.
+    #[prost(uint64, tag = "1")]
+    pub block_index: u64,
+
+    /// Direction.
+    #[prost(enumeration = "ProcessedTxOutDirection", tag = "2")]
+    pub direction: i32,
+
+    /// The subaddress index the tx out belongs to.
+    #[prost(uint64, tag = "3")]
+    pub subaddress_index: u64,
+
+    /// The public key of the TxOut.
+    #[prost(message, required, tag = "4")]
+    pub public_key: CompressedRistrettoPublic,
+
+    /// Key image of the TxOut.
+    #[prost(message, required, tag = "5")]
+    pub key_image: KeyImage,
+
+    /// Value of this TxOut.
+    #[prost(uint64, tag = "6")]
+    pub value: u64,
+
+    /// Token id.
+    #[prost(uint64, tag = "7")]
+    pub token_id: u64,
+
+    /// The type of memo attached to the TxOut.
+    #[prost(enumeration = "TxHistoryMemoType", tag = "8")]
+    pub memo_type: i32,
+
+    /// The short address hash found in the memo, if any.
+    #[prost(bytes, optional, tag = "9")]
+    pub address_hash: Option<Vec<u8>>,
+
+    /// The payment request id found in the memo, if any.
+    #[prost(uint64, optional, tag = "10")]
+    pub payment_request_id: Option<u64>,
+
+    /// The payment intent id found in the memo, if any.
+    #[prost(uint64, optional, tag = "11")]
+    pub payment_intent_id: Option<u64>,
+}
+
+impl TxHistoryEntry {
+    /// Create a history entry for a utxo, decoding the memo attached to it.
+    pub fn new(
+        utxo: &UnspentTxOut,
+        direction: ProcessedTxOutDirection,
+        block_index: u64,
+        view_private_key: &RistrettoPrivate,
+    ) -> Result<Self, Error> {
+        let mut entry = Self {
+            block_index,
+            direction: direction as i32,
+            subaddress_index: utxo.subaddress_index,
+            public_key: utxo.tx_out.public_key,
+            key_image: utxo.key_image,
+            value: utxo.value,
+            token_id: utxo.token_id,
+            memo_type: TxHistoryMemoType::Unknown as i32,
+            address_hash: None,
+            payment_request_id: None,
+            payment_intent_id: None,
+        };
+
+        let tx_public_key = RistrettoPublic::try_from(&utxo.tx_out.public_key)?;
+        let shared_secret = get_tx_out_shared_secret(view_private_key, &tx_public_key);
+        let memo_payload = utxo.tx_out.decrypt_memo(&shared_secret);
+
+        let memo_type = match MemoType::try_from(&memo_payload) {
+            Ok(memo_type) => memo_type,
+            Err(_) => return Ok(entry),
+        };
+
+        let memo_type = match memo_type {
+            MemoType::Unused(_) => TxHistoryMemoType::Unused,
+            MemoType::AuthenticatedSender(memo) => {
+                entry.address_hash = Some(address_hash_bytes(memo.sender_address_hash()));
+                TxHistoryMemoType::AuthenticatedSender
+            }
+            MemoType::AuthenticatedSenderWithPaymentRequestId(memo) => {
+                entry.address_hash = Some(address_hash_bytes(memo.sender_address_hash()));
+                entry.payment_request_id = Some(memo.payment_request_id());
+                TxHistoryMemoType::AuthenticatedSenderWithPaymentRequestId
+            }
+            MemoType::AuthenticatedSenderWithPaymentIntentId(memo) => {
+                entry.address_hash = Some(address_hash_bytes(memo.sender_address_hash()));
+                entry.payment_intent_id = Some(memo.payment_intent_id());
+                TxHistoryMemoType::AuthenticatedSenderWithPaymentIntentId
+            }
+            MemoType::Destination(memo) => {
+                entry.address_hash = Some(address_hash_bytes(memo.get_address_hash().clone()));
+                TxHistoryMemoType::Destination
+            }
+            MemoType::DestinationWithPaymentRequestId(memo) => {
+                entry.address_hash = Some(address_hash_bytes(memo.get_address_hash().clone()));
+                entry.payment_request_id = Some(memo.get_payment_request_id());
+                TxHistoryMemoType::DestinationWithPaymentRequestId
+            }
+            MemoType::DestinationWithPaymentIntentId(memo) => {
+                entry.address_hash = Some(address_hash_bytes(memo.get_address_hash().clone()));
+                entry.payment_intent_id = Some(memo.get_payment_intent_id());
+                TxHistoryMemoType::DestinationWithPaymentIntentId
+            }
+            MemoType::BurnRedemption(_) => TxHistoryMemoType::BurnRedemption,
+            MemoType::GiftCodeFunding(_) => TxHistoryMemoType::GiftCodeFunding,
+            MemoType::GiftCodeSender(_) => TxHistoryMemoType::GiftCodeSender,
+            MemoType::GiftCodeCancellation(_) => TxHistoryMemoType::GiftCodeCancellation,
+        };
+        entry.memo_type = memo_type as i32;
+
+        Ok(entry)
+    }
+
+    /// The key this entry is stored under.
+    pub fn key(&self, monitor_id: &MonitorId) -> TxHistoryKey {
+        TxHistoryKey {
+            monitor_id: *monitor_id,
+            block_index: self.block_index,
+            public_key: self.public_key,
+            direction: self.direction as u8,
+        }
+    }
+}
+
+fn address_hash_bytes(address_hash: impl Into<[u8; 16]>) -> Vec<u8> {
+    address_hash.into().to_vec()
+}
+
+/// The tx history database.
+#[derive(Clone)]
+pub struct TxHistoryStore {
+    /// Retain a reference to the Environment so the Database handles are valid.
+    _env: Arc<Environment>,
+
+    /// Mapping of TxHistoryKey -> TxHistoryEntry.
+    tx_history_key_to_tx_history_entry: Database,
+}
+
+impl TxHistoryStore {
+    pub fn new(env: Arc<Environment>, _logger: Logger) -> Result<Self, Error> {
+        let tx_history_key_to_tx_history_entry = env.create_db(
+            Some(TX_HISTORY_KEY_TO_TX_HISTORY_ENTRY_DB_NAME),
+            DatabaseFlags::empty(),
+        )?;
+
+        Ok(Self {
+            _env: env,
+            tx_history_key_to_tx_history_entry,
+        })
+    }
+
+    /// Get up to `max_entries` history entries of a given monitor id, in
+    /// block order, starting at `start_key`. If `exclude_start_key` is set,
+    /// an entry stored under exactly `start_key` is skipped, which allows
+    /// resuming after the last entry of a previous query.
+    pub fn get_entries(
+        &self,
+        db_txn: &impl Transaction,
+        start_key: &TxHistoryKey,
+        exclude_start_key: bool,
+        max_entries: usize,
+    ) -> Result<Vec<TxHistoryEntry>, Error> {
+        let mut cursor = db_txn.open_ro_cursor(self.tx_history_key_to_tx_history_entry)?;
+
+        let start_key_bytes = start_key.to_vec();
+        let mut entries = Vec::new();
+        for result in cursor.iter_from(&start_key_bytes) {
+            if entries.len() >= max_entries {
+                break;
+            }
+
+            let (db_key, db_value) = result?;
+            if exclude_start_key && db_key == start_key_bytes.as_slice() {
+                continue;
+            }
+
+            let key = TxHistoryKey::try_from(db_key)?;
+            if key.monitor_id != start_key.monitor_id {
+                break;
+            }
+
+            entries.push(mc_util_serial::decode(db_value)?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Remove the data associated with a given monitor id.
+    pub fn remove<'env>(
+        &self,
+        db_txn: &mut RwTransaction<'env>,
+        monitor_id: &MonitorId,
+    ) -> Result<(), Error> {
+        let start_key = TxHistoryKey::first_in_block(monitor_id, 0);
+        let start_key_bytes = start_key.to_vec();
+
+        let mut cursor = db_txn.open_rw_cursor(self.tx_history_key_to_tx_history_entry)?;
+
+        for (db_key, _db_value) in cursor.iter_from(&start_key_bytes).filter_map(|r| r.ok()) {
+            let key = TxHistoryKey::try_from(db_key)?;
+            if key.monitor_id == *monitor_id {
+                cursor.del(WriteFlags::empty())?;
+            } else {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Feed data processed from a given block.
+    pub fn block_processed<'env>(
+        &self,
+        db_txn: &mut RwTransaction<'env>,
+        monitor_id: &MonitorId,
+        view_private_key: &RistrettoPrivate,
+        block_index: u64,
+        discovered_utxos: &[UnspentTxOut],
+        spent_utxos: &[UnspentTxOut],
+    ) -> Result<(), Error> {
+        let received = discovered_utxos
+            .iter()
+            .map(|utxo| (utxo, ProcessedTxOutDirection::Received));
+        let spent = spent_utxos
+            .iter()
+            .map(|utxo| (utxo, ProcessedTxOutDirection::Spent));
+
+        for (utxo, direction) in received.chain(spent) {
+            let entry = TxHistoryEntry::new(utxo, direction, block_index, view_private_key)?;
+            db_txn.put(
+                self.tx_history_key_to_tx_history_entry,
+                &entry.key(monitor_id).to_vec(),
+                &mc_util_serial::encode(&entry),
+                WriteFlags::empty(),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{monitor_store::MonitorData, test_utils::BlockVersion};
+    use mc_account_keys::{AccountKey, ShortAddressHash};
+    use mc_common::logger::{test_with_logger, Logger};
+    use mc_crypto_rand::{CryptoRng, RngCore};
+    use mc_transaction_core::{
+        encrypted_fog_hint::EncryptedFogHint, tokens::Mob, tx::TxOut, Amount, MemoContext,
+        MemoPayload, NewMemoError, Token,
+    };
+    use mc_transaction_extra::{DestinationWithPaymentRequestIdMemo, UnusedMemo};
+    use mc_util_from_random::FromRandom;
+    use rand::{rngs::StdRng, SeedableRng};
+    use tempdir::TempDir;
+
+    fn setup_test_tx_history_store(logger: &Logger) -> (Arc<Environment>, TxHistoryStore, TempDir) {
+        let db_tmp = TempDir::new("tx_history_store_db")
+            .expect("Could not make tempdir for tx history store db");
+        let db_path = db_tmp
+            .path()
+            .to_str()
+            .expect("Could not get path as string");
+
+        let env = Arc::new(
+            Environment::new()
+                .set_max_dbs(10)
+                .set_map_size(10000000)
+                .open(db_path.as_ref())
+                .unwrap(),
+        );
+
+        let tx_history_store = TxHistoryStore::new(env.clone(), logger.clone()).unwrap();
+
+        (env, tx_history_store, db_tmp)
+    }
+
+    /// Create a utxo for the given account, with a memo created by `memo_fn`.
+    fn create_utxo(
+        account_key: &AccountKey,
+        value: u64,
+        memo_fn: impl FnOnce(MemoContext) -> Result<MemoPayload, NewMemoError>,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> UnspentTxOut {
+        let tx_out = TxOut::new_with_memo(
+            BlockVersion::MAX,
+            Amount::new(value, Mob::ID),
+            &account_key.default_subaddress(),
+            &RistrettoPrivate::from_random(rng),
+            EncryptedFogHint::fake_onetime_hint(rng),
+            memo_fn,
+        )
+        .unwrap();
+
+        UnspentTxOut {
+            tx_out,
+            subaddress_index: 0,
+            key_image: KeyImage::from(rng.next_u64()),
+            value,
+            attempted_spend_height: 0,
+            attempted_spend_tombstone: 0,
+            token_id: *Mob::ID,
+            key_image_unknown: false,
+        }
+    }
+
+    #[test_with_logger]
+    fn test_tx_history_store(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([123u8; 32]);
+        let (env, store, _db_tmp) = setup_test_tx_history_store(&logger);
+
+        let account_key = AccountKey::random(&mut rng);
+        let monitor_data = MonitorData::new(
+            account_key.clone(),
+            0,  // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .expect("failed to create data");
+        let monitor_id = MonitorId::try_from(&monitor_data).unwrap();
+
+        let other_monitor_data = MonitorData::new(
+            account_key.clone(),
+            30, // first_subaddress
+            20, // num_subaddresses
+            0,  // first_block
+            "", // name
+        )
+        .expect("failed to create data");
+        let other_monitor_id = MonitorId::try_from(&other_monitor_data).unwrap();
+
+        let recipient_hash =
+            ShortAddressHash::from(&AccountKey::random(&mut rng).default_subaddress());
+        let utxos = vec![
+            create_utxo(&account_key, 100, |_| Ok(UnusedMemo.into()), &mut rng),
+            create_utxo(
+                &account_key,
+                200,
+                |_| {
+                    let mut memo = DestinationWithPaymentRequestIdMemo::new(
+                        recipient_hash.clone(),
+                        10,
+                        1000,
+                        42,
+                    )
+                    .unwrap();
+                    memo.set_num_recipients(1);
+                    Ok(memo.into())
+                },
+                &mut rng,
+            ),
+            create_utxo(&account_key, 300, |_| Ok(UnusedMemo.into()), &mut rng),
+        ];
+
+        {
+            let mut db_txn = env.begin_rw_txn().unwrap();
+            store
+                .block_processed(
+                    &mut db_txn,
+                    &monitor_id,
+                    account_key.view_private_key(),
+                    5,
+                    &utxos[0..2],
+                    &[],
+                )
+                .unwrap();
+            store
+                .block_processed(
+                    &mut db_txn,
+                    &monitor_id,
+                    account_key.view_private_key(),
+                    7,
+                    &utxos[2..3],
+                    &utxos[0..1],
+                )
+                .unwrap();
+            store
+                .block_processed(
+                    &mut db_txn,
+                    &other_monitor_id,
+                    account_key.view_private_key(),
+                    6,
+                    &utxos[0..1],
+                    &[],
+                )
+                .unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        let db_txn = env.begin_ro_txn().unwrap();
+
+        // All entries are returned in block order.
+        let entries = store
+            .get_entries(
+                &db_txn,
+                &TxHistoryKey::first_in_block(&monitor_id, 0),
+                false,
+                100,
+            )
+            .unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.block_index)
+                .collect::<Vec<_>>(),
+            vec![5, 5, 7, 7]
+        );
+
+        // The destination memo is decoded.
+        let entry = entries.iter().find(|entry| entry.value == 200).unwrap();
+        assert_eq!(
+            entry.memo_type,
+            TxHistoryMemoType::DestinationWithPaymentRequestId as i32
+        );
+        assert_eq!(
+            entry.address_hash,
+            Some(<[u8; 16]>::from(recipient_hash).to_vec())
+        );
+        assert_eq!(entry.payment_request_id, Some(42));
+        assert_eq!(entry.payment_intent_id, None);
+        assert_eq!(entry.direction, ProcessedTxOutDirection::Received as i32);
+
+        let entry = entries
+            .iter()
+            .find(|entry| entry.direction == ProcessedTxOutDirection::Spent as i32)
+            .unwrap();
+        assert_eq!(entry.block_index, 7);
+        assert_eq!(entry.value, 100);
+        assert_eq!(entry.memo_type, TxHistoryMemoType::Unused as i32);
+        assert_eq!(entry.address_hash, None);
+
+        // Starting at a later block skips earlier entries.
+        let entries = store
+            .get_entries(
+                &db_txn,
+                &TxHistoryKey::first_in_block(&monitor_id, 6),
+                false,
+                100,
+            )
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+
+        // Paging through the entries yields all of them, once.
+        let mut paged_entries = Vec::new();
+        let mut start_key = TxHistoryKey::first_in_block(&monitor_id, 0);
+        let mut exclude_start_key = false;
+        loop {
+            let entries = store
+                .get_entries(&db_txn, &start_key, exclude_start_key, 3)
+                .unwrap();
+            match entries.last() {
+                Some(entry) => {
+                    start_key = entry.key(&monitor_id);
+                    exclude_start_key = true;
+                }
+                None => break,
+            }
+            paged_entries.extend(entries);
+        }
+        assert_eq!(
+            paged_entries,
+            store
+                .get_entries(
+                    &db_txn,
+                    &TxHistoryKey::first_in_block(&monitor_id, 0),
+                    false,
+                    100
+                )
+                .unwrap()
+        );
+
+        // The other monitor only sees its own entry.
+        let entries = store
+            .get_entries(
+                &db_txn,
+                &TxHistoryKey::first_in_block(&other_monitor_id, 0),
+                false,
+                100,
+            )
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].block_index, 6);
+        drop(db_txn);
+
+        // Removing a monitor removes its entries only.
+        {
+            let mut db_txn = env.begin_rw_txn().unwrap();
+            store.remove(&mut db_txn, &monitor_id).unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        let db_txn = env.begin_ro_txn().unwrap();
+        assert!(store
+            .get_entries(
+                &db_txn,
+                &TxHistoryKey::first_in_block(&monitor_id, 0),
+                false,
+                100
+            )
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store
+                .get_entries(
+                    &db_txn,
+                    &TxHistoryKey::first_in_block(&other_monitor_id, 0),
+                    false,
+                    100
+                )
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
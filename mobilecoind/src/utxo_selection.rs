@@ -0,0 +1,373 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Strategies for choosing which UTXOs a transaction spends.
+
+use crate::{error::Error, utxo_store::UnspentTxOut};
+use rand::seq::SliceRandom;
+use std::cmp::Reverse;
+
+/// The default number of search steps taken by [BranchAndBoundUtxoSelection]
+/// before it gives up looking for an exact match.
+pub const DEFAULT_BRANCH_AND_BOUND_MAX_TRIES: usize = 100_000;
+
+/// A strategy for choosing a subset of UTXOs to fund a transaction.
+pub trait UtxoSelectionStrategy: Send + Sync {
+    /// Select a subset of `utxos` totalling at least `value`, using at most
+    /// `max_inputs` of them.
+    ///
+    /// Callers guarantee that all `utxos` are of the same token id, and that
+    /// the `max_inputs` largest of them total at least `value`.
+    fn select_utxos(
+        &self,
+        utxos: &[UnspentTxOut],
+        value: u64,
+        max_inputs: usize,
+    ) -> Result<Vec<UnspentTxOut>, Error>;
+}
+
+/// Spends the smallest UTXOs that cover the value. If that would take more
+/// than `max_inputs` UTXOs, the smallest selected ones are swapped for larger
+/// ones. This merges small UTXOs over time, and is the default strategy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmallestFirstUtxoSelection;
+
+impl UtxoSelectionStrategy for SmallestFirstUtxoSelection {
+    fn select_utxos(
+        &self,
+        utxos: &[UnspentTxOut],
+        value: u64,
+        max_inputs: usize,
+    ) -> Result<Vec<UnspentTxOut>, Error> {
+        // Sort the utxos in descending order by value.
+        let mut sorted_utxos = utxos.to_vec();
+        sorted_utxos.sort_by_key(|utxo| Reverse(utxo.value));
+
+        let mut selected_utxos: Vec<UnspentTxOut> = Vec::new();
+        loop {
+            let total: u64 = selected_utxos.iter().map(|utxo| utxo.value).sum();
+            if total >= value {
+                break;
+            }
+
+            // Grab the next (smallest utxo)
+            let next_utxo = sorted_utxos.pop().ok_or(Error::InsufficientFunds)?;
+            selected_utxos.push(next_utxo);
+
+            // Cap at maximum allowed inputs.
+            if selected_utxos.len() > max_inputs {
+                // Remove the lowest utxo.
+                selected_utxos.remove(0);
+            }
+        }
+
+        Ok(selected_utxos)
+    }
+}
+
+/// Spends the largest UTXOs first. This uses as few inputs as possible, which
+/// keeps transactions small but leaves small UTXOs unspent.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LargestFirstUtxoSelection;
+
+impl UtxoSelectionStrategy for LargestFirstUtxoSelection {
+    fn select_utxos(
+        &self,
+        utxos: &[UnspentTxOut],
+        value: u64,
+        max_inputs: usize,
+    ) -> Result<Vec<UnspentTxOut>, Error> {
+        let mut sorted_utxos = utxos.to_vec();
+        sorted_utxos.sort_by_key(|utxo| Reverse(utxo.value));
+
+        let mut total = 0;
+        let mut selected_utxos = Vec::new();
+        for utxo in sorted_utxos.into_iter().take(max_inputs) {
+            if total >= value {
+                break;
+            }
+            total += utxo.value;
+            selected_utxos.push(utxo);
+        }
+
+        if total < value {
+            return Err(Error::InsufficientFundsFragmentedUtxos);
+        }
+        Ok(selected_utxos)
+    }
+}
+
+/// Searches for a subset of UTXOs whose total is exactly the value, so that
+/// the transaction needs no change output. The search is a depth-first branch
+/// and bound over the UTXOs sorted by descending value, and gives up after
+/// `max_tries` steps. If no exact match is found, the `fallback` strategy is
+/// used instead.
+#[derive(Clone, Copy, Debug)]
+pub struct BranchAndBoundUtxoSelection<S: UtxoSelectionStrategy = SmallestFirstUtxoSelection> {
+    /// The maximum number of search steps.
+    pub max_tries: usize,
+
+    /// The strategy used when there is no exact match.
+    pub fallback: S,
+}
+
+impl Default for BranchAndBoundUtxoSelection {
+    fn default() -> Self {
+        Self {
+            max_tries: DEFAULT_BRANCH_AND_BOUND_MAX_TRIES,
+            fallback: SmallestFirstUtxoSelection,
+        }
+    }
+}
+
+impl<S: UtxoSelectionStrategy> BranchAndBoundUtxoSelection<S> {
+    /// Returns the indices into `sorted_utxos` (sorted by descending value) of
+    /// a subset totalling exactly `value`, if one is found.
+    fn find_exact_match(
+        &self,
+        sorted_utxos: &[UnspentTxOut],
+        value: u64,
+        max_inputs: usize,
+    ) -> Option<Vec<usize>> {
+        // remaining[i] is the total value of sorted_utxos[i..].
+        let mut remaining = vec![0u64; sorted_utxos.len() + 1];
+        for (i, utxo) in sorted_utxos.iter().enumerate().rev() {
+            remaining[i] = remaining[i + 1].saturating_add(utxo.value);
+        }
+
+        let mut selected: Vec<usize> = Vec::new();
+        let mut total: u64 = 0;
+        let mut next = 0;
+        for _ in 0..self.max_tries {
+            if total == value {
+                return Some(selected);
+            }
+
+            let backtrack = total > value
+                || next >= sorted_utxos.len()
+                || selected.len() >= max_inputs
+                || total.saturating_add(remaining[next]) < value;
+
+            if backtrack {
+                // Undo the most recent inclusion, and continue without it. UTXOs of the
+                // same value lead to the same totals, so skip over them too.
+                let last = selected.pop()?;
+                total -= sorted_utxos[last].value;
+                next = last + 1;
+                while next < sorted_utxos.len()
+                    && sorted_utxos[next].value == sorted_utxos[last].value
+                {
+                    next += 1;
+                }
+            } else {
+                selected.push(next);
+                total += sorted_utxos[next].value;
+                next += 1;
+            }
+        }
+
+        None
+    }
+}
+
+impl<S: UtxoSelectionStrategy> UtxoSelectionStrategy for BranchAndBoundUtxoSelection<S> {
+    fn select_utxos(
+        &self,
+        utxos: &[UnspentTxOut],
+        value: u64,
+        max_inputs: usize,
+    ) -> Result<Vec<UnspentTxOut>, Error> {
+        let mut sorted_utxos = utxos.to_vec();
+        sorted_utxos.sort_by_key(|utxo| Reverse(utxo.value));
+
+        match self.find_exact_match(&sorted_utxos, value, max_inputs) {
+            Some(indices) => Ok(indices
+                .into_iter()
+                .map(|i| sorted_utxos[i].clone())
+                .collect()),
+            None => self.fallback.select_utxos(utxos, value, max_inputs),
+        }
+    }
+}
+
+/// Spends UTXOs in a random order, so that which UTXOs a wallet spends reveals
+/// as little as possible about the wallet. If that would take more than
+/// `max_inputs` UTXOs, the smallest selected ones are swapped for others.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomUtxoSelection;
+
+impl UtxoSelectionStrategy for RandomUtxoSelection {
+    fn select_utxos(
+        &self,
+        utxos: &[UnspentTxOut],
+        value: u64,
+        max_inputs: usize,
+    ) -> Result<Vec<UnspentTxOut>, Error> {
+        let mut shuffled_utxos = utxos.to_vec();
+        shuffled_utxos.shuffle(&mut rand::thread_rng());
+
+        let mut total = 0;
+        let mut selected_utxos: Vec<UnspentTxOut> = Vec::new();
+        for utxo in shuffled_utxos {
+            if total >= value {
+                break;
+            }
+            total += utxo.value;
+            selected_utxos.push(utxo);
+
+            // Cap at maximum allowed inputs.
+            if selected_utxos.len() > max_inputs {
+                let (smallest_index, smallest_utxo) = selected_utxos
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, utxo)| utxo.value)
+                    .expect("selected_utxos is not empty");
+                total -= smallest_utxo.value;
+                selected_utxos.remove(smallest_index);
+            }
+        }
+
+        if total < value {
+            return Err(Error::InsufficientFundsFragmentedUtxos);
+        }
+        Ok(selected_utxos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_account_keys::AccountKey;
+    use mc_crypto_keys::RistrettoPrivate;
+    use mc_transaction_core::{tokens::Mob, tx::TxOut, Amount, BlockVersion, Token};
+    use mc_util_from_random::FromRandom;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn generate_utxos(values: &[u64]) -> Vec<UnspentTxOut> {
+        let mut rng: StdRng = SeedableRng::from_seed([1u8; 32]);
+        let alice = AccountKey::random(&mut rng);
+
+        values
+            .iter()
+            .map(|value| {
+                let tx_out = TxOut::new(
+                    BlockVersion::MAX,
+                    Amount::new(*value, Mob::ID),
+                    &alice.default_subaddress(),
+                    &RistrettoPrivate::from_random(&mut rng),
+                    Default::default(),
+                )
+                .unwrap();
+
+                UnspentTxOut {
+                    tx_out,
+                    subaddress_index: 0,
+                    key_image: Default::default(),
+                    value: *value,
+                    attempted_spend_height: 0,
+                    attempted_spend_tombstone: 0,
+                    token_id: *Mob::ID,
+                    key_image_unknown: false,
+                }
+            })
+            .collect()
+    }
+
+    fn values(utxos: &[UnspentTxOut]) -> Vec<u64> {
+        let mut values: Vec<u64> = utxos.iter().map(|utxo| utxo.value).collect();
+        values.sort_unstable();
+        values
+    }
+
+    #[test]
+    fn test_smallest_first() {
+        let utxos = generate_utxos(&[100, 200, 300, 2000, 1000]);
+
+        let selected = SmallestFirstUtxoSelection
+            .select_utxos(&utxos, 300, utxos.len())
+            .unwrap();
+        assert_eq!(values(&selected), vec![100, 200]);
+
+        let selected = SmallestFirstUtxoSelection
+            .select_utxos(&utxos, 301, 2)
+            .unwrap();
+        assert_eq!(values(&selected), vec![200, 300]);
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let utxos = generate_utxos(&[100, 200, 300, 2000, 1000]);
+
+        let selected = LargestFirstUtxoSelection
+            .select_utxos(&utxos, 300, utxos.len())
+            .unwrap();
+        assert_eq!(values(&selected), vec![2000]);
+
+        let selected = LargestFirstUtxoSelection
+            .select_utxos(&utxos, 2500, utxos.len())
+            .unwrap();
+        assert_eq!(values(&selected), vec![1000, 2000]);
+
+        assert!(matches!(
+            LargestFirstUtxoSelection.select_utxos(&utxos, 3500, 2),
+            Err(Error::InsufficientFundsFragmentedUtxos)
+        ));
+    }
+
+    #[test]
+    fn test_branch_and_bound_finds_exact_match() {
+        let utxos = generate_utxos(&[100, 200, 300, 2000, 1000, 700]);
+
+        // 1000 + 300 is the only way to make 1300 with 2 inputs.
+        let selected = BranchAndBoundUtxoSelection::default()
+            .select_utxos(&utxos, 1300, 2)
+            .unwrap();
+        assert_eq!(values(&selected), vec![300, 1000]);
+
+        // 100 + 200 + 700 == 1000 is also exact, but a single input is found first.
+        let selected = BranchAndBoundUtxoSelection::default()
+            .select_utxos(&utxos, 1000, utxos.len())
+            .unwrap();
+        assert_eq!(values(&selected), vec![1000]);
+
+        // 3000 needs 2000 + 1000, or 2000 + 700 + 300.
+        let selected = BranchAndBoundUtxoSelection::default()
+            .select_utxos(&utxos, 3000, utxos.len())
+            .unwrap();
+        assert_eq!(selected.iter().map(|utxo| utxo.value).sum::<u64>(), 3000);
+    }
+
+    #[test]
+    fn test_branch_and_bound_falls_back() {
+        let utxos = generate_utxos(&[100, 200, 300, 2000, 1000]);
+
+        // Nothing adds up to 301, so the smallest-first fallback is used.
+        let selected = BranchAndBoundUtxoSelection::default()
+            .select_utxos(&utxos, 301, utxos.len())
+            .unwrap();
+        assert_eq!(values(&selected), vec![100, 200, 300]);
+
+        // The fallback is also used when the search runs out of tries.
+        let strategy = BranchAndBoundUtxoSelection {
+            max_tries: 1,
+            fallback: LargestFirstUtxoSelection,
+        };
+        let selected = strategy.select_utxos(&utxos, 300, utxos.len()).unwrap();
+        assert_eq!(values(&selected), vec![2000]);
+    }
+
+    #[test]
+    fn test_random() {
+        let utxos = generate_utxos(&[100, 200, 300, 2000, 1000, 700, 50, 25]);
+
+        for value in [1, 300, 1000, 2500, 3000] {
+            for max_inputs in [2, 4, utxos.len()] {
+                let selected = RandomUtxoSelection
+                    .select_utxos(&utxos, value, max_inputs)
+                    .unwrap();
+                assert!(!selected.is_empty());
+                assert!(selected.len() <= max_inputs);
+                assert!(selected.iter().map(|utxo| utxo.value).sum::<u64>() >= value);
+            }
+        }
+    }
+}
@@ -13,6 +13,7 @@ use mc_common::{
     logger::{log, Logger},
     HashMap,
 };
+use mc_crypto_keys::CompressedRistrettoPublic;
 use mc_transaction_core::{ring_signature::KeyImage, tx::TxOut};
 use mc_util_serial::Message;
 use std::sync::Arc;
@@ -60,6 +61,22 @@ pub struct UnspentTxOut {
     /// The token id of this TxOut
     #[prost(uint64, tag = "7")]
     pub token_id: u64,
+
+    /// True if this TxOut belongs to a view-only monitor and its real key
+    /// image has not been supplied yet. In that case `key_image` holds a
+    /// placeholder (see `UnspentTxOut::placeholder_key_image`) and the spent
+    /// status of this TxOut is unknown.
+    #[prost(bool, tag = "8")]
+    pub key_image_unknown: bool,
+}
+
+impl UnspentTxOut {
+    /// The placeholder key image used for TxOuts whose real key image is
+    /// unknown. TxOut public keys are unique in the ledger, so this keeps
+    /// UtxoIds unique, and it will never match a key image spent in a block.
+    pub fn placeholder_key_image(tx_out_public_key: &CompressedRistrettoPublic) -> KeyImage {
+        KeyImage::from(*tx_out_public_key.as_bytes())
+    }
 }
 
 /// Type used as the key in the utxo_id_to_utxo  database.
@@ -385,6 +402,33 @@ impl UtxoStore {
         Ok(())
     }
 
+    /// Replace the placeholder key image of an UnspentTxOut whose key image
+    /// is unknown with its real key image, and return the updated
+    /// UnspentTxOut.
+    pub fn set_key_image<'env>(
+        &self,
+        db_txn: &mut RwTransaction<'env>,
+        monitor_id: &MonitorId,
+        placeholder_key_image: &KeyImage,
+        key_image: &KeyImage,
+    ) -> Result<UnspentTxOut, Error> {
+        let mut removed_utxos =
+            self.remove_utxos_by_key_images(db_txn, monitor_id, &[*placeholder_key_image])?;
+        let mut utxo = removed_utxos.pop().ok_or(Error::UtxoIdNotFound)?;
+        if !utxo.key_image_unknown {
+            return Err(Error::InvalidArgument(
+                "placeholder_key_image".to_string(),
+                "utxo key image is already known".to_string(),
+            ));
+        }
+
+        utxo.key_image = *key_image;
+        utxo.key_image_unknown = false;
+        self.append_utxo(db_txn, monitor_id, utxo.subaddress_index, &utxo)?;
+
+        Ok(utxo)
+    }
+
     /// Get all UtxoIds associated with a given subaddress.
     fn get_utxo_ids(
         &self,
@@ -455,6 +499,7 @@ mod test {
                     attempted_spend_height: 0,
                     attempted_spend_tombstone: 0,
                     token_id: *Mob::ID,
+                    key_image_unknown: false,
                 }
             })
             .collect();
@@ -977,4 +1022,78 @@ mod test {
             }
         }
     }
+
+    /// set_key_image replaces placeholder key images of view-only utxos.
+    #[test_with_logger]
+    fn test_set_key_image(logger: Logger) {
+        let mut rng: StdRng = SeedableRng::from_seed([123u8; 32]);
+        let (env, _ledger_db, utxo_store, utxos) = setup_test_utxo_store(&mut rng, &logger);
+
+        let (_monitor_data, monitor_id0) = get_test_monitor_data_and_id(&mut rng);
+        let (_monitor_data, monitor_id1) = get_test_monitor_data_and_id(&mut rng);
+
+        // Turn the first utxo into one with an unknown key image.
+        let real_key_image = utxos[0].key_image;
+        let mut view_only_utxo = utxos[0].clone();
+        view_only_utxo.key_image =
+            UnspentTxOut::placeholder_key_image(&view_only_utxo.tx_out.public_key);
+        view_only_utxo.key_image_unknown = true;
+        let placeholder = view_only_utxo.key_image;
+
+        {
+            let mut db_txn = env.begin_rw_txn().unwrap();
+            utxo_store
+                .append_utxo(&mut db_txn, &monitor_id0, 0, &view_only_utxo)
+                .unwrap();
+            utxo_store
+                .append_utxo(&mut db_txn, &monitor_id0, 0, &utxos[1])
+                .unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        // Setting the key image using the wrong monitor should fail.
+        {
+            let mut db_txn = env.begin_rw_txn().unwrap();
+            assert!(matches!(
+                utxo_store.set_key_image(&mut db_txn, &monitor_id1, &placeholder, &real_key_image),
+                Err(Error::UtxoIdNotFound)
+            ));
+        }
+
+        // Setting the key image of a utxo whose key image is known should fail.
+        {
+            let mut db_txn = env.begin_rw_txn().unwrap();
+            assert!(matches!(
+                utxo_store.set_key_image(
+                    &mut db_txn,
+                    &monitor_id0,
+                    &utxos[1].key_image,
+                    &KeyImage::from(1234)
+                ),
+                Err(Error::InvalidArgument(_, _))
+            ));
+        }
+
+        // Setting it correctly re-keys the utxo.
+        {
+            let mut db_txn = env.begin_rw_txn().unwrap();
+            let updated = utxo_store
+                .set_key_image(&mut db_txn, &monitor_id0, &placeholder, &real_key_image)
+                .unwrap();
+            assert_eq!(updated, utxos[0]);
+            db_txn.commit().unwrap();
+        }
+
+        {
+            let db_txn = env.begin_ro_txn().unwrap();
+            assert_eq!(
+                HashSet::from_iter(utxo_store.get_utxos(&db_txn, &monitor_id0, 0).unwrap()),
+                HashSet::from_iter(vec![utxos[0].clone(), utxos[1].clone()])
+            );
+            assert!(matches!(
+                utxo_store.get_subaddress_id_by_utxo_id(&db_txn, &UtxoId::from(&placeholder)),
+                Err(Error::UtxoIdNotFound)
+            ));
+        }
+    }
 }
@@ -7,8 +7,11 @@
 use displaydoc::Display;
 use mc_watcher::{
     config::WatcherConfig,
+    divergence::CommandAlertHook,
     verification_reports_collector::VerificationReportsCollector,
     watcher::{SyncResult, Watcher},
+    watcher_api_grpc::create_watcher_api,
+    watcher_api_service::WatcherApiService,
     watcher_db::create_or_open_rw_watcher_db,
 };
 
@@ -39,11 +42,18 @@ fn main() {
         logger.clone(),
     )
     .expect("Could not create or open watcher db");
-    let watcher = Watcher::new(watcher_db.clone(), config.store_block_data, logger.clone())
+    let mut watcher = Watcher::new(watcher_db.clone(), config.store_block_data, logger.clone())
         .expect("Failed creating watcher");
+    watcher.set_stalled_source_threshold(config.stalled_source_threshold);
+    if let Some(alert_command) = &config.alert_command {
+        watcher.set_alert_hook(Arc::new(CommandAlertHook::new(
+            alert_command.clone(),
+            logger.clone(),
+        )));
+    }
 
     let _verification_reports_collector = <VerificationReportsCollector>::new(
-        watcher_db,
+        watcher_db.clone(),
         sources_config.sources().to_vec(),
         config.poll_interval,
         logger.clone(),
@@ -58,6 +68,8 @@ fn main() {
         Arc::new(move |_| HealthCheckStatus::SERVING);
     let health_service =
         HealthService::new(Some(health_check_callback), logger.clone()).into_service();
+    let watcher_api_service =
+        create_watcher_api(WatcherApiService::new(watcher_db, logger.clone()));
 
     let env = Arc::new(
         EnvBuilder::new()
@@ -67,6 +79,7 @@ fn main() {
 
     let server_builder = ServerBuilder::new(env)
         .register_service(health_service)
+        .register_service(watcher_api_service)
         .bind_using_uri(&config.client_listen_uri, logger.clone());
 
     let mut server = server_builder.build().unwrap();
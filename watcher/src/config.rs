@@ -44,6 +44,18 @@ pub struct WatcherConfig {
         env = "MC_CLIENT_LISTEN_URI"
     )]
     pub client_listen_uri: WatcherUri,
+
+    /// Number of blocks a source may lag behind the most advanced source
+    /// before it is reported as stalled.
+    #[clap(long, default_value = "100", env = "MC_STALLED_SOURCE_THRESHOLD")]
+    pub stalled_source_threshold: u64,
+
+    /// (Optional) Command to run whenever sources publish different versions
+    /// of a block, or a source stalls or recovers. The alert kind and
+    /// description are passed in the `MC_WATCHER_ALERT_KIND` and
+    /// `MC_WATCHER_ALERT` environment variables.
+    #[clap(long, env = "MC_ALERT_COMMAND")]
+    pub alert_command: Option<PathBuf>,
 }
 
 impl WatcherConfig {
@@ -0,0 +1,445 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Detection of sources that publish a different version of a block than the
+//! other watched sources, or that fall behind them.
+
+use crate::{
+    error::WatcherDBError,
+    metrics::WatcherMetrics,
+    watcher_db::{BlockDivergenceData, BlockIdentityData, StalledSourceData, WatcherDB},
+};
+use displaydoc::Display;
+use mc_blockchain_types::BlockIndex;
+use mc_common::{
+    logger::{log, Logger},
+    HashMap, HashSet,
+};
+use std::{
+    path::PathBuf,
+    process::Command,
+    sync::Arc,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use url::Url;
+
+/// Default number of blocks a source may lag behind the most advanced source
+/// before it is considered stalled.
+pub const DEFAULT_STALLED_SOURCE_THRESHOLD: u64 = 100;
+
+/// An event the operators of the watcher should be notified about.
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum WatcherAlert {
+    /// Sources published {num_variants} different versions of block
+    /// {block_index}: {identities:?}
+    Divergence {
+        /// The divergent block.
+        block_index: BlockIndex,
+
+        /// The number of distinct block versions.
+        num_variants: usize,
+
+        /// The identity published by each source.
+        identities: Vec<BlockIdentityData>,
+    },
+
+    /// Source {src_url} is stalled at block {last_synced_block:?} while
+    /// other sources reached block {highest_synced_block}
+    SourceStalled {
+        /// The stalled source.
+        src_url: Url,
+
+        /// The last block synced from the source.
+        last_synced_block: Option<BlockIndex>,
+
+        /// The highest block synced from any source.
+        highest_synced_block: BlockIndex,
+    },
+
+    /// Source {src_url} caught up with the other sources
+    SourceRecovered {
+        /// The recovered source.
+        src_url: Url,
+    },
+}
+
+impl WatcherAlert {
+    /// A short machine-readable name for the kind of alert.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Divergence { .. } => "divergence",
+            Self::SourceStalled { .. } => "source_stalled",
+            Self::SourceRecovered { .. } => "source_recovered",
+        }
+    }
+}
+
+/// Receives the alerts raised by the [DivergenceDetector].
+pub trait AlertHook: Send + Sync {
+    /// Handle an alert.
+    fn alert(&self, alert: &WatcherAlert);
+}
+
+/// An alert hook that runs an external command for every alert.
+///
+/// The command receives the alert kind in the `MC_WATCHER_ALERT_KIND`
+/// environment variable and its description in `MC_WATCHER_ALERT`. It runs in
+/// the background so that a slow command does not hold up syncing.
+pub struct CommandAlertHook {
+    command: PathBuf,
+    logger: Logger,
+}
+
+impl CommandAlertHook {
+    /// Create a hook running `command`.
+    pub fn new(command: PathBuf, logger: Logger) -> Self {
+        Self { command, logger }
+    }
+}
+
+impl AlertHook for CommandAlertHook {
+    fn alert(&self, alert: &WatcherAlert) {
+        let mut command = Command::new(&self.command);
+        command
+            .env("MC_WATCHER_ALERT_KIND", alert.kind())
+            .env("MC_WATCHER_ALERT", alert.to_string());
+
+        let logger = self.logger.clone();
+        let result =
+            thread::Builder::new()
+                .name("WatcherAlert".into())
+                .spawn(move || match command.status() {
+                    Ok(status) if status.success() => {}
+                    Ok(status) => {
+                        log::error!(logger, "Alert command {:?} failed: {}", command, status)
+                    }
+                    Err(err) => log::error!(
+                        logger,
+                        "Failed running alert command {:?}: {}",
+                        command,
+                        err
+                    ),
+                });
+        if let Err(err) = result {
+            log::error!(self.logger, "Failed spawning alert thread: {}", err);
+        }
+    }
+}
+
+/// Compares the blocks published by the watched sources, and keeps track of
+/// sources that stop making progress.
+pub struct DivergenceDetector {
+    watcher_db: WatcherDB,
+    stalled_source_threshold: u64,
+    alert_hook: Option<Arc<dyn AlertHook>>,
+    metrics: WatcherMetrics,
+    logger: Logger,
+}
+
+impl DivergenceDetector {
+    /// Create a new divergence detector.
+    ///
+    /// # Arguments
+    /// * `watcher_db` - The database holding the synced block identities
+    /// * `stalled_source_threshold` - The number of blocks a source may lag
+    ///   behind the most advanced source before it is considered stalled
+    /// * `alert_hook` - Optional hook to notify of divergences and stalls
+    /// * `logger` - Logger
+    pub fn new(
+        watcher_db: WatcherDB,
+        stalled_source_threshold: u64,
+        alert_hook: Option<Arc<dyn AlertHook>>,
+        logger: Logger,
+    ) -> Self {
+        Self {
+            watcher_db,
+            stalled_source_threshold,
+            alert_hook,
+            metrics: WatcherMetrics::new(),
+            logger,
+        }
+    }
+
+    /// Set the number of blocks a source may lag behind before it is
+    /// considered stalled.
+    pub fn set_stalled_source_threshold(&mut self, stalled_source_threshold: u64) {
+        self.stalled_source_threshold = stalled_source_threshold;
+    }
+
+    /// Set the hook notified of divergences and stalls.
+    pub fn set_alert_hook(&mut self, alert_hook: Arc<dyn AlertHook>) {
+        self.alert_hook = Some(alert_hook);
+    }
+
+    /// Compare the identities the sources published for a block. If they
+    /// differ, the divergence is recorded and returned, and an alert is raised
+    /// whenever a new version of the block shows up.
+    pub fn check_block(
+        &self,
+        block_index: BlockIndex,
+    ) -> Result<Option<BlockDivergenceData>, WatcherDBError> {
+        let identities = self.watcher_db.get_block_identities(block_index)?;
+        let num_variants = count_variants(&identities);
+        if num_variants <= 1 {
+            return Ok(None);
+        }
+
+        let previous = self.watcher_db.get_divergence(block_index)?;
+        if let Some(previous) = &previous {
+            if previous.identities == identities {
+                return Ok(Some(previous.clone()));
+            }
+        }
+
+        let divergence = BlockDivergenceData {
+            block_index,
+            identities: identities.clone(),
+            detected_at: previous
+                .as_ref()
+                .map(|previous| previous.detected_at)
+                .unwrap_or_else(now),
+        };
+        self.watcher_db.put_divergence(&divergence)?;
+
+        let previous_num_variants = previous
+            .as_ref()
+            .map(|previous| count_variants(&previous.identities))
+            .unwrap_or(1);
+        if previous.is_none() {
+            self.metrics.inc_divergent_blocks(block_index);
+        }
+        if num_variants > previous_num_variants {
+            self.raise(WatcherAlert::Divergence {
+                block_index,
+                num_variants,
+                identities,
+            });
+        }
+
+        Ok(Some(divergence))
+    }
+
+    /// Mark sources that lag too far behind the most advanced source as
+    /// stalled, and clear the mark of sources that caught up. Returns the
+    /// currently stalled sources.
+    pub fn check_stalled_sources(&self) -> Result<Vec<Url>, WatcherDBError> {
+        let last_synced = self.watcher_db.last_synced_blocks()?;
+        let highest_synced_block = match last_synced.values().flatten().max() {
+            Some(highest_synced_block) => *highest_synced_block,
+            None => return Ok(Vec::new()),
+        };
+        let previously_stalled = self.watcher_db.get_stalled_sources()?;
+
+        let mut stalled_by_url = HashMap::default();
+        for (src_url, last_synced_block) in last_synced {
+            let lag = match last_synced_block {
+                Some(last_synced_block) => highest_synced_block - last_synced_block,
+                None => highest_synced_block + 1,
+            };
+            let stalled = lag > self.stalled_source_threshold;
+
+            match (stalled, previously_stalled.contains_key(&src_url)) {
+                (true, false) => {
+                    self.watcher_db.put_stalled_source(&StalledSourceData {
+                        src_url: src_url.to_string(),
+                        last_synced_block,
+                        highest_synced_block,
+                        stalled_since: now(),
+                    })?;
+                    self.raise(WatcherAlert::SourceStalled {
+                        src_url: src_url.clone(),
+                        last_synced_block,
+                        highest_synced_block,
+                    });
+                }
+                (false, true) => {
+                    self.watcher_db.remove_stalled_source(&src_url)?;
+                    self.raise(WatcherAlert::SourceRecovered {
+                        src_url: src_url.clone(),
+                    });
+                }
+                _ => {}
+            }
+
+            stalled_by_url.insert(src_url, stalled);
+        }
+        self.metrics.collect_stalled_sources(&stalled_by_url);
+
+        let mut stalled = stalled_by_url
+            .into_iter()
+            .filter(|(_src_url, stalled)| *stalled)
+            .map(|(src_url, _stalled)| src_url)
+            .collect::<Vec<_>>();
+        stalled.sort();
+        Ok(stalled)
+    }
+
+    fn raise(&self, alert: WatcherAlert) {
+        log::error!(self.logger, "Watcher alert: {}", alert);
+        if let Some(alert_hook) = &self.alert_hook {
+            alert_hook.alert(&alert);
+        }
+    }
+}
+
+/// The number of distinct (block id, contents hash) pairs.
+fn count_variants(identities: &[BlockIdentityData]) -> usize {
+    identities
+        .iter()
+        .map(|identity| (&identity.block_id, &identity.contents_hash))
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher_db::tests::{setup_blocks, setup_watcher_db};
+    use mc_blockchain_types::{BlockContentsHash, BlockID};
+    use mc_common::logger::{test_with_logger, Logger};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct TestAlertHook {
+        alerts: Mutex<Vec<WatcherAlert>>,
+    }
+
+    impl AlertHook for TestAlertHook {
+        fn alert(&self, alert: &WatcherAlert) {
+            self.alerts.lock().unwrap().push(alert.clone());
+        }
+    }
+
+    impl TestAlertHook {
+        fn take(&self) -> Vec<WatcherAlert> {
+            std::mem::take(&mut self.alerts.lock().unwrap())
+        }
+    }
+
+    fn setup(
+        num_urls: usize,
+        logger: Logger,
+    ) -> (Vec<Url>, WatcherDB, DivergenceDetector, Arc<TestAlertHook>) {
+        let urls = (0..num_urls)
+            .map(|i| Url::parse(&format!("http://www.my_url{}.com", i)).unwrap())
+            .collect::<Vec<_>>();
+        let watcher_db = setup_watcher_db(&urls, logger.clone());
+        let alert_hook = Arc::new(TestAlertHook::default());
+        let detector = DivergenceDetector::new(
+            watcher_db.clone(),
+            2,
+            Some(alert_hook.clone() as Arc<dyn AlertHook>),
+            logger,
+        );
+        (urls, watcher_db, detector, alert_hook)
+    }
+
+    #[test_with_logger]
+    fn test_check_block(logger: Logger) {
+        let (urls, watcher_db, detector, alert_hook) = setup(3, logger);
+        let blocks = setup_blocks();
+        let block = blocks[1].block();
+        let contents_hash = blocks[1].contents().hash();
+
+        // Matching identities are not a divergence.
+        for url in &urls[0..2] {
+            watcher_db
+                .add_block_identity(url, 1, &block.id, &contents_hash)
+                .unwrap();
+        }
+        assert_eq!(detector.check_block(1).unwrap(), None);
+        assert!(alert_hook.take().is_empty());
+
+        // A source publishing different contents for the same block ID diverges.
+        let forked_contents_hash = BlockContentsHash([9; 32]);
+        watcher_db
+            .add_block_identity(&urls[2], 1, &block.id, &forked_contents_hash)
+            .unwrap();
+        let divergence = detector.check_block(1).unwrap().unwrap();
+        assert_eq!(divergence.block_index, 1);
+        assert_eq!(divergence.identities.len(), 3);
+        assert_eq!(
+            watcher_db.get_divergence(1).unwrap(),
+            Some(divergence.clone())
+        );
+        assert_eq!(
+            watcher_db.get_divergences().unwrap(),
+            vec![divergence.clone()]
+        );
+
+        let alerts = alert_hook.take();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind(), "divergence");
+
+        // Checking again does not raise another alert.
+        assert_eq!(detector.check_block(1).unwrap(), Some(divergence));
+        assert!(alert_hook.take().is_empty());
+
+        // Different block IDs are a divergence too.
+        watcher_db
+            .add_block_identity(
+                &urls[0],
+                2,
+                &blocks[2].block().id,
+                &blocks[2].contents().hash(),
+            )
+            .unwrap();
+        watcher_db
+            .add_block_identity(&urls[1], 2, &BlockID([7; 32]), &blocks[2].contents().hash())
+            .unwrap();
+        assert!(detector.check_block(2).unwrap().is_some());
+        assert_eq!(alert_hook.take().len(), 1);
+        assert_eq!(watcher_db.get_divergences().unwrap().len(), 2);
+    }
+
+    #[test_with_logger]
+    fn test_check_stalled_sources(logger: Logger) {
+        let (urls, watcher_db, detector, alert_hook) = setup(3, logger);
+
+        // Nothing synced yet.
+        assert!(detector.check_stalled_sources().unwrap().is_empty());
+
+        watcher_db.update_last_synced(&urls[0], 5).unwrap();
+        watcher_db.update_last_synced(&urls[1], 3).unwrap();
+        assert_eq!(
+            detector.check_stalled_sources().unwrap(),
+            vec![urls[2].clone()]
+        );
+        assert_eq!(
+            alert_hook.take(),
+            vec![WatcherAlert::SourceStalled {
+                src_url: urls[2].clone(),
+                last_synced_block: None,
+                highest_synced_block: 5,
+            }]
+        );
+
+        watcher_db.update_last_synced(&urls[0], 6).unwrap();
+        watcher_db.update_last_synced(&urls[2], 6).unwrap();
+        assert_eq!(
+            detector.check_stalled_sources().unwrap(),
+            vec![urls[1].clone()]
+        );
+        let stalled_sources = watcher_db.get_stalled_sources().unwrap();
+        assert_eq!(stalled_sources.len(), 1);
+        assert_eq!(stalled_sources[&urls[1]].last_synced_block, Some(3));
+
+        let alerts = alert_hook.take();
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts.contains(&WatcherAlert::SourceRecovered {
+            src_url: urls[2].clone()
+        }));
+        assert!(alerts.iter().any(|alert| alert.kind() == "source_stalled"));
+
+        // Removing the source clears its stalled status.
+        watcher_db.remove_all_for_source_url(&urls[1]).unwrap();
+        assert!(watcher_db.get_stalled_sources().unwrap().is_empty());
+    }
+}
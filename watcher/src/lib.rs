@@ -4,10 +4,22 @@
 #![deny(missing_docs)]
 #![forbid(unsafe_code)]
 
+mod autogenerated_code {
+    // Expose proto data types from included third-party/external proto files.
+    pub use mc_api::{blockchain, external};
+
+    // Include the auto-generated code.
+    include!(concat!(env!("OUT_DIR"), "/protos-auto-gen/mod.rs"));
+}
+pub use autogenerated_code::*;
+
 pub mod block_data_store;
 pub mod config;
+pub mod divergence;
 pub mod error;
 pub mod metrics;
+pub mod signer_timeline;
 pub mod verification_reports_collector;
 pub mod watcher;
+pub mod watcher_api_service;
 pub mod watcher_db;
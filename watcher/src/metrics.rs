@@ -3,7 +3,7 @@
 //! Watcher metrics comparing ledger height and block height
 
 use mc_common::HashMap;
-use mc_util_metrics::{IntGauge, OpMetrics};
+use mc_util_metrics::{IntCounter, IntGauge, OpMetrics};
 use url::Url;
 
 lazy_static::lazy_static! {
@@ -15,6 +15,15 @@ lazy_static::lazy_static! {
 pub struct WatcherMetrics {
     /// Number of blocks in the ledger
     ledger_block_height: IntGauge,
+
+    /// Number of blocks for which sources published different identities
+    divergent_blocks: IntCounter,
+
+    /// Index of the most recent divergent block
+    last_divergent_block: IntGauge,
+
+    /// Number of sources that fell behind the other sources
+    stalled_sources: IntGauge,
 }
 
 impl Default for WatcherMetrics {
@@ -27,8 +36,14 @@ impl WatcherMetrics {
     /// Initialize new metrics object
     pub fn new() -> Self {
         let ledger_block_height = COLLECTOR.gauge("ledger_block_height");
+        let divergent_blocks = COLLECTOR.counter("divergent_blocks");
+        let last_divergent_block = COLLECTOR.gauge("last_divergent_block");
+        let stalled_sources = COLLECTOR.gauge("stalled_sources");
         Self {
             ledger_block_height,
+            divergent_blocks,
+            last_divergent_block,
+            stalled_sources,
         }
     }
 
@@ -45,4 +60,21 @@ impl WatcherMetrics {
                 .set(num_blocks.unwrap_or(0) as i64);
         });
     }
+
+    /// Record a newly detected divergent block
+    pub fn inc_divergent_blocks(&self, block_index: u64) {
+        self.divergent_blocks.inc();
+        self.last_divergent_block.set(block_index as i64);
+    }
+
+    /// Record which sources are currently stalled
+    pub fn collect_stalled_sources(&self, stalled_by_url: &HashMap<Url, bool>) {
+        stalled_by_url.iter().for_each(|(url, stalled)| {
+            COLLECTOR
+                .peer_gauge("watcher_source_stalled", url.as_str())
+                .set(*stalled as i64);
+        });
+        self.stalled_sources
+            .set(stalled_by_url.values().filter(|stalled| **stalled).count() as i64);
+    }
 }
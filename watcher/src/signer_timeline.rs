@@ -0,0 +1,412 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Reports derived from the block signatures and verification reports stored
+//! in the watcher database: which key signed which blocks for each watched
+//! source, which blocks a source failed to sign, and how the attested enclave
+//! changed whenever a source rotated its signing key.
+
+use crate::{error::WatcherDBError, watcher_db::WatcherDB};
+use mc_attest_core::{
+    MrEnclave, MrSigner, ProductId, SecurityVersion, VerificationReport, VerificationReportData,
+};
+use mc_blockchain_types::{BlockIndex, BlockSignature};
+use mc_crypto_keys::Ed25519Public;
+use std::ops::RangeInclusive;
+use url::Url;
+
+/// A run of signatures from a single source that were all made by the same
+/// signer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignerPeriod {
+    /// The block signer.
+    pub signer: Ed25519Public,
+
+    /// The first block signed by this signer during the period.
+    pub first_block: BlockIndex,
+
+    /// The last block signed by this signer during the period.
+    pub last_block: BlockIndex,
+
+    /// The number of blocks signed during the period. This is smaller than
+    /// the length of the period when the source skipped some blocks.
+    pub num_signed_blocks: u64,
+
+    /// The signing timestamp of the first block in the period.
+    pub first_signed_at: u64,
+
+    /// The signing timestamp of the last block in the period.
+    pub last_signed_at: u64,
+}
+
+/// The enclave identity attested to by a verification report.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EnclaveMeasurement {
+    /// The MRENCLAVE of the enclave.
+    pub mr_enclave: MrEnclave,
+
+    /// The MRSIGNER of the enclave.
+    pub mr_signer: MrSigner,
+
+    /// The enclave product ID.
+    pub product_id: ProductId,
+
+    /// The enclave security version.
+    pub security_version: SecurityVersion,
+}
+
+impl EnclaveMeasurement {
+    /// Extract the enclave measurement from a verification report. Returns
+    /// None if the report cannot be parsed.
+    pub fn from_report(report: &VerificationReport) -> Option<Self> {
+        let report_data = VerificationReportData::try_from(report).ok()?;
+        let report_body = report_data.quote.report_body().ok()?;
+        Some(Self {
+            mr_enclave: report_body.mr_enclave(),
+            mr_signer: report_body.mr_signer(),
+            product_id: report_body.product_id(),
+            security_version: report_body.security_version(),
+        })
+    }
+}
+
+/// A change of block signer for a source, along with the enclave measurements
+/// of the previous and new signers, when their verification reports are
+/// known.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AttestationChange {
+    /// The first block signed by the new signer.
+    pub block_index: BlockIndex,
+
+    /// The signer that was replaced.
+    pub previous_signer: Ed25519Public,
+
+    /// The new signer.
+    pub signer: Ed25519Public,
+
+    /// The enclave measurement of the previous signer.
+    pub previous_measurement: Option<EnclaveMeasurement>,
+
+    /// The enclave measurement of the new signer.
+    pub measurement: Option<EnclaveMeasurement>,
+}
+
+impl AttestationChange {
+    /// Whether the new signer runs a different enclave than the previous one.
+    /// This is only known when both signers have a verification report.
+    pub fn measurement_changed(&self) -> bool {
+        match (&self.previous_measurement, &self.measurement) {
+            (Some(previous), Some(current)) => previous != current,
+            _ => false,
+        }
+    }
+}
+
+/// The signing history of a single source over a range of blocks.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignerTimeline {
+    /// The source the signatures were synced from.
+    pub src_url: Url,
+
+    /// The last block synced from the source, if any.
+    pub last_synced_block: Option<BlockIndex>,
+
+    /// Signer periods, ordered by block index.
+    pub periods: Vec<SignerPeriod>,
+
+    /// Ranges of synced blocks that the source did not sign, ordered by block
+    /// index.
+    pub missing_signatures: Vec<RangeInclusive<BlockIndex>>,
+}
+
+impl SignerTimeline {
+    /// Build the timeline of a source over `first_block..=last_block`.
+    /// Blocks past the last block synced from the source are never reported
+    /// as missing.
+    pub fn load(
+        watcher_db: &WatcherDB,
+        src_url: &Url,
+        first_block: BlockIndex,
+        last_block: BlockIndex,
+    ) -> Result<Self, WatcherDBError> {
+        let last_synced_block = *watcher_db
+            .last_synced_blocks()?
+            .get(src_url)
+            .ok_or(WatcherDBError::NotFound)?;
+
+        let signatures = watcher_db
+            .get_block_signatures_in_range(first_block, last_block)?
+            .into_iter()
+            .filter(|(_, signature_data)| signature_data.src_url == src_url.as_str())
+            .map(|(block_index, signature_data)| (block_index, signature_data.block_signature))
+            .collect::<Vec<_>>();
+
+        let missing_signatures = match last_synced_block {
+            Some(last_synced_block) => missing_signature_ranges(
+                signatures.iter().map(|(block_index, _)| *block_index),
+                first_block,
+                last_block.min(last_synced_block),
+            ),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            src_url: src_url.clone(),
+            last_synced_block,
+            periods: signer_periods(signatures.iter().map(|(index, sig)| (*index, sig))),
+            missing_signatures,
+        })
+    }
+
+    /// Look up the enclave measurement of every signer change in the
+    /// timeline.
+    pub fn attestation_changes(
+        &self,
+        watcher_db: &WatcherDB,
+    ) -> Result<Vec<AttestationChange>, WatcherDBError> {
+        attestation_changes(&self.periods, |signer| {
+            let reports =
+                watcher_db.get_verification_report_for_signer_and_url(signer, &self.src_url)?;
+            Ok(reports
+                .iter()
+                .flatten()
+                .find_map(EnclaveMeasurement::from_report))
+        })
+    }
+}
+
+/// Group signatures, ordered by block index, into periods of consecutive
+/// signatures by the same signer.
+pub fn signer_periods<'a>(
+    signatures: impl IntoIterator<Item = (BlockIndex, &'a BlockSignature)>,
+) -> Vec<SignerPeriod> {
+    let mut periods: Vec<SignerPeriod> = Vec::new();
+    for (block_index, signature) in signatures {
+        match periods.last_mut() {
+            Some(period) if period.signer == *signature.signer() => {
+                period.last_block = block_index;
+                period.num_signed_blocks += 1;
+                period.last_signed_at = signature.signed_at();
+            }
+            _ => periods.push(SignerPeriod {
+                signer: *signature.signer(),
+                first_block: block_index,
+                last_block: block_index,
+                num_signed_blocks: 1,
+                first_signed_at: signature.signed_at(),
+                last_signed_at: signature.signed_at(),
+            }),
+        }
+    }
+    periods
+}
+
+/// Find the ranges of blocks in `first_block..=last_block` that are not in
+/// `signed_blocks`, which must be sorted.
+pub fn missing_signature_ranges(
+    signed_blocks: impl IntoIterator<Item = BlockIndex>,
+    first_block: BlockIndex,
+    last_block: BlockIndex,
+) -> Vec<RangeInclusive<BlockIndex>> {
+    let mut ranges = Vec::new();
+    if first_block > last_block {
+        return ranges;
+    }
+
+    let mut next_expected = first_block;
+    for block_index in signed_blocks {
+        if block_index < next_expected {
+            continue;
+        }
+        if block_index > last_block {
+            break;
+        }
+        if block_index > next_expected {
+            ranges.push(next_expected..=block_index - 1);
+        }
+        next_expected = block_index + 1;
+    }
+    if next_expected <= last_block {
+        ranges.push(next_expected..=last_block);
+    }
+    ranges
+}
+
+/// List the signer changes between consecutive periods, using
+/// `get_measurement` to look up the enclave measurement of each signer.
+pub fn attestation_changes<E>(
+    periods: &[SignerPeriod],
+    mut get_measurement: impl FnMut(&Ed25519Public) -> Result<Option<EnclaveMeasurement>, E>,
+) -> Result<Vec<AttestationChange>, E> {
+    periods
+        .windows(2)
+        .filter(|pair| pair[0].signer != pair[1].signer)
+        .map(|pair| {
+            Ok(AttestationChange {
+                block_index: pair[1].first_block,
+                previous_signer: pair[0].signer,
+                signer: pair[1].signer,
+                previous_measurement: get_measurement(&pair[0].signer)?,
+                measurement: get_measurement(&pair[1].signer)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher_db::tests::{setup_blocks, setup_watcher_db};
+    use mc_common::logger::{test_with_logger, Logger};
+    use mc_crypto_keys::Ed25519Pair;
+    use mc_util_from_random::FromRandom;
+    use mc_util_test_helper::run_with_one_seed;
+    use std::collections::HashMap;
+
+    fn measurement(byte: u8) -> EnclaveMeasurement {
+        EnclaveMeasurement {
+            mr_enclave: MrEnclave::from([byte; 32]),
+            mr_signer: MrSigner::from([7; 32]),
+            product_id: 1,
+            security_version: 1,
+        }
+    }
+
+    #[test]
+    fn test_missing_signature_ranges() {
+        assert_eq!(
+            missing_signature_ranges(vec![1, 2, 5, 6, 9], 0, 10),
+            vec![0..=0, 3..=4, 7..=8, 10..=10]
+        );
+        assert!(missing_signature_ranges(vec![3, 4, 5], 3, 5).is_empty());
+        assert_eq!(missing_signature_ranges(vec![1, 8], 3, 5), vec![3..=5]);
+        assert!(missing_signature_ranges(vec![], 5, 3).is_empty());
+    }
+
+    #[test]
+    fn test_attestation_changes() {
+        run_with_one_seed(|mut rng| {
+            let signers = (0..3)
+                .map(|_| Ed25519Pair::from_random(&mut rng).public_key())
+                .collect::<Vec<_>>();
+            let period = |signer: usize, first_block: BlockIndex| SignerPeriod {
+                signer: signers[signer],
+                first_block,
+                last_block: first_block + 9,
+                num_signed_blocks: 10,
+                first_signed_at: first_block,
+                last_signed_at: first_block + 9,
+            };
+            let periods = vec![period(0, 0), period(1, 10), period(2, 20)];
+
+            let measurements = HashMap::from([
+                (signers[0], measurement(1)),
+                (signers[1], measurement(1)),
+                (signers[2], measurement(2)),
+            ]);
+            let changes =
+                attestation_changes::<()>(&periods, |signer| Ok(measurements.get(signer).copied()))
+                    .unwrap();
+
+            assert_eq!(changes.len(), 2);
+            assert_eq!(changes[0].block_index, 10);
+            assert_eq!(changes[0].previous_signer, signers[0]);
+            assert_eq!(changes[0].signer, signers[1]);
+            assert!(!changes[0].measurement_changed());
+            assert_eq!(changes[1].block_index, 20);
+            assert!(changes[1].measurement_changed());
+
+            // Unknown measurements are not reported as changed.
+            let changes = attestation_changes::<()>(&periods, |_| Ok(None)).unwrap();
+            assert!(changes.iter().all(|change| !change.measurement_changed()));
+        });
+    }
+
+    #[test_with_logger]
+    fn test_signer_timeline(logger: Logger) {
+        run_with_one_seed(|mut rng| {
+            let url1 = Url::parse("http://www.my_url1.com").unwrap();
+            let url2 = Url::parse("http://www.my_url2.com").unwrap();
+            let watcher_db = setup_watcher_db(&[url1.clone(), url2.clone()], logger.clone());
+            let blocks = setup_blocks();
+
+            let signing_key_a = Ed25519Pair::from_random(&mut rng);
+            let signing_key_b = Ed25519Pair::from_random(&mut rng);
+            let signing_key_c = Ed25519Pair::from_random(&mut rng);
+
+            // url1 signs blocks 1-3 with key a, skips 4-5, then signs 6-7 with
+            // key b.
+            for (block_index, key) in [
+                (1, &signing_key_a),
+                (2, &signing_key_a),
+                (3, &signing_key_a),
+                (6, &signing_key_b),
+                (7, &signing_key_b),
+            ] {
+                let mut signature =
+                    BlockSignature::from_block_and_keypair(blocks[block_index].block(), key)
+                        .unwrap();
+                signature.set_signed_at(100 + block_index as u64);
+                watcher_db
+                    .add_block_signature(&url1, block_index as u64, signature, "00/00".into())
+                    .unwrap();
+            }
+            watcher_db.update_last_synced(&url1, 8).unwrap();
+
+            // url2 signs everything with key c.
+            for block_index in 1..=8 {
+                let signature = BlockSignature::from_block_and_keypair(
+                    blocks[block_index].block(),
+                    &signing_key_c,
+                )
+                .unwrap();
+                watcher_db
+                    .add_block_signature(&url2, block_index as u64, signature, "00/00".into())
+                    .unwrap();
+            }
+
+            let timeline = SignerTimeline::load(&watcher_db, &url1, 1, 10).unwrap();
+            assert_eq!(timeline.last_synced_block, Some(8));
+            assert_eq!(
+                timeline.periods,
+                vec![
+                    SignerPeriod {
+                        signer: signing_key_a.public_key(),
+                        first_block: 1,
+                        last_block: 3,
+                        num_signed_blocks: 3,
+                        first_signed_at: 101,
+                        last_signed_at: 103,
+                    },
+                    SignerPeriod {
+                        signer: signing_key_b.public_key(),
+                        first_block: 6,
+                        last_block: 7,
+                        num_signed_blocks: 2,
+                        first_signed_at: 106,
+                        last_signed_at: 107,
+                    },
+                ]
+            );
+            // Blocks 9 and 10 have not been synced yet.
+            assert_eq!(timeline.missing_signatures, vec![4..=5, 8..=8]);
+
+            // No verification reports are known, so the change has no measurements.
+            let changes = timeline.attestation_changes(&watcher_db).unwrap();
+            assert_eq!(changes.len(), 1);
+            assert_eq!(changes[0].block_index, 6);
+            assert_eq!(changes[0].previous_measurement, None);
+            assert_eq!(changes[0].measurement, None);
+
+            let timeline = SignerTimeline::load(&watcher_db, &url2, 2, 5).unwrap();
+            assert_eq!(timeline.periods.len(), 1);
+            assert_eq!(timeline.periods[0].num_signed_blocks, 4);
+            assert!(timeline.missing_signatures.is_empty());
+
+            let url3 = Url::parse("http://www.my_url3.com").unwrap();
+            assert_eq!(
+                SignerTimeline::load(&watcher_db, &url3, 1, 10),
+                Err(WatcherDBError::NotFound)
+            );
+        });
+    }
+}
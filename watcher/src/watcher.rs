@@ -3,6 +3,7 @@
 //! Basic Watcher Node
 
 use crate::{
+    divergence::{AlertHook, DivergenceDetector, DEFAULT_STALLED_SOURCE_THRESHOLD},
     error::{WatcherDBError, WatcherError},
     metrics::WatcherMetrics,
     watcher_db::WatcherDB,
@@ -14,7 +15,7 @@ use mc_ledger_db::Ledger;
 use mc_ledger_sync::ReqwestTransactionsFetcher;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -38,6 +39,7 @@ pub struct Watcher {
     transactions_fetcher_by_url: Arc<HashMap<Url, ReqwestTransactionsFetcher>>,
     watcher_db: WatcherDB,
     store_block_data: bool,
+    divergence_detector: DivergenceDetector,
     logger: Logger,
     metrics: WatcherMetrics,
 }
@@ -88,15 +90,35 @@ impl Watcher {
 
         let metrics = WatcherMetrics::new();
 
+        let divergence_detector = DivergenceDetector::new(
+            watcher_db.clone(),
+            DEFAULT_STALLED_SOURCE_THRESHOLD,
+            None,
+            logger.clone(),
+        );
+
         Ok(Self {
             transactions_fetcher_by_url,
             watcher_db,
             store_block_data,
+            divergence_detector,
             logger,
             metrics,
         })
     }
 
+    /// Set the number of blocks a source may lag behind the most advanced
+    /// source before it is reported as stalled.
+    pub fn set_stalled_source_threshold(&mut self, stalled_source_threshold: u64) {
+        self.divergence_detector
+            .set_stalled_source_threshold(stalled_source_threshold);
+    }
+
+    /// Set the hook notified when sources diverge or stall.
+    pub fn set_alert_hook(&mut self, alert_hook: Arc<dyn AlertHook>) {
+        self.divergence_detector.set_alert_hook(alert_hook);
+    }
+
     /// The lowest next block we need to try and sync.
     pub fn lowest_next_block_to_sync(&self) -> Result<u64, WatcherError> {
         let last_synced = self.watcher_db.last_synced_blocks()?;
@@ -181,6 +203,7 @@ impl Watcher {
             // sources was able to produce block data. If so, more data might be
             // available.
             let mut had_success = false;
+            let mut synced_block_indexes = HashSet::new();
 
             for (src_url, (block_index, block_data_result)) in url_to_block_data_result {
                 match block_data_result {
@@ -199,6 +222,14 @@ impl Watcher {
                             };
                         }
 
+                        self.watcher_db.add_block_identity(
+                            &src_url,
+                            block_index,
+                            &block_data.block().id,
+                            &block_data.contents().hash(),
+                        )?;
+                        synced_block_indexes.insert(block_index);
+
                         if let Some(signature) = block_data.signature() {
                             let filename = block_num_to_s3block_path(block_index)
                                 .into_os_string()
@@ -237,6 +268,13 @@ impl Watcher {
                 }
             }
 
+            // Compare the newly synced blocks against the other sources, and look for
+            // sources that are falling behind.
+            for block_index in synced_block_indexes {
+                self.divergence_detector.check_block(block_index)?;
+            }
+            self.divergence_detector.check_stalled_sources()?;
+
             // If nothing succeeded, maybe we are synced all the way through or something
             // else is wrong.
             if !had_success {
@@ -0,0 +1,365 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! gRPC query API for the data collected in the watcher database.
+
+use crate::{
+    error::WatcherDBError,
+    signer_timeline::{AttestationChange, EnclaveMeasurement, SignerPeriod, SignerTimeline},
+    watcher_api::{
+        self, BlockRange, BlockSignatures, GetAttestationChangesResponse,
+        GetBlockSignaturesRequest, GetBlockSignaturesResponse, GetSignerTimelineRequest,
+        GetSignerTimelineResponse, SourceBlockSignature,
+    },
+    watcher_api_grpc::WatcherApi,
+    watcher_db::WatcherDB,
+};
+use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
+use mc_api::{blockchain, external};
+use mc_common::logger::Logger;
+use mc_util_grpc::{rpc_database_err, rpc_invalid_arg_error, rpc_logger, send_result};
+use mc_util_metrics::SVC_COUNTERS;
+use protobuf::RepeatedField;
+use url::Url;
+
+/// Maximal number of blocks returned by a single `GetBlockSignatures` call.
+pub const MAX_BLOCK_SIGNATURES_PAGE_SIZE: u32 = 1000;
+
+/// Maximal number of blocks covered by a single signer timeline query.
+pub const MAX_TIMELINE_RANGE: u64 = 100_000;
+
+/// Watcher query API service.
+#[derive(Clone)]
+pub struct WatcherApiService {
+    /// Watcher database.
+    watcher_db: WatcherDB,
+
+    /// Logger.
+    logger: Logger,
+}
+
+impl WatcherApiService {
+    /// Create a new service.
+    pub fn new(watcher_db: WatcherDB, logger: Logger) -> Self {
+        Self { watcher_db, logger }
+    }
+
+    fn get_block_signatures_impl(
+        &self,
+        request: GetBlockSignaturesRequest,
+        logger: &Logger,
+    ) -> Result<GetBlockSignaturesResponse, RpcStatus> {
+        let limit = request.limit.min(MAX_BLOCK_SIGNATURES_PAGE_SIZE) as u64;
+        let mut response = GetBlockSignaturesResponse::new();
+        if limit == 0 {
+            return Ok(response);
+        }
+        let last_block = request.first_block.saturating_add(limit - 1);
+
+        let signatures = self
+            .watcher_db
+            .get_block_signatures_in_range(request.first_block, last_block)
+            .map_err(|err| rpc_database_err(err, logger))?;
+
+        let mut blocks: Vec<BlockSignatures> = Vec::new();
+        for (block_index, signature_data) in signatures {
+            if blocks.last().map(|block| block.block_index) != Some(block_index) {
+                let mut block = BlockSignatures::new();
+                block.set_block_index(block_index);
+                blocks.push(block);
+            }
+
+            let mut signature = SourceBlockSignature::new();
+            signature.set_src_url(signature_data.src_url);
+            signature.set_archive_filename(signature_data.archive_filename);
+            signature.set_block_signature(blockchain::BlockSignature::from(
+                &signature_data.block_signature,
+            ));
+            blocks
+                .last_mut()
+                .expect("a block was just pushed")
+                .mut_signatures()
+                .push(signature);
+        }
+
+        response.set_blocks(RepeatedField::from_vec(blocks));
+        Ok(response)
+    }
+
+    fn load_timeline(
+        &self,
+        request: &GetSignerTimelineRequest,
+        logger: &Logger,
+    ) -> Result<SignerTimeline, RpcStatus> {
+        let src_url = Url::parse(request.get_src_url())
+            .map_err(|err| rpc_invalid_arg_error("src_url", err, logger))?;
+
+        if request.last_block < request.first_block {
+            return Err(rpc_invalid_arg_error(
+                "last_block",
+                "last_block is smaller than first_block",
+                logger,
+            ));
+        }
+        if request.last_block - request.first_block >= MAX_TIMELINE_RANGE {
+            return Err(rpc_invalid_arg_error(
+                "last_block",
+                format!("at most {} blocks may be queried", MAX_TIMELINE_RANGE),
+                logger,
+            ));
+        }
+
+        SignerTimeline::load(
+            &self.watcher_db,
+            &src_url,
+            request.first_block,
+            request.last_block,
+        )
+        .map_err(|err| match err {
+            WatcherDBError::NotFound => RpcStatus::with_message(
+                RpcStatusCode::NOT_FOUND,
+                format!("{} is not a watched source", src_url),
+            ),
+            err => rpc_database_err(err, logger),
+        })
+    }
+
+    fn get_signer_timeline_impl(
+        &self,
+        request: GetSignerTimelineRequest,
+        logger: &Logger,
+    ) -> Result<GetSignerTimelineResponse, RpcStatus> {
+        let timeline = self.load_timeline(&request, logger)?;
+
+        let mut response = GetSignerTimelineResponse::new();
+        if let Some(last_synced_block) = timeline.last_synced_block {
+            response.set_has_last_synced_block(true);
+            response.set_last_synced_block(last_synced_block);
+        }
+        response.set_periods(
+            timeline
+                .periods
+                .iter()
+                .map(signer_period_to_proto)
+                .collect(),
+        );
+        response.set_missing_signatures(
+            timeline
+                .missing_signatures
+                .iter()
+                .map(|range| {
+                    let mut proto = BlockRange::new();
+                    proto.set_first_block(*range.start());
+                    proto.set_last_block(*range.end());
+                    proto
+                })
+                .collect(),
+        );
+        Ok(response)
+    }
+
+    fn get_attestation_changes_impl(
+        &self,
+        request: GetSignerTimelineRequest,
+        logger: &Logger,
+    ) -> Result<GetAttestationChangesResponse, RpcStatus> {
+        let timeline = self.load_timeline(&request, logger)?;
+        let changes = timeline
+            .attestation_changes(&self.watcher_db)
+            .map_err(|err| rpc_database_err(err, logger))?;
+
+        let mut response = GetAttestationChangesResponse::new();
+        response.set_changes(changes.iter().map(attestation_change_to_proto).collect());
+        Ok(response)
+    }
+}
+
+fn signer_period_to_proto(period: &SignerPeriod) -> watcher_api::SignerPeriod {
+    let mut proto = watcher_api::SignerPeriod::new();
+    proto.set_signer(external::Ed25519Public::from(&period.signer));
+    proto.set_first_block(period.first_block);
+    proto.set_last_block(period.last_block);
+    proto.set_num_signed_blocks(period.num_signed_blocks);
+    proto.set_first_signed_at(period.first_signed_at);
+    proto.set_last_signed_at(period.last_signed_at);
+    proto
+}
+
+fn enclave_measurement_to_proto(
+    measurement: &EnclaveMeasurement,
+) -> watcher_api::EnclaveMeasurement {
+    let mr_enclave: &[u8] = measurement.mr_enclave.as_ref();
+    let mr_signer: &[u8] = measurement.mr_signer.as_ref();
+
+    let mut proto = watcher_api::EnclaveMeasurement::new();
+    proto.set_mr_enclave(mr_enclave.to_vec());
+    proto.set_mr_signer(mr_signer.to_vec());
+    proto.set_product_id(measurement.product_id as u32);
+    proto.set_security_version(measurement.security_version as u32);
+    proto
+}
+
+fn attestation_change_to_proto(change: &AttestationChange) -> watcher_api::AttestationChange {
+    let mut proto = watcher_api::AttestationChange::new();
+    proto.set_block_index(change.block_index);
+    proto.set_previous_signer(external::Ed25519Public::from(&change.previous_signer));
+    proto.set_signer(external::Ed25519Public::from(&change.signer));
+    if let Some(measurement) = &change.previous_measurement {
+        proto.set_previous_measurement(enclave_measurement_to_proto(measurement));
+    }
+    if let Some(measurement) = &change.measurement {
+        proto.set_measurement(enclave_measurement_to_proto(measurement));
+    }
+    proto.set_measurement_changed(change.measurement_changed());
+    proto
+}
+
+impl WatcherApi for WatcherApiService {
+    fn get_block_signatures(
+        &mut self,
+        ctx: RpcContext,
+        request: GetBlockSignaturesRequest,
+        sink: UnarySink<GetBlockSignaturesResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            send_result(
+                ctx,
+                sink,
+                self.get_block_signatures_impl(request, logger),
+                logger,
+            )
+        })
+    }
+
+    fn get_signer_timeline(
+        &mut self,
+        ctx: RpcContext,
+        request: GetSignerTimelineRequest,
+        sink: UnarySink<GetSignerTimelineResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            send_result(
+                ctx,
+                sink,
+                self.get_signer_timeline_impl(request, logger),
+                logger,
+            )
+        })
+    }
+
+    fn get_attestation_changes(
+        &mut self,
+        ctx: RpcContext,
+        request: GetSignerTimelineRequest,
+        sink: UnarySink<GetAttestationChangesResponse>,
+    ) {
+        let _timer = SVC_COUNTERS.req(&ctx);
+        mc_common::logger::scoped_global_logger(&rpc_logger(&ctx, &self.logger), |logger| {
+            send_result(
+                ctx,
+                sink,
+                self.get_attestation_changes_impl(request, logger),
+                logger,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher_db::tests::{setup_blocks, setup_watcher_db};
+    use mc_blockchain_types::BlockSignature;
+    use mc_common::logger::{test_with_logger, Logger};
+    use mc_crypto_keys::Ed25519Pair;
+    use mc_util_from_random::FromRandom;
+    use mc_util_test_helper::run_with_one_seed;
+
+    #[test_with_logger]
+    fn test_get_block_signatures_and_timeline(logger: Logger) {
+        run_with_one_seed(|mut rng| {
+            let url1 = Url::parse("http://www.my_url1.com").unwrap();
+            let url2 = Url::parse("http://www.my_url2.com").unwrap();
+            let watcher_db = setup_watcher_db(&[url1.clone(), url2.clone()], logger.clone());
+            let blocks = setup_blocks();
+
+            let signing_key_a = Ed25519Pair::from_random(&mut rng);
+            let signing_key_b = Ed25519Pair::from_random(&mut rng);
+            for block_index in 1..=4 {
+                let signature = BlockSignature::from_block_and_keypair(
+                    blocks[block_index].block(),
+                    &signing_key_a,
+                )
+                .unwrap();
+                watcher_db
+                    .add_block_signature(&url1, block_index as u64, signature, "00/00".into())
+                    .unwrap();
+            }
+            let signature =
+                BlockSignature::from_block_and_keypair(blocks[2].block(), &signing_key_b).unwrap();
+            watcher_db
+                .add_block_signature(&url2, 2, signature, "00/00".into())
+                .unwrap();
+
+            let service = WatcherApiService::new(watcher_db, logger.clone());
+
+            let mut request = GetBlockSignaturesRequest::new();
+            request.set_first_block(2);
+            request.set_limit(2);
+            let response = service.get_block_signatures_impl(request, &logger).unwrap();
+            assert_eq!(response.get_blocks().len(), 2);
+            assert_eq!(response.get_blocks()[0].get_block_index(), 2);
+            assert_eq!(response.get_blocks()[0].get_signatures().len(), 2);
+            assert_eq!(response.get_blocks()[1].get_block_index(), 3);
+            assert_eq!(response.get_blocks()[1].get_signatures().len(), 1);
+            assert_eq!(
+                response.get_blocks()[1].get_signatures()[0].get_src_url(),
+                url1.as_str()
+            );
+
+            // url2 only signed block 2 and has synced up to it.
+            let mut request = GetSignerTimelineRequest::new();
+            request.set_src_url(url2.to_string());
+            request.set_first_block(1);
+            request.set_last_block(4);
+            let response = service
+                .get_signer_timeline_impl(request.clone(), &logger)
+                .unwrap();
+            assert!(response.get_has_last_synced_block());
+            assert_eq!(response.get_last_synced_block(), 2);
+            assert_eq!(response.get_periods().len(), 1);
+            assert_eq!(
+                response.get_periods()[0].get_signer(),
+                &external::Ed25519Public::from(&signing_key_b.public_key())
+            );
+            assert_eq!(response.get_missing_signatures().len(), 1);
+            assert_eq!(response.get_missing_signatures()[0].get_first_block(), 1);
+            assert_eq!(response.get_missing_signatures()[0].get_last_block(), 1);
+
+            let response = service
+                .get_attestation_changes_impl(request.clone(), &logger)
+                .unwrap();
+            assert!(response.get_changes().is_empty());
+
+            // Invalid ranges and unknown sources are rejected.
+            request.set_first_block(5);
+            assert_eq!(
+                service
+                    .get_signer_timeline_impl(request.clone(), &logger)
+                    .unwrap_err()
+                    .code(),
+                RpcStatusCode::INVALID_ARGUMENT
+            );
+            request.set_first_block(1);
+            request.set_src_url("http://www.my_url3.com".to_owned());
+            assert_eq!(
+                service
+                    .get_signer_timeline_impl(request, &logger)
+                    .unwrap_err()
+                    .code(),
+                RpcStatusCode::NOT_FOUND
+            );
+        });
+    }
+}
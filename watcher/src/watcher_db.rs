@@ -4,7 +4,9 @@
 
 use crate::{block_data_store::BlockDataStore, error::WatcherDBError};
 
-use mc_blockchain_types::{BlockData, BlockIndex, BlockSignature, VerificationReport};
+use mc_blockchain_types::{
+    BlockContentsHash, BlockData, BlockID, BlockIndex, BlockSignature, VerificationReport,
+};
 use mc_common::{
     logger::{log, Logger},
     HashMap,
@@ -32,7 +34,7 @@ use url::Url;
 /// LMDB parameter: max file size.
 const MAX_LMDB_FILE_SIZE: usize = 1 << 40; // 1 TB
 /// LMDB parameter: max number of databases.
-const MAX_DATABASES: u32 = 10;
+const MAX_DATABASES: u32 = 13;
 
 /// Metadata store settings that are used for version control.
 #[derive(Clone, Default, Debug)]
@@ -42,8 +44,11 @@ impl MetadataStoreSettings for WatcherDbMetadataStoreSettings {
     // introduced. If this is properly maintained, we could check during ledger
     // db opening for any incompatibilities, and either refuse to open or
     // perform a migration.
+    // 20221017: Added the block identities, divergences and stalled sources
+    // databases. `create_or_open_rw_watcher_db` adds them to existing databases
+    // without discarding previously synced data.
     #[allow(clippy::unreadable_literal)]
-    const LATEST_VERSION: u64 = 20210127;
+    const LATEST_VERSION: u64 = 20221017;
 
     /// The current crate version that manages the database.
     const CRATE_VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -72,6 +77,15 @@ pub const LAST_SYNCED_DB_NAME: &str = "watcher_db:last_synced";
 /// Last known config database name.
 pub const CONFIG_DB_NAME: &str = "watcher_db:config";
 
+/// Block identities (block id and contents hash per source url) database name.
+pub const BLOCK_IDENTITIES_DB_NAME: &str = "watcher_db:block_identities";
+
+/// Divergent blocks database name.
+pub const DIVERGENCES_DB_NAME: &str = "watcher_db:divergences";
+
+/// Stalled sources database name.
+pub const STALLED_SOURCES_DB_NAME: &str = "watcher_db:stalled_sources";
+
 /// Keys used by the `config` database.
 pub const CONFIG_DB_KEY_TX_SOURCE_URLS: &str = "tx_source_urls";
 
@@ -108,6 +122,59 @@ pub struct BlockSignatureData {
     pub block_signature: BlockSignature,
 }
 
+/// The identity of a block, as published by a single source.
+#[derive(Clone, Eq, Hash, Message, PartialEq)]
+pub struct BlockIdentityData {
+    /// The src_url the block was synced from.
+    #[prost(string, tag = "1")]
+    pub src_url: String,
+
+    /// The block ID.
+    #[prost(bytes, tag = "2")]
+    pub block_id: Vec<u8>,
+
+    /// The hash of the block contents published by the source.
+    #[prost(bytes, tag = "3")]
+    pub contents_hash: Vec<u8>,
+}
+
+/// A block for which sources published different identities.
+#[derive(Clone, Eq, Message, PartialEq)]
+pub struct BlockDivergenceData {
+    /// The block index.
+    #[prost(uint64, tag = "1")]
+    pub block_index: u64,
+
+    /// The identities published by each source that synced the block.
+    #[prost(message, repeated, tag = "2")]
+    pub identities: Vec<BlockIdentityData>,
+
+    /// The time the divergence was first detected, in seconds since the
+    /// epoch.
+    #[prost(uint64, tag = "3")]
+    pub detected_at: u64,
+}
+
+/// A source that fell behind the other watched sources.
+#[derive(Clone, Eq, Message, PartialEq)]
+pub struct StalledSourceData {
+    /// The stalled src_url.
+    #[prost(string, tag = "1")]
+    pub src_url: String,
+
+    /// The last block synced from the source, if any.
+    #[prost(uint64, optional, tag = "2")]
+    pub last_synced_block: Option<u64>,
+
+    /// The highest block synced from any source when the stall was detected.
+    #[prost(uint64, tag = "3")]
+    pub highest_synced_block: u64,
+
+    /// The time the stall was detected, in seconds since the epoch.
+    #[prost(uint64, tag = "4")]
+    pub stalled_since: u64,
+}
+
 #[derive(Clone)]
 /// DB for Watcher Node.
 pub struct WatcherDB {
@@ -151,6 +218,16 @@ pub struct WatcherDB {
     /// the set of URLs currently being polled.
     config: Database,
 
+    /// Block identities database. This is a DUP_SORT database mapping a block
+    /// index to the identity of the block published by each source.
+    block_identities: Database,
+
+    /// Block index -> BlockDivergenceData.
+    divergences: Database,
+
+    /// Source url -> StalledSourceData.
+    stalled_sources: Database,
+
     /// Were we opened in write mode?
     write_allowed: bool,
 
@@ -188,6 +265,9 @@ impl WatcherDB {
             env.open_db(Some(VERIFICATION_REPORTS_POLL_QUEUE_DB_NAME))?;
         let last_synced = env.open_db(Some(LAST_SYNCED_DB_NAME))?;
         let config = env.open_db(Some(CONFIG_DB_NAME))?;
+        let block_identities = env.open_db(Some(BLOCK_IDENTITIES_DB_NAME))?;
+        let divergences = env.open_db(Some(DIVERGENCES_DB_NAME))?;
+        let stalled_sources = env.open_db(Some(STALLED_SOURCES_DB_NAME))?;
 
         let block_data_store = BlockDataStore::new(env.clone(), logger.clone())?;
 
@@ -200,6 +280,9 @@ impl WatcherDB {
             verification_reports_poll_queue,
             last_synced,
             config,
+            block_identities,
+            divergences,
+            stalled_sources,
             write_allowed: false,
             logger,
         })
@@ -243,6 +326,9 @@ impl WatcherDB {
         )?;
         env.create_db(Some(LAST_SYNCED_DB_NAME), DatabaseFlags::empty())?;
         env.create_db(Some(CONFIG_DB_NAME), DatabaseFlags::DUP_SORT)?;
+        env.create_db(Some(BLOCK_IDENTITIES_DB_NAME), DatabaseFlags::DUP_SORT)?;
+        env.create_db(Some(DIVERGENCES_DB_NAME), DatabaseFlags::empty())?;
+        env.create_db(Some(STALLED_SOURCES_DB_NAME), DatabaseFlags::empty())?;
 
         BlockDataStore::create(env)?;
 
@@ -368,6 +454,39 @@ impl WatcherDB {
         self.get_block_signatures_impl(&db_txn, block_index)
     }
 
+    /// Get the signatures for all blocks in the range
+    /// `first_block..=last_block`, ordered by block index.
+    pub fn get_block_signatures_in_range(
+        &self,
+        first_block: u64,
+        last_block: u64,
+    ) -> Result<Vec<(BlockIndex, BlockSignatureData)>, WatcherDBError> {
+        let db_txn = self.env.begin_ro_txn()?;
+        let mut cursor = db_txn.open_ro_cursor(self.block_signatures)?;
+
+        let mut results = Vec::new();
+        if first_block > last_block {
+            return Ok(results);
+        }
+
+        for result in cursor.iter_from(&first_block.to_be_bytes()) {
+            let (key_bytes, value_bytes) = result?;
+            let block_index = u64::from_be_bytes(
+                key_bytes
+                    .try_into()
+                    .map_err(|_| WatcherDBError::Deserialization)?,
+            );
+            if block_index > last_block {
+                break;
+            }
+
+            let signature_data: BlockSignatureData = decode(value_bytes)?;
+            results.push((block_index, signature_data));
+        }
+
+        Ok(results)
+    }
+
     fn get_block_signatures_impl(
         &self,
         db_txn: &impl Transaction,
@@ -401,6 +520,149 @@ impl WatcherDB {
             .collect()
     }
 
+    /// Record the identity of a block published by a source.
+    pub fn add_block_identity(
+        &self,
+        src_url: &Url,
+        block_index: u64,
+        block_id: &BlockID,
+        contents_hash: &BlockContentsHash,
+    ) -> Result<(), WatcherDBError> {
+        if !self.write_allowed {
+            return Err(WatcherDBError::ReadOnly);
+        }
+
+        let mut db_txn = self.env.begin_rw_txn()?;
+
+        // Sanity test - the URL needs to be configured.
+        let urls = self.get_config_urls_with_txn(&db_txn)?;
+        if !urls.contains(src_url) {
+            return Err(WatcherDBError::NotFound);
+        }
+
+        let identity = BlockIdentityData {
+            src_url: src_url.as_str().to_string(),
+            block_id: block_id.as_ref().to_vec(),
+            contents_hash: contents_hash.as_ref().to_vec(),
+        };
+        db_txn.put(
+            self.block_identities,
+            &block_index.to_be_bytes(),
+            &encode(&identity),
+            WriteFlags::empty(),
+        )?;
+
+        db_txn.commit()?;
+        Ok(())
+    }
+
+    /// Get the identities of a block published by each source that synced it.
+    pub fn get_block_identities(
+        &self,
+        block_index: u64,
+    ) -> Result<Vec<BlockIdentityData>, WatcherDBError> {
+        let db_txn = self.env.begin_ro_txn()?;
+        let mut cursor = db_txn.open_ro_cursor(self.block_identities)?;
+        let key_bytes = block_index.to_be_bytes();
+
+        cursor
+            .iter_dup_of(&key_bytes)
+            .map(|result| {
+                let (_key_bytes, value_bytes) = result?;
+                Ok(decode(value_bytes)?)
+            })
+            .collect()
+    }
+
+    /// Store a divergent block, replacing any previous record for the same
+    /// block index.
+    pub fn put_divergence(&self, divergence: &BlockDivergenceData) -> Result<(), WatcherDBError> {
+        if !self.write_allowed {
+            return Err(WatcherDBError::ReadOnly);
+        }
+
+        let mut db_txn = self.env.begin_rw_txn()?;
+        db_txn.put(
+            self.divergences,
+            &divergence.block_index.to_be_bytes(),
+            &encode(divergence),
+            WriteFlags::empty(),
+        )?;
+        db_txn.commit()?;
+        Ok(())
+    }
+
+    /// Get the divergence recorded for a block, if any.
+    pub fn get_divergence(
+        &self,
+        block_index: u64,
+    ) -> Result<Option<BlockDivergenceData>, WatcherDBError> {
+        let db_txn = self.env.begin_ro_txn()?;
+        match db_txn.get(self.divergences, &block_index.to_be_bytes()) {
+            Ok(value_bytes) => Ok(Some(decode(value_bytes)?)),
+            Err(lmdb::Error::NotFound) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Get all recorded divergences, ordered by block index.
+    pub fn get_divergences(&self) -> Result<Vec<BlockDivergenceData>, WatcherDBError> {
+        let db_txn = self.env.begin_ro_txn()?;
+        let mut cursor = db_txn.open_ro_cursor(self.divergences)?;
+        cursor
+            .iter_start()
+            .map(|result| {
+                let (_key_bytes, value_bytes) = result?;
+                Ok(decode(value_bytes)?)
+            })
+            .collect()
+    }
+
+    /// Mark a source as stalled.
+    pub fn put_stalled_source(&self, stalled: &StalledSourceData) -> Result<(), WatcherDBError> {
+        if !self.write_allowed {
+            return Err(WatcherDBError::ReadOnly);
+        }
+
+        let mut db_txn = self.env.begin_rw_txn()?;
+        db_txn.put(
+            self.stalled_sources,
+            &stalled.src_url.as_bytes(),
+            &encode(stalled),
+            WriteFlags::empty(),
+        )?;
+        db_txn.commit()?;
+        Ok(())
+    }
+
+    /// Clear the stalled mark of a source.
+    pub fn remove_stalled_source(&self, src_url: &Url) -> Result<(), WatcherDBError> {
+        if !self.write_allowed {
+            return Err(WatcherDBError::ReadOnly);
+        }
+
+        let mut db_txn = self.env.begin_rw_txn()?;
+        match db_txn.del(self.stalled_sources, &src_url.as_str().as_bytes(), None) {
+            Ok(()) | Err(lmdb::Error::NotFound) => {}
+            Err(err) => Err(err)?,
+        };
+        db_txn.commit()?;
+        Ok(())
+    }
+
+    /// Get the sources that are currently marked as stalled.
+    pub fn get_stalled_sources(&self) -> Result<HashMap<Url, StalledSourceData>, WatcherDBError> {
+        let db_txn = self.env.begin_ro_txn()?;
+        let mut cursor = db_txn.open_ro_cursor(self.stalled_sources)?;
+        cursor
+            .iter_start()
+            .map(|result| {
+                let (key_bytes, value_bytes) = result?;
+                Ok((bytes_to_url(key_bytes)?, decode(value_bytes)?))
+            })
+            .collect()
+    }
+
     /// Get the earliest timestamp for a given block.
     /// The earliest timestamp reflects the time closest to when the block
     /// passed consensus. If no timestamp is present, return u64::MAX, and a
@@ -1020,11 +1282,25 @@ impl WatcherDB {
             }
         }
 
-        // Remove last synced.
+        // Remove any block identities associated with this source URL.
+        let mut cursor = db_txn.open_rw_cursor(self.block_identities)?;
+        for (_key_bytes, value_bytes) in cursor.iter_start().filter_map(Result::ok) {
+            let identity: BlockIdentityData = decode(value_bytes)?;
+            if identity.src_url == src_url.as_str() {
+                cursor.del(WriteFlags::empty())?;
+            }
+        }
+        drop(cursor);
+
+        // Remove last synced and stalled status.
         match db_txn.del(self.last_synced, &src_url.as_str().as_bytes(), None) {
             Ok(()) | Err(lmdb::Error::NotFound) => {}
             Err(err) => Err(err)?,
         };
+        match db_txn.del(self.stalled_sources, &src_url.as_str().as_bytes(), None) {
+            Ok(()) | Err(lmdb::Error::NotFound) => {}
+            Err(err) => Err(err)?,
+        };
 
         // Remove verification reports.
         let signer_key_size = <Ed25519Public as ReprBytes>::Size::USIZE;